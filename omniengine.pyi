@@ -0,0 +1,171 @@
+"""Type stubs for the `omniengine` extension module (built with `--features python`).
+
+Generated from the bindings in `src/python/` by `make stubs`; do not edit by hand.
+"""
+
+from typing import Any, AsyncIterator, Awaitable, Callable, Iterator, Optional, Sequence
+
+import numpy as np
+import numpy.typing as npt
+
+ArrayLike = npt.ArrayLike
+DTypeLike = npt.DTypeLike
+Result = dict[str, Any]
+
+class OmniError(RuntimeError):
+    """Runtime error with a stable `code` and its HTTP `status`."""
+
+    code: str
+    status: int
+
+class PyEngine:
+    """Python wrapper around an inference engine."""
+
+    def __init__(self, path: str, device: Optional[str] = None) -> None: ...
+    def infer(
+        self,
+        input: ArrayLike,
+        normalize: bool = False,
+        dtype: Optional[DTypeLike] = None,
+    ) -> npt.NDArray[Any]:
+        """Runs inference on a NumPy array and returns the output as NumPy array."""
+    def predict(self, input: ArrayLike, normalize: bool = False) -> InferResult:
+        """Like `infer`, but returns an `InferResult` with the output tensor, latency and model name."""
+    def infer_batch(
+        self,
+        inputs: Sequence[ArrayLike],
+        normalize: bool = False,
+        dtype: Optional[DTypeLike] = None,
+    ) -> list[npt.NDArray[Any]]:
+        """Runs a list of samples in model-sized batches and returns one output per sample."""
+    def infer_async(
+        self,
+        input: ArrayLike,
+        normalize: bool = False,
+        dtype: Optional[DTypeLike] = None,
+    ) -> Awaitable[npt.NDArray[Any]]:
+        """Awaitable variant of `infer`; runs on a worker thread so the event loop stays responsive. Calls on the same engine run one at a time."""
+    @property
+    def backend(self) -> str:
+        """Name of the backend in use."""
+
+class InferResult:
+    """Output of one `PyEngine.predict` call."""
+
+    def __repr__(self) -> str: ...
+    def top_k(self, k: int = 5) -> list[tuple[int, float]]:
+        """The `k` highest scores of the flattened output as `(index, score)`, descending."""
+    @property
+    def tensor(self) -> npt.NDArray[np.float32]:
+        """Model output (`float32`)."""
+    @property
+    def latency_ms(self) -> float:
+        """Inference time in milliseconds (without input conversion)."""
+    @property
+    def model(self) -> str:
+        """Model name (file stem of `model_path`)."""
+
+PyOnnxEngine = PyEngine
+
+class PyRuntime:
+    """Embedded OmniEngine runtime: dynamic batching across all configured devices."""
+
+    @staticmethod
+    def start(config_path: str) -> PyRuntime:
+        """Loads the configuration and starts the dispatcher and one worker per device."""
+    def submit(self, array: ArrayLike, id: Optional[str] = None, normalize: bool = False) -> str:
+        """Queues one sample and returns its job ID (generated unless `id` is given)."""
+    def submit_async(
+        self,
+        array: ArrayLike,
+        id: Optional[str] = None,
+        normalize: bool = False,
+    ) -> Awaitable[str]:
+        """Awaitable variant of `submit`; waits for queue space without blocking the event loop."""
+    def get_result(self, id: str, timeout: Optional[float] = None) -> Result:
+        """Waits for the result of a submitted job and returns it as a dict."""
+    def get_result_async(self, id: str, timeout: Optional[float] = None) -> Awaitable[Result]:
+        """Awaitable variant of `get_result`."""
+    def shutdown(self) -> None:
+        """Stops accepting jobs, finishes all queued jobs and stops the workers."""
+    def on_result(self, callback: Callable[[Result], Any]) -> None:
+        """Calls `callback(result)` with each completed result dict, in completion order, on a background thread."""
+    def set_preprocessor(
+        self,
+        func: Optional[Callable[[npt.NDArray[np.float32]], npt.NDArray[np.float32]]],
+    ) -> None:
+        """Runs `func(batch)` before inference instead of the configured preprocessor; `None` restores it. `func` takes and returns the stacked `float32` batch as NumPy array and applies to the next batch."""
+    def set_postprocessor(
+        self,
+        func: Optional[Callable[[npt.NDArray[np.float32]], npt.NDArray[np.float32]]],
+    ) -> None:
+        """Runs `func(output)` after inference instead of the configured postprocessor; `None` restores it. Works like `set_preprocessor`."""
+    def results(self) -> PyResultStream:
+        """Returns a stream of completed result dicts, usable with `for` and `async for`. It ends after `shutdown()`."""
+
+class PyResultStream:
+    """Stream of completed results returned by `PyRuntime.results()`."""
+
+    def __iter__(self) -> Iterator[Result]: ...
+    def __next__(self) -> Result: ...
+    def __aiter__(self) -> AsyncIterator[Result]: ...
+    def __anext__(self) -> Awaitable[Result]: ...
+
+class PyClient:
+    """Client for a running OmniEngine service, over HTTP or Redis."""
+
+    def __init__(
+        self,
+        url: str,
+        max_connections: int = 16,
+        stream: str = ...,
+        out_prefix: str = ...,
+    ) -> None: ...
+    def __repr__(self) -> str: ...
+    def submit(
+        self,
+        array: ArrayLike,
+        id: Optional[str] = None,
+        meta: Optional[dict[str, Any]] = None,
+        normalize: bool = False,
+    ) -> str:
+        """Submits one input and returns its job id (generated unless `id` is given); `meta` is passed through to the result."""
+    def submit_async(
+        self,
+        array: ArrayLike,
+        id: Optional[str] = None,
+        meta: Optional[dict[str, Any]] = None,
+        normalize: bool = False,
+    ) -> Awaitable[str]:
+        """Awaitable variant of `submit`."""
+    def get_result(self, id: str, timeout: Optional[float] = None) -> Result:
+        """Waits for the result of a job and returns it as a dict."""
+    def get_result_async(self, id: str, timeout: Optional[float] = None) -> Awaitable[Result]:
+        """Awaitable variant of `get_result`."""
+    def infer(
+        self,
+        array: ArrayLike,
+        id: Optional[str] = None,
+        meta: Optional[dict[str, Any]] = None,
+        normalize: bool = False,
+        timeout: Optional[float] = None,
+    ) -> Result:
+        """Submits one input and waits for its result (`submit` plus `get_result` in one call)."""
+    def infer_async(
+        self,
+        array: ArrayLike,
+        id: Optional[str] = None,
+        meta: Optional[dict[str, Any]] = None,
+        normalize: bool = False,
+        timeout: Optional[float] = None,
+    ) -> Awaitable[Result]:
+        """Awaitable variant of `infer`."""
+    def infer_many(
+        self,
+        arrays: Sequence[ArrayLike],
+        normalize: bool = False,
+        timeout: Optional[float] = None,
+    ) -> list[Result]:
+        """Runs all inputs concurrently (up to `max_connections` at a time) and returns their results in input order. `timeout` applies to each input; the first failure is raised."""
+    @property
+    def url(self) -> str: ...
@@ -0,0 +1,294 @@
+//! GPU time, energy and cost accounting (`[accounting]`).
+//!
+//! Every model call is timed per device by
+//! [`MeteredEngine`](crate::engine::metered::MeteredEngine). With
+//! `energy = true` a background thread reads the NVML energy counters of the
+//! configured GPUs and attributes to the model the share of each interval's
+//! energy that its busy time on the GPU covers. The totals back the
+//! `omniengine_*` usage metrics and `GET /v1/usage`.
+
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+
+use anyhow::Result;
+use serde::Serialize;
+
+use crate::health::{health, label};
+use crate::types::{AccountingCfg, Config};
+
+/// Busy time per device and energy counters of this process.
+pub struct Accounting {
+    busy_us: Mutex<BTreeMap<String, u64>>,
+    energy: Mutex<Energy>,
+}
+
+struct Energy {
+    /// Whether the NVML sampler is running.
+    sampling: bool,
+    /// Energy used by each GPU (CUDA ordinal) since sampling started, mJ.
+    device_mj: BTreeMap<usize, u64>,
+    /// Part of `device_mj` attributed to the model by busy time, mJ.
+    attributed_mj: f64,
+}
+
+/// Accumulated usage of the model, as served by `GET /v1/usage`.
+#[derive(Debug, Clone, Serialize, utoipa::ToSchema)]
+pub struct UsageReport {
+    pub model: String,
+    /// Time spent in model calls per device (`gpu:N`, `cpu`), in seconds.
+    pub busy_seconds: BTreeMap<String, f64>,
+    /// Sum of `busy_seconds` over the GPUs.
+    pub gpu_seconds: f64,
+    /// Jobs whose results were stored.
+    pub inferences: u64,
+    /// Energy attributed to the model (`energy = true` only).
+    pub energy_joules: Option<f64>,
+    pub joules_per_1k_inferences: Option<f64>,
+    /// GPU-hours and kWh at the configured prices.
+    pub cost: Option<f64>,
+}
+
+static ACCOUNTING: Accounting = Accounting::new();
+static SETTINGS: OnceLock<(String, Option<AccountingCfg>)> = OnceLock::new();
+
+/// Returns the global accounting state.
+pub fn accounting() -> &'static Accounting {
+    &ACCOUNTING
+}
+
+/// Name of a device in metrics and reports, as used for workers.
+pub fn device_key(device: Option<usize>) -> String {
+    match device {
+        Some(id) => format!("gpu:{}", id),
+        None => "cpu".to_string(),
+    }
+}
+
+/// Sets the model label and starts energy sampling for the CUDA ordinals
+/// `gpus` if `[accounting]` asks for it.
+pub fn init(cfg: &Config, gpus: &[usize]) -> Result<()> {
+    let acc = cfg.accounting.clone();
+    let model = acc.as_ref().and_then(|a| a.model.clone()).unwrap_or_else(|| {
+        std::path::Path::new(&cfg.model.model_path).file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default()
+    });
+    if SETTINGS.set((model, acc.clone())).is_err() {
+        tracing::warn!("Accounting bereits initialisiert");
+        return Ok(());
+    }
+    let Some(acc) = acc.filter(|a| a.energy) else { return Ok(()) };
+    anyhow::ensure!(cfg!(feature = "nvml"), "[accounting] energy benötigt das Feature 'nvml'");
+    let mut gpus = gpus.to_vec();
+    gpus.sort_unstable();
+    gpus.dedup();
+    if gpus.is_empty() {
+        tracing::warn!("[accounting] energy: keine GPUs konfiguriert");
+        return Ok(());
+    }
+    let visible = std::env::var("CUDA_VISIBLE_DEVICES").ok();
+    let devices: Vec<(usize, String)> =
+        gpus.iter().map(|&g| (g, crate::gpu_health::nvml_selector(g, visible.as_deref()))).collect();
+    #[cfg(feature = "nvml")]
+    {
+        let interval = Duration::from_secs(acc.interval_secs.max(1));
+        std::thread::Builder::new().name("energy".to_string()).spawn(move || {
+            if let Err(e) = sampler::run(interval, &devices) {
+                tracing::warn!("Energiemessung beendet: {:#}", e);
+            }
+        })?;
+    }
+    #[cfg(not(feature = "nvml"))]
+    let _ = (acc, devices);
+    Ok(())
+}
+
+impl Accounting {
+    pub const fn new() -> Self {
+        Self {
+            busy_us: Mutex::new(BTreeMap::new()),
+            energy: Mutex::new(Energy { sampling: false, device_mj: BTreeMap::new(), attributed_mj: 0.0 }),
+        }
+    }
+
+    /// Adds the duration of one model call on `device` (see [`device_key`]).
+    pub fn record_busy(&self, device: &str, busy: Duration) {
+        *self.busy_us.lock().unwrap().entry(device.to_string()).or_default() += busy.as_micros() as u64;
+    }
+
+    /// Total time of model calls on `device`.
+    #[cfg_attr(not(feature = "nvml"), allow(dead_code))]
+    pub fn busy(&self, device: &str) -> Duration {
+        Duration::from_micros(self.busy_us.lock().unwrap().get(device).copied().unwrap_or(0))
+    }
+
+    #[cfg_attr(not(feature = "nvml"), allow(dead_code))]
+    fn start_energy(&self) {
+        self.energy.lock().unwrap().sampling = true;
+    }
+
+    /// Adds `mj` used by GPU `gpu` during `interval`, of which the model was
+    /// busy for `busy`.
+    #[cfg_attr(not(feature = "nvml"), allow(dead_code))]
+    fn add_energy(&self, gpu: usize, mj: u64, busy: Duration, interval: Duration) {
+        // Mehrere Worker je GPU können zusammen länger als das Intervall rechnen
+        let share = if interval.is_zero() { 0.0 } else { (busy.as_secs_f64() / interval.as_secs_f64()).min(1.0) };
+        let mut energy = self.energy.lock().unwrap();
+        *energy.device_mj.entry(gpu).or_default() += mj;
+        energy.attributed_mj += mj as f64 * share;
+    }
+
+    /// Current totals for `model`, priced with `cfg`.
+    fn report(&self, model: &str, cfg: Option<&AccountingCfg>, inferences: u64) -> UsageReport {
+        let busy_seconds: BTreeMap<String, f64> =
+            self.busy_us.lock().unwrap().iter().map(|(device, us)| (device.clone(), *us as f64 / 1e6)).collect();
+        let gpu_seconds = busy_seconds.iter().filter(|(device, _)| device.starts_with("gpu:")).map(|(_, s)| s).sum();
+        let energy = self.energy.lock().unwrap();
+        let energy_joules = energy.sampling.then_some(energy.attributed_mj / 1e3);
+        let joules_per_1k_inferences = energy_joules.filter(|_| inferences > 0).map(|j| j / inferences as f64 * 1e3);
+        let gpu_hour_price = cfg.and_then(|c| c.gpu_hour_price);
+        let kwh_price = cfg.and_then(|c| c.kwh_price).filter(|_| energy.sampling);
+        let cost = (gpu_hour_price.is_some() || kwh_price.is_some()).then(|| {
+            gpu_hour_price.unwrap_or(0.0) * gpu_seconds / 3600.0 + kwh_price.unwrap_or(0.0) * energy_joules.unwrap_or(0.0) / 3.6e6
+        });
+        UsageReport {
+            model: model.to_string(),
+            busy_seconds,
+            gpu_seconds,
+            inferences,
+            energy_joules,
+            joules_per_1k_inferences,
+            cost,
+        }
+    }
+
+    /// Renders the usage in Prometheus text format with constant `labels`.
+    fn render_metrics(&self, report: &UsageReport, labels: &[(String, String)]) -> String {
+        let pairs: Vec<String> = labels.iter().map(|(k, v)| label(k, v)).chain([label("model", &report.model)]).collect();
+        let with = |extra: String| format!("{{{}}}", pairs.iter().cloned().chain([extra]).collect::<Vec<_>>().join(","));
+        let labels = format!("{{{}}}", pairs.join(","));
+
+        let mut out = String::new();
+        let _ = writeln!(out, "# TYPE omniengine_busy_seconds_total counter");
+        for (device, secs) in &report.busy_seconds {
+            let _ = writeln!(out, "omniengine_busy_seconds_total{} {}", with(label("device", device)), secs);
+        }
+        let _ = writeln!(out, "# TYPE omniengine_gpu_seconds_total counter\nomniengine_gpu_seconds_total{} {}", labels, report.gpu_seconds);
+        if let Some(joules) = report.energy_joules {
+            let _ = writeln!(out, "# TYPE omniengine_energy_joules_total counter\nomniengine_energy_joules_total{} {}", labels, joules);
+            let _ = writeln!(out, "# TYPE omniengine_gpu_energy_joules_total counter");
+            for (gpu, mj) in &self.energy.lock().unwrap().device_mj {
+                let _ = writeln!(out, "omniengine_gpu_energy_joules_total{} {}", with(label("gpu", &gpu.to_string())), *mj as f64 / 1e3);
+            }
+        }
+        if let Some(per_1k) = report.joules_per_1k_inferences {
+            let _ = writeln!(out, "# TYPE omniengine_energy_joules_per_1k_inferences gauge\nomniengine_energy_joules_per_1k_inferences{} {}", labels, per_1k);
+        }
+        if let Some(cost) = report.cost {
+            let _ = writeln!(out, "# TYPE omniengine_cost_total counter\nomniengine_cost_total{} {}", labels, cost);
+        }
+        out
+    }
+}
+
+/// Usage of the model served by this runtime.
+pub fn report() -> UsageReport {
+    let (model, cfg) = SETTINGS.get().map(|(m, c)| (m.as_str(), c.as_ref())).unwrap_or_default();
+    accounting().report(model, cfg, health().completed())
+}
+
+/// Usage metrics, empty unless [`init`] ran.
+pub fn render_metrics(labels: &[(String, String)]) -> String {
+    match SETTINGS.get() {
+        Some(_) => accounting().render_metrics(&report(), labels),
+        None => String::new(),
+    }
+}
+
+#[cfg(feature = "nvml")]
+mod sampler {
+    use std::time::{Duration, Instant};
+
+    use anyhow::{Context, Result};
+    use nvml_wrapper::Nvml;
+
+    use super::{accounting, device_key};
+
+    /// Samples the energy counters of `devices` (CUDA ordinal, NVML
+    /// selector) every `interval` until NVML fails.
+    pub fn run(interval: Duration, devices: &[(usize, String)]) -> Result<()> {
+        let nvml = Nvml::init().context("NVML nicht verfügbar")?;
+        let mut counters = Vec::new();
+        for (gpu, selector) in devices {
+            let device = crate::gpu_health::probe::device(&nvml, *gpu, selector)?;
+            match device.total_energy_consumption() {
+                Ok(mj) => counters.push((*gpu, device, mj, accounting().busy(&device_key(Some(*gpu))))),
+                // z.B. MIG-Instanzen und GPUs vor Volta
+                Err(e) => tracing::warn!("GPU {}: kein Energiezähler ({}), Energie wird nicht erfasst", gpu, e),
+            }
+        }
+        anyhow::ensure!(!counters.is_empty(), "keine GPU mit Energiezähler");
+        accounting().start_energy();
+
+        let mut last = Instant::now();
+        loop {
+            std::thread::sleep(interval);
+            let elapsed = last.elapsed();
+            last = Instant::now();
+            for (gpu, device, mj, busy) in &mut counters {
+                let now = device.total_energy_consumption().context("Energiezähler")?;
+                let busy_now = accounting().busy(&device_key(Some(*gpu)));
+                accounting().add_energy(*gpu, now.saturating_sub(*mj), busy_now.saturating_sub(*busy), elapsed);
+                (*mj, *busy) = (now, busy_now);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn priced() -> AccountingCfg {
+        AccountingCfg { model: None, energy: true, interval_secs: 5, gpu_hour_price: Some(3.6), kwh_price: Some(0.36) }
+    }
+
+    #[test]
+    fn test_busy_time_per_device() {
+        let acc = Accounting::new();
+        acc.record_busy("gpu:0", Duration::from_millis(1500));
+        acc.record_busy("gpu:0", Duration::from_millis(500));
+        acc.record_busy("gpu:1", Duration::from_secs(1));
+        acc.record_busy("cpu", Duration::from_secs(4));
+
+        let report = acc.report("resnet", Some(&priced()), 10);
+        assert_eq!(report.busy_seconds["gpu:0"], 2.0);
+        assert_eq!(report.gpu_seconds, 3.0);
+        // Ohne laufende Energiemessung nur GPU-Stunden
+        assert_eq!(report.energy_joules, None);
+        assert!((report.cost.unwrap() - 0.003).abs() < 1e-9);
+        assert_eq!(acc.report("resnet", None, 10).cost, None);
+    }
+
+    #[test]
+    fn test_energy_attributed_by_busy_share() {
+        let acc = Accounting::new();
+        acc.start_energy();
+        let interval = Duration::from_secs(10);
+        acc.add_energy(0, 100_000, Duration::from_secs(5), interval);
+        acc.add_energy(1, 100_000, Duration::from_secs(30), interval);
+        acc.record_busy("gpu:0", Duration::from_secs(36));
+
+        let report = acc.report("resnet", Some(&priced()), 500);
+        assert_eq!(report.energy_joules, Some(150.0));
+        assert_eq!(report.joules_per_1k_inferences, Some(300.0));
+        // 0.01 GPU-Stunden zu 3.6 plus 150 J zu 0.36/kWh
+        assert!((report.cost.unwrap() - (0.036 + 0.000015)).abs() < 1e-9);
+
+        let text = acc.render_metrics(&report, &[("pod".to_string(), "omni-0".to_string())]);
+        assert!(text.contains("omniengine_busy_seconds_total{pod=\"omni-0\",model=\"resnet\",device=\"gpu:0\"} 36"));
+        assert!(text.contains("omniengine_energy_joules_total{pod=\"omni-0\",model=\"resnet\"} 150"));
+        assert!(text.contains("omniengine_gpu_energy_joules_total{pod=\"omni-0\",model=\"resnet\",gpu=\"1\"} 100"));
+        assert!(text.contains("omniengine_energy_joules_per_1k_inferences{pod=\"omni-0\",model=\"resnet\"} 300"));
+    }
+}
@@ -0,0 +1,154 @@
+//! Latency-SLO adaptive batching.
+//!
+//! Each worker keeps a window of recent batch latencies (from the first
+//! collected job until the results are stored) and tunes the batching
+//! parameters after every batch:
+//!
+//! * p99 above the target: halve the wait time; without a backlog also
+//!   halve the batch size, since smaller batches finish sooner.
+//! * p99 well below the target: grow the wait time step by step, so
+//!   batches fill better.
+//! * Backlog of at least one batch: double the batch size for throughput.
+//!
+//! Wait time and batch size never exceed the `[queue]` limits.
+
+use crate::batcher::BatchPolicy;
+use crate::types::{AdaptiveCfg, Job};
+use std::collections::VecDeque;
+use std::time::Duration;
+
+/// p99 below this share of the target counts as headroom.
+const HEADROOM: f64 = 0.8;
+
+/// Wait time increase per batch with headroom (ms).
+const WAIT_STEP_MS: f64 = 1.0;
+
+/// Batching parameters of one worker, adapted to the observed latency.
+pub struct AdaptiveBatcher {
+    target_p99_ms: f64,
+    min_wait_ms: f64,
+    max_wait_ms: f64,
+    max_batch: usize,
+    window: usize,
+    wait_ms: f64,
+    batch_limit: usize,
+    latencies: VecDeque<f64>,
+}
+
+impl AdaptiveBatcher {
+    /// Starts at the configured limits (`max_batch`, `max_wait_ms`).
+    pub fn new(cfg: &AdaptiveCfg, max_batch: usize, max_wait_ms: u64) -> Self {
+        let max_wait_ms = max_wait_ms.max(cfg.min_wait_ms) as f64;
+        Self {
+            target_p99_ms: cfg.target_p99_ms,
+            min_wait_ms: cfg.min_wait_ms as f64,
+            max_wait_ms,
+            max_batch: max_batch.max(1),
+            window: cfg.window.max(1),
+            wait_ms: max_wait_ms,
+            batch_limit: max_batch.max(1),
+            latencies: VecDeque::new(),
+        }
+    }
+
+    /// Current batch size limit.
+    pub fn batch_limit(&self) -> usize {
+        self.batch_limit
+    }
+
+    /// Current wait time for filling a batch.
+    pub fn wait_ms(&self) -> u64 {
+        self.wait_ms.round() as u64
+    }
+
+    /// p99 of the latency window in milliseconds.
+    pub fn p99_ms(&self) -> Option<f64> {
+        if self.latencies.is_empty() {
+            return None;
+        }
+        let mut sorted: Vec<f64> = self.latencies.iter().copied().collect();
+        sorted.sort_by(f64::total_cmp);
+        let rank = (0.99 * sorted.len() as f64).ceil() as usize;
+        Some(sorted[rank.clamp(1, sorted.len()) - 1])
+    }
+
+    /// Records the latency of a finished batch and the number of jobs still
+    /// waiting, then adapts wait time and batch size.
+    pub fn observe(&mut self, latency: Duration, queue_depth: usize) {
+        if self.latencies.len() == self.window {
+            self.latencies.pop_front();
+        }
+        self.latencies.push_back(latency.as_secs_f64() * 1000.0);
+        let p99 = self.p99_ms().unwrap_or(0.0);
+
+        if p99 > self.target_p99_ms {
+            self.wait_ms = (self.wait_ms / 2.0).max(self.min_wait_ms);
+        } else if p99 < HEADROOM * self.target_p99_ms {
+            self.wait_ms = (self.wait_ms + WAIT_STEP_MS).min(self.max_wait_ms);
+        }
+
+        if queue_depth >= self.batch_limit {
+            // Rückstau: größere Batches für Durchsatz
+            self.batch_limit = (self.batch_limit * 2).min(self.max_batch);
+        } else if p99 > self.target_p99_ms && queue_depth == 0 {
+            // Wenig Last: kleinere Batches sind schneller fertig
+            self.batch_limit = (self.batch_limit / 2).max(1);
+        }
+        tracing::trace!(
+            "Adaptives Batching: p99={:.1}ms, wait={}ms, batch={}",
+            p99,
+            self.wait_ms(),
+            self.batch_limit
+        );
+    }
+}
+
+impl BatchPolicy for AdaptiveBatcher {
+    fn max_wait(&self) -> Duration {
+        Duration::from_millis(self.wait_ms())
+    }
+
+    fn is_full(&self, pending: &[Job]) -> bool {
+        pending.len() >= self.batch_limit()
+    }
+
+    fn observe(&mut self, latency: Duration, queue_depth: usize) {
+        AdaptiveBatcher::observe(self, latency, queue_depth);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cfg() -> AdaptiveCfg {
+        AdaptiveCfg { target_p99_ms: 50.0, min_wait_ms: 0, window: 16 }
+    }
+
+    #[test]
+    fn test_shrinks_under_light_load_when_slow() {
+        let mut a = AdaptiveBatcher::new(&cfg(), 32, 20);
+        a.observe(Duration::from_millis(80), 0);
+        assert_eq!(a.wait_ms(), 10);
+        assert_eq!(a.batch_limit(), 16);
+        a.observe(Duration::from_millis(80), 0);
+        assert_eq!(a.wait_ms(), 5);
+        assert_eq!(a.batch_limit(), 8);
+    }
+
+    #[test]
+    fn test_grows_batch_under_pressure() {
+        let mut a = AdaptiveBatcher::new(&cfg(), 32, 20);
+        for _ in 0..3 {
+            a.observe(Duration::from_millis(80), 0);
+        }
+        assert_eq!(a.batch_limit(), 4);
+        // Schnelle Batches verdrängen die langsamen aus dem Fenster
+        for _ in 0..32 {
+            a.observe(Duration::from_millis(5), 100);
+        }
+        assert_eq!(a.batch_limit(), 32);
+        assert!(a.wait_ms() > 2);
+        assert!(a.wait_ms() <= 20);
+    }
+}
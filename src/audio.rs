@@ -0,0 +1,223 @@
+//! Audio frontend for waveform and spectrogram models.
+//!
+//! Provides a Rust-native mel-spectrogram `Preprocessor` and length bucketing
+//! helpers so variable-length waveforms can be batched for ASR and
+//! audio-classification models.
+
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use ndarray::{s, Array2, Array4, ArrayD, Axis, IxDyn};
+use rustfft::{num_complex::Complex, Fft, FftPlanner};
+
+use crate::pipeline::Preprocessor;
+use crate::types::AudioCfg;
+
+/// Log-mel spectrogram preprocessing stage.
+///
+/// Converts waveforms `[N, T]` or `[N, C, T]` (channels are downmixed) into
+/// log-mel spectrograms `[N, 1, n_mels, frames]`.
+pub struct MelSpectrogram {
+    n_fft: usize,
+    hop_length: usize,
+    window: Vec<f32>,
+    filters: Array2<f32>, // [n_mels, n_fft / 2 + 1]
+    fft: Arc<dyn Fft<f32>>,
+}
+
+impl MelSpectrogram {
+    /// Creates the stage from the `[audio]` configuration.
+    pub fn new(cfg: &AudioCfg) -> Result<Self> {
+        anyhow::ensure!(cfg.n_fft > 0 && cfg.hop_length > 0, "n_fft und hop_length müssen > 0 sein");
+        anyhow::ensure!(cfg.n_mels > 0, "n_mels muss > 0 sein");
+
+        let fmax = cfg.fmax.unwrap_or(cfg.sample_rate as f32 / 2.0);
+        anyhow::ensure!(cfg.fmin < fmax, "fmin muss kleiner als fmax sein");
+
+        // Hann-Fenster (periodisch)
+        let window = (0..cfg.n_fft)
+            .map(|i| {
+                let x = 2.0 * std::f32::consts::PI * i as f32 / cfg.n_fft as f32;
+                0.5 - 0.5 * x.cos()
+            })
+            .collect();
+
+        let filters = mel_filterbank(cfg.sample_rate, cfg.n_fft, cfg.n_mels, cfg.fmin, fmax);
+        let fft = FftPlanner::new().plan_fft_forward(cfg.n_fft);
+
+        Ok(Self { n_fft: cfg.n_fft, hop_length: cfg.hop_length, window, filters, fft })
+    }
+
+    /// Number of frames produced for a waveform of `len` samples.
+    pub fn num_frames(&self, len: usize) -> usize {
+        if len <= self.n_fft { 1 } else { 1 + (len - self.n_fft) / self.hop_length }
+    }
+
+    fn log_mel(&self, samples: &[f32]) -> Array2<f32> {
+        let bins = self.n_fft / 2 + 1;
+        let frames = self.num_frames(samples.len());
+        let mut power = Array2::<f32>::zeros((bins, frames));
+        let mut buf = vec![Complex::new(0.0f32, 0.0); self.n_fft];
+
+        for f in 0..frames {
+            let start = f * self.hop_length;
+            for (i, c) in buf.iter_mut().enumerate() {
+                let v = samples.get(start + i).copied().unwrap_or(0.0);
+                *c = Complex::new(v * self.window[i], 0.0);
+            }
+            self.fft.process(&mut buf);
+            for (k, c) in buf.iter().take(bins).enumerate() {
+                power[[k, f]] = c.norm_sqr();
+            }
+        }
+
+        self.filters.dot(&power).mapv(|v| v.max(1e-10).ln())
+    }
+}
+
+impl Preprocessor for MelSpectrogram {
+    fn run(&self, input: ArrayD<f32>) -> Result<ArrayD<f32>> {
+        // Kanäle auf Mono heruntermischen
+        let wave = match input.ndim() {
+            2 => input,
+            3 => input.mean_axis(Axis(1)).context("MelSpectrogram: Eingabe ohne Kanäle")?,
+            n => anyhow::bail!("MelSpectrogram erwartet [N, T] oder [N, C, T], bekommen {}D", n),
+        };
+
+        let (n, len) = (wave.shape()[0], wave.shape()[1]);
+        let frames = self.num_frames(len);
+        let mut out = Array4::<f32>::zeros((n, 1, self.filters.nrows(), frames));
+
+        for (i, row) in wave.axis_iter(Axis(0)).enumerate() {
+            let samples: Vec<f32> = row.iter().copied().collect();
+            out.slice_mut(s![i, 0, .., ..]).assign(&self.log_mel(&samples));
+        }
+
+        Ok(out.into_dyn())
+    }
+}
+
+/// Builds a triangular mel filterbank (HTK mel scale).
+fn mel_filterbank(sample_rate: u32, n_fft: usize, n_mels: usize, fmin: f32, fmax: f32) -> Array2<f32> {
+    let hz_to_mel = |f: f32| 2595.0 * (1.0 + f / 700.0).log10();
+    let mel_to_hz = |m: f32| 700.0 * (10f32.powf(m / 2595.0) - 1.0);
+
+    let bins = n_fft / 2 + 1;
+    let (mel_min, mel_max) = (hz_to_mel(fmin), hz_to_mel(fmax));
+    let points: Vec<f32> = (0..n_mels + 2)
+        .map(|i| mel_to_hz(mel_min + (mel_max - mel_min) * i as f32 / (n_mels + 1) as f32))
+        .collect();
+
+    let mut filters = Array2::<f32>::zeros((n_mels, bins));
+    for m in 0..n_mels {
+        let (lo, center, hi) = (points[m], points[m + 1], points[m + 2]);
+        for k in 0..bins {
+            let freq = k as f32 * sample_rate as f32 / n_fft as f32;
+            let w = if freq >= lo && freq <= center {
+                (freq - lo) / (center - lo)
+            } else if freq > center && freq <= hi {
+                (hi - freq) / (hi - center)
+            } else {
+                0.0
+            };
+            filters[[m, k]] = w;
+        }
+    }
+    filters
+}
+
+/// Returns the bucket length for a waveform of `len` samples.
+///
+/// Picks the smallest bucket that fits; inputs longer than every bucket map to
+/// the largest one (and get truncated). Without buckets the length is kept.
+pub fn bucket_length(len: usize, buckets: &[usize]) -> usize {
+    buckets
+        .iter()
+        .copied()
+        .filter(|&b| b >= len)
+        .min()
+        .or_else(|| buckets.iter().copied().max())
+        .unwrap_or(len)
+}
+
+/// Pads (with zeros) or truncates the last axis of `x` to its bucket length.
+pub fn pad_to_bucket(x: ArrayD<f32>, buckets: &[usize]) -> ArrayD<f32> {
+    let Some(&len) = x.shape().last() else { return x };
+    let target = bucket_length(len, buckets);
+    if target == len {
+        return x;
+    }
+
+    let mut shape = x.shape().to_vec();
+    *shape.last_mut().unwrap() = target;
+    let mut out = ArrayD::<f32>::zeros(IxDyn(&shape));
+    let keep = len.min(target);
+    let last = Axis(x.ndim() - 1);
+    out.slice_axis_mut(last, (0..keep).into())
+        .assign(&x.slice_axis(last, (0..keep).into()));
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::Array;
+
+    fn cfg() -> AudioCfg {
+        AudioCfg {
+            sample_rate: 16000,
+            n_fft: 400,
+            hop_length: 160,
+            n_mels: 40,
+            fmin: 0.0,
+            fmax: None,
+            buckets: vec![16000, 32000],
+        }
+    }
+
+    #[test]
+    fn test_bucket_length() {
+        let buckets = [16000, 32000];
+        assert_eq!(bucket_length(12000, &buckets), 16000);
+        assert_eq!(bucket_length(16000, &buckets), 16000);
+        assert_eq!(bucket_length(20000, &buckets), 32000);
+        assert_eq!(bucket_length(50000, &buckets), 32000);
+        assert_eq!(bucket_length(1234, &[]), 1234);
+    }
+
+    #[test]
+    fn test_pad_to_bucket() {
+        let x = Array::ones((1, 12000)).into_dyn();
+        let y = pad_to_bucket(x, &[16000, 32000]);
+        assert_eq!(y.shape(), &[1, 16000]);
+        assert_eq!(y[[0, 11999]], 1.0);
+        assert_eq!(y[[0, 12000]], 0.0);
+
+        let long = Array::ones((1, 40000)).into_dyn();
+        assert_eq!(pad_to_bucket(long, &[16000, 32000]).shape(), &[1, 32000]);
+    }
+
+    #[test]
+    fn test_mel_spectrogram_shape() {
+        let mel = MelSpectrogram::new(&cfg()).unwrap();
+        let x = Array::zeros((2, 1, 16000)).into_dyn();
+        let y = mel.run(x).unwrap();
+        assert_eq!(y.shape(), &[2, 1, 40, mel.num_frames(16000)]);
+        assert!(y.iter().all(|v| v.is_finite()));
+
+        assert!(mel.run(Array::zeros((2, 0, 16000)).into_dyn()).is_err());
+    }
+
+    #[test]
+    fn test_mel_spectrogram_tone_energy() {
+        let mel = MelSpectrogram::new(&cfg()).unwrap();
+        let tone: Vec<f32> = (0..16000)
+            .map(|i| (2.0 * std::f32::consts::PI * 1000.0 * i as f32 / 16000.0).sin())
+            .collect();
+        let x = Array::from_shape_vec((1, 16000), tone).unwrap().into_dyn();
+        let y = mel.run(x).unwrap();
+
+        // Energie eines Sinus liegt deutlich über dem Stille-Floor
+        assert!(y.iter().cloned().fold(f32::MIN, f32::max) > 0.0);
+    }
+}
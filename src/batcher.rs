@@ -2,20 +2,222 @@
 //!
 //! This module provides functionality to collect individual jobs into batches
 //! with configurable size limits and timeouts. Smaller batches are padded to
-//! match the model's expected batch size.
+//! match the model's expected batch size. Sequence jobs (`nt`/`nct` layouts:
+//! waveforms, token ids) whose tensors differ only in the last axis are
+//! zero-padded to the longest one; other shape mismatches are rejected.
+//! Multi-modal jobs carry additional named inputs, which are stacked
+//! independently of the main tensor. When a batch is dispatched is decided
+//! by a pluggable [`BatchPolicy`]. For sequence models, [`LengthSorter`]
+//! groups jobs of similar length so that little padding is needed.
 
-use crate::types::{Batch, Job};
+use crate::types::{Batch, BatchStats, NamedTensors};
 use anyhow::Result;
 use ndarray::{ArrayD, Axis, stack};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::pin::Pin;
+use std::sync::{OnceLock, RwLock};
 use tokio::sync::mpsc;
 use tokio::time::{self, Duration};
 
-/// Collects jobs into a batch of size `spec_n`.
+pub use crate::types::{Job, LengthSortCfg, QueueCfg};
+
+/// Decides when the pending jobs of a worker become a batch.
 ///
-/// This function implements dynamic batching by:
-/// 1. Blocking to receive at least one job
-/// 2. Collecting additional jobs up to `max_batch` or until timeout
-/// 3. Padding with zero tensors if needed to reach `spec_n`
+/// The batcher blocks for the first job, then keeps collecting until the
+/// policy reports the pending set as full, `max_wait` has passed or the
+/// model batch size is reached. Custom policies (cost-based, shape-aware,
+/// ...) are made available with [`register_policy`] and selected with
+/// `[queue] policy = "<name>"`.
+pub trait BatchPolicy: Send + Sync {
+    /// How long to wait for further jobs after the first one arrived.
+    fn max_wait(&self) -> Duration;
+
+    /// How long `job` may wait for further jobs after it arrived. A batch is
+    /// dispatched at the earliest deadline of its jobs.
+    fn max_wait_for(&self, _job: &Job) -> Duration {
+        self.max_wait()
+    }
+
+    /// Whether the pending jobs should be dispatched now.
+    fn is_full(&self, pending: &[Job]) -> bool;
+
+    /// Whether `job` may join the pending (non-empty) jobs. A job that is
+    /// not admitted starts the next batch.
+    fn admits(&self, _pending: &[Job], _job: &Job) -> bool {
+        true
+    }
+
+    /// Feedback after a batch was processed: latency from dispatch until the
+    /// results were stored and the number of jobs still queued.
+    fn observe(&mut self, _latency: Duration, _queue_depth: usize) {}
+}
+
+/// Default policy: dispatch at `max_batch` jobs or after `max_wait_ms`.
+pub struct SizeTimeoutPolicy {
+    max_batch: usize,
+    max_wait: Duration,
+}
+
+impl SizeTimeoutPolicy {
+    pub fn new(max_batch: usize, max_wait_ms: u64) -> Self {
+        Self { max_batch, max_wait: Duration::from_millis(max_wait_ms) }
+    }
+}
+
+impl BatchPolicy for SizeTimeoutPolicy {
+    fn max_wait(&self) -> Duration {
+        self.max_wait
+    }
+
+    fn is_full(&self, pending: &[Job]) -> bool {
+        pending.len() >= self.max_batch
+    }
+}
+
+/// Per-priority-class wait times and preemption on top of another policy.
+///
+/// Jobs name their class in the `priority` metadata field; for configured
+/// classes the class wait replaces the wait of the inner policy. Preempting
+/// classes without a configured wait do not wait: a job of such a class
+/// moves the deadline of the pending batch to now, so it is dispatched with
+/// the jobs that are already queued.
+pub struct PriorityWaits {
+    inner: Box<dyn BatchPolicy>,
+    waits: HashMap<String, Duration>,
+    preempt: HashSet<String>,
+}
+
+impl PriorityWaits {
+    pub fn new(inner: Box<dyn BatchPolicy>, waits_ms: &BTreeMap<String, u64>, preempt: &[String]) -> Self {
+        let waits = waits_ms.iter().map(|(class, &ms)| (class.clone(), Duration::from_millis(ms))).collect();
+        Self { inner, waits, preempt: preempt.iter().cloned().collect() }
+    }
+
+    fn preempts(&self, job: &Job) -> bool {
+        priority(job).is_some_and(|class| self.preempt.contains(class))
+    }
+}
+
+/// Priority class of a job (metadata field `priority`).
+fn priority(job: &Job) -> Option<&str> {
+    job.meta.get("priority").and_then(|p| p.as_str())
+}
+
+impl BatchPolicy for PriorityWaits {
+    fn max_wait(&self) -> Duration {
+        self.inner.max_wait()
+    }
+
+    fn max_wait_for(&self, job: &Job) -> Duration {
+        priority(job)
+            .and_then(|class| self.waits.get(class).copied())
+            .or_else(|| self.preempts(job).then_some(Duration::ZERO))
+            .unwrap_or_else(|| self.inner.max_wait_for(job))
+    }
+
+    fn is_full(&self, pending: &[Job]) -> bool {
+        self.inner.is_full(pending)
+    }
+
+    fn admits(&self, pending: &[Job], job: &Job) -> bool {
+        self.inner.admits(pending, job)
+    }
+
+    fn observe(&mut self, latency: Duration, queue_depth: usize) {
+        self.inner.observe(latency, queue_depth)
+    }
+}
+
+/// Limits the input payload of a batch to `max_bytes` on top of another
+/// policy, for jobs of heterogeneous size (e.g. dynamic resolutions).
+///
+/// A single job larger than the budget still forms a batch of its own.
+pub struct ByteBudget {
+    inner: Box<dyn BatchPolicy>,
+    max_bytes: usize,
+}
+
+impl ByteBudget {
+    pub fn new(inner: Box<dyn BatchPolicy>, max_bytes: usize) -> Self {
+        Self { inner, max_bytes }
+    }
+}
+
+impl BatchPolicy for ByteBudget {
+    fn max_wait(&self) -> Duration {
+        self.inner.max_wait()
+    }
+
+    fn max_wait_for(&self, job: &Job) -> Duration {
+        self.inner.max_wait_for(job)
+    }
+
+    fn is_full(&self, pending: &[Job]) -> bool {
+        self.inner.is_full(pending) || pending.iter().map(payload_bytes).sum::<usize>() >= self.max_bytes
+    }
+
+    fn admits(&self, pending: &[Job], job: &Job) -> bool {
+        let bytes: usize = pending.iter().map(payload_bytes).sum();
+        bytes + payload_bytes(job) <= self.max_bytes && self.inner.admits(pending, job)
+    }
+
+    fn observe(&mut self, latency: Duration, queue_depth: usize) {
+        self.inner.observe(latency, queue_depth)
+    }
+}
+
+/// Input bytes of a job: main tensor plus named inputs (f32).
+pub fn payload_bytes(job: &Job) -> usize {
+    let elements = job.tensor.len() + job.inputs.values().map(|a| a.len()).sum::<usize>();
+    elements * std::mem::size_of::<f32>()
+}
+
+/// Creates a policy from the `[queue]` section and the worker's batch limit
+/// (`max_batch` capped at the model batch size).
+pub type PolicyFactory = fn(&QueueCfg, usize) -> Box<dyn BatchPolicy>;
+
+fn policies() -> &'static RwLock<HashMap<String, PolicyFactory>> {
+    static POLICIES: OnceLock<RwLock<HashMap<String, PolicyFactory>>> = OnceLock::new();
+    POLICIES.get_or_init(Default::default)
+}
+
+/// Registers a batching policy under `name` for `[queue] policy`.
+///
+/// Must be called before the runtime starts its workers.
+pub fn register_policy(name: &str, factory: PolicyFactory) {
+    policies().write().unwrap().insert(name.to_string(), factory);
+}
+
+/// Selects the policy for a worker: `[queue] policy` if set, adaptive
+/// batching with `[queue.adaptive]`, otherwise size/timeout. It is wrapped
+/// in [`ByteBudget`] with `max_batch_bytes` and in [`PriorityWaits`] with
+/// `[queue.priority_wait_ms]` or `preempt`.
+pub fn policy_for(cfg: &QueueCfg, max_batch: usize) -> Result<Box<dyn BatchPolicy>> {
+    let mut policy: Box<dyn BatchPolicy> = if let Some(name) = &cfg.policy {
+        let factory = policies()
+            .read()
+            .unwrap()
+            .get(name)
+            .copied()
+            .ok_or_else(|| anyhow::anyhow!("Batch-Policy '{}' ist nicht registriert", name))?;
+        factory(cfg, max_batch)
+    } else {
+        match &cfg.adaptive {
+            Some(a) => Box::new(crate::adaptive::AdaptiveBatcher::new(a, max_batch, cfg.max_wait_ms)),
+            None => Box::new(SizeTimeoutPolicy::new(max_batch, cfg.max_wait_ms)),
+        }
+    };
+    if let Some(max_bytes) = cfg.max_batch_bytes {
+        policy = Box::new(ByteBudget::new(policy, max_bytes));
+    }
+    if !cfg.priority_wait_ms.is_empty() || !cfg.preempt.is_empty() {
+        policy = Box::new(PriorityWaits::new(policy, &cfg.priority_wait_ms, &cfg.preempt));
+    }
+    Ok(policy)
+}
+
+/// Collects jobs into a batch of size `spec_n` with the default
+/// size/timeout policy.
 ///
 /// # Arguments
 ///
@@ -47,32 +249,76 @@ pub async fn collect_batch(
     max_batch: usize,
     max_wait_ms: u64,
 ) -> Result<Option<Batch>> {
-    let mut ids = Vec::with_capacity(max_batch);
-    let mut items: Vec<ArrayD<f32>> = Vec::with_capacity(max_batch);
+    collect_batch_with(spec_n, rx, &SizeTimeoutPolicy::new(max_batch, max_wait_ms)).await
+}
+
+/// Collects jobs into a batch of size `spec_n` as decided by `policy`.
+///
+/// This function implements dynamic batching by:
+/// 1. Blocking to receive at least one job
+/// 2. Collecting additional jobs until the policy is satisfied, its wait
+///    time has passed or `spec_n` jobs are pending
+/// 3. Zero-padding variable-length items to a common last-axis length
+/// 4. Padding with zero tensors if needed to reach `spec_n`
+/// 5. Stacking each named input of multi-modal jobs the same way
+///
+/// Without a place to keep it, a job the policy does not admit still joins
+/// the batch and ends it; [`collect_batch_held`] holds it back instead.
+pub async fn collect_batch_with(
+    spec_n: usize,
+    rx: &mut mpsc::Receiver<Job>,
+    policy: &dyn BatchPolicy,
+) -> Result<Option<Batch>> {
+    collect(spec_n, rx, policy, None).await
+}
 
-    // blockierend erstes Item holen
-    let first = match rx.recv().await {
+/// Like [`collect_batch_with`], but a job the policy does not admit is kept
+/// in `held` and starts the next batch.
+pub async fn collect_batch_held(
+    spec_n: usize,
+    rx: &mut mpsc::Receiver<Job>,
+    policy: &dyn BatchPolicy,
+    held: &mut Option<Job>,
+) -> Result<Option<Batch>> {
+    collect(spec_n, rx, policy, Some(held)).await
+}
+
+async fn collect(
+    spec_n: usize,
+    rx: &mut mpsc::Receiver<Job>,
+    policy: &dyn BatchPolicy,
+    mut held: Option<&mut Option<Job>>,
+) -> Result<Option<Batch>> {
+    // blockierend erstes Item holen (zurückgehaltener Job zuerst)
+    let first = match held.as_deref_mut().and_then(Option::take) {
         Some(j) => j,
-        None => return Ok(None),
+        None => match rx.recv().await {
+            Some(j) => j,
+            None => return Ok(None),
+        },
     };
-    ids.push(first.id);
-    items.push(first.tensor);
+    let mut pending = vec![first];
 
-    // bis max_batch sammeln, mit Timer
-    let deadline = Duration::from_millis(max_wait_ms);
-    let timer = time::sleep(deadline);
+    // sammeln, bis die Policy zufrieden ist, mit Timer
+    let timer = time::sleep(policy.max_wait_for(&pending[0]));
     tokio::pin!(timer);
 
-    while ids.len() < max_batch {
+    while pending.len() < spec_n.max(1) && !policy.is_full(&pending) {
         tokio::select! {
             biased;
             _ = &mut timer => break,
             maybe_job = rx.recv() => {
                 match maybe_job {
+                    Some(j) if !policy.admits(&pending, &j) => {
+                        match held.as_deref_mut() {
+                            Some(slot) => *slot = Some(j),
+                            None => pending.push(j),
+                        }
+                        break;
+                    }
                     Some(j) => {
-                        ids.push(j.id);
-                        items.push(j.tensor);
-                        if ids.len() >= max_batch { break; }
+                        tighten_deadline(timer.as_mut(), policy, &j);
+                        pending.push(j);
                     }
                     None => break,
                 }
@@ -80,18 +326,217 @@ pub async fn collect_batch(
         }
     }
 
-    let actual_len = items.len();
+    build_batch(pending, spec_n).map(Some)
+}
+
+/// Length-sorted batching: collects a window of jobs, sorts it by sequence
+/// length (last axis) and emits batches of neighbouring lengths.
+pub struct LengthSorter {
+    window: usize,
+    buckets: Vec<usize>,
+    sorted: VecDeque<Job>,
+}
+
+impl LengthSorter {
+    pub fn new(cfg: &LengthSortCfg) -> Self {
+        Self { window: cfg.window.max(1), buckets: cfg.buckets.clone(), sorted: VecDeque::new() }
+    }
+
+    /// Returns the next batch; collects and sorts a new window once the
+    /// previous one is used up. Each batch is padded only to its own (or
+    /// its bucket's) maximum length.
+    pub async fn next_batch(
+        &mut self,
+        spec_n: usize,
+        rx: &mut mpsc::Receiver<Job>,
+        policy: &dyn BatchPolicy,
+    ) -> Result<Option<Batch>> {
+        if self.sorted.is_empty() && !self.fill(rx, policy).await {
+            return Ok(None);
+        }
 
-    // Padding bis spec_n
-    while items.len() < spec_n {
-        let shape = items[0].shape().to_vec();
-        items.push(ArrayD::<f32>::zeros(shape));
-        ids.push(format!("DUMMY-{}", items.len()));
+        let first = self.sorted.pop_front().expect("Fenster ist nicht leer");
+        let bucket = self.bucket_of(&first);
+        let mut jobs = vec![first];
+        while jobs.len() < spec_n.max(1) && !policy.is_full(&jobs) {
+            match self.sorted.front() {
+                Some(j) if self.bucket_of(j) == bucket && policy.admits(&jobs, j) => {
+                    jobs.push(self.sorted.pop_front().unwrap())
+                }
+                _ => break,
+            }
+        }
+        // Bucket-Padding zählt in den Statistiken als Padding
+        let real: usize = jobs.iter().map(|j| j.tensor.len()).sum();
+        if !self.buckets.is_empty() {
+            for j in jobs.iter_mut() {
+                j.tensor = crate::audio::pad_to_bucket(std::mem::take(&mut j.tensor), &self.buckets);
+            }
+        }
+        let mut batch = build_batch(jobs, spec_n)?;
+        batch.stats.padding = batch.stats.elements.saturating_sub(real);
+        Ok(Some(batch))
     }
 
-    // stapeln entlang N
-    let views: Vec<_> = items.iter().map(|a| a.view()).collect();
-    let batch_tensor = stack(Axis(0), &views)?;
+    /// Collects up to `window` jobs (waiting at most the policy's wait after
+    /// the first) and sorts them; `false` once the channel is closed.
+    async fn fill(&mut self, rx: &mut mpsc::Receiver<Job>, policy: &dyn BatchPolicy) -> bool {
+        let Some(first) = rx.recv().await else { return false };
+        let timer = time::sleep(policy.max_wait_for(&first));
+        tokio::pin!(timer);
+        let mut window = vec![first];
+
+        while window.len() < self.window {
+            tokio::select! {
+                biased;
+                _ = &mut timer => break,
+                maybe_job = rx.recv() => match maybe_job {
+                    Some(j) => {
+                        tighten_deadline(timer.as_mut(), policy, &j);
+                        window.push(j);
+                    }
+                    None => break,
+                },
+            }
+        }
+
+        // Stabil sortieren: gleich lange Jobs behalten ihre Reihenfolge
+        window.sort_by_key(seq_len);
+        self.sorted.extend(window);
+        true
+    }
+
+    /// Jobs already taken from the queue but not yet batched, e.g. to
+    /// redistribute them when the worker stops.
+    pub fn take_pending(&mut self) -> Vec<Job> {
+        self.sorted.drain(..).collect()
+    }
+
+    /// Bucket length of a job; its own length without buckets, so every
+    /// length can share a batch.
+    fn bucket_of(&self, job: &Job) -> usize {
+        if self.buckets.is_empty() {
+            0
+        } else {
+            crate::audio::bucket_length(seq_len(job), &self.buckets)
+        }
+    }
+}
+
+/// Moves the dispatch deadline forward if `job` may wait less than the
+/// pending ones (e.g. an interactive job joining bulk jobs).
+fn tighten_deadline(timer: Pin<&mut time::Sleep>, policy: &dyn BatchPolicy, job: &Job) {
+    let deadline = time::Instant::now() + policy.max_wait_for(job);
+    if deadline < timer.deadline() {
+        timer.reset(deadline);
+    }
+}
+
+/// Sequence length of a job (last tensor axis).
+fn seq_len(job: &Job) -> usize {
+    job.tensor.shape().last().copied().unwrap_or(0)
+}
+
+/// Error of jobs that could not be stacked into one batch (e.g. differing
+/// shapes).
+///
+/// Carries the batch without tensors, so the worker can answer its jobs
+/// with error results and go on with the next batch.
+#[derive(Debug)]
+pub struct Unbatchable {
+    pub reason: String,
+    pub batch: Batch,
+}
+
+impl std::fmt::Display for Unbatchable {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.reason)
+    }
+}
+
+impl std::error::Error for Unbatchable {}
+
+/// Stacks the collected jobs into a batch padded to `spec_n`.
+///
+/// Fails with [`Unbatchable`] if the tensors of the jobs cannot be stacked.
+fn build_batch(jobs: Vec<Job>, spec_n: usize) -> Result<Batch> {
+    let actual_len = jobs.len();
+    let mut ids = Vec::with_capacity(spec_n.max(actual_len));
+    let mut items: Vec<ArrayD<f32>> = Vec::with_capacity(actual_len);
+    let mut metas = Vec::with_capacity(actual_len);
+    let mut named: Vec<NamedTensors> = Vec::with_capacity(actual_len);
+    let mut acks = Vec::with_capacity(actual_len);
+    let mut queue_waits = Vec::with_capacity(actual_len);
+    for j in jobs {
+        queue_waits.extend(j.enqueued.map(|t| t.elapsed()));
+        ids.push(j.id);
+        items.push(j.tensor);
+        metas.push(j.meta);
+        named.push(j.inputs);
+        acks.push(j.ack);
+    }
+
+    // Padding-IDs bis spec_n
+    while ids.len() < spec_n {
+        ids.push(format!("DUMMY-{}", ids.len() + 1));
+    }
+    let real: usize = items.iter().map(|a| a.len()).sum();
+    let (batch_tensor, inputs) = match stack_batch(items, named, spec_n) {
+        Ok(stacked) => stacked,
+        Err(e) => {
+            let batch = Batch { ids, actual_len, metas, acks, ..Default::default() };
+            return Err(Unbatchable { reason: format!("{:#}", e), batch }.into());
+        }
+    };
+
+    let stats = BatchStats {
+        slots: spec_n,
+        elements: batch_tensor.len(),
+        padding: batch_tensor.len().saturating_sub(real),
+        queue_waits,
+    };
+    Ok(Batch { ids, tensor: batch_tensor, actual_len, metas, inputs, acks, stats })
+}
+
+/// Splits a stacked batch back into its real jobs, e.g. to redistribute a
+/// batch that was collected but must not run. Variable-length samples keep
+/// the padding they got in the batch.
+pub fn unstack(batch: Batch) -> Vec<Job> {
+    let Batch { ids, tensor, actual_len, metas, inputs, acks, .. } = batch;
+    let row = |t: &ArrayD<f32>, i: usize| t.index_axis(Axis(0), i).to_owned();
+    ids.into_iter()
+        .take(actual_len)
+        .zip(metas.into_iter().zip(acks))
+        .enumerate()
+        .map(|(i, (id, (meta, ack)))| Job {
+            id,
+            tensor: row(&tensor, i),
+            meta,
+            inputs: inputs.iter().map(|(name, t)| (name.clone(), row(t, i))).collect(),
+            ack,
+            ..Default::default()
+        })
+        .collect()
+}
+
+/// Stacks the main tensors and, independently, each named input of the jobs.
+fn stack_batch(items: Vec<ArrayD<f32>>, mut named: Vec<NamedTensors>, spec_n: usize) -> Result<(ArrayD<f32>, NamedTensors)> {
+    let batch_tensor = stack_items(items, spec_n)?;
+
+    // Benannte Inputs unabhängig voneinander stapeln
+    let mut inputs = NamedTensors::new();
+    for name in named[0].keys().cloned().collect::<Vec<_>>() {
+        let per_job = named
+            .iter_mut()
+            .map(|m| m.remove(&name))
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(|| anyhow::anyhow!("Input '{}' fehlt bei einem Job im Batch", name))?;
+        inputs.insert(name, stack_items(per_job, spec_n)?);
+    }
+    anyhow::ensure!(
+        named.iter().all(|m| m.is_empty()),
+        "Jobs im Batch haben unterschiedliche benannte Inputs"
+    );
 
     anyhow::ensure!(
         batch_tensor.shape()[0] == spec_n,
@@ -99,8 +544,59 @@ pub async fn collect_batch(
         batch_tensor.shape()[0],
         spec_n
     );
+    Ok((batch_tensor, inputs))
+}
+
+/// Largest sample rank of a variable-length layout (`nct` without batch axis).
+const MAX_SEQUENCE_RANK: usize = 2;
+
+/// Pads variable lengths, fills up to `spec_n` with zeros and stacks along N.
+pub(crate) fn stack_items(mut items: Vec<ArrayD<f32>>, spec_n: usize) -> Result<ArrayD<f32>> {
+    // Variable Längen (letzte Achse) angleichen
+    pad_last_axis(&mut items);
+
+    // Padding bis spec_n
+    while items.len() < spec_n {
+        let shape = items[0].shape().to_vec();
+        items.push(ArrayD::<f32>::zeros(shape));
+    }
+
+    if let Some(other) = items.iter().find(|a| a.shape() != items[0].shape()) {
+        anyhow::bail!("Jobs im Batch haben unterschiedliche Shapes: {:?} und {:?}", items[0].shape(), other.shape());
+    }
+
+    // stapeln entlang N
+    let views: Vec<_> = items.iter().map(|a| a.view()).collect();
+    Ok(stack(Axis(0), &views)?)
+}
 
-    Ok(Some(Batch { ids, tensor: batch_tensor, actual_len }))
+/// Zero-pads sequence items along the last axis to the longest item.
+///
+/// Only samples of the variable-length layouts are padded: `[T]` (`nt`) and
+/// `[C, T]` (`nct`). Images and volumes, or items whose leading dimensions
+/// differ, are left untouched; stacking will then report the mismatch.
+fn pad_last_axis(items: &mut [ArrayD<f32>]) {
+    let Some(first) = items.first() else { return };
+    if first.ndim() > MAX_SEQUENCE_RANK {
+        return;
+    }
+    let lead = first.shape()[..first.ndim().saturating_sub(1)].to_vec();
+    if items.iter().any(|a| a.ndim() != lead.len() + 1 || a.shape()[..lead.len()] != lead[..]) {
+        return;
+    }
+
+    let max_len = items.iter().map(|a| a.shape()[lead.len()]).max().unwrap_or(0);
+    let last = Axis(lead.len());
+    for item in items.iter_mut() {
+        let len = item.shape()[lead.len()];
+        if len < max_len {
+            let mut shape = lead.clone();
+            shape.push(max_len);
+            let mut padded = ArrayD::<f32>::zeros(shape);
+            padded.slice_axis_mut(last, (0..len).into()).assign(item);
+            *item = padded;
+        }
+    }
 }
 
 #[cfg(test)]
@@ -115,6 +611,7 @@ mod tests {
         let job = Job {
             id: "job1".to_string(),
             tensor: Array::zeros((1, 3, 64, 64)).into_dyn(),
+            ..Default::default()
         };
         
         tx.send(job).await.unwrap();
@@ -135,6 +632,7 @@ mod tests {
             let job = Job {
                 id: format!("job{}", i),
                 tensor: Array::ones((1, 3, 32, 32)).into_dyn(),
+                ..Default::default()
             };
             tx.send(job).await.unwrap();
         }
@@ -165,6 +663,7 @@ mod tests {
             let job = Job {
                 id: format!("job{}", i),
                 tensor: Array::zeros((1, 1, 16, 16)).into_dyn(),
+                ..Default::default()
             };
             tx.send(job).await.unwrap();
         }
@@ -175,4 +674,295 @@ mod tests {
         assert_eq!(batch.actual_len, 4);
         assert_eq!(batch.ids.len(), 4);
     }
+
+    #[tokio::test]
+    async fn test_collect_batch_pads_variable_length() {
+        let (tx, mut rx) = mpsc::channel(10);
+
+        for len in [8000, 16000] {
+            let job = Job {
+                id: format!("audio{}", len),
+                tensor: Array::ones((1, len)).into_dyn(),
+                ..Default::default()
+            };
+            tx.send(job).await.unwrap();
+        }
+        drop(tx);
+
+        let batch = collect_batch(2, &mut rx, 2, 10).await.unwrap().unwrap();
+
+        assert_eq!(batch.tensor.shape(), &[2, 1, 16000]);
+        assert_eq!(batch.tensor[[0, 0, 7999]], 1.0);
+        assert_eq!(batch.tensor[[0, 0, 8000]], 0.0);
+    }
+
+    #[tokio::test]
+    async fn test_collect_batch_rejects_mismatched_images() {
+        let (tx, mut rx) = mpsc::channel(10);
+
+        for width in [4, 6] {
+            let job = Job {
+                id: format!("img{}", width),
+                tensor: Array::ones((3, 4, width)).into_dyn(),
+                ..Default::default()
+            };
+            tx.send(job).await.unwrap();
+        }
+        drop(tx);
+
+        let err = collect_batch(2, &mut rx, 2, 10).await.unwrap_err();
+        assert!(err.to_string().contains("unterschiedliche Shapes"), "{}", err);
+        let rejected = err.downcast::<Unbatchable>().unwrap();
+        assert_eq!(rejected.batch.ids, ["img4", "img6"]);
+        assert_eq!(rejected.batch.actual_len, 2);
+    }
+
+    #[tokio::test]
+    async fn test_collect_batch_stats() {
+        let (tx, mut rx) = mpsc::channel(10);
+        for (len, enqueued) in [(3, None), (5, Some(std::time::Instant::now()))] {
+            let job = Job { id: format!("seq{}", len), tensor: Array::ones((2, len)).into_dyn(), enqueued, ..Default::default() };
+            tx.send(job).await.unwrap();
+        }
+        drop(tx);
+
+        let batch = collect_batch(4, &mut rx, 4, 10).await.unwrap().unwrap();
+
+        // 4 Slots à 2x5, davon 6 + 10 echte Werte
+        assert_eq!(batch.stats.slots, 4);
+        assert_eq!(batch.stats.elements, 40);
+        assert_eq!(batch.stats.padding, 24);
+        assert_eq!(batch.stats.queue_waits.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_collect_batch_stacks_named_inputs() {
+        let (tx, mut rx) = mpsc::channel(10);
+
+        for len in [5, 7] {
+            let mut inputs = NamedTensors::new();
+            inputs.insert("input_ids".to_string(), Array::ones(len).into_dyn());
+            let job = Job {
+                id: format!("clip{}", len),
+                tensor: Array::zeros((3, 8, 8)).into_dyn(),
+                inputs,
+                ..Default::default()
+            };
+            tx.send(job).await.unwrap();
+        }
+        drop(tx);
+
+        let batch = collect_batch(4, &mut rx, 4, 10).await.unwrap().unwrap();
+
+        assert_eq!(batch.tensor.shape(), &[4, 3, 8, 8]);
+        assert_eq!(batch.inputs["input_ids"].shape(), &[4, 7]);
+        assert_eq!(batch.inputs["input_ids"][[0, 5]], 0.0);
+        assert_eq!(batch.inputs["input_ids"][[1, 6]], 1.0);
+
+        // Zurück in die echten Jobs, ohne Dummy-Samples
+        let jobs = unstack(batch);
+        assert_eq!(jobs.iter().map(|j| j.id.as_str()).collect::<Vec<_>>(), ["clip5", "clip7"]);
+        assert_eq!(jobs[1].tensor.shape(), &[3, 8, 8]);
+        assert_eq!(jobs[0].inputs["input_ids"].shape(), &[7]);
+    }
+
+    /// Dispatches as soon as the summed sequence length reaches a budget.
+    struct TokenBudget(usize);
+
+    impl BatchPolicy for TokenBudget {
+        fn max_wait(&self) -> Duration {
+            Duration::from_millis(50)
+        }
+
+        fn is_full(&self, pending: &[Job]) -> bool {
+            pending.iter().map(|j| j.tensor.len()).sum::<usize>() >= self.0
+        }
+    }
+
+    #[tokio::test]
+    async fn test_collect_batch_with_custom_policy() {
+        let (tx, mut rx) = mpsc::channel(10);
+        for len in [6, 6, 6] {
+            let job = Job { id: format!("seq{}", len), tensor: Array::ones(len).into_dyn(), ..Default::default() };
+            tx.send(job).await.unwrap();
+        }
+
+        let batch = collect_batch_with(4, &mut rx, &TokenBudget(10)).await.unwrap().unwrap();
+
+        assert_eq!(batch.actual_len, 2);
+        assert_eq!(rx.len(), 1);
+    }
+
+    #[test]
+    fn test_policy_for_registered_name() {
+        fn fixed(_: &QueueCfg, _: usize) -> Box<dyn BatchPolicy> {
+            Box::new(SizeTimeoutPolicy::new(1, 0))
+        }
+        register_policy("fixed-test", fixed);
+
+        let mut cfg = QueueCfg { max_batch: 8, max_wait_ms: 10, adaptive: None, policy: Some("fixed-test".into()), length_sort: None, max_in_flight: 1, priority_wait_ms: BTreeMap::new(), max_batch_bytes: None, rebalance: None, preempt: vec![], stages: None };
+        let job = Job::default();
+        assert!(policy_for(&cfg, 8).unwrap().is_full(std::slice::from_ref(&job)));
+
+        cfg.policy = Some("missing".into());
+        assert!(policy_for(&cfg, 8).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_byte_budget_holds_back_large_job() {
+        let (tx, mut rx) = mpsc::channel(10);
+        for (id, len) in [("a", 100), ("b", 100), ("c", 300), ("d", 100)] {
+            tx.send(Job { id: id.into(), tensor: Array::zeros(len).into_dyn(), ..Default::default() }).await.unwrap();
+        }
+        drop(tx);
+        // 1600 Bytes: a+b passen, c (1200 Bytes) nicht mehr
+        let policy = ByteBudget::new(Box::new(SizeTimeoutPolicy::new(8, 10)), 1600);
+        let mut held = None;
+
+        let first = collect_batch_held(8, &mut rx, &policy, &mut held).await.unwrap().unwrap();
+        assert_eq!(first.ids[..first.actual_len], ["a", "b"]);
+        assert_eq!(held.as_ref().map(|j| j.id.as_str()), Some("c"));
+
+        let second = collect_batch_held(8, &mut rx, &policy, &mut held).await.unwrap().unwrap();
+        assert_eq!(second.ids[..second.actual_len], ["c", "d"]);
+        assert!(collect_batch_held(8, &mut rx, &policy, &mut held).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_priority_class_shortens_wait() {
+        let (tx, mut rx) = mpsc::channel(10);
+        let cfg = QueueCfg {
+            max_batch: 8,
+            max_wait_ms: 60_000,
+            adaptive: None,
+            policy: None,
+            length_sort: None,
+            max_in_flight: 1,
+            priority_wait_ms: BTreeMap::from([("interactive".to_string(), 5)]),
+            max_batch_bytes: None,
+            rebalance: None,
+            preempt: vec![],
+            stages: None,
+        };
+        let policy = policy_for(&cfg, 8).unwrap();
+
+        let mut meta = crate::types::JobMeta::new();
+        meta.insert("priority".into(), "interactive".into());
+        tx.send(Job { id: "bulk".into(), tensor: Array::zeros(2).into_dyn(), ..Default::default() }).await.unwrap();
+        tx.send(Job { id: "fast".into(), tensor: Array::zeros(2).into_dyn(), meta, ..Default::default() }).await.unwrap();
+
+        // Kanal bleibt offen: nur die Frist des interaktiven Jobs beendet das Sammeln
+        let batch = time::timeout(Duration::from_secs(5), collect_batch_with(8, &mut rx, policy.as_ref()))
+            .await
+            .expect("interaktiver Job muss den Batch früh auslösen")
+            .unwrap()
+            .unwrap();
+        assert_eq!(batch.actual_len, 2);
+        drop(tx);
+    }
+
+    #[tokio::test]
+    async fn test_interactive_job_preempts_bulk_batch() {
+        let (tx, mut rx) = mpsc::channel(10);
+        let mut interactive = crate::types::JobMeta::new();
+        interactive.insert("priority".into(), "interactive".into());
+        for (id, meta) in [("b1", None), ("b2", None), ("i1", Some(interactive)), ("b3", None)] {
+            let job = Job { id: id.into(), tensor: Array::zeros(2).into_dyn(), meta: meta.unwrap_or_default(), ..Default::default() };
+            tx.send(job).await.unwrap();
+        }
+        let policy = PriorityWaits::new(Box::new(SizeTimeoutPolicy::new(8, 60_000)), &BTreeMap::new(), &["interactive".to_string()]);
+
+        // Bereits wartende Jobs kommen mit, dann gilt die Frist des interaktiven Jobs
+        let batch = time::timeout(Duration::from_secs(5), collect_batch_with(8, &mut rx, &policy))
+            .await
+            .expect("interaktiver Job muss den Batch sofort auslösen")
+            .unwrap()
+            .unwrap();
+        assert_eq!(batch.ids[..3], ["b1", "b2", "i1"]);
+        drop(tx);
+    }
+
+    #[tokio::test]
+    async fn test_interactive_jobs_still_coalesce() {
+        let (tx, mut rx) = mpsc::channel(10);
+        let mut interactive = crate::types::JobMeta::new();
+        interactive.insert("priority".into(), "interactive".into());
+        let policy = PriorityWaits::new(
+            Box::new(SizeTimeoutPolicy::new(8, 60_000)),
+            &BTreeMap::from([("interactive".to_string(), 50)]),
+            &["interactive".to_string()],
+        );
+
+        let send = async {
+            for id in ["i1", "i2", "i3"] {
+                let job = Job { id: id.into(), tensor: Array::zeros(2).into_dyn(), meta: interactive.clone(), ..Default::default() };
+                tx.send(job).await.unwrap();
+                time::sleep(Duration::from_millis(5)).await;
+            }
+        };
+        let (batch, ()) = tokio::join!(collect_batch_with(8, &mut rx, &policy), send);
+        // Ein interaktiver Job schickt den Batch nicht allein los
+        assert_eq!(batch.unwrap().unwrap().ids[..3], ["i1", "i2", "i3"]);
+    }
+
+    #[tokio::test]
+    async fn test_length_sorter_batches_similar_lengths() {
+        let (tx, mut rx) = mpsc::channel(10);
+        for len in [10, 3, 9, 2] {
+            let job = Job { id: format!("seq{}", len), tensor: Array::ones(len).into_dyn(), ..Default::default() };
+            tx.send(job).await.unwrap();
+        }
+        drop(tx);
+
+        let mut sorter = LengthSorter::new(&LengthSortCfg { window: 8, buckets: vec![] });
+        let policy = SizeTimeoutPolicy::new(2, 10);
+
+        let short = sorter.next_batch(2, &mut rx, &policy).await.unwrap().unwrap();
+        assert_eq!(short.ids, vec!["seq2", "seq3"]);
+        assert_eq!(short.tensor.shape(), &[2, 3]);
+
+        let long = sorter.next_batch(2, &mut rx, &policy).await.unwrap().unwrap();
+        assert_eq!(long.tensor.shape(), &[2, 10]);
+        assert!(sorter.next_batch(2, &mut rx, &policy).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_length_sorter_returns_pending_jobs() {
+        let (tx, mut rx) = mpsc::channel(10);
+        for len in [4, 1, 3] {
+            let job = Job { id: format!("seq{}", len), tensor: Array::ones(len).into_dyn(), ..Default::default() };
+            tx.send(job).await.unwrap();
+        }
+        drop(tx);
+
+        let mut sorter = LengthSorter::new(&LengthSortCfg { window: 8, buckets: vec![] });
+        let first = sorter.next_batch(1, &mut rx, &SizeTimeoutPolicy::new(1, 10)).await.unwrap().unwrap();
+        assert_eq!(first.ids, vec!["seq1"]);
+
+        // Rest des Fensters geht beim Stoppen nicht verloren
+        let pending: Vec<_> = sorter.take_pending().into_iter().map(|j| j.id).collect();
+        assert_eq!(pending, vec!["seq3", "seq4"]);
+        assert!(sorter.take_pending().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_length_sorter_pads_to_bucket() {
+        let (tx, mut rx) = mpsc::channel(10);
+        for len in [5, 20, 6] {
+            let job = Job { id: format!("seq{}", len), tensor: Array::ones(len).into_dyn(), ..Default::default() };
+            tx.send(job).await.unwrap();
+        }
+        drop(tx);
+
+        let mut sorter = LengthSorter::new(&LengthSortCfg { window: 8, buckets: vec![8, 32] });
+        let policy = SizeTimeoutPolicy::new(4, 10);
+
+        let first = sorter.next_batch(4, &mut rx, &policy).await.unwrap().unwrap();
+        assert_eq!(first.actual_len, 2);
+        assert_eq!(first.tensor.shape(), &[4, 8]);
+        assert_eq!(first.stats.padding, 32 - 11);
+        let second = sorter.next_batch(4, &mut rx, &policy).await.unwrap().unwrap();
+        assert_eq!(second.actual_len, 1);
+        assert_eq!(second.tensor.shape(), &[4, 32]);
+    }
 }
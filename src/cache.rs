@@ -0,0 +1,294 @@
+//! Content-addressed result cache (`[cache]`).
+//!
+//! The dispatcher hashes the input tensors of each job together with the
+//! model version. On a hit the cached result is stored for the new job
+//! right away and the job never reaches a worker; on a miss the key is
+//! remembered until the job's result is published and then cached.
+//!
+//! With `[tta]`, whether a job is augmented (`meta.tta`) is part of the
+//! key, since it changes the result.
+//!
+//! Entries expire after `ttl_secs`; beyond `max_entries` the least
+//! recently used entry is evicted. The cache lives in process memory, so
+//! each node caches its own results.
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, HashMap};
+use std::hash::{Hash, Hasher};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use serde_json::Value;
+
+use crate::tta::Tta;
+use crate::types::{CacheCfg, Config, Job};
+
+/// Cache key: 128-bit hash of model version and inputs.
+type Key = u128;
+
+struct Entry {
+    result: Arc<Value>,
+    stored: Instant,
+    tick: u64,
+}
+
+#[derive(Default)]
+struct State {
+    entries: HashMap<Key, Entry>,
+    /// Least recently used first.
+    order: BTreeMap<u64, Key>,
+    tick: u64,
+    /// Jobs sent to a worker after a miss, by job id.
+    pending: HashMap<String, (Key, Instant)>,
+}
+
+pub struct ResultCache {
+    ttl: Duration,
+    max_entries: usize,
+    model: String,
+    tta: Option<Arc<Tta>>,
+    state: Mutex<State>,
+}
+
+impl ResultCache {
+    pub fn new(cfg: &CacheCfg, model: String) -> Self {
+        Self {
+            ttl: Duration::from_secs(cfg.ttl_secs),
+            max_entries: cfg.max_entries.max(1),
+            model,
+            tta: None,
+            state: Mutex::new(State::default()),
+        }
+    }
+
+    /// Keys results by whether `tta` augments the job.
+    pub fn with_tta(mut self, tta: Option<Arc<Tta>>) -> Self {
+        self.tta = tta;
+        self
+    }
+
+    /// Creates the cache from `[cache]`. Without an explicit
+    /// `model_version`, path and modification time of the model file
+    /// identify the model, so a replaced model does not hit old results.
+    pub fn from_config(cfg: &Config) -> Result<Option<Self>> {
+        let Some(cache) = &cfg.cache else { return Ok(None) };
+        let model = cache.model_version.clone().unwrap_or_else(|| {
+            let modified = std::fs::metadata(&cfg.model.model_path).and_then(|m| m.modified()).ok();
+            format!("{}@{:?}", cfg.model.model_path, modified)
+        });
+        Ok(Some(Self::new(cache, model).with_tta(Tta::from_config(cfg)?)))
+    }
+
+    /// Hash of the model version, the job's input tensors and whether the
+    /// job is augmented.
+    fn key(&self, job: &Job) -> Key {
+        let augmented = self.tta.as_ref().is_some_and(|tta| tta.wanted(&job.meta));
+        let hash = |seed: u8| {
+            let mut h = DefaultHasher::new();
+            seed.hash(&mut h);
+            self.model.hash(&mut h);
+            augmented.hash(&mut h);
+            let tensors = std::iter::once(("", &job.tensor)).chain(job.inputs.iter().map(|(n, t)| (n.as_str(), t)));
+            for (name, tensor) in tensors {
+                name.hash(&mut h);
+                tensor.shape().hash(&mut h);
+                tensor.iter().for_each(|v| v.to_bits().hash(&mut h));
+            }
+            h.finish()
+        };
+        (hash(0) as u128) << 64 | hash(1) as u128
+    }
+
+    /// Returns the cached result for `job`, rewritten to its id and metadata.
+    /// On a miss the job is remembered so its result is cached once stored.
+    pub fn lookup(&self, job: &Job) -> Option<Value> {
+        let key = self.key(job);
+        let mut state = self.state.lock().unwrap();
+        state.tick += 1;
+        let tick = state.tick;
+
+        let cached = match state.entries.get_mut(&key) {
+            Some(entry) if entry.stored.elapsed() < self.ttl => {
+                let previous = std::mem::replace(&mut entry.tick, tick);
+                Some((Arc::clone(&entry.result), previous))
+            }
+            _ => None,
+        };
+        if let Some((result, previous)) = cached {
+            state.order.remove(&previous);
+            state.order.insert(tick, key);
+            return Some(for_job(&result, job));
+        }
+
+        // Verwaiste Einträge (Ergebnis nie veröffentlicht) begrenzen
+        if state.pending.len() >= self.max_entries {
+            let ttl = self.ttl;
+            state.pending.retain(|_, (_, since)| since.elapsed() < ttl);
+        }
+        state.pending.insert(job.id.clone(), (key, Instant::now()));
+        None
+    }
+
+    /// Caches a published result if it belongs to a job that missed.
+    /// Error results are not cached.
+    pub fn complete(&self, result: &Arc<Value>) {
+        let Some(id) = result.get("id").and_then(Value::as_str) else { return };
+        let mut state = self.state.lock().unwrap();
+        let Some((key, _)) = state.pending.remove(id) else { return };
+        if result.get("error").is_some() {
+            return;
+        }
+
+        state.tick += 1;
+        let tick = state.tick;
+        let entry = Entry { result: Arc::clone(result), stored: Instant::now(), tick };
+        if let Some(old) = state.entries.insert(key, entry) {
+            state.order.remove(&old.tick);
+        }
+        state.order.insert(tick, key);
+        while state.entries.len() > self.max_entries {
+            let Some((_, oldest)) = state.order.pop_first() else { break };
+            state.entries.remove(&oldest);
+        }
+    }
+
+    /// Caches results as they are published until the runtime stops.
+    pub fn spawn_collector(self: &Arc<Self>) {
+        let cache = Arc::clone(self);
+        let mut results = crate::results::subscribe();
+        tokio::spawn(async move {
+            loop {
+                match results.recv().await {
+                    Ok(result) => cache.complete(&result),
+                    // Verpasste Ergebnisse werden nur nicht gecacht
+                    Err(tokio::sync::broadcast::error::RecvError::Lagged(_)) => continue,
+                    Err(tokio::sync::broadcast::error::RecvError::Closed) => break,
+                }
+            }
+        });
+    }
+}
+
+/// Copy of a cached result for another job with identical inputs.
+fn for_job(result: &Value, job: &Job) -> Value {
+    let mut result = result.clone();
+    result["id"] = job.id.clone().into();
+    result["timestamp"] = chrono::Utc::now().to_rfc3339().into();
+    result["cached"] = true.into();
+    if let Some(fields) = result.as_object_mut() {
+        if job.meta.is_empty() {
+            fields.remove("meta");
+        } else {
+            fields.insert("meta".to_string(), Value::Object(job.meta.clone()));
+        }
+    }
+    crate::trace_context::annotate(&mut result, &job.meta);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::{ArrayD, IxDyn};
+    use serde_json::json;
+
+    fn cache(max_entries: usize) -> ResultCache {
+        ResultCache::new(&CacheCfg { ttl_secs: 60, max_entries, model_version: None }, "model-a".into())
+    }
+
+    fn job(id: &str, value: f32) -> Job {
+        Job { id: id.to_string(), tensor: ArrayD::from_elem(IxDyn(&[2]), value), ..Default::default() }
+    }
+
+    #[test]
+    fn test_identical_input_hits_after_result() {
+        let cache = cache(8);
+        assert!(cache.lookup(&job("a", 1.0)).is_none());
+        // Ergebnis steht noch aus: weiterhin Miss
+        assert!(cache.lookup(&job("b", 1.0)).is_none());
+
+        cache.complete(&Arc::new(json!({"id": "a", "top_k": [1], "meta": {"k": "v"}})));
+        let mut c = job("c", 1.0);
+        c.meta.insert("src".into(), "x".into());
+        let hit = cache.lookup(&c).unwrap();
+        assert_eq!(hit["id"], "c");
+        assert_eq!(hit["top_k"], json!([1]));
+        assert_eq!(hit["cached"], true);
+        assert_eq!(hit["meta"], json!({"src": "x"}));
+
+        assert!(cache.lookup(&job("d", 2.0)).is_none());
+    }
+
+    #[test]
+    fn test_errors_are_not_cached_and_lru_eviction() {
+        let cache = cache(2);
+        assert!(cache.lookup(&job("e", 9.0)).is_none());
+        cache.complete(&Arc::new(json!({"id": "e", "error": "kein Worker verfügbar"})));
+        assert!(cache.lookup(&job("e2", 9.0)).is_none());
+
+        for (id, v) in [("a", 1.0), ("b", 2.0), ("c", 3.0)] {
+            assert!(cache.lookup(&job(id, v)).is_none());
+            cache.complete(&Arc::new(json!({"id": id})));
+        }
+        // Ältester Eintrag (a) verdrängt
+        assert!(cache.lookup(&job("a2", 1.0)).is_none());
+        assert!(cache.lookup(&job("c2", 3.0)).is_some());
+    }
+
+    #[test]
+    fn test_tta_decision_is_part_of_the_key() {
+        let cfg: Config = toml::from_str(
+            r#"
+            [model]
+            backend = "onnx"
+            device = "cpu"
+            model_path = "model.onnx"
+            input_names = ["image"]
+            input_shapes = [[0, 3, 4, 4]]
+            output_names = ["logits"]
+            output_shapes = [[0, 10]]
+            [queue]
+            max_batch = 2
+            max_wait_ms = 5
+            [redis]
+            url = "memory://"
+            out_prefix = "results"
+            [input]
+            batch = 2
+            channels = 3
+            height = 4
+            width = 4
+            dtype = "f32"
+            [tta]
+            transforms = ["hflip"]
+            [cache]
+            model_version = "v1"
+            "#,
+        )
+        .unwrap();
+        let cache = ResultCache::from_config(&cfg).unwrap().unwrap();
+        let with_tta = |id: &str, tta: bool| {
+            let mut j = job(id, 1.0);
+            j.meta.insert("tta".into(), tta.into());
+            j
+        };
+
+        assert!(cache.lookup(&with_tta("plain", false)).is_none());
+        cache.complete(&Arc::new(json!({"id": "plain", "top_k": [1]})));
+        // Augmentierte Jobs bekommen nicht das Ergebnis ohne TTA, und umgekehrt
+        assert!(cache.lookup(&with_tta("augmented", true)).is_none());
+        cache.complete(&Arc::new(json!({"id": "augmented", "top_k": [2]})));
+        assert_eq!(cache.lookup(&with_tta("plain2", false)).unwrap()["top_k"], json!([1]));
+        assert_eq!(cache.lookup(&with_tta("augmented2", true)).unwrap()["top_k"], json!([2]));
+        // Ohne meta.tta gilt [tta] default (false)
+        assert_eq!(cache.lookup(&job("default", 1.0)).unwrap()["top_k"], json!([1]));
+    }
+
+    #[test]
+    fn test_model_version_is_part_of_the_key() {
+        let a = cache(8);
+        let b = ResultCache::new(&CacheCfg { ttl_secs: 60, max_entries: 8, model_version: None }, "model-b".into());
+        assert_ne!(a.key(&job("x", 1.0)), b.key(&job("x", 1.0)));
+    }
+}
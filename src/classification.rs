@@ -0,0 +1,113 @@
+//! Top-k classification results.
+//!
+//! Turns per-sample logits into the `top_k` most probable classes with
+//! index, label and softmax probability, instead of dumping raw logits.
+
+use std::fs;
+
+use anyhow::{Context, Result};
+use ndarray::ArrayViewD;
+use serde::Serialize;
+use serde_json::{json, Value};
+
+use crate::pipeline::OutputFormatter;
+use crate::processors::softmax;
+use crate::types::ClassificationCfg;
+
+/// A single ranked class.
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct ClassScore {
+    pub class_id: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    pub score: f32,
+}
+
+/// Returns the `k` highest scores in descending order.
+pub fn top_k(scores: &[f32], k: usize) -> Vec<(usize, f32)> {
+    let mut ranked: Vec<(usize, f32)> = scores.iter().copied().enumerate().collect();
+    ranked.sort_by(|a, b| b.1.total_cmp(&a.1));
+    ranked.truncate(k);
+    ranked
+}
+
+/// Output formatter producing `{"top_k": [...]}` results.
+pub struct ClassificationOutput {
+    top_k: usize,
+    softmax: bool,
+    labels: Vec<String>,
+}
+
+impl ClassificationOutput {
+    /// Creates the formatter from the `[classification]` configuration.
+    pub fn new(cfg: &ClassificationCfg) -> Result<Self> {
+        anyhow::ensure!(cfg.top_k > 0, "top_k muss > 0 sein");
+        let labels = match &cfg.labels_path {
+            Some(path) => fs::read_to_string(path)
+                .with_context(|| format!("Label-Datei konnte nicht geladen werden: {}", path))?
+                .lines()
+                .map(|l| l.trim().to_string())
+                .collect(),
+            None => cfg.labels.clone(),
+        };
+        Ok(Self { top_k: cfg.top_k, softmax: cfg.softmax, labels })
+    }
+
+    /// Ranks the classes of one sample output (`[C]` or `[1, C]`).
+    pub fn rank(&self, output: ArrayViewD<f32>) -> Vec<ClassScore> {
+        let scores: Vec<f32> = if self.softmax {
+            softmax(output.iter().copied())
+        } else {
+            output.iter().copied().collect()
+        };
+        top_k(&scores, self.top_k)
+            .into_iter()
+            .map(|(class_id, score)| ClassScore { class_id, label: self.labels.get(class_id).cloned(), score })
+            .collect()
+    }
+}
+
+impl OutputFormatter for ClassificationOutput {
+    fn format(&self, output: ArrayViewD<f32>) -> Result<Value> {
+        Ok(json!({ "top_k": self.rank(output) }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::Array;
+
+    fn cfg(top_k: usize) -> ClassificationCfg {
+        ClassificationCfg {
+            top_k,
+            softmax: true,
+            labels: vec!["cat".into(), "dog".into(), "bird".into()],
+            labels_path: None,
+        }
+    }
+
+    #[test]
+    fn test_top_k_order_and_probabilities() {
+        let logits = Array::from_vec(vec![1.0f32, 3.0, 2.0]).into_dyn();
+        let ranked = ClassificationOutput::new(&cfg(2)).unwrap().rank(logits.view());
+
+        assert_eq!(ranked.len(), 2);
+        assert_eq!(ranked[0].label.as_deref(), Some("dog"));
+        assert_eq!(ranked[1].class_id, 2);
+        assert!(ranked[0].score > ranked[1].score);
+
+        let all = ClassificationOutput::new(&cfg(10)).unwrap().rank(logits.view());
+        let total: f32 = all.iter().map(|c| c.score).sum();
+        assert!((total - 1.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_format_without_softmax() {
+        let out = ClassificationOutput::new(&ClassificationCfg { softmax: false, ..cfg(1) }).unwrap();
+        let probs = Array::from_shape_vec((1, 3), vec![0.2f32, 0.1, 0.7]).unwrap().into_dyn();
+        let v = out.format(probs.view()).unwrap();
+        assert_eq!(v["top_k"][0]["label"], "bird");
+        assert!((v["top_k"][0]["score"].as_f64().unwrap() - 0.7).abs() < 1e-6);
+    }
+}
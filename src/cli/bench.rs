@@ -0,0 +1,162 @@
+//! `bench`: synthetic load against the local engine.
+
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use serde::Serialize;
+use tokio::sync::mpsc;
+use tracing::info;
+
+use crate::types::{Batch, Config, Job};
+
+/// Load parameters of a benchmark run.
+#[derive(Debug, Clone)]
+pub struct BenchOptions {
+    /// Number of synthetic jobs.
+    pub requests: usize,
+    /// Offered load in jobs per second (`0` = as fast as possible).
+    pub rate: f64,
+    /// Batches run before measuring (not counted).
+    pub warmup: usize,
+}
+
+/// Result of a benchmark run. Latencies are end-to-end per job (submission
+/// until formatted result) in milliseconds.
+#[derive(Debug, Clone, Serialize)]
+pub struct BenchReport {
+    pub requests: usize,
+    pub batches: usize,
+    pub elapsed_secs: f64,
+    pub throughput: f64,
+    pub mean_ms: f64,
+    pub p50_ms: f64,
+    pub p90_ms: f64,
+    pub p99_ms: f64,
+    pub max_ms: f64,
+}
+
+impl fmt::Display for BenchReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Jobs:       {} in {} Batches", self.requests, self.batches)?;
+        writeln!(f, "Dauer:      {:.2} s", self.elapsed_secs)?;
+        writeln!(f, "Durchsatz:  {:.1} Jobs/s", self.throughput)?;
+        writeln!(f, "Latenz:     mean {:.2} ms", self.mean_ms)?;
+        write!(
+            f,
+            "            p50 {:.2} ms | p90 {:.2} ms | p99 {:.2} ms | max {:.2} ms",
+            self.p50_ms, self.p90_ms, self.p99_ms, self.max_ms
+        )
+    }
+}
+
+/// Nearest-rank percentile (`p` in 0..=100) of ascending sorted values.
+fn percentile(sorted: &[f64], p: f64) -> f64 {
+    if sorted.is_empty() {
+        return 0.0;
+    }
+    let rank = ((p / 100.0) * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+/// Builds a synthetic job matching the configured input.
+pub(super) fn synthetic_job(cfg: &Config, encoder: Option<&crate::text::TextEncoder>, k: usize) -> Result<Job> {
+    crate::loadgen::synthetic_job(cfg, encoder, format!("bench-{}", k), None)
+}
+
+/// Runs the benchmark: a producer offers synthetic jobs at the requested
+/// rate, the batching/inference loop processes them like a worker would
+/// (without storing results).
+pub async fn bench(config_path: &str, opts: &BenchOptions) -> Result<BenchReport> {
+    let cfg = crate::load_config(config_path)?;
+    anyhow::ensure!(cfg.generation.is_none(), "bench unterstützt keine generativen Modelle ([generation])");
+    anyhow::ensure!(opts.requests > 0, "requests muss > 0 sein");
+    let spec = cfg.input_spec();
+    let (pipeline, text_encoder) = crate::build_pipeline(&cfg)?;
+    let mut engine = super::local_engine(&cfg)?;
+    let max_batch = cfg.queue.max_batch.min(spec.batch);
+
+    // Warmup mit vollen Batches
+    for w in 0..opts.warmup {
+        let mut jobs = Vec::with_capacity(spec.batch);
+        for k in 0..spec.batch {
+            jobs.push(synthetic_job(&cfg, text_encoder.as_ref(), w * spec.batch + k)?);
+        }
+        let (tx, mut rx) = mpsc::channel(spec.batch);
+        for job in jobs {
+            tx.send(job).await?;
+        }
+        drop(tx);
+        if let Some(batch) = crate::batcher::collect_batch(spec.batch, &mut rx, max_batch, 0).await? {
+            crate::worker::infer_batch(engine.as_mut(), &pipeline, &cfg, batch.tensor, batch.inputs)?;
+        }
+    }
+    info!("Benchmark: {} Jobs, Rate {}", opts.requests, if opts.rate > 0.0 { opts.rate.to_string() } else { "max".into() });
+
+    let mut jobs = Vec::with_capacity(opts.requests);
+    for k in 0..opts.requests {
+        jobs.push(synthetic_job(&cfg, text_encoder.as_ref(), k)?);
+    }
+
+    let (tx, mut rx) = mpsc::channel::<Job>(1024);
+    let start = Instant::now();
+    let rate = opts.rate;
+    tokio::spawn(async move {
+        for (k, mut job) in jobs.into_iter().enumerate() {
+            if rate > 0.0 {
+                let due = start + Duration::from_secs_f64(k as f64 / rate);
+                tokio::time::sleep_until(due.into()).await;
+            }
+            // Sendezeitpunkt relativ zum Start für die Latenzmessung
+            job.meta.insert("sent_us".to_string(), (start.elapsed().as_micros() as u64).into());
+            if tx.send(job).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    let mut latencies = Vec::with_capacity(opts.requests);
+    let mut batches = 0;
+    while let Some(batch) = crate::batcher::collect_batch(spec.batch, &mut rx, max_batch, cfg.queue.max_wait_ms).await? {
+        let Batch { ids, tensor, actual_len, metas, inputs, .. } = batch;
+        let y = crate::worker::infer_batch(engine.as_mut(), &pipeline, &cfg, tensor, inputs)?;
+        let batch = Batch { ids, actual_len, metas, ..Default::default() };
+        crate::worker::format_results(&batch, &y, pipeline.output.as_ref())?;
+
+        let done_us = start.elapsed().as_micros() as f64;
+        for meta in batch.metas.iter().take(actual_len) {
+            let sent_us = meta.get("sent_us").and_then(|v| v.as_f64()).unwrap_or(0.0);
+            latencies.push((done_us - sent_us) / 1000.0);
+        }
+        batches += 1;
+    }
+    let elapsed = start.elapsed().as_secs_f64();
+
+    latencies.sort_by(f64::total_cmp);
+    Ok(BenchReport {
+        requests: latencies.len(),
+        batches,
+        elapsed_secs: elapsed,
+        throughput: latencies.len() as f64 / elapsed.max(f64::EPSILON),
+        mean_ms: latencies.iter().sum::<f64>() / latencies.len().max(1) as f64,
+        p50_ms: percentile(&latencies, 50.0),
+        p90_ms: percentile(&latencies, 90.0),
+        p99_ms: percentile(&latencies, 99.0),
+        max_ms: latencies.last().copied().unwrap_or(0.0),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_percentile_nearest_rank() {
+        let values: Vec<f64> = (1..=100).map(|v| v as f64).collect();
+        assert_eq!(percentile(&values, 50.0), 50.0);
+        assert_eq!(percentile(&values, 99.0), 99.0);
+        assert_eq!(percentile(&values, 100.0), 100.0);
+        assert_eq!(percentile(&[3.0], 90.0), 3.0);
+        assert_eq!(percentile(&[], 50.0), 0.0);
+    }
+}
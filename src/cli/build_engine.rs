@@ -0,0 +1,177 @@
+//! `build-engine`: ONNX → TensorRT engine conversion with an on-disk cache.
+//!
+//! The build runs NVIDIA's `trtexec`. The engine is written next to the ONNX
+//! model as `<stem>.<precision>.<hash>.engine`, where the hash covers the
+//! model bytes and all build flags; an existing file with the same name is
+//! reused, so repeated deployments skip the multi-minute build.
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use anyhow::{Context, Result};
+use tracing::info;
+
+use crate::types::Config;
+
+/// Build parameters of `build-engine`.
+#[derive(Debug, Clone)]
+pub struct BuildEngineOptions {
+    /// ONNX model (default: `model.model_path`).
+    pub onnx: Option<PathBuf>,
+    /// Target file (default: cache path next to the model).
+    pub output: Option<PathBuf>,
+    /// `fp32`, `fp16`, `int8` or `best`.
+    pub precision: String,
+    /// Builder workspace in MiB.
+    pub workspace_mb: usize,
+    /// Optimization profile in trtexec syntax (`input:1x3x224x224,...`);
+    /// derived from the config when unset.
+    pub min_shapes: Option<String>,
+    pub opt_shapes: Option<String>,
+    pub max_shapes: Option<String>,
+    /// GPU to build on (engines are specific to the GPU model).
+    pub device: usize,
+    /// Path of the `trtexec` binary.
+    pub trtexec: String,
+    /// Rebuild even if a cached engine exists.
+    pub force: bool,
+}
+
+/// Optimization profile as trtexec shape strings (min, opt, max).
+fn default_profile(cfg: &Config) -> (String, String, String) {
+    let batch = cfg.input.batch.max(1);
+    let profile = |dynamic: usize| {
+        cfg.model
+            .input_names
+            .iter()
+            .zip(&cfg.model.input_shapes)
+            .map(|(name, shape)| {
+                // Dynamische Achsen (0) für min auf 1, für opt/max auf die Batchgröße
+                let dims: Vec<String> = shape.iter().map(|&d| if d == 0 { dynamic } else { d }.to_string()).collect();
+                format!("{}:{}", name, dims.join("x"))
+            })
+            .collect::<Vec<_>>()
+            .join(",")
+    };
+    (profile(1), profile(batch), profile(batch))
+}
+
+/// FNV-1a, stable across builds and platforms (cache keys on disk).
+fn fnv1a(hash: u64, bytes: &[u8]) -> u64 {
+    bytes.iter().fold(hash, |h, &b| (h ^ b as u64).wrapping_mul(0x100000001b3))
+}
+
+/// Cache path of the engine for `onnx` built with `args`.
+fn cache_path(onnx: &Path, model_bytes: &[u8], precision: &str, args: &[String]) -> PathBuf {
+    let mut hash = fnv1a(0xcbf29ce484222325, model_bytes);
+    for arg in args {
+        hash = fnv1a(hash, arg.as_bytes());
+    }
+    let stem = onnx.file_stem().and_then(|s| s.to_str()).unwrap_or("model");
+    onnx.with_file_name(format!("{}.{}.{:08x}.engine", stem, precision, hash as u32))
+}
+
+/// trtexec flags for everything except input and output paths.
+fn build_args(opts: &BuildEngineOptions, profile: (String, String, String)) -> Result<Vec<String>> {
+    let mut args = match opts.precision.as_str() {
+        "fp32" => vec![],
+        "fp16" => vec!["--fp16".to_string()],
+        "int8" => vec!["--int8".to_string()],
+        "best" => vec!["--best".to_string()],
+        other => anyhow::bail!("Unbekannte Präzision '{}' (fp32, fp16, int8, best)", other),
+    };
+    args.push(format!("--memPoolSize=workspace:{}", opts.workspace_mb));
+    args.push(format!("--device={}", opts.device));
+    let (min, opt, max) = profile;
+    args.push(format!("--minShapes={}", opts.min_shapes.clone().unwrap_or(min)));
+    args.push(format!("--optShapes={}", opts.opt_shapes.clone().unwrap_or(opt)));
+    args.push(format!("--maxShapes={}", opts.max_shapes.clone().unwrap_or(max)));
+    Ok(args)
+}
+
+/// Builds (or reuses) the TensorRT engine and returns its path.
+pub fn build_engine(config_path: &str, opts: &BuildEngineOptions) -> Result<PathBuf> {
+    let cfg = crate::load_config(config_path)?;
+    let onnx = match &opts.onnx {
+        Some(p) => p.clone(),
+        None => {
+            anyhow::ensure!(
+                cfg.model.model_path.ends_with(".onnx"),
+                "model.model_path ist kein ONNX-Modell, bitte --onnx angeben"
+            );
+            PathBuf::from(&cfg.model.model_path)
+        }
+    };
+    let model = std::fs::read(&onnx).with_context(|| format!("ONNX-Modell konnte nicht gelesen werden: {}", onnx.display()))?;
+
+    let args = build_args(opts, default_profile(&cfg))?;
+    let target = opts.output.clone().unwrap_or_else(|| cache_path(&onnx, &model, &opts.precision, &args));
+    if target.exists() && !opts.force {
+        info!("Engine aus Cache: {}", target.display());
+        return Ok(target);
+    }
+
+    // In temporäre Datei bauen und erst bei Erfolg umbenennen
+    let partial = target.with_extension("engine.partial");
+    info!("Baue TensorRT-Engine {} ({})", target.display(), opts.precision);
+    let status = Command::new(&opts.trtexec)
+        .arg(format!("--onnx={}", onnx.display()))
+        .arg(format!("--saveEngine={}", partial.display()))
+        .args(&args)
+        .status()
+        .with_context(|| format!("'{}' konnte nicht gestartet werden (TensorRT installiert?)", opts.trtexec))?;
+    if !status.success() {
+        let _ = std::fs::remove_file(&partial);
+        anyhow::bail!("trtexec fehlgeschlagen: {}", status);
+    }
+    std::fs::rename(&partial, &target)?;
+    Ok(target)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn opts(precision: &str) -> BuildEngineOptions {
+        BuildEngineOptions {
+            onnx: None,
+            output: None,
+            precision: precision.to_string(),
+            workspace_mb: 4096,
+            min_shapes: None,
+            opt_shapes: None,
+            max_shapes: Some("input:16x3x224x224".to_string()),
+            device: 0,
+            trtexec: "trtexec".to_string(),
+            force: false,
+        }
+    }
+
+    fn profile() -> (String, String, String) {
+        ("input:1x3x224x224".into(), "input:8x3x224x224".into(), "input:8x3x224x224".into())
+    }
+
+    #[test]
+    fn test_build_args_precision_and_profile() {
+        let args = build_args(&opts("fp16"), profile()).unwrap();
+        assert_eq!(args[0], "--fp16");
+        assert!(args.contains(&"--memPoolSize=workspace:4096".to_string()));
+        assert!(args.contains(&"--minShapes=input:1x3x224x224".to_string()));
+        assert!(args.contains(&"--maxShapes=input:16x3x224x224".to_string()));
+        assert!(build_args(&opts("fp8"), profile()).is_err());
+    }
+
+    #[test]
+    fn test_cache_path_depends_on_model_and_flags() {
+        let onnx = Path::new("/models/resnet.onnx");
+        let fp16 = build_args(&opts("fp16"), profile()).unwrap();
+        let fp32 = build_args(&opts("fp32"), profile()).unwrap();
+
+        let a = cache_path(onnx, b"model-v1", "fp16", &fp16);
+        assert_eq!(a.parent(), Some(Path::new("/models")));
+        assert!(a.file_name().unwrap().to_str().unwrap().starts_with("resnet.fp16."));
+        assert_eq!(a, cache_path(onnx, b"model-v1", "fp16", &fp16));
+        assert_ne!(a, cache_path(onnx, b"model-v2", "fp16", &fp16));
+        assert_ne!(cache_path(onnx, b"model-v1", "fp32", &fp32), cache_path(onnx, b"model-v1", "fp32", &fp16));
+    }
+}
@@ -0,0 +1,174 @@
+//! `demo`: zero-configuration local demo.
+//!
+//! Downloads a small public ONNX model (MNIST digit classifier) into the demo
+//! directory, writes a matching configuration with the in-process result
+//! store (`memory://`) and the HTTP API, starts the runtime and runs one
+//! sample digit through it. The runtime keeps serving afterwards, so the
+//! printed `curl` commands (with the sample as `sample.json`) can be tried
+//! right away. An existing configuration in the directory is reused, so it
+//! can be edited.
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use ndarray::{ArrayD, IxDyn};
+use serde_json::Value;
+
+use crate::types::Job;
+
+/// Public MNIST model of the ONNX model zoo (26 KB).
+pub const DEMO_MODEL_URL: &str =
+    "https://github.com/onnx/models/raw/main/validated/vision/classification/mnist/model/mnist-12.onnx";
+
+/// Options of the `demo` subcommand.
+#[derive(Debug, Clone)]
+pub struct DemoOptions {
+    /// Directory for the model and the generated configuration.
+    pub dir: PathBuf,
+    /// Download URL of the ONNX model.
+    pub model_url: String,
+    /// Address of the HTTP API.
+    pub bind: String,
+    /// Stop after the sample inference instead of serving.
+    pub once: bool,
+}
+
+/// Prepares the demo directory, starts the runtime and prints the result
+/// of a sample inference. Serves until the process is stopped unless
+/// `opts.once` is set.
+pub async fn demo(opts: &DemoOptions) -> Result<()> {
+    std::fs::create_dir_all(&opts.dir)
+        .with_context(|| format!("Demo-Verzeichnis {} konnte nicht angelegt werden", opts.dir.display()))?;
+    let model = opts.dir.join("mnist-12.onnx");
+    if !model.exists() {
+        println!("Lade Demo-Modell von {}", opts.model_url);
+        download(&opts.model_url, &model)?;
+    }
+    let config_path = opts.dir.join("runtime.toml");
+    if !config_path.exists() {
+        std::fs::write(&config_path, demo_config(&model, &opts.bind))
+            .with_context(|| format!("{} konnte nicht geschrieben werden", config_path.display()))?;
+    }
+    let sample = opts.dir.join("sample.json");
+    std::fs::write(&sample, sample_request())?;
+    println!("Konfiguration: {}", config_path.display());
+
+    let cfg = crate::load_config(&config_path.to_string_lossy())?;
+    let (pipeline, _) = crate::build_pipeline(&cfg)?;
+    let runtime = crate::Runtime::start(&cfg, pipeline).await?;
+    crate::server::spawn_servers(&cfg, &runtime.store, &runtime.tx, None)?;
+
+    // Vor dem Einreihen abonnieren, sonst kann das Ergebnis verpasst werden
+    let mut results = crate::results::subscribe();
+    let job = Job { id: "demo-1".to_string(), tensor: sample_digit(), ..Default::default() };
+    runtime.tx.send(job).await.context("Runtime nimmt keine Jobs an")?;
+    let result = crate::server::infer::wait_for(&mut results, "demo-1", Duration::from_secs(30))
+        .await
+        .context("Kein Ergebnis für die Beispiel-Inferenz innerhalb von 30 s")?;
+    println!("Beispiel-Inferenz (eine gezeichnete 1):");
+    println!("{}", serde_json::to_string_pretty(result.as_ref())?);
+
+    if opts.once {
+        return Ok(());
+    }
+    let base = format!("http://{}", opts.bind.replace("0.0.0.0", "localhost"));
+    println!();
+    println!("HTTP-API auf {} (Strg+C beendet):", base);
+    println!("  curl {}/v1/model", base);
+    println!("  curl \"{}/v1/results/demo-1\"", base);
+    println!("  curl -X POST \"{}/v1/infer?wait_ms=5000\" -d @{}", base, sample.display());
+    runtime.join().await;
+    Ok(())
+}
+
+/// Downloads `url` to `target` with `curl`.
+fn download(url: &str, target: &Path) -> Result<()> {
+    let partial = target.with_extension("part");
+    let status = Command::new("curl")
+        .args(["--fail", "--location", "--silent", "--show-error", "--output"])
+        .arg(&partial)
+        .arg(url)
+        .status()
+        .context("curl konnte nicht gestartet werden; Modell manuell herunterladen")?;
+    anyhow::ensure!(status.success(), "Download von {} fehlgeschlagen ({})", url, status);
+    std::fs::rename(&partial, target)?;
+    Ok(())
+}
+
+/// Runtime configuration for the MNIST model at `model`.
+fn demo_config(model: &Path, bind: &str) -> String {
+    let labels: Vec<String> = (0..10).map(|d| format!("\"{}\"", d)).collect();
+    format!(
+        r#"# Generated by `omniengine demo`; edit freely, it is reused.
+
+[model]
+backend = "onnx"
+device = "cpu"
+model_path = "{model}"
+input_names = ["Input3"]
+input_shapes = [[1, 1, 28, 28]]
+output_names = ["Plus214_Output_0"]
+output_shapes = [[1, 10]]
+
+[input]
+batch = 1
+channels = 1
+height = 28
+width = 28
+dtype = "f32"
+
+[queue]
+max_batch = 1
+max_wait_ms = 1
+
+# Results stay in this process, no Redis needed
+[redis]
+url = "memory://"
+out_prefix = "demo"
+
+[classification]
+top_k = 3
+labels = [{labels}]
+
+[server.http]
+bind = "{bind}"
+
+[server.http.infer]
+"#,
+        model = model.display().to_string().replace('\\', "/"),
+        labels = labels.join(", "),
+        bind = bind,
+    )
+}
+
+/// A `1` drawn on the 28×28 MNIST canvas (white on black, 0-255).
+fn sample_digit() -> ArrayD<f32> {
+    ArrayD::from_shape_fn(IxDyn(&[1, 28, 28]), |i| {
+        let (row, col) = (i[1], i[2]);
+        if (5..23).contains(&row) && (13..16).contains(&col) { 255.0 } else { 0.0 }
+    })
+}
+
+/// The sample digit as `POST /v1/infer` body.
+fn sample_request() -> String {
+    let digit = sample_digit();
+    let request: Value = serde_json::json!({ "shape": digit.shape(), "data": digit.iter().collect::<Vec<_>>() });
+    request.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_demo_config_is_valid() {
+        let cfg: crate::types::Config = toml::from_str(&demo_config(Path::new("demo/mnist-12.onnx"), "0.0.0.0:8000")).unwrap();
+        assert_eq!(cfg.redis.url, crate::storage::redis_store::MEMORY_URL);
+        assert_eq!(cfg.input_spec().rank().unwrap(), 4);
+        assert_eq!(cfg.classification.unwrap().labels.len(), 10);
+        assert!(cfg.server.http.unwrap().infer.is_some());
+        assert_eq!(sample_digit().sum(), 255.0 * 18.0 * 3.0);
+    }
+}
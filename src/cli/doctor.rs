@@ -0,0 +1,340 @@
+//! `doctor`: startup self-test with a diagnostics report.
+//!
+//! Runs the checks a support ticket needs, in order: configuration, built-in
+//! backends, GPU driver, Redis, ONNX operator preflight, memory estimate,
+//! model loading and one test inference. Later checks that depend on a failed one are skipped. The
+//! report prints as text or, with `--json`, as a machine-readable document.
+
+use std::fmt;
+use std::process::Command;
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+use serde_json::{json, Value};
+use tokio::sync::mpsc;
+
+use crate::types::{Batch, Config};
+
+/// Outcome of one check.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CheckStatus {
+    Ok,
+    Warn,
+    Fail,
+    Skip,
+}
+
+/// One diagnostics check.
+#[derive(Debug, Clone, Serialize)]
+pub struct Check {
+    pub name: &'static str,
+    pub status: CheckStatus,
+    pub detail: String,
+    /// Structured findings (versions, devices, shapes).
+    #[serde(skip_serializing_if = "Value::is_null")]
+    pub data: Value,
+    pub elapsed_ms: f64,
+}
+
+/// Report of `doctor`.
+#[derive(Debug, Clone, Serialize)]
+pub struct DoctorReport {
+    pub omniengine: &'static str,
+    pub os: &'static str,
+    pub arch: &'static str,
+    pub features: Vec<&'static str>,
+    pub checks: Vec<Check>,
+}
+
+impl DoctorReport {
+    /// Whether no check failed.
+    pub fn ok(&self) -> bool {
+        self.checks.iter().all(|c| c.status != CheckStatus::Fail)
+    }
+
+    fn run<T: Into<Value>>(&mut self, name: &'static str, check: impl FnOnce() -> Result<(CheckStatus, String, T)>) -> bool {
+        let started = Instant::now();
+        let (status, detail, data) = match check() {
+            Ok((status, detail, data)) => (status, detail, data.into()),
+            Err(e) => (CheckStatus::Fail, format!("{:#}", e), Value::Null),
+        };
+        let elapsed_ms = started.elapsed().as_secs_f64() * 1000.0;
+        self.checks.push(Check { name, status, detail, data, elapsed_ms });
+        status != CheckStatus::Fail
+    }
+
+    fn skip(&mut self, name: &'static str, reason: &str) {
+        self.checks.push(Check { name, status: CheckStatus::Skip, detail: reason.to_string(), data: Value::Null, elapsed_ms: 0.0 });
+    }
+}
+
+impl fmt::Display for DoctorReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "omniengine {} ({}/{}), Features: {}", self.omniengine, self.os, self.arch, self.features.join(", "))?;
+        for c in &self.checks {
+            let status = match c.status {
+                CheckStatus::Ok => "OK  ",
+                CheckStatus::Warn => "WARN",
+                CheckStatus::Fail => "FAIL",
+                CheckStatus::Skip => "SKIP",
+            };
+            writeln!(f, "{} {:<10} {}", status, c.name, c.detail)?;
+        }
+        let failed = self.checks.iter().filter(|c| c.status == CheckStatus::Fail).count();
+        write!(f, "{} Prüfungen, {} fehlgeschlagen", self.checks.len(), failed)
+    }
+}
+
+/// Backends and optional integrations compiled into this binary.
+fn compiled_features() -> Vec<&'static str> {
+    [
+        ("onnx", cfg!(feature = "onnx")),
+        ("onnx-cuda", cfg!(feature = "onnx-cuda")),
+        ("tensorrt", cfg!(feature = "tensorrt")),
+        ("torch", cfg!(feature = "torch")),
+        ("tensorflow", cfg!(feature = "tensorflow")),
+        ("python", cfg!(feature = "python")),
+        ("video", cfg!(feature = "video")),
+        ("zstd", cfg!(feature = "zstd")),
+        ("protobuf", cfg!(feature = "protobuf")),
+    ]
+    .into_iter()
+    .filter_map(|(name, on)| on.then_some(name))
+    .collect()
+}
+
+/// Model files to load: those of the stages for an ensemble.
+fn model_paths(cfg: &Config) -> Vec<&str> {
+    if cfg.model.stages.is_empty() {
+        vec![cfg.model.model_path.as_str()]
+    } else {
+        cfg.model.stages.iter().map(|s| s.model_path.as_str()).collect()
+    }
+}
+
+/// GPUs and driver as reported by `nvidia-smi`.
+fn gpu_info(required: bool) -> Result<(CheckStatus, String, Value)> {
+    let output = Command::new("nvidia-smi").args(["--query-gpu=index,name,driver_version,memory.total", "--format=csv,noheader"]).output();
+    let output = match output {
+        Ok(o) if o.status.success() => o,
+        Ok(o) => anyhow::bail!("nvidia-smi fehlgeschlagen: {}", String::from_utf8_lossy(&o.stderr).trim()),
+        Err(_) if !required => return Ok((CheckStatus::Skip, "nvidia-smi nicht gefunden, Gerät ist cpu".to_string(), Value::Null)),
+        Err(e) => return Err(e).context("nvidia-smi nicht gefunden, obwohl device = \"gpu\""),
+    };
+    let gpus = parse_gpus(&String::from_utf8_lossy(&output.stdout));
+    // CUDA-Version steht nur im Kopf der Standardausgabe
+    let cuda = Command::new("nvidia-smi")
+        .output()
+        .ok()
+        .and_then(|o| parse_cuda_version(&String::from_utf8_lossy(&o.stdout)));
+    let driver = gpus.first().and_then(|g| g["driver"].as_str()).unwrap_or("?").to_string();
+    let detail = format!("{} GPU(s), Treiber {}, CUDA {}", gpus.len(), driver, cuda.as_deref().unwrap_or("?"));
+    Ok((CheckStatus::Ok, detail, json!({ "cuda": cuda, "gpus": gpus })))
+}
+
+fn parse_gpus(csv: &str) -> Vec<Value> {
+    csv.lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split(',').map(str::trim).collect();
+            let [index, name, driver, memory] = fields.as_slice() else { return None };
+            Some(json!({ "index": index.parse::<usize>().ok(), "name": name, "driver": driver, "memory": memory }))
+        })
+        .collect()
+}
+
+/// Memory of the smallest GPU in the `gpu` check data (`"40960 MiB"`).
+fn smallest_gpu_mib(gpu: &Value) -> Option<u64> {
+    let gpus = gpu["gpus"].as_array()?;
+    gpus.iter().filter_map(|g| g["memory"].as_str()?.trim_end_matches("MiB").trim().parse().ok()).min()
+}
+
+fn parse_cuda_version(header: &str) -> Option<String> {
+    let rest = header.split("CUDA Version:").nth(1)?;
+    rest.split_whitespace().next().map(|v| v.trim_end_matches('|').to_string())
+}
+
+/// Connects to Redis and sends a PING.
+async fn ping_redis(url: &str) -> Result<String> {
+    let client = redis::Client::open(url)?;
+    let ping = async {
+        let mut conn = client.get_multiplexed_async_connection().await?;
+        redis::cmd("PING").query_async::<String>(&mut conn).await
+    };
+    tokio::time::timeout(Duration::from_secs(3), ping).await.context("Zeitüberschreitung nach 3 s")?.map_err(Into::into)
+}
+
+/// Runs all checks. `device` overrides the configured device like in `infer`.
+pub async fn doctor(config_path: &str, device: Option<&str>) -> DoctorReport {
+    let mut report = DoctorReport {
+        omniengine: env!("CARGO_PKG_VERSION"),
+        os: std::env::consts::OS,
+        arch: std::env::consts::ARCH,
+        features: compiled_features(),
+        checks: Vec::new(),
+    };
+
+    let mut cfg: Option<Config> = None;
+    report.run("config", || {
+        let mut loaded = crate::load_config(config_path)?;
+        super::select_device(&mut loaded, device)?;
+        let detail = format!("{}: backend {}, device {}", config_path, loaded.model.backend, super::device_name(&loaded));
+        cfg = Some(loaded);
+        Ok((CheckStatus::Ok, detail, Value::Null))
+    });
+    let Some(cfg) = cfg else {
+        for name in ["backend", "gpu", "redis", "preflight", "memory", "model", "inference"] {
+            report.skip(name, "Konfiguration fehlt");
+        }
+        return report;
+    };
+
+    let backend_ok = report.run("backend", || {
+        let backend = cfg.model.backend.as_str();
+        // Ein Ensemble braucht die Backends seiner Stufen
+        for backend in std::iter::once(backend).chain(cfg.model.stages.iter().map(|s| s.backend.as_str())).filter(|b| *b != "ensemble") {
+            anyhow::ensure!(
+                compiled_features().contains(&backend),
+                "Backend '{}' ist nicht einkompiliert (Features: {})",
+                backend,
+                compiled_features().join(", ")
+            );
+        }
+        let version = crate::engine::backend_version(backend);
+        let detail = format!("{} {}", backend, version.as_deref().unwrap_or("(Version unbekannt)"));
+        Ok((CheckStatus::Ok, detail, json!({ "backend": backend, "version": version })))
+    });
+
+    report.run("gpu", || gpu_info(cfg.model.device == "gpu"));
+    let gpu_memory_mib = report.checks.last().and_then(|gpu| smallest_gpu_mib(&gpu.data));
+
+    if cfg.redis.url == crate::storage::redis_store::MEMORY_URL {
+        report.skip("redis", "Ergebnisse im Prozess (memory://)");
+    } else {
+        let started = Instant::now();
+        let redis = ping_redis(&cfg.redis.url).await;
+        let latency_ms = started.elapsed().as_secs_f64() * 1000.0;
+        report.run("redis", || {
+            let reply = redis.with_context(|| format!("{} nicht erreichbar", cfg.redis.url))?;
+            Ok((CheckStatus::Ok, format!("{} antwortet {} ({:.1} ms)", cfg.redis.url, reply, latency_ms), Value::Null))
+        });
+    }
+
+    if cfg.model.backend != "onnx" {
+        report.skip("preflight", "nur für ONNX");
+    } else if !cfg.model.preflight {
+        report.skip("preflight", "deaktiviert (preflight = false)");
+    } else {
+        report.run("preflight", || {
+            let checked = crate::engine::preflight::check_file(&cfg)?;
+            checked.ensure_supported()?;
+            let opsets: Vec<String> = checked.opsets.iter().map(|(d, v)| format!("{} {}", d, v)).collect();
+            let (status, detail) = if checked.cpu_fallback.is_empty() {
+                (CheckStatus::Ok, format!("{}: Opsets {}", checked.provider, opsets.join(", ")))
+            } else {
+                (CheckStatus::Warn, format!("auf der CPU statt {}: {}", checked.provider, checked.cpu_fallback.join(", ")))
+            };
+            Ok((status, detail, serde_json::to_value(&checked)?))
+        });
+    }
+
+    report.run("memory", || {
+        let batch = cfg.queue.max_batch;
+        let estimate = crate::engine::memory::estimate(&cfg, batch, None, None)?;
+        let total_mib = estimate.total_bytes >> 20;
+        let mut detail = format!("~{} MiB bei Batch {}", total_mib, batch);
+        let mut status = CheckStatus::Ok;
+        if let Some(gpu_mib) = gpu_memory_mib.filter(|&gpu_mib| cfg.model.device == "gpu" && total_mib > gpu_mib) {
+            status = CheckStatus::Warn;
+            detail = format!("{}, mehr als die {} MiB der GPU", detail, gpu_mib);
+        }
+        if let Some(note) = estimate.notes.first() {
+            detail = format!("{} ({})", detail, note);
+        }
+        Ok((status, detail, serde_json::to_value(&estimate)?))
+    });
+
+    if !backend_ok {
+        report.skip("model", "Backend fehlt");
+        report.skip("inference", "Backend fehlt");
+        return report;
+    }
+    let mut engine = None;
+    report.run("model", || {
+        for path in model_paths(&cfg) {
+            anyhow::ensure!(std::path::Path::new(path).exists(), "Modelldatei {} fehlt", path);
+        }
+        let loaded = super::local_engine(&cfg)?;
+        let capabilities = serde_json::to_value(loaded.capabilities())?;
+        let detail = format!("{} geladen ({})", cfg.model.model_path, loaded.name());
+        engine = Some(loaded);
+        Ok((CheckStatus::Ok, detail, json!({ "capabilities": capabilities })))
+    });
+    let Some(mut engine) = engine else {
+        report.skip("inference", "Modell nicht geladen");
+        return report;
+    };
+
+    let inference = test_inference(&cfg, engine.as_mut()).await;
+    report.run("inference", || inference);
+    report
+}
+
+/// Runs one synthetic job through pipeline and engine.
+async fn test_inference(cfg: &Config, engine: &mut dyn crate::engine::Engine) -> Result<(CheckStatus, String, Value)> {
+    anyhow::ensure!(cfg.generation.is_none(), "Testinferenz für generative Modelle nicht unterstützt");
+    let spec = cfg.input_spec();
+    let (pipeline, text_encoder) = crate::build_pipeline(cfg)?;
+    let job = super::bench::synthetic_job(cfg, text_encoder.as_ref(), 0)?;
+
+    let started = Instant::now();
+    let (tx, mut rx) = mpsc::channel(1);
+    tx.send(job).await?;
+    drop(tx);
+    let batch = crate::batcher::collect_batch(spec.batch, &mut rx, 1, 0).await?.context("Kein Job erzeugt")?;
+    let Batch { tensor, inputs, .. } = batch;
+    let y = crate::worker::infer_batch(engine, &pipeline, cfg, tensor, inputs)?;
+    let elapsed_ms = started.elapsed().as_secs_f64() * 1000.0;
+
+    let non_finite = y.iter().filter(|v| !v.is_finite()).count();
+    let data = json!({ "output_shape": y.shape(), "latency_ms": elapsed_ms, "non_finite": non_finite });
+    if non_finite > 0 {
+        return Ok((CheckStatus::Warn, format!("Output {:?} enthält {} NaN/Inf-Werte", y.shape(), non_finite), data));
+    }
+    Ok((CheckStatus::Ok, format!("Output {:?} in {:.1} ms", y.shape(), elapsed_ms), data))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_nvidia_smi() {
+        let gpus = parse_gpus("0, NVIDIA A100-SXM4-40GB, 550.54.15, 40960 MiB\n1, NVIDIA A100-SXM4-40GB, 550.54.15, 40960 MiB\n");
+        assert_eq!(gpus.len(), 2);
+        assert_eq!(gpus[1]["index"], 1);
+        assert_eq!(gpus[0]["driver"], "550.54.15");
+        assert_eq!(smallest_gpu_mib(&json!({ "gpus": gpus })), Some(40960));
+        assert_eq!(smallest_gpu_mib(&Value::Null), None);
+
+        let header = "| NVIDIA-SMI 550.54.15   Driver Version: 550.54.15   CUDA Version: 12.4     |";
+        assert_eq!(parse_cuda_version(header).as_deref(), Some("12.4"));
+        assert_eq!(parse_cuda_version("kein GPU"), None);
+    }
+
+    #[test]
+    fn test_failed_check_fails_report() {
+        let mut report = DoctorReport { omniengine: "0.0.0", os: "linux", arch: "x86_64", features: vec!["onnx"], checks: Vec::new() };
+        assert!(report.run("config", || Ok((CheckStatus::Ok, "runtime.toml".to_string(), Value::Null))));
+        assert!(!report.run("redis", || -> Result<(CheckStatus, String, Value)> { anyhow::bail!("nicht erreichbar") }));
+        report.skip("model", "Backend fehlt");
+        assert!(!report.ok());
+
+        let json = serde_json::to_value(&report).unwrap();
+        assert_eq!(json["checks"][1]["status"], "fail");
+        assert_eq!(json["checks"][1]["detail"], "nicht erreichbar");
+        assert!(json["checks"][0].get("data").is_none());
+        assert!(report.to_string().contains("SKIP model"));
+    }
+}
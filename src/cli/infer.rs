@@ -0,0 +1,204 @@
+//! `infer`: one-shot local inference.
+
+use std::path::Path;
+use std::time::Instant;
+
+use anyhow::{Context, Result};
+use image::imageops::FilterType;
+use ndarray::{Array3, ArrayD, Axis};
+use serde::Serialize;
+use serde_json::Value;
+use tokio::sync::mpsc;
+
+use crate::text::{TextEncoder, TextJob};
+use crate::types::{Batch, Config, InputSpec, Job, JobMeta};
+
+/// Result of a one-shot inference.
+#[derive(Debug, Clone, Serialize)]
+pub struct InferReport {
+    /// Input file.
+    pub input: String,
+    /// Device the engine ran on (`cpu`, `gpu:N`).
+    pub device: String,
+    /// Shape of the loaded input sample (before batching).
+    pub input_shape: Vec<usize>,
+    /// Wall time of pre→infer→post and formatting.
+    pub elapsed_ms: f64,
+    /// Result payload as it would be stored in Redis.
+    pub result: Value,
+}
+
+/// Runs a single input file through pipeline and engine.
+///
+/// `device` overrides the configured device (`cpu`, `gpu`, `gpu:N`).
+/// `.npy` files are loaded as tensors, otherwise the file is read as text
+/// (with a `[text]` section) or decoded as an image and resized to the
+/// configured input size.
+pub async fn infer(config_path: &str, input: &Path, device: Option<&str>) -> Result<InferReport> {
+    let mut cfg = crate::load_config(config_path)?;
+    super::select_device(&mut cfg, device)?;
+    let spec = cfg.input_spec();
+    let (pipeline, text_encoder) = crate::build_pipeline(&cfg)?;
+    let mut engine = super::local_engine(&cfg)?;
+
+    let job = load_input(&cfg, text_encoder.as_ref(), input)?;
+    let input_shape = job.tensor.shape().to_vec();
+
+    // Über den Batcher, damit auf die Modell-Batchgröße aufgefüllt wird
+    let started = Instant::now();
+    let (tx, mut rx) = mpsc::channel(1);
+    tx.send(job).await?;
+    drop(tx);
+    let batch = crate::batcher::collect_batch(spec.batch, &mut rx, 1, 0).await?.context("Kein Job erzeugt")?;
+
+    let Batch { ids, tensor, actual_len, metas, inputs, .. } = batch;
+    let y = crate::worker::infer_batch(engine.as_mut(), &pipeline, &cfg, tensor, inputs)?;
+    let batch = Batch { ids, actual_len, metas, ..Default::default() };
+    let mut results = crate::worker::format_results(&batch, &y, pipeline.output.as_ref())?;
+
+    Ok(InferReport {
+        input: input.display().to_string(),
+        device: super::device_name(&cfg),
+        input_shape,
+        elapsed_ms: started.elapsed().as_secs_f64() * 1000.0,
+        result: results.remove(0),
+    })
+}
+
+/// Loads an input file as a job with the file name as id.
+///
+/// `.npy` files are loaded as tensors, otherwise the file is read as text
+/// (with a text encoder) or decoded as an image.
+pub(crate) fn load_input(cfg: &Config, text_encoder: Option<&TextEncoder>, input: &Path) -> Result<Job> {
+    let id = input.file_name().and_then(|n| n.to_str()).unwrap_or("input").to_string();
+    let mut meta = JobMeta::new();
+    meta.insert("source".to_string(), input.display().to_string().into());
+    let is_npy = input.extension().is_some_and(|e| e.eq_ignore_ascii_case("npy"));
+    match text_encoder {
+        Some(enc) if !is_npy => {
+            let text = std::fs::read_to_string(input)
+                .with_context(|| format!("Eingabe konnte nicht gelesen werden: {}", input.display()))?;
+            enc.encode(&TextJob { id, text, meta })
+        }
+        _ => {
+            let tensor = if is_npy { load_npy_sample(input, cfg)? } else { load_image(input, &cfg.input_spec())? };
+            Ok(Job { id, tensor, meta, ..Default::default() })
+        }
+    }
+}
+
+/// Loads a `.npy` sample. A leading batch axis of 1 is dropped; audio
+/// waveforms are padded to their bucket like in the dispatcher.
+fn load_npy_sample(path: &Path, cfg: &Config) -> Result<ArrayD<f32>> {
+    sample_from_array(cfg, crate::npy::load(path)?)
+}
+
+/// Turns an array into one sample: drops a leading batch axis of 1 and pads
+/// audio to its bucket.
+pub(crate) fn sample_from_array(cfg: &Config, mut tensor: ArrayD<f32>) -> Result<ArrayD<f32>> {
+    let rank = match &cfg.audio {
+        Some(_) => 1,
+        None => cfg.input_spec().sample_shape()?.len(),
+    };
+    if tensor.ndim() == rank + 1 && tensor.shape()[0] == 1 {
+        tensor = tensor.index_axis_move(Axis(0), 0);
+    }
+    anyhow::ensure!(tensor.ndim() == rank, "Eingabe muss {}D sein, bekommen {:?}", rank, tensor.shape());
+    if let Some(audio) = cfg.audio.as_ref().filter(|a| !a.buckets.is_empty()) {
+        tensor = crate::audio::pad_to_bucket(tensor, &audio.buckets);
+    }
+    Ok(tensor)
+}
+
+/// Decodes an image file into a `[C, H, W]` tensor scaled to `[0, 1]`,
+/// resized to the configured input size (1 channel = grayscale, 3 = RGB).
+/// With `height`/`width` of `0` (tiled inference) the image keeps its size.
+pub fn load_image(path: &Path, spec: &InputSpec) -> Result<ArrayD<f32>> {
+    anyhow::ensure!(spec.layout == "nchw", "Bild-Eingaben benötigen layout = \"nchw\"");
+    let load = || -> Result<image::DynamicImage> {
+        let mut reader = image::ImageReader::open(path)?.with_guessed_format()?;
+        // Gigapixel-Bilder überschreiten die Standard-Limits des Decoders
+        reader.no_limits();
+        Ok(reader.decode()?)
+    };
+    let img = load().with_context(|| format!("Bild konnte nicht geladen werden: {}", path.display()))?;
+    image_to_tensor(img, spec)
+}
+
+/// Like [`load_image`], for an encoded image (JPEG, PNG) in memory.
+#[cfg_attr(not(any(feature = "mqtt", feature = "s3")), allow(dead_code))]
+pub fn decode_image(bytes: &[u8], spec: &InputSpec) -> Result<ArrayD<f32>> {
+    anyhow::ensure!(spec.layout == "nchw", "Bild-Eingaben benötigen layout = \"nchw\"");
+    let img = image::load_from_memory(bytes).context("Bild konnte nicht dekodiert werden")?;
+    image_to_tensor(img, spec)
+}
+
+/// Resizes and converts a decoded image into a `[C, H, W]` tensor.
+fn image_to_tensor(img: image::DynamicImage, spec: &InputSpec) -> Result<ArrayD<f32>> {
+    let img = match (spec.height, spec.width) {
+        (0, _) | (_, 0) => img,
+        (h, w) => img.resize_exact(w as u32, h as u32, FilterType::Triangle),
+    };
+
+    let (h, w) = (img.height() as usize, img.width() as usize);
+    let tensor = match spec.channels {
+        1 => {
+            let gray = img.to_luma8();
+            Array3::from_shape_fn((1, h, w), |(_, y, x)| gray.get_pixel(x as u32, y as u32)[0] as f32 / 255.0)
+        }
+        3 => {
+            let rgb = img.to_rgb8();
+            Array3::from_shape_fn((3, h, w), |(c, y, x)| rgb.get_pixel(x as u32, y as u32)[c] as f32 / 255.0)
+        }
+        n => anyhow::bail!("Bilder mit {} Kanälen werden nicht unterstützt (1 oder 3)", n),
+    };
+    Ok(tensor.into_dyn())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_image_resizes_to_chw() {
+        let path = std::env::temp_dir().join(format!("omni-infer-{}.png", std::process::id()));
+        image::RgbImage::from_pixel(8, 4, image::Rgb([255, 0, 0])).save(&path).unwrap();
+
+        let spec = InputSpec {
+            batch: 1,
+            channels: 3,
+            depth: 0,
+            height: 2,
+            width: 2,
+            dtype: "f32".to_string(),
+            layout: "nchw".to_string(),
+        };
+        let t = load_image(&path, &spec).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(t.shape(), &[3, 2, 2]);
+        assert_eq!(t[[0, 1, 1]], 1.0);
+        assert_eq!(t[[1, 0, 0]], 0.0);
+    }
+
+    #[test]
+    fn test_load_image_keeps_size_for_tiling() {
+        let path = std::env::temp_dir().join(format!("omni-infer-tiled-{}.png", std::process::id()));
+        image::GrayImage::from_pixel(9, 5, image::Luma([51])).save(&path).unwrap();
+
+        let spec = InputSpec {
+            batch: 1,
+            channels: 1,
+            depth: 0,
+            height: 0,
+            width: 0,
+            dtype: "f32".to_string(),
+            layout: "nchw".to_string(),
+        };
+        let t = load_image(&path, &spec).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(t.shape(), &[1, 5, 9]);
+        assert!((t[[0, 4, 8]] - 0.2).abs() < 1e-6);
+    }
+}
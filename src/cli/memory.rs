@@ -0,0 +1,31 @@
+//! `memory`: model memory footprint estimate without loading the model.
+//!
+//! Sizes weights, peak activations and workspace at a batch size (default
+//! `[queue] max_batch`) and precision, to check whether a model fits a GPU
+//! before deploying it and how many instances share one device.
+
+use anyhow::Result;
+
+pub use crate::engine::memory::{MemoryEstimate, Precision};
+
+/// Options of the `memory` subcommand.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryOptions {
+    /// Samples per inference (default: `[queue] max_batch`).
+    pub batch: Option<usize>,
+    /// fp32, fp16, bf16 or int8 (default: as stored in the model).
+    pub precision: Option<String>,
+    /// Device override: cpu, gpu or gpu:N.
+    pub device: Option<String>,
+    /// Workspace in MiB instead of the planned one.
+    pub workspace_mb: Option<u64>,
+}
+
+/// Estimates the memory of the configured model.
+pub fn memory(config_path: &str, opts: &MemoryOptions) -> Result<MemoryEstimate> {
+    let mut cfg = crate::load_config(config_path)?;
+    super::select_device(&mut cfg, opts.device.as_deref())?;
+    let precision = opts.precision.as_deref().map(Precision::parse).transpose()?;
+    let batch = opts.batch.unwrap_or(cfg.queue.max_batch);
+    crate::engine::memory::estimate(&cfg, batch, precision, opts.workspace_mb.map(|mb| mb << 20))
+}
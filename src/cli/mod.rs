@@ -0,0 +1,115 @@
+//! Command-line subcommands besides `serve`.
+//!
+//! * `infer` - one-shot local inference of a single image/npy/text file
+//!   through the configured pipeline on CPU or a chosen GPU, printing the
+//!   result instead of storing it in Redis
+//! * `bench` - synthetic load against the local engine with latency percentiles
+//! * `build-engine` - converts the ONNX model to a cached TensorRT engine
+//! * `queue` - backlog inspection, draining and requeueing of the cluster
+//!   job stream and its dead-letter stream
+//! * `verify` - golden-output regression test over a dataset directory
+//! * `skew` - compares the runtime preprocessing with a reference Python
+//!   function on a dataset directory
+//! * `doctor` - self-test of driver, backend, Redis and model with a
+//!   diagnostics report
+//! * `demo` - downloads a small public model and serves it with a generated
+//!   configuration and the in-process result store
+//! * `memory` - estimates the memory of the model at a batch size and
+//!   precision without loading it
+//! * `pipe` - scores newline-delimited JSON jobs from stdin and writes the
+//!   results to stdout for shell pipelines
+//!
+//! Except for `queue` and `doctor`, all run locally without Redis.
+
+mod bench;
+mod build_engine;
+mod demo;
+mod doctor;
+mod infer;
+mod memory;
+mod pipe;
+mod queue;
+mod skew;
+mod verify;
+
+pub use bench::{bench, BenchOptions, BenchReport};
+pub use build_engine::{build_engine, BuildEngineOptions};
+pub use demo::{demo, DemoOptions, DEMO_MODEL_URL};
+pub use doctor::{doctor, Check, CheckStatus, DoctorReport};
+#[cfg(any(feature = "mqtt", feature = "s3"))]
+pub(crate) use infer::decode_image;
+#[cfg(feature = "watch")]
+pub(crate) use infer::load_input;
+#[cfg(any(feature = "python", feature = "s3"))]
+pub(crate) use infer::sample_from_array;
+pub use infer::{infer, InferReport};
+pub use memory::{memory, MemoryEstimate, MemoryOptions, Precision};
+pub use pipe::{pipe, PipeSummary};
+pub use queue::{queue_drain, queue_ls, queue_requeue, GroupStatus, QueueStatus, RequeueFrom};
+pub use skew::{skew, ElementDiff, SampleSkew, SkewReport};
+pub use verify::{record, verify, Mismatch, VerifyReport};
+
+use anyhow::Result;
+
+use crate::engine::{Engine, EngineFactory};
+use crate::types::Config;
+
+/// Creates the engine on the first configured device (GPU or CPU).
+pub(crate) fn local_engine(cfg: &Config) -> Result<Box<dyn Engine>> {
+    let device = match cfg.model.device.as_str() {
+        "gpu" => Some(crate::engine::devices::assign(&cfg.model)?.gpus.first().copied().unwrap_or(0)),
+        _ => None,
+    };
+    EngineFactory::create_for_device(cfg, device)
+}
+
+/// Parses a `--device` value: `cpu`, `gpu` (GPU 0) or `gpu:N`.
+pub fn parse_device(s: &str) -> Result<Option<usize>> {
+    match s.to_lowercase().as_str() {
+        "cpu" => Ok(None),
+        "gpu" | "cuda" => Ok(Some(0)),
+        other => {
+            let id = other
+                .strip_prefix("gpu:")
+                .or_else(|| other.strip_prefix("cuda:"))
+                .and_then(|n| n.parse().ok())
+                .ok_or_else(|| anyhow::anyhow!("Ungültiges Device '{}' (cpu, gpu, gpu:N)", s))?;
+            Ok(Some(id))
+        }
+    }
+}
+
+/// Overrides the configured device; `None` keeps the configuration.
+pub(crate) fn select_device(cfg: &mut Config, device: Option<&str>) -> Result<()> {
+    match device.map(parse_device).transpose()? {
+        None => {}
+        Some(None) => cfg.model.device = "cpu".to_string(),
+        Some(Some(id)) => {
+            cfg.model.device = "gpu".to_string();
+            cfg.model.gpu_ids = vec![crate::types::GpuId::Index(id)];
+        }
+    }
+    Ok(())
+}
+
+/// Human-readable name of the device `local_engine` uses.
+fn device_name(cfg: &Config) -> String {
+    match cfg.model.gpu_ids.first() {
+        Some(id) if cfg.model.device == "gpu" => format!("gpu:{}", id),
+        _ if cfg.model.device == "gpu" => "gpu:0".to_string(),
+        _ => "cpu".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_device() {
+        assert_eq!(parse_device("cpu").unwrap(), None);
+        assert_eq!(parse_device("GPU").unwrap(), Some(0));
+        assert_eq!(parse_device("gpu:3").unwrap(), Some(3));
+        assert!(parse_device("tpu").is_err());
+    }
+}
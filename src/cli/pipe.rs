@@ -0,0 +1,349 @@
+//! `pipe`: NDJSON jobs from stdin, results to stdout.
+//!
+//! Each input line is one JSON job with an optional `id`, an optional
+//! `meta` object and exactly one input:
+//!
+//! * `path` - image, `.npy` or text file, loaded like `omniengine infer`
+//! * `text` - text for models with a `[text]` section
+//! * `tensor` - base64 of the raw little-endian elements with `shape` (one
+//!   sample, without batch axis) and `dtype` (`f32`, `f16`, `bf16`, `i8`)
+//! * `data` - the elements as a JSON array with `shape` (job wire format)
+//!
+//! Jobs are batched like in the service (`[queue] max_batch`, flushed after
+//! `max_wait_ms` without new lines) and scored in-process without Redis.
+//! Every line produces one output line in input order: the result payload
+//! as it would be stored, or `{"id": ..., "error": ...}`.
+
+use std::io::{BufRead, Write};
+use std::path::PathBuf;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use base64::Engine as _;
+use ndarray::{ArrayD, IxDyn};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tokio::sync::mpsc;
+
+use crate::engine::Engine;
+use crate::pipeline::Pipeline;
+use crate::text::{TextEncoder, TextJob};
+use crate::types::{Batch, Config, Job, JobMeta, TensorData, TensorDtype};
+
+/// Counts of a `pipe` run.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct PipeSummary {
+    /// Input lines (blank lines excluded).
+    pub jobs: usize,
+    /// Lines answered with an error.
+    pub failed: usize,
+}
+
+/// One input line.
+#[derive(Debug, Deserialize)]
+struct PipeJob {
+    id: Option<String>,
+    path: Option<PathBuf>,
+    text: Option<String>,
+    tensor: Option<String>,
+    data: Option<Vec<f32>>,
+    shape: Option<Vec<usize>>,
+    #[serde(default)]
+    dtype: TensorDtype,
+    #[serde(default)]
+    meta: JobMeta,
+}
+
+/// Scores the NDJSON jobs of `input` and writes one line per job to `output`.
+///
+/// `device` overrides the configured device (`cpu`, `gpu`, `gpu:N`). Stops
+/// at the end of `input` or when `output` is closed (e.g. `| head`).
+pub async fn pipe<R, W>(config_path: &str, device: Option<&str>, input: R, output: W) -> Result<PipeSummary>
+where
+    R: BufRead + Send + 'static,
+    W: Write,
+{
+    let mut cfg = crate::load_config(config_path)?;
+    super::select_device(&mut cfg, device)?;
+    let (pipeline, text_encoder) = crate::build_pipeline(&cfg)?;
+    let engine = super::local_engine(&cfg)?;
+    let mut scorer = Scorer { cfg, pipeline, text_encoder, engine };
+    run(&mut scorer, input, output).await
+}
+
+/// Engine and pipeline of a `pipe` run.
+struct Scorer {
+    cfg: Config,
+    pipeline: Pipeline,
+    text_encoder: Option<TextEncoder>,
+    engine: Box<dyn Engine>,
+}
+
+impl Scorer {
+    /// Runs one batch of jobs through pipeline and engine.
+    async fn score(&mut self, jobs: Vec<Job>) -> Result<Vec<Value>> {
+        let spec = self.cfg.input_spec();
+        let n = jobs.len();
+        let (tx, mut rx) = mpsc::channel(n);
+        for job in jobs {
+            tx.send(job).await?;
+        }
+        drop(tx);
+        let batch = crate::batcher::collect_batch(spec.batch, &mut rx, n, 0).await?.context("Leerer Batch")?;
+
+        let Batch { ids, tensor, actual_len, metas, inputs, .. } = batch;
+        let y = crate::worker::infer_batch(self.engine.as_mut(), &self.pipeline, &self.cfg, tensor, inputs)?;
+        let batch = Batch { ids, actual_len, metas, ..Default::default() };
+        crate::worker::format_results(&batch, &y, self.pipeline.output.as_ref())
+    }
+}
+
+async fn run<R, W>(scorer: &mut Scorer, input: R, mut output: W) -> Result<PipeSummary>
+where
+    R: BufRead + Send + 'static,
+    W: Write,
+{
+    let spec = scorer.cfg.input_spec();
+    let max_batch = scorer.cfg.queue.max_batch.min(spec.batch).max(1);
+    let max_wait = Duration::from_millis(scorer.cfg.queue.max_wait_ms);
+
+    // Blockierendes Lesen auf eigenem Thread, damit Teil-Batches nach max_wait laufen
+    let (tx, mut lines) = mpsc::channel(max_batch * 2);
+    std::thread::Builder::new().name("omniengine-stdin".to_string()).spawn(move || {
+        for line in input.lines() {
+            if tx.blocking_send(line).is_err() {
+                break;
+            }
+        }
+    })?;
+
+    let mut summary = PipeSummary::default();
+    let mut pending = Vec::new();
+    let mut n = 0;
+    loop {
+        let line = if pending.is_empty() {
+            lines.recv().await
+        } else {
+            match tokio::time::timeout(max_wait, lines.recv()).await {
+                Ok(line) => line,
+                Err(_) => {
+                    if !flush(scorer, &mut pending, &mut output, &mut summary).await? {
+                        return Ok(summary);
+                    }
+                    continue;
+                }
+            }
+        };
+        let Some(line) = line else { break };
+        let line = line.context("Eingabe konnte nicht gelesen werden")?;
+        n += 1;
+        if line.trim().is_empty() {
+            continue;
+        }
+        summary.jobs += 1;
+        match parse_line(&scorer.cfg, scorer.text_encoder.as_ref(), &line, n) {
+            Ok(job) => pending.push(job),
+            Err((id, e)) => {
+                // Fehlerzeile erst nach den vorherigen Jobs, Reihenfolge bleibt erhalten
+                let open = flush(scorer, &mut pending, &mut output, &mut summary).await?
+                    && emit(&mut output, &[error_line(&id, &e)])?;
+                summary.failed += 1;
+                if !open {
+                    return Ok(summary);
+                }
+            }
+        }
+        if pending.len() >= max_batch && !flush(scorer, &mut pending, &mut output, &mut summary).await? {
+            return Ok(summary);
+        }
+    }
+    flush(scorer, &mut pending, &mut output, &mut summary).await?;
+    tracing::info!("{} Jobs verarbeitet, {} fehlgeschlagen", summary.jobs, summary.failed);
+    Ok(summary)
+}
+
+/// Scores and writes the pending jobs; a failed batch gives an error line
+/// per job. Returns `false` once the output is closed.
+async fn flush<W: Write>(scorer: &mut Scorer, pending: &mut Vec<Job>, output: &mut W, summary: &mut PipeSummary) -> Result<bool> {
+    if pending.is_empty() {
+        return Ok(true);
+    }
+    let ids: Vec<String> = pending.iter().map(|job| job.id.clone()).collect();
+    let lines = match scorer.score(std::mem::take(pending)).await {
+        Ok(results) => results,
+        Err(e) => {
+            tracing::warn!("Batch mit {} Jobs fehlgeschlagen: {:#}", ids.len(), e);
+            summary.failed += ids.len();
+            ids.iter().map(|id| error_line(id, &e)).collect()
+        }
+    };
+    emit(output, &lines)
+}
+
+/// Writes one JSON value per line. Returns `false` if the reader is gone.
+fn emit<W: Write>(output: &mut W, lines: &[Value]) -> Result<bool> {
+    let written = lines.iter().try_for_each(|line| writeln!(output, "{}", line)).and_then(|_| output.flush());
+    match written {
+        Ok(()) => Ok(true),
+        Err(e) if e.kind() == std::io::ErrorKind::BrokenPipe => Ok(false),
+        Err(e) => Err(e).context("Ausgabe konnte nicht geschrieben werden"),
+    }
+}
+
+fn error_line(id: &str, e: &anyhow::Error) -> Value {
+    json!({ "id": id, "error": format!("{:#}", e) })
+}
+
+/// Parses input line `n` into a job; errors carry the job id (`line-{n}`
+/// without one).
+fn parse_line(cfg: &Config, text_encoder: Option<&TextEncoder>, line: &str, n: usize) -> Result<Job, (String, anyhow::Error)> {
+    let fallback = || format!("line-{}", n);
+    let request: PipeJob = serde_json::from_str(line).map_err(|e| (fallback(), anyhow::anyhow!("Ungültige JSON-Zeile: {}", e)))?;
+    let id = request.id.clone();
+    to_job(cfg, text_encoder, request, &fallback).map_err(|e| (id.unwrap_or_else(fallback), e))
+}
+
+fn to_job(cfg: &Config, text_encoder: Option<&TextEncoder>, request: PipeJob, fallback: &dyn Fn() -> String) -> Result<Job> {
+    let PipeJob { id, path, text, tensor, data, shape, dtype, meta } = request;
+    let mut job = match (path, text, tensor, data) {
+        // Dateiname als ID, wenn keine angegeben ist
+        (Some(path), None, None, None) => super::infer::load_input(cfg, text_encoder, &path)?,
+        (None, Some(text), None, None) => {
+            let encoder = text_encoder.context("'text' benötigt eine [text]-Sektion")?;
+            encoder.encode(&TextJob { id: fallback(), text, meta: JobMeta::new() })?
+        }
+        (None, None, Some(encoded), None) => {
+            let shape = shape.context("'tensor' benötigt 'shape'")?;
+            let bytes = base64::engine::general_purpose::STANDARD.decode(encoded).context("'tensor' ist kein gültiges Base64")?;
+            let expected = shape.iter().try_fold(dtype.size(), |n, &d| n.checked_mul(d)).context("Shape zu groß")?;
+            anyhow::ensure!(
+                bytes.len() == expected,
+                "'tensor' hat {} Bytes, Shape {:?} als {} braucht {}",
+                bytes.len(), shape, dtype.name(), expected
+            );
+            let values = crate::storage::redis_store::decode_values(&bytes, dtype, 1.0);
+            Job { id: fallback(), tensor: ArrayD::from_shape_vec(IxDyn(&shape), values)?, ..Default::default() }
+        }
+        (None, None, None, Some(data)) => {
+            let shape = shape.context("'data' benötigt 'shape'")?;
+            Job { id: fallback(), tensor: TensorData { shape, data }.into_array()?, ..Default::default() }
+        }
+        _ => anyhow::bail!("Genau eines von 'path', 'text', 'tensor' oder 'data' erwartet"),
+    };
+    if let Some(id) = id {
+        job.id = id;
+    }
+    job.meta.extend(meta);
+    Ok(job)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    /// Returns each sample flattened.
+    struct Flatten;
+
+    impl Engine for Flatten {
+        fn name(&self) -> &'static str {
+            "flatten"
+        }
+
+        fn infer_array(&mut self, input: ArrayD<f32>) -> Result<ArrayD<f32>> {
+            let n = input.shape()[0];
+            Ok(input.into_shape_with_order(IxDyn(&[n, 2]))?)
+        }
+    }
+
+    /// Pre/post stage passing tensors through.
+    struct Identity;
+
+    impl crate::pipeline::Preprocessor for Identity {
+        fn run(&self, input: ArrayD<f32>) -> Result<ArrayD<f32>> {
+            Ok(input)
+        }
+    }
+
+    impl crate::pipeline::Postprocessor for Identity {
+        fn run(&self, input: ArrayD<f32>) -> Result<ArrayD<f32>> {
+            Ok(input)
+        }
+    }
+
+    fn config() -> Config {
+        toml::from_str(
+            r#"
+            [model]
+            backend = "onnx"
+            device = "cpu"
+            model_path = "model.onnx"
+            input_names = ["input"]
+            input_shapes = [[0, 1, 2, 1]]
+            output_names = ["output"]
+            output_shapes = [[0, 2]]
+            [queue]
+            max_batch = 2
+            max_wait_ms = 5
+            [redis]
+            url = "memory://"
+            out_prefix = "results"
+            [input]
+            batch = 2
+            channels = 1
+            height = 2
+            width = 1
+            dtype = "f32"
+            "#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_parse_line_inputs() {
+        let cfg = config();
+        let bytes: Vec<u8> = [0.5f32, 1.5].iter().flat_map(|v| v.to_le_bytes()).collect();
+        let line = json!({"tensor": base64::engine::general_purpose::STANDARD.encode(bytes), "shape": [1, 2, 1], "meta": {"k": 1}});
+        let job = parse_line(&cfg, None, &line.to_string(), 3).unwrap();
+        assert_eq!((job.id.as_str(), job.tensor.shape(), job.meta["k"].as_i64()), ("line-3", &[1, 2, 1][..], Some(1)));
+        assert_eq!(job.tensor[[0, 1, 0]], 1.5);
+
+        let job = parse_line(&cfg, None, r#"{"id": "a", "shape": [1, 2, 1], "data": [1, 2]}"#, 1).unwrap();
+        assert_eq!(job.id, "a");
+
+        let (id, e) = parse_line(&cfg, None, r#"{"id": "b", "shape": [1, 2, 1], "tensor": "AAAA"}"#, 1).unwrap_err();
+        assert!(e.to_string().contains("3 Bytes"), "{} {}", id, e);
+        assert_eq!(parse_line(&cfg, None, r#"{"id": "c", "text": "hallo"}"#, 1).unwrap_err().0, "c");
+        assert_eq!(parse_line(&cfg, None, r#"{"id": "d"}"#, 1).unwrap_err().0, "d");
+        assert_eq!(parse_line(&cfg, None, "{kaputt", 7).unwrap_err().0, "line-7");
+    }
+
+    #[tokio::test]
+    async fn test_results_keep_input_order() {
+        let cfg = config();
+        let pipeline = Pipeline {
+            pre: Arc::new(Identity),
+            post: Arc::new(Identity),
+            output: Arc::new(crate::pipeline::RawOutput),
+        };
+        let mut scorer = Scorer { cfg, pipeline, text_encoder: None, engine: Box::new(Flatten) };
+        let input = [
+            r#"{"id": "a", "shape": [1, 2, 1], "data": [1, 2]}"#,
+            r#"{"id": "b", "shape": [1, 2, 1], "data": [3, 4]}"#,
+            "",
+            r#"{"id": "c", "shape": [1, 2, 1], "data": [5, 6]}"#,
+            r#"{"id": "kaputt"}"#,
+            r#"{"id": "d", "shape": [1, 2, 1], "data": [7, 8]}"#,
+        ]
+        .join("\n");
+        let mut output = Vec::new();
+        let summary = run(&mut scorer, std::io::Cursor::new(input), &mut output).await.unwrap();
+        assert_eq!((summary.jobs, summary.failed), (5, 1));
+
+        let lines: Vec<Value> = String::from_utf8(output).unwrap().lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+        let ids: Vec<&str> = lines.iter().map(|l| l["id"].as_str().unwrap()).collect();
+        assert_eq!(ids, ["a", "b", "c", "kaputt", "d"]);
+        assert!(lines[3]["error"].as_str().unwrap().contains("Genau eines"));
+        assert!(lines[0].get("error").is_none());
+    }
+}
@@ -0,0 +1,196 @@
+//! `queue ls|drain|requeue`: operating the cluster job stream and its DLQ.
+
+use std::fmt;
+
+use anyhow::{Context, Result};
+use redis::streams::{StreamInfoGroupsReply, StreamMaxlen, StreamPendingCountReply, StreamPendingId, StreamRangeReply};
+use redis::AsyncCommands;
+use serde::Serialize;
+
+use crate::cluster::{dead_letter_key, heartbeat_key, job_payload};
+use crate::types::{ClusterCfg, Config};
+
+/// Consumer group state of the job stream.
+#[derive(Debug, Clone, Serialize)]
+pub struct GroupStatus {
+    pub name: String,
+    pub consumers: usize,
+    /// Delivered but not yet acknowledged.
+    pub pending: usize,
+    /// Not yet delivered (`None` if the server cannot tell).
+    pub lag: Option<usize>,
+}
+
+/// Backlog overview of `queue ls`.
+#[derive(Debug, Clone, Serialize)]
+pub struct QueueStatus {
+    pub stream: String,
+    pub length: usize,
+    pub groups: Vec<GroupStatus>,
+    /// Live nodes (heartbeat keys).
+    pub nodes: Vec<String>,
+    pub dlq: String,
+    pub dlq_length: usize,
+}
+
+impl fmt::Display for QueueStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Stream {}: {} Einträge", self.stream, self.length)?;
+        for g in &self.groups {
+            let lag = g.lag.map(|l| l.to_string()).unwrap_or_else(|| "?".to_string());
+            writeln!(f, "  Gruppe {}: {} Consumer, {} pending, {} offen", g.name, g.consumers, g.pending, lag)?;
+        }
+        writeln!(f, "Knoten ({}): {}", self.nodes.len(), self.nodes.join(", "))?;
+        write!(f, "DLQ {}: {} Einträge", self.dlq, self.dlq_length)
+    }
+}
+
+/// Source of `queue requeue`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RequeueFrom {
+    /// Dead-letter stream.
+    Dlq,
+    /// Messages pending in the consumer group longer than `min_idle_ms`
+    /// (stuck on a node).
+    Pending,
+}
+
+fn cluster_cfg(cfg: &Config) -> Result<ClusterCfg> {
+    cfg.cluster.clone().context("Keine Job-Queue konfiguriert ([cluster] fehlt)")
+}
+
+async fn connect(cfg: &Config) -> Result<redis::aio::MultiplexedConnection> {
+    let client = redis::Client::open(cfg.redis.url.as_str())?;
+    Ok(client.get_multiplexed_async_connection().await?)
+}
+
+/// Lists backlog depth, consumer groups, live nodes and the DLQ.
+pub async fn queue_ls(config_path: &str) -> Result<QueueStatus> {
+    let cfg = crate::load_config(config_path)?;
+    let cluster = cluster_cfg(&cfg)?;
+    let mut con = connect(&cfg).await?;
+
+    let length: usize = con.xlen(&cluster.stream).await?;
+    let groups = if length > 0 || con.exists::<_, bool>(&cluster.stream).await? {
+        let reply: StreamInfoGroupsReply = con.xinfo_groups(&cluster.stream).await?;
+        reply
+            .groups
+            .into_iter()
+            .map(|g| GroupStatus { name: g.name, consumers: g.consumers, pending: g.pending, lag: g.lag })
+            .collect()
+    } else {
+        Vec::new()
+    };
+
+    let prefix = heartbeat_key(&cluster, "");
+    let mut nodes = Vec::new();
+    let mut keys: redis::AsyncIter<String> = con.scan_match(format!("{}*", prefix)).await?;
+    while let Some(key) = keys.next_item().await {
+        nodes.push(key.trim_start_matches(&prefix).to_string());
+    }
+    drop(keys);
+    nodes.sort();
+
+    let dlq = dead_letter_key(&cluster);
+    let dlq_length: usize = con.xlen(&dlq).await?;
+    Ok(QueueStatus { stream: cluster.stream, length, groups, nodes, dlq, dlq_length })
+}
+
+/// Discards the backlog: all entries of the job stream (acknowledging
+/// pending ones) or of the DLQ. Returns the number of removed entries.
+pub async fn queue_drain(config_path: &str, dlq: bool) -> Result<usize> {
+    let cfg = crate::load_config(config_path)?;
+    let cluster = cluster_cfg(&cfg)?;
+    let mut con = connect(&cfg).await?;
+
+    if dlq {
+        return Ok(con.xtrim(dead_letter_key(&cluster), StreamMaxlen::Equals(0)).await?);
+    }
+    let removed: usize = con.xtrim(&cluster.stream, StreamMaxlen::Equals(0)).await?;
+
+    // Ausstehende Nachrichten quittieren, sonst übernimmt sie XAUTOCLAIM
+    loop {
+        let pending: StreamPendingCountReply = con.xpending_count(&cluster.stream, &cluster.group, "-", "+", 1000).await?;
+        if pending.ids.is_empty() {
+            break;
+        }
+        let ids: Vec<&str> = pending.ids.iter().map(|p| p.id.as_str()).collect();
+        con.xack::<_, _, _, ()>(&cluster.stream, &cluster.group, &ids).await?;
+    }
+    Ok(removed)
+}
+
+/// Pending messages idle for at least `min_idle_ms`.
+fn stuck(pending: &[StreamPendingId], min_idle_ms: usize) -> Vec<&str> {
+    pending.iter().filter(|p| p.last_delivered_ms >= min_idle_ms).map(|p| p.id.as_str()).collect()
+}
+
+/// Appends up to `count` messages from the DLQ or from stuck pending
+/// entries to the job stream as new messages and removes the originals.
+/// Returns the number of requeued messages.
+pub async fn queue_requeue(config_path: &str, from: RequeueFrom, min_idle_ms: u64, count: usize) -> Result<usize> {
+    let cfg = crate::load_config(config_path)?;
+    let cluster = cluster_cfg(&cfg)?;
+    let mut con = connect(&cfg).await?;
+    let mut requeued = 0;
+
+    match from {
+        RequeueFrom::Dlq => {
+            let dlq = dead_letter_key(&cluster);
+            let entries: StreamRangeReply = con.xrange_count(&dlq, "-", "+", count).await?;
+            for entry in entries.ids {
+                let Some(job) = job_payload(&entry) else {
+                    tracing::warn!("DLQ-Eintrag {} ohne Job übersprungen", entry.id);
+                    continue;
+                };
+                con.xadd::<_, _, _, _, ()>(&cluster.stream, "*", &[job]).await?;
+                con.xdel::<_, _, ()>(&dlq, &[&entry.id]).await?;
+                requeued += 1;
+            }
+        }
+        RequeueFrom::Pending => {
+            let pending: StreamPendingCountReply =
+                con.xpending_count(&cluster.stream, &cluster.group, "-", "+", count).await?;
+            for id in stuck(&pending.ids, min_idle_ms as usize) {
+                let entry: StreamRangeReply = con.xrange(&cluster.stream, id, id).await?;
+                if let Some(job) = entry.ids.first().and_then(job_payload) {
+                    con.xadd::<_, _, _, _, ()>(&cluster.stream, "*", &[job]).await?;
+                    requeued += 1;
+                }
+                con.xack::<_, _, _, ()>(&cluster.stream, &cluster.group, &[id]).await?;
+                con.xdel::<_, _, ()>(&cluster.stream, &[id]).await?;
+            }
+        }
+    }
+    Ok(requeued)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stuck_filters_by_idle_time() {
+        let pending = vec![
+            StreamPendingId { id: "1-0".into(), consumer: "a".into(), last_delivered_ms: 120_000, times_delivered: 1 },
+            StreamPendingId { id: "2-0".into(), consumer: "b".into(), last_delivered_ms: 500, times_delivered: 1 },
+        ];
+        assert_eq!(stuck(&pending, 60_000), vec!["1-0"]);
+        assert_eq!(stuck(&pending, 0).len(), 2);
+    }
+
+    #[test]
+    fn test_status_display() {
+        let status = QueueStatus {
+            stream: "jobs".into(),
+            length: 7,
+            groups: vec![GroupStatus { name: "omni".into(), consumers: 2, pending: 3, lag: None }],
+            nodes: vec!["gpu-a".into(), "gpu-b".into()],
+            dlq: "jobs:dlq".into(),
+            dlq_length: 1,
+        };
+        let text = status.to_string();
+        assert!(text.contains("Gruppe omni: 2 Consumer, 3 pending, ? offen"));
+        assert!(text.contains("Knoten (2): gpu-a, gpu-b"));
+    }
+}
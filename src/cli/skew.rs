@@ -0,0 +1,240 @@
+//! `skew`: training-serving skew check.
+//!
+//! Loads every file of a dataset directory the way the runtime does and
+//! runs the configured preprocessing stages on it, then calls a reference
+//! Python function (typically the preprocessing of the training code) on
+//! the same file and compares both tensors element by element. Python runs
+//! as a subprocess and needs numpy; the function gets the file path and
+//! returns an array.
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use anyhow::{Context, Result};
+use ndarray::{ArrayD, Axis, Dimension};
+use serde::Serialize;
+
+/// Calls the reference function on each file and saves the results as
+/// `{out}/{i}.npy`. Arguments: reference, output directory, files.
+const DRIVER: &str = r#"
+import importlib, importlib.util, os, sys
+import numpy as np
+
+ref, out = sys.argv[1], sys.argv[2]
+module, _, name = ref.rpartition(":")
+if module.endswith(".py"):
+    spec = importlib.util.spec_from_file_location("reference", module)
+    mod = importlib.util.module_from_spec(spec)
+    spec.loader.exec_module(mod)
+else:
+    sys.path.insert(0, os.getcwd())
+    mod = importlib.import_module(module)
+fn = getattr(mod, name)
+for i, path in enumerate(sys.argv[3:]):
+    np.save(os.path.join(out, "%d.npy" % i), np.ascontiguousarray(fn(path), dtype=np.float32))
+"#;
+
+/// Element with the largest difference.
+#[derive(Debug, Clone, Serialize)]
+pub struct ElementDiff {
+    pub index: Vec<usize>,
+    pub rust: f32,
+    pub python: f32,
+}
+
+/// Comparison of one sample.
+#[derive(Debug, Clone, Serialize)]
+pub struct SampleSkew {
+    pub id: String,
+    pub rust_shape: Vec<usize>,
+    pub python_shape: Vec<usize>,
+    /// Elements differing by more than the tolerance (NaN included).
+    pub mismatched: usize,
+    pub max_abs_diff: f64,
+    pub mean_abs_diff: f64,
+    /// `None` if the shapes differ or all elements are equal.
+    pub worst: Option<ElementDiff>,
+}
+
+impl SampleSkew {
+    pub fn ok(&self) -> bool {
+        self.rust_shape == self.python_shape && self.mismatched == 0
+    }
+}
+
+/// Outcome of `skew`.
+#[derive(Debug, Clone, Serialize)]
+pub struct SkewReport {
+    pub tolerance: f64,
+    pub samples: Vec<SampleSkew>,
+}
+
+impl SkewReport {
+    pub fn ok(&self) -> bool {
+        self.samples.iter().all(SampleSkew::ok)
+    }
+
+    /// Largest absolute difference over all samples with matching shapes.
+    pub fn max_abs_diff(&self) -> f64 {
+        self.samples.iter().map(|s| s.max_abs_diff).fold(0.0, f64::max)
+    }
+}
+
+impl fmt::Display for SkewReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for s in &self.samples {
+            if s.rust_shape != s.python_shape {
+                writeln!(f, "FAIL {}: Shape {:?} (Rust) vs. {:?} (Python)", s.id, s.rust_shape, s.python_shape)?;
+                continue;
+            }
+            let status = if s.ok() { "OK  " } else { "FAIL" };
+            write!(f, "{} {}: {} Elemente abweichend, max. {:.3e}, Mittel {:.3e}", status, s.id, s.mismatched, s.max_abs_diff, s.mean_abs_diff)?;
+            match &s.worst {
+                Some(w) => writeln!(f, " bei {:?} (Rust {}, Python {})", w.index, w.rust, w.python)?,
+                None => writeln!(f)?,
+            }
+        }
+        let passed = self.samples.iter().filter(|s| s.ok()).count();
+        write!(
+            f,
+            "{}/{} Samples innerhalb der Toleranz {:.0e}, max. Abweichung {:.3e}",
+            passed,
+            self.samples.len(),
+            self.tolerance,
+            self.max_abs_diff()
+        )
+    }
+}
+
+/// Compares the runtime preprocessing of every dataset file with the
+/// Python function `reference` (`file.py:function` or `module:function`).
+pub fn skew(config_path: &str, dataset: &Path, reference: &str, python: &str, tolerance: f64) -> Result<SkewReport> {
+    anyhow::ensure!(
+        reference.rsplit_once(':').is_some_and(|(m, f)| !m.is_empty() && !f.is_empty()),
+        "Referenz '{}' muss 'datei.py:funktion' oder 'modul:funktion' sein",
+        reference
+    );
+    let cfg = crate::load_config(config_path)?;
+    let (pipeline, text_encoder) = crate::build_pipeline(&cfg)?;
+    let files = super::verify::dataset_files(dataset, Path::new(""))?;
+    anyhow::ensure!(!files.is_empty(), "Keine Eingaben in {}", dataset.display());
+
+    let out = std::env::temp_dir().join(format!("omniengine-skew-{}", std::process::id()));
+    std::fs::create_dir_all(&out)?;
+    let expected = run_reference(python, reference, &files, &out);
+    let result = expected.and_then(|()| {
+        let mut samples = Vec::with_capacity(files.len());
+        for (i, file) in files.iter().enumerate() {
+            let job = super::infer::load_input(&cfg, text_encoder.as_ref(), file)?;
+            let rust = pipeline.run_pre(job.tensor.insert_axis(Axis(0)))?.index_axis_move(Axis(0), 0);
+            let python = crate::npy::load(&out.join(format!("{}.npy", i)))?;
+            samples.push(compare(&job.id, &rust, python, tolerance));
+        }
+        Ok(SkewReport { tolerance, samples })
+    });
+    let _ = std::fs::remove_dir_all(&out);
+    result
+}
+
+/// Runs the reference function on `files` in a Python subprocess.
+fn run_reference(python: &str, reference: &str, files: &[PathBuf], out: &Path) -> Result<()> {
+    let output = Command::new(python)
+        .arg("-c")
+        .arg(DRIVER)
+        .arg(reference)
+        .arg(out)
+        .args(files)
+        .output()
+        .with_context(|| format!("Python ('{}') konnte nicht gestartet werden", python))?;
+    anyhow::ensure!(
+        output.status.success(),
+        "Referenzfunktion {} fehlgeschlagen ({}):\n{}",
+        reference,
+        output.status,
+        String::from_utf8_lossy(&output.stderr).trim_end()
+    );
+    Ok(())
+}
+
+/// Compares one sample. A leading batch axis of 1 in the Python result is
+/// dropped.
+fn compare(id: &str, rust: &ArrayD<f32>, mut python: ArrayD<f32>, tolerance: f64) -> SampleSkew {
+    if python.ndim() == rust.ndim() + 1 && python.shape()[0] == 1 {
+        python = python.index_axis_move(Axis(0), 0);
+    }
+    let mut skew = SampleSkew {
+        id: id.to_string(),
+        rust_shape: rust.shape().to_vec(),
+        python_shape: python.shape().to_vec(),
+        mismatched: 0,
+        max_abs_diff: 0.0,
+        mean_abs_diff: 0.0,
+        worst: None,
+    };
+    if rust.shape() != python.shape() {
+        return skew;
+    }
+    let mut sum = 0.0;
+    for ((index, &r), &p) in rust.indexed_iter().zip(python.iter()) {
+        // NaN auf einer Seite zählt immer als Abweichung
+        let diff = match (r.is_nan(), p.is_nan()) {
+            (true, true) => 0.0,
+            (false, false) => (r as f64 - p as f64).abs(),
+            _ => f64::INFINITY,
+        };
+        sum += diff;
+        if diff > tolerance {
+            skew.mismatched += 1;
+        }
+        if diff > skew.max_abs_diff {
+            skew.max_abs_diff = diff;
+            skew.worst = Some(ElementDiff { index: index.slice().to_vec(), rust: r, python: p });
+        }
+    }
+    skew.mean_abs_diff = sum / rust.len().max(1) as f64;
+    skew
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::IxDyn;
+
+    fn array(shape: &[usize], data: Vec<f32>) -> ArrayD<f32> {
+        ArrayD::from_shape_vec(IxDyn(shape), data).unwrap()
+    }
+
+    #[test]
+    fn test_compare_reports_worst_element() {
+        let rust = array(&[2, 2], vec![0.0, 0.5, 1.0, 0.25]);
+        let python = array(&[1, 2, 2], vec![0.0, 0.5, 0.99, 0.2500001]);
+        let skew = compare("a.png", &rust, python, 1e-4);
+        assert_eq!(skew.python_shape, vec![2, 2]);
+        assert_eq!(skew.mismatched, 1);
+        assert!(!skew.ok());
+        let worst = skew.worst.unwrap();
+        assert_eq!(worst.index, vec![1, 0]);
+        assert_eq!((worst.rust, worst.python), (1.0, 0.99));
+        assert!((skew.max_abs_diff - 0.01).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_compare_shape_mismatch() {
+        // Typischer Fehler: HWC im Training, CHW in der Runtime
+        let rust = ArrayD::zeros(IxDyn(&[3, 4, 4]));
+        let python = ArrayD::zeros(IxDyn(&[4, 4, 3]));
+        let report = SkewReport { tolerance: 1e-5, samples: vec![compare("a.png", &rust, python, 1e-5)] };
+        assert!(!report.ok());
+        assert!(report.to_string().contains("Shape [3, 4, 4] (Rust) vs. [4, 4, 3] (Python)"));
+    }
+
+    #[test]
+    fn test_compare_nan_on_one_side() {
+        let skew = compare("x.npy", &array(&[2], vec![f32::NAN, 1.0]), array(&[2], vec![f32::NAN, 1.0]), 1e-5);
+        assert!(skew.ok());
+        let skew = compare("x.npy", &array(&[2], vec![f32::NAN, 1.0]), array(&[2], vec![0.0, 1.0]), 1e-5);
+        assert_eq!(skew.mismatched, 1);
+        assert_eq!(skew.max_abs_diff, f64::INFINITY);
+    }
+}
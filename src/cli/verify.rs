@@ -0,0 +1,266 @@
+//! `verify`: golden-output regression testing.
+//!
+//! Runs every file of a dataset directory through the full pipeline and
+//! compares the results with recorded golden outputs. Numbers may differ by
+//! `tolerance` (absolute); strings, booleans and array lengths must match.
+//! `timestamp` and `meta` are not compared.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+use serde_json::Value;
+use tokio::sync::mpsc;
+
+use crate::types::Batch;
+
+/// Result fields that legitimately differ between runs.
+const IGNORED_FIELDS: &[&str] = &["timestamp", "meta", "schema_version", "reproducibility"];
+
+/// First difference found for one sample.
+#[derive(Debug, Clone, Serialize)]
+pub struct Mismatch {
+    pub id: String,
+    /// JSON path of the differing value (e.g. `top_k[0].score`).
+    pub path: String,
+    pub expected: Value,
+    pub actual: Value,
+}
+
+/// Outcome of `verify`.
+#[derive(Debug, Clone, Serialize)]
+pub struct VerifyReport {
+    pub samples: usize,
+    pub passed: usize,
+    /// Largest absolute numeric difference over all samples.
+    pub max_abs_diff: f64,
+    pub mismatches: Vec<Mismatch>,
+    /// Dataset files without a golden output.
+    pub missing: Vec<String>,
+}
+
+impl VerifyReport {
+    pub fn ok(&self) -> bool {
+        self.mismatches.is_empty() && self.missing.is_empty()
+    }
+}
+
+impl fmt::Display for VerifyReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for m in &self.mismatches {
+            writeln!(f, "FAIL {} bei {}: erwartet {}, bekommen {}", m.id, m.path, m.expected, m.actual)?;
+        }
+        for id in &self.missing {
+            writeln!(f, "FAIL {}: keine Golden-Ausgabe", id)?;
+        }
+        write!(
+            f,
+            "{}/{} Samples bestanden, max. Abweichung {:.3e}",
+            self.passed, self.samples, self.max_abs_diff
+        )
+    }
+}
+
+/// Compares `actual` with `expected` and returns the first differing path.
+fn compare(path: &str, actual: &Value, expected: &Value, tolerance: f64, max_diff: &mut f64) -> Option<String> {
+    match (actual, expected) {
+        (Value::Number(a), Value::Number(e)) => {
+            let diff = (a.as_f64().unwrap_or(f64::NAN) - e.as_f64().unwrap_or(f64::NAN)).abs();
+            *max_diff = max_diff.max(diff);
+            (diff.is_nan() || diff > tolerance).then(|| path.to_string())
+        }
+        (Value::Array(a), Value::Array(e)) => {
+            if a.len() != e.len() {
+                return Some(format!("{}.len", path));
+            }
+            let mut first = None;
+            for (i, (av, ev)) in a.iter().zip(e).enumerate() {
+                let found = compare(&format!("{}[{}]", path, i), av, ev, tolerance, max_diff);
+                first = first.or(found);
+            }
+            first
+        }
+        (Value::Object(a), Value::Object(e)) => {
+            let mut first = None;
+            for (key, ev) in e.iter().filter(|(k, _)| !IGNORED_FIELDS.contains(&k.as_str())) {
+                let sub = if path.is_empty() { key.clone() } else { format!("{}.{}", path, key) };
+                let found = match a.get(key) {
+                    Some(av) => compare(&sub, av, ev, tolerance, max_diff),
+                    None => Some(sub),
+                };
+                first = first.or(found);
+            }
+            first
+        }
+        _ => (actual != expected).then(|| path.to_string()),
+    }
+}
+
+/// Looks up the value at a path produced by `compare` (for reporting).
+fn lookup<'a>(value: &'a Value, path: &str) -> &'a Value {
+    let mut current = value;
+    for part in path.split('.').filter(|p| !p.is_empty()) {
+        let (key, indices) = part.split_once('[').map(|(k, rest)| (k, Some(rest))).unwrap_or((part, None));
+        if !key.is_empty() {
+            current = current.get(key).unwrap_or(&Value::Null);
+        }
+        for idx in indices.into_iter().flat_map(|r| r.split('[')) {
+            let i: usize = idx.trim_end_matches(']').parse().unwrap_or(usize::MAX);
+            current = current.get(i).unwrap_or(&Value::Null);
+        }
+    }
+    current
+}
+
+/// Dataset files (sorted), skipping hidden files and `exclude`.
+pub(super) fn dataset_files(dir: &Path, exclude: &Path) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for entry in std::fs::read_dir(dir).with_context(|| format!("Datensatz nicht lesbar: {}", dir.display()))? {
+        let path = entry?.path();
+        let hidden = path.file_name().and_then(|n| n.to_str()).is_none_or(|n| n.starts_with('.'));
+        let supported = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .is_some_and(|e| ["npy", "png", "jpg", "jpeg", "txt"].contains(&e.to_lowercase().as_str()));
+        if path.is_file() && !hidden && supported && path != exclude {
+            files.push(path);
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Loads golden outputs: a JSON object `{id: result}` or an array of
+/// results with an `id` field.
+fn load_expected(path: &Path) -> Result<BTreeMap<String, Value>> {
+    let raw = std::fs::read_to_string(path).with_context(|| format!("Golden-Datei nicht lesbar: {}", path.display()))?;
+    match serde_json::from_str(&raw)? {
+        Value::Object(map) => Ok(map.into_iter().collect()),
+        Value::Array(items) => items
+            .into_iter()
+            .map(|item| {
+                let id = item.get("id").and_then(|v| v.as_str()).context("Golden-Ergebnis ohne 'id'")?;
+                Ok((id.to_string(), item))
+            })
+            .collect(),
+        _ => anyhow::bail!("Golden-Datei muss ein JSON-Objekt oder -Array sein"),
+    }
+}
+
+/// Runs all dataset files through the pipeline and returns `{id: result}`.
+async fn run_dataset(config_path: &str, dataset: &Path, exclude: &Path, device: Option<&str>) -> Result<BTreeMap<String, Value>> {
+    let mut cfg = crate::load_config(config_path)?;
+    super::select_device(&mut cfg, device)?;
+    let spec = cfg.input_spec();
+    let (pipeline, text_encoder) = crate::build_pipeline(&cfg)?;
+    let mut engine = super::local_engine(&cfg)?;
+    let max_batch = cfg.queue.max_batch.min(spec.batch).max(1);
+
+    let files = dataset_files(dataset, exclude)?;
+    anyhow::ensure!(!files.is_empty(), "Keine Eingaben in {}", dataset.display());
+
+    let mut results = BTreeMap::new();
+    for chunk in files.chunks(max_batch) {
+        let (tx, mut rx) = mpsc::channel(chunk.len());
+        for file in chunk {
+            tx.send(super::infer::load_input(&cfg, text_encoder.as_ref(), file)?).await?;
+        }
+        drop(tx);
+        let batch = crate::batcher::collect_batch(spec.batch, &mut rx, max_batch, 0).await?.context("Leerer Batch")?;
+
+        let Batch { ids, tensor, actual_len, metas, inputs, .. } = batch;
+        let y = crate::worker::infer_batch(engine.as_mut(), &pipeline, &cfg, tensor, inputs)?;
+        let batch = Batch { ids, actual_len, metas, ..Default::default() };
+        for (id, result) in batch.ids.iter().zip(crate::worker::format_results(&batch, &y, pipeline.output.as_ref())?) {
+            results.insert(id.clone(), result);
+        }
+    }
+    Ok(results)
+}
+
+/// Compares the dataset results with the golden outputs in `expected`.
+pub async fn verify(
+    config_path: &str,
+    dataset: &Path,
+    expected: &Path,
+    tolerance: f64,
+    device: Option<&str>,
+) -> Result<VerifyReport> {
+    let golden = load_expected(expected)?;
+    let actual = run_dataset(config_path, dataset, expected, device).await?;
+    Ok(check(&actual, &golden, tolerance))
+}
+
+/// Records the current dataset results as golden outputs to `expected`.
+/// Returns the number of recorded samples.
+pub async fn record(config_path: &str, dataset: &Path, expected: &Path, device: Option<&str>) -> Result<usize> {
+    let mut results = run_dataset(config_path, dataset, expected, device).await?;
+    for result in results.values_mut() {
+        if let Value::Object(fields) = result {
+            fields.retain(|k, _| !IGNORED_FIELDS.contains(&k.as_str()));
+        }
+    }
+    std::fs::write(expected, serde_json::to_string_pretty(&results)?)
+        .with_context(|| format!("Golden-Datei konnte nicht geschrieben werden: {}", expected.display()))?;
+    Ok(results.len())
+}
+
+fn check(actual: &BTreeMap<String, Value>, golden: &BTreeMap<String, Value>, tolerance: f64) -> VerifyReport {
+    let mut report =
+        VerifyReport { samples: actual.len(), passed: 0, max_abs_diff: 0.0, mismatches: Vec::new(), missing: Vec::new() };
+    for (id, result) in actual {
+        let Some(expected) = golden.get(id) else {
+            report.missing.push(id.clone());
+            continue;
+        };
+        match compare("", result, expected, tolerance, &mut report.max_abs_diff) {
+            None => report.passed += 1,
+            Some(path) => report.mismatches.push(Mismatch {
+                id: id.clone(),
+                expected: lookup(expected, &path).clone(),
+                actual: lookup(result, &path).clone(),
+                path,
+            }),
+        }
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_compare_within_tolerance_ignores_timestamp() {
+        let expected = json!({"id": "a.png", "timestamp": "t1", "top_k": [{"label": "cat", "score": 0.9}]});
+        let actual = json!({"id": "a.png", "timestamp": "t2", "top_k": [{"label": "cat", "score": 0.90005}]});
+        let mut max_diff = 0.0;
+        assert_eq!(compare("", &actual, &expected, 1e-4, &mut max_diff), None);
+        assert!(max_diff > 0.0 && max_diff < 1e-4);
+    }
+
+    #[test]
+    fn test_check_reports_first_mismatch_and_missing() {
+        let golden = BTreeMap::from([("a".to_string(), json!({"top_k": [{"label": "cat", "score": 0.9}]}))]);
+        let actual = BTreeMap::from([
+            ("a".to_string(), json!({"top_k": [{"label": "dog", "score": 0.9}]})),
+            ("b".to_string(), json!({})),
+        ]);
+        let report = check(&actual, &golden, 1e-4);
+        assert!(!report.ok());
+        assert_eq!(report.mismatches[0].path, "top_k[0].label");
+        assert_eq!(report.mismatches[0].expected, "cat");
+        assert_eq!(report.mismatches[0].actual, "dog");
+        assert_eq!(report.missing, vec!["b"]);
+    }
+
+    #[test]
+    fn test_array_length_mismatch() {
+        let mut max_diff = 0.0;
+        let path = compare("", &json!({"data": [1.0]}), &json!({"data": [1.0, 2.0]}), 1e-4, &mut max_diff);
+        assert_eq!(path.as_deref(), Some("data.len"));
+    }
+}
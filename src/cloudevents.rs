@@ -0,0 +1,133 @@
+//! CloudEvents envelopes (structured JSON mode, spec 1.0) for queue messages.
+//!
+//! Incoming jobs may be wrapped in an event whose `data` holds the JSON job
+//! format; results can be emitted as events of type [`RESULT_TYPE`].
+
+use anyhow::{bail, Context, Result};
+use serde_json::{json, Value};
+
+use crate::trace_context::{self, TRACEPARENT, TRACESTATE};
+
+/// CloudEvents specification version produced and accepted.
+pub const SPEC_VERSION: &str = "1.0";
+
+/// Event type of emitted results.
+pub const RESULT_TYPE: &str = "io.omniengine.result";
+
+/// Returns whether `message` is a structured-mode CloudEvent.
+pub fn is_event(message: &Value) -> bool {
+    message.get("specversion").is_some()
+}
+
+/// Unwraps a CloudEvent into the contained job; plain jobs are returned
+/// unchanged.
+///
+/// The job id defaults to the event id. Event `id`, `source` and `type`
+/// are kept in `meta.cloudevent` and so appear in the result. The
+/// distributed tracing extension (`traceparent`, `tracestate`) becomes the
+/// job's trace context unless `data.meta` has one.
+pub fn unwrap_job(message: Value) -> Result<Value> {
+    if !is_event(&message) {
+        return Ok(message);
+    }
+    let Value::Object(mut event) = message else { unreachable!() };
+    let version = event["specversion"].as_str().unwrap_or_default();
+    anyhow::ensure!(version.starts_with("1."), "CloudEvents-Version '{}' nicht unterstützt", version);
+    if let Some(content_type) = event.get("datacontenttype").and_then(Value::as_str) {
+        anyhow::ensure!(content_type.contains("json"), "CloudEvent-Inhalt '{}' ist kein JSON", content_type);
+    }
+    let id = event.get("id").and_then(Value::as_str).context("CloudEvent ohne 'id'")?.to_string();
+    let mut job = match event.remove("data") {
+        Some(Value::Object(job)) => job,
+        Some(_) => bail!("CloudEvent '{}': 'data' muss ein Job-Objekt sein", id),
+        None if event.contains_key("data_base64") => bail!("CloudEvent '{}': 'data_base64' wird nicht unterstützt", id),
+        None => bail!("CloudEvent '{}' ohne 'data'", id),
+    };
+
+    job.entry("id").or_insert_with(|| id.clone().into());
+    let origin = json!({ "id": id, "source": event.get("source"), "type": event.get("type") });
+    let meta = job.entry("meta").or_insert_with(|| json!({}));
+    if let Value::Object(meta) = meta {
+        meta.insert("cloudevent".to_string(), origin);
+        let attribute = |name| event.get(name).and_then(Value::as_str);
+        trace_context::inject(meta, attribute(TRACEPARENT), attribute(TRACESTATE));
+    }
+    Ok(Value::Object(job))
+}
+
+/// Wraps a result payload in a CloudEvent from `source`.
+///
+/// The event id is the job id, so redelivered jobs produce duplicate events
+/// consumers can drop. The trace context of the result is set as the
+/// distributed tracing extension.
+pub fn result_event(result: &Value, source: &str) -> Value {
+    let mut event = json!({
+        "specversion": SPEC_VERSION,
+        "id": result["id"],
+        "source": source,
+        "type": RESULT_TYPE,
+        "subject": result["id"],
+        "datacontenttype": "application/json",
+        "data": result,
+    });
+    if let Some(time) = result.get("timestamp") {
+        event["time"] = time.clone();
+    }
+    for key in [TRACEPARENT, TRACESTATE] {
+        if let Some(value) = result.get(key) {
+            event[key] = value.clone();
+        }
+    }
+    event
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unwrap_job_event() {
+        let event = json!({
+            "specversion": "1.0",
+            "id": "evt-1",
+            "source": "/cameras/7",
+            "type": "com.example.frame",
+            "datacontenttype": "application/json",
+            "data": {"shape": [1], "data": [0.5], "meta": {"k": "v"}},
+        });
+        let job = unwrap_job(event).unwrap();
+        assert_eq!(job["id"], "evt-1");
+        assert_eq!(job["meta"]["k"], "v");
+        assert_eq!(job["meta"]["cloudevent"]["source"], "/cameras/7");
+
+        let plain = json!({"id": "j1", "shape": [1], "data": [0.5]});
+        assert_eq!(unwrap_job(plain.clone()).unwrap(), plain);
+
+        assert!(unwrap_job(json!({"specversion": "1.0", "id": "e", "data": "text"})).is_err());
+        assert!(unwrap_job(json!({"specversion": "0.3", "id": "e", "data": {}})).is_err());
+    }
+
+    #[test]
+    fn test_tracing_extension() {
+        let traceparent = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+        let event = json!({"specversion": "1.0", "id": "e", "traceparent": traceparent, "tracestate": "v=1", "data": {}});
+        let job = unwrap_job(event).unwrap();
+        assert_eq!(job["meta"][TRACEPARENT], traceparent);
+        assert_eq!(job["meta"][TRACESTATE], "v=1");
+
+        let result = json!({"id": "e", "traceparent": traceparent});
+        let event = result_event(&result, "omniengine/gpu-a");
+        assert_eq!(event[TRACEPARENT], traceparent);
+        assert!(event.get(TRACESTATE).is_none());
+    }
+
+    #[test]
+    fn test_result_event() {
+        let result = json!({"id": "j1", "timestamp": "2026-01-01T00:00:00Z", "top_k": []});
+        let event = result_event(&result, "omniengine/gpu-a");
+        assert_eq!(event["id"], "j1");
+        assert_eq!(event["type"], RESULT_TYPE);
+        assert_eq!(event["time"], "2026-01-01T00:00:00Z");
+        assert_eq!(event["data"], result);
+    }
+}
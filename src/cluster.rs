@@ -0,0 +1,338 @@
+//! Multi-node work sharing via Redis Streams consumer groups.
+//!
+//! Every node joins the same consumer group on a shared job stream under its
+//! own node id, so each message is delivered to exactly one node. Messages
+//! are acknowledged (`XACK`) only after their results are stored. A node that
+//! dies leaves its messages pending; other nodes take them over with
+//! `XAUTOCLAIM` once they have been idle for `claim_idle_ms`.
+//!
+//! Each node also refreshes a heartbeat key `{stream}:nodes:{node_id}` that
+//! expires after three missed heartbeats, so live nodes can be listed with
+//! `SCAN`/`KEYS`.
+//!
+//! Messages that cannot be decoded are moved to the dead-letter stream
+//! `{stream}:dlq` (with the error) instead of being redelivered forever;
+//! `omniengine queue requeue` moves them back.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use chrono::Utc;
+use redis::streams::{StreamAutoClaimOptions, StreamAutoClaimReply, StreamId, StreamReadOptions, StreamReadReply};
+use redis::AsyncCommands;
+use tokio::sync::{broadcast, mpsc};
+use tracing::{info, warn};
+
+use crate::health::health;
+use crate::types::{Ack, ClusterCfg, Job, JobRequest};
+
+/// Stream entry field holding the JSON-encoded `JobRequest`.
+pub const JOB_FIELD: &str = "job";
+
+/// Returns the node identity: configured id, `$HOSTNAME` (pod name) or pid.
+pub fn node_id(cfg: &ClusterCfg) -> String {
+    cfg.node_id.clone().unwrap_or_else(default_node_id)
+}
+
+/// Node identity without configuration: `$HOSTNAME` (pod name) or pid.
+pub fn default_node_id() -> String {
+    std::env::var("HOSTNAME")
+        .ok()
+        .filter(|h| !h.is_empty())
+        .unwrap_or_else(|| format!("node-{}", std::process::id()))
+}
+
+/// Key of a node's heartbeat entry.
+pub fn heartbeat_key(cfg: &ClusterCfg, node: &str) -> String {
+    format!("{}:nodes:{}", cfg.stream, node)
+}
+
+/// Key of the dead-letter stream.
+pub fn dead_letter_key(cfg: &ClusterCfg) -> String {
+    format!("{}:dlq", cfg.stream)
+}
+
+/// Stream entry field holding a protobuf-encoded job (feature `protobuf`).
+pub const JOB_PB_FIELD: &str = "job_pb";
+
+/// Stream entry field holding a JSON result (or result CloudEvent).
+pub const RESULT_FIELD: &str = "result";
+
+/// Stream entry field holding a protobuf-encoded result.
+#[cfg_attr(not(feature = "protobuf"), allow(dead_code))]
+pub const RESULT_PB_FIELD: &str = "result_pb";
+
+/// Returns the job field of a stream entry and its raw content.
+pub fn job_payload(entry: &StreamId) -> Option<(&'static str, Vec<u8>)> {
+    [JOB_FIELD, JOB_PB_FIELD]
+        .into_iter()
+        .find_map(|field| entry.get::<Vec<u8>>(field).filter(|raw| !raw.is_empty()).map(|raw| (field, raw)))
+}
+
+/// Decodes a stream entry (plain job, CloudEvent or protobuf) into a job.
+fn decode(entry: &StreamId) -> Result<Job> {
+    if let Some(raw) = entry.get::<Vec<u8>>(JOB_PB_FIELD) {
+        return decode_pb(&raw);
+    }
+    let raw: String = entry.get(JOB_FIELD).with_context(|| format!("Feld '{}' fehlt", JOB_FIELD))?;
+    let message = crate::cloudevents::unwrap_job(serde_json::from_str(&raw)?)?;
+    serde_json::from_value::<JobRequest>(message)?.into_job()
+}
+
+/// Decodes a protobuf job message.
+#[cfg(feature = "protobuf")]
+pub(crate) fn decode_pb(raw: &[u8]) -> Result<Job> {
+    crate::proto::decode_job(raw)
+}
+
+#[cfg(not(feature = "protobuf"))]
+pub(crate) fn decode_pb(_raw: &[u8]) -> Result<Job> {
+    anyhow::bail!("Protobuf-Job empfangen, aber Feature 'protobuf' nicht aktiviert")
+}
+
+/// Encodes a result for the result stream as (field, value).
+fn encode_result(cfg: &ClusterCfg, result: &serde_json::Value, source: &str) -> (&'static str, Vec<u8>) {
+    match cfg.result_encoding.as_str() {
+        #[cfg(feature = "protobuf")]
+        "protobuf" => (RESULT_PB_FIELD, crate::proto::encode_result(result)),
+        _ if cfg.cloudevents => (RESULT_FIELD, crate::cloudevents::result_event(result, source).to_string().into_bytes()),
+        _ => (RESULT_FIELD, result.to_string().into_bytes()),
+    }
+}
+
+/// Checks the result stream settings.
+pub fn validate_result_encoding(cfg: &ClusterCfg) -> Result<()> {
+    match cfg.result_encoding.as_str() {
+        "json" => Ok(()),
+        "protobuf" if cfg.cloudevents => anyhow::bail!("cloudevents und result_encoding = \"protobuf\" schließen sich aus"),
+        "protobuf" if cfg!(feature = "protobuf") => Ok(()),
+        "protobuf" => anyhow::bail!("result_encoding = \"protobuf\" benötigt das Feature 'protobuf'"),
+        other => anyhow::bail!("Unbekanntes result_encoding '{}' (erwartet: json, protobuf)", other),
+    }
+}
+
+/// Appends every result of this node to `cfg.result_stream`.
+///
+/// Results are taken from the in-process notifications, so a sink that
+/// falls far behind skips results (with a warning) instead of blocking
+/// the workers.
+pub async fn run_result_sink(cfg: ClusterCfg, redis_url: String) -> Result<()> {
+    let Some(stream) = cfg.result_stream.clone() else { return Ok(()) };
+    let source = format!("omniengine/{}", node_id(&cfg));
+    let mut results = crate::results::subscribe();
+    let client = redis::Client::open(redis_url.as_str())?;
+    let mut con = client.get_multiplexed_async_connection().await?;
+    info!("Ergebnisse werden nach {} geschrieben", stream);
+
+    loop {
+        let result = match results.recv().await {
+            Ok(result) => result,
+            Err(broadcast::error::RecvError::Lagged(n)) => {
+                warn!("Ergebnis-Stream {}: {} Ergebnisse übersprungen", stream, n);
+                continue;
+            }
+            Err(broadcast::error::RecvError::Closed) => return Ok(()),
+        };
+        let added: redis::RedisResult<String> = con.xadd(&stream, "*", &[encode_result(&cfg, &result, &source)]).await;
+        if let Err(e) = added {
+            warn!("Ergebnis {} nicht in {} geschrieben: {}", result["id"], stream, e);
+        }
+    }
+}
+
+/// Consumes jobs from the shared stream and feeds them into `tx`.
+///
+/// Runs until the input channel is closed.
+pub async fn run_cluster_source(cfg: ClusterCfg, redis_url: String, tx: mpsc::Sender<Job>) -> Result<()> {
+    let node = node_id(&cfg);
+    let client = redis::Client::open(redis_url.as_str())?;
+    let mut con = client.get_multiplexed_async_connection().await?;
+
+    // Gruppe anlegen (existiert sie schon, ist das kein Fehler)
+    let created: redis::RedisResult<()> = con.xgroup_create_mkstream(&cfg.stream, &cfg.group, "$").await;
+    if let Err(e) = created {
+        anyhow::ensure!(e.code() == Some("BUSYGROUP"), "Consumer-Gruppe konnte nicht angelegt werden: {}", e);
+    }
+    info!("Cluster-Knoten {} liest {} (Gruppe {})", node, cfg.stream, cfg.group);
+
+    let delivered = Arc::new(AtomicU64::new(0));
+    let ack_tx = spawn_acker(&client, &cfg).await?;
+    tokio::spawn(heartbeat(client.clone(), cfg.clone(), node.clone(), Arc::clone(&delivered)));
+
+    // Blockierendes XREADGROUP auf eigener Verbindung
+    let mut read_con = client.get_multiplexed_async_connection().await?;
+    let read_opts = StreamReadOptions::default().group(&cfg.group, &node).count(cfg.read_count).block(1000);
+    let claim_every = Duration::from_millis((cfg.claim_idle_ms / 2).max(1000));
+    let mut last_claim = Instant::now();
+    let mut claim_cursor = "0-0".to_string();
+
+    loop {
+        // Beim Drain keine neuen Nachrichten mehr annehmen
+        if health().is_draining() {
+            info!("Knoten {} im Drain, lese keine Nachrichten mehr", node);
+            return Ok(());
+        }
+        // Pausiert: zugestellte Jobs laufen weiter, neue werden nicht gelesen
+        if health().is_paused() {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            continue;
+        }
+        let mut entries: Vec<StreamId> = Vec::new();
+
+        // Verwaiste Nachrichten ausgefallener Knoten übernehmen
+        if last_claim.elapsed() >= claim_every {
+            last_claim = Instant::now();
+            let reply: StreamAutoClaimReply = con
+                .xautoclaim_options(
+                    &cfg.stream,
+                    &cfg.group,
+                    &node,
+                    cfg.claim_idle_ms,
+                    &claim_cursor,
+                    StreamAutoClaimOptions::default().count(cfg.read_count),
+                )
+                .await?;
+            if !reply.claimed.is_empty() {
+                warn!("Knoten {} übernimmt {} verwaiste Nachrichten", node, reply.claimed.len());
+            }
+            claim_cursor = reply.next_stream_id;
+            entries.extend(reply.claimed);
+        }
+
+        let reply: StreamReadReply = read_con.xread_options(&[&cfg.stream], &[">"], &read_opts).await?;
+        entries.extend(reply.keys.into_iter().flat_map(|k| k.ids));
+
+        for entry in entries {
+            let mut job = match decode(&entry) {
+                Ok(job) => job,
+                Err(e) => {
+                    // Nicht dekodierbar: in die DLQ und quittieren, sonst wird sie endlos neu zugestellt
+                    warn!("Nachricht {} in DLQ verschoben: {}", entry.id, e);
+                    let (field, raw) = job_payload(&entry).unwrap_or((JOB_FIELD, Vec::new()));
+                    let fields = [
+                        (field, raw),
+                        ("error", e.to_string().into_bytes()),
+                        ("source_id", entry.id.clone().into_bytes()),
+                        ("node", node.clone().into_bytes()),
+                    ];
+                    let moved: redis::RedisResult<String> = con.xadd(dead_letter_key(&cfg), "*", &fields).await;
+                    if let Err(e) = moved {
+                        warn!("DLQ-Eintrag für {} fehlgeschlagen: {}", entry.id, e);
+                    }
+                    let _ = ack_tx.send(entry.id);
+                    continue;
+                }
+            };
+            job.meta.insert("node".to_string(), node.clone().into());
+            job.ack = Some(Ack::new(ack_tx.clone(), entry.id));
+            if tx.send(job).await.is_err() {
+                return Ok(()); // Runtime beendet
+            }
+            delivered.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Starts the task acknowledging finished messages in batches.
+async fn spawn_acker(client: &redis::Client, cfg: &ClusterCfg) -> Result<mpsc::UnboundedSender<String>> {
+    let mut con = client.get_multiplexed_async_connection().await?;
+    let (tx, mut rx) = mpsc::unbounded_channel::<String>();
+    let (stream, group) = (cfg.stream.clone(), cfg.group.clone());
+
+    tokio::spawn(async move {
+        while let Some(first) = rx.recv().await {
+            let mut ids = vec![first];
+            while let Ok(id) = rx.try_recv() {
+                ids.push(id);
+            }
+            if let Err(e) = con.xack::<_, _, _, ()>(&stream, &group, &ids).await {
+                warn!("XACK für {} Nachrichten fehlgeschlagen: {}", ids.len(), e);
+            }
+        }
+    });
+    Ok(tx)
+}
+
+/// Refreshes the node's heartbeat key until the process exits.
+async fn heartbeat(client: redis::Client, cfg: ClusterCfg, node: String, delivered: Arc<AtomicU64>) {
+    let key = heartbeat_key(&cfg, &node);
+    let started_at = Utc::now().to_rfc3339();
+    let ttl = cfg.heartbeat_secs.max(1) * 3;
+    let mut ticker = tokio::time::interval(Duration::from_secs(cfg.heartbeat_secs.max(1)));
+
+    loop {
+        ticker.tick().await;
+        let value = serde_json::json!({
+            "node": node,
+            "started_at": started_at,
+            "last_seen": Utc::now().to_rfc3339(),
+            "delivered": delivered.load(Ordering::Relaxed),
+        });
+        let result = async {
+            let mut con = client.get_multiplexed_async_connection().await?;
+            con.set_ex::<_, _, ()>(&key, value.to_string(), ttl).await
+        }
+        .await;
+        if let Err(e) = result {
+            warn!("Heartbeat für Knoten {} fehlgeschlagen: {}", node, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use redis::Value;
+
+    fn cfg() -> ClusterCfg {
+        toml::from_str("node_id = \"gpu-a\"").unwrap()
+    }
+
+    #[test]
+    fn test_node_identity_and_heartbeat_key() {
+        assert_eq!(node_id(&cfg()), "gpu-a");
+        assert_eq!(heartbeat_key(&cfg(), "gpu-a"), "omniengine:jobs:nodes:gpu-a");
+        assert_eq!(dead_letter_key(&cfg()), "omniengine:jobs:dlq");
+
+        let anonymous = ClusterCfg { node_id: None, ..cfg() };
+        assert!(!node_id(&anonymous).is_empty());
+    }
+
+    #[test]
+    fn test_decode_stream_entry() {
+        let mut entry = StreamId { id: "1-0".to_string(), ..Default::default() };
+        let job = r#"{"id": "j1", "shape": [2], "data": [0.5, 1.5]}"#;
+        entry.map.insert(JOB_FIELD.to_string(), Value::BulkString(job.as_bytes().to_vec()));
+        assert_eq!(decode(&entry).unwrap().tensor[[1]], 1.5);
+
+        let event = r#"{"specversion": "1.0", "id": "evt-1", "source": "/s", "type": "t", "data": {"shape": [1], "data": [2]}}"#;
+        entry.map.insert(JOB_FIELD.to_string(), Value::BulkString(event.as_bytes().to_vec()));
+        let job = decode(&entry).unwrap();
+        assert_eq!(job.id, "evt-1");
+        assert_eq!(job.meta["cloudevent"]["source"], "/s");
+
+        entry.map.clear();
+        assert!(decode(&entry).is_err());
+    }
+
+    #[test]
+    fn test_result_encoding_validation() {
+        let with = |toml_src: &str| toml::from_str::<ClusterCfg>(toml_src).unwrap();
+        assert!(validate_result_encoding(&with("cloudevents = true")).is_ok());
+        assert!(validate_result_encoding(&with("result_encoding = \"avro\"")).is_err());
+        assert!(validate_result_encoding(&with("result_encoding = \"protobuf\"\ncloudevents = true")).is_err());
+        assert_eq!(validate_result_encoding(&with("result_encoding = \"protobuf\"")).is_ok(), cfg!(feature = "protobuf"));
+    }
+
+    #[cfg(feature = "protobuf")]
+    #[test]
+    fn test_decode_protobuf_entry() {
+        let job = Job { id: "j1".to_string(), tensor: ndarray::ArrayD::from_elem(ndarray::IxDyn(&[2]), 1.5), ..Default::default() };
+        let mut entry = StreamId { id: "1-0".to_string(), ..Default::default() };
+        entry.map.insert(JOB_PB_FIELD.to_string(), Value::BulkString(crate::proto::encode_job(&job)));
+        assert_eq!(decode(&entry).unwrap().tensor, job.tensor);
+        assert_eq!(job_payload(&entry).unwrap().0, JOB_PB_FIELD);
+    }
+}
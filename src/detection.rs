@@ -0,0 +1,181 @@
+//! Structured object detection results.
+//!
+//! Decodes raw detector outputs (`[num_boxes, 4 (+1) + num_classes]` per
+//! sample), applies score filtering and class-wise non-maximum suppression,
+//! attaches labels and stores the result as a list of detections instead of a
+//! flat float array.
+
+use anyhow::Result;
+use ndarray::{ArrayViewD, Axis};
+use serde::Serialize;
+use serde_json::{json, Value};
+
+use crate::pipeline::OutputFormatter;
+use crate::types::DetectionCfg;
+
+/// A single detected object.
+///
+/// `bbox` is `[x1, y1, x2, y2]`, in pixels or normalized to `[0, 1]` by the
+/// input size depending on the configuration.
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct Detection {
+    pub bbox: [f32; 4],
+    pub class_id: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    pub score: f32,
+}
+
+/// Converts a box from the model's format to xyxy.
+fn to_xyxy(b: [f32; 4], format: &str) -> Result<[f32; 4]> {
+    Ok(match format {
+        "xyxy" => b,
+        "xywh" => [b[0], b[1], b[0] + b[2], b[1] + b[3]],
+        "cxcywh" => [b[0] - b[2] / 2.0, b[1] - b[3] / 2.0, b[0] + b[2] / 2.0, b[1] + b[3] / 2.0],
+        other => anyhow::bail!("Unbekanntes Box-Format '{}'", other),
+    })
+}
+
+/// Intersection over union of two xyxy boxes.
+pub fn iou(a: &[f32; 4], b: &[f32; 4]) -> f32 {
+    let w = (a[2].min(b[2]) - a[0].max(b[0])).max(0.0);
+    let h = (a[3].min(b[3]) - a[1].max(b[1])).max(0.0);
+    let inter = w * h;
+    let area = |r: &[f32; 4]| (r[2] - r[0]).max(0.0) * (r[3] - r[1]).max(0.0);
+    let union = area(a) + area(b) - inter;
+    if union > 0.0 { inter / union } else { 0.0 }
+}
+
+/// Greedy class-wise non-maximum suppression.
+///
+/// Keeps the highest scoring boxes and drops boxes of the same class that
+/// overlap a kept box by more than `iou_threshold`.
+pub fn nms(mut dets: Vec<Detection>, iou_threshold: f32, max_detections: usize) -> Vec<Detection> {
+    dets.sort_by(|a, b| b.score.total_cmp(&a.score));
+    let mut kept: Vec<Detection> = Vec::new();
+    for d in dets {
+        if kept.len() >= max_detections {
+            break;
+        }
+        if kept.iter().all(|k| k.class_id != d.class_id || iou(&k.bbox, &d.bbox) <= iou_threshold) {
+            kept.push(d);
+        }
+    }
+    kept
+}
+
+/// Output formatter producing `{"detections": [...]}` results.
+pub struct DetectionOutput {
+    cfg: DetectionCfg,
+    input_size: (f32, f32), // (width, height)
+}
+
+impl DetectionOutput {
+    /// Creates the formatter; `width`/`height` are the model input size used
+    /// for normalized boxes.
+    pub fn new(cfg: &DetectionCfg, width: usize, height: usize) -> Self {
+        Self { cfg: cfg.clone(), input_size: (width as f32, height as f32) }
+    }
+
+    /// Decodes one sample output into filtered, suppressed detections.
+    pub fn decode(&self, output: ArrayViewD<f32>) -> Result<Vec<Detection>> {
+        let rows = match output.ndim() {
+            2 => output,
+            3 if output.shape()[0] == 1 => output.index_axis_move(Axis(0), 0),
+            _ => anyhow::bail!("Detektor-Output muss [boxes, attrs] sein, bekommen {:?}", output.shape()),
+        };
+        let offset = if self.cfg.objectness { 5 } else { 4 };
+        anyhow::ensure!(
+            rows.shape()[1] > offset,
+            "Detektor-Output hat zu wenige Attribute ({}), erwartet > {}",
+            rows.shape()[1],
+            offset
+        );
+
+        let mut dets = Vec::new();
+        for row in rows.axis_iter(Axis(0)) {
+            let row: Vec<f32> = row.iter().copied().collect();
+            let (class_id, class_score) = row[offset..]
+                .iter()
+                .copied()
+                .enumerate()
+                .max_by(|a, b| a.1.total_cmp(&b.1))
+                .unwrap_or((0, 0.0));
+            let score = if self.cfg.objectness { row[4] * class_score } else { class_score };
+            if score < self.cfg.score_threshold {
+                continue;
+            }
+
+            let mut bbox = to_xyxy([row[0], row[1], row[2], row[3]], &self.cfg.box_format)?;
+            if self.cfg.normalize {
+                let (w, h) = self.input_size;
+                bbox = [bbox[0] / w, bbox[1] / h, bbox[2] / w, bbox[3] / h];
+            }
+            dets.push(Detection { bbox, class_id, label: self.cfg.labels.get(class_id).cloned(), score });
+        }
+
+        Ok(nms(dets, self.cfg.iou_threshold, self.cfg.max_detections))
+    }
+}
+
+impl OutputFormatter for DetectionOutput {
+    fn format(&self, output: ArrayViewD<f32>) -> Result<Value> {
+        Ok(json!({ "detections": self.decode(output)? }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::Array;
+
+    fn cfg() -> DetectionCfg {
+        DetectionCfg {
+            box_format: "xyxy".to_string(),
+            objectness: false,
+            score_threshold: 0.25,
+            iou_threshold: 0.5,
+            max_detections: 100,
+            normalize: false,
+            labels: vec!["cat".to_string(), "dog".to_string()],
+        }
+    }
+
+    #[test]
+    fn test_iou() {
+        let a = [0.0, 0.0, 10.0, 10.0];
+        assert!((iou(&a, &a) - 1.0).abs() < 1e-6);
+        assert_eq!(iou(&a, &[20.0, 20.0, 30.0, 30.0]), 0.0);
+        assert!((iou(&a, &[5.0, 0.0, 15.0, 10.0]) - 1.0 / 3.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_decode_filters_and_suppresses() {
+        let out = Array::from_shape_vec(
+            (4, 6),
+            vec![
+                0.0, 0.0, 10.0, 10.0, 0.9, 0.1, // cat
+                1.0, 1.0, 11.0, 11.0, 0.8, 0.1, // cat, überlappt -> NMS
+                1.0, 1.0, 11.0, 11.0, 0.1, 0.7, // dog, andere Klasse bleibt
+                50.0, 50.0, 60.0, 60.0, 0.1, 0.1, // unter Schwellwert
+            ],
+        )
+        .unwrap()
+        .into_dyn();
+
+        let dets = DetectionOutput::new(&cfg(), 100, 100).decode(out.view()).unwrap();
+        assert_eq!(dets.len(), 2);
+        assert_eq!(dets[0].label.as_deref(), Some("cat"));
+        assert_eq!(dets[1].label.as_deref(), Some("dog"));
+    }
+
+    #[test]
+    fn test_decode_cxcywh_normalized() {
+        let cfg = DetectionCfg { box_format: "cxcywh".to_string(), normalize: true, ..cfg() };
+        let out = Array::from_shape_vec((1, 1, 6), vec![50.0, 50.0, 20.0, 40.0, 0.0, 0.9]).unwrap().into_dyn();
+
+        let dets = DetectionOutput::new(&cfg, 100, 200).decode(out.view()).unwrap();
+        assert_eq!(dets[0].bbox, [0.4, 0.15, 0.6, 0.35]);
+        assert_eq!(dets[0].class_id, 1);
+    }
+}
@@ -0,0 +1,369 @@
+//! Input and prediction drift monitoring (`[drift]`).
+//!
+//! The dispatcher feeds every accepted job's input, the workers feed every
+//! stored output. Running mean, standard deviation and a value histogram of
+//! the inputs plus the distribution of predicted classes (argmax of 1-D
+//! outputs) are exported as metrics. Each full window of jobs is compared to
+//! a recorded baseline by the population stability index (PSI); drifting
+//! windows raise an event on Redis Pub/Sub. Without a baseline file the first
+//! full window is recorded as the baseline.
+
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use ndarray::ArrayViewD;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::storage::redis_store::RedisStorage;
+use crate::types::{Config, DriftCfg};
+
+/// Smoothing of empty bins in the PSI.
+const EPSILON: f64 = 1e-4;
+
+/// Input and prediction statistics over a number of jobs.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Stats {
+    pub jobs: u64,
+    pub count: u64,
+    pub sum: f64,
+    pub sum_sq: f64,
+    pub histogram: Vec<u64>,
+    pub classes: BTreeMap<usize, u64>,
+}
+
+impl Stats {
+    fn new(bins: usize) -> Self {
+        Self { histogram: vec![0; bins], ..Default::default() }
+    }
+
+    pub fn mean(&self) -> f64 {
+        if self.count == 0 {
+            return 0.0;
+        }
+        self.sum / self.count as f64
+    }
+
+    pub fn std(&self) -> f64 {
+        if self.count == 0 {
+            return 0.0;
+        }
+        (self.sum_sq / self.count as f64 - self.mean().powi(2)).max(0.0).sqrt()
+    }
+}
+
+/// Comparison of one window with the baseline.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct DriftReport {
+    pub jobs: u64,
+    pub input_psi: f64,
+    pub prediction_psi: f64,
+    /// Shift of the input mean in baseline standard deviations.
+    pub mean_shift: f64,
+    pub drifted: bool,
+}
+
+struct State {
+    total: Stats,
+    window: Stats,
+    baseline: Option<Stats>,
+    last: Option<DriftReport>,
+    events: u64,
+}
+
+/// Drift monitor of the model served by this process.
+pub struct DriftMonitor {
+    cfg: DriftCfg,
+    model: String,
+    state: Mutex<State>,
+}
+
+static MONITOR: OnceLock<DriftMonitor> = OnceLock::new();
+
+/// Returns the monitor configured by [`init`], if any.
+pub fn monitor() -> Option<&'static DriftMonitor> {
+    MONITOR.get()
+}
+
+/// Sets up the monitor for `[drift]` and starts the periodic comparison.
+pub fn init(cfg: &Config, store: RedisStorage) -> Result<()> {
+    let Some(drift) = &cfg.drift else { return Ok(()) };
+    let monitor = DriftMonitor::new(drift.clone(), &cfg.model.model_path)?;
+    if MONITOR.set(monitor).is_err() {
+        tracing::warn!("Drift-Monitor bereits initialisiert");
+        return Ok(());
+    }
+    let monitor = MONITOR.get().unwrap();
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(Duration::from_secs(monitor.cfg.interval_secs.max(1)));
+        loop {
+            ticker.tick().await;
+            match monitor.check() {
+                Ok(Some(event)) => {
+                    if let Err(e) = store.publish_json(&monitor.cfg.channel, &event).await {
+                        tracing::warn!("Drift-Event nicht veröffentlicht: {}", e);
+                    }
+                }
+                Ok(None) => {}
+                Err(e) => tracing::warn!("Drift-Prüfung fehlgeschlagen: {:#}", e),
+            }
+        }
+    });
+    Ok(())
+}
+
+impl DriftMonitor {
+    pub fn new(cfg: DriftCfg, model: &str) -> Result<Self> {
+        anyhow::ensure!(cfg.bins > 0, "drift.bins muss > 0 sein");
+        anyhow::ensure!(cfg.range[0] < cfg.range[1], "drift.range {:?} ist leer", cfg.range);
+        let baseline = match std::fs::read(&cfg.baseline_path) {
+            Ok(raw) => {
+                let stats: Stats = serde_json::from_slice(&raw)
+                    .with_context(|| format!("Drift-Baseline {} ungültig", cfg.baseline_path))?;
+                anyhow::ensure!(
+                    stats.histogram.len() == cfg.bins,
+                    "Drift-Baseline {} hat {} statt {} Bins",
+                    cfg.baseline_path,
+                    stats.histogram.len(),
+                    cfg.bins
+                );
+                Some(stats)
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+            Err(e) => return Err(e).with_context(|| format!("Drift-Baseline {} nicht lesbar", cfg.baseline_path)),
+        };
+        let state = State { total: Stats::new(cfg.bins), window: Stats::new(cfg.bins), baseline, last: None, events: 0 };
+        Ok(Self { cfg, model: model.to_string(), state: Mutex::new(state) })
+    }
+
+    /// Adds the input tensor of one job.
+    pub fn observe_input(&self, input: ArrayViewD<f32>) {
+        let [lo, hi] = self.cfg.range;
+        let bins = self.cfg.bins;
+        let mut job = Stats::new(bins);
+        job.jobs = 1;
+        for &v in input.iter().filter(|v| v.is_finite()) {
+            job.count += 1;
+            job.sum += v as f64;
+            job.sum_sq += (v as f64).powi(2);
+            let bin = ((v - lo) / (hi - lo) * bins as f32).floor().clamp(0.0, (bins - 1) as f32);
+            job.histogram[bin as usize] += 1;
+        }
+        let mut state = self.state.lock().unwrap();
+        merge(&mut state.total, &job);
+        merge(&mut state.window, &job);
+    }
+
+    /// Adds the output of one job; only 1-D outputs (class scores) count.
+    pub fn observe_output(&self, output: ArrayViewD<f32>) {
+        if output.ndim() != 1 || output.len() < 2 {
+            return;
+        }
+        let class = output.iter().enumerate().fold((0, f32::NEG_INFINITY), |best, (i, &v)| if v > best.1 { (i, v) } else { best }).0;
+        let mut state = self.state.lock().unwrap();
+        *state.total.classes.entry(class).or_default() += 1;
+        *state.window.classes.entry(class).or_default() += 1;
+    }
+
+    /// Compares a full window with the baseline (or records it as the
+    /// baseline). Returns the event to publish if the window drifted.
+    pub fn check(&self) -> Result<Option<Value>> {
+        let mut state = self.state.lock().unwrap();
+        if state.window.jobs < self.cfg.window {
+            return Ok(None);
+        }
+        let window = std::mem::replace(&mut state.window, Stats::new(self.cfg.bins));
+        let Some(baseline) = &state.baseline else {
+            std::fs::write(&self.cfg.baseline_path, serde_json::to_vec_pretty(&window)?)
+                .with_context(|| format!("Drift-Baseline {} nicht schreibbar", self.cfg.baseline_path))?;
+            tracing::info!("Drift-Baseline aus {} Jobs nach {} geschrieben", window.jobs, self.cfg.baseline_path);
+            state.baseline = Some(window);
+            return Ok(None);
+        };
+
+        let report = compare(baseline, &window, self.cfg.threshold);
+        let input = json!({ "mean": window.mean(), "std": window.std(), "baseline_mean": baseline.mean(), "baseline_std": baseline.std() });
+        state.last = Some(report.clone());
+        if !report.drifted {
+            return Ok(None);
+        }
+        state.events += 1;
+        tracing::warn!(
+            "Drift erkannt (Modell {}): Input-PSI {:.3}, Vorhersage-PSI {:.3}, Mittelwert-Verschiebung {:.2}σ",
+            self.model,
+            report.input_psi,
+            report.prediction_psi,
+            report.mean_shift
+        );
+        Ok(Some(json!({
+            "type": "drift",
+            "model": self.model,
+            "timestamp": chrono::Utc::now().to_rfc3339(),
+            "threshold": self.cfg.threshold,
+            "window": report,
+            "input": input,
+        })))
+    }
+
+    /// Renders the statistics in Prometheus text format with constant `labels`.
+    pub fn render_metrics(&self, labels: &[(String, String)]) -> String {
+        let pairs: Vec<String> = labels.iter().map(|(k, v)| crate::health::label(k, v)).collect();
+        let with = |extra: Option<String>| {
+            let all: Vec<String> = pairs.iter().cloned().chain(extra).collect();
+            if all.is_empty() { String::new() } else { format!("{{{}}}", all.join(",")) }
+        };
+        let labels = with(None);
+        let state = self.state.lock().unwrap();
+        let last = state.last.clone().unwrap_or_default();
+
+        let mut out = String::new();
+        let gauges = [
+            ("omniengine_input_mean", state.total.mean()),
+            ("omniengine_input_std", state.total.std()),
+            ("omniengine_drift_input_psi", last.input_psi),
+            ("omniengine_drift_prediction_psi", last.prediction_psi),
+            ("omniengine_drift_mean_shift", last.mean_shift),
+        ];
+        for (name, value) in gauges {
+            let _ = writeln!(out, "# TYPE {} gauge\n{}{} {}", name, name, labels, value);
+        }
+        let _ = writeln!(out, "# TYPE omniengine_drift_events_total counter\nomniengine_drift_events_total{} {}", labels, state.events);
+
+        let [lo, hi] = self.cfg.range;
+        let width = (hi - lo) / self.cfg.bins as f32;
+        let _ = writeln!(out, "# TYPE omniengine_input_values histogram");
+        let mut cumulative = 0;
+        for (i, n) in state.total.histogram.iter().enumerate().take(self.cfg.bins - 1) {
+            cumulative += n;
+            let le = crate::health::label("le", &(lo + width * (i + 1) as f32).to_string());
+            let _ = writeln!(out, "omniengine_input_values_bucket{} {}", with(Some(le)), cumulative);
+        }
+        let le = crate::health::label("le", "+Inf");
+        let _ = writeln!(out, "omniengine_input_values_bucket{} {}", with(Some(le)), state.total.count);
+        let _ = writeln!(out, "omniengine_input_values_sum{} {}", labels, state.total.sum);
+        let _ = writeln!(out, "omniengine_input_values_count{} {}", labels, state.total.count);
+
+        let _ = writeln!(out, "# TYPE omniengine_predictions_total counter");
+        for (class, n) in &state.total.classes {
+            let class = crate::health::label("class", &class.to_string());
+            let _ = writeln!(out, "omniengine_predictions_total{} {}", with(Some(class)), n);
+        }
+        out
+    }
+}
+
+/// Renders the drift metrics if `[drift]` is configured.
+pub fn render_metrics(labels: &[(String, String)]) -> String {
+    monitor().map(|m| m.render_metrics(labels)).unwrap_or_default()
+}
+
+fn merge(into: &mut Stats, other: &Stats) {
+    into.jobs += other.jobs;
+    into.count += other.count;
+    into.sum += other.sum;
+    into.sum_sq += other.sum_sq;
+    for (a, b) in into.histogram.iter_mut().zip(&other.histogram) {
+        *a += b;
+    }
+}
+
+fn compare(baseline: &Stats, window: &Stats, threshold: f64) -> DriftReport {
+    let input_psi = psi(baseline.histogram.iter().copied().zip(window.histogram.iter().copied()));
+    // Klassen, die nur in einer der beiden Verteilungen vorkommen, zählen mit 0
+    let classes: std::collections::BTreeSet<_> = baseline.classes.keys().chain(window.classes.keys()).collect();
+    let count = |s: &Stats, c: &usize| s.classes.get(c).copied().unwrap_or(0);
+    let prediction_psi = psi(classes.iter().map(|c| (count(baseline, c), count(window, c))));
+    let mean_shift = (window.mean() - baseline.mean()).abs() / baseline.std().max(f64::EPSILON);
+    DriftReport {
+        jobs: window.jobs,
+        input_psi,
+        prediction_psi,
+        mean_shift,
+        drifted: input_psi > threshold || prediction_psi > threshold,
+    }
+}
+
+/// Population stability index of two count distributions.
+fn psi(bins: impl Iterator<Item = (u64, u64)> + Clone) -> f64 {
+    let (total_a, total_b) = bins.clone().fold((0, 0), |(a, b), (x, y)| (a + x, b + y));
+    if total_a == 0 || total_b == 0 {
+        return 0.0;
+    }
+    bins.map(|(a, b)| {
+        let p = (a as f64 / total_a as f64).max(EPSILON);
+        let q = (b as f64 / total_b as f64).max(EPSILON);
+        (q - p) * (q / p).ln()
+    })
+    .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::{arr1, ArrayD, IxDyn};
+
+    fn monitor(path: &std::path::Path) -> DriftMonitor {
+        let cfg: DriftCfg = toml::from_str(&format!(
+            "baseline_path = {:?}\nrange = [0.0, 1.0]\nbins = 4\nwindow = 2\nthreshold = 0.2",
+            path.to_str().unwrap()
+        ))
+        .unwrap();
+        DriftMonitor::new(cfg, "model.onnx").unwrap()
+    }
+
+    fn feed(m: &DriftMonitor, value: f32, class: usize) {
+        m.observe_input(ArrayD::from_elem(IxDyn(&[1, 4]), value).view());
+        let mut scores = arr1(&[0.0, 0.0, 0.0]);
+        scores[class] = 1.0;
+        m.observe_output(scores.into_dyn().view());
+    }
+
+    #[test]
+    fn test_records_baseline_then_detects_drift() {
+        let path = std::env::temp_dir().join(format!("omni-drift-{}.json", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let m = monitor(&path);
+
+        feed(&m, 0.1, 0);
+        assert!(m.check().unwrap().is_none(), "Fenster noch nicht voll");
+        feed(&m, 0.3, 1);
+        assert!(m.check().unwrap().is_none());
+        let baseline: Stats = serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+        assert_eq!((baseline.jobs, baseline.count), (2, 8));
+        assert_eq!(baseline.histogram, vec![4, 4, 0, 0]);
+
+        // Gleiche Verteilung: keine Drift
+        feed(&m, 0.1, 0);
+        feed(&m, 0.3, 1);
+        assert!(m.check().unwrap().is_none());
+
+        // Werte außerhalb der Range landen im letzten Bin, Klasse 2 ist neu
+        feed(&m, 5.0, 2);
+        feed(&m, 0.9, 2);
+        let event = m.check().unwrap().unwrap();
+        assert_eq!(event["type"], "drift");
+        assert!(event["window"]["input_psi"].as_f64().unwrap() > 0.2);
+        assert!(event["window"]["prediction_psi"].as_f64().unwrap() > 0.2);
+
+        // Neuer Monitor liest die aufgezeichnete Baseline
+        let reloaded = monitor(&path);
+        assert_eq!(reloaded.state.lock().unwrap().baseline, Some(baseline));
+        std::fs::remove_file(&path).unwrap();
+
+        let text = m.render_metrics(&[("pod".to_string(), "omni-0".to_string())]);
+        assert!(text.contains("omniengine_drift_events_total{pod=\"omni-0\"} 1"));
+        assert!(text.contains("omniengine_predictions_total{pod=\"omni-0\",class=\"2\"} 2"));
+        assert!(text.contains("omniengine_input_values_bucket{pod=\"omni-0\",le=\"0.25\"} 8"));
+        assert!(text.contains("omniengine_input_values_count{pod=\"omni-0\"} 24"));
+    }
+
+    #[test]
+    fn test_psi() {
+        assert_eq!(psi([(10, 20), (10, 20)].into_iter()), 0.0);
+        assert!(psi([(90, 10), (10, 90)].into_iter()) > 1.0);
+        assert_eq!(psi([(0, 0)].into_iter()), 0.0);
+    }
+}
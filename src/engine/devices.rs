@@ -0,0 +1,123 @@
+//! GPU selection by CUDA ordinal or device UUID.
+//!
+//! Backends address GPUs by CUDA ordinal. On partitioned A100/H100 nodes the
+//! schedulable devices are MIG instances, which CUDA only exposes through
+//! `CUDA_VISIBLE_DEVICES`; their ordinals are positions in that list. UUIDs
+//! in `gpu_ids` are therefore resolved against `CUDA_VISIBLE_DEVICES`, or, if
+//! it is unset, the runtime sets it to the configured UUIDs before the first
+//! engine initializes CUDA.
+
+use anyhow::{bail, Result};
+
+use crate::types::{GpuId, ModelCfg};
+
+/// Resolved CUDA ordinals of the configured workers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Assignment {
+    pub gpus: Vec<usize>,
+    pub standby: Vec<usize>,
+    /// `CUDA_VISIBLE_DEVICES` to set before CUDA is initialized.
+    pub visible: Option<String>,
+}
+
+/// Resolves `gpu_ids` and `standby_gpu_ids` and sets `CUDA_VISIBLE_DEVICES`
+/// if the UUIDs require it. Must run before the first engine is created.
+pub fn assign(model: &ModelCfg) -> Result<Assignment> {
+    let visible = std::env::var("CUDA_VISIBLE_DEVICES").ok();
+    let assignment = plan(&model.gpu_ids, &model.standby_gpu_ids, visible.as_deref())?;
+    if let Some(visible) = &assignment.visible {
+        tracing::info!("CUDA_VISIBLE_DEVICES={} (aus gpu_ids)", visible);
+        std::env::set_var("CUDA_VISIBLE_DEVICES", visible);
+    }
+    Ok(assignment)
+}
+
+/// Maps the selectors to CUDA ordinals given the current
+/// `CUDA_VISIBLE_DEVICES`.
+pub fn plan(gpus: &[GpuId], standby: &[GpuId], visible: Option<&str>) -> Result<Assignment> {
+    let mut uuids: Vec<&str> = Vec::new();
+    for id in gpus.iter().chain(standby) {
+        if let GpuId::Uuid(uuid) = id {
+            if !uuids.iter().any(|u| u.eq_ignore_ascii_case(uuid)) {
+                uuids.push(uuid);
+            }
+        }
+    }
+    let migs: Vec<&str> = uuids.iter().copied().filter(|u| is_mig(u)).collect();
+    if migs.len() > 1 {
+        bail!(
+            "CUDA adressiert je Prozess nur eine MIG-Instanz, konfiguriert sind {:?}; je Instanz einen Runtime-Prozess starten",
+            migs
+        );
+    }
+    let visible = visible.map(str::trim).filter(|v| !v.is_empty());
+    let set_visible = match visible {
+        None if !uuids.is_empty() => {
+            // Ordinalzahlen würden sich durch das gesetzte CUDA_VISIBLE_DEVICES verschieben
+            if gpus.iter().chain(standby).any(|id| matches!(id, GpuId::Index(_))) {
+                bail!("gpu_ids mischt Ordinalzahlen und UUIDs; ohne CUDA_VISIBLE_DEVICES nur UUIDs verwenden");
+            }
+            Some(uuids.join(","))
+        }
+        _ => None,
+    };
+    let list: Vec<&str> = match visible {
+        Some(visible) => visible.split(',').map(str::trim).collect(),
+        None => uuids.clone(),
+    };
+    let resolve = |id: &GpuId| match id {
+        // Nur Ordinalzahlen: unverändert übernehmen
+        GpuId::Index(i) if uuids.is_empty() || *i < list.len() => Ok(*i),
+        GpuId::Index(i) => bail!("GPU {} existiert nicht (CUDA_VISIBLE_DEVICES={})", i, list.join(",")),
+        GpuId::Uuid(uuid) => match list.iter().position(|v| v.eq_ignore_ascii_case(uuid)) {
+            Some(i) => Ok(i),
+            None => bail!("GPU '{}' ist nicht in CUDA_VISIBLE_DEVICES={}", uuid, list.join(",")),
+        },
+    };
+    Ok(Assignment {
+        gpus: gpus.iter().map(resolve).collect::<Result<_>>()?,
+        standby: standby.iter().map(resolve).collect::<Result<_>>()?,
+        visible: set_visible,
+    })
+}
+
+/// Whether `uuid` names a MIG instance (`MIG-<uuid>` or the older
+/// `MIG-GPU-<uuid>/<gi>/<ci>`).
+fn is_mig(uuid: &str) -> bool {
+    uuid.get(..4).is_some_and(|p| p.eq_ignore_ascii_case("MIG-"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn uuid(s: &str) -> GpuId {
+        GpuId::Uuid(s.to_string())
+    }
+
+    #[test]
+    fn test_ordinals_unchanged() {
+        let plan = plan(&[GpuId::Index(2), GpuId::Index(3)], &[GpuId::Index(0)], Some("4,5,6,7")).unwrap();
+        assert_eq!(plan, Assignment { gpus: vec![2, 3], standby: vec![0], visible: None });
+    }
+
+    #[test]
+    fn test_uuids_resolve_against_visible_devices() {
+        let visible = Some("GPU-aaa, MIG-bbb");
+        let plan = plan(&[uuid("mig-bbb")], &[GpuId::Index(0)], visible).unwrap();
+        assert_eq!((plan.gpus, plan.standby, plan.visible), (vec![1], vec![0], None));
+
+        assert!(super::plan(&[uuid("GPU-ccc")], &[], visible).is_err());
+        assert!(super::plan(&[uuid("GPU-aaa"), GpuId::Index(2)], &[], visible).is_err());
+    }
+
+    #[test]
+    fn test_uuids_set_visible_devices() {
+        let plan = plan(&[uuid("GPU-aaa"), uuid("MIG-bbb")], &[uuid("GPU-ccc")], None).unwrap();
+        assert_eq!(plan.visible.as_deref(), Some("GPU-aaa,MIG-bbb,GPU-ccc"));
+        assert_eq!((plan.gpus, plan.standby), (vec![0, 1], vec![2]));
+
+        assert!(super::plan(&[uuid("GPU-aaa"), GpuId::Index(0)], &[], None).is_err());
+        assert!(super::plan(&[uuid("MIG-aaa"), uuid("MIG-bbb")], &[], None).is_err());
+    }
+}
@@ -0,0 +1,333 @@
+//! Ensembles of several models (`backend = "ensemble"`, `[[model.stages]]`).
+//!
+//! The stages form a graph: each one reads the job inputs or outputs of
+//! earlier stages, e.g. a detector whose crops feed a classifier. The last
+//! stage produces the ensemble's outputs.
+//!
+//! Stages are gang-scheduled: every worker loads all of them on its own
+//! device and runs them back to back in one call. Intermediate tensors stay
+//! inside the worker and on its GPU's host, instead of going through the
+//! queue, the result store or another device between stages. Engines
+//! exchange host arrays, so a tensor is still copied between backend calls.
+
+use anyhow::{Context, Result};
+use ndarray::ArrayD;
+
+use crate::engine::{Capabilities, Engine, EngineFactory, Residency};
+use crate::types::{Config, StageCfg};
+
+/// Where a stage input comes from.
+#[derive(Debug, Clone, PartialEq)]
+enum Source {
+    /// Job input by name.
+    Input(String),
+    /// Output `1` of stage `0`.
+    Output(usize, usize),
+}
+
+struct Stage {
+    name: String,
+    engine: Box<dyn Engine>,
+    inputs: Vec<(String, Source)>,
+}
+
+/// Engine running the stages of an ensemble in order on one device.
+pub struct EnsembleEngine {
+    stages: Vec<Stage>,
+    /// Name of the job tensor in [`Engine::infer_named`] calls.
+    primary: String,
+    capabilities: Capabilities,
+}
+
+impl EnsembleEngine {
+    /// Loads every stage on `device_id`, the device of the worker.
+    pub fn new(cfg: &Config, device_id: Option<usize>) -> Result<Self> {
+        let mut stages = Vec::with_capacity(cfg.model.stages.len());
+        for stage in &cfg.model.stages {
+            let stage_cfg = stage_config(cfg, stage);
+            let engine = EngineFactory::create_backend(&stage_cfg, device_id)
+                .with_context(|| format!("Ensemble-Stufe '{}' konnte nicht geladen werden", stage.name))?;
+            stages.push((stage.clone(), engine));
+        }
+        tracing::info!(
+            "Ensemble mit {} Stufen auf {}: {}",
+            stages.len(),
+            crate::accounting::device_key(device_id),
+            cfg.model.stages.iter().map(|s| s.name.as_str()).collect::<Vec<_>>().join(" → ")
+        );
+        Self::from_stages(cfg, stages)
+    }
+
+    /// Builds the ensemble from loaded stage engines; checks the graph.
+    pub fn from_stages(cfg: &Config, stages: Vec<(StageCfg, Box<dyn Engine>)>) -> Result<Self> {
+        anyhow::ensure!(!stages.is_empty(), "backend = \"ensemble\" benötigt [[model.stages]]");
+        let primary = cfg.model.input_names.first().context("model.input_names ist leer")?.clone();
+        let job_inputs: Vec<&str> = cfg
+            .model
+            .input_names
+            .iter()
+            .chain(cfg.input.extra.iter().map(|e| &e.name))
+            .map(String::as_str)
+            .collect();
+
+        let mut names: Vec<&str> = Vec::with_capacity(stages.len());
+        let mut outputs: Vec<&[String]> = Vec::with_capacity(stages.len());
+        let mut sources = Vec::with_capacity(stages.len());
+        for (stage, _) in &stages {
+            anyhow::ensure!(
+                stage.name != "input" && !names.contains(&stage.name.as_str()),
+                "Ensemble-Stufe '{}': Name ist reserviert oder doppelt",
+                stage.name
+            );
+            anyhow::ensure!(stage.backend != "ensemble", "Ensemble-Stufe '{}': Ensembles lassen sich nicht schachteln", stage.name);
+            anyhow::ensure!(
+                stage.inputs.len() == stage.input_names.len(),
+                "Ensemble-Stufe '{}': {} inputs für {} input_names",
+                stage.name,
+                stage.inputs.len(),
+                stage.input_names.len()
+            );
+            let inputs = stage
+                .input_names
+                .iter()
+                .zip(&stage.inputs)
+                .map(|(name, source)| {
+                    let resolved = resolve(source, &primary, &job_inputs, &names, &outputs)
+                        .with_context(|| format!("Ensemble-Stufe '{}', Input '{}'", stage.name, name))?;
+                    Ok((name.clone(), resolved))
+                })
+                .collect::<Result<Vec<_>>>()?;
+            names.push(&stage.name);
+            outputs.push(&stage.output_names);
+            sources.push(inputs);
+        }
+        let (last, _) = stages.last().expect("mindestens eine Stufe");
+        anyhow::ensure!(
+            last.output_names.len() == cfg.model.output_names.len(),
+            "Ensemble liefert die {} Outputs der letzten Stufe '{}', model.output_names nennt {}",
+            last.output_names.len(),
+            last.name,
+            cfg.model.output_names.len()
+        );
+
+        let stages: Vec<Stage> = stages
+            .into_iter()
+            .zip(sources)
+            .map(|((stage, engine), inputs)| Stage { name: stage.name, engine, inputs })
+            .collect();
+        let capabilities = combined_capabilities(stages.iter().map(|s| s.engine.capabilities()));
+        Ok(Self { stages, primary, capabilities })
+    }
+
+    /// Runs all stages; returns the outputs of the last one.
+    fn run(&mut self, inputs: Vec<(String, ArrayD<f32>)>) -> Result<Vec<ArrayD<f32>>> {
+        let mut results: Vec<Vec<ArrayD<f32>>> = Vec::with_capacity(self.stages.len());
+        for stage in &mut self.stages {
+            let feed = stage
+                .inputs
+                .iter()
+                .map(|(name, source)| {
+                    let tensor = match source {
+                        Source::Input(input) => inputs.iter().find(|(n, _)| n == input).map(|(_, t)| t),
+                        Source::Output(from, output) => results[*from].get(*output),
+                    };
+                    let tensor = tensor.with_context(|| format!("Ensemble-Stufe '{}': Input '{}' fehlt", stage.name, name))?;
+                    Ok((name.clone(), tensor.clone()))
+                })
+                .collect::<Result<Vec<_>>>()?;
+            let outputs = stage.engine.infer_named(feed).with_context(|| format!("Ensemble-Stufe '{}'", stage.name))?;
+            results.push(outputs);
+        }
+        Ok(results.pop().unwrap_or_default())
+    }
+}
+
+impl Engine for EnsembleEngine {
+    fn name(&self) -> &'static str {
+        "ensemble"
+    }
+
+    fn infer_array(&mut self, input: ArrayD<f32>) -> Result<ArrayD<f32>> {
+        let primary = self.primary.clone();
+        self.run(vec![(primary, input)])?.into_iter().next().context("Letzte Ensemble-Stufe liefert keinen Output")
+    }
+
+    fn infer_named(&mut self, inputs: Vec<(String, ArrayD<f32>)>) -> Result<Vec<ArrayD<f32>>> {
+        self.run(inputs)
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        self.capabilities.clone()
+    }
+}
+
+/// Configuration of one stage: `[model]` with the stage's model and I/O.
+fn stage_config(cfg: &Config, stage: &StageCfg) -> Config {
+    let mut stage_cfg = cfg.clone();
+    let model = &mut stage_cfg.model;
+    model.backend = stage.backend.clone();
+    model.model_path = stage.model_path.clone();
+    model.layout = stage.layout.clone();
+    model.input_names = stage.input_names.clone();
+    model.input_shapes = stage.input_shapes.clone();
+    model.output_names = stage.output_names.clone();
+    model.output_shapes = stage.output_shapes.clone();
+    model.stages = Vec::new();
+    stage_cfg
+}
+
+/// Resolves a stage input reference against the job inputs and the
+/// outputs of the earlier stages `names`.
+fn resolve(source: &str, primary: &str, job_inputs: &[&str], names: &[&str], outputs: &[&[String]]) -> Result<Source> {
+    let (head, output) = match source.split_once('.') {
+        Some((head, output)) => (head, Some(output)),
+        None => (source, None),
+    };
+    if head == "input" {
+        let input = output.unwrap_or(primary);
+        anyhow::ensure!(job_inputs.contains(&input), "'{}': kein Input '{}' in model.input_names oder [[input.extra]]", source, input);
+        return Ok(Source::Input(input.to_string()));
+    }
+    let stage = names
+        .iter()
+        .position(|n| *n == head)
+        .with_context(|| format!("'{}': keine frühere Stufe '{}'", source, head))?;
+    let index = match output {
+        Some(output) => outputs[stage]
+            .iter()
+            .position(|n| n == output)
+            .with_context(|| format!("'{}': Stufe '{}' hat kein Output '{}'", source, head, output))?,
+        None => 0,
+    };
+    Ok(Source::Output(stage, index))
+}
+
+/// Capabilities of the whole ensemble: the smallest batch limit of the
+/// stages; device-resident only if every stage is.
+fn combined_capabilities(stages: impl Iterator<Item = Capabilities>) -> Capabilities {
+    let mut combined = Capabilities { named_inputs: true, residency: Residency::Device, ..Capabilities::default() };
+    for caps in stages {
+        combined.max_batch = match (combined.max_batch, caps.max_batch) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        };
+        if caps.residency == Residency::Host {
+            combined.residency = Residency::Host;
+        }
+    }
+    combined
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Multiplies its first input by a factor; with two inputs adds the second.
+    struct Scale(f32);
+
+    impl Engine for Scale {
+        fn name(&self) -> &'static str {
+            "scale"
+        }
+
+        fn infer_array(&mut self, input: ArrayD<f32>) -> Result<ArrayD<f32>> {
+            Ok(input * self.0)
+        }
+
+        fn infer_named(&mut self, inputs: Vec<(String, ArrayD<f32>)>) -> Result<Vec<ArrayD<f32>>> {
+            let mut tensors = inputs.into_iter().map(|(_, t)| t);
+            let first = tensors.next().context("kein Input")? * self.0;
+            let sum = tensors.fold(first.clone(), |acc, t| acc + t);
+            Ok(vec![sum, first])
+        }
+    }
+
+    fn config() -> Config {
+        toml::from_str(
+            r#"
+            [model]
+            backend = "ensemble"
+            device = "cpu"
+            model_path = "pipeline"
+            input_names = ["images"]
+            input_shapes = [[1, 2]]
+            output_names = ["scores", "scaled"]
+            output_shapes = [[1, 2], [1, 2]]
+
+            [input]
+            batch = 1
+            channels = 1
+            height = 1
+            width = 2
+            dtype = "f32"
+
+            [queue]
+            max_batch = 1
+            max_wait_ms = 1
+
+            [redis]
+            url = "memory://"
+            out_prefix = "results"
+            "#,
+        )
+        .unwrap()
+    }
+
+    fn stage(name: &str, inputs: &[&str]) -> StageCfg {
+        StageCfg {
+            name: name.to_string(),
+            backend: "onnx".to_string(),
+            model_path: format!("{}.onnx", name),
+            inputs: inputs.iter().map(|i| i.to_string()).collect(),
+            layout: None,
+            input_names: (0..inputs.len()).map(|i| format!("x{}", i)).collect(),
+            input_shapes: vec![vec![1, 2]; inputs.len()],
+            output_names: vec!["sum".to_string(), "scaled".to_string()],
+            output_shapes: vec![vec![1, 2]; 2],
+        }
+    }
+
+    #[test]
+    fn test_stages_run_as_graph() {
+        // detector(x) = 2x; classifier = 3 * detector + x
+        let stages: Vec<(StageCfg, Box<dyn Engine>)> = vec![
+            (stage("detector", &["input"]), Box::new(Scale(2.0))),
+            (stage("classifier", &["detector.scaled", "input.images"]), Box::new(Scale(3.0))),
+        ];
+        let mut ensemble = EnsembleEngine::from_stages(&config(), stages).unwrap();
+        let x = ArrayD::from_shape_vec(ndarray::IxDyn(&[1, 2]), vec![1.0, 2.0]).unwrap();
+        assert_eq!(ensemble.infer_array(x.clone()).unwrap().into_raw_vec_and_offset().0, vec![7.0, 14.0]);
+
+        let outputs = ensemble.infer_named(vec![("images".to_string(), x)]).unwrap();
+        assert_eq!(outputs.len(), 2);
+        assert_eq!(outputs[1].iter().copied().collect::<Vec<_>>(), vec![6.0, 12.0]);
+        assert!(ensemble.capabilities().named_inputs);
+        assert_eq!(ensemble.capabilities().residency, Residency::Host);
+    }
+
+    #[test]
+    fn test_rejects_invalid_graphs() {
+        let check = |stages: Vec<StageCfg>| {
+            let stages = stages.into_iter().map(|s| (s, Box::new(Scale(1.0)) as Box<dyn Engine>)).collect();
+            EnsembleEngine::from_stages(&config(), stages).err().map(|e| format!("{:#}", e))
+        };
+        assert!(check(vec![stage("a", &["input"])]).is_none());
+        // Verweis auf eine spätere Stufe (Zyklus)
+        assert!(check(vec![stage("a", &["b"]), stage("b", &["a"])]).unwrap().contains("keine frühere Stufe 'b'"));
+        assert!(check(vec![stage("a", &["input.mask"])]).unwrap().contains("kein Input 'mask'"));
+        assert!(check(vec![stage("a", &["input"]), stage("b", &["a.boxes"])]).unwrap().contains("kein Output 'boxes'"));
+        assert!(check(vec![stage("a", &["input"]), stage("a", &["input"])]).unwrap().contains("doppelt"));
+        assert!(check(vec![]).unwrap().contains("[[model.stages]]"));
+    }
+
+    #[test]
+    fn test_stage_config_takes_device_from_model() {
+        let mut cfg = config();
+        cfg.model.device = "gpu".to_string();
+        cfg.model.stages = vec![stage("detector", &["input"])];
+        let stage_cfg = stage_config(&cfg, &cfg.model.stages[0]);
+        assert_eq!((stage_cfg.model.backend.as_str(), stage_cfg.model.device.as_str()), ("onnx", "gpu"));
+        assert_eq!(stage_cfg.model.model_path, "detector.onnx");
+        assert!(stage_cfg.model.stages.is_empty());
+    }
+}
@@ -0,0 +1,231 @@
+//! Precision fallback (`[precision_fallback]`).
+//!
+//! fp16/int8 exports can overflow to NaN/Inf on inputs outside their
+//! calibration range. [`FallbackEngine`] scans every output of the reduced
+//! precision model; on the first non-finite value it loads the fp32 variant
+//! on the same device, repeats the call there and keeps using it. The switch
+//! is logged, counted in `omniengine_precision_fallbacks_total` and published
+//! as an alert, so results stay correct while the export gets fixed.
+//! Failed accuracy probes ([`crate::probes`]) can request the switch too.
+
+use std::collections::BTreeMap;
+use std::sync::{Mutex, OnceLock};
+
+use anyhow::{Context, Result};
+use ndarray::ArrayD;
+use serde_json::{json, Value};
+use tokio::sync::mpsc;
+
+use crate::engine::{Capabilities, Engine, EngineFactory};
+use crate::health::health;
+use crate::storage::redis_store::RedisStorage;
+use crate::types::Config;
+
+/// Loads the full-precision engine.
+type Loader = Box<dyn FnOnce() -> Result<Box<dyn Engine>> + Send + Sync>;
+
+static ALERTS: OnceLock<mpsc::UnboundedSender<Value>> = OnceLock::new();
+
+/// Switches requested from outside, by device, with the reason.
+static REQUESTS: Mutex<BTreeMap<String, String>> = Mutex::new(BTreeMap::new());
+
+/// Asks the engine on `device` (e.g. `gpu:0`) to switch before its next call.
+pub fn request(device: &str, reason: &str) {
+    REQUESTS.lock().unwrap().insert(device.to_string(), reason.to_string());
+}
+
+/// Publishes fallback alerts to `[precision_fallback] channel`.
+pub fn init(cfg: &Config, store: RedisStorage) {
+    let Some(fallback) = &cfg.precision_fallback else { return };
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    if ALERTS.set(tx).is_err() {
+        return;
+    }
+    let channel = fallback.channel.clone();
+    tokio::spawn(async move {
+        while let Some(alert) = rx.recv().await {
+            if let Err(e) = store.publish_json(&channel, &alert).await {
+                tracing::warn!("Fallback-Alarm nicht veröffentlicht: {}", e);
+            }
+        }
+    });
+}
+
+/// Engine that replaces a reduced-precision model by its fp32 variant once
+/// it produces NaN or Inf.
+pub struct FallbackEngine {
+    active: Box<dyn Engine>,
+    /// `None` once switched to the fp32 variant.
+    load: Option<Loader>,
+    model: String,
+    fallback: String,
+    device: String,
+}
+
+impl FallbackEngine {
+    /// Wraps `engine` if `[precision_fallback]` is configured.
+    pub fn from_config(cfg: &Config, device_id: Option<usize>, engine: Box<dyn Engine>) -> Box<dyn Engine> {
+        let Some(fallback) = &cfg.precision_fallback else { return engine };
+        let mut fp32 = cfg.clone();
+        fp32.model.model_path = fallback.model_path.clone();
+        if let Some(backend) = &fallback.backend {
+            fp32.model.backend = backend.clone();
+        }
+        let load: Loader = Box::new(move || EngineFactory::create_backend(&fp32, device_id));
+        let device = crate::accounting::device_key(device_id);
+        Box::new(Self::new(engine, load, &cfg.model.model_path, &fallback.model_path, &device))
+    }
+
+    fn new(engine: Box<dyn Engine>, load: Loader, model: &str, fallback: &str, device: &str) -> Self {
+        Self { active: engine, load: Some(load), model: model.to_string(), fallback: fallback.to_string(), device: device.to_string() }
+    }
+
+    /// Whether the fp32 variant is in use.
+    pub fn switched(&self) -> bool {
+        self.load.is_none()
+    }
+
+    /// Applies a switch requested by [`request`].
+    fn check_request(&mut self) -> Result<()> {
+        let requested = REQUESTS.lock().unwrap().remove(&self.device);
+        match requested {
+            Some(reason) => self.switch(&reason),
+            None => Ok(()),
+        }
+    }
+
+    fn switch(&mut self, reason: &str) -> Result<()> {
+        let Some(load) = self.load.take() else { return Ok(()) };
+        tracing::error!("{}: {} im Output von {}, wechsle auf {}", self.device, reason, self.model, self.fallback);
+        self.active = load().with_context(|| format!("Fallback-Modell {} nicht geladen", self.fallback))?;
+        health().precision_fallback();
+        if let Some(alerts) = ALERTS.get() {
+            let _ = alerts.send(json!({
+                "type": "precision_fallback",
+                "model": self.model,
+                "fallback": self.fallback,
+                "worker": self.device,
+                "reason": reason,
+                "timestamp": chrono::Utc::now().to_rfc3339(),
+            }));
+        }
+        Ok(())
+    }
+}
+
+/// Describes the NaN/Inf values in `outputs`, if there are any.
+fn non_finite<'a>(outputs: impl IntoIterator<Item = &'a ArrayD<f32>>) -> Option<String> {
+    let (mut nan, mut inf) = (0, 0);
+    for output in outputs {
+        nan += output.iter().filter(|v| v.is_nan()).count();
+        inf += output.iter().filter(|v| v.is_infinite()).count();
+    }
+    (nan + inf > 0).then(|| format!("{} NaN und {} Inf", nan, inf))
+}
+
+impl Engine for FallbackEngine {
+    fn name(&self) -> &'static str {
+        self.active.name()
+    }
+
+    fn infer_array(&mut self, input: ArrayD<f32>) -> Result<ArrayD<f32>> {
+        self.check_request()?;
+        if self.switched() {
+            return self.active.infer_array(input);
+        }
+        // Eingabe behalten, um den Aufruf mit dem fp32-Modell zu wiederholen
+        let y = self.active.infer_array(input.clone())?;
+        match non_finite([&y]) {
+            None => Ok(y),
+            Some(reason) => {
+                self.switch(&reason)?;
+                self.active.infer_array(input)
+            }
+        }
+    }
+
+    fn infer_named(&mut self, inputs: Vec<(String, ArrayD<f32>)>) -> Result<Vec<ArrayD<f32>>> {
+        self.check_request()?;
+        if self.switched() {
+            return self.active.infer_named(inputs);
+        }
+        let ys = self.active.infer_named(inputs.clone())?;
+        match non_finite(&ys) {
+            None => Ok(ys),
+            Some(reason) => {
+                self.switch(&reason)?;
+                self.active.infer_named(inputs)
+            }
+        }
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        self.active.capabilities()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::IxDyn;
+
+    /// Returns the input, with NaN where it exceeds the fp16 range.
+    struct Half;
+
+    impl Engine for Half {
+        fn name(&self) -> &'static str {
+            "half"
+        }
+
+        fn infer_array(&mut self, input: ArrayD<f32>) -> Result<ArrayD<f32>> {
+            Ok(input.mapv(|v| if v.abs() > 65504.0 { f32::NAN } else { v }))
+        }
+    }
+
+    struct Full;
+
+    impl Engine for Full {
+        fn name(&self) -> &'static str {
+            "full"
+        }
+
+        fn infer_array(&mut self, input: ArrayD<f32>) -> Result<ArrayD<f32>> {
+            Ok(input)
+        }
+    }
+
+    fn engine() -> FallbackEngine {
+        FallbackEngine::new(Box::new(Half), Box::new(|| Ok(Box::new(Full) as Box<dyn Engine>)), "model_fp16.onnx", "model.onnx", "gpu:0")
+    }
+
+    #[test]
+    fn test_switches_on_non_finite_output() {
+        let mut engine = engine();
+        let small = ArrayD::from_elem(IxDyn(&[2]), 1.0);
+        assert_eq!(engine.infer_array(small).unwrap()[0], 1.0);
+        assert!(!engine.switched());
+
+        let large = ArrayD::from_elem(IxDyn(&[2]), 1e5);
+        // Der fehlerhafte Aufruf wird mit dem fp32-Modell wiederholt
+        assert_eq!(engine.infer_array(large.clone()).unwrap()[0], 1e5);
+        assert!(engine.switched());
+        assert_eq!(engine.name(), "full");
+        assert_eq!(engine.infer_named(vec![("x".to_string(), large)]).unwrap()[0][1], 1e5);
+    }
+
+    #[test]
+    fn test_switches_on_request() {
+        let mut engine = FallbackEngine::new(Box::new(Half), Box::new(|| Ok(Box::new(Full) as Box<dyn Engine>)), "m.onnx", "m32.onnx", "gpu:7");
+        request("gpu:7", "Abweichung von Canary 'cat'");
+        assert_eq!(engine.infer_array(ArrayD::from_elem(IxDyn(&[1]), 1.0)).unwrap()[0], 1.0);
+        assert!(engine.switched());
+    }
+
+    #[test]
+    fn test_failed_load_is_an_error() {
+        let load: Loader = Box::new(|| anyhow::bail!("Datei fehlt"));
+        let mut engine = FallbackEngine::new(Box::new(Half), load, "model_fp16.onnx", "model.onnx", "cpu");
+        let err = engine.infer_array(ArrayD::from_elem(IxDyn(&[1]), 1e6)).unwrap_err();
+        assert!(format!("{:#}", err).contains("Fallback-Modell model.onnx nicht geladen"));
+    }
+}
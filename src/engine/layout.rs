@@ -0,0 +1,180 @@
+//! Channels-first to channels-last adaptation (`[model] layout`).
+//!
+//! Clients, sources and preprocessing produce channels-first tensors
+//! (`[input] layout = "nchw"` or `"ncdhw"`). Models exported from TensorFlow
+//! or Keras usually expect channels-last input (`NHWC`/`NDHWC`).
+//! [`LayoutEngine`] moves the channel axis of the model's first input to the
+//! end before each call, so the same client code serves both kinds of models.
+//!
+//! With `layout = "auto"` (the default) the model layout is read from
+//! `model.input_shapes[0]`: a last axis equal to `[input] channels` and a
+//! second axis that differs from it mark a channels-last model. Outputs are
+//! passed through unchanged.
+
+use anyhow::Result;
+use ndarray::{ArrayD, IxDyn};
+
+use crate::engine::{Capabilities, Engine};
+use crate::types::Config;
+
+/// Engine that feeds the first model input in channels-last layout.
+pub struct LayoutEngine {
+    inner: Box<dyn Engine>,
+    /// Name of the transposed input in [`Engine::infer_named`] calls.
+    input: Option<String>,
+}
+
+impl LayoutEngine {
+    /// Wraps `engine` if the model expects channels-last input.
+    pub fn from_config(cfg: &Config, engine: Box<dyn Engine>) -> Result<Box<dyn Engine>> {
+        if !channels_last(cfg)? {
+            return Ok(engine);
+        }
+        tracing::info!("Modell erwartet Channels-Last, Eingaben werden transponiert");
+        Ok(Box::new(Self { inner: engine, input: cfg.model.input_names.first().cloned() }))
+    }
+}
+
+/// Whether the model's first input is channels-last.
+fn channels_last(cfg: &Config) -> Result<bool> {
+    let rank = cfg.input_spec().rank()?;
+    let layout = cfg.model.layout.as_deref().unwrap_or("auto");
+    let explicit = match layout {
+        "auto" => None,
+        "nchw" | "ncdhw" => Some(false),
+        "nhwc" | "ndhwc" => Some(true),
+        other => anyhow::bail!("Unbekanntes [model] layout '{}' (erwartet: auto, nchw, ncdhw, nhwc, ndhwc)", other),
+    };
+    match explicit {
+        Some(true) => {
+            anyhow::ensure!(
+                rank >= 4,
+                "[model] layout = \"{}\" benötigt [input] layout = \"nchw\" oder \"ncdhw\"",
+                layout
+            );
+            Ok(true)
+        }
+        Some(false) => Ok(false),
+        // Nur Bild- und Volumen-Eingaben haben eine eindeutige Kanal-Achse
+        None => Ok(rank >= 4
+            && cfg.input.channels > 0
+            && cfg.model.input_shapes.first().is_some_and(|shape| {
+                shape.len() == rank && shape[rank - 1] == cfg.input.channels && shape[1] != cfg.input.channels
+            })),
+    }
+}
+
+/// Moves axis 1 (channels) of `x` to the end: `[N, C, ...]` → `[N, ..., C]`.
+pub fn to_channels_last(x: ArrayD<f32>) -> ArrayD<f32> {
+    let rank = x.ndim();
+    if rank < 3 {
+        return x;
+    }
+    let mut axes: Vec<usize> = (0..rank).collect();
+    axes[1..].rotate_left(1);
+    x.permuted_axes(IxDyn(&axes)).as_standard_layout().into_owned()
+}
+
+impl Engine for LayoutEngine {
+    fn name(&self) -> &'static str {
+        self.inner.name()
+    }
+
+    fn infer_array(&mut self, input: ArrayD<f32>) -> Result<ArrayD<f32>> {
+        self.inner.infer_array(to_channels_last(input))
+    }
+
+    fn infer_named(&mut self, inputs: Vec<(String, ArrayD<f32>)>) -> Result<Vec<ArrayD<f32>>> {
+        let inputs = inputs
+            .into_iter()
+            .map(|(name, x)| match Some(&name) == self.input.as_ref() {
+                true => (name, to_channels_last(x)),
+                false => (name, x),
+            })
+            .collect();
+        self.inner.infer_named(inputs)
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        self.inner.capabilities()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(layout: &str, input_shape: &str) -> Config {
+        let raw = format!(
+            r#"
+            [model]
+            backend = "tensorflow"
+            device = "cpu"
+            model_path = "models/saved"
+            {}
+            input_names = ["image"]
+            input_shapes = [{}]
+            output_names = ["logits"]
+            output_shapes = [[0, 10]]
+            [queue]
+            max_batch = 8
+            max_wait_ms = 5
+            [redis]
+            url = "redis://127.0.0.1:1/"
+            out_prefix = "results"
+            [input]
+            batch = 1
+            channels = 3
+            height = 4
+            width = 2
+            dtype = "f32"
+            "#,
+            layout, input_shape
+        );
+        toml::from_str(&raw).unwrap()
+    }
+
+    /// Returns the input unchanged.
+    struct Echo;
+
+    impl Engine for Echo {
+        fn name(&self) -> &'static str {
+            "echo"
+        }
+
+        fn infer_array(&mut self, input: ArrayD<f32>) -> Result<ArrayD<f32>> {
+            Ok(input)
+        }
+
+        fn infer_named(&mut self, inputs: Vec<(String, ArrayD<f32>)>) -> Result<Vec<ArrayD<f32>>> {
+            Ok(inputs.into_iter().map(|(_, x)| x).collect())
+        }
+    }
+
+    #[test]
+    fn test_detects_channels_last_models() {
+        assert!(channels_last(&config("", "[0, 4, 2, 3]")).unwrap());
+        assert!(!channels_last(&config("", "[0, 3, 4, 2]")).unwrap());
+        // Mehrdeutig (H = C): nur explizit
+        assert!(!channels_last(&config("", "[0, 3, 2, 3]")).unwrap());
+        assert!(channels_last(&config("layout = \"nhwc\"", "[0, 3, 2, 3]")).unwrap());
+        assert!(!channels_last(&config("layout = \"nchw\"", "[0, 4, 2, 3]")).unwrap());
+        assert!(channels_last(&config("layout = \"hwc\"", "[0, 4, 2, 3]")).is_err());
+    }
+
+    #[test]
+    fn test_transposes_first_input() {
+        let cfg = config("", "[0, 4, 2, 3]");
+        let mut engine = LayoutEngine::from_config(&cfg, Box::new(Echo)).unwrap();
+        let x = ArrayD::from_shape_fn(IxDyn(&[1, 3, 4, 2]), |i| (i[1] * 100 + i[2] * 10 + i[3]) as f32);
+
+        let y = engine.infer_array(x.clone()).unwrap();
+        assert_eq!(y.shape(), &[1, 4, 2, 3]);
+        assert_eq!(y[[0, 3, 1, 2]], 231.0);
+
+        let extra = ArrayD::zeros(IxDyn(&[1, 3, 4, 2]));
+        let ys = engine.infer_named(vec![("image".into(), x), ("mask".into(), extra)]).unwrap();
+        assert_eq!(ys[0].shape(), &[1, 4, 2, 3]);
+        assert_eq!(ys[1].shape(), &[1, 3, 4, 2]);
+    }
+}
@@ -0,0 +1,140 @@
+//! Concurrency limit for model calls (`model.max_concurrent_batches`).
+//!
+//! Several workers may serve the same model on one device (repeated GPU ids
+//! or standby workers). Each engine is wrapped in a [`LimitedEngine`] that
+//! shares one semaphore per model and device, so at most
+//! `max_concurrent_batches` batches run on the device at a time. Inference
+//! calls block, so the semaphore does too.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Condvar, Mutex, OnceLock};
+
+use anyhow::Result;
+use ndarray::ArrayD;
+
+use crate::engine::{Capabilities, Engine};
+
+/// Semaphores by model path and device.
+type Registry = Mutex<HashMap<(String, Option<usize>), Arc<Semaphore>>>;
+
+/// Counting semaphore with blocking acquire.
+pub struct Semaphore {
+    running: Mutex<usize>,
+    released: Condvar,
+    max: usize,
+}
+
+/// Held while one batch runs; releases its slot when dropped.
+pub struct Permit<'a>(&'a Semaphore);
+
+impl Semaphore {
+    pub fn new(max: usize) -> Self {
+        Self { running: Mutex::new(0), released: Condvar::new(), max: max.max(1) }
+    }
+
+    /// Returns the semaphore shared by all engines of `model` on `device`.
+    pub fn for_device(model: &str, device: Option<usize>, max: usize) -> Arc<Self> {
+        static LIMITS: OnceLock<Registry> = OnceLock::new();
+        let mut limits = LIMITS.get_or_init(Default::default).lock().unwrap();
+        Arc::clone(limits.entry((model.to_string(), device)).or_insert_with(|| Arc::new(Self::new(max))))
+    }
+
+    /// Waits for a free slot.
+    pub fn acquire(&self) -> Permit<'_> {
+        let mut running = self.running.lock().unwrap();
+        while *running >= self.max {
+            running = self.released.wait(running).unwrap();
+        }
+        *running += 1;
+        Permit(self)
+    }
+}
+
+impl Drop for Permit<'_> {
+    fn drop(&mut self) {
+        *self.0.running.lock().unwrap() -= 1;
+        self.0.released.notify_one();
+    }
+}
+
+/// Engine whose model calls are limited by a shared [`Semaphore`].
+pub struct LimitedEngine {
+    inner: Box<dyn Engine>,
+    limit: Arc<Semaphore>,
+}
+
+impl LimitedEngine {
+    pub fn new(inner: Box<dyn Engine>, limit: Arc<Semaphore>) -> Self {
+        Self { inner, limit }
+    }
+}
+
+impl Engine for LimitedEngine {
+    fn name(&self) -> &'static str {
+        self.inner.name()
+    }
+
+    fn infer_array(&mut self, input: ArrayD<f32>) -> Result<ArrayD<f32>> {
+        let _permit = self.limit.acquire();
+        self.inner.infer_array(input)
+    }
+
+    fn infer_named(&mut self, inputs: Vec<(String, ArrayD<f32>)>) -> Result<Vec<ArrayD<f32>>> {
+        let _permit = self.limit.acquire();
+        self.inner.infer_named(inputs)
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        self.inner.capabilities()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    /// Records the highest number of concurrent calls.
+    struct Slow {
+        running: Arc<AtomicUsize>,
+        peak: Arc<AtomicUsize>,
+    }
+
+    impl Engine for Slow {
+        fn name(&self) -> &'static str {
+            "slow"
+        }
+
+        fn infer_array(&mut self, input: ArrayD<f32>) -> Result<ArrayD<f32>> {
+            let now = self.running.fetch_add(1, Ordering::SeqCst) + 1;
+            self.peak.fetch_max(now, Ordering::SeqCst);
+            std::thread::sleep(Duration::from_millis(20));
+            self.running.fetch_sub(1, Ordering::SeqCst);
+            Ok(input)
+        }
+    }
+
+    #[test]
+    fn test_limit_shared_per_model_and_device() {
+        let (running, peak) = (Arc::new(AtomicUsize::new(0)), Arc::new(AtomicUsize::new(0)));
+        let threads: Vec<_> = (0..4)
+            .map(|_| {
+                let slow = Slow { running: Arc::clone(&running), peak: Arc::clone(&peak) };
+                let mut engine = LimitedEngine::new(Box::new(slow), Semaphore::for_device("limit-test.onnx", Some(0), 2));
+                std::thread::spawn(move || {
+                    for _ in 0..3 {
+                        engine.infer_array(ArrayD::zeros(ndarray::IxDyn(&[1]))).unwrap();
+                    }
+                })
+            })
+            .collect();
+        for t in threads {
+            t.join().unwrap();
+        }
+        assert!(peak.load(Ordering::SeqCst) <= 2);
+
+        let other = Semaphore::for_device("limit-test.onnx", Some(1), 2);
+        assert!(!Arc::ptr_eq(&other, &Semaphore::for_device("limit-test.onnx", Some(0), 2)));
+    }
+}
@@ -0,0 +1,612 @@
+//! Model memory footprint estimation.
+//!
+//! Estimates the memory a model needs at a batch size and precision before
+//! it is loaded, for capacity planning and device placement:
+//!
+//! * weights: ONNX initializers (also external data) by element count, other
+//!   backends by the size of the model file;
+//! * activations: the peak of simultaneously live tensors when the ONNX
+//!   nodes run in graph order, sized from the graph's `value_info` (present
+//!   after `onnx.shape_inference`); without shapes, and for other backends,
+//!   only inputs and outputs are counted;
+//! * workspace: allocator slack plus, on GPUs, the CUDA context and
+//!   cuDNN/cuBLAS scratch space.
+//!
+//! Float tensors are counted at the requested precision, integer tensors
+//! at their own size. Runtimes may fuse or reuse buffers beyond the graph
+//! order, so the activation peak is an upper bound.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+
+use super::preflight::{string, Field, Fields};
+use crate::types::Config;
+
+const MIB: u64 = 1 << 20;
+
+/// Device memory held by the CUDA context of one process.
+const CUDA_CONTEXT_BYTES: u64 = 300 * MIB;
+
+/// Smallest cuDNN/cuBLAS scratch space planned on GPUs.
+const MIN_GPU_WORKSPACE_BYTES: u64 = 64 * MIB;
+
+/// Numeric precision float tensors are held in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Precision {
+    Fp32,
+    Fp16,
+    Bf16,
+    Int8,
+}
+
+impl Precision {
+    pub fn parse(name: &str) -> Result<Self> {
+        Ok(match name.to_ascii_lowercase().as_str() {
+            "fp32" => Precision::Fp32,
+            "fp16" => Precision::Fp16,
+            "bf16" => Precision::Bf16,
+            "int8" => Precision::Int8,
+            other => anyhow::bail!("Unbekannte Präzision '{}' (fp32, fp16, bf16, int8)", other),
+        })
+    }
+
+    fn bytes(self) -> u64 {
+        match self {
+            Precision::Fp32 => 4,
+            Precision::Fp16 | Precision::Bf16 => 2,
+            Precision::Int8 => 1,
+        }
+    }
+}
+
+/// Estimated memory of one model instance.
+#[derive(Debug, Clone, Serialize)]
+pub struct MemoryEstimate {
+    pub model: String,
+    pub backend: String,
+    pub device: String,
+    pub batch: usize,
+    /// `None`: float tensors as stored in the model.
+    pub precision: Option<Precision>,
+    pub weight_bytes: u64,
+    pub activation_bytes: u64,
+    pub workspace_bytes: u64,
+    pub total_bytes: u64,
+    /// Parts the estimate could not cover.
+    pub notes: Vec<String>,
+}
+
+impl fmt::Display for MemoryEstimate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let precision = self.precision.map_or("wie gespeichert".to_string(), |p| format!("{:?}", p).to_lowercase());
+        writeln!(f, "{} ({}, {}), Batch {}, Präzision {}", self.model, self.backend, self.device, self.batch, precision)?;
+        writeln!(f, "  Gewichte      {:>10.1} MiB", self.weight_bytes as f64 / MIB as f64)?;
+        writeln!(f, "  Aktivierungen {:>10.1} MiB", self.activation_bytes as f64 / MIB as f64)?;
+        writeln!(f, "  Workspace     {:>10.1} MiB", self.workspace_bytes as f64 / MIB as f64)?;
+        write!(f, "  Gesamt        {:>10.1} MiB", self.total_bytes as f64 / MIB as f64)?;
+        for note in &self.notes {
+            write!(f, "\n  Hinweis: {}", note)?;
+        }
+        Ok(())
+    }
+}
+
+/// Estimates the memory of the model in `cfg` at `batch` samples per
+/// inference; `workspace` replaces the planned workspace (e.g. a TensorRT
+/// builder workspace).
+pub fn estimate(cfg: &Config, batch: usize, precision: Option<Precision>, workspace: Option<u64>) -> Result<MemoryEstimate> {
+    anyhow::ensure!(batch > 0, "Batchgröße muss größer als 0 sein");
+    let path = &cfg.model.model_path;
+    let mut notes = Vec::new();
+    let (weight_bytes, activation_bytes) = if cfg.model.backend == "onnx" {
+        let bytes = std::fs::read(path).with_context(|| format!("ONNX-Modell konnte nicht gelesen werden: {}", path))?;
+        let graph = Graph::parse(&bytes).with_context(|| format!("Kein gültiges ONNX-Modell: {}", path))?;
+        let sizes = Sizes::new(&graph, cfg, batch, precision);
+        let (activations, unknown) = graph.peak_activations(&sizes);
+        if unknown > 0 {
+            notes.push(format!(
+                "{} Zwischentensoren ohne bekannte Form nicht gezählt (Modell mit onnx.shape_inference speichern)",
+                unknown
+            ));
+        }
+        (graph.weight_bytes(precision), activations)
+    } else {
+        let size = file_size(Path::new(path)).with_context(|| format!("Modell {} nicht lesbar", path))?;
+        // TensorRT-Engines sind schon in ihrer Präzision gebaut
+        let weights = match precision {
+            Some(p) if cfg.model.backend != "tensorrt" => size * p.bytes() / 4,
+            _ => size,
+        };
+        notes.push(format!("Zwischenaktivierungen für Backend {} unbekannt, nur Ein- und Ausgaben gezählt", cfg.model.backend));
+        (weights, io_bytes(cfg, batch, precision))
+    };
+
+    let gpu = cfg.model.device.starts_with("gpu");
+    let workspace_bytes = workspace.unwrap_or_else(|| {
+        let slack = (weight_bytes + activation_bytes) / 10;
+        if gpu { CUDA_CONTEXT_BYTES + slack.max(MIN_GPU_WORKSPACE_BYTES) } else { slack }
+    });
+    Ok(MemoryEstimate {
+        model: path.clone(),
+        backend: cfg.model.backend.clone(),
+        device: cfg.model.device.clone(),
+        batch,
+        precision,
+        weight_bytes,
+        activation_bytes,
+        workspace_bytes,
+        total_bytes: weight_bytes + activation_bytes + workspace_bytes,
+        notes,
+    })
+}
+
+/// Size of a file or, for directories (SavedModel), of all files in it.
+fn file_size(path: &Path) -> Result<u64> {
+    let meta = std::fs::metadata(path)?;
+    if !meta.is_dir() {
+        return Ok(meta.len());
+    }
+    let mut total = 0;
+    for entry in std::fs::read_dir(path)? {
+        total += file_size(&entry?.path())?;
+    }
+    Ok(total)
+}
+
+/// Inputs and outputs of one batch from `[model]` shapes; dynamic axes
+/// after the batch axis take the `[input]` size.
+fn io_bytes(cfg: &Config, batch: usize, precision: Option<Precision>) -> u64 {
+    let element = precision.map_or(4, Precision::bytes);
+    let sample = cfg.input_spec().sample_shape().unwrap_or_default();
+    let shapes = cfg.model.input_shapes.iter().chain(&cfg.model.output_shapes);
+    let elements: u64 = shapes
+        .map(|shape| {
+            let per_sample = shape.iter().enumerate().skip(1).map(|(i, &d)| match d {
+                0 => sample.get(i - 1).copied().unwrap_or(1),
+                d => d,
+            });
+            per_sample.product::<usize>() as u64
+        })
+        .sum();
+    elements * batch as u64 * element
+}
+
+/// Size in bytes of one element of ONNX `TensorProto.DataType`, and whether
+/// it is a float type.
+fn element_size(data_type: u64) -> (u64, bool) {
+    match data_type {
+        1 => (4, true),              // FLOAT
+        10 | 16 => (2, true),        // FLOAT16, BFLOAT16
+        11 => (8, true),             // DOUBLE
+        2 | 3 | 9 => (1, false),     // UINT8, INT8, BOOL
+        4 | 5 => (2, false),         // UINT16, INT16
+        7 | 13 | 14 => (8, false),   // INT64, UINT64, COMPLEX64
+        17..=20 => (1, true),        // FLOAT8-Varianten
+        _ => (4, false),
+    }
+}
+
+/// Element size at `precision`.
+fn stored_size(data_type: u64, precision: Option<Precision>) -> u64 {
+    match (element_size(data_type), precision) {
+        ((_, true), Some(p)) => p.bytes(),
+        ((size, _), _) => size,
+    }
+}
+
+/// Axis of a tensor shape.
+#[derive(Debug, Clone, PartialEq)]
+enum Dim {
+    Fixed(u64),
+    Param(String),
+    Unknown,
+}
+
+#[derive(Debug, Clone, Default)]
+struct Tensor {
+    name: String,
+    data_type: u64,
+    dims: Vec<Dim>,
+}
+
+/// The parts of a `GraphProto` the estimate needs.
+#[derive(Debug, Default)]
+struct Graph {
+    /// `(element count, data type)` of each initializer.
+    initializers: Vec<(u64, u64)>,
+    initializer_names: HashSet<String>,
+    inputs: Vec<Tensor>,
+    outputs: Vec<Tensor>,
+    value_info: Vec<Tensor>,
+    /// `(inputs, outputs)` of the top-level nodes in graph order.
+    nodes: Vec<(Vec<String>, Vec<String>)>,
+}
+
+impl Graph {
+    /// Reads the main graph of a serialized `ModelProto`.
+    fn parse(bytes: &[u8]) -> Result<Self> {
+        let mut fields = Fields(bytes);
+        while let Some((number, field)) = fields.next()? {
+            if let (7, Field::Bytes(graph)) = (number, field) {
+                return Graph::read(graph);
+            }
+        }
+        anyhow::bail!("Modell ohne Graph")
+    }
+
+    /// `GraphProto`: node (1), initializer (5), input (11), output (12),
+    /// value_info (13).
+    fn read(bytes: &[u8]) -> Result<Self> {
+        let mut graph = Graph::default();
+        let mut fields = Fields(bytes);
+        while let Some((number, field)) = fields.next()? {
+            let Field::Bytes(value) = field else { continue };
+            match number {
+                1 => graph.nodes.push(read_node(value)?),
+                5 => {
+                    let tensor = read_initializer(value)?;
+                    let count = tensor.dims.iter().map(|d| if let Dim::Fixed(n) = d { *n } else { 1 }).product();
+                    graph.initializers.push((count, tensor.data_type));
+                    graph.initializer_names.insert(tensor.name);
+                }
+                11 => graph.inputs.push(read_value_info(value)?),
+                12 => graph.outputs.push(read_value_info(value)?),
+                13 => graph.value_info.push(read_value_info(value)?),
+                _ => {}
+            }
+        }
+        Ok(graph)
+    }
+
+    fn weight_bytes(&self, precision: Option<Precision>) -> u64 {
+        self.initializers.iter().map(|&(count, data_type)| count * stored_size(data_type, precision)).sum()
+    }
+
+    /// Graph inputs that are fed per inference (not initializers).
+    fn feeds(&self) -> impl Iterator<Item = &Tensor> {
+        self.inputs.iter().filter(|t| !self.initializer_names.contains(&t.name))
+    }
+
+    /// Peak bytes of live tensors while the nodes run in graph order, and
+    /// the number of tensors of unknown size.
+    fn peak_activations(&self, sizes: &Sizes) -> (u64, usize) {
+        // Letzte Verwendung je Tensor; Graph-Ausgaben leben bis zum Ende
+        let mut last_use: HashMap<&str, usize> = HashMap::new();
+        for (i, (inputs, _)) in self.nodes.iter().enumerate() {
+            for name in inputs {
+                last_use.insert(name, i);
+            }
+        }
+        for output in &self.outputs {
+            last_use.insert(&output.name, usize::MAX);
+        }
+
+        let mut unknown = HashSet::new();
+        let mut size = |name: &str| {
+            sizes.bytes(name).unwrap_or_else(|| {
+                unknown.insert(name.to_string());
+                0
+            })
+        };
+        let mut live: HashMap<&str, u64> = self.feeds().map(|t| (t.name.as_str(), size(&t.name))).collect();
+        let mut current: u64 = live.values().sum();
+        let mut peak = current;
+        for (i, (_, outputs)) in self.nodes.iter().enumerate() {
+            for name in outputs.iter().filter(|n| !n.is_empty()) {
+                let bytes = size(name);
+                current += bytes;
+                if let Some(old) = live.insert(name, bytes) {
+                    current -= old;
+                }
+            }
+            peak = peak.max(current);
+            live.retain(|name, bytes| {
+                let keep = last_use.get(name).is_some_and(|&last| last > i);
+                if !keep {
+                    current -= *bytes;
+                }
+                keep
+            });
+        }
+        (peak, unknown.len())
+    }
+}
+
+/// Tensor sizes of one batch.
+struct Sizes<'g> {
+    shapes: HashMap<&'g str, &'g Tensor>,
+    /// Values of symbolic axes, bound from `[input]`.
+    params: HashMap<&'g str, u64>,
+    batch: u64,
+    precision: Option<Precision>,
+}
+
+impl<'g> Sizes<'g> {
+    fn new(graph: &'g Graph, cfg: &Config, batch: usize, precision: Option<Precision>) -> Self {
+        let shapes = graph.inputs.iter().chain(&graph.outputs).chain(&graph.value_info).map(|t| (t.name.as_str(), t)).collect();
+        // Symbolische Achsen der ersten Eingabe (außer Batch) aus [input]
+        let mut params = HashMap::new();
+        if let (Some(input), Ok(sample)) = (graph.feeds().next(), cfg.input_spec().sample_shape()) {
+            if input.dims.len() == sample.len() + 1 {
+                for (dim, &size) in input.dims[1..].iter().zip(&sample) {
+                    if let Dim::Param(name) = dim {
+                        params.insert(name.as_str(), size as u64);
+                    }
+                }
+            }
+        }
+        Self { shapes, params, batch: batch as u64, precision }
+    }
+
+    /// Bytes of tensor `name`, `None` if its shape is unknown.
+    fn bytes(&self, name: &str) -> Option<u64> {
+        let tensor = self.shapes.get(name)?;
+        let mut elements = 1u64;
+        for (axis, dim) in tensor.dims.iter().enumerate() {
+            elements *= match dim {
+                Dim::Fixed(n) => *n,
+                Dim::Param(p) if self.params.contains_key(p.as_str()) => self.params[p.as_str()],
+                _ if axis == 0 => self.batch,
+                _ => return None,
+            };
+        }
+        Some(elements * stored_size(tensor.data_type, self.precision))
+    }
+}
+
+/// `NodeProto`: input (1), output (2).
+fn read_node(bytes: &[u8]) -> Result<(Vec<String>, Vec<String>)> {
+    let (mut inputs, mut outputs) = (Vec::new(), Vec::new());
+    let mut fields = Fields(bytes);
+    while let Some((number, field)) = fields.next()? {
+        match (number, field) {
+            (1, Field::Bytes(name)) => inputs.push(string(name)?),
+            (2, Field::Bytes(name)) => outputs.push(string(name)?),
+            _ => {}
+        }
+    }
+    Ok((inputs, outputs))
+}
+
+/// `TensorProto`: dims (1, packed or not), data_type (2), name (8).
+fn read_initializer(bytes: &[u8]) -> Result<Tensor> {
+    let mut tensor = Tensor::default();
+    let mut fields = Fields(bytes);
+    while let Some((number, field)) = fields.next()? {
+        match (number, field) {
+            (1, Field::Varint(n)) => tensor.dims.push(Dim::Fixed(n)),
+            (1, Field::Bytes(packed)) => {
+                let mut values = Fields(packed);
+                while !values.0.is_empty() {
+                    tensor.dims.push(Dim::Fixed(values.varint()?));
+                }
+            }
+            (2, Field::Varint(data_type)) => tensor.data_type = data_type,
+            (8, Field::Bytes(name)) => tensor.name = string(name)?,
+            _ => {}
+        }
+    }
+    Ok(tensor)
+}
+
+/// `ValueInfoProto`: name (1), type (2) with `TypeProto.tensor_type` (1):
+/// elem_type (1) and shape (2) of dims (1) with dim_value (1) or
+/// dim_param (2).
+fn read_value_info(bytes: &[u8]) -> Result<Tensor> {
+    let mut tensor = Tensor::default();
+    for (number, field) in collect(bytes)? {
+        match (number, field) {
+            (1, Field::Bytes(name)) => tensor.name = string(name)?,
+            (2, Field::Bytes(type_proto)) => {
+                for (number, field) in collect(type_proto)? {
+                    let (1, Field::Bytes(tensor_type)) = (number, field) else { continue };
+                    for (number, field) in collect(tensor_type)? {
+                        match (number, field) {
+                            (1, Field::Varint(data_type)) => tensor.data_type = data_type,
+                            (2, Field::Bytes(shape)) => {
+                                for (number, field) in collect(shape)? {
+                                    if let (1, Field::Bytes(dim)) = (number, field) {
+                                        tensor.dims.push(read_dim(dim)?);
+                                    }
+                                }
+                            }
+                            _ => {}
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(tensor)
+}
+
+fn read_dim(bytes: &[u8]) -> Result<Dim> {
+    let mut dim = Dim::Unknown;
+    for (number, field) in collect(bytes)? {
+        match (number, field) {
+            (1, Field::Varint(n)) if n > 0 => dim = Dim::Fixed(n),
+            (2, Field::Bytes(param)) => dim = Dim::Param(string(param)?),
+            _ => {}
+        }
+    }
+    Ok(dim)
+}
+
+fn collect(bytes: &[u8]) -> Result<Vec<(u64, Field<'_>)>> {
+    let mut fields = Fields(bytes);
+    let mut all = Vec::new();
+    while let Some(field) = fields.next()? {
+        all.push(field);
+    }
+    Ok(all)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn varint(out: &mut Vec<u8>, mut v: u64) {
+        while v >= 0x80 {
+            out.push((v as u8) | 0x80);
+            v >>= 7;
+        }
+        out.push(v as u8);
+    }
+
+    fn bytes(out: &mut Vec<u8>, number: u64, value: &[u8]) {
+        varint(out, number << 3 | 2);
+        varint(out, value.len() as u64);
+        out.extend_from_slice(value);
+    }
+
+    /// ValueInfoProto of a float tensor; `None` axes are symbolic ("N").
+    fn value_info(name: &str, dims: &[Option<u64>]) -> Vec<u8> {
+        let mut shape = Vec::new();
+        for dim in dims {
+            let mut d = Vec::new();
+            match dim {
+                Some(n) => {
+                    varint(&mut d, 1 << 3);
+                    varint(&mut d, *n);
+                }
+                None => bytes(&mut d, 2, b"N"),
+            }
+            bytes(&mut shape, 1, &d);
+        }
+        let mut tensor_type = Vec::new();
+        varint(&mut tensor_type, 1 << 3);
+        varint(&mut tensor_type, 1);
+        bytes(&mut tensor_type, 2, &shape);
+        let mut type_proto = Vec::new();
+        bytes(&mut type_proto, 1, &tensor_type);
+        let mut info = Vec::new();
+        bytes(&mut info, 1, name.as_bytes());
+        bytes(&mut info, 2, &type_proto);
+        info
+    }
+
+    fn node(inputs: &[&str], outputs: &[&str]) -> Vec<u8> {
+        let mut node = Vec::new();
+        for name in inputs {
+            bytes(&mut node, 1, name.as_bytes());
+        }
+        for name in outputs {
+            bytes(&mut node, 2, name.as_bytes());
+        }
+        node
+    }
+
+    /// x[N,100] -> MatMul(w[100,50]) -> a -> Relu -> b -> Add(x') -> y[N,50];
+    /// `c` has no shape.
+    fn model(with_shapes: bool) -> Vec<u8> {
+        let mut graph = Vec::new();
+        bytes(&mut graph, 1, &node(&["x", "w"], &["a"]));
+        bytes(&mut graph, 1, &node(&["a"], &["b", "c"]));
+        bytes(&mut graph, 1, &node(&["b", "b"], &["y"]));
+        let mut weight = Vec::new();
+        let mut dims = Vec::new();
+        varint(&mut dims, 100);
+        varint(&mut dims, 50);
+        bytes(&mut weight, 1, &dims);
+        varint(&mut weight, 2 << 3);
+        varint(&mut weight, 1);
+        bytes(&mut weight, 8, b"w");
+        bytes(&mut graph, 5, &weight);
+        bytes(&mut graph, 11, &value_info("x", &[None, Some(100)]));
+        bytes(&mut graph, 11, &value_info("w", &[Some(100), Some(50)]));
+        bytes(&mut graph, 12, &value_info("y", &[None, Some(50)]));
+        if with_shapes {
+            bytes(&mut graph, 13, &value_info("a", &[None, Some(50)]));
+            bytes(&mut graph, 13, &value_info("b", &[None, Some(50)]));
+        }
+        let mut model = Vec::new();
+        varint(&mut model, 1 << 3);
+        varint(&mut model, 8);
+        bytes(&mut model, 7, &graph);
+        model
+    }
+
+    fn config(path: &Path, backend: &str, device: &str) -> Config {
+        toml::from_str(&format!(
+            r#"
+            [model]
+            backend = "{}"
+            device = "{}"
+            model_path = "{}"
+            input_names = ["x"]
+            input_shapes = [[0, 100]]
+            output_names = ["y"]
+            output_shapes = [[0, 50]]
+            [queue]
+            max_batch = 8
+            max_wait_ms = 5
+            [redis]
+            url = "memory://"
+            out_prefix = "results"
+            [input]
+            batch = 8
+            channels = 1
+            height = 1
+            width = 100
+            dtype = "f32"
+            layout = "nt"
+            "#,
+            backend,
+            device,
+            path.display()
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_peak_activations_follow_liveness() {
+        let graph = Graph::parse(&model(true)).unwrap();
+        assert_eq!(graph.weight_bytes(None), 100 * 50 * 4);
+        assert_eq!(graph.weight_bytes(Some(Precision::Fp16)), 100 * 50 * 2);
+
+        let cfg = config(Path::new("model.onnx"), "onnx", "cpu");
+        let sizes = Sizes::new(&graph, &cfg, 2, None);
+        assert_eq!(sizes.bytes("x"), Some(2 * 100 * 4));
+        // x und a leben gleichzeitig (800 + 400), danach nur noch a/b bzw. b/y
+        assert_eq!(graph.peak_activations(&sizes), (1200, 1));
+
+        let graph = Graph::parse(&model(false)).unwrap();
+        let sizes = Sizes::new(&graph, &cfg, 2, None);
+        assert_eq!(graph.peak_activations(&sizes), (800, 3));
+    }
+
+    #[test]
+    fn test_estimate_file() {
+        let dir = std::env::temp_dir().join(format!("omni-memory-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("model.onnx");
+        std::fs::write(&path, model(true)).unwrap();
+
+        let cpu = estimate(&config(&path, "onnx", "cpu"), 2, None, None).unwrap();
+        assert_eq!((cpu.weight_bytes, cpu.activation_bytes), (20_000, 1200));
+        assert_eq!(cpu.workspace_bytes, 2120);
+        assert_eq!(cpu.total_bytes, 23_320);
+        assert_eq!(cpu.notes.len(), 1);
+
+        let fp16 = estimate(&config(&path, "onnx", "gpu"), 2, Some(Precision::Fp16), Some(0)).unwrap();
+        assert_eq!((fp16.weight_bytes, fp16.activation_bytes, fp16.workspace_bytes), (10_000, 600, 0));
+        let gpu = estimate(&config(&path, "onnx", "gpu"), 2, None, None).unwrap();
+        assert_eq!(gpu.workspace_bytes, CUDA_CONTEXT_BYTES + MIN_GPU_WORKSPACE_BYTES);
+
+        // Andere Backends: Dateigröße und nur Ein-/Ausgaben
+        let torch = estimate(&config(&path, "torch", "cpu"), 4, None, None).unwrap();
+        assert_eq!(torch.weight_bytes, std::fs::metadata(&path).unwrap().len());
+        assert_eq!(torch.activation_bytes, 4 * (100 + 50) * 4);
+        assert!(torch.to_string().contains("Hinweis"));
+
+        assert!(Precision::parse("FP16").is_ok());
+        assert!(Precision::parse("fp8").is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,48 @@
+//! Busy time of model calls for [`crate::accounting`].
+//!
+//! Wraps the backend directly, so waiting for a concurrency slot
+//! ([`super::limit`]) does not count as device time.
+
+use std::time::Instant;
+
+use anyhow::Result;
+use ndarray::ArrayD;
+
+use crate::accounting::{accounting, device_key};
+use crate::engine::{Capabilities, Engine};
+
+/// Engine that records the duration of each call for its device.
+pub struct MeteredEngine {
+    inner: Box<dyn Engine>,
+    device: String,
+}
+
+impl MeteredEngine {
+    pub fn new(inner: Box<dyn Engine>, device_id: Option<usize>) -> Self {
+        Self { inner, device: device_key(device_id) }
+    }
+}
+
+impl Engine for MeteredEngine {
+    fn name(&self) -> &'static str {
+        self.inner.name()
+    }
+
+    fn infer_array(&mut self, input: ArrayD<f32>) -> Result<ArrayD<f32>> {
+        let started = Instant::now();
+        let y = self.inner.infer_array(input);
+        accounting().record_busy(&self.device, started.elapsed());
+        y
+    }
+
+    fn infer_named(&mut self, inputs: Vec<(String, ArrayD<f32>)>) -> Result<Vec<ArrayD<f32>>> {
+        let started = Instant::now();
+        let y = self.inner.infer_named(inputs);
+        accounting().record_busy(&self.device, started.elapsed());
+        y
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        self.inner.capabilities()
+    }
+}
@@ -4,9 +4,15 @@
 //! (ONNX Runtime, TensorRT, PyTorch, TensorFlow) allowing runtime selection
 //! based on configuration.
 
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
 use anyhow::Result;
+use serde::Serialize;
 use crate::types::Config;
 
+//SCAFFOLD
+#[cfg(feature = "onnx")]
 pub mod onnx;
 #[cfg(feature = "tensorrt")]
 pub mod tensorrt;
@@ -14,6 +20,69 @@ pub mod tensorrt;
 pub mod torch;
 #[cfg(feature = "tensorflow")]
 pub mod tensorflow;
+pub mod devices;
+pub mod ensemble;
+pub mod fallback;
+pub mod layout;
+pub mod limit;
+pub mod memory;
+pub mod metered;
+pub mod preflight;
+pub mod tiling;
+
+/// Where a backend keeps the model weights.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, utoipa::ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Residency {
+    #[default]
+    Host,
+    #[cfg_attr(not(any(feature = "onnx", feature = "tensorrt", feature = "torch", feature = "tensorflow")), allow(dead_code))]
+    Device,
+}
+
+/// Features of a loaded engine, used to adapt batching and input checks to
+/// the backend instead of assuming ONNX-like behavior.
+#[derive(Debug, Clone, PartialEq, Serialize, utoipa::ToSchema)]
+pub struct Capabilities {
+    /// Input element types the backend accepts.
+    pub dtypes: Vec<String>,
+    /// Whether dimensions configured as `0` may vary between calls.
+    pub dynamic_shapes: bool,
+    /// Largest batch per call, if the model fixes one.
+    pub max_batch: Option<usize>,
+    /// Whether several named inputs are supported ([`Engine::infer_named`]).
+    pub named_inputs: bool,
+    pub residency: Residency,
+}
+
+impl Default for Capabilities {
+    fn default() -> Self {
+        Self { dtypes: vec!["f32".to_string()], dynamic_shapes: false, max_batch: None, named_inputs: false, residency: Residency::Host }
+    }
+}
+
+impl Capabilities {
+    /// Capabilities implied by the configured input shapes: a fixed batch
+    /// dimension limits the batch, `0` dimensions are dynamic.
+    #[cfg_attr(not(any(feature = "onnx", feature = "tensorrt", feature = "torch", feature = "tensorflow")), allow(dead_code))]
+    pub fn from_shapes(shapes: &[Vec<usize>], dynamic: bool) -> Self {
+        let max_batch = shapes.first().and_then(|s| s.first()).copied().filter(|&n| n > 0);
+        let dynamic_shapes = dynamic && shapes.iter().flatten().any(|&d| d == 0);
+        Self { dynamic_shapes, max_batch, ..Self::default() }
+    }
+}
+
+static CAPABILITIES: Mutex<BTreeMap<String, Capabilities>> = Mutex::new(BTreeMap::new());
+
+/// Records the capabilities of the engine serving `worker` (e.g. `gpu:0`).
+pub fn register_capabilities(worker: &str, capabilities: Capabilities) {
+    CAPABILITIES.lock().unwrap().insert(worker.to_string(), capabilities);
+}
+
+/// Capabilities of the engines loaded so far, by worker.
+pub fn registered_capabilities() -> BTreeMap<String, Capabilities> {
+    CAPABILITIES.lock().unwrap().clone()
+}
 
 /// Trait for inference engine implementations.
 ///
@@ -33,6 +102,47 @@ pub trait Engine: Send + Sync {
     ///
     /// Output tensor from model inference
     fn infer_array(&mut self, input: ndarray::ArrayD<f32>) -> Result<ndarray::ArrayD<f32>>;
+
+    /// Performs inference with multiple named inputs.
+    ///
+    /// Returns all model outputs in the order of `output_names` from the
+    /// model configuration. The default supports a single input only and
+    /// forwards it to [`Engine::infer_array`].
+    fn infer_named(&mut self, inputs: Vec<(String, ndarray::ArrayD<f32>)>) -> Result<Vec<ndarray::ArrayD<f32>>> {
+        anyhow::ensure!(
+            inputs.len() == 1,
+            "{}: mehrere benannte Inputs werden nicht unterstützt",
+            self.name()
+        );
+        let (_, input) = inputs.into_iter().next().unwrap();
+        Ok(vec![self.infer_array(input)?])
+    }
+
+    /// Describes what the backend supports. The default is a host-resident
+    /// f32 model with static shapes and a single input.
+    fn capabilities(&self) -> Capabilities {
+        Capabilities::default()
+    }
+}
+
+/// Checks a tensor shape against a configured shape.
+///
+/// A configured dimension of `0` is treated as dynamic and matches any size
+/// (e.g. the time axis of variable-length audio).
+pub fn shape_matches(expected: &[usize], actual: &[usize]) -> bool {
+    expected.len() == actual.len()
+        && expected.iter().zip(actual).all(|(&e, &a)| e == 0 || e == a)
+}
+
+/// Returns the version of the library behind `backend`, if it reports one.
+pub fn backend_version(backend: &str) -> Option<String> {
+    match backend {
+        #[cfg(feature = "onnx")]
+        "onnx" => Some(ort::info().to_string()),
+        #[cfg(feature = "tensorflow")]
+        "tensorflow" => tensorflow::version().ok(),
+        _ => None,
+    }
 }
 
 /// Factory for creating inference engines based on configuration.
@@ -53,7 +163,48 @@ impl EngineFactory {
     /// * `Ok(Box<dyn Engine>)` - Initialized engine
     /// * `Err(e)` - Unsupported backend or initialization error
     pub fn create_for_device(cfg: &Config, device_id: Option<usize>) -> Result<Box<dyn Engine>> {
+        // fp32-Fallback innen, damit auch wiederholte Aufrufe gemessen werden
+        let engine = fallback::FallbackEngine::from_config(cfg, device_id, Self::create_backend(cfg, device_id)?);
+        let engine: Box<dyn Engine> = Box::new(metered::MeteredEngine::new(engine, device_id));
+        // Gemeinsames Limit aller Worker dieses Modells auf dem Gerät
+        let engine: Box<dyn Engine> = match cfg.model.max_concurrent_batches {
+            Some(max) => {
+                let limit = limit::Semaphore::for_device(&cfg.model.model_path, device_id, max);
+                Box::new(limit::LimitedEngine::new(engine, limit))
+            }
+            None => engine,
+        };
+        // Kacheln außen: das Limit gilt je Kachel-Batch
+        tiling::TiledEngine::from_config(cfg, engine)
+    }
+
+    fn create_backend(cfg: &Config, device_id: Option<usize>) -> Result<Box<dyn Engine>> {
+        anyhow::ensure!(
+            cfg.model.custom_ops.is_empty() || cfg.model.backend == "onnx",
+            "[[model.custom_ops]] wird nur mit backend = \"onnx\" unterstützt"
+        );
+        anyhow::ensure!(
+            cfg.model.torch.is_none() || cfg.model.backend == "torch",
+            "[model.torch] wird nur mit backend = \"torch\" unterstützt"
+        );
+        anyhow::ensure!(
+            cfg.model.tensorflow.is_none() || cfg.model.backend == "tensorflow",
+            "[model.tensorflow] wird nur mit backend = \"tensorflow\" unterstützt"
+        );
+        anyhow::ensure!(
+            cfg.model.stages.is_empty() || cfg.model.backend == "ensemble",
+            "[[model.stages]] wird nur mit backend = \"ensemble\" unterstützt"
+        );
+        // Layout-Anpassung direkt am Backend, damit sie auch für das fp32-Fallback gilt
+        layout::LayoutEngine::from_config(cfg, Self::load_backend(cfg, device_id)?)
+    }
+
+    fn load_backend(cfg: &Config, device_id: Option<usize>) -> Result<Box<dyn Engine>> {
         match cfg.model.backend.as_str() {
+            "ensemble" => Ok(Box::new(ensemble::EnsembleEngine::new(cfg, device_id)?)),
+
+            //SCAFFOLD
+            #[cfg(feature = "onnx")]
             "onnx" => Ok(Box::new(crate::engine::onnx::OnnxEngine::new(cfg, device_id)?)),
 
             #[cfg(feature = "tensorrt")]
@@ -72,3 +223,27 @@ impl EngineFactory {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shape_matches_dynamic_dims() {
+        assert!(shape_matches(&[1, 80, 0], &[1, 80, 300]));
+        assert!(shape_matches(&[1, 10], &[1, 10]));
+        assert!(!shape_matches(&[1, 10], &[2, 10]));
+        assert!(!shape_matches(&[1, 80, 0], &[1, 80]));
+    }
+
+    #[test]
+    fn test_capabilities_from_shapes() {
+        let caps = Capabilities::from_shapes(&[vec![8, 80, 0]], true);
+        assert_eq!(caps.max_batch, Some(8));
+        assert!(caps.dynamic_shapes);
+
+        let caps = Capabilities::from_shapes(&[vec![0, 10]], false);
+        assert_eq!(caps.max_batch, None);
+        assert!(!caps.dynamic_shapes);
+    }
+}
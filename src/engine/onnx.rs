@@ -4,6 +4,7 @@
 //! - Uses `ModelCfg` for input/output names and shapes.
 //! - Optional CUDA support via feature `onnx-cuda`.
 //! - Can run without a system-wide ONNX installation (`download-binaries`).
+//! - Registers custom operator libraries from `[[model.custom_ops]]`.
 //!
 //! Notes for `ort` v2:
 //! - Call `ort::init().commit()?` globally before creating the first session.
@@ -17,7 +18,7 @@ use ort::{
     session::{builder::GraphOptimizationLevel, builder::SessionBuilder, Session},
     value::{DynValue, Tensor},
 };
-use crate::engine::Engine;
+use crate::engine::{shape_matches, Capabilities, Engine, Residency};
 use crate::types::Config;
 use std::sync::Mutex;
 
@@ -28,6 +29,7 @@ pub struct OnnxEngine {
     output_names: Vec<String>,
     input_shapes: Vec<Vec<usize>>,
     output_shapes: Vec<Vec<usize>>,
+    residency: Residency,
 }
 
 impl OnnxEngine {
@@ -37,14 +39,39 @@ impl OnnxEngine {
     /// device selection (CPU/GPU). If the `onnx-cuda` feature is enabled and
     /// `device` is GPU, the CUDA execution provider will be registered.
     pub fn new(cfg: &Config, _device_id: Option<usize>) -> Result<Self> {
+        // Nicht unterstützte Operatoren vor dem Laden benennen
+        if cfg.model.preflight {
+            let report = crate::engine::preflight::check_file(cfg)?;
+            report.ensure_supported()?;
+            if !report.cpu_fallback.is_empty() {
+                tracing::warn!(
+                    "ONNX: Operatoren ohne {}-Kernel laufen auf der CPU: {}",
+                    report.provider,
+                    report.cpu_fallback.join(", ")
+                );
+            }
+        }
+
+        let cuda = cfg!(feature = "onnx-cuda") && cfg.model.device.eq_ignore_ascii_case("gpu");
+        let residency = if cuda { Residency::Device } else { Residency::Host };
         let mut builder = SessionBuilder::new()
             .with_context(|| "Fehler beim Erstellen des SessionBuilder")?;
         builder = builder.with_optimization_level(GraphOptimizationLevel::Level3)?;
+        if cfg.reproducibility.is_some() {
+            builder = builder.with_deterministic_compute(true)?;
+        }
+
+        // Bibliotheken mit eigenen Operatoren vor dem Laden des Modells registrieren
+        for custom in &cfg.model.custom_ops {
+            builder = builder
+                .with_operator_library(&custom.library)
+                .with_context(|| format!("Custom-Op-Bibliothek konnte nicht geladen werden: {}", custom.library))?;
+        }
 
         // CUDA-Provider optional aktivieren
         #[cfg(feature = "onnx-cuda")]
         {
-            if cfg.model.device.to_lowercase() == "gpu" {
+            if cuda {
                 let gpu_id = _device_id.unwrap_or(0) as i32;
                 builder = builder
                     .with_execution_providers([ort::execution_providers::CUDAExecutionProvider::default().with_device_id(gpu_id)])?;
@@ -70,6 +97,7 @@ impl OnnxEngine {
             output_names: cfg.model.output_names.clone(),
             input_shapes: cfg.model.input_shapes.clone(),
             output_shapes: cfg.model.output_shapes.clone(),
+            residency,
         })
     }
 }
@@ -77,13 +105,19 @@ impl OnnxEngine {
 impl Engine for OnnxEngine {
     fn name(&self) -> &'static str { "onnx" }
 
+    /// Dynamic dimensions and named inputs are supported; weights live on the
+    /// GPU when the CUDA provider is registered.
+    fn capabilities(&self) -> Capabilities {
+        Capabilities { named_inputs: true, residency: self.residency, ..Capabilities::from_shapes(&self.input_shapes, true) }
+    }
+
     /// Runs inference on the provided input tensor and returns the output tensor.
     fn infer_array(&mut self, input: ArrayD<f32>) -> Result<ArrayD<f32>> {
         let mut session = self.session.lock().unwrap();
 
         let expected_in = &self.input_shapes[0];
         anyhow::ensure!(
-            input.shape() == expected_in.as_slice(),
+            shape_matches(expected_in, input.shape()),
             "ONNX: Input-Shape passt nicht. Erwartet {:?}, bekommen {:?}",
             expected_in, input.shape()
         );
@@ -101,11 +135,49 @@ impl Engine for OnnxEngine {
 
         let expected_out = &self.output_shapes[0];
         anyhow::ensure!(
-            out_view.shape() == expected_out.as_slice(),
+            shape_matches(expected_out, out_view.shape()),
             "ONNX: Output-Shape passt nicht. Erwartet {:?}, bekommen {:?}",
             expected_out, out_view.shape()
         );
 
         Ok(out_view.to_owned())
     }
+
+    /// Runs inference with all named inputs and returns every configured output.
+    fn infer_named(&mut self, inputs: Vec<(String, ArrayD<f32>)>) -> Result<Vec<ArrayD<f32>>> {
+        let mut session = self.session.lock().unwrap();
+
+        let mut feeds: Vec<(String, Tensor<f32>)> = Vec::with_capacity(inputs.len());
+        for (name, array) in inputs {
+            let idx = self
+                .input_names
+                .iter()
+                .position(|n| *n == name)
+                .with_context(|| format!("ONNX: unbekannter Input '{}'", name))?;
+            anyhow::ensure!(
+                shape_matches(&self.input_shapes[idx], array.shape()),
+                "ONNX: Input-Shape von '{}' passt nicht. Erwartet {:?}, bekommen {:?}",
+                name, self.input_shapes[idx], array.shape()
+            );
+            feeds.push((name, Tensor::from_array(array)?));
+        }
+
+        let outputs = session.run(feeds)?;
+
+        self.output_names
+            .iter()
+            .zip(&self.output_shapes)
+            .map(|(name, expected)| {
+                let view = outputs[name.as_str()]
+                    .try_extract_array::<f32>()
+                    .map_err(|_| anyhow::anyhow!("ONNX: Output '{}' ist kein Tensor<f32>", name))?;
+                anyhow::ensure!(
+                    shape_matches(expected, view.shape()),
+                    "ONNX: Output-Shape von '{}' passt nicht. Erwartet {:?}, bekommen {:?}",
+                    name, expected, view.shape()
+                );
+                Ok(view.to_owned())
+            })
+            .collect()
+    }
 }
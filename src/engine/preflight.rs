@@ -0,0 +1,460 @@
+//! ONNX opset and operator preflight.
+//!
+//! Reads opset imports and operators straight from the model protobuf (no
+//! ONNX Runtime needed) and checks them against what the selected execution
+//! provider supports, before a session is committed. Unsupported operators
+//! are reported by name instead of surfacing as an opaque error on the first
+//! inference. Operators of the CUDA provider without a CUDA kernel run on the
+//! CPU; they are reported as fallbacks, not as errors.
+//!
+//! The operator tables follow ONNX Runtime 1.22 (`ort = 2.0.0-rc.10`).
+//! Contrib domains (`com.microsoft…`) and the domains of registered custom
+//! operator libraries (`[[model.custom_ops]]`) are not checked per operator.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+
+use crate::types::{Config, CustomOpsCfg};
+
+/// Opsets of the default domain ONNX Runtime loads.
+const ONNX_OPSETS: std::ops::RangeInclusive<i64> = 7..=22;
+
+/// Highest supported opset of `ai.onnx.ml`.
+const ML_OPSET_MAX: i64 = 5;
+
+/// Operators of the default domain with a CPU kernel. Function operators
+/// without a kernel (e.g. `Mish`) are expanded and listed as well.
+const ONNX_OPS: &[&str] = &[
+    "Abs", "Acos", "Acosh", "Add", "AffineGrid", "And", "ArgMax", "ArgMin", "Asin", "Asinh", "Atan", "Atanh",
+    "AveragePool", "BatchNormalization", "Bernoulli", "BitShift", "BitwiseAnd", "BitwiseNot", "BitwiseOr",
+    "BitwiseXor", "BlackmanWindow", "Cast", "CastLike", "Ceil", "Celu", "CenterCropPad", "Clip", "Col2Im",
+    "Compress", "Concat", "ConcatFromSequence", "Constant", "ConstantOfShape", "Conv", "ConvInteger",
+    "ConvTranspose", "Cos", "Cosh", "CumSum", "DFT", "DeformConv", "DepthToSpace", "DequantizeLinear", "Det",
+    "Div", "Dropout", "DynamicQuantizeLinear", "Einsum", "Elu", "Equal", "Erf", "Exp", "Expand", "EyeLike",
+    "Flatten", "Floor", "GRU", "Gather", "GatherElements", "GatherND", "Gelu", "Gemm", "GlobalAveragePool",
+    "GlobalLpPool", "GlobalMaxPool", "Greater", "GreaterOrEqual", "GridSample", "GroupNormalization",
+    "HammingWindow", "HannWindow", "HardSigmoid", "HardSwish", "Hardmax", "Identity", "If",
+    "InstanceNormalization", "IsInf", "IsNaN", "LRN", "LSTM", "LayerNormalization", "LeakyRelu", "Less",
+    "LessOrEqual", "Log", "LogSoftmax", "Loop", "LpNormalization", "LpPool", "MatMul", "MatMulInteger", "Max",
+    "MaxPool", "MaxRoiPool", "MaxUnpool", "Mean", "MeanVarianceNormalization", "MelWeightMatrix", "Min", "Mish",
+    "Mod", "Mul", "Multinomial", "Neg", "NegativeLogLikelihoodLoss", "NonMaxSuppression", "NonZero", "Not",
+    "OneHot", "Optional", "OptionalGetElement", "OptionalHasElement", "Or", "PRelu", "Pad", "Pow", "QLinearConv",
+    "QLinearMatMul", "QuantizeLinear", "RNN", "RandomNormal", "RandomNormalLike", "RandomUniform",
+    "RandomUniformLike", "Range", "Reciprocal", "ReduceL1", "ReduceL2", "ReduceLogSum", "ReduceLogSumExp",
+    "ReduceMax", "ReduceMean", "ReduceMin", "ReduceProd", "ReduceSum", "ReduceSumSquare", "RegexFullMatch",
+    "Relu", "Reshape", "Resize", "ReverseSequence", "RoiAlign", "Round", "STFT", "Scan", "Scatter",
+    "ScatterElements", "ScatterND", "Selu", "SequenceAt", "SequenceConstruct", "SequenceEmpty", "SequenceErase",
+    "SequenceInsert", "SequenceLength", "SequenceMap", "Shape", "Shrink", "Sigmoid", "Sign", "Sin", "Sinh",
+    "Size", "Slice", "Softmax", "SoftmaxCrossEntropyLoss", "Softplus", "Softsign", "SpaceToDepth", "Split",
+    "SplitToSequence", "Sqrt", "Squeeze", "StringConcat", "StringNormalizer", "StringSplit", "Sub", "Sum", "Tan",
+    "Tanh", "TfIdfVectorizer", "ThresholdedRelu", "Tile", "TopK", "Transpose", "Trilu", "Unique", "Unsqueeze",
+    "Upsample", "Where", "Xor",
+];
+
+/// Operators of `ai.onnx.ml` (CPU only).
+const ML_OPS: &[&str] = &[
+    "ArrayFeatureExtractor", "Binarizer", "CastMap", "CategoryMapper", "DictVectorizer", "FeatureVectorizer",
+    "Imputer", "LabelEncoder", "LinearClassifier", "LinearRegressor", "Normalizer", "OneHotEncoder",
+    "SVMClassifier", "SVMRegressor", "Scaler", "TreeEnsemble", "TreeEnsembleClassifier", "TreeEnsembleRegressor",
+    "ZipMap",
+];
+
+/// Default-domain operators without a CUDA kernel.
+const CUDA_CPU_ONLY: &[&str] = &[
+    "Col2Im", "DFT", "Det", "GlobalLpPool", "LpPool", "MaxRoiPool", "RegexFullMatch", "STFT", "StringConcat",
+    "StringNormalizer", "StringSplit", "TfIdfVectorizer", "Unique",
+];
+
+/// Domains ONNX Runtime ships kernels for, besides the checked ones.
+const CONTRIB_DOMAINS: &[&str] = &["com.microsoft", "com.microsoft.nchwc", "com.microsoft.internal.nhwc", "ai.onnx.preview.training"];
+
+const DEFAULT_DOMAIN: &str = "ai.onnx";
+const ML_DOMAIN: &str = "ai.onnx.ml";
+
+/// Execution provider a session is committed with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Provider {
+    Cpu,
+    Cuda,
+}
+
+impl Provider {
+    /// Provider `OnnxEngine` registers for `cfg`.
+    pub fn for_config(cfg: &Config) -> Self {
+        if cfg!(feature = "onnx-cuda") && cfg.model.device.eq_ignore_ascii_case("gpu") {
+            Provider::Cuda
+        } else {
+            Provider::Cpu
+        }
+    }
+}
+
+impl fmt::Display for Provider {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Provider::Cpu => "CPU",
+            Provider::Cuda => "CUDA",
+        })
+    }
+}
+
+/// Opsets and operators used by a model.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ModelOps {
+    pub ir_version: i64,
+    /// Imported opset version per domain (`ai.onnx` for the default domain).
+    pub opsets: BTreeMap<String, i64>,
+    /// `(domain, op_type)` of all nodes, including subgraphs and functions.
+    pub ops: BTreeSet<(String, String)>,
+    /// `(domain, name)` of model-local functions.
+    pub functions: BTreeSet<(String, String)>,
+}
+
+/// Outcome of the preflight.
+#[derive(Debug, Clone, Serialize)]
+pub struct Report {
+    pub provider: Provider,
+    pub opsets: BTreeMap<String, i64>,
+    pub unsupported_opsets: Vec<String>,
+    pub unsupported_ops: Vec<String>,
+    /// Operators that run on the CPU instead of the selected provider.
+    pub cpu_fallback: Vec<String>,
+}
+
+impl Report {
+    /// Fails with the unsupported opsets and operators by name.
+    pub fn ensure_supported(&self) -> Result<()> {
+        let mut problems = Vec::new();
+        if !self.unsupported_opsets.is_empty() {
+            problems.push(format!("Opsets {}", self.unsupported_opsets.join(", ")));
+        }
+        if !self.unsupported_ops.is_empty() {
+            problems.push(format!("Operatoren {}", self.unsupported_ops.join(", ")));
+        }
+        anyhow::ensure!(
+            problems.is_empty(),
+            "ONNX Runtime ({}) unterstützt nicht: {}",
+            self.provider,
+            problems.join("; ")
+        );
+        Ok(())
+    }
+}
+
+/// Checks the model file of `cfg` against its execution provider and the
+/// configured custom operator libraries, which must exist.
+pub fn check_file(cfg: &Config) -> Result<Report> {
+    for custom in &cfg.model.custom_ops {
+        anyhow::ensure!(
+            std::path::Path::new(&custom.library).is_file(),
+            "Custom-Op-Bibliothek fehlt: {}",
+            custom.library
+        );
+    }
+    let path = &cfg.model.model_path;
+    let bytes = std::fs::read(path).with_context(|| format!("ONNX-Modell konnte nicht gelesen werden: {}", path))?;
+    let ops = ModelOps::parse(&bytes).with_context(|| format!("Kein gültiges ONNX-Modell: {}", path))?;
+    Ok(check(&ops, Provider::for_config(cfg), &cfg.model.custom_ops))
+}
+
+/// Checks opsets and operators against `provider`; operators of
+/// `custom_ops` domains are taken as provided by those libraries.
+pub fn check(model: &ModelOps, provider: Provider, custom_ops: &[CustomOpsCfg]) -> Report {
+    let known_domain = |domain: &str| {
+        CONTRIB_DOMAINS.contains(&domain)
+            || model.functions.iter().any(|(d, _)| d == domain)
+            || custom_ops.iter().any(|c| c.domains.is_empty() || c.domains.iter().any(|d| d == domain))
+    };
+    let mut report = Report {
+        provider,
+        opsets: model.opsets.clone(),
+        unsupported_opsets: Vec::new(),
+        unsupported_ops: Vec::new(),
+        cpu_fallback: Vec::new(),
+    };
+    for (domain, &version) in &model.opsets {
+        let supported = match domain.as_str() {
+            DEFAULT_DOMAIN => ONNX_OPSETS.contains(&version),
+            ML_DOMAIN => (1..=ML_OPSET_MAX).contains(&version),
+            other => known_domain(other),
+        };
+        if !supported {
+            report.unsupported_opsets.push(format!("{} {}", domain, version));
+        }
+    }
+    for (domain, op) in &model.ops {
+        if model.functions.contains(&(domain.clone(), op.clone())) {
+            continue;
+        }
+        let (supported, cuda) = match domain.as_str() {
+            DEFAULT_DOMAIN => (ONNX_OPS.contains(&op.as_str()), !CUDA_CPU_ONLY.contains(&op.as_str())),
+            ML_DOMAIN => (ML_OPS.contains(&op.as_str()), false),
+            other => (known_domain(other), true),
+        };
+        let name = if domain == DEFAULT_DOMAIN { op.clone() } else { format!("{}::{}", domain, op) };
+        if !supported {
+            report.unsupported_ops.push(name);
+        } else if provider == Provider::Cuda && !cuda {
+            report.cpu_fallback.push(name);
+        }
+    }
+    report
+}
+
+impl ModelOps {
+    /// Reads a serialized `ModelProto`.
+    pub fn parse(bytes: &[u8]) -> Result<Self> {
+        let mut model = ModelOps::default();
+        let mut fields = Fields(bytes);
+        while let Some((number, field)) = fields.next()? {
+            match (number, field) {
+                (1, Field::Varint(v)) => model.ir_version = v as i64,
+                (7, Field::Bytes(graph)) => model.read_graph(graph)?,
+                (8, Field::Bytes(opset)) => {
+                    let (mut domain, mut version) = (String::new(), 0);
+                    let mut fields = Fields(opset);
+                    while let Some((number, field)) = fields.next()? {
+                        match (number, field) {
+                            (1, Field::Bytes(d)) => domain = string(d)?,
+                            (2, Field::Varint(v)) => version = v as i64,
+                            _ => {}
+                        }
+                    }
+                    model.opsets.insert(normalize_domain(domain), version);
+                }
+                (25, Field::Bytes(function)) => model.read_function(function)?,
+                _ => {}
+            }
+        }
+        anyhow::ensure!(model.ir_version > 0, "ir_version fehlt");
+        Ok(model)
+    }
+
+    /// `GraphProto`: nodes (field 1).
+    fn read_graph(&mut self, graph: &[u8]) -> Result<()> {
+        let mut fields = Fields(graph);
+        while let Some((number, field)) = fields.next()? {
+            if let (1, Field::Bytes(node)) = (number, field) {
+                self.read_node(node)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// `NodeProto`: op_type (4), domain (7) and subgraphs of attributes (5).
+    fn read_node(&mut self, node: &[u8]) -> Result<()> {
+        let (mut op_type, mut domain) = (String::new(), String::new());
+        let mut fields = Fields(node);
+        while let Some((number, field)) = fields.next()? {
+            match (number, field) {
+                (4, Field::Bytes(v)) => op_type = string(v)?,
+                (7, Field::Bytes(v)) => domain = string(v)?,
+                // AttributeProto: g (6), graphs (11), z. B. Bodies von If/Loop
+                (5, Field::Bytes(attribute)) => {
+                    let mut fields = Fields(attribute);
+                    while let Some((number, field)) = fields.next()? {
+                        if let (6 | 11, Field::Bytes(graph)) = (number, field) {
+                            self.read_graph(graph)?;
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        self.ops.insert((normalize_domain(domain), op_type));
+        Ok(())
+    }
+
+    /// `FunctionProto`: name (1), nodes (7), domain (10).
+    fn read_function(&mut self, function: &[u8]) -> Result<()> {
+        let (mut name, mut domain) = (String::new(), String::new());
+        let mut fields = Fields(function);
+        while let Some((number, field)) = fields.next()? {
+            match (number, field) {
+                (1, Field::Bytes(v)) => name = string(v)?,
+                (7, Field::Bytes(node)) => self.read_node(node)?,
+                (10, Field::Bytes(v)) => domain = string(v)?,
+                _ => {}
+            }
+        }
+        self.functions.insert((normalize_domain(domain), name));
+        Ok(())
+    }
+}
+
+fn normalize_domain(domain: String) -> String {
+    if domain.is_empty() { DEFAULT_DOMAIN.to_string() } else { domain }
+}
+
+pub(super) fn string(bytes: &[u8]) -> Result<String> {
+    Ok(std::str::from_utf8(bytes).context("Ungültiges UTF-8 im Modell")?.to_string())
+}
+
+/// A protobuf field value; fixed-width values are skipped.
+pub(super) enum Field<'a> {
+    Varint(u64),
+    Bytes(&'a [u8]),
+    Fixed,
+}
+
+/// Minimal protobuf wire-format reader over one message.
+pub(super) struct Fields<'a>(pub(super) &'a [u8]);
+
+impl<'a> Fields<'a> {
+    pub(super) fn varint(&mut self) -> Result<u64> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let (&byte, rest) = self.0.split_first().context("Protobuf abgeschnitten")?;
+            self.0 = rest;
+            value |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        anyhow::bail!("Protobuf-Varint zu lang")
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        anyhow::ensure!(len <= self.0.len(), "Protobuf abgeschnitten");
+        let (head, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(head)
+    }
+
+    pub(super) fn next(&mut self) -> Result<Option<(u64, Field<'a>)>> {
+        if self.0.is_empty() {
+            return Ok(None);
+        }
+        let key = self.varint()?;
+        let field = match key & 7 {
+            0 => Field::Varint(self.varint()?),
+            1 => {
+                self.take(8)?;
+                Field::Fixed
+            }
+            2 => {
+                let len = usize::try_from(self.varint()?)?;
+                Field::Bytes(self.take(len)?)
+            }
+            5 => {
+                self.take(4)?;
+                Field::Fixed
+            }
+            wire => anyhow::bail!("Protobuf-Wire-Type {} nicht unterstützt", wire),
+        };
+        Ok(Some((key >> 3, field)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn varint(out: &mut Vec<u8>, mut v: u64) {
+        while v >= 0x80 {
+            out.push((v as u8) | 0x80);
+            v >>= 7;
+        }
+        out.push(v as u8);
+    }
+
+    fn bytes(out: &mut Vec<u8>, number: u64, value: &[u8]) {
+        varint(out, number << 3 | 2);
+        varint(out, value.len() as u64);
+        out.extend_from_slice(value);
+    }
+
+    fn node(op_type: &str, domain: &str, subgraph: Option<&[u8]>) -> Vec<u8> {
+        let mut node = Vec::new();
+        bytes(&mut node, 4, op_type.as_bytes());
+        if !domain.is_empty() {
+            bytes(&mut node, 7, domain.as_bytes());
+        }
+        if let Some(graph) = subgraph {
+            let mut attribute = Vec::new();
+            bytes(&mut attribute, 1, b"then_branch");
+            bytes(&mut attribute, 6, graph);
+            bytes(&mut node, 5, &attribute);
+        }
+        node
+    }
+
+    /// ModelProto with opsets, top-level nodes and one local function.
+    fn model(opsets: &[(&str, u64)], nodes: &[Vec<u8>]) -> Vec<u8> {
+        let mut model = Vec::new();
+        varint(&mut model, 1 << 3);
+        varint(&mut model, 8);
+        let mut graph = Vec::new();
+        for node in nodes {
+            bytes(&mut graph, 1, node);
+        }
+        // Initializer (Feld 5) und fixed32-Felder werden übersprungen
+        bytes(&mut graph, 5, &[0x0d, 0, 0, 0x80, 0x3f]);
+        bytes(&mut model, 7, &graph);
+        varint(&mut model, 30 << 3 | 5);
+        model.extend_from_slice(&[0; 4]);
+        for (domain, version) in opsets {
+            let mut opset = Vec::new();
+            bytes(&mut opset, 1, domain.as_bytes());
+            varint(&mut opset, 2 << 3);
+            varint(&mut opset, *version);
+            bytes(&mut model, 8, &opset);
+        }
+        let mut function = Vec::new();
+        bytes(&mut function, 1, b"Block");
+        bytes(&mut function, 7, &node("Relu", "", None));
+        bytes(&mut function, 10, b"local");
+        bytes(&mut model, 25, &function);
+        model
+    }
+
+    #[test]
+    fn test_parse_model_ops() {
+        let mut branch = Vec::new();
+        bytes(&mut branch, 1, &node("Unique", "", None));
+        let raw = model(&[("", 17), ("local", 1)], &[node("Conv", "", None), node("If", "", Some(&branch)), node("Block", "local", None)]);
+        let ops = ModelOps::parse(&raw).unwrap();
+        assert_eq!(ops.ir_version, 8);
+        assert_eq!(ops.opsets["ai.onnx"], 17);
+        let names: Vec<_> = ops.ops.iter().map(|(_, op)| op.as_str()).collect();
+        assert_eq!(names, vec!["Conv", "If", "Relu", "Unique", "Block"]);
+
+        assert!(ModelOps::parse(&raw[..raw.len() - 3]).is_err());
+        assert!(ModelOps::parse(b"not a model").is_err());
+    }
+
+    #[test]
+    fn test_check_against_provider() {
+        let nodes = [node("Conv", "", None), node("Unique", "", None), node("Block", "local", None), node("Scaler", "ai.onnx.ml", None)];
+        let ops = ModelOps::parse(&model(&[("", 17), ("ai.onnx.ml", 3), ("local", 1)], &nodes)).unwrap();
+        let report = check(&ops, Provider::Cpu, &[]);
+        assert!(report.ensure_supported().is_ok());
+        assert!(report.cpu_fallback.is_empty());
+        let report = check(&ops, Provider::Cuda, &[]);
+        assert!(report.ensure_supported().is_ok());
+        assert_eq!(report.cpu_fallback, vec!["Unique", "ai.onnx.ml::Scaler"]);
+    }
+
+    #[test]
+    fn test_check_reports_unsupported() {
+        let nodes = [node("FancyOp", "", None), node("Plugin", "com.vendor", None), node("FusedConv", "com.microsoft", None)];
+        let ops = ModelOps::parse(&model(&[("", 30), ("com.vendor", 1), ("com.microsoft", 1)], &nodes)).unwrap();
+        let report = check(&ops, Provider::Cpu, &[]);
+        assert_eq!(report.unsupported_opsets, vec!["ai.onnx 30", "com.vendor 1"]);
+        assert_eq!(report.unsupported_ops, vec!["FancyOp", "com.vendor::Plugin"]);
+        let err = report.ensure_supported().unwrap_err().to_string();
+        assert!(err.contains("FancyOp, com.vendor::Plugin"), "{}", err);
+
+        // Domänen registrierter Custom-Op-Bibliotheken gelten als unterstützt
+        let custom = [CustomOpsCfg { library: "libvendor_ops.so".to_string(), domains: vec!["com.vendor".to_string()] }];
+        let report = check(&ops, Provider::Cpu, &custom);
+        assert_eq!(report.unsupported_opsets, vec!["ai.onnx 30"]);
+        assert_eq!(report.unsupported_ops, vec!["FancyOp"]);
+        let custom = [CustomOpsCfg { library: "libvendor_ops.so".to_string(), domains: Vec::new() }];
+        assert_eq!(check(&ops, Provider::Cpu, &custom).unsupported_ops, vec!["FancyOp"]);
+    }
+}
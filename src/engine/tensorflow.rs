@@ -1,17 +1,33 @@
 //! TensorFlow engine using the `tensorflow` Rust bindings.
 //!
-//! This backend loads a TensorFlow graph and executes inference using a session.
-//! It expects model I/O names and shapes to be provided via configuration.
+//! A directory `model_path` is loaded as SavedModel bundle with the tags of
+//! `[model.tensorflow]`; `input_names` and `output_names` are the input and
+//! output keys of its signature (default `serving_default`). A file is
+//! imported as frozen `GraphDef`, where the names are tensor names
+//! (`op:index`). Any number of feeds and fetches is supported.
+//!
+//! `device = "gpu"` pins the session to the worker's GPU; `"cpu"` hides all
+//! GPUs from it.
+
+use std::path::Path;
 
 use anyhow::{Context, Result};
 use ndarray::ArrayD;
-use tensorflow::{Graph, Session, SessionOptions, SessionRunArgs, Tensor as TfTensor};
-use crate::engine::Engine;
+use tensorflow::{
+    Graph, ImportGraphDefOptions, Operation, OutputName, SavedModelBundle, Session, SessionOptions, SessionRunArgs,
+    Tensor as TfTensor,
+};
+use crate::engine::{shape_matches, Capabilities, Engine, Residency};
 use crate::types::Config;
 
 /// TensorFlow inference engine implementation.
 pub struct TfEngine {
     session: Session,
+    gpu: bool,
+    /// Feed endpoints in `input_names` order.
+    inputs: Vec<(Operation, i32)>,
+    /// Fetch endpoints in `output_names` order.
+    outputs: Vec<(Operation, i32)>,
     input_names: Vec<String>,
     output_names: Vec<String>,
     input_shapes: Vec<Vec<usize>>,
@@ -20,11 +36,7 @@ pub struct TfEngine {
 
 impl TfEngine {
     /// Creates a new TensorFlow engine from the provided runtime configuration.
-    pub fn new(cfg: &Config, _device_id: Option<usize>) -> Result<Self> {
-        let mut graph = Graph::new();
-        let session = Session::new(&SessionOptions::new(), &graph)
-            .context("TensorFlow: Session erstellen fehlgeschlagen")?;
-
+    pub fn new(cfg: &Config, device_id: Option<usize>) -> Result<Self> {
         anyhow::ensure!(
             cfg.model.input_names.len() == cfg.model.input_shapes.len(),
             "TensorFlow: input_names und input_shapes haben unterschiedliche Länge"
@@ -33,9 +45,50 @@ impl TfEngine {
             cfg.model.output_names.len() == cfg.model.output_shapes.len(),
             "TensorFlow: output_names und output_shapes haben unterschiedliche Länge"
         );
+        let tf_cfg = cfg.model.tensorflow.clone().unwrap_or_default();
+
+        // Device wählen
+        let gpu = cfg.model.device.eq_ignore_ascii_case("gpu");
+        let mut options = SessionOptions::new();
+        options
+            .set_config(&session_config(gpu.then(|| device_id.unwrap_or(0)), tf_cfg.allow_growth))
+            .context("TensorFlow: Session-Konfiguration ungültig")?;
+
+        let mut graph = Graph::new();
+        let path = Path::new(&cfg.model.model_path);
+        let (session, inputs, outputs) = if path.is_dir() {
+            let bundle = SavedModelBundle::load(&options, &tf_cfg.tags, &mut graph, path)
+                .with_context(|| format!("TensorFlow: SavedModel laden fehlgeschlagen: {}", cfg.model.model_path))?;
+            let signature = bundle
+                .meta_graph_def()
+                .get_signature(&tf_cfg.signature)
+                .with_context(|| format!("TensorFlow: Signatur '{}' nicht gefunden", tf_cfg.signature))?;
+            let inputs = resolve(&graph, &cfg.model.input_names, |key| {
+                Ok(signature.get_input(key).with_context(|| format!("Signatur '{}' hat keinen Input '{}'", tf_cfg.signature, key))?.name().clone())
+            })?;
+            let outputs = resolve(&graph, &cfg.model.output_names, |key| {
+                Ok(signature.get_output(key).with_context(|| format!("Signatur '{}' hat keinen Output '{}'", tf_cfg.signature, key))?.name().clone())
+            })?;
+            (bundle.session, inputs, outputs)
+        } else {
+            // Eingefrorener Graph: Namen sind Tensor-Namen (op:index)
+            let graph_def = std::fs::read(path)
+                .with_context(|| format!("TensorFlow: Graph lesen fehlgeschlagen: {}", cfg.model.model_path))?;
+            graph
+                .import_graph_def(&graph_def, &ImportGraphDefOptions::new())
+                .context("TensorFlow: Graph importieren fehlgeschlagen")?;
+            let session = Session::new(&options, &graph).context("TensorFlow: Session erstellen fehlgeschlagen")?;
+            let parse = |name: &str| name.parse::<OutputName>().with_context(|| format!("Ungültiger Tensor-Name '{}'", name));
+            let inputs = resolve(&graph, &cfg.model.input_names, parse)?;
+            let outputs = resolve(&graph, &cfg.model.output_names, parse)?;
+            (session, inputs, outputs)
+        };
 
         Ok(Self {
             session,
+            gpu,
+            inputs,
+            outputs,
             input_names: cfg.model.input_names.clone(),
             output_names: cfg.model.output_names.clone(),
             input_shapes: cfg.model.input_shapes.clone(),
@@ -44,34 +97,117 @@ impl TfEngine {
     }
 }
 
+/// Looks up the graph operation and output index behind each name.
+fn resolve(
+    graph: &Graph,
+    names: &[String],
+    lookup: impl Fn(&str) -> Result<OutputName>,
+) -> Result<Vec<(Operation, i32)>> {
+    names
+        .iter()
+        .map(|name| {
+            let output = lookup(name).with_context(|| format!("TensorFlow: '{}' nicht auflösbar", name))?;
+            let op = graph
+                .operation_by_name_required(&output.name)
+                .with_context(|| format!("TensorFlow: Operation '{}' fehlt im Graph", output.name))?;
+            Ok((op, output.index))
+        })
+        .collect()
+}
+
+/// Serialized `ConfigProto` for the session.
+///
+/// With a GPU ordinal only that GPU is visible to the session (as
+/// `/device:GPU:0`); without one, no GPU is used. The few fields are encoded
+/// by hand since the bindings take the raw protobuf.
+fn session_config(gpu: Option<usize>, allow_growth: bool) -> Vec<u8> {
+    fn field(out: &mut Vec<u8>, number: u8, bytes: &[u8]) {
+        out.push((number << 3) | 2);
+        out.push(bytes.len() as u8);
+        out.extend_from_slice(bytes);
+    }
+
+    let mut config = Vec::new();
+    match gpu {
+        Some(ordinal) => {
+            // GPUOptions: allow_growth = 4, visible_device_list = 5
+            let mut gpu_options = vec![4 << 3, allow_growth as u8];
+            field(&mut gpu_options, 5, ordinal.to_string().as_bytes());
+            field(&mut config, 6, &gpu_options);
+        }
+        None => {
+            // device_count = 1: {"GPU": 0}
+            let mut entry = Vec::new();
+            field(&mut entry, 1, b"GPU");
+            entry.extend_from_slice(&[2 << 3, 0]);
+            field(&mut config, 1, &entry);
+        }
+    }
+    // allow_soft_placement = 7: Ops ohne GPU-Kernel auf der CPU ausführen
+    config.extend_from_slice(&[7 << 3, 1]);
+    config
+}
+
 impl Engine for TfEngine {
     fn name(&self) -> &'static str { "tensorflow" }
 
-    /// Runs inference on the provided tensor using the configured graph/session.
+    fn capabilities(&self) -> Capabilities {
+        let residency = if self.gpu { Residency::Device } else { Residency::Host };
+        Capabilities { named_inputs: true, residency, ..Capabilities::from_shapes(&self.input_shapes, false) }
+    }
+
+    /// Runs inference on the provided tensor and returns the first output.
     fn infer_array(&mut self, input: ArrayD<f32>) -> Result<ArrayD<f32>> {
-        let expected = &self.input_shapes[0];
+        let name = self.input_names.first().cloned().unwrap_or_default();
+        let outputs = self.infer_named(vec![(name, input)])?;
+        outputs.into_iter().next().context("TensorFlow: keine Outputs konfiguriert")
+    }
+
+    /// Feeds all named inputs and fetches the outputs in `output_names` order.
+    fn infer_named(&mut self, inputs: Vec<(String, ArrayD<f32>)>) -> Result<Vec<ArrayD<f32>>> {
+        let mut feeds = Vec::with_capacity(inputs.len());
+        for (name, array) in inputs {
+            let idx = self
+                .input_names
+                .iter()
+                .position(|n| *n == name)
+                .with_context(|| format!("TensorFlow: unbekannter Input '{}'", name))?;
+            anyhow::ensure!(
+                shape_matches(&self.input_shapes[idx], array.shape()),
+                "TensorFlow: Input-Shape von '{}' passt nicht. Erwartet {:?}, bekommen {:?}",
+                name, self.input_shapes[idx], array.shape()
+            );
+            let dims: Vec<u64> = array.shape().iter().map(|&d| d as u64).collect();
+            let tensor = TfTensor::<f32>::new(&dims).with_values(array.as_standard_layout().as_slice().unwrap())?;
+            feeds.push((idx, tensor));
+        }
         anyhow::ensure!(
-            input.shape() == expected.as_slice(),
-            "TensorFlow: Input-Shape passt nicht. Erwartet {:?}, bekommen {:?}",
-            expected,
-            input.shape()
+            feeds.len() == self.inputs.len(),
+            "TensorFlow: {} Inputs erwartet, bekommen {}",
+            self.inputs.len(), feeds.len()
         );
 
-        // TensorFlow Tensor
-        let dims: Vec<u64> = expected.iter().map(|&d| d as u64).collect();
-        let mut tf_tensor = TfTensor::<f32>::new(&dims)?;
-        tf_tensor.copy_from_slice(input.as_slice().unwrap());
-
         let mut args = SessionRunArgs::new();
-        let in_op = self.session.graph().operation_by_name_required(&self.input_names[0])?;
-        let out_op = self.session.graph().operation_by_name_required(&self.output_names[0])?;
-        args.add_feed(&in_op, 0, &tf_tensor);
-        let out_token = args.request_fetch(&out_op, 0);
-
-        self.session.run(&mut args)?;
-        let output: TfTensor<f32> = args.fetch(out_token)?;
+        for (idx, tensor) in &feeds {
+            let (op, index) = &self.inputs[*idx];
+            args.add_feed(op, *index, tensor);
+        }
+        let tokens: Vec<_> = self.outputs.iter().map(|(op, index)| args.request_fetch(op, *index)).collect();
+        self.session.run(&mut args).context("TensorFlow: Session-Lauf fehlgeschlagen")?;
 
-        let arr = ArrayD::from_shape_vec(self.output_shapes[0].clone(), output.to_vec())?;
-        Ok(arr)
+        tokens
+            .into_iter()
+            .enumerate()
+            .map(|(i, token)| {
+                let output: TfTensor<f32> = args.fetch(token)?;
+                let shape: Vec<usize> = output.dims().iter().map(|&d| d as usize).collect();
+                anyhow::ensure!(
+                    shape_matches(&self.output_shapes[i], &shape),
+                    "TensorFlow: Output-Shape von '{}' passt nicht. Erwartet {:?}, bekommen {:?}",
+                    self.output_names[i], self.output_shapes[i], shape
+                );
+                Ok(ArrayD::from_shape_vec(shape, output.to_vec())?)
+            })
+            .collect()
     }
 }
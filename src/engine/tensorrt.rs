@@ -8,7 +8,7 @@
 use anyhow::{Result, Context};
 use ndarray::{ArrayD, IxDyn};
 use crate::types::Config;
-use super::Engine;
+use super::{Capabilities, Engine, Residency};
 
 /// TensorRT inference engine implementation.
 pub struct TrtEngine {
@@ -18,6 +18,7 @@ pub struct TrtEngine {
     input_names: Vec<String>,
     output_names: Vec<String>,
     output_shapes: Vec<Vec<usize>>,
+    input_shapes: Vec<Vec<usize>>,
 }
 
 impl TrtEngine {
@@ -51,6 +52,7 @@ impl TrtEngine {
             input_names: cfg.model.input_names.clone(),
             output_names: cfg.model.output_names.clone(),
             output_shapes: cfg.model.output_shapes.clone(),
+            input_shapes: cfg.model.input_shapes.clone(),
         })
     }
 }
@@ -58,6 +60,11 @@ impl TrtEngine {
 impl Engine for TrtEngine {
     fn name(&self) -> &'static str { "tensorrt" }
 
+    /// Serialized engines have fixed shapes and run on the GPU.
+    fn capabilities(&self) -> Capabilities {
+        Capabilities { residency: Residency::Device, ..Capabilities::from_shapes(&self.input_shapes, false) }
+    }
+
     /// Runs inference using the TensorRT execution context and returns the output tensor.
     fn infer_array(&mut self, input: ArrayD<f32>) -> Result<ArrayD<f32>> {
         unsafe {
@@ -69,7 +76,7 @@ impl Engine for TrtEngine {
         let mut bindings = self.engine.allocate_bindings()?;
 
         let in_name = &self.input_names[0];
-        bindings.set_input(in_name, input.as_slice().unwrap(), &shape)?;
+        bindings.set_input(in_name, input.as_standard_layout().as_slice().unwrap(), &shape)?;
 
         self.context.enqueue(&mut bindings)?;
 
@@ -0,0 +1,305 @@
+//! Sliding-window tiling for inputs larger than the model input (`[tiling]`).
+//!
+//! Volumes (`ncdhw`) often exceed the fixed input of a 3-D model. The
+//! [`TiledEngine`] cuts every sample into overlapping tiles of the model's
+//! spatial size (zero-padded at the borders), runs them through the inner
+//! engine in model-sized batches and stitches the outputs back together:
+//! dense outputs with the tile's spatial shape (segmentation) are blended
+//! where tiles overlap, all other outputs (classification) are averaged
+//! over the tiles. The same applies to high-resolution images (`nchw`)
+//! with two-dimensional tiles.
+
+use anyhow::{Context, Result};
+use ndarray::{ArrayD, ArrayViewD, Axis, IxDyn, Slice};
+
+use crate::engine::{Capabilities, Engine};
+use crate::types::{Config, TileBlend, TilingCfg};
+
+/// Tile grid over the spatial axes of one sample `[C, ...spatial]`.
+#[derive(Debug, Clone)]
+pub struct Tiler {
+    tile: Vec<usize>,
+    stride: Vec<usize>,
+    /// Blending weight of each tile position.
+    weights: ArrayD<f32>,
+}
+
+impl Tiler {
+    pub fn new(cfg: &TilingCfg) -> Result<Self> {
+        anyhow::ensure!(!cfg.tile.is_empty() && !cfg.tile.contains(&0), "tiling: ungültige Kachelgröße {:?}", cfg.tile);
+        let overlap = if cfg.overlap.is_empty() { vec![0; cfg.tile.len()] } else { cfg.overlap.clone() };
+        anyhow::ensure!(
+            overlap.len() == cfg.tile.len(),
+            "tiling: overlap {:?} passt nicht zu tile {:?}",
+            overlap,
+            cfg.tile
+        );
+        let stride = cfg
+            .tile
+            .iter()
+            .zip(&overlap)
+            .map(|(&t, &o)| {
+                anyhow::ensure!(o < t, "tiling: overlap {} muss kleiner als die Kachel {} sein", o, t);
+                Ok(t - o)
+            })
+            .collect::<Result<_>>()?;
+        let axes: Vec<Vec<f32>> = cfg.tile.iter().zip(&overlap).map(|(&t, &o)| axis_weights(cfg.blend, t, o)).collect();
+        let weights = ArrayD::from_shape_fn(IxDyn(&cfg.tile), |i| axes.iter().enumerate().map(|(a, w)| w[i[a]]).product());
+        Ok(Self { tile: cfg.tile.clone(), stride, weights })
+    }
+
+    /// Origins of all tiles covering `size`; the last tile of each axis is
+    /// aligned to the end, so no tile reaches past a large enough volume.
+    pub fn origins(&self, size: &[usize]) -> Vec<Vec<usize>> {
+        let per_axis: Vec<Vec<usize>> = size
+            .iter()
+            .zip(self.tile.iter().zip(&self.stride))
+            .map(|(&len, (&tile, &stride))| {
+                if len <= tile {
+                    return vec![0];
+                }
+                let mut starts: Vec<usize> = (0..len - tile).step_by(stride).collect();
+                starts.push(len - tile);
+                starts
+            })
+            .collect();
+        // Kartesisches Produkt der Achsen-Startpunkte
+        per_axis.iter().fold(vec![Vec::new()], |acc, starts| {
+            acc.iter().flat_map(|prefix| starts.iter().map(move |&o| [prefix.as_slice(), &[o]].concat())).collect()
+        })
+    }
+
+    /// Copies the tile at `origin` out of `sample`, zero-padded past its end.
+    pub fn cut(&self, sample: &ArrayViewD<f32>, origin: &[usize]) -> ArrayD<f32> {
+        let mut shape = vec![sample.shape()[0]];
+        shape.extend(&self.tile);
+        let mut tile = ArrayD::zeros(IxDyn(&shape));
+        let (mut src, mut dst) = (sample.view(), tile.view_mut());
+        for (axis, (&o, &t)) in origin.iter().zip(&self.tile).enumerate() {
+            let len = t.min(src.shape()[axis + 1] - o);
+            src.slice_axis_inplace(Axis(axis + 1), Slice::from(o..o + len));
+            dst.slice_axis_inplace(Axis(axis + 1), Slice::from(0..len));
+        }
+        dst.assign(&src);
+        tile
+    }
+
+    /// Combines the tile outputs `[K, ...]` of one sample with `size`.
+    pub fn stitch(&self, size: &[usize], tiles: Vec<(Vec<usize>, ArrayD<f32>)>) -> Result<ArrayD<f32>> {
+        let first = tiles.first().context("tiling: keine Kacheln")?.1.shape().to_vec();
+        let dense = first.len() == self.tile.len() + 1 && first[1..] == self.tile[..];
+        if !dense {
+            let n = tiles.len() as f32;
+            let mut sum = ArrayD::<f32>::zeros(IxDyn(&first));
+            for (_, out) in &tiles {
+                sum += out;
+            }
+            return Ok(sum / n);
+        }
+
+        let mut shape = vec![first[0]];
+        shape.extend(size);
+        let mut sum = ArrayD::<f32>::zeros(IxDyn(&shape));
+        let mut count = ArrayD::<f32>::zeros(IxDyn(size));
+        for (origin, out) in &tiles {
+            let (mut dst, mut hits, mut src, mut weights) = (sum.view_mut(), count.view_mut(), out.view(), self.weights.view());
+            for (axis, (&o, &t)) in origin.iter().zip(&self.tile).enumerate() {
+                let len = t.min(size[axis] - o);
+                dst.slice_axis_inplace(Axis(axis + 1), Slice::from(o..o + len));
+                hits.slice_axis_inplace(Axis(axis), Slice::from(o..o + len));
+                src.slice_axis_inplace(Axis(axis + 1), Slice::from(0..len));
+                weights.slice_axis_inplace(Axis(axis), Slice::from(0..len));
+            }
+            dst += &(&src * &weights);
+            hits += &weights;
+        }
+        // Überlappungen gewichtet mitteln
+        for mut channel in sum.outer_iter_mut() {
+            channel /= &count;
+        }
+        Ok(sum)
+    }
+}
+
+/// Blending weights along one tile axis.
+fn axis_weights(blend: TileBlend, tile: usize, overlap: usize) -> Vec<f32> {
+    (0..tile)
+        .map(|i| match blend {
+            TileBlend::Mean => 1.0,
+            TileBlend::Linear => {
+                let ramp = (overlap + 1) as f32;
+                ((i + 1) as f32 / ramp).min((tile - i) as f32 / ramp).min(1.0)
+            }
+            TileBlend::Gaussian => {
+                let (centre, sigma) = ((tile - 1) as f32 / 2.0, tile as f32 / 8.0);
+                // Untergrenze: Ränder ohne Nachbarkachel behalten ihren Wert
+                (-(i as f32 - centre).powi(2) / (2.0 * sigma * sigma)).exp().max(1e-3)
+            }
+        })
+        .collect()
+}
+
+/// Engine running oversized inputs tile by tile.
+pub struct TiledEngine {
+    inner: Box<dyn Engine>,
+    tiler: Tiler,
+    batch: usize,
+}
+
+impl TiledEngine {
+    pub fn new(inner: Box<dyn Engine>, tiler: Tiler, batch: usize) -> Self {
+        Self { inner, tiler, batch: batch.max(1) }
+    }
+
+    /// Wraps `engine` if `[tiling]` is configured.
+    pub fn from_config(cfg: &Config, engine: Box<dyn Engine>) -> Result<Box<dyn Engine>> {
+        let Some(tiling) = &cfg.tiling else { return Ok(engine) };
+        anyhow::ensure!(cfg.input.extra.is_empty(), "tiling: nicht mit [[input.extra]] kombinierbar");
+        let rank = cfg.input_spec().rank()?;
+        anyhow::ensure!(
+            tiling.tile.len() + 2 == rank,
+            "tiling: tile {:?} braucht {} Achsen für layout \"{}\"",
+            tiling.tile,
+            rank.saturating_sub(2),
+            cfg.input.layout
+        );
+        // Feste Batch-Achse des Modells hat Vorrang
+        let batch = match cfg.model.input_shapes.first().and_then(|s| s.first()) {
+            Some(&b) if b > 0 => b,
+            _ => tiling.batch,
+        };
+        Ok(Box::new(Self::new(engine, Tiler::new(tiling)?, batch)))
+    }
+
+    /// Runs tiles `[C, ...tile]` in batches, zero-padding the last one.
+    fn run_tiles(&mut self, tiles: &[ArrayD<f32>]) -> Result<Vec<ArrayD<f32>>> {
+        let mut outputs = Vec::with_capacity(tiles.len());
+        for group in tiles.chunks(self.batch) {
+            let padding = ArrayD::zeros(group[0].raw_dim());
+            let mut views: Vec<_> = group.iter().map(|t| t.view()).collect();
+            views.resize(self.batch, padding.view());
+            let y = self.inner.infer_array(ndarray::stack(Axis(0), &views)?)?;
+            anyhow::ensure!(y.shape().first() == Some(&self.batch), "tiling: Output {:?} ohne Batch-Achse", y.shape());
+            outputs.extend(y.outer_iter().take(group.len()).map(|o| o.to_owned()));
+        }
+        Ok(outputs)
+    }
+}
+
+impl Engine for TiledEngine {
+    fn name(&self) -> &'static str {
+        self.inner.name()
+    }
+
+    fn infer_array(&mut self, input: ArrayD<f32>) -> Result<ArrayD<f32>> {
+        anyhow::ensure!(
+            input.ndim() == self.tiler.tile.len() + 2,
+            "tiling: Input {:?} passt nicht zu tile {:?}",
+            input.shape(),
+            self.tiler.tile
+        );
+        let mut results = Vec::with_capacity(input.shape()[0]);
+        for sample in input.outer_iter() {
+            let size = sample.shape()[1..].to_vec();
+            let origins = self.tiler.origins(&size);
+            let tiles: Vec<_> = origins.iter().map(|o| self.tiler.cut(&sample, o)).collect();
+            let outputs = self.run_tiles(&tiles)?;
+            results.push(self.tiler.stitch(&size, origins.into_iter().zip(outputs).collect())?);
+        }
+        let views: Vec<_> = results.iter().map(|r| r.view()).collect();
+        Ok(ndarray::stack(Axis(0), &views)?)
+    }
+
+    fn infer_named(&mut self, inputs: Vec<(String, ArrayD<f32>)>) -> Result<Vec<ArrayD<f32>>> {
+        self.inner.infer_named(inputs)
+    }
+
+    fn capabilities(&self) -> Capabilities {
+        Capabilities { dynamic_shapes: true, ..self.inner.capabilities() }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::s;
+
+    fn tiler(tile: Vec<usize>, overlap: Vec<usize>) -> Tiler {
+        Tiler::new(&TilingCfg { tile, overlap, batch: 2, blend: TileBlend::Mean }).unwrap()
+    }
+
+    /// Segmentation stand-in: per-voxel output equal to the input.
+    struct Identity;
+
+    impl Engine for Identity {
+        fn name(&self) -> &'static str {
+            "identity"
+        }
+
+        fn infer_array(&mut self, input: ArrayD<f32>) -> Result<ArrayD<f32>> {
+            anyhow::ensure!(input.shape()[2..] == [4, 4, 4], "Kachel {:?}", input.shape());
+            Ok(input)
+        }
+    }
+
+    #[test]
+    fn test_origins_cover_volume() {
+        let t = tiler(vec![4, 4], vec![1, 0]);
+        assert_eq!(t.origins(&[10, 4]), vec![vec![0, 0], vec![3, 0], vec![6, 0]]);
+        assert_eq!(t.origins(&[2, 9]), vec![vec![0, 0], vec![0, 4], vec![0, 5]]);
+        assert!(Tiler::new(&TilingCfg { tile: vec![4], overlap: vec![4], batch: 1, blend: TileBlend::Mean }).is_err());
+    }
+
+    #[test]
+    fn test_dense_output_is_stitched() {
+        let volume = ArrayD::from_shape_fn(IxDyn(&[1, 1, 6, 5, 9]), |i| (i[2] * 100 + i[3] * 10 + i[4]) as f32);
+        let mut engine = TiledEngine::new(Box::new(Identity), tiler(vec![4, 4, 4], vec![2, 1, 1]), 2);
+        let y = engine.infer_array(volume.clone()).unwrap();
+        assert_eq!(y, volume);
+
+        // Kleiner als eine Kachel: Null-Padding, wieder abgeschnitten
+        let small = volume.slice(s![.., .., ..2, ..3, ..3]).to_owned().into_dyn();
+        assert_eq!(engine.infer_array(small.clone()).unwrap(), small);
+    }
+
+    #[test]
+    fn test_global_output_is_averaged() {
+        let t = tiler(vec![2], vec![]);
+        let tiles = vec![(vec![0], ArrayD::from_elem(IxDyn(&[3]), 1.0)), (vec![2], ArrayD::from_elem(IxDyn(&[3]), 3.0))];
+        assert_eq!(t.stitch(&[4], tiles).unwrap(), ArrayD::from_elem(IxDyn(&[3]), 2.0));
+    }
+
+    #[test]
+    fn test_blending_weights_overlap() {
+        let tiles = || vec![(vec![0], ArrayD::zeros(IxDyn(&[1, 4]))), (vec![2], ArrayD::ones(IxDyn(&[1, 4])))];
+        let mean = tiler(vec![4], vec![2]).stitch(&[6], tiles()).unwrap();
+        assert_eq!(mean.iter().copied().collect::<Vec<_>>(), vec![0.0, 0.0, 0.5, 0.5, 1.0, 1.0]);
+
+        let linear = Tiler::new(&TilingCfg { tile: vec![4], overlap: vec![2], batch: 1, blend: TileBlend::Linear }).unwrap();
+        let y = linear.stitch(&[6], tiles()).unwrap();
+        let expected = [0.0, 0.0, 1.0 / 3.0, 2.0 / 3.0, 1.0, 1.0];
+        assert!(y.iter().zip(expected).all(|(a, b)| (a - b).abs() < 1e-6), "{:?}", y);
+    }
+
+    #[test]
+    fn test_image_tiles_with_gaussian_blend() {
+        let cfg = TilingCfg { tile: vec![4, 4], overlap: vec![2, 2], batch: 3, blend: TileBlend::Gaussian };
+        let mut engine = TiledEngine::new(Box::new(Identity2d), Tiler::new(&cfg).unwrap(), 3);
+        let image = ArrayD::from_shape_fn(IxDyn(&[2, 3, 9, 7]), |i| (i[1] * 100 + i[2] * 10 + i[3]) as f32);
+        let y = engine.infer_array(image.clone()).unwrap();
+        assert!(y.iter().zip(image.iter()).all(|(a, b)| (a - b).abs() < 1e-3));
+    }
+
+    struct Identity2d;
+
+    impl Engine for Identity2d {
+        fn name(&self) -> &'static str {
+            "identity"
+        }
+
+        fn infer_array(&mut self, input: ArrayD<f32>) -> Result<ArrayD<f32>> {
+            anyhow::ensure!(input.shape() == [3, 3, 4, 4], "Kachel-Batch {:?}", input.shape());
+            Ok(input)
+        }
+    }
+}
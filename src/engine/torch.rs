@@ -2,17 +2,35 @@
 //!
 //! Loads a TorchScript `CModule` and performs inference on CPU or CUDA devices.
 //! Input and output names/shapes are taken from the runtime configuration.
+//!
+//! `[model.torch]` selects the method to call (default `forward`) and how
+//! inputs are passed: positionally in `input_names` order, or as a single
+//! `Dict[str, Tensor]`. A tensor result is the first output; tuple and list
+//! results map to `output_names` in order, dict results by name.
+//!
+//! The module runs in eval mode under `no_grad`: `tch` has no binding for
+//! libtorch's `InferenceMode` guard, `no_grad` is the closest it offers.
+//! Freezing and `optimize_for_inference` have no `tch` binding either and
+//! must be applied at export; `require_frozen` only checks that the module
+//! was frozen. The graph executor, fuser and thread settings of
+//! `[model.torch]` are process-wide libtorch switches.
 
 use anyhow::{Context, Result};
 use ndarray::ArrayD;
-use tch::{CModule, Device as TchDevice, Tensor, kind::Kind};
+use tch::{CModule, Device as TchDevice, IValue, Tensor, kind::Kind};
 use crate::types::Config;
-use super::Engine;
+use super::{shape_matches, Capabilities, Engine, Residency};
 
 /// TorchScript inference engine.
 pub struct TorchEngine {
     module: CModule,
     device: TchDevice,
+    /// Method called for inference.
+    method: String,
+    /// Pass the inputs as one `Dict[str, Tensor]` instead of positionally.
+    dict_inputs: bool,
+    /// Pass 4D inputs with channels-last strides.
+    channels_last: bool,
     input_names: Vec<String>,
     output_names: Vec<String>,
     input_shapes: Vec<Vec<usize>>,
@@ -29,9 +47,50 @@ impl TorchEngine {
             _ => TchDevice::Cpu,
         };
 
+        // Aufrufkonvention
+        let torch = cfg.model.torch.clone().unwrap_or_default();
+        let dict_inputs = match torch.inputs.as_str() {
+            "positional" => false,
+            "dict" => true,
+            other => anyhow::bail!("Unbekanntes inputs '{}' in [model.torch] (erwartet: positional, dict)", other),
+        };
+
+        // JIT- und Thread-Einstellungen gelten prozessweit
+        tch::jit::set_graph_executor_optimize(torch.graph_executor_optimize);
+        if let Some(enabled) = torch.tensor_expr_fuser {
+            tch::jit::set_tensor_expr_fuser_enabled(enabled);
+        }
+        if let Some(threads) = torch.intra_op_threads {
+            tch::set_num_threads(threads as i32);
+        }
+        if let Some(threads) = torch.inter_op_threads {
+            // Nur vor der ersten parallelen Operation änderbar
+            if tch::get_num_interop_threads() != threads as i32 {
+                tch::set_num_interop_threads(threads as i32);
+            }
+        }
+
+        // Reproduzierbarkeit: feste Seeds, kein cuDNN-Autotuning
+        if let Some(repro) = &cfg.reproducibility {
+            tch::manual_seed(repro.seed as i64);
+            tch::Cuda::manual_seed_all(repro.seed);
+            tch::Cuda::cudnn_set_benchmark(false);
+        }
+
         // TorchScript Modell laden
-        let module = CModule::load_on_device(&cfg.model.model_path, device)
+        let mut module = CModule::load_on_device(&cfg.model.model_path, device)
             .with_context(|| format!("TorchScript: Modell laden fehlgeschlagen: {}", cfg.model.model_path))?;
+        // Dropout/BatchNorm im Inferenzmodus
+        module.set_eval();
+        if torch.require_frozen {
+            // Eingefrorene Module haben ihre Gewichte als Konstanten, keine Parameter
+            let parameters = module.named_parameters().context("TorchScript: Parameter nicht lesbar")?;
+            anyhow::ensure!(
+                parameters.is_empty(),
+                "TorchScript: Modell ist nicht eingefroren ({} Parameter); mit torch.jit.freeze exportieren",
+                parameters.len()
+            );
+        }
 
         // Konsistenz-Check
         anyhow::ensure!(
@@ -46,43 +105,157 @@ impl TorchEngine {
         Ok(Self {
             module,
             device,
+            method: torch.method,
+            dict_inputs,
+            channels_last: torch.channels_last,
             input_names: cfg.model.input_names.clone(),
             output_names: cfg.model.output_names.clone(),
             input_shapes: cfg.model.input_shapes.clone(),
             output_shapes: cfg.model.output_shapes.clone(),
         })
     }
+
+    /// Copies an input array into a float tensor on the engine's device.
+    fn to_tensor(&self, input: ArrayD<f32>) -> Tensor {
+        let shape: Vec<i64> = input.shape().iter().map(|&d| d as i64).collect();
+        // Kacheln und Ausschnitte (z. B. aus Volumen) sind nicht zusammenhängend
+        let input = input.as_standard_layout();
+        let tensor = Tensor::from_slice(input.as_slice().unwrap())
+            .to_device(self.device)
+            .to_kind(Kind::Float)
+            .reshape(shape.as_slice());
+        if self.channels_last && shape.len() == 4 {
+            // NCHW-Shape mit NHWC-Strides (torch.channels_last)
+            return tensor.permute([0, 2, 3, 1]).contiguous().permute([0, 3, 1, 2]);
+        }
+        tensor
+    }
+
+    /// Splits the method's result into tensors in `output_names` order.
+    fn outputs(&self, result: IValue) -> Result<Vec<Tensor>> {
+        let tensor = |value: IValue, what: &str| match value {
+            IValue::Tensor(t) => Ok(t),
+            other => anyhow::bail!("Torch: Output {} ist kein Tensor: {:?}", what, other),
+        };
+        let outputs = match result {
+            IValue::Tensor(t) => vec![t],
+            IValue::TensorList(list) => list,
+            IValue::Tuple(items) | IValue::GenericList(items) => items
+                .into_iter()
+                .enumerate()
+                .map(|(i, item)| tensor(item, &i.to_string()))
+                .collect::<Result<_>>()?,
+            IValue::GenericDict(entries) => {
+                // Dict-Ausgaben über die Schlüssel den output_names zuordnen
+                let mut entries: Vec<(String, IValue)> = entries
+                    .into_iter()
+                    .map(|(key, value)| match key {
+                        IValue::String(key) => Ok((key, value)),
+                        other => anyhow::bail!("Torch: Dict-Schlüssel ist kein String: {:?}", other),
+                    })
+                    .collect::<Result<_>>()?;
+                return self
+                    .output_names
+                    .iter()
+                    .map(|name| {
+                        let idx = entries
+                            .iter()
+                            .position(|(key, _)| key == name)
+                            .with_context(|| format!("Torch: Output '{}' fehlt im Ergebnis-Dict", name))?;
+                        tensor(entries.swap_remove(idx).1, &format!("'{}'", name))
+                    })
+                    .collect();
+            }
+            other => anyhow::bail!("Torch: Ergebnis von '{}' wird nicht unterstützt: {:?}", self.method, other),
+        };
+        anyhow::ensure!(
+            outputs.len() >= self.output_names.len().max(1),
+            "Torch: '{}' liefert {} Outputs, konfiguriert sind {}",
+            self.method, outputs.len(), self.output_names.len()
+        );
+        Ok(outputs)
+    }
 }
 
 impl Engine for TorchEngine {
     fn name(&self) -> &'static str { "torch" }
 
-    /// Runs inference using the loaded TorchScript module and returns the output tensor.
-    fn infer_array(&self, input: ArrayD<f32>) -> Result<ArrayD<f32>> {
-        // Input-Shape validieren
-        let expected = &self.input_shapes[0];
-        anyhow::ensure!(
-            input.shape() == expected.as_slice(),
-            "Torch: Input-Shape passt nicht. Erwartet {:?}, bekommen {:?}",
-            expected, input.shape()
-        );
+    fn capabilities(&self) -> Capabilities {
+        let residency = if self.device == TchDevice::Cpu { Residency::Host } else { Residency::Device };
+        Capabilities { named_inputs: true, residency, ..Capabilities::from_shapes(&self.input_shapes, false) }
+    }
 
-        // Input nach Tensor
-        let tensor = Tensor::of_slice(input.as_slice().unwrap())
-            .to_device(self.device)
-            .to_kind(Kind::Float)
-            .reshape(&expected.iter().map(|&d| d as i64).collect::<Vec<_>>());
+    /// Runs inference using the loaded TorchScript module and returns the first output.
+    fn infer_array(&mut self, input: ArrayD<f32>) -> Result<ArrayD<f32>> {
+        let name = self.input_names.first().cloned().unwrap_or_default();
+        let outputs = self.infer_named(vec![(name, input)])?;
+        Ok(outputs.into_iter().next().unwrap())
+    }
 
-        // Forward Pass
-        let output = self.module.forward_ts(&[tensor])?;
+    /// Calls the configured method with all inputs; returns the outputs in
+    /// `output_names` order.
+    fn infer_named(&mut self, inputs: Vec<(String, ArrayD<f32>)>) -> Result<Vec<ArrayD<f32>>> {
+        // Inputs in die Reihenfolge von input_names bringen
+        let mut ordered: Vec<Option<ArrayD<f32>>> = vec![None; self.input_names.len().max(1)];
+        for (name, array) in inputs {
+            let idx = if self.input_names.is_empty() {
+                0
+            } else {
+                self.input_names
+                    .iter()
+                    .position(|n| *n == name)
+                    .with_context(|| format!("Torch: unbekannter Input '{}'", name))?
+            };
+            if let Some(expected) = self.input_shapes.get(idx) {
+                anyhow::ensure!(
+                    shape_matches(expected, array.shape()),
+                    "Torch: Input-Shape von '{}' passt nicht. Erwartet {:?}, bekommen {:?}",
+                    name, expected, array.shape()
+                );
+            }
+            ordered[idx] = Some(array);
+        }
+
+        let mut tensors = Vec::with_capacity(ordered.len());
+        for (idx, array) in ordered.into_iter().enumerate() {
+            let array = array.with_context(|| {
+                format!("Torch: Input '{}' fehlt", self.input_names.get(idx).map(String::as_str).unwrap_or_default())
+            })?;
+            tensors.push(self.to_tensor(array));
+        }
+
+        let args: Vec<IValue> = if self.dict_inputs {
+            let entries = self
+                .input_names
+                .iter()
+                .zip(tensors)
+                .map(|(name, t)| (IValue::String(name.clone()), IValue::Tensor(t)))
+                .collect();
+            vec![IValue::GenericDict(entries)]
+        } else {
+            tensors.into_iter().map(IValue::Tensor).collect()
+        };
 
-        // TorchScript gibt oft Tuple zurück, aber `forward_ts` gibt Vec<Tensor>
-        let out0 = output[0].to_device(TchDevice::Cpu);
-        let out_vec: Vec<f32> = Vec::<f32>::from(out0);
+        let result = tch::no_grad(|| self.module.method_is(&self.method, &args))
+            .with_context(|| format!("Torch: Methode '{}' fehlgeschlagen", self.method))?;
 
-        // Output-Shape aus Config übernehmen
-        let out_shape = self.output_shapes[0].clone();
-        let arr = ArrayD::from_shape_vec(out_shape, out_vec)?;
-        Ok(arr)
+        self.outputs(result)?
+            .into_iter()
+            .take(self.output_names.len().max(1))
+            .enumerate()
+            .map(|(i, t)| {
+                let t = t.to_device(TchDevice::Cpu).to_kind(Kind::Float).contiguous();
+                let shape: Vec<usize> = t.size().iter().map(|&d| d as usize).collect();
+                if let Some(expected) = self.output_shapes.get(i) {
+                    anyhow::ensure!(
+                        shape_matches(expected, &shape),
+                        "Torch: Output-Shape von '{}' passt nicht. Erwartet {:?}, bekommen {:?}",
+                        self.output_names[i], expected, shape
+                    );
+                }
+                let data = Vec::<f32>::try_from(&t.flatten(0, -1))?;
+                Ok(ArrayD::from_shape_vec(shape, data)?)
+            })
+            .collect()
     }
 }
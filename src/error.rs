@@ -0,0 +1,328 @@
+//! Errors reported to clients ([`OmniError`]).
+//!
+//! Every error a client can see has a stable `code` (e.g. `NO_WORKER`) to
+//! match on, an HTTP status and a gRPC status code. Its message is
+//! available in German and English: stored error results use
+//! `[server] language`, the HTTP API follows `Accept-Language`. Details
+//! passed through from checks (e.g. input validation) keep their wording.
+//! Internal errors stay `anyhow` errors and surface as `INTERNAL`.
+
+use std::fmt;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+use axum::http::StatusCode;
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+/// Language of error messages.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Language {
+    #[default]
+    De,
+    En,
+}
+
+static LANGUAGE: AtomicU8 = AtomicU8::new(0);
+
+/// Sets the language of messages without a negotiated one.
+pub fn set_language(language: Language) {
+    LANGUAGE.store(language as u8, Ordering::Relaxed);
+}
+
+/// Configured message language.
+pub fn language() -> Language {
+    match LANGUAGE.load(Ordering::Relaxed) {
+        0 => Language::De,
+        _ => Language::En,
+    }
+}
+
+impl Language {
+    /// First supported language of an `Accept-Language` header, by weight.
+    pub fn negotiate(header: &str) -> Option<Self> {
+        let mut ranges: Vec<(f32, &str)> = header
+            .split(',')
+            .map(|range| {
+                let mut parts = range.split(';');
+                let tag = parts.next().unwrap_or("").trim();
+                let q = parts.find_map(|p| p.trim().strip_prefix("q=")?.parse().ok()).unwrap_or(1.0);
+                (q, tag)
+            })
+            .filter(|(q, _)| *q > 0.0)
+            .collect();
+        // Stabil sortiert: gleiche Gewichte behalten die Reihenfolge des Headers
+        ranges.sort_by(|a, b| b.0.total_cmp(&a.0));
+        ranges.into_iter().find_map(|(_, tag)| match tag.split('-').next()?.to_lowercase().as_str() {
+            "de" => Some(Self::De),
+            "en" => Some(Self::En),
+            _ => None,
+        })
+    }
+}
+
+/// Error reported to clients.
+#[derive(Debug, Clone, PartialEq)]
+pub enum OmniError {
+    /// Job input rejected by `[validation]`; holds the failed check.
+    InvalidInput(String),
+    /// Required request header missing or malformed.
+    InvalidHeader(&'static str),
+    MissingJobId,
+    InvalidShape(Vec<usize>),
+    InvalidChunkSize { chunk_bytes: usize, max: usize },
+    ChunkOutOfRange { index: usize, chunks: usize },
+    ChunkLength { index: usize, actual: usize, expected: usize },
+    ChecksumMismatch { index: usize, actual: u32, expected: u32 },
+    UploadTooLarge { shape: Vec<usize>, max_bytes: usize },
+    UploadExists(String),
+    TooManyUploads(usize),
+    UploadNotFound(String),
+    UploadIncomplete { id: String, missing: Vec<usize> },
+    /// Referenced input blob not cached (expired or never uploaded).
+    BlobNotFound(String),
+    BlobHashMismatch { expected: String, actual: String },
+    ResultNotFound(String),
+    /// Request for a model this runtime does not serve.
+    ModelNotFound(String),
+    TensorNotChunked(String),
+    RangeNotSatisfiable,
+    /// Result store (Redis) not reachable; holds the cause.
+    StoreUnavailable(String),
+    NoWorker,
+    /// Input queue full; the client should retry later.
+    QueueFull,
+    ShuttingDown,
+    OutputNonFinite,
+    Timeout { id: String, secs: f64 },
+    /// Any other failure; holds the error chain.
+    Internal(String),
+}
+
+impl OmniError {
+    /// Stable identifier, unchanged across releases and languages.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::InvalidInput(_) => "INVALID_INPUT",
+            Self::InvalidHeader(_) => "INVALID_HEADER",
+            Self::MissingJobId => "MISSING_JOB_ID",
+            Self::InvalidShape(_) => "INVALID_SHAPE",
+            Self::InvalidChunkSize { .. } => "INVALID_CHUNK_SIZE",
+            Self::ChunkOutOfRange { .. } => "CHUNK_OUT_OF_RANGE",
+            Self::ChunkLength { .. } => "CHUNK_LENGTH",
+            Self::ChecksumMismatch { .. } => "CHECKSUM_MISMATCH",
+            Self::UploadTooLarge { .. } => "UPLOAD_TOO_LARGE",
+            Self::UploadExists(_) => "UPLOAD_EXISTS",
+            Self::TooManyUploads(_) => "TOO_MANY_UPLOADS",
+            Self::UploadNotFound(_) => "UPLOAD_NOT_FOUND",
+            Self::UploadIncomplete { .. } => "UPLOAD_INCOMPLETE",
+            Self::BlobNotFound(_) => "BLOB_NOT_FOUND",
+            Self::BlobHashMismatch { .. } => "BLOB_HASH_MISMATCH",
+            Self::ResultNotFound(_) => "RESULT_NOT_FOUND",
+            Self::ModelNotFound(_) => "MODEL_NOT_FOUND",
+            Self::TensorNotChunked(_) => "TENSOR_NOT_CHUNKED",
+            Self::RangeNotSatisfiable => "RANGE_NOT_SATISFIABLE",
+            Self::StoreUnavailable(_) => "STORE_UNAVAILABLE",
+            Self::NoWorker => "NO_WORKER",
+            Self::QueueFull => "QUEUE_FULL",
+            Self::ShuttingDown => "SHUTTING_DOWN",
+            Self::OutputNonFinite => "OUTPUT_NON_FINITE",
+            Self::Timeout { .. } => "TIMEOUT",
+            Self::Internal(_) => "INTERNAL",
+        }
+    }
+
+    /// HTTP status of the error.
+    pub fn status(&self) -> StatusCode {
+        match self {
+            Self::InvalidInput(_)
+            | Self::InvalidHeader(_)
+            | Self::MissingJobId
+            | Self::InvalidShape(_)
+            | Self::InvalidChunkSize { .. }
+            | Self::ChunkOutOfRange { .. }
+            | Self::ChunkLength { .. } => StatusCode::BAD_REQUEST,
+            Self::ChecksumMismatch { .. } | Self::BlobHashMismatch { .. } => StatusCode::UNPROCESSABLE_ENTITY,
+            Self::UploadTooLarge { .. } => StatusCode::PAYLOAD_TOO_LARGE,
+            Self::UploadExists(_) | Self::UploadIncomplete { .. } => StatusCode::CONFLICT,
+            Self::TooManyUploads(_) => StatusCode::TOO_MANY_REQUESTS,
+            Self::UploadNotFound(_)
+            | Self::ResultNotFound(_)
+            | Self::ModelNotFound(_)
+            | Self::TensorNotChunked(_)
+            | Self::BlobNotFound(_) => StatusCode::NOT_FOUND,
+            Self::RangeNotSatisfiable => StatusCode::RANGE_NOT_SATISFIABLE,
+            Self::StoreUnavailable(_) | Self::NoWorker | Self::QueueFull | Self::ShuttingDown => StatusCode::SERVICE_UNAVAILABLE,
+            Self::Timeout { .. } => StatusCode::GATEWAY_TIMEOUT,
+            Self::OutputNonFinite | Self::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    /// Canonical gRPC status code (`google.rpc.Code`).
+    pub fn grpc_code(&self) -> i32 {
+        match self {
+            Self::InvalidInput(_)
+            | Self::InvalidHeader(_)
+            | Self::MissingJobId
+            | Self::InvalidShape(_)
+            | Self::InvalidChunkSize { .. }
+            | Self::ChunkLength { .. } => 3, // INVALID_ARGUMENT
+            Self::Timeout { .. } => 4, // DEADLINE_EXCEEDED
+            Self::UploadNotFound(_)
+            | Self::ResultNotFound(_)
+            | Self::ModelNotFound(_)
+            | Self::TensorNotChunked(_)
+            | Self::BlobNotFound(_) => 5, // NOT_FOUND
+            Self::UploadExists(_) => 6, // ALREADY_EXISTS
+            Self::UploadTooLarge { .. } | Self::TooManyUploads(_) | Self::QueueFull => 8, // RESOURCE_EXHAUSTED
+            Self::UploadIncomplete { .. } => 9, // FAILED_PRECONDITION
+            Self::ChunkOutOfRange { .. } | Self::RangeNotSatisfiable => 11, // OUT_OF_RANGE
+            Self::OutputNonFinite | Self::Internal(_) => 13, // INTERNAL
+            Self::StoreUnavailable(_) | Self::NoWorker | Self::ShuttingDown => 14, // UNAVAILABLE
+            Self::ChecksumMismatch { .. } | Self::BlobHashMismatch { .. } => 15, // DATA_LOSS
+        }
+    }
+
+    /// Message in `language`.
+    pub fn message(&self, language: Language) -> String {
+        let de = language == Language::De;
+        match self {
+            Self::InvalidInput(detail) if de => format!("ungültige Eingabe: {}", detail),
+            Self::InvalidInput(detail) => format!("invalid input: {}", detail),
+            Self::InvalidHeader(name) if de => format!("Header {} fehlt oder ist ungültig", name),
+            Self::InvalidHeader(name) => format!("header {} is missing or invalid", name),
+            Self::MissingJobId if de => "Upload ohne Job-ID".to_string(),
+            Self::MissingJobId => "upload without job id".to_string(),
+            Self::InvalidShape(shape) if de => format!("ungültige Shape {:?}", shape),
+            Self::InvalidShape(shape) => format!("invalid shape {:?}", shape),
+            Self::InvalidChunkSize { chunk_bytes, max } if de => {
+                format!("chunk_bytes {} muss ein Vielfaches von 4 bis {} sein", chunk_bytes, max)
+            }
+            Self::InvalidChunkSize { chunk_bytes, max } => {
+                format!("chunk_bytes {} must be a multiple of 4 up to {}", chunk_bytes, max)
+            }
+            Self::ChunkOutOfRange { index, chunks } if de => format!("Chunk {} außerhalb von 0..{}", index, chunks),
+            Self::ChunkOutOfRange { index, chunks } => format!("chunk {} outside of 0..{}", index, chunks),
+            Self::ChunkLength { index, actual, expected } if de => format!("Chunk {}: {} statt {} Bytes", index, actual, expected),
+            Self::ChunkLength { index, actual, expected } => format!("chunk {}: {} instead of {} bytes", index, actual, expected),
+            Self::ChecksumMismatch { index, actual, expected } if de => {
+                format!("Chunk {}: Prüfsumme {:08x} statt {:08x}", index, actual, expected)
+            }
+            Self::ChecksumMismatch { index, actual, expected } => {
+                format!("chunk {}: checksum {:08x} instead of {:08x}", index, actual, expected)
+            }
+            Self::UploadTooLarge { shape, max_bytes } if de => format!("Tensor {:?} überschreitet max_bytes {}", shape, max_bytes),
+            Self::UploadTooLarge { shape, max_bytes } => format!("tensor {:?} exceeds max_bytes {}", shape, max_bytes),
+            Self::UploadExists(id) if de => format!("Upload '{}' existiert bereits", id),
+            Self::UploadExists(id) => format!("upload '{}' already exists", id),
+            Self::TooManyUploads(n) if de => format!("bereits {} offene Uploads", n),
+            Self::TooManyUploads(n) => format!("{} uploads already pending", n),
+            Self::UploadNotFound(id) if de => format!("Kein Upload '{}'", id),
+            Self::UploadNotFound(id) => format!("no upload '{}'", id),
+            Self::UploadIncomplete { id, missing } if de => {
+                format!("Upload '{}': {} Chunk(s) fehlen: {:?}", id, missing.len(), missing)
+            }
+            Self::UploadIncomplete { id, missing } => format!("upload '{}': {} chunk(s) missing: {:?}", id, missing.len(), missing),
+            Self::BlobNotFound(hash) if de => format!("Kein Blob {}, erneut hochladen", hash),
+            Self::BlobNotFound(hash) => format!("no blob {}, upload it again", hash),
+            Self::BlobHashMismatch { expected, actual } if de => format!("Blob hat SHA-256 {} statt {}", actual, expected),
+            Self::BlobHashMismatch { expected, actual } => format!("blob has SHA-256 {} instead of {}", actual, expected),
+            Self::ResultNotFound(id) if de => format!("Kein Ergebnis für Job '{}'", id),
+            Self::ResultNotFound(id) => format!("no result for job '{}'", id),
+            Self::ModelNotFound(name) if de => format!("Modell '{}' wird nicht bereitgestellt", name),
+            Self::ModelNotFound(name) => format!("model '{}' is not served", name),
+            Self::TensorNotChunked(id) if de => format!("Tensor von Job '{}' wurde nicht in Chunks gespeichert", id),
+            Self::TensorNotChunked(id) => format!("tensor of job '{}' was not stored in chunks", id),
+            Self::RangeNotSatisfiable if de => "Bereich liegt außerhalb des Tensors".to_string(),
+            Self::RangeNotSatisfiable => "range lies outside of the tensor".to_string(),
+            Self::StoreUnavailable(cause) if de => format!("Ergebnis-Speicher nicht erreichbar: {}", cause),
+            Self::StoreUnavailable(cause) => format!("result store unreachable: {}", cause),
+            Self::NoWorker if de => "kein Worker verfügbar".to_string(),
+            Self::NoWorker => "no worker available".to_string(),
+            Self::QueueFull if de => "Eingangs-Queue voll, später erneut versuchen".to_string(),
+            Self::QueueFull => "input queue full, retry later".to_string(),
+            Self::ShuttingDown if de => "Runtime nimmt keine Jobs mehr an".to_string(),
+            Self::ShuttingDown => "runtime no longer accepts jobs".to_string(),
+            Self::OutputNonFinite if de => "Output enthält NaN/Inf".to_string(),
+            Self::OutputNonFinite => "output contains NaN/Inf".to_string(),
+            Self::Timeout { id, secs } if de => format!("Kein Ergebnis für Job '{}' nach {:.1} s", id, secs),
+            Self::Timeout { id, secs } => format!("no result for job '{}' after {:.1} s", id, secs),
+            Self::Internal(detail) if de => format!("interner Fehler: {}", detail),
+            Self::Internal(detail) => format!("internal error: {}", detail),
+        }
+    }
+
+    /// Client error from an internal one: an `OmniError` in the chain is
+    /// kept, anything else becomes `INTERNAL`.
+    pub fn from_anyhow(e: &anyhow::Error) -> Self {
+        match e.chain().find_map(|cause| cause.downcast_ref::<Self>()) {
+            Some(err) => err.clone(),
+            None => Self::Internal(format!("{:#}", e)),
+        }
+    }
+
+    /// Fields of an error result: `error` (message) and `code`.
+    pub fn to_json(&self) -> Value {
+        json!({ "error": self.to_string(), "code": self.code() })
+    }
+
+    /// RFC 9457 problem details (`application/problem+json`). `error`
+    /// repeats `detail` for clients of the earlier `{"error": ...}` body.
+    pub fn to_problem(&self, language: Language) -> Value {
+        let status = self.status();
+        let detail = self.message(language);
+        json!({
+            "type": format!("urn:omniengine:error:{}", self.code().to_lowercase()),
+            "title": status.canonical_reason().unwrap_or(""),
+            "status": status.as_u16(),
+            "detail": detail,
+            "code": self.code(),
+            "error": detail,
+        })
+    }
+}
+
+impl fmt::Display for OmniError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message(language()))
+    }
+}
+
+impl std::error::Error for OmniError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_codes_and_messages() {
+        let err = OmniError::ResultNotFound("job-1".to_string());
+        assert_eq!(err.code(), "RESULT_NOT_FOUND");
+        assert_eq!(err.status(), StatusCode::NOT_FOUND);
+        assert_eq!(err.grpc_code(), 5);
+        assert_eq!(err.message(Language::De), "Kein Ergebnis für Job 'job-1'");
+        assert_eq!(err.message(Language::En), "no result for job 'job-1'");
+
+        let problem = OmniError::NoWorker.to_problem(Language::En);
+        assert_eq!(problem["status"], 503);
+        assert_eq!(problem["code"], "NO_WORKER");
+        assert_eq!(problem["type"], "urn:omniengine:error:no_worker");
+        assert_eq!(problem["detail"], "no worker available");
+    }
+
+    #[test]
+    fn test_from_anyhow_keeps_typed_error() {
+        let e = anyhow::Error::new(OmniError::ShuttingDown).context("Job nicht eingereiht");
+        assert_eq!(OmniError::from_anyhow(&e), OmniError::ShuttingDown);
+        let e = anyhow::anyhow!("Datei fehlt").context("Modell nicht geladen");
+        assert_eq!(OmniError::from_anyhow(&e), OmniError::Internal("Modell nicht geladen: Datei fehlt".to_string()));
+    }
+
+    #[test]
+    fn test_negotiate_language() {
+        assert_eq!(Language::negotiate("de-DE,de;q=0.9,en;q=0.8"), Some(Language::De));
+        assert_eq!(Language::negotiate("fr, en-US;q=0.7, de;q=0.5"), Some(Language::En));
+        assert_eq!(Language::negotiate("de;q=0, en"), Some(Language::En));
+        assert_eq!(Language::negotiate("fr"), None);
+    }
+}
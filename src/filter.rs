@@ -0,0 +1,369 @@
+//! Output post-filtering with a small expression language (`[filter]`).
+//!
+//! An expression is a chain of stages separated by `|`, applied to the
+//! arrays of a formatted result before it is stored:
+//!
+//! * a predicate keeps matching items, e.g. `score > 0.5 and label != 'cat'`
+//!   (comparisons `> >= < <= == !=`, `and`/`or`/`not`, parentheses)
+//! * `topk(N)` keeps the `N` items with the highest `score`, `topk(N, key)`
+//!   sorts by another field
+//!
+//! Identifiers name fields of object items; items that are plain numbers
+//! are addressed as `value`. Without `field`, all top-level arrays of
+//! objects in the result are filtered (e.g. `detections`, `top_k`).
+
+use anyhow::{bail, Context, Result};
+use ndarray::ArrayViewD;
+use serde_json::Value;
+use std::cmp::Ordering;
+use std::sync::Arc;
+
+use crate::pipeline::OutputFormatter;
+use crate::types::FilterCfg;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    Num(f64),
+    Str(String),
+    Op(&'static str),
+    LParen,
+    RParen,
+    Comma,
+    Pipe,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Literal {
+    Num(f64),
+    Str(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Expr {
+    Cmp(String, &'static str, Literal),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Stage {
+    Where(Expr),
+    TopK(usize, String),
+}
+
+/// Parsed filter expression with its target field.
+#[derive(Debug, Clone)]
+pub struct OutputFilter {
+    field: Option<String>,
+    stages: Vec<Stage>,
+}
+
+impl OutputFilter {
+    pub fn new(cfg: &FilterCfg) -> Result<Self> {
+        let stages = parse(&cfg.expr).with_context(|| format!("Ungültiger Filter-Ausdruck '{}'", cfg.expr))?;
+        Ok(Self { field: cfg.field.clone(), stages })
+    }
+
+    /// Filters the configured array (or all arrays of objects) of `result`.
+    pub fn apply(&self, result: &mut Value) {
+        let Value::Object(fields) = result else { return };
+        for (name, value) in fields.iter_mut() {
+            let Value::Array(items) = value else { continue };
+            let selected = match &self.field {
+                Some(field) => field == name,
+                None => items.first().is_some_and(Value::is_object),
+            };
+            if selected {
+                self.apply_items(items);
+            }
+        }
+    }
+
+    fn apply_items(&self, items: &mut Vec<Value>) {
+        for stage in &self.stages {
+            match stage {
+                Stage::Where(expr) => items.retain(|item| eval(expr, item)),
+                Stage::TopK(k, key) => {
+                    items.sort_by(|a, b| {
+                        let (a, b) = (number(field(a, key)), number(field(b, key)));
+                        b.partial_cmp(&a).unwrap_or(Ordering::Equal)
+                    });
+                    items.truncate(*k);
+                }
+            }
+        }
+    }
+}
+
+/// Output formatter applying an [`OutputFilter`] to another formatter's result.
+pub struct FilteredOutput {
+    inner: Arc<dyn OutputFormatter>,
+    filter: OutputFilter,
+}
+
+impl FilteredOutput {
+    pub fn new(inner: Arc<dyn OutputFormatter>, filter: OutputFilter) -> Self {
+        Self { inner, filter }
+    }
+}
+
+impl OutputFormatter for FilteredOutput {
+    fn format(&self, output: ArrayViewD<f32>) -> Result<Value> {
+        let mut result = self.inner.format(output)?;
+        self.filter.apply(&mut result);
+        Ok(result)
+    }
+}
+
+fn field<'a>(item: &'a Value, name: &str) -> Option<&'a Value> {
+    match item {
+        Value::Object(fields) => fields.get(name),
+        Value::Number(_) if name == "value" => Some(item),
+        _ => None,
+    }
+}
+
+fn number(value: Option<&Value>) -> f64 {
+    value.and_then(Value::as_f64).unwrap_or(f64::NEG_INFINITY)
+}
+
+fn eval(expr: &Expr, item: &Value) -> bool {
+    match expr {
+        Expr::And(a, b) => eval(a, item) && eval(b, item),
+        Expr::Or(a, b) => eval(a, item) || eval(b, item),
+        Expr::Not(a) => !eval(a, item),
+        Expr::Cmp(name, op, lit) => {
+            let ordering = match (field(item, name), lit) {
+                (Some(Value::Number(n)), Literal::Num(x)) => n.as_f64().and_then(|n| n.partial_cmp(x)),
+                (Some(Value::String(s)), Literal::Str(x)) => Some(s.as_str().cmp(x.as_str())),
+                (Some(Value::Bool(b)), Literal::Str(x)) => Some(b.to_string().as_str().cmp(x.as_str())),
+                _ => None,
+            };
+            // Fehlende Felder oder Typkonflikte erfüllen nur `!=`
+            let Some(ordering) = ordering else { return *op == "!=" };
+            match *op {
+                ">" => ordering == Ordering::Greater,
+                ">=" => ordering != Ordering::Less,
+                "<" => ordering == Ordering::Less,
+                "<=" => ordering != Ordering::Greater,
+                "==" => ordering == Ordering::Equal,
+                _ => ordering != Ordering::Equal,
+            }
+        }
+    }
+}
+
+fn tokenize(src: &str) -> Result<Vec<Token>> {
+    let chars: Vec<char> = src.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let next = chars.get(i + 1).copied();
+        match c {
+            ' ' | '\t' | '\n' => i += 1,
+            '(' | ')' | ',' | '|' | '&' => {
+                // `||` und `&&` als Alternativen zu `or` und `and`
+                let (token, len) = match (c, next) {
+                    ('|', Some('|')) => (Token::Ident("or".into()), 2),
+                    ('&', Some('&')) => (Token::Ident("and".into()), 2),
+                    ('(', _) => (Token::LParen, 1),
+                    (')', _) => (Token::RParen, 1),
+                    (',', _) => (Token::Comma, 1),
+                    ('|', _) => (Token::Pipe, 1),
+                    _ => bail!("Unerwartetes Zeichen '&' an Position {}", i),
+                };
+                tokens.push(token);
+                i += len;
+            }
+            '>' | '<' | '=' | '!' => {
+                let op = match (c, next) {
+                    ('>', Some('=')) => ">=",
+                    ('<', Some('=')) => "<=",
+                    ('=', Some('=')) => "==",
+                    ('!', Some('=')) => "!=",
+                    ('>', _) => ">",
+                    ('<', _) => "<",
+                    ('!', _) => {
+                        tokens.push(Token::Ident("not".into()));
+                        i += 1;
+                        continue;
+                    }
+                    _ => bail!("'=' an Position {}: Vergleich ist '=='", i),
+                };
+                tokens.push(Token::Op(op));
+                i += op.len();
+            }
+            '\'' | '"' => {
+                let end = chars[i + 1..]
+                    .iter()
+                    .position(|&q| q == c)
+                    .with_context(|| format!("Zeichenkette ab Position {} nicht geschlossen", i))?;
+                tokens.push(Token::Str(chars[i + 1..i + 1 + end].iter().collect()));
+                i += end + 2;
+            }
+            c if c.is_ascii_digit() || c == '-' || c == '.' => {
+                let len = chars[i + 1..].iter().take_while(|c| c.is_ascii_digit() || matches!(c, '.' | 'e' | 'E')).count() + 1;
+                let text: String = chars[i..i + len].iter().collect();
+                tokens.push(Token::Num(text.parse().with_context(|| format!("Ungültige Zahl '{}'", text))?));
+                i += len;
+            }
+            c if c.is_alphabetic() || c == '_' => {
+                let len = chars[i..].iter().take_while(|c| c.is_alphanumeric() || **c == '_').count();
+                tokens.push(Token::Ident(chars[i..i + len].iter().collect()));
+                i += len;
+            }
+            other => bail!("Unerwartetes Zeichen '{}' an Position {}", other, i),
+        }
+    }
+    Ok(tokens)
+}
+
+fn parse(src: &str) -> Result<Vec<Stage>> {
+    let mut parser = Parser { tokens: tokenize(src)?, pos: 0 };
+    let mut stages = vec![parser.stage()?];
+    while parser.eat(&Token::Pipe) {
+        stages.push(parser.stage()?);
+    }
+    if let Some(token) = parser.peek() {
+        bail!("Unerwartetes Token {:?}", token);
+    }
+    Ok(stages)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn eat(&mut self, token: &Token) -> bool {
+        let matches = self.peek() == Some(token);
+        if matches {
+            self.pos += 1;
+        }
+        matches
+    }
+
+    fn keyword(&mut self, word: &str) -> bool {
+        self.eat(&Token::Ident(word.to_string()))
+    }
+
+    fn stage(&mut self) -> Result<Stage> {
+        if self.peek() == Some(&Token::Ident("topk".into())) && self.tokens.get(self.pos + 1) == Some(&Token::LParen) {
+            self.pos += 2;
+            let k = match self.next() {
+                Some(Token::Num(k)) if k >= 0.0 && k.fract() == 0.0 => k as usize,
+                other => bail!("topk erwartet eine Anzahl, bekommen {:?}", other),
+            };
+            let key = if self.eat(&Token::Comma) {
+                match self.next() {
+                    Some(Token::Ident(key)) => key,
+                    other => bail!("topk erwartet ein Feld, bekommen {:?}", other),
+                }
+            } else {
+                "score".to_string()
+            };
+            anyhow::ensure!(self.eat(&Token::RParen), "')' nach topk fehlt");
+            return Ok(Stage::TopK(k, key));
+        }
+        Ok(Stage::Where(self.or()?))
+    }
+
+    fn or(&mut self) -> Result<Expr> {
+        let mut expr = self.and()?;
+        while self.keyword("or") {
+            expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr> {
+        let mut expr = self.unary()?;
+        while self.keyword("and") {
+            expr = Expr::And(Box::new(expr), Box::new(self.unary()?));
+        }
+        Ok(expr)
+    }
+
+    fn unary(&mut self) -> Result<Expr> {
+        if self.keyword("not") {
+            return Ok(Expr::Not(Box::new(self.unary()?)));
+        }
+        if self.eat(&Token::LParen) {
+            let expr = self.or()?;
+            anyhow::ensure!(self.eat(&Token::RParen), "')' fehlt");
+            return Ok(expr);
+        }
+        let name = match self.next() {
+            Some(Token::Ident(name)) => name,
+            other => bail!("Feldname erwartet, bekommen {:?}", other),
+        };
+        let op = match self.next() {
+            Some(Token::Op(op)) => op,
+            other => bail!("Vergleich nach '{}' erwartet, bekommen {:?}", name, other),
+        };
+        let literal = match self.next() {
+            Some(Token::Num(x)) => Literal::Num(x),
+            Some(Token::Str(s)) => Literal::Str(s),
+            Some(Token::Ident(s)) if s == "true" || s == "false" => Literal::Str(s),
+            other => bail!("Zahl oder Zeichenkette erwartet, bekommen {:?}", other),
+        };
+        Ok(Expr::Cmp(name, op, literal))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn filter(expr: &str, field: Option<&str>) -> OutputFilter {
+        OutputFilter::new(&FilterCfg { expr: expr.to_string(), field: field.map(String::from) }).unwrap()
+    }
+
+    #[test]
+    fn test_predicate_and_topk() {
+        let mut result = json!({
+            "id": "j1",
+            "detections": [
+                {"label": "cat", "score": 0.9},
+                {"label": "dog", "score": 0.4},
+                {"label": "bird", "score": 0.7},
+                {"label": "cat", "score": 0.6},
+            ],
+        });
+        filter("score > 0.5 and not (label == 'bird') | topk(1)", None).apply(&mut result);
+        assert_eq!(result["detections"], json!([{"label": "cat", "score": 0.9}]));
+        assert_eq!(result["id"], "j1");
+    }
+
+    #[test]
+    fn test_number_arrays_only_with_field() {
+        let mut result = json!({ "data": [0.1, 0.8, 0.3], "shape": [3] });
+        filter("value >= 0.3", None).apply(&mut result);
+        assert_eq!(result["data"], json!([0.1, 0.8, 0.3]));
+
+        filter("value >= 0.3", Some("data")).apply(&mut result);
+        assert_eq!(result["data"], json!([0.8, 0.3]));
+    }
+
+    #[test]
+    fn test_invalid_expressions() {
+        for expr in ["score >", "score = 1", "topk(x)", "(score > 1", "score > 1 extra"] {
+            assert!(OutputFilter::new(&FilterCfg { expr: expr.to_string(), field: None }).is_err(), "{}", expr);
+        }
+    }
+}
@@ -0,0 +1,679 @@
+//! Autoregressive text generation for decoder models.
+//!
+//! Runs a token-by-token generation loop on top of any `Engine` that returns
+//! next-token logits (`[1, T, V]` or `[1, V]`), with temperature / top-p
+//! sampling, stop sequences, grammar constraints ([`crate::grammar`]) and
+//! per-token streaming events. With a decoder that keeps past key/values,
+//! jobs carrying a `meta["session_id"]` reuse their KV cache across requests
+//! and shared prompt prefixes in `meta["prefix"]` are prefilled once per
+//! device.
+
+use anyhow::{Context, Result};
+use ndarray::{ArrayD, IxDyn};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::Serialize;
+use tokio::sync::mpsc;
+
+use crate::engine::Engine;
+use crate::error::OmniError;
+use crate::grammar::Constraint;
+use crate::kv_cache::{prefix_key, KvCache, KvCacheManager};
+use crate::storage::redis_store::RedisStorage;
+use crate::text::{Tokenizer, WordPieceTokenizer};
+use crate::reproducibility::Reproducibility;
+use crate::types::{GenerationCfg, Job, KvCacheCfg, SamplingParams};
+use crate::worker::WorkerJobs;
+
+/// Streaming event emitted during generation.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum GenerationEvent {
+    /// A new token and the text it added (may be empty while a possible stop
+    /// sequence is held back).
+    Token { index: usize, id: u32, text: String },
+    /// Generation finished; `text` is the complete generated text.
+    Done { text: String, finish_reason: String },
+}
+
+/// Trait for models that produce next-token logits for a token prefix.
+pub trait DecoderModel: Send {
+    fn next_logits(&mut self, tokens: &[u32]) -> Result<Vec<f32>>;
+
+    /// KV-aware variant: `past` holds the attention state for `past.tokens`
+    /// (a prefix of `tokens`), so only the remaining tokens need to be fed.
+    /// Implementations must update `past` to cover all of `tokens`.
+    ///
+    /// The default recomputes the full prefix and only tracks the tokens.
+    fn next_logits_cached(&mut self, tokens: &[u32], past: &mut KvCache) -> Result<Vec<f32>> {
+        past.tokens = tokens.to_vec();
+        self.next_logits(tokens)
+    }
+
+    /// Whether [`Self::next_logits_cached`] keeps past key/values in `past`
+    /// and skips the tokens they cover. The KV caches of the worker are only
+    /// used if it does; otherwise they would hold nothing to reuse.
+    fn supports_kv_cache(&self) -> bool {
+        false
+    }
+}
+
+/// Adapter running a decoder model through a regular inference engine.
+///
+/// Feeds the full prefix as `[1, T]` ids (f32) and takes the logits of the
+/// last position. Engines exchange no past key/values, so it does not
+/// support KV caching.
+pub struct EngineDecoder {
+    engine: Box<dyn Engine>,
+}
+
+impl EngineDecoder {
+    pub fn new(engine: Box<dyn Engine>) -> Self {
+        Self { engine }
+    }
+}
+
+impl DecoderModel for EngineDecoder {
+    fn next_logits(&mut self, tokens: &[u32]) -> Result<Vec<f32>> {
+        let input = ArrayD::from_shape_vec(
+            IxDyn(&[1, tokens.len()]),
+            tokens.iter().map(|&t| t as f32).collect(),
+        )?;
+        let out = self.engine.infer_array(input)?;
+        let vocab = *out.shape().last().context("Leere Modell-Ausgabe")?;
+        let flat: Vec<f32> = out.iter().copied().collect();
+        anyhow::ensure!(flat.len() >= vocab && vocab > 0, "Ungültige Logits-Shape {:?}", out.shape());
+        Ok(flat[flat.len() - vocab..].to_vec())
+    }
+}
+
+/// Temperature / nucleus (top-p) sampler.
+pub struct Sampler {
+    rng: StdRng,
+}
+
+impl Sampler {
+    pub fn new(seed: Option<u64>) -> Self {
+        let rng = match seed {
+            Some(s) => StdRng::seed_from_u64(s),
+            None => StdRng::from_os_rng(),
+        };
+        Self { rng }
+    }
+
+    /// Picks the next token id. A temperature of `0` selects greedily.
+    pub fn sample(&mut self, logits: &[f32], params: &SamplingParams) -> u32 {
+        if params.temperature <= 0.0 {
+            return argmax(logits);
+        }
+
+        let max = logits.iter().cloned().fold(f32::NEG_INFINITY, f32::max);
+        let mut probs: Vec<(u32, f32)> = logits
+            .iter()
+            .enumerate()
+            .map(|(i, &l)| (i as u32, ((l - max) / params.temperature).exp()))
+            .collect();
+        let sum: f32 = probs.iter().map(|p| p.1).sum();
+        probs.iter_mut().for_each(|p| p.1 /= sum);
+        probs.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+        // Nucleus: kleinste Menge mit kumulierter Wahrscheinlichkeit >= top_p
+        let mut cumulative = 0.0;
+        let mut cut = probs.len();
+        for (i, p) in probs.iter().enumerate() {
+            cumulative += p.1;
+            if cumulative >= params.top_p {
+                cut = i + 1;
+                break;
+            }
+        }
+        probs.truncate(cut);
+
+        let total: f32 = probs.iter().map(|p| p.1).sum();
+        let mut r = self.rng.random::<f32>() * total;
+        for &(id, p) in &probs {
+            if r < p {
+                return id;
+            }
+            r -= p;
+        }
+        probs.last().map(|p| p.0).unwrap_or(0)
+    }
+}
+
+fn argmax(values: &[f32]) -> u32 {
+    values
+        .iter()
+        .enumerate()
+        .max_by(|a, b| a.1.total_cmp(b.1))
+        .map(|(i, _)| i as u32)
+        .unwrap_or(0)
+}
+
+/// Length of the longest suffix of `text` that is a proper prefix of a stop
+/// sequence. That part is held back from streaming until it is resolved.
+fn stop_holdback(text: &str, stop: &[String]) -> usize {
+    stop.iter()
+        .flat_map(|s| {
+            (1..s.len())
+                .filter(|&n| s.is_char_boundary(n) && text.ends_with(&s[..n]))
+                .max()
+        })
+        .max()
+        .unwrap_or(0)
+}
+
+/// Runs the generation loop for one prompt.
+///
+/// Emits a `Token` event per generated token and a final `Done` event. Stops
+/// at `max_new_tokens`, the `eos` token or the first stop sequence (which is
+/// not part of the returned text). `past` is the (possibly empty) KV cache of
+/// the session and covers prompt plus generated tokens afterwards.
+///
+/// With `params.grammar` or `params.json_schema` only tokens that keep the
+/// text within the grammar are sampled, and generation also ends once the
+/// text is complete and cannot be continued (`finish_reason` `"grammar"`).
+pub fn generate(
+    model: &mut dyn DecoderModel,
+    tokenizer: &dyn Tokenizer,
+    prompt: &[u32],
+    params: &SamplingParams,
+    eos: Option<u32>,
+    past: &mut KvCache,
+    mut on_event: impl FnMut(GenerationEvent),
+) -> Result<String> {
+    let mut sampler = Sampler::new(params.seed);
+    let mut tokens = prompt.to_vec();
+    let mut generated = Vec::new();
+    let mut emitted = 0;
+    let mut text = String::new();
+    let mut finish_reason = "length";
+    let mut constraint = Constraint::from_params(params)?;
+
+    for index in 0..params.max_new_tokens {
+        let mut logits = model.next_logits_cached(&tokens, past)?;
+        if let Some(constraint) = constraint.as_mut() {
+            if constraint.mask(&mut logits, tokenizer, eos) == 0 {
+                anyhow::ensure!(constraint.complete(), "Keine gültige Fortsetzung der Grammatik nach '{}'", constraint.text());
+                finish_reason = "grammar";
+                break;
+            }
+        }
+        let id = sampler.sample(&logits, params);
+        if Some(id) == eos {
+            finish_reason = "eos";
+            break;
+        }
+        tokens.push(id);
+        generated.push(id);
+        text = match constraint.as_mut() {
+            Some(constraint) => {
+                constraint.advance(id)?;
+                constraint.text().to_string()
+            }
+            None => tokenizer.decode(&generated)?,
+        };
+
+        if let Some(pos) = params.stop.iter().filter_map(|s| text.find(s.as_str())).min() {
+            text.truncate(pos);
+            finish_reason = "stop";
+        }
+
+        let visible = if finish_reason == "stop" {
+            text.len()
+        } else {
+            text.len() - stop_holdback(&text, &params.stop)
+        };
+        let delta = text.get(emitted.min(visible)..visible).unwrap_or_default().to_string();
+        emitted = emitted.max(visible);
+        on_event(GenerationEvent::Token { index, id, text: delta });
+
+        if finish_reason == "stop" {
+            break;
+        }
+    }
+
+    on_event(GenerationEvent::Done { text: text.clone(), finish_reason: finish_reason.to_string() });
+    Ok(text)
+}
+
+/// Sets `past` to the cached state of the prompt prefix `prefix`, or
+/// prefills the prefix and caches the state. Returns whether it was cached.
+fn restore_prefix(model: &mut dyn DecoderModel, prefixes: &mut KvCacheManager, prefix: &[u32], past: &mut KvCache) -> Result<bool> {
+    let key = prefix_key(prefix);
+    if let Some(cache) = prefixes.get(&key, prefix) {
+        *past = cache;
+        return Ok(true);
+    }
+    model.next_logits_cached(prefix, past)?;
+    prefixes.checkin(&key, past.clone());
+    Ok(false)
+}
+
+/// Prefix tokens, full prompt tokens and sampling parameters of a job.
+fn parse_request(
+    job: &Job,
+    cfg: &GenerationCfg,
+    tokenizer: &WordPieceTokenizer,
+    repro: Option<&Reproducibility>,
+) -> Result<(Vec<u32>, Vec<u32>, SamplingParams)> {
+    let prefix = match job.meta.get("prefix").and_then(|p| p.as_str()) {
+        Some(p) => tokenizer.encode(p)?,
+        None => Vec::new(),
+    };
+    let mut prompt = prefix.clone();
+    match job.meta.get("prompt").and_then(|p| p.as_str()) {
+        Some(p) => prompt.extend(tokenizer.encode(p)?),
+        None => prompt.extend(job.tensor.iter().map(|&v| v as u32)),
+    }
+    let mut params: SamplingParams = match job.meta.get("sampling") {
+        Some(v) => serde_json::from_value(v.clone()).context("Ungültige Sampling-Parameter")?,
+        None => cfg.sampling.clone(),
+    };
+    if let Some(repro) = repro {
+        params.seed.get_or_insert(repro.seed);
+    }
+    // Grammatik/Schema vorab prüfen: Fehler sind ungültige Eingaben, keine Generierungsfehler
+    Constraint::from_params(&params)?;
+    Ok((prefix, prompt, params))
+}
+
+/// Answers a job that could not be generated with an error result and
+/// acknowledges it; the worker goes on with the next job.
+async fn fail_job(store: &RedisStorage, job: &Job, error: OmniError) {
+    tracing::warn!("Generierung für {} fehlgeschlagen: {}", job.id, error);
+    if matches!(error, OmniError::InvalidInput(_)) {
+        crate::health::health().job_invalid();
+    }
+    crate::store_error(store, job, error).await;
+    crate::health::health().jobs_completed(1);
+    if let Some(ack) = &job.ack {
+        ack.done();
+    }
+}
+
+/// Processes generation jobs sequentially on one device.
+///
+/// The prompt is taken from `meta["prompt"]` (tokenized with the `[text]`
+/// vocabulary) or, if absent, from the job tensor as token ids. Sampling
+/// parameters can be overridden per job via `meta["sampling"]`. Token events
+/// are published to `{out_prefix}:{job_id}:stream`, the final text is stored
+/// as the job result.
+///
+/// KV caches of `meta["session_id"]` sessions are kept within the
+/// `[kv_cache]` budget if the decoder supports them
+/// ([`DecoderModel::supports_kv_cache`]); `meta["session_close"] = true`
+/// drops the cache after the request. `meta["prefix"]` (system prompt, template) is prepended to
+/// the prompt; with such a decoder its state is cached within
+/// `prefix_budget_mb` and shared by all jobs with the same prefix.
+///
+/// A job with invalid input or a failed generation gets an error result;
+/// the worker continues with the next job. It stops with
+/// [`crate::worker::Evicted`] once its GPU is unhealthy.
+pub async fn run_generation_worker(
+    cfg: GenerationCfg,
+    mut model: Box<dyn DecoderModel>,
+    tokenizer: WordPieceTokenizer,
+    jobs: &mut WorkerJobs<'_>,
+    store: RedisStorage,
+    kv: Option<&KvCacheCfg>,
+    repro: Option<Reproducibility>,
+) -> Result<()> {
+    let eos = cfg.eos_token.as_deref().and_then(|t| tokenizer.token_id(t));
+    let session_caching = model.supports_kv_cache();
+    if kv.is_some() && !session_caching {
+        tracing::warn!("[kv_cache] ist wirkungslos: das Backend liefert keine Past-Key-Values, jeder Schritt rechnet den ganzen Prompt");
+    }
+    let prefix_budget = kv.map_or(0, |kv| kv.prefix_budget_mb << 20);
+    let mut kv = KvCacheManager::new(kv.map_or(0, |kv| kv.budget_mb << 20));
+    let mut prefixes = KvCacheManager::new(prefix_budget);
+    let prefix_caching = prefix_budget > 0 && session_caching;
+
+    while let Some(job) = jobs.next().await? {
+        let (prefix, prompt, params) = match parse_request(&job, &cfg, &tokenizer, repro.as_ref()) {
+            Ok(request) => request,
+            Err(e) => {
+                fail_job(&store, &job, OmniError::InvalidInput(format!("{:#}", e))).await;
+                continue;
+            }
+        };
+
+        // Events über einen Kanal an den Redis-Publisher weiterreichen
+        let (tx, mut events) = mpsc::unbounded_channel();
+        let publisher = tokio::spawn({
+            let store = store.clone();
+            let id = job.id.clone();
+            async move {
+                while let Some(event) = events.recv().await {
+                    if let Err(e) = store.publish_json(&format!("{}:stream", id), &event).await {
+                        tracing::warn!("Stream-Event für {} nicht publiziert: {}", id, e);
+                    }
+                }
+            }
+        });
+
+        let session = job.meta.get("session_id").and_then(|s| s.as_str());
+        let session = session.filter(|_| session_caching);
+        let mut past = match session {
+            Some(s) => kv.checkout(s, &prompt),
+            None => KvCache::default(),
+        };
+        let generated = (|| {
+            if past.tokens.is_empty() && !prefix.is_empty() && prefix_caching {
+                let hit = restore_prefix(model.as_mut(), &mut prefixes, &prefix, &mut past)?;
+                crate::health::health().prefix_cache_lookup(hit);
+            }
+            generate(model.as_mut(), &tokenizer, &prompt, &params, eos, &mut past, |event| {
+                let _ = tx.send(event);
+            })
+        })();
+        drop(tx);
+        let _ = publisher.await;
+        let text = match generated {
+            Ok(text) => text,
+            Err(e) => {
+                // Zustand der Session ist unvollständig und wird verworfen
+                if let Some(s) = session {
+                    kv.remove(s);
+                }
+                fail_job(&store, &job, OmniError::Internal(format!("{:#}", e))).await;
+                continue;
+            }
+        };
+
+        if let Some(s) = session {
+            if job.meta.get("session_close").and_then(|v| v.as_bool()) == Some(true) {
+                kv.remove(s);
+            } else {
+                kv.checkin(s, past);
+            }
+        }
+
+        let mut payload = serde_json::json!({
+            "schema_version": crate::types::SCHEMA_VERSION,
+            "id": job.id,
+            "timestamp": chrono::Utc::now().to_rfc3339(),
+            "text": text,
+        });
+        if let Some(repro) = &repro {
+            payload["reproducibility"] = repro.provenance.clone();
+        }
+        crate::trace_context::annotate(&mut payload, &job.meta);
+        crate::results::publish(&payload);
+        match crate::results::take_route(&job.id) {
+            Some(route) => {
+                let _ = route.send(crate::results::Direct { payload, tensor: None });
+            }
+            None => {
+                // Wie im Batch-Worker: melden, unquittiert lassen und weitermachen
+                if let Err(e) = store.store_json(&job.id, &payload).await {
+                    tracing::warn!("Ergebnis für {} nicht gespeichert: {:#}", job.id, e);
+                    continue;
+                }
+            }
+        }
+        crate::health::health().jobs_completed(1);
+        if let Some(ack) = &job.ack {
+            ack.done();
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Always predicts `(last + 1) % vocab`.
+    struct CountingModel {
+        vocab: usize,
+    }
+
+    impl DecoderModel for CountingModel {
+        fn next_logits(&mut self, tokens: &[u32]) -> Result<Vec<f32>> {
+            let next = (*tokens.last().unwrap_or(&0) as usize + 1) % self.vocab;
+            let mut logits = vec![0.0; self.vocab];
+            logits[next] = 10.0;
+            Ok(logits)
+        }
+    }
+
+    fn tokenizer() -> WordPieceTokenizer {
+        let vocab = ["[UNK]", "a", "b", "c", "d", "[SEP]"];
+        WordPieceTokenizer::from_tokens(vocab.iter().map(|t| t.to_string()).collect(), false).unwrap()
+    }
+
+    fn greedy(max_new_tokens: usize, stop: &[&str]) -> SamplingParams {
+        SamplingParams {
+            temperature: 0.0,
+            max_new_tokens,
+            stop: stop.iter().map(|s| s.to_string()).collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_generate_greedy_until_eos() {
+        let mut model = CountingModel { vocab: 6 };
+        let mut events = Vec::new();
+        let text = generate(&mut model, &tokenizer(), &[1], &greedy(10, &[]), Some(5), &mut KvCache::default(), |e| {
+            events.push(e)
+        }).unwrap();
+
+        assert_eq!(text, "b c d");
+        assert_eq!(events.len(), 4);
+        assert!(matches!(&events[3], GenerationEvent::Done { finish_reason, .. } if finish_reason == "eos"));
+    }
+
+    #[test]
+    fn test_generate_stop_sequence_not_streamed() {
+        let mut model = CountingModel { vocab: 6 };
+        let mut streamed = String::new();
+        let text = generate(&mut model, &tokenizer(), &[1], &greedy(10, &["c d"]), None, &mut KvCache::default(), |e| {
+            if let GenerationEvent::Token { text, .. } = e {
+                streamed.push_str(&text);
+            }
+        })
+        .unwrap();
+
+        assert_eq!(text, "b ");
+        assert_eq!(streamed, "b ");
+    }
+
+    #[test]
+    fn test_generate_tracks_session_tokens() {
+        let mut model = CountingModel { vocab: 6 };
+        let mut past = KvCache::default();
+        generate(&mut model, &tokenizer(), &[1], &greedy(2, &[]), None, &mut past, |_| {}).unwrap();
+
+        // Prompt + generierte Tokens bis auf das letzte (noch nicht eingespeist)
+        assert_eq!(past.tokens, vec![1, 2]);
+        assert_eq!(past.reusable_prefix(&[1, 2, 3, 4]), 2);
+    }
+
+    #[test]
+    fn test_generate_follows_grammar() {
+        let mut model = CountingModel { vocab: 6 };
+        let params = SamplingParams { grammar: Some(r#"root ::= "b" " "? "d""#.to_string()), ..greedy(10, &[]) };
+        let mut events = Vec::new();
+        let text = generate(&mut model, &tokenizer(), &[1], &params, None, &mut KvCache::default(), |e| events.push(e)).unwrap();
+
+        // Das Modell bevorzugt "c", die Grammatik erzwingt "d"
+        assert_eq!(text, "b d");
+        assert!(matches!(events.last(), Some(GenerationEvent::Done { finish_reason, .. }) if finish_reason == "grammar"));
+    }
+
+    /// Counts the tokens fed to the model, keeping one dummy tensor per token.
+    #[derive(Default)]
+    struct KvModel {
+        fed: std::sync::Arc<std::sync::atomic::AtomicUsize>,
+    }
+
+    impl KvModel {
+        fn fed(&self) -> usize {
+            self.fed.load(std::sync::atomic::Ordering::Relaxed)
+        }
+    }
+
+    impl DecoderModel for KvModel {
+        fn next_logits(&mut self, tokens: &[u32]) -> Result<Vec<f32>> {
+            CountingModel { vocab: 6 }.next_logits(tokens)
+        }
+
+        fn next_logits_cached(&mut self, tokens: &[u32], past: &mut KvCache) -> Result<Vec<f32>> {
+            self.fed.fetch_add(tokens.len() - past.reusable_prefix(tokens), std::sync::atomic::Ordering::Relaxed);
+            past.tokens = tokens.to_vec();
+            past.tensors = vec![ArrayD::zeros(IxDyn(&[tokens.len()]))];
+            self.next_logits(tokens)
+        }
+
+        fn supports_kv_cache(&self) -> bool {
+            true
+        }
+    }
+
+    #[test]
+    fn test_prefix_prefilled_once() {
+        let mut model = KvModel::default();
+        let mut prefixes = KvCacheManager::new(1024);
+        let system = [4, 4, 4, 4];
+
+        let mut past = KvCache::default();
+        assert!(!restore_prefix(&mut model, &mut prefixes, &system, &mut past).unwrap());
+        generate(&mut model, &tokenizer(), &[4, 4, 4, 4, 1], &greedy(1, &[]), None, &mut past, |_| {}).unwrap();
+        assert_eq!(model.fed(), 5);
+
+        // Zweiter Prompt mit gleichem Prefix: nur die neuen Tokens werden eingespeist
+        let mut past = KvCache::default();
+        assert!(restore_prefix(&mut model, &mut prefixes, &system, &mut past).unwrap());
+        generate(&mut model, &tokenizer(), &[4, 4, 4, 4, 2, 3], &greedy(1, &[]), None, &mut past, |_| {}).unwrap();
+        assert_eq!(model.fed(), 7);
+    }
+
+    /// Decoder engine for `[1, T]` ids predicting `(last + 1) % 6`.
+    struct CountingEngine;
+
+    impl Engine for CountingEngine {
+        fn name(&self) -> &'static str {
+            "counting"
+        }
+
+        fn infer_array(&mut self, input: ArrayD<f32>) -> Result<ArrayD<f32>> {
+            let last = *input.iter().last().context("Leere Eingabe")? as usize;
+            let mut logits = ArrayD::zeros(IxDyn(&[1, 6]));
+            logits[[0, (last + 1) % 6]] = 10.0;
+            Ok(logits)
+        }
+    }
+
+    fn job(id: &str, meta: serde_json::Value) -> Job {
+        let meta = serde_json::from_value(meta).unwrap();
+        Job { id: id.to_string(), tensor: ArrayD::from_elem(IxDyn(&[1]), 1.0), meta, ..Default::default() }
+    }
+
+    /// Runs the worker over `jobs` until the queue is closed.
+    async fn serve(jobs: Vec<Job>) -> RedisStorage {
+        let store = RedisStorage::new(crate::storage::redis_store::MEMORY_URL, "gen".to_string()).unwrap();
+        let (tx, mut rx) = mpsc::channel(jobs.len());
+        for job in jobs {
+            tx.send(job).await.unwrap();
+        }
+        drop(tx);
+        let cfg = GenerationCfg { sampling: greedy(3, &[]), eos_token: None };
+        let mut jobs = WorkerJobs { rx: &mut rx, device: None, worker: "gen" };
+        run_generation_worker(cfg, Box::new(EngineDecoder::new(Box::new(CountingEngine))), tokenizer(), &mut jobs, store.clone(), None, None).await.unwrap();
+        store
+    }
+
+    #[tokio::test]
+    async fn test_worker_prefills_shared_prefix_once() {
+        let store = RedisStorage::new(crate::storage::redis_store::MEMORY_URL, "gen".to_string()).unwrap();
+        let (tx, mut rx) = mpsc::channel(2);
+        for (id, prompt) in [("gen-prefix-1", "b"), ("gen-prefix-2", "c")] {
+            tx.send(job(id, serde_json::json!({"prefix": "a a a a", "prompt": prompt}))).await.unwrap();
+        }
+        drop(tx);
+        let model = KvModel::default();
+        let fed = std::sync::Arc::clone(&model.fed);
+        let cfg = GenerationCfg { sampling: greedy(3, &[]), eos_token: None };
+        let kv: KvCacheCfg = toml::from_str("budget_mb = 1\nprefix_budget_mb = 1").unwrap();
+        let mut jobs = WorkerJobs { rx: &mut rx, device: None, worker: "gen" };
+        run_generation_worker(cfg, Box::new(model), tokenizer(), &mut jobs, store.clone(), Some(&kv), None).await.unwrap();
+
+        // Prefix einmal (4), dann je Job ein Prompt-Token und zwei weitere Schritte
+        assert_eq!(fed.load(std::sync::atomic::Ordering::Relaxed), 4 + 3 + 3);
+        assert_eq!(store.get_json("gen-prefix-2").await.unwrap().unwrap()["text"], "d");
+    }
+
+    #[tokio::test]
+    async fn test_worker_stops_on_unhealthy_gpu() {
+        let store = RedisStorage::new(crate::storage::redis_store::MEMORY_URL, "gen".to_string()).unwrap();
+        let (tx, mut rx) = mpsc::channel(2);
+        let mut jobs = WorkerJobs { rx: &mut rx, device: Some(93), worker: "gpu:93" };
+        let cfg = GenerationCfg { sampling: greedy(3, &[]), eos_token: None };
+        let worker = run_generation_worker(cfg, Box::new(EngineDecoder::new(Box::new(CountingEngine))), tokenizer(), &mut jobs, store.clone(), None, None);
+
+        // Erster Job läuft, danach fällt die GPU aus
+        let driver = async {
+            tx.send(job("gen-before-xid", serde_json::json!({}))).await.unwrap();
+            while store.get_json("gen-before-xid").await.unwrap().is_none() {
+                tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+            }
+            crate::health::health().mark_gpu_unhealthy(93, "Xid 79");
+            tx.send(job("gen-after-xid", serde_json::json!({}))).await.unwrap();
+        };
+        let (stopped, ()) = tokio::join!(worker, driver);
+
+        let evicted = stopped.unwrap_err().downcast::<crate::worker::Evicted>().unwrap();
+        assert_eq!(evicted.reason, "Xid 79");
+        // Nicht gelaufen: entweder noch in der Queue oder zum Neuverteilen zurückgegeben
+        let pending: Vec<_> = evicted.jobs.into_iter().chain(rx.try_recv().ok()).map(|j| j.id).collect();
+        assert_eq!(pending, ["gen-after-xid"]);
+        assert!(store.get_json("gen-after-xid").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_worker_answers_invalid_job_and_continues() {
+        let store = serve(vec![
+            job("gen-bad", serde_json::json!({"sampling": {"temperature": "heiß"}})),
+            job("gen-ok", serde_json::json!({})),
+        ])
+        .await;
+
+        let bad = store.get_json("gen-bad").await.unwrap().unwrap();
+        assert_eq!(bad["code"], "INVALID_INPUT");
+        assert!(bad["error"].as_str().unwrap().contains("Sampling-Parameter"));
+        assert_eq!(store.get_json("gen-ok").await.unwrap().unwrap()["text"], "b c d");
+    }
+
+    #[tokio::test]
+    async fn test_worker_answers_grammar_errors_and_continues() {
+        let schema = serde_json::json!({"$ref": "#/definitions/person"});
+        // Nach "b" verlangt die Grammatik "x", das nicht im Vokabular steht: Sackgasse
+        let dead_end = r#"root ::= "b" "x""#;
+        let store = serve(vec![
+            job("gen-schema", serde_json::json!({"sampling": {"temperature": 0, "json_schema": schema}})),
+            job("gen-dead-end", serde_json::json!({"sampling": {"temperature": 0, "grammar": dead_end}})),
+            job("gen-next", serde_json::json!({})),
+        ])
+        .await;
+
+        let schema = store.get_json("gen-schema").await.unwrap().unwrap();
+        assert_eq!(schema["code"], "INVALID_INPUT");
+        let dead_end = store.get_json("gen-dead-end").await.unwrap().unwrap();
+        assert_eq!(dead_end["code"], "INTERNAL");
+        assert!(dead_end["error"].as_str().unwrap().contains("Keine gültige Fortsetzung"), "{}", dead_end);
+        assert_eq!(store.get_json("gen-next").await.unwrap().unwrap()["text"], "b c d");
+    }
+
+    #[test]
+    fn test_sampler_top_p_restricts_to_nucleus() {
+        let mut sampler = Sampler::new(Some(7));
+        let params = SamplingParams { temperature: 1.0, top_p: 0.5, ..Default::default() };
+        let logits = [5.0, 0.0, 0.0, 0.0];
+        for _ in 0..50 {
+            assert_eq!(sampler.sample(&logits, &params), 0);
+        }
+    }
+}
@@ -0,0 +1,220 @@
+//! GPU health probing via NVML (`[gpu_health]`).
+//!
+//! A background thread waits for critical Xid and double-bit ECC events of
+//! the configured GPUs and polls their uncorrected ECC counters. A failing
+//! GPU is marked in [`health`]; its workers finish the batches in flight,
+//! stop and fail over like a crashed worker (queued jobs are redistributed,
+//! standby workers take over). Requires the `nvml` feature.
+
+use anyhow::Result;
+
+use crate::health::health;
+use crate::types::Config;
+
+/// Starts probing the GPUs with CUDA ordinals `gpus` if `[gpu_health]` is
+/// configured. Must run after `CUDA_VISIBLE_DEVICES` is final.
+pub fn spawn(cfg: &Config, gpus: &[usize]) -> Result<()> {
+    let Some(gpu_cfg) = &cfg.gpu_health else { return Ok(()) };
+    anyhow::ensure!(cfg!(feature = "nvml"), "[gpu_health] benötigt das Feature 'nvml'");
+    if gpus.is_empty() {
+        return Ok(());
+    }
+    let visible = std::env::var("CUDA_VISIBLE_DEVICES").ok();
+    let devices: Vec<(usize, String)> = gpus.iter().map(|&g| (g, nvml_selector(g, visible.as_deref()))).collect();
+    #[cfg(feature = "nvml")]
+    {
+        let gpu_cfg = gpu_cfg.clone();
+        std::thread::Builder::new().name("gpu-health".to_string()).spawn(move || {
+            if let Err(e) = probe::run(&gpu_cfg, &devices) {
+                tracing::warn!("GPU-Überwachung beendet: {:#}", e);
+            }
+        })?;
+    }
+    #[cfg(not(feature = "nvml"))]
+    let _ = (gpu_cfg, devices);
+    Ok(())
+}
+
+/// Reason why the worker on `device` must stop, if its GPU is unhealthy.
+pub fn evicted(device: Option<usize>) -> Option<String> {
+    device.and_then(|gpu| health().gpu_unhealthy(gpu))
+}
+
+/// Builds one event set shared by `devices` (CUDA ordinal and handle).
+///
+/// A failed registration releases the whole set in NVML, so the failing
+/// device is dropped with a warning (it keeps the ECC counter polling) and
+/// the set is rebuilt from the remaining devices. `None` if no device can
+/// register events.
+#[cfg_attr(not(feature = "nvml"), allow(dead_code))]
+pub(crate) fn shared_event_set<D, S>(
+    devices: &[(usize, D)],
+    create: impl Fn() -> Result<S>,
+    register: impl Fn(&D, S) -> Result<S>,
+) -> Option<S> {
+    let mut candidates: Vec<&(usize, D)> = devices.iter().collect();
+    while !candidates.is_empty() {
+        let set = match create() {
+            Ok(set) => set,
+            Err(e) => {
+                tracing::warn!("Keine NVML-Ereignisse ({:#}), nur ECC-Zähler", e);
+                return None;
+            }
+        };
+        let registered = candidates
+            .iter()
+            .enumerate()
+            .try_fold(set, |set, (i, (gpu, device))| register(device, set).map_err(|e| (i, *gpu, e)));
+        match registered {
+            Ok(set) => return Some(set),
+            Err((i, gpu, e)) => {
+                tracing::warn!("GPU {}: keine NVML-Ereignisse ({:#}), nur ECC-Zähler", gpu, e);
+                candidates.remove(i);
+            }
+        }
+    }
+    None
+}
+
+/// NVML selector (physical index or UUID) of CUDA ordinal `gpu`, given
+/// `CUDA_VISIBLE_DEVICES`.
+pub(crate) fn nvml_selector(gpu: usize, visible: Option<&str>) -> String {
+    visible
+        .and_then(|v| v.split(',').map(str::trim).filter(|e| !e.is_empty()).nth(gpu))
+        .map(str::to_string)
+        .unwrap_or_else(|| gpu.to_string())
+}
+
+#[cfg(feature = "nvml")]
+pub(crate) mod probe {
+    use std::collections::HashMap;
+
+    use anyhow::{Context, Result};
+    use nvml_wrapper::bitmasks::event::EventTypes;
+    use nvml_wrapper::enum_wrappers::device::{EccCounter, MemoryError};
+    use nvml_wrapper::enums::event::XidError;
+    use nvml_wrapper::error::NvmlError;
+    use nvml_wrapper::{Device, Nvml};
+
+    use crate::health::health;
+    use crate::types::GpuHealthCfg;
+
+    /// Probes until NVML fails; `devices` pairs CUDA ordinals with NVML
+    /// selectors.
+    pub fn run(cfg: &GpuHealthCfg, devices: &[(usize, String)]) -> Result<()> {
+        let nvml = Nvml::init().context("NVML nicht verfügbar")?;
+        let mut handles: Vec<(usize, Device)> = Vec::new();
+        for (gpu, selector) in devices {
+            match device(&nvml, *gpu, selector) {
+                Ok(device) => handles.push((*gpu, device)),
+                Err(e) => tracing::warn!("GPU {} wird nicht überwacht: {:#}", gpu, e),
+            }
+        }
+
+        // Ereignisse je physischer GPU; mehrere Worker können dieselbe GPU teilen
+        let mut uuids: HashMap<String, Vec<usize>> = HashMap::new();
+        for (gpu, device) in &handles {
+            match device.uuid() {
+                Ok(uuid) => uuids.entry(uuid).or_default().push(*gpu),
+                Err(e) => tracing::warn!("GPU {}: UUID nicht lesbar ({}), Ereignisse nicht zuordenbar", gpu, e),
+            }
+        }
+        let events = super::shared_event_set(
+            &handles,
+            || Ok(nvml.create_event_set()?),
+            |device, set| Ok(device.register_events(EventTypes::CRITICAL_XID_ERROR | EventTypes::DOUBLE_BIT_ECC_ERROR, set)?),
+        );
+        let uncorrected = |device: &Device| device.total_ecc_errors(MemoryError::Uncorrected, EccCounter::Volatile).ok();
+        let baseline: Vec<Option<u64>> = handles.iter().map(|(_, d)| uncorrected(d)).collect();
+        let interval_ms = (cfg.interval_secs.max(1) * 1000) as u32;
+
+        loop {
+            match events.as_ref().map(|set| set.wait(interval_ms)) {
+                Some(Ok(event)) => {
+                    let gpus = event.device.uuid().ok().and_then(|u| uuids.get(&u)).cloned().unwrap_or_default();
+                    let reason = match event.event_data {
+                        _ if event.event_type.contains(EventTypes::DOUBLE_BIT_ECC_ERROR) => Some("Double-Bit-ECC-Fehler".to_string()),
+                        Some(XidError::Value(xid)) if !cfg.ignore_xids.contains(&xid) => Some(format!("Xid {}", xid)),
+                        Some(XidError::Value(xid)) => {
+                            tracing::warn!("GPU {:?}: Xid {} ignoriert", gpus, xid);
+                            None
+                        }
+                        Some(XidError::Unknown) => Some("unbekannter Xid-Fehler".to_string()),
+                        None => None,
+                    };
+                    if let Some(reason) = reason {
+                        for gpu in gpus {
+                            health().mark_gpu_unhealthy(gpu, &reason);
+                        }
+                    }
+                }
+                Some(Err(NvmlError::Timeout)) => {}
+                Some(Err(e)) => return Err(e).context("NVML-Ereignisse"),
+                None => std::thread::sleep(std::time::Duration::from_millis(interval_ms as u64)),
+            }
+
+            for ((gpu, device), base) in handles.iter().zip(&baseline) {
+                if let Err(NvmlError::GpuLost) = device.uuid() {
+                    health().mark_gpu_unhealthy(*gpu, "GPU nicht mehr erreichbar");
+                    continue;
+                }
+                if let (Some(base), Some(now)) = (base, uncorrected(device)) {
+                    if now.saturating_sub(*base) > cfg.max_uncorrected_ecc {
+                        health().mark_gpu_unhealthy(*gpu, &format!("{} neue unkorrigierte ECC-Fehler", now - base));
+                    }
+                }
+            }
+        }
+    }
+
+    /// Opens CUDA ordinal `gpu` by its NVML selector (see [`super::nvml_selector`]).
+    pub fn device<'n>(nvml: &'n Nvml, gpu: usize, selector: &str) -> Result<Device<'n>> {
+        match selector.parse::<u32>() {
+            Ok(index) => nvml.device_by_index(index),
+            Err(_) => nvml.device_by_uuid(selector),
+        }
+        .with_context(|| format!("GPU {} ({}) in NVML nicht gefunden", gpu, selector))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_nvml_selector() {
+        assert_eq!(nvml_selector(1, None), "1");
+        assert_eq!(nvml_selector(1, Some("2, 3")), "3");
+        assert_eq!(nvml_selector(0, Some("MIG-abc")), "MIG-abc");
+        assert_eq!(nvml_selector(4, Some("2,3")), "4");
+    }
+
+    #[test]
+    fn test_shared_event_set_skips_failing_device() {
+        let created = std::cell::Cell::new(0);
+        let create = || {
+            created.set(created.get() + 1);
+            Ok(Vec::new())
+        };
+        // Wie NVML: eine fehlgeschlagene Registrierung verwirft die Menge
+        let register = |device: &&'static str, mut set: Vec<&'static str>| {
+            anyhow::ensure!(*device != "broken", "NotSupported");
+            set.push(*device);
+            Ok(set)
+        };
+        let devices = [(0, "a"), (1, "broken"), (2, "c")];
+        assert_eq!(shared_event_set(&devices, create, register), Some(vec!["a", "c"]));
+        assert_eq!(created.get(), 2);
+
+        assert_eq!(shared_event_set(&[(1, "broken")], create, register), None);
+        let unavailable = || -> Result<Vec<&'static str>> { anyhow::bail!("Uninitialized") };
+        assert_eq!(shared_event_set(&devices, unavailable, register), None);
+    }
+
+    #[test]
+    fn test_evicted_after_marking() {
+        assert_eq!(evicted(None), None);
+        health().mark_gpu_unhealthy(97, "Xid 79");
+        assert_eq!(evicted(Some(97)).as_deref(), Some("Xid 79"));
+    }
+}
@@ -0,0 +1,610 @@
+//! Grammar-constrained decoding for generation jobs.
+//!
+//! `sampling.grammar` takes an EBNF grammar (GBNF style, start rule `root`),
+//! `sampling.json_schema` a JSON schema that is translated into one. While
+//! generating, [`Constraint`] masks every token whose text would leave the
+//! language, so the final text is a sentence of the grammar unless the token
+//! limit cuts it off.
+//!
+//! ```text
+//! root   ::= answer ws "(" [0-9]+ "%)"
+//! answer ::= "yes" | "no"
+//! ws     ::= " "*
+//! ```
+//!
+//! Rules are alternatives (`|`) of sequences of literals (`"..."`), character
+//! classes (`[a-z]`, `[^"]`), rule names and groups, each optionally
+//! repeated (`*`, `+`, `?`). `#` starts a comment. The recognizer keeps the
+//! pending symbols of every possible parse as a stack, so left-recursive
+//! rules are rejected.
+
+use std::collections::{HashMap, HashSet};
+
+use anyhow::{bail, Context, Result};
+use serde_json::Value;
+
+use crate::text::Tokenizer;
+use crate::types::SamplingParams;
+
+/// Parse stacks beyond which a grammar counts as left-recursive or too
+/// ambiguous.
+const MAX_STACKS: usize = 4096;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Sym {
+    /// Character class by index.
+    T(u32),
+    /// Rule by index.
+    N(u32),
+}
+
+#[derive(Debug, Clone)]
+struct CharClass {
+    ranges: Vec<(char, char)>,
+    negated: bool,
+}
+
+impl CharClass {
+    fn matches(&self, c: char) -> bool {
+        self.ranges.iter().any(|&(lo, hi)| lo <= c && c <= hi) != self.negated
+    }
+}
+
+/// Compiled grammar: rules as alternatives of symbol sequences.
+#[derive(Debug, Clone)]
+pub struct Grammar {
+    rules: Vec<Vec<Vec<Sym>>>,
+    classes: Vec<CharClass>,
+    root: u32,
+}
+
+/// Recognizer state after a prefix: the pending symbols of each parse, top
+/// last, expanded until a character class or nothing is on top.
+#[derive(Debug, Clone)]
+pub struct State {
+    stacks: Vec<Vec<Sym>>,
+}
+
+impl State {
+    /// Whether the prefix is a complete sentence.
+    pub fn accepting(&self) -> bool {
+        self.stacks.iter().any(Vec::is_empty)
+    }
+}
+
+impl Grammar {
+    /// Parses an EBNF grammar with start rule `root`.
+    pub fn parse(src: &str) -> Result<Self> {
+        let mut parser = Parser { chars: src.chars().collect(), pos: 0, names: HashMap::new(), defined: Vec::new(), rules: Vec::new(), classes: Vec::new() };
+        parser.grammar()?;
+        let root = *parser.names.get("root").context("Grammatik hat keine Regel 'root'")?;
+        if let Some((name, _)) = parser.names.iter().find(|(_, &id)| !parser.defined[id as usize]) {
+            bail!("Grammatik: Regel '{}' ist nicht definiert", name);
+        }
+        let grammar = Self { rules: parser.rules, classes: parser.classes, root };
+        grammar.start()?;
+        Ok(grammar)
+    }
+
+    /// Grammar of the JSON documents valid under `schema`.
+    pub fn from_json_schema(schema: &Value) -> Result<Self> {
+        Self::parse(&json_schema_ebnf(schema)?)
+    }
+
+    /// State before the first character.
+    pub fn start(&self) -> Result<State> {
+        self.close(vec![vec![Sym::N(self.root)]])
+    }
+
+    /// State after `text`, or `None` if no sentence starts with it.
+    pub fn feed(&self, state: &State, text: &str) -> Option<State> {
+        let mut state = state.clone();
+        for c in text.chars() {
+            let next: Vec<Vec<Sym>> = state
+                .stacks
+                .iter()
+                .filter(|s| matches!(s.last(), Some(Sym::T(t)) if self.classes[*t as usize].matches(c)))
+                .map(|s| s[..s.len() - 1].to_vec())
+                .collect();
+            if next.is_empty() {
+                return None;
+            }
+            state = self.close(next).ok()?;
+        }
+        Some(state)
+    }
+
+    /// Expands rules on top of the stacks until a class or nothing is on top.
+    fn close(&self, mut work: Vec<Vec<Sym>>) -> Result<State> {
+        let mut seen = HashSet::new();
+        let mut stacks = Vec::new();
+        while let Some(mut stack) = work.pop() {
+            if !seen.insert(stack.clone()) {
+                continue;
+            }
+            anyhow::ensure!(seen.len() <= MAX_STACKS, "Grammatik ist linksrekursiv oder zu mehrdeutig");
+            match stack.last() {
+                Some(Sym::N(rule)) => {
+                    let rule = *rule as usize;
+                    stack.pop();
+                    for alt in &self.rules[rule] {
+                        let mut next = stack.clone();
+                        next.extend(alt.iter().rev());
+                        work.push(next);
+                    }
+                }
+                _ => stacks.push(stack),
+            }
+        }
+        Ok(State { stacks })
+    }
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+    names: HashMap<String, u32>,
+    defined: Vec<bool>,
+    rules: Vec<Vec<Vec<Sym>>>,
+    classes: Vec<CharClass>,
+}
+
+impl Parser {
+    fn grammar(&mut self) -> Result<()> {
+        loop {
+            self.skip();
+            if self.pos >= self.chars.len() {
+                return Ok(());
+            }
+            let name = self.ident().context("Grammatik: Regelname erwartet")?;
+            self.skip();
+            anyhow::ensure!(self.eat("::="), "Grammatik: '::=' nach '{}' erwartet", name);
+            let alts = self.alternatives()?;
+            let id = self.rule(&name);
+            anyhow::ensure!(!self.defined[id as usize], "Grammatik: Regel '{}' doppelt definiert", name);
+            self.defined[id as usize] = true;
+            self.rules[id as usize] = alts;
+        }
+    }
+
+    fn alternatives(&mut self) -> Result<Vec<Vec<Sym>>> {
+        let mut alts = vec![self.sequence()?];
+        while self.eat("|") {
+            alts.push(self.sequence()?);
+        }
+        Ok(alts)
+    }
+
+    fn sequence(&mut self) -> Result<Vec<Sym>> {
+        let mut seq = Vec::new();
+        loop {
+            self.skip();
+            match self.peek() {
+                None | Some('|') | Some(')') => return Ok(seq),
+                _ if self.at_rule_start() => return Ok(seq),
+                _ => {}
+            }
+            let mut atom = self.atom()?;
+            while let Some(op @ ('*' | '+' | '?')) = self.peek() {
+                self.pos += 1;
+                let x = match atom.as_slice() {
+                    [sym] => *sym,
+                    _ => self.fresh(vec![atom.clone()]),
+                };
+                let id = self.fresh(Vec::new());
+                let Sym::N(r) = id else { unreachable!() };
+                self.rules[r as usize] = match op {
+                    '*' => vec![vec![x, id], vec![]],
+                    '+' => vec![vec![x, id], vec![x]],
+                    _ => vec![vec![x], vec![]],
+                };
+                atom = vec![id];
+            }
+            seq.extend(atom);
+        }
+    }
+
+    fn atom(&mut self) -> Result<Vec<Sym>> {
+        match self.peek() {
+            Some('"') => {
+                self.pos += 1;
+                let mut syms = Vec::new();
+                loop {
+                    match self.next().context("Grammatik: Literal nicht abgeschlossen")? {
+                        '"' => return Ok(syms),
+                        '\\' => {
+                            let c = self.escaped()?;
+                            syms.push(self.class(vec![(c, c)], false));
+                        }
+                        c => syms.push(self.class(vec![(c, c)], false)),
+                    }
+                }
+            }
+            Some('[') => {
+                self.pos += 1;
+                let negated = self.eat_char('^');
+                let mut ranges = Vec::new();
+                loop {
+                    let lo = match self.next().context("Grammatik: Zeichenklasse nicht abgeschlossen")? {
+                        ']' => return Ok(vec![self.class(ranges, negated)]),
+                        '\\' => self.escaped()?,
+                        c => c,
+                    };
+                    let hi = if self.peek() == Some('-') && self.chars.get(self.pos + 1) != Some(&']') {
+                        self.pos += 1;
+                        match self.next().context("Grammatik: Zeichenklasse nicht abgeschlossen")? {
+                            '\\' => self.escaped()?,
+                            c => c,
+                        }
+                    } else {
+                        lo
+                    };
+                    ranges.push((lo, hi));
+                }
+            }
+            Some('(') => {
+                self.pos += 1;
+                let alts = self.alternatives()?;
+                self.skip();
+                anyhow::ensure!(self.eat_char(')'), "Grammatik: ')' erwartet an Position {}", self.pos);
+                Ok(vec![self.fresh(alts)])
+            }
+            _ => {
+                let name = self.ident().with_context(|| format!("Grammatik: unerwartetes Zeichen an Position {}", self.pos))?;
+                Ok(vec![Sym::N(self.rule(&name))])
+            }
+        }
+    }
+
+    fn escaped(&mut self) -> Result<char> {
+        Ok(match self.next().context("Grammatik: Escape-Sequenz unvollständig")? {
+            'n' => '\n',
+            't' => '\t',
+            'r' => '\r',
+            c => c,
+        })
+    }
+
+    /// Whether the input continues with `name ::=`.
+    fn at_rule_start(&mut self) -> bool {
+        let start = self.pos;
+        let found = self.ident().is_some() && {
+            self.skip();
+            self.chars[self.pos..].starts_with(&[':', ':', '='])
+        };
+        self.pos = start;
+        found
+    }
+
+    fn ident(&mut self) -> Option<String> {
+        let start = self.pos;
+        while self.peek().is_some_and(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+            self.pos += 1;
+        }
+        (self.pos > start).then(|| self.chars[start..self.pos].iter().collect())
+    }
+
+    /// Skips whitespace and comments.
+    fn skip(&mut self) {
+        while let Some(c) = self.peek() {
+            if c == '#' {
+                while self.peek().is_some_and(|c| c != '\n') {
+                    self.pos += 1;
+                }
+            } else if c.is_whitespace() {
+                self.pos += 1;
+            } else {
+                break;
+            }
+        }
+    }
+
+    fn eat(&mut self, token: &str) -> bool {
+        self.skip();
+        let token: Vec<char> = token.chars().collect();
+        let found = self.chars[self.pos..].starts_with(&token);
+        if found {
+            self.pos += token.len();
+        }
+        found
+    }
+
+    fn eat_char(&mut self, c: char) -> bool {
+        let found = self.peek() == Some(c);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += 1;
+        Some(c)
+    }
+
+    fn rule(&mut self, name: &str) -> u32 {
+        if let Some(&id) = self.names.get(name) {
+            return id;
+        }
+        let Sym::N(id) = self.fresh(Vec::new()) else { unreachable!() };
+        self.defined[id as usize] = false;
+        self.names.insert(name.to_string(), id);
+        id
+    }
+
+    /// Adds an anonymous rule (groups, repetitions).
+    fn fresh(&mut self, alts: Vec<Vec<Sym>>) -> Sym {
+        self.rules.push(alts);
+        self.defined.push(true);
+        Sym::N(self.rules.len() as u32 - 1)
+    }
+
+    fn class(&mut self, ranges: Vec<(char, char)>, negated: bool) -> Sym {
+        self.classes.push(CharClass { ranges, negated });
+        Sym::T(self.classes.len() as u32 - 1)
+    }
+}
+
+/// Rules shared by all JSON schema grammars. `ws` sits between all tokens,
+/// since decoded text may space them.
+const JSON_RULES: &str = r#"
+ws      ::= [ \t\n]*
+value   ::= object | array | string | number | boolean | null
+object  ::= "{" ws ( string ws ":" ws value ws ( "," ws string ws ":" ws value ws )* )? "}"
+array   ::= "[" ws ( value ws ( "," ws value ws )* )? "]"
+string  ::= "\"" char* "\""
+char    ::= [^"\\] | "\\" ( ["\\/bfnrt] | "u" hex hex hex hex )
+hex     ::= [0-9a-fA-F]
+integer ::= "-"? ( "0" | [1-9] [0-9]* )
+number  ::= integer ( "." [0-9]+ )? ( [eE] [-+]? [0-9]+ )?
+boolean ::= "true" | "false"
+null    ::= "null"
+"#;
+
+/// EBNF of the JSON documents valid under `schema`.
+///
+/// Supports `type` (also as a list), `properties` (all listed properties
+/// are generated, in alphabetical order), `items`, `enum`, `const`, `anyOf` and `oneOf`.
+/// Other keywords do not restrict the output.
+fn json_schema_ebnf(schema: &Value) -> Result<String> {
+    let mut rules = Vec::new();
+    let root = schema_expr(schema, &mut rules)?;
+    let mut ebnf = format!("root ::= ws {} ws\n", root);
+    for (i, rule) in rules.iter().enumerate() {
+        ebnf.push_str(&format!("schema-{} ::= {}\n", i, rule));
+    }
+    ebnf.push_str(JSON_RULES);
+    Ok(ebnf)
+}
+
+/// EBNF literal matching `text` exactly.
+fn literal(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n"))
+}
+
+fn schema_expr(schema: &Value, rules: &mut Vec<String>) -> Result<String> {
+    let Some(schema) = schema.as_object() else {
+        // `true` oder fehlendes Schema: beliebiger Wert
+        return Ok("value".to_string());
+    };
+    if schema.contains_key("$ref") {
+        bail!("JSON-Schema: $ref wird nicht unterstützt");
+    }
+    if let Some(value) = schema.get("const") {
+        return Ok(literal(&value.to_string()));
+    }
+    if let Some(values) = schema.get("enum").and_then(Value::as_array) {
+        anyhow::ensure!(!values.is_empty(), "JSON-Schema: leeres enum");
+        let alts: Vec<String> = values.iter().map(|v| literal(&v.to_string())).collect();
+        return Ok(format!("( {} )", alts.join(" | ")));
+    }
+    if let Some(options) = schema.get("anyOf").or_else(|| schema.get("oneOf")).and_then(Value::as_array) {
+        let alts = options.iter().map(|o| schema_expr(o, rules)).collect::<Result<Vec<_>>>()?;
+        return Ok(format!("( {} )", alts.join(" | ")));
+    }
+    let types: Vec<&str> = match schema.get("type") {
+        Some(Value::String(t)) => vec![t.as_str()],
+        Some(Value::Array(ts)) => ts.iter().filter_map(Value::as_str).collect(),
+        _ => return Ok("value".to_string()),
+    };
+    let mut alts = Vec::new();
+    for ty in types {
+        alts.push(match ty {
+            "object" => match schema.get("properties").and_then(Value::as_object) {
+                Some(props) if !props.is_empty() => {
+                    let mut pairs = Vec::new();
+                    for (name, sub) in props {
+                        let value = schema_expr(sub, rules)?;
+                        pairs.push(format!("{} ws \":\" ws {} ws", literal(&Value::from(name.as_str()).to_string()), value));
+                    }
+                    rules.push(format!("\"{{\" ws {} \"}}\"", pairs.join(" \",\" ws ")));
+                    format!("schema-{}", rules.len() - 1)
+                }
+                _ => "object".to_string(),
+            },
+            "array" => match schema.get("items") {
+                Some(items) => {
+                    let item = schema_expr(items, rules)?;
+                    rules.push(format!("\"[\" ws ( {} ws ( \",\" ws {} ws )* )? \"]\"", item, item));
+                    format!("schema-{}", rules.len() - 1)
+                }
+                None => "array".to_string(),
+            },
+            "string" | "number" | "integer" | "boolean" | "null" => ty.to_string(),
+            other => bail!("JSON-Schema: Typ '{}' unbekannt", other),
+        });
+    }
+    Ok(format!("( {} )", alts.join(" | ")))
+}
+
+/// Token mask of one generation job.
+///
+/// The text is built from the tokens' pieces. A word token is preceded by a
+/// space where the grammar allows one and attached directly otherwise, so
+/// e.g. JSON keys come out without the spaces a plain decode would insert.
+pub struct Constraint {
+    grammar: Grammar,
+    state: State,
+    text: String,
+    /// Text and word-continuation flag per token id, built on first use.
+    pieces: Vec<Option<(String, bool)>>,
+    /// Text each allowed token adds, from the last [`Constraint::mask`].
+    allowed: Vec<Option<String>>,
+}
+
+impl Constraint {
+    /// Constraint of `sampling.grammar` or `sampling.json_schema`, if set.
+    pub fn from_params(params: &SamplingParams) -> Result<Option<Self>> {
+        let grammar = match (&params.grammar, &params.json_schema) {
+            (Some(_), Some(_)) => bail!("sampling: nur eines von grammar und json_schema angeben"),
+            (Some(ebnf), None) => Grammar::parse(ebnf)?,
+            (None, Some(schema)) => Grammar::from_json_schema(schema)?,
+            (None, None) => return Ok(None),
+        };
+        let state = grammar.start()?;
+        Ok(Some(Self { grammar, state, text: String::new(), pieces: Vec::new(), allowed: Vec::new() }))
+    }
+
+    /// Text generated so far.
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// Whether the text is a complete sentence of the grammar.
+    pub fn complete(&self) -> bool {
+        self.state.accepting()
+    }
+
+    /// Sets the logits of tokens that would leave the grammar to `-inf`;
+    /// `eos` is allowed once the text is complete. Returns the number of
+    /// allowed tokens.
+    pub fn mask(&mut self, logits: &mut [f32], tokenizer: &dyn Tokenizer, eos: Option<u32>) -> usize {
+        if self.pieces.len() != logits.len() {
+            self.pieces = (0..logits.len() as u32).map(|id| tokenizer.piece(id)).collect();
+        }
+        self.allowed = vec![None; logits.len()];
+        let mut count = 0;
+        for (id, logit) in logits.iter_mut().enumerate() {
+            let ok = if Some(id as u32) == eos {
+                self.complete()
+            } else if let Some((piece, joins)) = &self.pieces[id] {
+                let spaced = (!joins && !self.text.is_empty()).then(|| format!(" {}", piece));
+                let delta = spaced.into_iter().chain([piece.clone()]).find(|d| self.grammar.feed(&self.state, d).is_some());
+                let ok = delta.is_some();
+                self.allowed[id] = delta;
+                ok
+            } else {
+                false
+            };
+            if ok {
+                count += 1;
+            } else {
+                *logit = f32::NEG_INFINITY;
+            }
+        }
+        count
+    }
+
+    /// Appends token `id`, which the last [`Constraint::mask`] allowed.
+    pub fn advance(&mut self, id: u32) -> Result<()> {
+        let delta = self.allowed.get_mut(id as usize).and_then(Option::take).context("Token von der Grammatik nicht erlaubt")?;
+        self.state = self.grammar.feed(&self.state, &delta).context("Token von der Grammatik nicht erlaubt")?;
+        self.text.push_str(&delta);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn accepts(grammar: &Grammar, text: &str) -> bool {
+        grammar.feed(&grammar.start().unwrap(), text).is_some_and(|s| s.accepting())
+    }
+
+    fn prefix(grammar: &Grammar, text: &str) -> bool {
+        grammar.feed(&grammar.start().unwrap(), text).is_some()
+    }
+
+    #[test]
+    fn test_ebnf_operators() {
+        let grammar = Grammar::parse(
+            r#"
+            # Antwort mit Konfidenz
+            root   ::= answer ws "(" [0-9]+ "%)"
+            answer ::= "yes" | "no"
+            ws     ::= " "*
+            "#,
+        )
+        .unwrap();
+        assert!(accepts(&grammar, "yes (90%)"));
+        assert!(accepts(&grammar, "no(5%)"));
+        assert!(prefix(&grammar, "ye"));
+        assert!(!accepts(&grammar, "yes (%)"));
+        assert!(!prefix(&grammar, "maybe"));
+
+        let quoted = Grammar::parse(r#"root ::= "\"" [^"\\]* "\"" [a-c-]?"#).unwrap();
+        assert!(accepts(&quoted, "\"a b\"-"));
+        assert!(!prefix(&quoted, "\"a\"d"));
+    }
+
+    #[test]
+    fn test_ebnf_errors() {
+        assert!(Grammar::parse("start ::= \"a\"").is_err());
+        assert!(Grammar::parse("root ::= missing").is_err());
+        assert!(Grammar::parse("root ::= root \"a\" | \"b\"").is_err());
+        assert!(Grammar::parse("root ::= \"a").is_err());
+    }
+
+    #[test]
+    fn test_json_schema() {
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "name": {"type": "string"},
+                "age": {"type": "integer"},
+                "tags": {"type": "array", "items": {"enum": ["a", "b"]}},
+                "score": {"type": ["number", "null"]}
+            }
+        });
+        let grammar = Grammar::from_json_schema(&schema).unwrap();
+        assert!(accepts(&grammar, r#"{"age": 42, "name": "bob", "score": null, "tags": ["a", "b"]}"#));
+        assert!(accepts(&grammar, r#"{ "age" : -1 , "name" : "x" , "score" : 0.5e3 , "tags" : [ ] }"#));
+        assert!(prefix(&grammar, r#"{"age": 1, "name": "bo"#));
+        assert!(!prefix(&grammar, r#"{"ag""#));
+        assert!(!prefix(&grammar, r#"{"age": "1"#));
+        assert!(!accepts(&grammar, r#"{"age": 4.2, "name": "bob", "score": null, "tags": []}"#));
+
+        let any = Grammar::from_json_schema(&json!({})).unwrap();
+        assert!(accepts(&any, r#"[1, {"x": [true, "ä"]}]"#));
+        assert!(Grammar::from_json_schema(&json!({"$ref": "#/defs/a"})).is_err());
+    }
+
+    #[test]
+    fn test_constraint_spacing() {
+        let vocab = ["[UNK]", "{", "}", "\"", ":", "id", "##s", "7", "[SEP]"];
+        let tokenizer = crate::text::WordPieceTokenizer::from_tokens(vocab.iter().map(|t| t.to_string()).collect(), false).unwrap();
+        let params = SamplingParams { json_schema: Some(json!({"type": "object", "properties": {"ids": {"type": "integer"}}})), ..Default::default() };
+        let mut constraint = Constraint::from_params(&params).unwrap().unwrap();
+
+        for id in [1, 3, 5, 6, 3, 4, 7, 2] {
+            let mut logits = vec![0.0; vocab.len()];
+            assert!(constraint.mask(&mut logits, &tokenizer, Some(8)) > 0);
+            assert!(logits[id].is_finite(), "Token {} nach '{}' maskiert", vocab[id], constraint.text());
+            constraint.advance(id as u32).unwrap();
+        }
+        assert_eq!(constraint.text(), r#"{ "ids" : 7 }"#);
+        assert!(serde_json::from_str::<Value>(constraint.text()).is_ok());
+
+        // Vollständig: nur noch EOS erlaubt
+        let mut logits = vec![0.0; vocab.len()];
+        assert_eq!(constraint.mask(&mut logits, &tokenizer, Some(8)), 1);
+        assert!(logits[8].is_finite());
+    }
+}
@@ -0,0 +1,392 @@
+//! Process-wide runtime health and counters.
+//!
+//! Workers report warmup completion, finished jobs and batch statistics,
+//! the dispatcher reports accepted jobs. The state backs the readiness
+//! probe, graceful draining and the Prometheus metrics endpoint.
+
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+use crate::types::BatchStats;
+
+/// Upper bounds (seconds) of the queue wait histogram buckets.
+const QUEUE_WAIT_BUCKETS: [f64; 12] = [0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 10.0];
+
+/// Readiness and job counters of this runtime instance.
+pub struct Health {
+    workers_total: AtomicUsize,
+    workers_ready: AtomicUsize,
+    draining: AtomicBool,
+    paused: AtomicBool,
+    accepted: AtomicU64,
+    completed: AtomicU64,
+    rerouted: AtomicU64,
+    undelivered: AtomicU64,
+    invalid: AtomicU64,
+    non_finite: AtomicU64,
+    rebalanced: AtomicU64,
+    standby_activations: AtomicU64,
+    precision_fallbacks: AtomicU64,
+    canary_checks: AtomicU64,
+    canary_failures: AtomicU64,
+    requeued: AtomicU64,
+    cache_hits: AtomicU64,
+    cache_misses: AtomicU64,
+    prefix_cache_hits: AtomicU64,
+    prefix_cache_misses: AtomicU64,
+    batches: AtomicU64,
+    batch_jobs: AtomicU64,
+    batch_slots: AtomicU64,
+    batch_elements: AtomicU64,
+    batch_padding: AtomicU64,
+    queue_wait: [AtomicU64; QUEUE_WAIT_BUCKETS.len()],
+    queue_wait_count: AtomicU64,
+    queue_wait_sum_us: AtomicU64,
+    worker_batches: Mutex<BTreeMap<String, u64>>,
+    unhealthy_gpus: Mutex<BTreeMap<usize, String>>,
+}
+
+static HEALTH: Health = Health::new();
+
+/// Returns the global health state.
+pub fn health() -> &'static Health {
+    &HEALTH
+}
+
+impl Health {
+    pub const fn new() -> Self {
+        Self {
+            workers_total: AtomicUsize::new(0),
+            workers_ready: AtomicUsize::new(0),
+            draining: AtomicBool::new(false),
+            paused: AtomicBool::new(false),
+            accepted: AtomicU64::new(0),
+            completed: AtomicU64::new(0),
+            rerouted: AtomicU64::new(0),
+            undelivered: AtomicU64::new(0),
+            invalid: AtomicU64::new(0),
+            non_finite: AtomicU64::new(0),
+            rebalanced: AtomicU64::new(0),
+            standby_activations: AtomicU64::new(0),
+            precision_fallbacks: AtomicU64::new(0),
+            canary_checks: AtomicU64::new(0),
+            canary_failures: AtomicU64::new(0),
+            requeued: AtomicU64::new(0),
+            cache_hits: AtomicU64::new(0),
+            cache_misses: AtomicU64::new(0),
+            prefix_cache_hits: AtomicU64::new(0),
+            prefix_cache_misses: AtomicU64::new(0),
+            batches: AtomicU64::new(0),
+            batch_jobs: AtomicU64::new(0),
+            batch_slots: AtomicU64::new(0),
+            batch_elements: AtomicU64::new(0),
+            batch_padding: AtomicU64::new(0),
+            queue_wait: [const { AtomicU64::new(0) }; QUEUE_WAIT_BUCKETS.len()],
+            queue_wait_count: AtomicU64::new(0),
+            queue_wait_sum_us: AtomicU64::new(0),
+            worker_batches: Mutex::new(BTreeMap::new()),
+            unhealthy_gpus: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// Sets the number of workers that must be warm before the instance is ready.
+    pub fn set_workers(&self, n: usize) {
+        self.workers_total.store(n, Ordering::SeqCst);
+    }
+
+    /// Marks one worker as warmed up.
+    pub fn worker_ready(&self) {
+        self.workers_ready.fetch_add(1, Ordering::SeqCst);
+    }
+
+    /// Ready when all workers are warm and the instance is not draining.
+    pub fn is_ready(&self) -> bool {
+        let total = self.workers_total.load(Ordering::SeqCst);
+        !self.is_draining() && total > 0 && self.workers_ready.load(Ordering::SeqCst) >= total
+    }
+
+    /// Starts draining: readiness fails and sources stop taking new work.
+    pub fn start_drain(&self) {
+        self.draining.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_draining(&self) -> bool {
+        self.draining.load(Ordering::SeqCst)
+    }
+
+    /// Pauses the sources: they stop pulling new jobs, while queued and
+    /// in-flight batches still finish.
+    pub fn pause(&self) {
+        self.paused.store(true, Ordering::SeqCst);
+    }
+
+    /// Lets the sources pull jobs again.
+    pub fn resume(&self) {
+        self.paused.store(false, Ordering::SeqCst);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::SeqCst)
+    }
+
+    /// Counts a job handed to a worker.
+    pub fn job_accepted(&self) {
+        self.accepted.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Counts a job handed to another worker because its worker had stopped.
+    pub fn job_rerouted(&self) {
+        self.rerouted.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Counts a job no worker accepted.
+    pub fn job_undelivered(&self) {
+        self.undelivered.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Counts a job rejected by input validation.
+    pub fn job_invalid(&self) {
+        self.invalid.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Counts a job output containing NaN or Inf.
+    pub fn output_non_finite(&self) {
+        self.non_finite.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Counts jobs moved between worker queues.
+    pub fn jobs_rebalanced(&self, n: usize) {
+        self.rebalanced.fetch_add(n as u64, Ordering::Relaxed);
+    }
+
+    /// Counts a standby worker taking over from a failed one.
+    pub fn standby_activated(&self) {
+        self.standby_activations.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Counts a worker that switched to the fp32 fallback model.
+    pub fn precision_fallback(&self) {
+        self.precision_fallbacks.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Counts a checked canary output and whether it deviated.
+    pub fn canary_checked(&self, failed: bool) {
+        self.canary_checks.fetch_add(1, Ordering::Relaxed);
+        if failed {
+            self.canary_failures.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Counts an accepted job handed back to the dispatcher by a failed worker.
+    pub fn job_requeued(&self) {
+        self.requeued.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Counts a job answered from the result cache or sent on after a miss.
+    pub fn cache_lookup(&self, hit: bool) {
+        let counter = if hit { &self.cache_hits } else { &self.cache_misses };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Counts a lookup in the prompt prefix cache of a generation worker.
+    pub fn prefix_cache_lookup(&self, hit: bool) {
+        let counter = if hit { &self.prefix_cache_hits } else { &self.prefix_cache_misses };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Counts jobs whose results were stored.
+    pub fn jobs_completed(&self, n: usize) {
+        self.completed.fetch_add(n as u64, Ordering::Relaxed);
+    }
+
+    /// Counts a processed batch.
+    pub fn batch_done(&self) {
+        self.batches.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Records fill, padding and queue waits of a batch processed by `worker`.
+    pub fn record_batch(&self, worker: &str, jobs: usize, stats: &BatchStats) {
+        self.batch_done();
+        self.batch_jobs.fetch_add(jobs as u64, Ordering::Relaxed);
+        self.batch_slots.fetch_add(stats.slots as u64, Ordering::Relaxed);
+        self.batch_elements.fetch_add(stats.elements as u64, Ordering::Relaxed);
+        self.batch_padding.fetch_add(stats.padding as u64, Ordering::Relaxed);
+        for wait in &stats.queue_waits {
+            let secs = wait.as_secs_f64();
+            if let Some(i) = QUEUE_WAIT_BUCKETS.iter().position(|&le| secs <= le) {
+                self.queue_wait[i].fetch_add(1, Ordering::Relaxed);
+            }
+            self.queue_wait_count.fetch_add(1, Ordering::Relaxed);
+            self.queue_wait_sum_us.fetch_add(wait.as_micros() as u64, Ordering::Relaxed);
+        }
+        let mut per_worker = self.worker_batches.lock().unwrap();
+        *per_worker.entry(worker.to_string()).or_default() += 1;
+    }
+
+    /// Marks GPU `gpu` (CUDA ordinal) as unhealthy; its workers stop taking
+    /// batches. Returns whether it was healthy before.
+    #[cfg_attr(not(feature = "nvml"), allow(dead_code))]
+    pub fn mark_gpu_unhealthy(&self, gpu: usize, reason: &str) -> bool {
+        let mut gpus = self.unhealthy_gpus.lock().unwrap();
+        if gpus.contains_key(&gpu) {
+            return false;
+        }
+        tracing::error!("GPU {} als defekt markiert: {}", gpu, reason);
+        gpus.insert(gpu, reason.to_string());
+        true
+    }
+
+    /// Reason why GPU `gpu` was marked unhealthy, if it was.
+    pub fn gpu_unhealthy(&self, gpu: usize) -> Option<String> {
+        self.unhealthy_gpus.lock().unwrap().get(&gpu).cloned()
+    }
+
+    /// Jobs whose results were stored so far.
+    pub fn completed(&self) -> u64 {
+        self.completed.load(Ordering::Relaxed)
+    }
+
+    /// Jobs accepted but not yet completed.
+    pub fn in_flight(&self) -> u64 {
+        let done = self.completed.load(Ordering::Relaxed) + self.requeued.load(Ordering::Relaxed);
+        self.accepted.load(Ordering::Relaxed).saturating_sub(done)
+    }
+
+    /// Renders the counters in Prometheus text format with constant `labels`.
+    pub fn render_metrics(&self, labels: &[(String, String)]) -> String {
+        let pairs: Vec<String> = labels.iter().map(|(k, v)| label(k, v)).collect();
+        let labels = if pairs.is_empty() { String::new() } else { format!("{{{}}}", pairs.join(",")) };
+        // Konstante Labels plus ein zusätzliches (Worker, Bucket-Grenze)
+        let with = |extra: String| format!("{{{}}}", pairs.iter().cloned().chain([extra]).collect::<Vec<_>>().join(","));
+
+        let metrics: [(&str, &str, u64); 25] = [
+            ("omniengine_ready", "gauge", self.is_ready() as u64),
+            ("omniengine_draining", "gauge", self.is_draining() as u64),
+            ("omniengine_paused", "gauge", self.is_paused() as u64),
+            ("omniengine_workers_ready", "gauge", self.workers_ready.load(Ordering::SeqCst) as u64),
+            ("omniengine_jobs_accepted_total", "counter", self.accepted.load(Ordering::Relaxed)),
+            ("omniengine_jobs_completed_total", "counter", self.completed.load(Ordering::Relaxed)),
+            ("omniengine_jobs_rerouted_total", "counter", self.rerouted.load(Ordering::Relaxed)),
+            ("omniengine_jobs_undelivered_total", "counter", self.undelivered.load(Ordering::Relaxed)),
+            ("omniengine_jobs_invalid_total", "counter", self.invalid.load(Ordering::Relaxed)),
+            ("omniengine_outputs_non_finite_total", "counter", self.non_finite.load(Ordering::Relaxed)),
+            ("omniengine_jobs_rebalanced_total", "counter", self.rebalanced.load(Ordering::Relaxed)),
+            ("omniengine_standby_activations_total", "counter", self.standby_activations.load(Ordering::Relaxed)),
+            ("omniengine_precision_fallbacks_total", "counter", self.precision_fallbacks.load(Ordering::Relaxed)),
+            ("omniengine_canary_checks_total", "counter", self.canary_checks.load(Ordering::Relaxed)),
+            ("omniengine_canary_failures_total", "counter", self.canary_failures.load(Ordering::Relaxed)),
+            ("omniengine_jobs_requeued_total", "counter", self.requeued.load(Ordering::Relaxed)),
+            ("omniengine_cache_hits_total", "counter", self.cache_hits.load(Ordering::Relaxed)),
+            ("omniengine_cache_misses_total", "counter", self.cache_misses.load(Ordering::Relaxed)),
+            ("omniengine_prefix_cache_hits_total", "counter", self.prefix_cache_hits.load(Ordering::Relaxed)),
+            ("omniengine_prefix_cache_misses_total", "counter", self.prefix_cache_misses.load(Ordering::Relaxed)),
+            ("omniengine_batches_total", "counter", self.batches.load(Ordering::Relaxed)),
+            ("omniengine_batch_jobs_total", "counter", self.batch_jobs.load(Ordering::Relaxed)),
+            ("omniengine_batch_slots_total", "counter", self.batch_slots.load(Ordering::Relaxed)),
+            ("omniengine_batch_elements_total", "counter", self.batch_elements.load(Ordering::Relaxed)),
+            ("omniengine_batch_padding_elements_total", "counter", self.batch_padding.load(Ordering::Relaxed)),
+        ];
+        let mut out = String::new();
+        for (name, kind, value) in metrics {
+            let _ = writeln!(out, "# TYPE {} {}\n{}{} {}", name, kind, name, labels, value);
+        }
+
+        let _ = writeln!(out, "# TYPE omniengine_worker_batches_total counter");
+        for (worker, n) in self.worker_batches.lock().unwrap().iter() {
+            let _ = writeln!(out, "omniengine_worker_batches_total{} {}", with(label("worker", worker)), n);
+        }
+
+        let _ = writeln!(out, "# TYPE omniengine_gpu_unhealthy gauge");
+        for gpu in self.unhealthy_gpus.lock().unwrap().keys() {
+            let _ = writeln!(out, "omniengine_gpu_unhealthy{} 1", with(label("gpu", &gpu.to_string())));
+        }
+
+        let _ = writeln!(out, "# TYPE omniengine_queue_wait_seconds histogram");
+        let mut cumulative = 0;
+        for (le, count) in QUEUE_WAIT_BUCKETS.iter().zip(&self.queue_wait) {
+            cumulative += count.load(Ordering::Relaxed);
+            let _ = writeln!(out, "omniengine_queue_wait_seconds_bucket{} {}", with(label("le", &le.to_string())), cumulative);
+        }
+        let count = self.queue_wait_count.load(Ordering::Relaxed);
+        let sum = self.queue_wait_sum_us.load(Ordering::Relaxed) as f64 / 1e6;
+        let _ = writeln!(out, "omniengine_queue_wait_seconds_bucket{} {}", with(label("le", "+Inf")), count);
+        let _ = writeln!(out, "omniengine_queue_wait_seconds_sum{} {}", labels, sum);
+        let _ = writeln!(out, "omniengine_queue_wait_seconds_count{} {}", labels, count);
+        out
+    }
+}
+
+/// Formats one Prometheus label pair with an escaped value.
+pub(crate) fn label(key: &str, value: &str) -> String {
+    format!("{}=\"{}\"", key, value.replace('"', "\\\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_ready_after_warmup_until_drain() {
+        let h = Health::new();
+        assert!(!h.is_ready());
+        h.set_workers(2);
+        h.worker_ready();
+        assert!(!h.is_ready());
+        h.worker_ready();
+        assert!(h.is_ready());
+        h.start_drain();
+        assert!(!h.is_ready());
+    }
+
+    #[test]
+    fn test_unhealthy_gpus() {
+        let h = Health::new();
+        assert!(h.mark_gpu_unhealthy(1, "Xid 79"));
+        assert!(!h.mark_gpu_unhealthy(1, "Xid 48"));
+        assert_eq!(h.gpu_unhealthy(1).as_deref(), Some("Xid 79"));
+        assert_eq!(h.gpu_unhealthy(0), None);
+        let text = h.render_metrics(&[("pod".to_string(), "omni-0".to_string())]);
+        assert!(text.contains("omniengine_gpu_unhealthy{pod=\"omni-0\",gpu=\"1\"} 1"));
+    }
+
+    #[test]
+    fn test_in_flight_and_metrics_labels() {
+        let h = Health::new();
+        for _ in 0..3 {
+            h.job_accepted();
+        }
+        h.jobs_completed(2);
+        assert_eq!(h.in_flight(), 1);
+
+        let text = h.render_metrics(&[("pod".to_string(), "omni-0".to_string())]);
+        assert!(text.contains("omniengine_jobs_accepted_total{pod=\"omni-0\"} 3"));
+        assert!(text.contains("# TYPE omniengine_ready gauge"));
+    }
+
+    #[test]
+    fn test_batch_stats_metrics() {
+        let h = Health::new();
+        let stats = BatchStats {
+            slots: 8,
+            elements: 80,
+            padding: 30,
+            queue_waits: vec![Duration::from_micros(500), Duration::from_millis(40), Duration::from_secs(30)],
+        };
+        h.record_batch("gpu:0", 5, &stats);
+        h.record_batch("gpu:0", 5, &stats);
+
+        let text = h.render_metrics(&[("pod".to_string(), "omni-0".to_string())]);
+        assert!(text.contains("omniengine_batches_total{pod=\"omni-0\"} 2"));
+        assert!(text.contains("omniengine_batch_jobs_total{pod=\"omni-0\"} 10"));
+        assert!(text.contains("omniengine_batch_slots_total{pod=\"omni-0\"} 16"));
+        assert!(text.contains("omniengine_batch_padding_elements_total{pod=\"omni-0\"} 60"));
+        assert!(text.contains("omniengine_worker_batches_total{pod=\"omni-0\",worker=\"gpu:0\"} 2"));
+        assert!(text.contains("omniengine_queue_wait_seconds_bucket{pod=\"omni-0\",le=\"0.001\"} 2"));
+        assert!(text.contains("omniengine_queue_wait_seconds_bucket{pod=\"omni-0\",le=\"0.05\"} 4"));
+        assert!(text.contains("omniengine_queue_wait_seconds_bucket{pod=\"omni-0\",le=\"+Inf\"} 6"));
+        assert!(text.contains("omniengine_queue_wait_seconds_count{pod=\"omni-0\"} 6"));
+    }
+}
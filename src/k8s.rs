@@ -0,0 +1,236 @@
+//! Kubernetes lifecycle integration.
+//!
+//! * Probe server with `/healthz` (liveness), `/readyz` (ready once all
+//!   workers finished model warmup, failing while draining) and `/metrics`.
+//! * `/drain` for the `preStop` hook and SIGTERM handling: stop taking new
+//!   work and wait for in-flight jobs before the pod is killed.
+//! * `/pause` and `/resume` for maintenance windows: sources stop pulling
+//!   jobs until resumed, without failing readiness.
+//! * `/admin/recent-jobs` with the last finished jobs (see [`crate::recent`]).
+//! * `/openapi.json` with the OpenAPI description of all endpoints.
+//! * Metrics carry constant labels resolved from Downward-API env vars.
+//! * Redis lease based leader election for singleton sources.
+
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use redis::AsyncCommands;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+use tracing::{error, info, warn};
+
+use crate::health::{health, Health};
+use crate::types::K8sCfg;
+
+/// Resolves the configured metric labels (`label -> env var`) from the
+/// environment; unset variables are skipped.
+pub fn resolve_labels(cfg: &K8sCfg) -> Vec<(String, String)> {
+    cfg.labels
+        .iter()
+        .filter_map(|(label, var)| std::env::var(var).ok().map(|v| (label.clone(), v)))
+        .collect()
+}
+
+/// Starts draining and waits until all in-flight jobs are done or the
+/// timeout expires. Returns `true` if fully drained.
+pub async fn drain(state: &Health, timeout: Duration) -> bool {
+    state.start_drain();
+    wait_idle(state, timeout).await
+}
+
+/// Waits until all in-flight jobs are done or the timeout expires.
+pub(crate) async fn wait_idle(state: &Health, timeout: Duration) -> bool {
+    let deadline = Instant::now() + timeout;
+    while state.in_flight() > 0 {
+        if Instant::now() >= deadline {
+            warn!("Timeout: {} Jobs noch in Bearbeitung", state.in_flight());
+            return false;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    true
+}
+
+/// Answers a probe request path with status code and body.
+async fn route(path: &str, state: &Health, cfg: &K8sCfg, labels: &[(String, String)]) -> (u16, String) {
+    match path {
+        "/healthz" => (200, "ok\n".to_string()),
+        "/readyz" if state.is_ready() => (200, "ready\n".to_string()),
+        "/readyz" => (503, if state.is_draining() { "draining\n" } else { "warming up\n" }.to_string()),
+        "/metrics" => (200, state.render_metrics(labels) + &crate::drift::render_metrics(labels) + &crate::accounting::render_metrics(labels)),
+        "/openapi.json" => (200, crate::openapi::spec_json()),
+        "/admin/recent-jobs" => (200, serde_json::to_string_pretty(&crate::recent::snapshot()).unwrap_or_default()),
+        "/drain" => {
+            info!("Drain angefordert (preStop)");
+            if drain(state, Duration::from_secs(cfg.drain_timeout_secs)).await {
+                (200, "drained\n".to_string())
+            } else {
+                (503, "drain timeout\n".to_string())
+            }
+        }
+        "/pause" => {
+            info!("Pause angefordert");
+            state.pause();
+            if wait_idle(state, Duration::from_secs(cfg.drain_timeout_secs)).await {
+                (200, "paused\n".to_string())
+            } else {
+                (503, "paused, jobs still in flight\n".to_string())
+            }
+        }
+        "/resume" => {
+            info!("Fortsetzen angefordert");
+            state.resume();
+            (200, "resumed\n".to_string())
+        }
+        _ => (404, "not found\n".to_string()),
+    }
+}
+
+/// Serves the probe endpoints on `0.0.0.0:{port}` (minimal HTTP/1.1, GET only).
+pub async fn serve_probes(cfg: K8sCfg) -> Result<()> {
+    let listener = TcpListener::bind(("0.0.0.0", cfg.port)).await?;
+    let labels = resolve_labels(&cfg);
+    info!("Probe-Server auf Port {}", cfg.port);
+
+    loop {
+        let (mut sock, _) = listener.accept().await?;
+        let (cfg, labels) = (cfg.clone(), labels.clone());
+        tokio::spawn(async move {
+            let mut buf = [0u8; 2048];
+            let n = match sock.read(&mut buf).await {
+                Ok(n) => n,
+                Err(_) => return,
+            };
+            let request = String::from_utf8_lossy(&buf[..n]);
+            let path = request.split_whitespace().nth(1).unwrap_or("/");
+            let path = path.split('?').next().unwrap_or(path);
+
+            let (status, body) = route(path, health(), &cfg, &labels).await;
+            let reason = match status {
+                200 => "OK",
+                404 => "Not Found",
+                _ => "Service Unavailable",
+            };
+            let json = path.ends_with(".json") || path.starts_with("/admin/");
+            let content_type = if json { "application/json" } else { "text/plain; version=0.0.4" };
+            let response = format!(
+                "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                status,
+                reason,
+                content_type,
+                body.len(),
+                body
+            );
+            let _ = sock.write_all(response.as_bytes()).await;
+        });
+    }
+}
+
+/// Drains on SIGTERM and exits once in-flight jobs are done.
+#[cfg(unix)]
+pub async fn drain_on_sigterm(cfg: K8sCfg) -> Result<()> {
+    use tokio::signal::unix::{signal, SignalKind};
+    signal(SignalKind::terminate())?.recv().await;
+    info!("SIGTERM empfangen, beende nach Drain");
+    drain(health(), Duration::from_secs(cfg.drain_timeout_secs)).await;
+    std::process::exit(0);
+}
+
+/// Waits until this node holds the leader lease, then keeps renewing it.
+///
+/// The lease is a Redis key set with `NX` and a TTL of `lease_ms`. Losing the
+/// lease terminates the process so singleton sources never run twice; the
+/// pod is restarted by Kubernetes and competes again.
+pub async fn acquire_leadership(cfg: &K8sCfg, redis_url: &str, node: &str) -> Result<()> {
+    let client = redis::Client::open(redis_url)?;
+    let mut con = client.get_multiplexed_async_connection().await?;
+    let opts = redis::SetOptions::default()
+        .conditional_set(redis::ExistenceCheck::NX)
+        .with_expiration(redis::SetExpiry::PX(cfg.lease_ms));
+
+    loop {
+        let acquired: Option<String> = con.set_options(&cfg.lease_key, node, opts).await?;
+        if acquired.is_some() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(cfg.lease_ms / 3)).await;
+    }
+    info!("Knoten {} ist Leader ({})", node, cfg.lease_key);
+
+    // Lease nur verlängern, solange sie noch uns gehört
+    let renew = redis::Script::new(
+        "if redis.call('get', KEYS[1]) == ARGV[1] then return redis.call('pexpire', KEYS[1], ARGV[2]) else return 0 end",
+    );
+    let (key, node, lease_ms) = (cfg.lease_key.clone(), node.to_string(), cfg.lease_ms);
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(Duration::from_millis(lease_ms / 3)).await;
+            let renewed: redis::RedisResult<i64> =
+                renew.key(&key).arg(&node).arg(lease_ms).invoke_async(&mut con).await;
+            match renewed {
+                Ok(1) => {}
+                Ok(_) => {
+                    error!("Leader-Lease {} verloren, beende Prozess", key);
+                    std::process::exit(1);
+                }
+                Err(e) => warn!("Leader-Lease konnte nicht verlängert werden: {}", e),
+            }
+        }
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cfg() -> K8sCfg {
+        toml::from_str("drain_timeout_secs = 1").unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_probe_routes() {
+        let state = Health::new();
+        assert_eq!(route("/healthz", &state, &cfg(), &[]).await.0, 200);
+        assert_eq!(route("/readyz", &state, &cfg(), &[]).await.0, 503);
+
+        state.set_workers(1);
+        state.worker_ready();
+        assert_eq!(route("/readyz", &state, &cfg(), &[]).await.0, 200);
+        assert!(route("/metrics", &state, &cfg(), &[]).await.1.contains("omniengine_ready 1"));
+        assert_eq!(route("/nope", &state, &cfg(), &[]).await.0, 404);
+
+        let (status, body) = route("/admin/recent-jobs", &state, &cfg(), &[]).await;
+        assert_eq!(status, 200);
+        assert!(serde_json::from_str::<serde_json::Value>(&body).unwrap().is_array());
+    }
+
+    #[tokio::test]
+    async fn test_drain_waits_for_in_flight() {
+        let state = Health::new();
+        state.set_workers(1);
+        state.worker_ready();
+        state.job_accepted();
+
+        assert_eq!(route("/drain", &state, &cfg(), &[]).await.0, 503); // Timeout
+        assert_eq!(route("/readyz", &state, &cfg(), &[]).await.1, "draining\n");
+
+        state.jobs_completed(1);
+        assert!(drain(&state, Duration::from_secs(1)).await);
+    }
+
+    #[tokio::test]
+    async fn test_pause_and_resume() {
+        let state = Health::new();
+        state.set_workers(1);
+        state.worker_ready();
+
+        assert_eq!(route("/pause", &state, &cfg(), &[]).await, (200, "paused\n".to_string()));
+        assert!(state.is_paused());
+        assert!(state.is_ready());
+        assert!(route("/metrics", &state, &cfg(), &[]).await.1.contains("omniengine_paused 1"));
+
+        assert_eq!(route("/resume", &state, &cfg(), &[]).await.0, 200);
+        assert!(!state.is_paused());
+    }
+}
@@ -0,0 +1,209 @@
+//! Per-session KV-cache management for transformer decoders.
+//!
+//! Each generation worker (one per device) owns a `KvCacheManager` with a
+//! memory budget. Sessions check their cache out before a request and back in
+//! afterwards; least recently used sessions are evicted when the budget is
+//! exceeded. A cache is only reused if its tokens are a prefix of the new
+//! prompt, so multi-turn conversations skip re-encoding the shared history.
+//! Caching needs a decoder that keeps past key/values
+//! ([`crate::generation::DecoderModel::supports_kv_cache`]); the built-in
+//! engine adapter does not, so with it the caches stay unused.
+//!
+//! A second manager holds the state of shared prompt prefixes (system
+//! prompts, templates) under the hash of their tokens ([`prefix_key`]);
+//! lookups copy the cache instead of checking it out.
+
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+
+use ndarray::ArrayD;
+
+/// Cached attention state for a token sequence.
+///
+/// `tensors` holds the backend-specific past key/value tensors that belong to
+/// `tokens`. Backends without KV support keep `tensors` empty.
+#[derive(Debug, Clone, Default)]
+pub struct KvCache {
+    pub tokens: Vec<u32>,
+    pub tensors: Vec<ArrayD<f32>>,
+}
+
+impl KvCache {
+    /// Memory held by the cached tensors in bytes.
+    pub fn bytes(&self) -> usize {
+        self.tensors.iter().map(|t| t.len() * std::mem::size_of::<f32>()).sum()
+    }
+
+    /// Number of leading prompt tokens already covered by this cache.
+    pub fn reusable_prefix(&self, prompt: &[u32]) -> usize {
+        if prompt.starts_with(&self.tokens) { self.tokens.len() } else { 0 }
+    }
+}
+
+struct Entry {
+    cache: KvCache,
+    bytes: usize,
+    last_used: u64,
+}
+
+/// LRU store of session KV caches with a byte budget.
+pub struct KvCacheManager {
+    budget_bytes: usize,
+    used_bytes: usize,
+    clock: u64,
+    entries: HashMap<String, Entry>,
+}
+
+impl KvCacheManager {
+    /// Creates a manager holding at most `budget_bytes` of cached tensors.
+    pub fn new(budget_bytes: usize) -> Self {
+        Self { budget_bytes, used_bytes: 0, clock: 0, entries: HashMap::new() }
+    }
+
+    /// Takes the session cache out of the store.
+    ///
+    /// Returns an empty cache if the session is unknown or its cached tokens
+    /// are not a prefix of `prompt`.
+    pub fn checkout(&mut self, session: &str, prompt: &[u32]) -> KvCache {
+        match self.entries.remove(session) {
+            Some(entry) => {
+                self.used_bytes -= entry.bytes;
+                if entry.cache.reusable_prefix(prompt) > 0 {
+                    entry.cache
+                } else {
+                    KvCache::default()
+                }
+            }
+            None => KvCache::default(),
+        }
+    }
+
+    /// Returns a copy of the cache stored under `key` if its tokens are a
+    /// prefix of `prompt`, and marks it as recently used.
+    pub fn get(&mut self, key: &str, prompt: &[u32]) -> Option<KvCache> {
+        let entry = self.entries.get_mut(key).filter(|e| e.cache.reusable_prefix(prompt) > 0)?;
+        self.clock += 1;
+        entry.last_used = self.clock;
+        Some(entry.cache.clone())
+    }
+
+    /// Stores the session cache, evicting least recently used sessions until
+    /// the budget fits. Caches larger than the whole budget are dropped, as are
+    /// caches without tensors (nothing to reuse).
+    pub fn checkin(&mut self, session: &str, cache: KvCache) {
+        self.remove(session);
+        if cache.tensors.is_empty() {
+            return;
+        }
+        let bytes = cache.bytes();
+        if bytes > self.budget_bytes {
+            tracing::debug!("KV-Cache für Session {} überschreitet Budget ({} Bytes)", session, bytes);
+            return;
+        }
+
+        while self.used_bytes + bytes > self.budget_bytes {
+            let Some(oldest) = self
+                .entries
+                .iter()
+                .min_by_key(|(_, e)| e.last_used)
+                .map(|(k, _)| k.clone())
+            else {
+                break;
+            };
+            tracing::debug!("KV-Cache verdrängt: Session {}", oldest);
+            self.remove(&oldest);
+        }
+
+        self.clock += 1;
+        self.used_bytes += bytes;
+        self.entries.insert(session.to_string(), Entry { cache, bytes, last_used: self.clock });
+    }
+
+    /// Drops the cache of a session (e.g. when the session is closed).
+    pub fn remove(&mut self, session: &str) {
+        if let Some(entry) = self.entries.remove(session) {
+            self.used_bytes -= entry.bytes;
+        }
+    }
+
+    /// Bytes currently held by cached sessions.
+    #[cfg(test)]
+    pub fn used_bytes(&self) -> usize {
+        self.used_bytes
+    }
+
+    /// Number of cached sessions.
+    #[cfg(test)]
+    pub fn session_count(&self) -> usize {
+        self.entries.len()
+    }
+}
+
+/// Cache key of a prompt prefix.
+pub fn prefix_key(tokens: &[u32]) -> String {
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    tokens.hash(&mut hasher);
+    format!("{:016x}", hasher.finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::Array;
+
+    fn cache(tokens: &[u32], floats: usize) -> KvCache {
+        KvCache { tokens: tokens.to_vec(), tensors: vec![Array::zeros(floats).into_dyn()] }
+    }
+
+    #[test]
+    fn test_checkout_reuses_prefix() {
+        let mut mgr = KvCacheManager::new(1024);
+        mgr.checkin("s1", cache(&[1, 2, 3], 8));
+
+        let reused = mgr.checkout("s1", &[1, 2, 3, 4]);
+        assert_eq!(reused.reusable_prefix(&[1, 2, 3, 4]), 3);
+        assert_eq!(mgr.session_count(), 0);
+
+        mgr.checkin("s1", cache(&[1, 2, 3], 8));
+        assert!(mgr.checkout("s1", &[9, 9]).tokens.is_empty());
+    }
+
+    #[test]
+    fn test_lru_eviction_within_budget() {
+        let mut mgr = KvCacheManager::new(64); // 16 f32
+        mgr.checkin("a", cache(&[1], 8));
+        mgr.checkin("b", cache(&[2], 8));
+
+        // "a" erneut benutzen, dann verdrängt "c" die Session "b"
+        let a = mgr.checkout("a", &[1]);
+        mgr.checkin("a", a);
+        mgr.checkin("c", cache(&[3], 8));
+
+        assert_eq!(mgr.session_count(), 2);
+        assert_eq!(mgr.used_bytes(), 64);
+        assert!(mgr.checkout("b", &[2]).tokens.is_empty());
+        assert_eq!(mgr.checkout("a", &[1]).tokens, vec![1]);
+    }
+
+    #[test]
+    fn test_get_copies_prefix_cache() {
+        let mut mgr = KvCacheManager::new(1024);
+        let key = prefix_key(&[7, 8]);
+        assert_ne!(key, prefix_key(&[8, 7]));
+        mgr.checkin(&key, cache(&[7, 8], 8));
+
+        assert_eq!(mgr.get(&key, &[7, 8, 1]).unwrap().tokens, vec![7, 8]);
+        assert_eq!(mgr.get(&key, &[7, 8, 2]).unwrap().tokens, vec![7, 8]);
+        assert!(mgr.get(&key, &[7, 9]).is_none());
+        assert_eq!(mgr.session_count(), 1);
+    }
+
+    #[test]
+    fn test_oversized_cache_dropped() {
+        let mut mgr = KvCacheManager::new(16);
+        mgr.checkin("big", cache(&[1], 100));
+        mgr.checkin("tokens-only", KvCache { tokens: vec![1], tensors: vec![] });
+        assert_eq!(mgr.session_count(), 0);
+        assert_eq!(mgr.used_bytes(), 0);
+    }
+}
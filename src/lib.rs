@@ -26,12 +26,59 @@
 //! ```
 
 mod types;
-mod storage { pub mod redis_store; }
+pub mod error;
+mod storage { pub mod compression; pub mod redis_store; pub mod vector_store; }
 mod engine;
-mod batcher;
+pub mod batcher;
+mod adaptive;
+mod rebalance;
+mod stages;
 mod worker;
 mod pipeline;
+mod audio;
+mod text;
+mod processors;
+mod sidecar;
+mod generation;
+mod kv_cache;
+mod grammar;
+mod detection;
+mod segmentation;
+mod tta;
+mod classification;
+mod filter;
+mod session;
+mod cache;
+mod validation;
+mod output_check;
+mod probes;
+mod drift;
+mod prediction_log;
+mod reproducibility;
+mod cloudevents;
+mod trace_context;
+mod cluster;
+mod health;
+mod gpu_health;
+mod accounting;
+mod recent;
+mod k8s;
+#[cfg(unix)]
+mod systemd;
+mod timeseries;
+mod source;
+mod loadgen;
+mod server;
+mod npy;
+#[cfg(feature = "protobuf")]
+pub mod proto;
+mod results;
+#[cfg(feature = "python")]
+mod python;
+pub mod openapi;
+pub mod cli;
 
+use crate::error::OmniError;
 use crate::storage::redis_store::RedisStorage;
 use crate::types::{Config, Job};
 pub mod scripting;
@@ -40,7 +87,7 @@ use pipeline::Pipeline;
 use tokio::sync::mpsc;
 use tracing::{info, Level};
 use tracing_subscriber::EnvFilter;
-use anyhow::Result;
+use anyhow::{Context, Result};
 use std::{fs, sync::Arc};
 
 /// Starts the OmniEngine runtime with configuration from runtime.toml.
@@ -68,76 +115,452 @@ use std::{fs, sync::Arc};
 /// }
 /// ```
 pub async fn start_runtime() -> Result<()> {
+    init_tracing();
+    serve("runtime.toml").await
+}
+
+/// Initializes the tracing subscriber (INFO unless `RUST_LOG` says otherwise).
+///
+/// Logs go to stderr so command output on stdout stays machine-readable.
+pub fn init_tracing() {
     tracing_subscriber::fmt()
+        .with_writer(std::io::stderr)
         .with_env_filter(EnvFilter::from_default_env().add_directive(Level::INFO.into()))
         .init();
+}
+
+/// Reads and parses a runtime configuration file.
+pub(crate) fn load_config(path: &str) -> Result<Config> {
+    let raw = fs::read_to_string(path).with_context(|| format!("Konfiguration konnte nicht gelesen werden: {}", path))?;
+    let mut cfg: Config = toml::from_str(&raw).with_context(|| format!("Ungültige Konfiguration: {}", path))?;
+    if let Some(sidecar) = sidecar::load(&mut cfg)? {
+        tracing::info!("Modell-Metadaten geladen: {}", sidecar.display());
+    }
+    Ok(cfg)
+}
 
-    let cfg: Config = toml::from_str(&fs::read_to_string("runtime.toml")?)?;
+/// Builds the pre/postprocessing pipeline and the optional text encoder
+/// from the configured task sections.
+pub(crate) fn build_pipeline(cfg: &Config) -> Result<(Pipeline, Option<text::TextEncoder>)> {
     let spec = cfg.input_spec();
-    info!("Starte Runtime: backend={}, batch={}x{}x{}",
-        cfg.model.backend, spec.batch, spec.height, spec.width);
+    let mut pipeline = Pipeline::new(None, None);
+    if let Some(audio_cfg) = &cfg.audio {
+        pipeline = pipeline.with_pre(audio::MelSpectrogram::new(audio_cfg)?);
+    }
+    if !cfg.input.mean.is_empty() || !cfg.input.std.is_empty() {
+        anyhow::ensure!(cfg.audio.is_none(), "[input] mean/std ist mit [audio] nicht nutzbar");
+        let (mean, std) = (cfg.input.mean.clone(), cfg.input.std.clone());
+        pipeline = pipeline.with_pre(processors::Normalize::new(mean, std, cfg.input.channels)?);
+    }
+    let text_encoder = match &cfg.text {
+        Some(text_cfg) => {
+            pipeline = pipeline.with_output(text::TextOutput::new(text_cfg)?);
+            Some(text::TextEncoder::new(text_cfg)?)
+        }
+        None => None,
+    };
+    if let Some(cls_cfg) = &cfg.classification {
+        pipeline = pipeline.with_output(classification::ClassificationOutput::new(cls_cfg)?);
+    }
+    if let Some(det_cfg) = &cfg.detection {
+        pipeline = pipeline.with_output(detection::DetectionOutput::new(det_cfg, spec.width, spec.height));
+    }
+    if let Some(seg_cfg) = &cfg.segmentation {
+        pipeline = pipeline.with_output(segmentation::SegmentationOutput::new(seg_cfg)?);
+    }
+    if let Some(filter_cfg) = &cfg.filter {
+        let inner = Arc::clone(&pipeline.output);
+        pipeline = pipeline.with_output(filter::FilteredOutput::new(inner, filter::OutputFilter::new(filter_cfg)?));
+    }
+    if let Some(repro) = reproducibility::Reproducibility::from_config(cfg) {
+        let inner = Arc::clone(&pipeline.output);
+        pipeline = pipeline.with_output(reproducibility::RecordedOutput::new(inner, repro.provenance));
+    }
+    if cfg.embedding.as_ref().is_some_and(|e| e.normalize) {
+        pipeline = pipeline.with_post(processors::L2Normalize);
+    }
+    Ok((pipeline, text_encoder))
+}
 
-    // Redis
-    let store = RedisStorage::new(&cfg.redis.url, cfg.redis.out_prefix.clone())?;
+/// Running runtime core: Redis store, job dispatcher and one worker per
+/// device. Jobs are submitted through `tx`.
+pub(crate) struct Runtime {
+    pub tx: mpsc::Sender<Job>,
+    pub store: RedisStorage,
+    handles: Vec<tokio::task::JoinHandle<()>>,
+}
 
-    // Pipeline als Arc (wird zwischen Workern geteilt)
-    let pipeline = Arc::new(Pipeline::new(None, None));
+impl Runtime {
+    /// Starts dispatcher and workers. Service concerns (probes, signals,
+    /// sources) are left to the caller, so the core can also be embedded.
+    pub async fn start(cfg: &Config, pipeline: Pipeline) -> Result<Self> {
+        // Redis
+        let store = RedisStorage::new(&cfg.redis.url, cfg.redis.out_prefix.clone())?
+            .with_chunk_elements(cfg.redis.chunk_elements)
+            .with_tensor_dtype(cfg.redis.tensor_dtype)
+            .with_compression(cfg.redis.compression.as_ref().map(storage::compression::Compression::new).transpose()?);
 
-    // Input-Queue
-    let (tx, rx_main) = mpsc::channel::<Job>(1024);
+        // Pipeline als Arc (wird zwischen Workern geteilt)
+        let pipeline = Arc::new(pipeline);
+        let buckets = cfg.audio.as_ref().map(|a| a.buckets.clone()).unwrap_or_default();
+        let mut windower = cfg.timeseries.as_ref().map(timeseries::Windower::new).transpose()?;
+        let validator = validation::InputValidator::from_config(cfg)?;
+        error::set_language(cfg.server.language);
+        drift::init(cfg, store.clone())?;
+        prediction_log::init(cfg)?;
+        engine::fallback::init(cfg, store.clone());
+        probes::init(cfg, store.clone())?;
 
-    // Worker je GPU
-    let mut handles = vec![];
-    let gpu_ids = if cfg.model.device == "gpu" && !cfg.model.gpu_ids.is_empty() {
-        cfg.model.gpu_ids.clone()
-    } else {
-        vec![usize::MAX] // „CPU“ oder default
-    };
+        // Input-Queue
+        let (tx, rx_main) = mpsc::channel::<Job>(1024);
 
-    // Dispatcher-Task: verteilt Jobs an alle Worker-Sender
-    let mut worker_senders = vec![];
-    for gpu in gpu_ids.into_iter() {
-        let (tx_w, rx_w) = mpsc::channel::<Job>(512);
-        worker_senders.push((gpu, rx_w, tx_w));
-    }
-
-    // Ein Dispatcher, der rx_main liest und Jobs round-robin an tx_w verteilt
-    tokio::spawn({
-        let mut worker_idx = 0usize;
-        let senders: Vec<_> = worker_senders.iter().map(|(_, _, tx)| tx.clone()).collect();
-        async move {
-            let mut rx_main = rx_main;
-            while let Some(job) = rx_main.recv().await {
-                let tx = &senders[worker_idx % senders.len()];
-                let _ = tx.send(job).await;
-                worker_idx = worker_idx.wrapping_add(1);
+        // Worker je GPU
+        let mut handles = vec![];
+        // UUIDs (MIG) vor der ersten Engine in CUDA-Ordinalzahlen auflösen
+        let devices = match cfg.model.device.as_str() {
+            "gpu" => engine::devices::assign(&cfg.model)?,
+            _ => engine::devices::Assignment { gpus: vec![], standby: vec![], visible: None },
+        };
+        let gpu_ids = if !devices.gpus.is_empty() {
+            devices.gpus.clone()
+        } else {
+            vec![usize::MAX] // „CPU“ oder default
+        };
+
+        // Standby-Worker laden das Modell vorab, bekommen aber erst nach einem Ausfall Jobs
+        let standby_ids = devices.standby;
+        let probed: Vec<usize> = devices.gpus.iter().chain(&standby_ids).copied().collect();
+        gpu_health::spawn(cfg, &probed)?;
+        accounting::init(cfg, &probed)?;
+        recent::init(cfg.k8s.as_ref().map_or(0, |k8s| k8s.recent_jobs));
+        health::health().set_workers(gpu_ids.len() + standby_ids.len());
+
+        // Dispatcher-Task: verteilt Jobs an alle Worker-Sender
+        let mut worker_senders = vec![];
+        for (gpu, standby) in gpu_ids.into_iter().map(|g| (g, false)).chain(standby_ids.into_iter().map(|g| (g, true))) {
+            let (tx_w, rx_w) = mpsc::channel::<Job>(512);
+            worker_senders.push((gpu, standby, rx_w, tx_w));
+        }
+
+        // Ein Dispatcher, der rx_main liest und Jobs round-robin an tx_w verteilt
+        tokio::spawn({
+            let mut worker_idx = 0usize;
+            let mut senders: Vec<_> = worker_senders.iter().filter(|w| !w.1).map(|w| w.3.clone()).collect();
+            let mut standby: Vec<_> = worker_senders.iter().filter(|w| w.1).map(|w| w.3.clone()).rev().collect();
+            let dispatch_store = store.clone();
+            // Zustandsbehaftete Worker (Generierung, Sessions) werden nie gecacht
+            let cache = match cfg.generation.is_none() && cfg.session.is_none() {
+                true => cache::ResultCache::from_config(cfg)?.map(Arc::new),
+                false => None,
+            };
+            if let Some(cache) = &cache {
+                cache.spawn_collector();
+            }
+            async move {
+                let mut rx_main = rx_main;
+                while let Some(mut job) = rx_main.recv().await {
+                    promote_standby(&mut senders, &mut standby);
+                    trace_context::sanitize(&mut job.meta);
+                    if let Some(v) = &validator {
+                        if let Err(e) = v.check(&job) {
+                            invalid_input(&dispatch_store, job, e).await;
+                            continue;
+                        }
+                    }
+                    // Zeitreihen: Punkte puffern, nur vollständige Fenster weiterreichen
+                    if let Some(w) = windower.as_mut() {
+                        // Punkte liegen nur im Speicher: beim Puffern quittieren
+                        if let Some(ack) = job.ack.take() {
+                            ack.done();
+                        }
+                        job = match w.push(job) {
+                            Ok(Some(window)) => window,
+                            Ok(None) => continue,
+                            Err(e) => {
+                                tracing::warn!("Zeitreihen-Punkt verworfen: {}", e);
+                                continue;
+                            }
+                        };
+                        tracing::trace!("Fenster {} ({} Serien)", job.id, w.series_count());
+                    }
+                    if let Some(drift) = drift::monitor() {
+                        drift.observe_input(job.tensor.view());
+                    }
+                    // Variable Audio-Längen auf Buckets auffüllen
+                    if !buckets.is_empty() {
+                        job.tensor = audio::pad_to_bucket(job.tensor, &buckets);
+                    }
+                    // Session-Jobs bleiben auf demselben Worker (State liegt dort)
+                    let (idx, sticky) = match job.meta.get("session_id").and_then(|s| s.as_str()) {
+                        Some(s) => (session::sticky_worker(s, senders.len()), true),
+                        // Bevorzugter Worker (z. B. Kafka-Partition), fällt bei Ausfall weiter
+                        None => match job.meta.get(source::AFFINITY).and_then(|a| a.as_u64()) {
+                            Some(a) => ((a % senders.len() as u64) as usize, false),
+                            None => {
+                                worker_idx = worker_idx.wrapping_add(1);
+                                (worker_idx % senders.len(), false)
+                            }
+                        },
+                    };
+                    // Identische Eingaben: gecachtes Ergebnis statt Inferenz
+                    if let Some(cache) = &cache {
+                        let hit = cache.lookup(&job);
+                        health::health().cache_lookup(hit.is_some());
+                        if let Some(result) = hit {
+                            answer_from_cache(&dispatch_store, job, result).await;
+                            continue;
+                        }
+                    }
+                    job.enqueued.get_or_insert_with(std::time::Instant::now);
+                    match deliver(&senders, idx, sticky, job).await {
+                        Ok(()) => health::health().job_accepted(),
+                        Err(job) => reject(&dispatch_store, job).await,
+                    }
+                }
             }
+        });
+
+        // Worker-Queues teilen sich Worker und Rebalancer; Generierungs- und
+        // Session-Worker halten ihre Queue dauerhaft und werden nie umverteilt
+        let worker_senders: Vec<_> = worker_senders
+            .into_iter()
+            .map(|(gpu, standby, rx_w, tx_w)| (gpu, standby, Arc::new(tokio::sync::Mutex::new(rx_w)), tx_w))
+            .collect();
+        // Canaries gehen auch an Standby-Worker, damit sie vor der Übernahme geprüft sind
+        probes::spawn(
+            worker_senders
+                .iter()
+                .map(|(gpu, _, _, tx_w)| (accounting::device_key(Some(*gpu).filter(|&g| g != usize::MAX)), tx_w.downgrade()))
+                .collect(),
+        );
+        if let Some(rb) = &cfg.queue.rebalance {
+            let queues = worker_senders
+                .iter()
+                .filter(|w| !w.1)
+                .map(|(_, _, rx_w, tx_w)| rebalance::QueueHandle { tx: tx_w.downgrade(), rx: Arc::clone(rx_w) })
+                .collect();
+            rebalance::spawn(rb.clone(), queues);
         }
+
+        // Worker starten
+        for (gpu, _, rx_w, _) in worker_senders {
+            let cfg_cl = cfg.clone();
+            let store_cl = store.clone();
+            let pipeline_cl = Arc::clone(&pipeline);
+            let requeue = tx.downgrade();
+
+            handles.push(tokio::spawn(async move {
+                let device = if gpu == usize::MAX { None } else { Some(gpu) };
+                let queue = Arc::clone(&rx_w);
+                if let Err(mut e) = worker::run_gpu_worker(cfg_cl, device, rx_w, store_cl, (*pipeline_cl).clone()).await {
+                    eprintln!("[worker gpu={:?}] error: {:?}", device, e);
+                    // Bereits entnommene, noch nicht gestapelte Jobs zuerst
+                    let mut taken = e.downcast_mut::<worker::Evicted>().map(|ev| std::mem::take(&mut ev.jobs)).unwrap_or_default();
+                    // Queue schließen (Standby übernimmt) und wartende Jobs neu verteilen
+                    let mut rx = queue.lock().await;
+                    rx.close();
+                    if let Some(tx) = requeue.upgrade() {
+                        taken.extend(std::iter::from_fn(|| rx.try_recv().ok()));
+                        for job in taken {
+                            health::health().job_requeued();
+                            if let Err(e) = tx.send(job).await {
+                                tracing::warn!("Job {} nach Worker-Ausfall nicht neu verteilt", e.0.id);
+                                health::health().job_undelivered();
+                            }
+                        }
+                    }
+                }
+            }));
+        }
+
+        Ok(Self { tx, store, handles })
+    }
+
+    /// Closes the input and waits until the workers have finished all jobs.
+    pub async fn join(self) {
+        drop(self.tx);
+        for h in self.handles {
+            let _ = h.await;
+        }
+        prediction_log::flush().await;
+    }
+}
+
+/// Control handle of the runtime in this process.
+///
+/// Pausing stops all sources from pulling new jobs; jobs already queued and
+/// batches in flight still finish. Useful for maintenance windows and model
+/// hot-swaps. The same is available on the probe server as `/pause` and
+/// `/resume`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RuntimeHandle;
+
+impl RuntimeHandle {
+    pub fn pause(&self) {
+        health::health().pause();
+    }
+
+    pub fn resume(&self) {
+        health::health().resume();
+    }
+
+    pub fn is_paused(&self) -> bool {
+        health::health().is_paused()
+    }
+
+    /// Waits until all accepted jobs are stored; `false` on timeout.
+    pub async fn wait_idle(&self, timeout: std::time::Duration) -> bool {
+        k8s::wait_idle(health::health(), timeout).await
+    }
+}
+
+/// Replaces workers whose queue has closed (worker failed) with warm
+/// standby workers, keeping the position so session routing stays stable.
+fn promote_standby(senders: &mut [mpsc::Sender<Job>], standby: &mut Vec<mpsc::Sender<Job>>) {
+    for slot in senders.iter_mut().filter(|s| s.is_closed()) {
+        while let Some(next) = standby.pop() {
+            if !next.is_closed() {
+                tracing::warn!("Worker ausgefallen, Standby-Worker übernimmt");
+                health::health().standby_activated();
+                *slot = next;
+                break;
+            }
+        }
+    }
+}
+
+/// Hands `job` to worker `idx`; the bounded worker queue blocks the
+/// dispatcher while full (backpressure). If that worker has stopped, the job
+/// goes to the next live one, except session jobs whose state lives on their
+/// worker. Returns the job if no worker takes it.
+async fn deliver(senders: &[mpsc::Sender<Job>], idx: usize, sticky: bool, job: Job) -> std::result::Result<(), Job> {
+    let tries = if sticky { 1 } else { senders.len() };
+    let mut job = job;
+    for k in 0..tries {
+        let target = (idx + k) % senders.len();
+        match senders[target].send(job).await {
+            Ok(()) => {
+                if k > 0 {
+                    health::health().job_rerouted();
+                }
+                return Ok(());
+            }
+            Err(mpsc::error::SendError(j)) => job = j,
+        }
+    }
+    Err(job)
+}
+
+/// Stores a cached result for `job` and acknowledges it.
+async fn answer_from_cache(store: &RedisStorage, job: Job, result: serde_json::Value) {
+    if let Some(route) = results::take_route(&job.id) {
+        let _ = route.send(results::Direct { payload: result.clone(), tensor: None });
+    } else if let Err(e) = store.store_json(&job.id, &result).await {
+        // Ohne gespeichertes Ergebnis nicht quittieren: durable Jobs kommen erneut
+        tracing::warn!("Gecachtes Ergebnis für {} nicht gespeichert: {}", job.id, e);
+        return;
+    }
+    results::publish(&result);
+    let health = health::health();
+    health.job_accepted();
+    health.jobs_completed(1);
+    if let Some(ack) = &job.ack {
+        ack.done();
+    }
+}
+
+/// Reports a job no worker accepted. Jobs from durable queues stay
+/// unacknowledged and are redelivered; others get an error result.
+async fn reject(store: &RedisStorage, job: Job) {
+    health::health().job_undelivered();
+    if job.ack.is_some() {
+        tracing::warn!("Kein Worker für Job {} verfügbar, bleibt zur Neuzustellung in der Queue", job.id);
+        return;
+    }
+    tracing::warn!("Kein Worker für Job {} verfügbar, Job abgewiesen", job.id);
+    store_error(store, &job, OmniError::NoWorker).await;
+}
+
+/// Answers a job that failed input validation with an error result. The job
+/// is acknowledged: redelivering the same input would fail again.
+async fn invalid_input(store: &RedisStorage, job: Job, error: anyhow::Error) {
+    health::health().job_invalid();
+    tracing::warn!("Job {} abgewiesen, ungültige Eingabe: {}", job.id, error);
+    store_error(store, &job, OmniError::InvalidInput(error.to_string())).await;
+    if let Some(ack) = &job.ack {
+        ack.done();
+    }
+}
+
+/// Stores and publishes an error result for `job`.
+pub(crate) async fn store_error(store: &RedisStorage, job: &Job, error: OmniError) {
+    let mut payload = serde_json::json!({
+        "schema_version": types::SCHEMA_VERSION,
+        "id": job.id,
+        "error": error.to_string(),
+        "code": error.code(),
+        "meta": job.meta,
     });
+    trace_context::annotate(&mut payload, &job.meta);
+    results::publish(&payload);
+    if let Some(route) = results::take_route(&job.id) {
+        let _ = route.send(results::Direct { payload, tensor: None });
+        return;
+    }
+    if let Err(e) = store.store_json(&job.id, &payload).await {
+        tracing::warn!("Fehlerergebnis für {} nicht gespeichert: {}", job.id, e);
+    }
+}
+
+/// Runs the runtime service with the given configuration file: workers per
+/// device, job dispatcher, sources and Redis result storage.
+pub async fn serve(config_path: &str) -> Result<()> {
+    let cfg = load_config(config_path)?;
+    let spec = cfg.input_spec();
+    info!("Starte Runtime: backend={}, batch={}x{}x{}",
+        cfg.model.backend, spec.batch, spec.height, spec.width);
 
-    // Worker starten
-    for (gpu, rx_w, _) in worker_senders {
-        let cfg_cl = cfg.clone();
-        let store_cl = store.clone();
-        let pipeline_cl = Arc::clone(&pipeline);
+    let (pipeline, text_encoder) = build_pipeline(&cfg)?;
+    let text_encoder = text_encoder.map(Arc::new);
 
-        handles.push(tokio::spawn(async move {
-            let device = if gpu == usize::MAX { None } else { Some(gpu) };
-            if let Err(e) = worker::run_gpu_worker(cfg_cl, device, rx_w, store_cl, (*pipeline_cl).clone()).await {
-                eprintln!("[worker gpu={:?}] error: {:?}", device, e);
+    // Kubernetes: Probes, Drain bei SIGTERM
+    if let Some(k8s_cfg) = &cfg.k8s {
+        let probes = k8s_cfg.clone();
+        tokio::spawn(async move {
+            if let Err(e) = k8s::serve_probes(probes).await {
+                tracing::error!("Probe-Server fehlgeschlagen: {:?}", e);
             }
-        }));
+        });
+        // Im Daemon-Modus übernimmt systemd::start die Signale
+        #[cfg(unix)]
+        if cfg.daemon.is_none() {
+            tokio::spawn(k8s::drain_on_sigterm(k8s_cfg.clone()));
+        }
     }
 
-    // Demo-Jobs
-    for k in 0..(spec.batch * 4) {
-        let x = ndarray::Array::zeros((1, spec.channels, spec.height, spec.width)).into_dyn();
-        let job = Job { id: format!("job-{}", k), tensor: x };
-        let _ = tx.send(job).await;
+    // systemd: PID-Datei, Readiness, Watchdog, Drain bei SIGTERM
+    if let Some(daemon_cfg) = &cfg.daemon {
+        #[cfg(unix)]
+        systemd::start(daemon_cfg)?;
+        #[cfg(not(unix))]
+        tracing::warn!("[daemon] wird nur unter Unix unterstützt: {:?}", daemon_cfg);
+    }
+
+    let runtime = Runtime::start(&cfg, pipeline).await?;
+    let tx = runtime.tx.clone();
+
+    // API-Server (Ergebnisabfrage, Uploads, gRPC)
+    let accepts_jobs = server::spawn_servers(&cfg, &runtime.store, &tx, text_encoder.clone())?;
+
+    // Quellen und synthetische Last starten
+    let sources = source::spawn_sources(&cfg, &tx)?;
+    let loadgen = loadgen::spawn(&cfg, text_encoder, &tx)?;
+    if !(sources || accepts_jobs || loadgen) {
+        tracing::warn!("Keine Quelle, kein HTTP-/gRPC-Eingang und kein [loadgen] konfiguriert: keine Jobs, Runtime beendet sich");
     }
     drop(tx);
 
-    for h in handles { let _ = h.await; }
+    runtime.join().await;
     Ok(())
 }
 
@@ -158,6 +581,7 @@ mod tests {
         let job = Job {
             id: "test-job-1".to_string(),
             tensor: ndarray::Array::zeros((1, 3, 224, 224)).into_dyn(),
+            ..Default::default()
         };
         
         tx.send(job).await.unwrap();
@@ -167,11 +591,46 @@ mod tests {
         assert_eq!(received.tensor.shape(), &[1, 3, 224, 224]);
     }
 
+    #[tokio::test]
+    async fn test_promote_standby_replaces_failed_worker() {
+        let (tx0, rx0) = mpsc::channel::<Job>(1);
+        let (tx1, _rx1) = mpsc::channel::<Job>(1);
+        let (standby_tx, mut standby_rx) = mpsc::channel::<Job>(1);
+        let mut senders = vec![tx0, tx1];
+        let mut standby = vec![standby_tx];
+
+        promote_standby(&mut senders, &mut standby);
+        assert_eq!(standby.len(), 1);
+
+        drop(rx0);
+        promote_standby(&mut senders, &mut standby);
+        assert!(standby.is_empty());
+        senders[0].send(Job { id: "j1".to_string(), ..Default::default() }).await.unwrap();
+        assert_eq!(standby_rx.recv().await.unwrap().id, "j1");
+    }
+
+    #[tokio::test]
+    async fn test_deliver_skips_stopped_worker() {
+        let (tx0, rx0) = mpsc::channel::<Job>(1);
+        let (tx1, mut rx1) = mpsc::channel::<Job>(1);
+        drop(rx0);
+        let senders = [tx0, tx1];
+
+        let job = Job { id: "j1".to_string(), ..Default::default() };
+        assert!(deliver(&senders, 0, false, job).await.is_ok());
+        assert_eq!(rx1.recv().await.unwrap().id, "j1");
+
+        // Session-Jobs wechseln den Worker nicht
+        let job = Job { id: "j2".to_string(), ..Default::default() };
+        assert_eq!(deliver(&senders, 0, true, job).await.unwrap_err().id, "j2");
+    }
+
     #[tokio::test]
     async fn test_job_creation() {
         let job = Job {
             id: "test-123".to_string(),
             tensor: ndarray::Array::ones((2, 3, 64, 64)).into_dyn(),
+            ..Default::default()
         };
         
         assert_eq!(job.id, "test-123");
@@ -0,0 +1,178 @@
+//! Synthetic load generator (`[loadgen]`).
+//!
+//! Sends random jobs through the regular dispatcher, so a deployment can be
+//! smoke- or soak-tested without clients: `count` jobs (or endlessly) at
+//! `rate` jobs per second, evenly spaced or with Poisson arrivals. Without
+//! `[loadgen]` the runtime only processes jobs from sources and uploads.
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use ndarray::ArrayD;
+use tokio::sync::mpsc;
+
+use crate::health::health;
+use crate::text::{TextEncoder, TextJob};
+use crate::types::{Arrival, Config, Job, JobMeta, LoadGenCfg};
+
+/// Starts the load generator if `[loadgen]` is configured.
+///
+/// Returns `true` if it was started; it holds a sender until `count` jobs
+/// are sent, so the runtime keeps running until then.
+pub fn spawn(cfg: &Config, encoder: Option<Arc<TextEncoder>>, tx: &mpsc::Sender<Job>) -> Result<bool> {
+    let Some(loadgen) = cfg.loadgen.clone() else { return Ok(false) };
+    anyhow::ensure!(loadgen.rate >= 0.0 && loadgen.rate.is_finite(), "[loadgen] rate muss >= 0 sein");
+    if let Some(shape) = &loadgen.shape {
+        anyhow::ensure!(encoder.is_none(), "[loadgen] shape ist mit [text] nicht nutzbar");
+        anyhow::ensure!(
+            !shape.is_empty() && !shape.contains(&0),
+            "[loadgen] shape {:?} ungültig (Achsen > 0 erwartet)",
+            shape
+        );
+    }
+    // Einen Job vorab bauen, damit Konfigurationsfehler den Start abbrechen
+    synthetic_job(cfg, encoder.as_deref(), "loadgen-0".to_string(), loadgen.shape.as_deref())?;
+
+    tracing::info!(
+        "Lastgenerator: {} Jobs/s ({:?}), {}",
+        if loadgen.rate > 0.0 { loadgen.rate.to_string() } else { "max".into() },
+        loadgen.distribution,
+        if loadgen.count > 0 { format!("{} Jobs", loadgen.count) } else { "bis zum Beenden".into() }
+    );
+    let (cfg, tx) = (cfg.clone(), tx.clone());
+    tokio::spawn(async move {
+        match run(&cfg, &loadgen, encoder.as_deref(), tx).await {
+            Ok(sent) => tracing::info!("Lastgenerator beendet, {} Jobs gesendet", sent),
+            Err(e) => tracing::error!("Lastgenerator fehlgeschlagen: {:?}", e),
+        }
+    });
+    Ok(true)
+}
+
+/// Sends the jobs until `count` is reached, the runtime drains or the
+/// channel closes. Returns the number of jobs sent.
+async fn run(cfg: &Config, loadgen: &LoadGenCfg, encoder: Option<&TextEncoder>, tx: mpsc::Sender<Job>) -> Result<usize> {
+    let mut schedule = Schedule::new(loadgen.rate, loadgen.distribution);
+    let start = Instant::now();
+    let mut sent = 0;
+    while loadgen.count == 0 || sent < loadgen.count {
+        if let Some(offset) = schedule.next(rand::random()) {
+            tokio::time::sleep_until((start + offset).into()).await;
+        }
+        if health().is_draining() {
+            break;
+        }
+        // Pausiert: Takt weiterlaufen lassen, Jobs entfallen
+        if health().is_paused() {
+            if schedule.rate <= 0.0 {
+                tokio::time::sleep(Duration::from_millis(100)).await;
+            }
+            continue;
+        }
+        let job = synthetic_job(cfg, encoder, format!("loadgen-{}", sent), loadgen.shape.as_deref())?;
+        if tx.send(job).await.is_err() {
+            break;
+        }
+        sent += 1;
+    }
+    Ok(sent)
+}
+
+/// Builds a synthetic job: an encoded text for `[text]` models (also the
+/// prompt with `[generation]`), otherwise uniform random values of `shape`
+/// (default: one sample of the input spec, or the largest audio bucket).
+/// Extra inputs get random values, dynamic axes as 1.
+pub(crate) fn synthetic_job(cfg: &Config, encoder: Option<&TextEncoder>, id: String, shape: Option<&[usize]>) -> Result<Job> {
+    if let Some(enc) = encoder {
+        let text = format!("synthetic text {}", id);
+        let mut meta = JobMeta::new();
+        if cfg.generation.is_some() {
+            meta.insert("prompt".to_string(), text.clone().into());
+        }
+        return enc.encode(&TextJob { id, text, meta });
+    }
+
+    // Audio: Rohsignal in Länge des größten Buckets, sonst ein Sample der Input-Spec
+    let shape = match (shape, &cfg.audio) {
+        (Some(shape), _) => shape.to_vec(),
+        (None, Some(a)) => vec![a.buckets.last().copied().unwrap_or(a.sample_rate as usize)],
+        (None, None) => cfg.input_spec().sample_shape()?,
+    };
+    let tensor = ArrayD::from_shape_simple_fn(shape, rand::random::<f32>);
+    let inputs = cfg
+        .input
+        .extra
+        .iter()
+        .map(|e| {
+            let shape: Vec<usize> = e.shape.iter().map(|&d| d.max(1)).collect();
+            (e.name.clone(), ArrayD::from_shape_simple_fn(shape, rand::random::<f32>))
+        })
+        .collect();
+    Ok(Job { id, tensor, inputs, ..Default::default() })
+}
+
+/// Send times relative to the start of the generator.
+struct Schedule {
+    rate: f64,
+    distribution: Arrival,
+    offset: f64,
+}
+
+impl Schedule {
+    fn new(rate: f64, distribution: Arrival) -> Self {
+        Self { rate, distribution, offset: 0.0 }
+    }
+
+    /// Offset of the next job; `None` without a rate limit. `u` is uniform
+    /// in `[0, 1)` and draws the gap after it for Poisson arrivals.
+    fn next(&mut self, u: f64) -> Option<Duration> {
+        if self.rate <= 0.0 {
+            return None;
+        }
+        let due = self.offset;
+        self.offset += match self.distribution {
+            Arrival::Constant => 1.0 / self.rate,
+            // Inversionsmethode: Exp(rate) aus gleichverteiltem u
+            Arrival::Poisson => -(1.0 - u).ln() / self.rate,
+        };
+        Some(Duration::from_secs_f64(due))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_constant_schedule() {
+        let mut schedule = Schedule::new(4.0, Arrival::Constant);
+        let offsets: Vec<_> = (0..3).map(|_| schedule.next(0.9).unwrap().as_millis()).collect();
+        assert_eq!(offsets, vec![0, 250, 500]);
+        assert!(Schedule::new(0.0, Arrival::Poisson).next(0.5).is_none());
+    }
+
+    #[test]
+    fn test_poisson_schedule() {
+        // u = 1 - e^-1 ergibt genau den Mittelwert 1/rate als Abstand
+        let mut schedule = Schedule::new(2.0, Arrival::Poisson);
+        assert_eq!(schedule.next(1.0 - (-1.0f64).exp()), Some(Duration::ZERO));
+        assert_eq!(schedule.next(0.0).unwrap().as_millis(), 500);
+        assert_eq!(schedule.next(0.0).unwrap().as_millis(), 500);
+
+        // Mittlerer Abstand über viele Ankünfte nahe 1/rate
+        let mut schedule = Schedule::new(100.0, Arrival::Poisson);
+        let last = (0..=10_000).map(|_| schedule.next(rand::random()).unwrap()).last().unwrap();
+        assert!((last.as_secs_f64() / 10_000.0 - 0.01).abs() < 0.001);
+    }
+
+    #[test]
+    fn test_loadgen_config() {
+        let cfg: LoadGenCfg = toml::from_str("count = 100\nshape = [3, 32, 32]\ndistribution = \"poisson\"").unwrap();
+        assert_eq!(cfg.rate, 10.0);
+        assert_eq!(cfg.count, 100);
+        assert_eq!(cfg.shape, Some(vec![3, 32, 32]));
+        assert_eq!(cfg.distribution, Arrival::Poisson);
+        assert_eq!(toml::from_str::<LoadGenCfg>("").unwrap().distribution, Arrival::Constant);
+    }
+}
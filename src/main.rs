@@ -1,15 +1,377 @@
 //! OmniEngine CLI - Command-line interface for the inference runtime.
 //!
-//! This binary provides a simple CLI wrapper around the OmniEngine library.
-//! Configuration is read from runtime.toml in the current directory.
+//! Subcommands:
+//!
+//! * `serve` - run the inference service (default without subcommand)
+//! * `infer <file>` - one-shot local inference of an image/npy/text file,
+//!   prints the structured result
+//! * `bench` - synthetic load with latency percentiles
+//! * `build-engine` - convert the ONNX model to a cached TensorRT engine
+//! * `queue ls|drain|requeue` - inspect and repair the cluster job queue
+//! * `verify` - compare dataset results with golden outputs
+//! * `skew` - compare the runtime preprocessing with the training preprocessing
+//! * `openapi` - print the OpenAPI description of the HTTP APIs
+//! * `doctor` - check driver, backend, Redis and model, print a diagnostics report
+//! * `demo` - download a small public model and serve it without any setup
+//! * `memory` - estimate the memory of the model at a batch size and precision
+//! * `pipe` - score NDJSON jobs from stdin, write the results to stdout
+//!
+//! Configuration is read from runtime.toml in the current directory unless
+//! `--config` is given.
+
+use std::path::PathBuf;
 
-use omniengine::start_runtime;
+use clap::{Parser, Subcommand};
+use omniengine::cli::{self, BenchOptions, BuildEngineOptions, DemoOptions, MemoryOptions, RequeueFrom};
+
+#[derive(Parser)]
+#[command(name = "omniengine", version, about = "Unified AI/ML inference runtime")]
+struct Cli {
+    /// Runtime configuration file
+    #[arg(short, long, global = true, default_value = "runtime.toml")]
+    config: String,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Run the inference service (workers, sources, Redis output)
+    Serve,
+    /// Run one input file through the configured pipeline and print the result
+    Infer {
+        /// Input file: image, .npy tensor, or text with a [text] section
+        #[arg(required_unless_present = "input")]
+        file: Option<PathBuf>,
+        /// Input file (alternative to the positional argument)
+        #[arg(short, long, hide = true, conflicts_with = "file")]
+        input: Option<PathBuf>,
+        /// Device override: cpu, gpu or gpu:N (default: from the config)
+        #[arg(short, long)]
+        device: Option<String>,
+        /// Print single-line JSON
+        #[arg(long)]
+        compact: bool,
+    },
+    /// Measure throughput and latency with synthetic jobs
+    Bench {
+        /// Number of jobs
+        #[arg(short = 'n', long, default_value_t = 1000)]
+        requests: usize,
+        /// Offered load in jobs per second (0 = as fast as possible)
+        #[arg(long, default_value_t = 0.0)]
+        rate: f64,
+        /// Warmup batches before measuring
+        #[arg(long, default_value_t = 3)]
+        warmup: usize,
+        /// Print the report as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Convert the ONNX model to a TensorRT engine stored next to it
+    BuildEngine {
+        /// ONNX model (default: model.model_path)
+        #[arg(long)]
+        onnx: Option<PathBuf>,
+        /// Engine file (default: <model>.<precision>.<hash>.engine)
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        /// Precision: fp32, fp16, int8 or best
+        #[arg(short, long, default_value = "fp16")]
+        precision: String,
+        /// Builder workspace in MiB
+        #[arg(long, default_value_t = 4096)]
+        workspace_mb: usize,
+        /// Optimization profile minimum (trtexec syntax, e.g. input:1x3x224x224)
+        #[arg(long)]
+        min_shapes: Option<String>,
+        /// Optimization profile optimum
+        #[arg(long)]
+        opt_shapes: Option<String>,
+        /// Optimization profile maximum
+        #[arg(long)]
+        max_shapes: Option<String>,
+        /// GPU to build on
+        #[arg(long, default_value_t = 0)]
+        gpu: usize,
+        /// Path of the trtexec binary
+        #[arg(long, default_value = "trtexec")]
+        trtexec: String,
+        /// Rebuild even if a cached engine exists
+        #[arg(long)]
+        force: bool,
+    },
+    /// Score NDJSON jobs from stdin and write one result per line to stdout
+    Pipe {
+        /// Device override: cpu, gpu or gpu:N (default: from the config)
+        #[arg(short, long)]
+        device: Option<String>,
+    },
+    /// Run a dataset through the pipeline and compare with golden outputs
+    Verify {
+        /// Directory with input files (images, .npy, .txt)
+        #[arg(long)]
+        dataset: PathBuf,
+        /// Golden outputs (JSON object id -> result)
+        #[arg(long)]
+        expected: PathBuf,
+        /// Allowed absolute difference of numeric values
+        #[arg(long, default_value_t = 1e-4)]
+        tolerance: f64,
+        /// Device override: cpu, gpu or gpu:N
+        #[arg(short, long)]
+        device: Option<String>,
+        /// Write the current results to --expected instead of comparing
+        #[arg(long)]
+        record: bool,
+        /// Print the report as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Compare the runtime preprocessing with a reference Python function
+    Skew {
+        /// Directory with input files (images, .npy, .txt)
+        #[arg(long)]
+        dataset: PathBuf,
+        /// Reference preprocessing: file.py:function or module:function
+        #[arg(long)]
+        reference: String,
+        /// Python interpreter (needs numpy)
+        #[arg(long, default_value = "python3")]
+        python: String,
+        /// Allowed absolute difference per element
+        #[arg(long, default_value_t = 1e-5)]
+        tolerance: f64,
+        /// Print the report as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Print the OpenAPI document of the HTTP APIs (for client generation)
+    Openapi,
+    /// Check GPU driver, backend, Redis, model loading and a test inference
+    Doctor {
+        /// Device override: cpu, gpu or gpu:N
+        #[arg(short, long)]
+        device: Option<String>,
+        /// Print the report as JSON (for support tickets)
+        #[arg(long)]
+        json: bool,
+    },
+    /// Download a small public model and serve it without Redis or a config
+    Demo {
+        /// Directory for the model and the generated configuration
+        #[arg(long, default_value = "omniengine-demo")]
+        dir: PathBuf,
+        /// Download URL of the ONNX model
+        #[arg(long, default_value = cli::DEMO_MODEL_URL)]
+        model_url: String,
+        /// Address of the HTTP API
+        #[arg(long, default_value = "127.0.0.1:8000")]
+        bind: String,
+        /// Exit after the sample inference instead of serving
+        #[arg(long)]
+        once: bool,
+    },
+    /// Estimate weights, activations and workspace memory before loading the model
+    Memory {
+        /// Samples per inference (default: [queue] max_batch)
+        #[arg(short, long)]
+        batch: Option<usize>,
+        /// fp32, fp16, bf16 or int8 (default: as stored in the model)
+        #[arg(short, long)]
+        precision: Option<String>,
+        /// Device override: cpu, gpu or gpu:N
+        #[arg(short, long)]
+        device: Option<String>,
+        /// Workspace in MiB instead of the planned one (e.g. TensorRT)
+        #[arg(long)]
+        workspace_mb: Option<u64>,
+        /// Print the estimate as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Inspect and repair the cluster job queue ([cluster]) and its DLQ
+    Queue {
+        #[command(subcommand)]
+        action: QueueAction,
+    },
+}
+
+#[derive(Subcommand)]
+enum QueueAction {
+    /// Show backlog depth, consumer groups, live nodes and DLQ size
+    Ls {
+        /// Print as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Discard all queued jobs (or the DLQ)
+    Drain {
+        /// Drain the dead-letter stream instead of the job stream
+        #[arg(long)]
+        dlq: bool,
+        /// Confirm the deletion
+        #[arg(long)]
+        yes: bool,
+    },
+    /// Move jobs from the DLQ (or stuck pending ones) back into the queue
+    Requeue {
+        /// Requeue pending jobs idle for --min-idle-ms instead of the DLQ
+        #[arg(long)]
+        pending: bool,
+        /// Minimum idle time of pending jobs
+        #[arg(long, default_value_t = 60000)]
+        min_idle_ms: u64,
+        /// Maximum number of jobs
+        #[arg(short = 'n', long, default_value_t = 1000)]
+        count: usize,
+    },
+}
 
 /// Main entry point for the OmniEngine CLI.
 ///
-/// Reads configuration from runtime.toml and starts the inference runtime.
-/// The runtime will process jobs from the input queue and write results to Redis.
+/// Parses the subcommand and dispatches to the library. Without a
+/// subcommand the service is started, as before.
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    start_runtime().await
+    let args = Cli::parse();
+    omniengine::init_tracing();
+
+    match args.command.unwrap_or(Command::Serve) {
+        Command::Serve => omniengine::serve(&args.config).await,
+        Command::Infer { file, input, device, compact } => {
+            let file = file.or(input).expect("von clap erzwungen");
+            let report = cli::infer(&args.config, &file, device.as_deref()).await?;
+            if compact {
+                println!("{}", serde_json::to_string(&report)?);
+            } else {
+                println!("{}", serde_json::to_string_pretty(&report)?);
+            }
+            Ok(())
+        }
+        Command::Bench { requests, rate, warmup, json } => {
+            let report = cli::bench(&args.config, &BenchOptions { requests, rate, warmup }).await?;
+            if json {
+                println!("{}", serde_json::to_string_pretty(&report)?);
+            } else {
+                println!("{}", report);
+            }
+            Ok(())
+        }
+        Command::BuildEngine {
+            onnx,
+            output,
+            precision,
+            workspace_mb,
+            min_shapes,
+            opt_shapes,
+            max_shapes,
+            gpu,
+            trtexec,
+            force,
+        } => {
+            let opts = BuildEngineOptions {
+                onnx,
+                output,
+                precision,
+                workspace_mb,
+                min_shapes,
+                opt_shapes,
+                max_shapes,
+                device: gpu,
+                trtexec,
+                force,
+            };
+            let path = cli::build_engine(&args.config, &opts)?;
+            println!("{}", path.display());
+            Ok(())
+        }
+        Command::Pipe { device } => {
+            let stdin = std::io::BufReader::new(std::io::stdin());
+            let summary = cli::pipe(&args.config, device.as_deref(), stdin, std::io::BufWriter::new(std::io::stdout())).await?;
+            if summary.failed > 0 {
+                std::process::exit(1);
+            }
+            Ok(())
+        }
+        Command::Verify { dataset, expected, tolerance, device, record, json } => {
+            if record {
+                let n = cli::record(&args.config, &dataset, &expected, device.as_deref()).await?;
+                println!("{} Golden-Ausgaben nach {} geschrieben", n, expected.display());
+                return Ok(());
+            }
+            let report = cli::verify(&args.config, &dataset, &expected, tolerance, device.as_deref()).await?;
+            if json {
+                println!("{}", serde_json::to_string_pretty(&report)?);
+            } else {
+                println!("{}", report);
+            }
+            if !report.ok() {
+                std::process::exit(1);
+            }
+            Ok(())
+        }
+        Command::Skew { dataset, reference, python, tolerance, json } => {
+            let report = cli::skew(&args.config, &dataset, &reference, &python, tolerance)?;
+            if json {
+                println!("{}", serde_json::to_string_pretty(&report)?);
+            } else {
+                println!("{}", report);
+            }
+            if !report.ok() {
+                std::process::exit(1);
+            }
+            Ok(())
+        }
+        Command::Openapi => {
+            println!("{}", omniengine::openapi::spec_json());
+            Ok(())
+        }
+        Command::Doctor { device, json } => {
+            let report = cli::doctor(&args.config, device.as_deref()).await;
+            if json {
+                println!("{}", serde_json::to_string_pretty(&report)?);
+            } else {
+                println!("{}", report);
+            }
+            if !report.ok() {
+                std::process::exit(1);
+            }
+            Ok(())
+        }
+        Command::Demo { dir, model_url, bind, once } => cli::demo(&DemoOptions { dir, model_url, bind, once }).await,
+        Command::Memory { batch, precision, device, workspace_mb, json } => {
+            let estimate = cli::memory(&args.config, &MemoryOptions { batch, precision, device, workspace_mb })?;
+            if json {
+                println!("{}", serde_json::to_string_pretty(&estimate)?);
+            } else {
+                println!("{}", estimate);
+            }
+            Ok(())
+        }
+        Command::Queue { action } => match action {
+            QueueAction::Ls { json } => {
+                let status = cli::queue_ls(&args.config).await?;
+                if json {
+                    println!("{}", serde_json::to_string_pretty(&status)?);
+                } else {
+                    println!("{}", status);
+                }
+                Ok(())
+            }
+            QueueAction::Drain { dlq, yes } => {
+                anyhow::ensure!(yes, "Drain löscht alle Jobs unwiderruflich, mit --yes bestätigen");
+                let removed = cli::queue_drain(&args.config, dlq).await?;
+                println!("{} Einträge entfernt", removed);
+                Ok(())
+            }
+            QueueAction::Requeue { pending, min_idle_ms, count } => {
+                let from = if pending { RequeueFrom::Pending } else { RequeueFrom::Dlq };
+                let requeued = cli::queue_requeue(&args.config, from, min_idle_ms, count).await?;
+                println!("{} Jobs neu eingereiht", requeued);
+                Ok(())
+            }
+        },
+    }
 }
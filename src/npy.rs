@@ -0,0 +1,154 @@
+//! Minimal reader and writer for NumPy `.npy` files.
+//!
+//! Reading supports format versions 1.0-3.0, C order, little-endian `f4`,
+//! `f8`, `i4`, `i8` and `u1` data; values are converted to `f32`. Writing
+//! produces version 1.0 files with `<f4` data.
+
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use ndarray::{ArrayD, IxDyn};
+
+const MAGIC: &[u8] = b"\x93NUMPY";
+
+/// Loads a `.npy` file as an `f32` array.
+pub fn load(path: &Path) -> Result<ArrayD<f32>> {
+    let bytes = std::fs::read(path).with_context(|| format!("NPY-Datei konnte nicht gelesen werden: {}", path.display()))?;
+    parse(&bytes).with_context(|| format!("Ungültige NPY-Datei: {}", path.display()))
+}
+
+/// Parses the contents of a `.npy` file.
+pub fn parse(bytes: &[u8]) -> Result<ArrayD<f32>> {
+    anyhow::ensure!(bytes.len() >= 10 && bytes.starts_with(MAGIC), "NPY-Signatur fehlt");
+    let (header_len, offset) = match bytes[6] {
+        1 => (u16::from_le_bytes([bytes[8], bytes[9]]) as usize, 10),
+        2 | 3 => {
+            anyhow::ensure!(bytes.len() >= 12, "NPY-Header abgeschnitten");
+            (u32::from_le_bytes([bytes[8], bytes[9], bytes[10], bytes[11]]) as usize, 12)
+        }
+        v => anyhow::bail!("NPY-Version {} wird nicht unterstützt", v),
+    };
+    let header = bytes.get(offset..offset + header_len).context("NPY-Header abgeschnitten")?;
+    let header = std::str::from_utf8(header)?;
+    let data = &bytes[offset + header_len..];
+
+    let descr = dict_value(header, "descr").context("'descr' fehlt im Header")?;
+    let descr = descr.get(1..).and_then(|d| d.split(['\'', '"']).next()).unwrap_or("");
+    anyhow::ensure!(
+        dict_value(header, "fortran_order").is_some_and(|v| v.starts_with("False")),
+        "Nur C-Reihenfolge wird unterstützt"
+    );
+    let shape_raw = dict_value(header, "shape").context("'shape' fehlt im Header")?;
+    let shape: Vec<usize> = shape_raw
+        .trim_start_matches('(')
+        .split(')')
+        .next()
+        .unwrap_or("")
+        .split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(|s| s.parse::<usize>())
+        .collect::<Result<_, _>>()?;
+    let count = shape
+        .iter()
+        .try_fold(1usize, |n, &d| n.checked_mul(d))
+        .with_context(|| format!("NPY-Shape {:?} ist zu groß", shape))?;
+
+    let values: Vec<f32> = match descr {
+        "<f4" => chunks::<4>(data, count)?.map(f32::from_le_bytes).collect(),
+        "<f8" => chunks::<8>(data, count)?.map(|b| f64::from_le_bytes(b) as f32).collect(),
+        "<i4" => chunks::<4>(data, count)?.map(|b| i32::from_le_bytes(b) as f32).collect(),
+        "<i8" => chunks::<8>(data, count)?.map(|b| i64::from_le_bytes(b) as f32).collect(),
+        "|u1" | "<u1" => chunks::<1>(data, count)?.map(|b| b[0] as f32).collect(),
+        other => anyhow::bail!("dtype '{}' wird nicht unterstützt", other),
+    };
+    Ok(ArrayD::from_shape_vec(IxDyn(&shape), values)?)
+}
+
+/// Serializes an `f32` array as `.npy` (version 1.0, C order).
+#[cfg_attr(not(feature = "websocket"), allow(dead_code))]
+pub fn to_bytes(array: &ArrayD<f32>) -> Vec<u8> {
+    let shape = match array.shape() {
+        [n] => format!("({},)", n),
+        dims => format!("({})", dims.iter().map(|d| d.to_string()).collect::<Vec<_>>().join(", ")),
+    };
+    let mut header = format!("{{'descr': '<f4', 'fortran_order': False, 'shape': {}, }}", shape);
+    // Daten auf 64 Bytes ausrichten, wie NumPy selbst
+    header.push_str(&" ".repeat(63 - (10 + header.len()) % 64));
+    header.push('\n');
+    let mut out = Vec::with_capacity(10 + header.len() + array.len() * 4);
+    out.extend_from_slice(MAGIC);
+    out.extend([1, 0]);
+    out.extend((header.len() as u16).to_le_bytes());
+    out.extend(header.as_bytes());
+    out.extend(array.iter().flat_map(|v| v.to_le_bytes()));
+    out
+}
+
+/// Returns the raw value text following `'key':` in the header dict.
+fn dict_value<'a>(header: &'a str, key: &str) -> Option<&'a str> {
+    let start = header.find(&format!("'{}':", key))? + key.len() + 3;
+    Some(header[start..].trim_start())
+}
+
+/// Splits `data` into `count` little-endian values of `N` bytes.
+fn chunks<const N: usize>(data: &[u8], count: usize) -> Result<impl Iterator<Item = [u8; N]> + '_> {
+    let len = count.checked_mul(N).context("NPY-Daten zu groß")?;
+    anyhow::ensure!(data.len() >= len, "NPY-Daten abgeschnitten: {} statt {} Bytes", data.len(), len);
+    Ok(data[..len].chunks_exact(N).map(|c| c.try_into().unwrap()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn npy(descr: &str, shape: &str, data: &[u8]) -> Vec<u8> {
+        let mut header = format!("{{'descr': '{}', 'fortran_order': False, 'shape': {}, }}", descr, shape);
+        while (10 + header.len() + 1) % 64 != 0 {
+            header.push(' ');
+        }
+        header.push('\n');
+        let mut out = MAGIC.to_vec();
+        out.extend([1, 0]);
+        out.extend((header.len() as u16).to_le_bytes());
+        out.extend(header.as_bytes());
+        out.extend(data);
+        out
+    }
+
+    #[test]
+    fn test_parse_f32_matrix() {
+        let data: Vec<u8> = [1.0f32, 2.0, 3.0, 4.0, 5.0, 6.0].iter().flat_map(|v| v.to_le_bytes()).collect();
+        let a = parse(&npy("<f4", "(2, 3)", &data)).unwrap();
+        assert_eq!(a.shape(), &[2, 3]);
+        assert_eq!(a[[1, 0]], 4.0);
+    }
+
+    #[test]
+    fn test_parse_u8_vector_and_scalar_shape() {
+        let a = parse(&npy("|u1", "(3,)", &[0, 128, 255])).unwrap();
+        assert_eq!(a.shape(), &[3]);
+        assert_eq!(a[[2]], 255.0);
+
+        let s = parse(&npy("<f8", "()", &2.5f64.to_le_bytes())).unwrap();
+        assert_eq!(s.ndim(), 0);
+    }
+
+    #[test]
+    fn test_parse_rejects_truncated_and_unsupported() {
+        assert!(parse(&npy("<f4", "(4,)", &[0; 8])).is_err());
+        assert!(parse(&npy(">f4", "(1,)", &[0; 4])).is_err());
+        assert!(parse(b"not npy").is_err());
+        let huge = format!("({}, {})", usize::MAX, 2);
+        assert!(parse(&npy("<f4", &huge, &[0; 4])).unwrap_err().to_string().contains("zu groß"));
+    }
+
+    #[test]
+    fn test_to_bytes_roundtrip() {
+        let a = ArrayD::from_shape_fn(IxDyn(&[2, 3, 4]), |i| (i[0] * 12 + i[1] * 4 + i[2]) as f32);
+        let bytes = to_bytes(&a);
+        assert_eq!((bytes.len() - a.len() * 4) % 64, 0);
+        assert_eq!(parse(&bytes).unwrap(), a);
+        assert_eq!(parse(&to_bytes(&ArrayD::zeros(IxDyn(&[5])))).unwrap().shape(), &[5]);
+    }
+}
@@ -0,0 +1,472 @@
+//! OpenAPI description of the HTTP APIs.
+//!
+//! Wire types derive `ToSchema`; endpoints are registered in `spec()`, so
+//! new routes must be added there as well. The document is served at
+//! `/openapi.json` on the probe and HTTP API servers and printed by
+//! `omniengine openapi` for offline client generation.
+
+use utoipa::openapi::path::{Operation, OperationBuilder, ParameterBuilder, ParameterIn};
+use utoipa::openapi::schema::AdditionalProperties;
+use utoipa::openapi::{
+    ContentBuilder, HttpMethod, KnownFormat, ObjectBuilder, OpenApi as Spec, PathItem, PathsBuilder, Ref, Required,
+    Response, ResponseBuilder, SchemaFormat, Type,
+};
+use utoipa::OpenApi;
+
+use crate::accounting::UsageReport;
+use crate::engine::{Capabilities, Residency};
+use crate::recent::{Outcome, RecentJob};
+use crate::server::blobs::BlobStatus;
+use crate::server::upload::{UploadRequest, UploadStatus};
+use crate::types::{JobRequest, TensorData};
+
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "OmniEngine",
+        description = "Inference runtime: job wire format, results and admin endpoints."
+    ),
+    components(schemas(TensorData, JobRequest, Capabilities, Residency, UploadRequest, UploadStatus, BlobStatus, UsageReport, RecentJob, Outcome)),
+    tags(
+        (name = "inference", description = "Inference requests ([server.http.infer])"),
+        (name = "results", description = "Result retrieval ([server.http])"),
+        (name = "model", description = "Model and backend description ([server.http])"),
+        (name = "uploads", description = "Chunked upload of large inputs ([server.http.upload])"),
+        (name = "blobs", description = "Content-addressed input cache ([server.http.infer.blobs])"),
+        (name = "probes", description = "Kubernetes probes and metrics ([k8s] port)"),
+        (name = "admin", description = "Lifecycle control")
+    )
+)]
+struct ApiDoc;
+
+/// Response with a body of the given content type and schema.
+fn response(description: &str, content_type: &str, schema: impl Into<utoipa::openapi::RefOr<utoipa::openapi::Schema>>) -> Response {
+    ResponseBuilder::new()
+        .description(description)
+        .content(content_type, ContentBuilder::new().schema(Some(schema)).build())
+        .build()
+}
+
+fn json_error(description: &str) -> Response {
+    response(description, "application/problem+json", Ref::from_schema_name("Problem"))
+}
+
+/// Schema of error bodies (RFC 9457 problem details, see [`crate::error`]).
+fn problem_schema() -> ObjectBuilder {
+    let string = || ObjectBuilder::new().schema_type(Type::String);
+    ObjectBuilder::new()
+        .description(Some("Problem details; `code` is stable and meant for matching, `detail` is localized"))
+        .property("type", string())
+        .property("title", string())
+        .property("status", ObjectBuilder::new().schema_type(Type::Integer))
+        .property("detail", string())
+        .property("code", string().description(Some("Stable error code, e.g. UPLOAD_NOT_FOUND")))
+        .property("error", string().description(Some("Same as detail (earlier error body)")))
+        .required("code")
+        .required("detail")
+}
+
+fn text(description: &str) -> Response {
+    response(description, "text/plain", ObjectBuilder::new().schema_type(Type::String).build())
+}
+
+fn query_int(name: &str, description: &str) -> ParameterBuilder {
+    ParameterBuilder::new()
+        .name(name)
+        .parameter_in(ParameterIn::Query)
+        .description(Some(description))
+        .schema(Some(ObjectBuilder::new().schema_type(Type::Integer).minimum(Some(0))))
+}
+
+fn operation(tag: &str, id: &str, summary: &str) -> OperationBuilder {
+    OperationBuilder::new().tag(tag).operation_id(Some(id)).summary(Some(summary))
+}
+
+fn get(op: impl Into<Operation>) -> PathItem {
+    PathItem::new(HttpMethod::Get, op)
+}
+
+fn post(op: impl Into<Operation>) -> PathItem {
+    PathItem::new(HttpMethod::Post, op)
+}
+
+/// Schema of a stored job result: `id`, `timestamp`, optional `meta` and
+/// the fields of the configured output formatter.
+fn job_result_schema() -> ObjectBuilder {
+    ObjectBuilder::new()
+        .description(Some("Job result; besides id/timestamp/meta the fields depend on the output formatter"))
+        .property("id", ObjectBuilder::new().schema_type(Type::String))
+        .required("id")
+        .property(
+            "timestamp",
+            ObjectBuilder::new().schema_type(Type::String).format(Some(SchemaFormat::KnownFormat(KnownFormat::DateTime))),
+        )
+        .required("timestamp")
+        .property("meta", ObjectBuilder::new().schema_type(Type::Object))
+        .property(
+            "traceparent",
+            ObjectBuilder::new().schema_type(Type::String).description(Some("W3C trace context of the job, if it had one")),
+        )
+        .property("tracestate", ObjectBuilder::new().schema_type(Type::String))
+        .additional_properties(Some(AdditionalProperties::FreeForm(true)))
+}
+
+/// Schema of `GET /v1/model`.
+fn model_schema() -> ObjectBuilder {
+    let tensors = || {
+        utoipa::openapi::schema::ArrayBuilder::new().items(
+            ObjectBuilder::new()
+                .property("name", ObjectBuilder::new().schema_type(Type::String))
+                .property("shape", utoipa::openapi::schema::ArrayBuilder::new().items(ObjectBuilder::new().schema_type(Type::Integer))),
+        )
+    };
+    ObjectBuilder::new()
+        .property("backend", ObjectBuilder::new().schema_type(Type::String))
+        .property("device", ObjectBuilder::new().schema_type(Type::String))
+        .property("inputs", tensors())
+        .property("outputs", tensors())
+        .property(
+            "workers",
+            ObjectBuilder::new()
+                .description(Some("Engine capabilities by worker (e.g. `gpu:0`)"))
+                .additional_properties(Some(AdditionalProperties::RefOr(Ref::from_schema_name("Capabilities").into()))),
+        )
+}
+
+/// Builds the OpenAPI document.
+pub fn spec() -> Spec {
+    let mut doc = ApiDoc::openapi();
+    if let Some(components) = doc.components.as_mut() {
+        components.schemas.insert("JobResult".to_string(), job_result_schema().build().into());
+        components.schemas.insert("Problem".to_string(), problem_schema().build().into());
+    }
+
+    let job_id = ParameterBuilder::new()
+        .name("job_id")
+        .parameter_in(ParameterIn::Path)
+        .required(Required::True)
+        .schema(Some(ObjectBuilder::new().schema_type(Type::String)))
+        .build();
+    let wait_ms = ParameterBuilder::new()
+        .name("wait_ms")
+        .parameter_in(ParameterIn::Query)
+        .required(Required::False)
+        .description(Some("Long-poll: wait up to this many milliseconds for the result (capped by max_wait_ms)"))
+        .schema(Some(ObjectBuilder::new().schema_type(Type::Integer).minimum(Some(0))));
+
+    let upload_status = || response("Upload progress", "application/json", Ref::from_schema_name("UploadStatus"));
+    let chunk_index = ParameterBuilder::new()
+        .name("index")
+        .parameter_in(ParameterIn::Path)
+        .required(Required::True)
+        .schema(Some(ObjectBuilder::new().schema_type(Type::Integer).minimum(Some(0))))
+        .build();
+    let crc32 = ParameterBuilder::new()
+        .name("X-Chunk-Crc32")
+        .parameter_in(ParameterIn::Header)
+        .required(Required::True)
+        .description(Some("CRC32 (IEEE) of the chunk body, hexadecimal"))
+        .schema(Some(ObjectBuilder::new().schema_type(Type::String)))
+        .build();
+    let blob_status = || response("Cached input", "application/json", Ref::from_schema_name("BlobStatus"));
+    let sha256 = ParameterBuilder::new()
+        .name("sha256")
+        .parameter_in(ParameterIn::Path)
+        .required(Required::True)
+        .description(Some("SHA-256 of the uploaded body, hexadecimal"))
+        .schema(Some(ObjectBuilder::new().schema_type(Type::String)))
+        .build();
+    let trace_header = |name: &str, description: &str| {
+        ParameterBuilder::new()
+            .name(name)
+            .parameter_in(ParameterIn::Header)
+            .required(Required::False)
+            .description(Some(description))
+            .schema(Some(ObjectBuilder::new().schema_type(Type::String)))
+            .build()
+    };
+
+    doc.paths = PathsBuilder::new()
+        .path(
+            "/v1/infer",
+            post(operation("inference", "infer", "Enqueue one job; with wait_ms wait for its result")
+                .parameter(
+                    ParameterBuilder::new()
+                        .name("wait_ms")
+                        .parameter_in(ParameterIn::Query)
+                        .required(Required::False)
+                        .description(Some("Wait up to this many milliseconds for the result (capped by max_wait_ms); 0 answers right away"))
+                        .schema(Some(ObjectBuilder::new().schema_type(Type::Integer).minimum(Some(0)))),
+                )
+                .parameter(trace_header("traceparent", "W3C trace context, stored as meta.traceparent and repeated in the result"))
+                .parameter(trace_header("tracestate", "W3C vendor trace state, kept with traceparent"))
+                .request_body(Some(
+                    utoipa::openapi::request_body::RequestBodyBuilder::new()
+                        .description(Some("Job in the wire format (`id` optional) or a CloudEvent wrapping it; `\"blob\": \"<sha256>\"` instead of `shape`/`data` references a cached input; with `[text]`, `\"text\"` instead of `shape`/`data` is tokenized"))
+                        .content("application/json", ContentBuilder::new().schema(Some(Ref::from_schema_name("JobRequest"))).build())
+                        .build(),
+                ))
+                .response("200", response("Result, ready within wait_ms", "application/json", Ref::from_schema_name("JobResult")))
+                .response(
+                    "202",
+                    response(
+                        "Job enqueued; the result follows at the `Location` header",
+                        "application/json",
+                        ObjectBuilder::new()
+                            .property("id", ObjectBuilder::new().schema_type(Type::String))
+                            .property("result", ObjectBuilder::new().schema_type(Type::String).description(Some("Result URL")))
+                            .build(),
+                    ),
+                )
+                .response("400", json_error("Malformed job"))
+                .response("413", ResponseBuilder::new().description("Body exceeds max_body_bytes").build())
+                .response("503", json_error("Input queue full or runtime shutting down"))),
+        )
+        .path(
+            "/v1/results/{job_id}",
+            get(operation("results", "getResult", "Stored result of a job")
+                .parameter(job_id.clone())
+                .parameter(wait_ms)
+                .response("200", response("Job result", "application/json", Ref::from_schema_name("JobResult")))
+                .response("404", json_error("No result (yet)"))
+                .response("503", json_error("Result store unavailable"))),
+        )
+        .path(
+            "/v1/results/{job_id}/tensor",
+            get(operation("results", "getResultTensor", "Full output tensor of a chunked result, optionally a range")
+                .parameter(job_id.clone())
+                .parameter(query_int("offset", "First element (flattened, default 0)"))
+                .parameter(query_int("limit", "Number of elements (default: up to the end)"))
+                .parameter(query_int("chunk", "Return exactly this stored chunk instead of offset/limit"))
+                .response(
+                    "200",
+                    ResponseBuilder::new()
+                        .description("Elements as JSON (`shape`, `offset`, `data`) or, with `Accept: application/octet-stream`, raw little-endian values of the stored dtype with `X-Tensor-Shape`/`X-Tensor-Offset`/`X-Tensor-Dtype` headers")
+                        .content("application/json", ContentBuilder::new().schema(Some(ObjectBuilder::new().schema_type(Type::Object))).build())
+                        .content("application/octet-stream", ContentBuilder::new().schema(Some(ObjectBuilder::new().schema_type(Type::String).format(Some(SchemaFormat::KnownFormat(KnownFormat::Binary))))).build())
+                        .build(),
+                )
+                .response("404", json_error("No result or result not stored in chunks"))
+                .response("416", json_error("Range outside the tensor"))
+                .response("503", json_error("Result store unavailable"))),
+        )
+        .path(
+            "/v1/traces/{trace_id}",
+            get(operation("results", "getTrace", "Ids of the stored results belonging to a W3C trace")
+                .parameter(
+                    ParameterBuilder::new()
+                        .name("trace_id")
+                        .parameter_in(ParameterIn::Path)
+                        .required(Required::True)
+                        .description(Some("Trace id from the jobs' traceparent (32 hex digits)"))
+                        .schema(Some(ObjectBuilder::new().schema_type(Type::String))),
+                )
+                .response(
+                    "200",
+                    response(
+                        "Job ids, empty if no result carries the trace",
+                        "application/json",
+                        ObjectBuilder::new()
+                            .property("trace_id", ObjectBuilder::new().schema_type(Type::String))
+                            .property("jobs", utoipa::openapi::schema::ArrayBuilder::new().items(ObjectBuilder::new().schema_type(Type::String)))
+                            .build(),
+                    ),
+                )
+                .response("503", json_error("Result store unavailable"))),
+        )
+        .path(
+            "/v1/model",
+            get(operation("model", "getModel", "Backend, configured inputs/outputs and engine capabilities per worker")
+                .response("200", response("Model description", "application/json", model_schema().build()))),
+        )
+        .path(
+            "/v1/usage",
+            get(operation("model", "getUsage", "GPU-seconds, energy and cost of the model since start")
+                .response("200", response("Accumulated usage", "application/json", Ref::from_schema_name("UsageReport")))),
+        )
+        .path(
+            "/v1/uploads",
+            post(operation("uploads", "createUpload", "Announce a chunked upload of one input tensor")
+                .parameter(trace_header("traceparent", "W3C trace context, stored as meta.traceparent and repeated in the result"))
+                .parameter(trace_header("tracestate", "W3C vendor trace state, kept with traceparent"))
+                .request_body(Some(
+                    utoipa::openapi::request_body::RequestBodyBuilder::new()
+                        .content("application/json", ContentBuilder::new().schema(Some(Ref::from_schema_name("UploadRequest"))).build())
+                        .build(),
+                ))
+                .response("201", upload_status())
+                .response("400", json_error("Invalid shape or chunk size"))
+                .response("409", json_error("Upload with this id exists"))
+                .response("413", json_error("Tensor exceeds max_bytes"))
+                .response("429", json_error("Too many pending uploads"))),
+        )
+        .path(
+            "/v1/uploads/{job_id}",
+            get(operation("uploads", "getUpload", "Progress of an upload, including the missing chunks")
+                .parameter(job_id.clone())
+                .response("200", upload_status())
+                .response("404", json_error("No such upload"))),
+        )
+        .path(
+            "/v1/uploads/{job_id}",
+            PathItem::new(
+                HttpMethod::Delete,
+                operation("uploads", "deleteUpload", "Abort an upload")
+                    .parameter(job_id.clone())
+                    .response("204", ResponseBuilder::new().description("Upload dropped").build())
+                    .response("404", json_error("No such upload")),
+            ),
+        )
+        .path(
+            "/v1/uploads/{job_id}/chunks/{index}",
+            PathItem::new(
+                HttpMethod::Put,
+                operation("uploads", "putUploadChunk", "Store one chunk (raw little-endian f32); resending replaces it")
+                    .parameter(job_id.clone())
+                    .parameter(chunk_index)
+                    .parameter(crc32)
+                    .request_body(Some(
+                        utoipa::openapi::request_body::RequestBodyBuilder::new()
+                            .content(
+                                "application/octet-stream",
+                                ContentBuilder::new().schema(Some(ObjectBuilder::new().schema_type(Type::String).format(Some(SchemaFormat::KnownFormat(KnownFormat::Binary))))).build(),
+                            )
+                            .build(),
+                    ))
+                    .response("200", upload_status())
+                    .response("400", json_error("Wrong chunk index or length, missing checksum"))
+                    .response("404", json_error("No such upload"))
+                    .response("422", json_error("Checksum mismatch, resend the chunk")),
+            ),
+        )
+        .path(
+            "/v1/uploads/{job_id}/complete",
+            post(operation("uploads", "completeUpload", "Assemble the tensor and enqueue the job")
+                .parameter(job_id)
+                .response(
+                    "202",
+                    response(
+                        "Job enqueued; `result` is the path of its result",
+                        "application/json",
+                        ObjectBuilder::new()
+                            .property("id", ObjectBuilder::new().schema_type(Type::String))
+                            .property("result", ObjectBuilder::new().schema_type(Type::String))
+                            .build(),
+                    ),
+                )
+                .response("404", json_error("No such upload"))
+                .response("409", json_error("Chunks missing"))
+                .response("503", json_error("Runtime no longer accepts jobs"))),
+        )
+        .path(
+            "/v1/blobs/{sha256}",
+            PathItem::new(
+                HttpMethod::Put,
+                operation("blobs", "putBlob", "Cache an input tensor under the SHA-256 of the body")
+                    .parameter(sha256.clone())
+                    .request_body(Some(
+                        utoipa::openapi::request_body::RequestBodyBuilder::new()
+                            .content(
+                                "application/octet-stream",
+                                ContentBuilder::new().schema(Some(ObjectBuilder::new().schema_type(Type::String).format(Some(SchemaFormat::KnownFormat(KnownFormat::Binary))))).build(),
+                            )
+                            .content("application/json", ContentBuilder::new().schema(Some(Ref::from_schema_name("TensorData"))).build())
+                            .description(Some("`.npy` file or JSON tensor"))
+                            .build(),
+                    ))
+                    .response("201", blob_status())
+                    .response("400", json_error("Body is neither .npy nor a JSON tensor"))
+                    .response("413", json_error("Tensor exceeds max_bytes"))
+                    .response("422", json_error("SHA-256 of the body does not match")),
+            ),
+        )
+        .path(
+            "/v1/blobs/{sha256}",
+            get(operation("blobs", "getBlob", "Whether an input is still cached; counts as a use")
+                .parameter(sha256)
+                .response("200", blob_status())
+                .response("404", json_error("Not cached, upload it again"))),
+        )
+        .path(
+            "/healthz",
+            get(operation("probes", "healthz", "Liveness").response("200", text("Process is alive"))),
+        )
+        .path(
+            "/readyz",
+            get(operation("probes", "readyz", "Readiness")
+                .response("200", text("All workers warm"))
+                .response("503", text("Warming up or draining"))),
+        )
+        .path(
+            "/metrics",
+            get(operation("probes", "metrics", "Prometheus metrics")
+                .response("200", text("Prometheus text format 0.0.4"))),
+        )
+        .path(
+            "/drain",
+            get(operation("admin", "drain", "Stop taking work and wait for in-flight jobs (preStop hook)")
+                .response("200", text("Drained"))
+                .response("503", text("Drain timeout"))),
+        )
+        .path(
+            "/pause",
+            get(operation("admin", "pause", "Stop pulling jobs from sources and wait for in-flight jobs")
+                .response("200", text("Paused, no jobs in flight"))
+                .response("503", text("Paused, jobs still in flight after the drain timeout"))),
+        )
+        .path(
+            "/resume",
+            get(operation("admin", "resume", "Pull jobs from sources again").response("200", text("Resumed"))),
+        )
+        .path(
+            "/admin/recent-jobs",
+            get(operation("admin", "recentJobs", "Last finished jobs with stage timings, newest first ([k8s] recent_jobs)").response(
+                "200",
+                response("Recent jobs", "application/json", utoipa::openapi::schema::ArrayBuilder::new().items(Ref::from_schema_name("RecentJob"))),
+            )),
+        )
+        .path(
+            "/openapi.json",
+            get(operation("admin", "openapi", "This document").response(
+                "200",
+                response("OpenAPI 3.1 document", "application/json", ObjectBuilder::new().schema_type(Type::Object)),
+            )),
+        )
+        .build();
+    doc
+}
+
+/// The OpenAPI document as JSON.
+pub fn spec_json() -> String {
+    spec().to_pretty_json().unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_spec_lists_endpoints_and_wire_types() {
+        let doc: serde_json::Value = serde_json::from_str(&spec_json()).unwrap();
+        for path in ["/v1/results/{job_id}", "/v1/results/{job_id}/tensor", "/v1/traces/{trace_id}", "/v1/model", "/v1/usage", "/healthz", "/readyz", "/metrics", "/drain", "/pause", "/resume", "/admin/recent-jobs", "/openapi.json"] {
+            assert!(doc["paths"][path]["get"].is_object(), "{} fehlt", path);
+        }
+        let schemas = &doc["components"]["schemas"];
+        assert!(schemas["JobRequest"].is_object());
+        assert!(schemas["TensorData"]["properties"]["shape"].is_object());
+        assert_eq!(schemas["JobResult"]["required"][0], "id");
+        assert!(schemas["Capabilities"]["properties"]["max_batch"].is_object());
+        assert!(doc["paths"]["/v1/uploads/{job_id}"]["delete"].is_object());
+        assert!(doc["paths"]["/v1/infer"]["post"]["responses"]["202"].is_object());
+        assert!(doc["paths"]["/v1/uploads/{job_id}/chunks/{index}"]["put"].is_object());
+        assert!(doc["paths"]["/v1/blobs/{sha256}"]["put"].is_object());
+        assert!(doc["paths"]["/v1/blobs/{sha256}"]["get"].is_object());
+        assert!(schemas["UploadStatus"]["properties"]["missing"].is_object());
+        assert!(schemas["UsageReport"]["properties"]["gpu_seconds"].is_object());
+        assert!(schemas["RecentJob"]["properties"]["inference_ms"].is_object());
+        assert!(schemas["Problem"]["properties"]["code"].is_object());
+        assert!(schemas["JobResult"]["properties"]["traceparent"].is_object());
+        let not_found = &doc["paths"]["/v1/results/{job_id}"]["get"]["responses"]["404"]["content"];
+        assert!(not_found["application/problem+json"].is_object());
+    }
+}
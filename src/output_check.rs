@@ -0,0 +1,107 @@
+//! NaN/Inf detection on model outputs (`[output_check]`).
+//!
+//! Each job's output is scanned before its result is stored. Affected
+//! results are either flagged with a `non_finite` field or replaced by an
+//! error result; both carry the counts, the model and the worker, so broken
+//! exports or fp16 overflow show up with the job that triggered them.
+
+use ndarray::ArrayViewD;
+use serde_json::{json, Value};
+
+use crate::error::OmniError;
+use crate::health::health;
+use crate::trace_context::{TRACEPARENT, TRACESTATE};
+use crate::types::{Config, NonFiniteAction};
+
+/// Output scan of one worker.
+#[derive(Debug, Clone)]
+pub struct OutputCheck {
+    action: NonFiniteAction,
+    model: String,
+    worker: String,
+}
+
+impl OutputCheck {
+    /// Returns the check configured in `[output_check]`, if any.
+    pub fn from_config(cfg: &Config, worker: &str) -> Option<Self> {
+        let check = cfg.output_check.as_ref()?;
+        Some(Self { action: check.action, model: cfg.model.model_path.clone(), worker: worker.to_string() })
+    }
+
+    /// Scans one job's output and flags or fails its result payload.
+    /// Returns whether the payload was replaced by an error result.
+    pub fn apply(&self, payload: &mut Value, output: ArrayViewD<f32>) -> bool {
+        let nan = output.iter().filter(|v| v.is_nan()).count();
+        let inf = output.iter().filter(|v| v.is_infinite()).count();
+        if nan + inf == 0 {
+            return false;
+        }
+        health().output_non_finite();
+        tracing::warn!(
+            "Job {}: Output enthält {} NaN und {} Inf (Modell {}, Worker {})",
+            payload["id"].as_str().unwrap_or_default(),
+            nan,
+            inf,
+            self.model,
+            self.worker
+        );
+        let diagnostics = json!({ "nan": nan, "inf": inf, "elements": output.len(), "model": self.model, "worker": self.worker });
+        match self.action {
+            NonFiniteAction::Flag => {
+                payload["non_finite"] = diagnostics;
+                false
+            }
+            NonFiniteAction::Fail => {
+                let mut failed = json!({
+                    "schema_version": payload["schema_version"],
+                    "id": payload["id"],
+                    "timestamp": payload["timestamp"],
+                    "error": OmniError::OutputNonFinite.to_string(),
+                    "code": OmniError::OutputNonFinite.code(),
+                    "non_finite": diagnostics,
+                });
+                for key in ["meta", TRACEPARENT, TRACESTATE] {
+                    if let Some(value) = payload.get(key) {
+                        failed[key] = value.clone();
+                    }
+                }
+                *payload = failed;
+                true
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::{ArrayD, IxDyn};
+
+    fn check(action: NonFiniteAction) -> OutputCheck {
+        OutputCheck { action, model: "model.onnx".to_string(), worker: "gpu:1".to_string() }
+    }
+
+    #[test]
+    fn test_flag_and_fail() {
+        let output = ArrayD::from_shape_vec(IxDyn(&[4]), vec![0.5, f32::NAN, f32::INFINITY, f32::NAN]).unwrap();
+        let result = json!({"schema_version": 1, "id": "j1", "timestamp": "t", "top_k": [], "meta": {"k": "v"}});
+
+        let mut flagged = result.clone();
+        assert!(!check(NonFiniteAction::Flag).apply(&mut flagged, output.view()));
+        assert_eq!(flagged["top_k"], json!([]));
+        assert_eq!(flagged["non_finite"]["nan"], 2);
+        assert_eq!(flagged["non_finite"]["worker"], "gpu:1");
+
+        let mut failed = result.clone();
+        assert!(check(NonFiniteAction::Fail).apply(&mut failed, output.view()));
+        assert!(failed.get("top_k").is_none());
+        assert_eq!(failed["error"], "Output enthält NaN/Inf");
+        assert_eq!(failed["code"], "OUTPUT_NON_FINITE");
+        assert_eq!(failed["non_finite"]["inf"], 1);
+        assert_eq!(failed["meta"]["k"], "v");
+
+        let mut clean = result.clone();
+        assert!(!check(NonFiniteAction::Fail).apply(&mut clean, ArrayD::zeros(IxDyn(&[4])).view()));
+        assert_eq!(clean, result);
+    }
+}
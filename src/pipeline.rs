@@ -1,11 +1,13 @@
 //! Pipeline abstraction for pre/post-processing.
 //!
 //! Provides a flexible system for applying transformations before and after inference.
-//! Supports custom Python-based processors or identity (no-op) processors.
+//! Supports custom Python-based processors or identity (no-op) processors, and an
+//! output formatter that turns per-sample tensors into the stored result fields.
 
 use std::sync::Arc;
 use anyhow::Result;
-use ndarray::ArrayD;
+use ndarray::{ArrayD, ArrayViewD};
+use serde_json::{json, Value};
 
 use crate::scripting::plugins::{PythonPreprocessor, PythonPostprocessor};
 
@@ -23,6 +25,26 @@ pub trait Postprocessor: Send + Sync {
     fn run(&self, input: ArrayD<f32>) -> Result<ArrayD<f32>>;
 }
 
+/// Trait for turning a single output sample into result fields.
+///
+/// The returned JSON object is merged into the stored result next to `id` and
+/// `timestamp`. Implementations can attach labels, decoded text, etc.
+pub trait OutputFormatter: Send + Sync {
+    fn format(&self, output: ArrayViewD<f32>) -> Result<Value>;
+}
+
+/// Default formatter storing shape and (up to 256) raw output values.
+pub struct RawOutput;
+
+impl OutputFormatter for RawOutput {
+    fn format(&self, output: ArrayViewD<f32>) -> Result<Value> {
+        Ok(json!({
+            "shape": output.shape(),
+            "data": output.iter().take(256).cloned().collect::<Vec<f32>>() // Beispiel: nur Top-256 Werte
+        }))
+    }
+}
+
 /// Complete processing pipeline with pre and post stages.
 ///
 /// Combines preprocessing and postprocessing into a single pipeline that can be
@@ -31,6 +53,7 @@ pub trait Postprocessor: Send + Sync {
 pub struct Pipeline {
     pub pre: Arc<dyn Preprocessor>,
     pub post: Arc<dyn Postprocessor>,
+    pub output: Arc<dyn OutputFormatter>,
 }
 
 impl Pipeline {
@@ -49,9 +72,40 @@ impl Pipeline {
         Self {
             pre: Arc::new(pre.unwrap_or_else(|| PythonPreprocessor::identity())),
             post: Arc::new(post.unwrap_or_else(|| PythonPostprocessor::identity())),
+            output: Arc::new(RawOutput),
         }
     }
 
+    /// Replaces the preprocessing stage with a Rust-native processor.
+    ///
+    /// # Arguments
+    ///
+    /// * `pre` - Preprocessor to run before inference
+    pub fn with_pre(mut self, pre: impl Preprocessor + 'static) -> Self {
+        self.pre = Arc::new(pre);
+        self
+    }
+
+    /// Replaces the postprocessing stage with a Rust-native processor.
+    ///
+    /// # Arguments
+    ///
+    /// * `post` - Postprocessor to run after inference
+    pub fn with_post(mut self, post: impl Postprocessor + 'static) -> Self {
+        self.post = Arc::new(post);
+        self
+    }
+
+    /// Replaces the output formatter used when storing results.
+    ///
+    /// # Arguments
+    ///
+    /// * `output` - Formatter for per-sample result fields
+    pub fn with_output(mut self, output: impl OutputFormatter + 'static) -> Self {
+        self.output = Arc::new(output);
+        self
+    }
+
     /// Applies preprocessing to the input tensor.
     ///
     /// # Arguments
@@ -0,0 +1,388 @@
+//! Sampled prediction logging for retraining (`[prediction_log]`).
+//!
+//! A configurable fraction of stored results is buffered as rows of job id,
+//! timestamp, model, input reference (a meta field such as an image URL),
+//! job meta, the result JSON and optionally the raw output tensor. Full
+//! buffers and the periodic flush write one Parquet file each to S3 or a
+//! local directory, partitioned Hive-style as
+//! `<prefix>/model=<name>/date=<YYYY-MM-DD>/part-<time>-<host>-<n>.parquet`,
+//! so Spark, DuckDB or `pyarrow.dataset` read the log directly. Requires the
+//! `prediction-log` feature.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, OnceLock};
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use ndarray::ArrayViewD;
+use serde_json::Value;
+
+use crate::types::{Config, JobMeta, PredictionLogCfg};
+
+/// One logged prediction.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Row {
+    pub id: String,
+    pub timestamp: DateTime<Utc>,
+    pub input_ref: Option<String>,
+    pub meta: String,
+    pub result: String,
+    pub output_shape: Vec<i32>,
+    pub output: Vec<f32>,
+}
+
+/// Prediction log of the model served by this process.
+pub struct PredictionLog {
+    cfg: PredictionLogCfg,
+    model: String,
+    host: String,
+    rows: Mutex<Vec<Row>>,
+    files: AtomicU64,
+    full: tokio::sync::Notify,
+    #[cfg(feature = "prediction-log")]
+    store: sink::Store,
+}
+
+static LOG: OnceLock<PredictionLog> = OnceLock::new();
+
+/// Returns the log configured by [`init`], if any.
+pub fn log() -> Option<&'static PredictionLog> {
+    LOG.get()
+}
+
+/// Sets up the log for `[prediction_log]` and starts the periodic flush.
+pub fn init(cfg: &Config) -> Result<()> {
+    let Some(log_cfg) = &cfg.prediction_log else { return Ok(()) };
+    anyhow::ensure!(
+        cfg!(feature = "prediction-log"),
+        "[prediction_log] benötigt das Feature 'prediction-log'"
+    );
+    if LOG.set(PredictionLog::new(log_cfg.clone(), &cfg.model.model_path)?).is_err() {
+        tracing::warn!("Prediction-Log bereits initialisiert");
+        return Ok(());
+    }
+    let log = LOG.get().unwrap();
+    tokio::spawn(async move {
+        let period = std::time::Duration::from_secs(log.cfg.flush_secs.max(1));
+        loop {
+            tokio::select! {
+                _ = log.full.notified() => {}
+                _ = tokio::time::sleep(period) => {}
+            }
+            log.flush().await;
+        }
+    });
+    Ok(())
+}
+
+/// Writes the buffered rows; called on shutdown.
+pub async fn flush() {
+    if let Some(log) = log() {
+        log.flush().await;
+    }
+}
+
+impl PredictionLog {
+    pub fn new(cfg: PredictionLogCfg, model_path: &str) -> Result<Self> {
+        anyhow::ensure!(
+            (0.0..=1.0).contains(&cfg.sample_rate),
+            "prediction_log.sample_rate muss zwischen 0 und 1 liegen"
+        );
+        anyhow::ensure!(cfg.rows_per_file > 0, "prediction_log.rows_per_file muss > 0 sein");
+        let model = cfg.model_name.clone().unwrap_or_else(|| {
+            std::path::Path::new(model_path).file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default()
+        });
+        Ok(Self {
+            #[cfg(feature = "prediction-log")]
+            store: sink::Store::open(&cfg.uri)?,
+            cfg,
+            model,
+            host: std::env::var("HOSTNAME").unwrap_or_else(|_| std::process::id().to_string()),
+            rows: Mutex::new(Vec::new()),
+            files: AtomicU64::new(0),
+            full: tokio::sync::Notify::new(),
+        })
+    }
+
+    /// Whether the job with `meta` is logged; `meta.log_prediction`
+    /// overrides the sample rate.
+    fn sampled(&self, meta: &JobMeta) -> bool {
+        match meta.get("log_prediction").and_then(Value::as_bool) {
+            Some(forced) => forced,
+            None => rand::random::<f64>() < self.cfg.sample_rate,
+        }
+    }
+
+    /// Buffers the stored `result` of one job if it is sampled.
+    pub fn record(&self, id: &str, meta: &JobMeta, result: &Value, output: ArrayViewD<f32>) {
+        if !self.sampled(meta) {
+            return;
+        }
+        let (output_shape, output) = match self.cfg.include_output {
+            true => (output.shape().iter().map(|&d| d as i32).collect(), output.iter().copied().collect()),
+            false => (Vec::new(), Vec::new()),
+        };
+        let row = Row {
+            id: id.to_string(),
+            timestamp: Utc::now(),
+            input_ref: meta.get(&self.cfg.input_ref_key).map(|v| v.as_str().map(str::to_string).unwrap_or_else(|| v.to_string())),
+            meta: Value::Object(meta.clone()).to_string(),
+            result: result.to_string(),
+            output_shape,
+            output,
+        };
+        let mut rows = self.rows.lock().unwrap();
+        rows.push(row);
+        if rows.len() >= self.cfg.rows_per_file {
+            self.full.notify_one();
+        }
+    }
+
+    /// Takes up to one file worth of buffered rows.
+    fn take(&self) -> Vec<Row> {
+        let mut rows = self.rows.lock().unwrap();
+        let n = rows.len().min(self.cfg.rows_per_file);
+        let rest = rows.split_off(n);
+        std::mem::replace(&mut *rows, rest)
+    }
+
+    /// Object path of the next file, relative to the configured prefix.
+    fn file_path(&self, now: DateTime<Utc>) -> String {
+        format!(
+            "model={}/date={}/part-{}-{}-{}.parquet",
+            self.model,
+            now.format("%Y-%m-%d"),
+            now.format("%Y%m%dT%H%M%S%.3f"),
+            self.host,
+            self.files.fetch_add(1, Ordering::Relaxed)
+        )
+    }
+
+    /// Writes all buffered rows, one file per `rows_per_file` rows. Rows of
+    /// a failed upload are dropped with a warning.
+    pub async fn flush(&self) {
+        loop {
+            let rows = self.take();
+            if rows.is_empty() {
+                return;
+            }
+            let path = self.file_path(Utc::now());
+            #[cfg(feature = "prediction-log")]
+            match self.store.write(&path, &self.model, &rows).await {
+                Ok(()) => tracing::debug!("{} Vorhersagen nach {} geschrieben", rows.len(), path),
+                Err(e) => tracing::warn!("Prediction-Log {}: {} Zeilen verworfen: {:#}", path, rows.len(), e),
+            }
+            #[cfg(not(feature = "prediction-log"))]
+            tracing::warn!(
+                "Prediction-Log {}/{}: {} Zeilen verworfen (Feature 'prediction-log' fehlt)",
+                self.cfg.uri,
+                path,
+                rows.len()
+            );
+        }
+    }
+}
+
+/// Parquet encoding and upload.
+#[cfg(feature = "prediction-log")]
+mod sink {
+    use std::sync::Arc;
+
+    use anyhow::{Context, Result};
+    use object_store::path::Path;
+    use object_store::ObjectStore;
+    use parquet::basic::Compression;
+    use parquet::data_type::{ByteArray, ByteArrayType, DataType, FloatType, Int32Type, Int64Type};
+    use parquet::file::properties::WriterProperties;
+    use parquet::file::writer::SerializedFileWriter;
+    use parquet::schema::parser::parse_message_type;
+
+    use super::Row;
+
+    const SCHEMA: &str = "
+        message prediction {
+            required binary id (STRING);
+            required int64 timestamp (TIMESTAMP(MILLIS,true));
+            required binary model (STRING);
+            optional binary input_ref (STRING);
+            required binary meta (STRING);
+            required binary result (STRING);
+            repeated int32 output_shape;
+            repeated float output;
+        }
+    ";
+
+    /// Target bucket or directory with the key prefix.
+    pub struct Store {
+        inner: Arc<dyn ObjectStore>,
+        prefix: String,
+    }
+
+    impl Store {
+        pub fn open(uri: &str) -> Result<Self> {
+            if let Some(rest) = uri.strip_prefix("s3://") {
+                let (bucket, prefix) = rest.split_once('/').unwrap_or((rest, ""));
+                let s3 = object_store::aws::AmazonS3Builder::from_env()
+                    .with_bucket_name(bucket)
+                    .build()
+                    .with_context(|| format!("S3-Bucket '{}' nicht nutzbar", bucket))?;
+                return Ok(Self { inner: Arc::new(s3), prefix: prefix.trim_end_matches('/').to_string() });
+            }
+            std::fs::create_dir_all(uri).with_context(|| format!("Verzeichnis {} nicht anlegbar", uri))?;
+            let local = object_store::local::LocalFileSystem::new_with_prefix(uri)?;
+            Ok(Self { inner: Arc::new(local), prefix: String::new() })
+        }
+
+        pub async fn write(&self, path: &str, model: &str, rows: &[Row]) -> Result<()> {
+            let key = match self.prefix.as_str() {
+                "" => path.to_string(),
+                prefix => format!("{}/{}", prefix, path),
+            };
+            self.inner.put(&Path::from(key), encode(model, rows)?.into()).await?;
+            Ok(())
+        }
+    }
+
+    /// Encodes `rows` as one Parquet file (one row group, Snappy).
+    pub fn encode(model: &str, rows: &[Row]) -> Result<Vec<u8>> {
+        let schema = Arc::new(parse_message_type(SCHEMA)?);
+        let props = Arc::new(WriterProperties::builder().set_compression(Compression::SNAPPY).build());
+        let mut writer = SerializedFileWriter::new(Vec::new(), schema, props)?;
+        let mut group = writer.next_row_group()?;
+
+        let strings = |f: fn(&Row) -> &str| rows.iter().map(|r| ByteArray::from(f(r))).collect::<Vec<_>>();
+        let mut index = 0;
+        while let Some(mut column) = group.next_column()? {
+            match index {
+                0 => write::<ByteArrayType>(&mut column, &strings(|r| &r.id), None, None)?,
+                1 => {
+                    let millis: Vec<i64> = rows.iter().map(|r| r.timestamp.timestamp_millis()).collect();
+                    write::<Int64Type>(&mut column, &millis, None, None)?
+                }
+                2 => write::<ByteArrayType>(&mut column, &vec![ByteArray::from(model); rows.len()], None, None)?,
+                3 => {
+                    let values: Vec<_> = rows.iter().filter_map(|r| r.input_ref.as_deref()).map(ByteArray::from).collect();
+                    let def: Vec<i16> = rows.iter().map(|r| r.input_ref.is_some() as i16).collect();
+                    write::<ByteArrayType>(&mut column, &values, Some(&def), None)?
+                }
+                4 => write::<ByteArrayType>(&mut column, &strings(|r| &r.meta), None, None)?,
+                5 => write::<ByteArrayType>(&mut column, &strings(|r| &r.result), None, None)?,
+                6 => {
+                    let (values, def, rep) = repeated(rows.iter().map(|r| r.output_shape.as_slice()));
+                    write::<Int32Type>(&mut column, &values, Some(&def), Some(&rep))?
+                }
+                _ => {
+                    let (values, def, rep) = repeated(rows.iter().map(|r| r.output.as_slice()));
+                    write::<FloatType>(&mut column, &values, Some(&def), Some(&rep))?
+                }
+            }
+            column.close()?;
+            index += 1;
+        }
+        group.close()?;
+        Ok(writer.into_inner()?)
+    }
+
+    fn write<T: DataType>(
+        column: &mut parquet::file::writer::SerializedColumnWriter<'_>,
+        values: &[T::T],
+        def: Option<&[i16]>,
+        rep: Option<&[i16]>,
+    ) -> Result<()> {
+        column.typed::<T>().write_batch(values, def, rep)?;
+        Ok(())
+    }
+
+    /// Flattens lists into values with definition and repetition levels;
+    /// an empty list is a single level pair without value.
+    fn repeated<'a, T: Copy + 'a>(lists: impl Iterator<Item = &'a [T]>) -> (Vec<T>, Vec<i16>, Vec<i16>) {
+        let (mut values, mut def, mut rep) = (Vec::new(), Vec::new(), Vec::new());
+        for list in lists {
+            if list.is_empty() {
+                def.push(0);
+                rep.push(0);
+            }
+            for (i, &v) in list.iter().enumerate() {
+                values.push(v);
+                def.push(1);
+                rep.push((i > 0) as i16);
+            }
+        }
+        (values, def, rep)
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use parquet::file::reader::{FileReader, SerializedFileReader};
+
+        #[test]
+        fn test_encode_roundtrip() {
+            let row = |id: &str, input_ref: Option<&str>, output: Vec<f32>| Row {
+                id: id.to_string(),
+                timestamp: chrono::DateTime::from_timestamp_millis(1_700_000_000_000).unwrap(),
+                input_ref: input_ref.map(str::to_string),
+                meta: "{}".to_string(),
+                result: r#"{"id":"x"}"#.to_string(),
+                output_shape: if output.is_empty() { vec![] } else { vec![output.len() as i32] },
+                output,
+            };
+            let rows = [row("a", Some("s3://in/a.png"), vec![0.25, 0.75]), row("b", None, vec![])];
+            let bytes = encode("resnet", &rows).unwrap();
+
+            let path = std::env::temp_dir().join(format!("omni-predlog-{}.parquet", std::process::id()));
+            std::fs::write(&path, bytes).unwrap();
+            let reader = SerializedFileReader::new(std::fs::File::open(&path).unwrap()).unwrap();
+            let read: Vec<String> = reader.get_row_iter(None).unwrap().map(|r| r.unwrap().to_string()).collect();
+            std::fs::remove_file(&path).unwrap();
+
+            assert_eq!(read.len(), 2);
+            assert!(read[0].contains("id: \"a\""), "{}", read[0]);
+            assert!(read[0].contains("input_ref: \"s3://in/a.png\""), "{}", read[0]);
+            assert!(read[0].contains("output: [0.25, 0.75]"), "{}", read[0]);
+            assert!(read[1].contains("input_ref: null"), "{}", read[1]);
+            assert!(read[1].contains("output: []"), "{}", read[1]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::{ArrayD, IxDyn};
+    use serde_json::json;
+
+    fn log(sample_rate: f64, rows_per_file: usize) -> PredictionLog {
+        let dir = std::env::temp_dir().join(format!("omni-predlog-{}", std::process::id()));
+        let cfg: PredictionLogCfg = toml::from_str(&format!(
+            "uri = {:?}\nsample_rate = {}\nrows_per_file = {}",
+            dir.to_str().unwrap(),
+            sample_rate,
+            rows_per_file
+        ))
+        .unwrap();
+        PredictionLog::new(cfg, "/models/resnet50.onnx").unwrap()
+    }
+
+    #[test]
+    fn test_sampling_and_batching() {
+        let meta = |v: Value| v.as_object().unwrap().clone();
+        let output = ArrayD::from_elem(IxDyn(&[2]), 0.5);
+        let log = log(0.0, 2);
+        for id in ["a", "b", "c"] {
+            log.record(id, &meta(json!({"log_prediction": true, "input_ref": "s3://in/a.png"})), &json!({"id": id}), output.view());
+        }
+        log.record("d", &meta(json!({})), &json!({"id": "d"}), output.view());
+
+        let first = log.take();
+        assert_eq!(first.iter().map(|r| r.id.as_str()).collect::<Vec<_>>(), ["a", "b"]);
+        assert_eq!(first[0].input_ref.as_deref(), Some("s3://in/a.png"));
+        assert_eq!((first[0].output_shape.clone(), first[0].output.clone()), (vec![2], vec![0.5, 0.5]));
+        assert_eq!(log.take().len(), 1);
+        assert!(log.take().is_empty());
+
+        let path = log.file_path("2026-10-17T08:30:00Z".parse().unwrap());
+        assert!(path.starts_with("model=resnet50/date=2026-10-17/part-20261017T083000.000-"), "{}", path);
+        assert!(path.ends_with("-0.parquet"), "{}", path);
+    }
+}
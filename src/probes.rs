@@ -0,0 +1,280 @@
+//! Accuracy probes (`[probes]`).
+//!
+//! Silent corruption (driver bugs, flipped bits in device memory, a broken
+//! model reload) raises no errors, it only changes the outputs. Every
+//! `interval_secs` each worker gets the configured canary jobs into its
+//! queue, so they are batched and run like any other job. Their outputs are
+//! compared with the recorded expected outputs instead of being stored; a
+//! deviation beyond the tolerance is logged, counted in
+//! `omniengine_canary_failures_total` and published as an alert. With
+//! `fallback = true` the worker also switches to `[precision_fallback]`.
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use ndarray::{ArrayD, ArrayViewD};
+use serde_json::{json, Value};
+use tokio::sync::mpsc;
+
+use crate::health::health;
+use crate::storage::redis_store::RedisStorage;
+use crate::types::{CanaryCfg, Config, Job, ProbesCfg};
+
+/// Canaries without a result after this many rounds are given up.
+const EXPIRE_ROUNDS: u32 = 10;
+
+static PROBES: OnceLock<Probes> = OnceLock::new();
+
+/// Loads the canaries and publishes failures to `[probes] channel`.
+pub fn init(cfg: &Config, store: RedisStorage) -> Result<()> {
+    let Some(probes_cfg) = &cfg.probes else { return Ok(()) };
+    anyhow::ensure!(
+        cfg.generation.is_none() && cfg.session.is_none(),
+        "[probes] ist nur mit Batch-Workern nutzbar (nicht mit [generation] oder [session])"
+    );
+    anyhow::ensure!(
+        !probes_cfg.fallback || cfg.precision_fallback.is_some(),
+        "[probes] fallback = true benötigt [precision_fallback]"
+    );
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    if PROBES.set(Probes::new(probes_cfg, tx)?).is_err() {
+        return Ok(());
+    }
+    let channel = probes_cfg.channel.clone();
+    tokio::spawn(async move {
+        while let Some(alert) = rx.recv().await {
+            if let Err(e) = store.publish_json(&channel, &alert).await {
+                tracing::warn!("Canary-Alarm nicht veröffentlicht: {}", e);
+            }
+        }
+    });
+    Ok(())
+}
+
+/// Injects the canaries into the worker queues every `interval_secs`.
+///
+/// Holds the queues weakly, so the probes end with the workers.
+pub fn spawn(workers: Vec<(String, mpsc::WeakSender<Job>)>) {
+    let Some(probes) = PROBES.get() else { return };
+    tokio::spawn(async move {
+        let mut tick = tokio::time::interval(probes.interval);
+        // Erste Runde erst nach einem Intervall (Warmup, Start der Quellen)
+        tick.tick().await;
+        loop {
+            tick.tick().await;
+            probes.expire(probes.interval * EXPIRE_ROUNDS);
+            let mut open = false;
+            for (worker, tx) in &workers {
+                let Some(tx) = tx.upgrade() else { continue };
+                open = true;
+                for i in 0..probes.canaries.len() {
+                    let job = probes.job(i, worker);
+                    let id = job.id.clone();
+                    match tx.send(job).await {
+                        Ok(()) => health().job_accepted(),
+                        Err(_) => probes.forget(&id),
+                    }
+                }
+            }
+            if !open {
+                break;
+            }
+        }
+    });
+}
+
+/// Whether `id` is a canary job waiting for its result.
+pub fn is_canary(id: &str) -> bool {
+    PROBES.get().is_some_and(|p| p.pending.lock().unwrap().contains_key(id))
+}
+
+/// Checks the output of a canary job. Returns `false` for regular jobs,
+/// which are stored as usual.
+pub fn verify(id: &str, output: ArrayViewD<f32>) -> bool {
+    PROBES.get().is_some_and(|p| p.verify(id, output))
+}
+
+/// A canary input with its expected output.
+struct Canary {
+    name: String,
+    input: ArrayD<f32>,
+    expected: ArrayD<f32>,
+    tolerance: f32,
+}
+
+impl Canary {
+    fn load(cfg: &CanaryCfg, tolerance: f32) -> Result<Self> {
+        let input = crate::npy::load(Path::new(&cfg.input)).with_context(|| format!("Canary '{}': Input", cfg.name))?;
+        let expected =
+            crate::npy::load(Path::new(&cfg.expected)).with_context(|| format!("Canary '{}': erwarteter Output", cfg.name))?;
+        Ok(Self { name: cfg.name.clone(), input, expected, tolerance: cfg.tolerance.unwrap_or(tolerance) })
+    }
+
+    /// Largest absolute deviation from the expected output; infinite for
+    /// NaN or a different shape.
+    fn deviation(&self, output: ArrayViewD<f32>) -> f32 {
+        if output.shape() != self.expected.shape() {
+            return f32::INFINITY;
+        }
+        output.iter().zip(&self.expected).fold(0.0, |max, (a, b)| {
+            let d = (a - b).abs();
+            if d.is_nan() { f32::INFINITY } else { max.max(d) }
+        })
+    }
+}
+
+/// A canary on its way through a worker.
+struct Pending {
+    canary: usize,
+    worker: String,
+    sent: Instant,
+}
+
+struct Probes {
+    canaries: Vec<Canary>,
+    interval: Duration,
+    fallback: bool,
+    pending: Mutex<HashMap<String, Pending>>,
+    seq: AtomicU64,
+    alerts: mpsc::UnboundedSender<Value>,
+}
+
+impl Probes {
+    fn new(cfg: &ProbesCfg, alerts: mpsc::UnboundedSender<Value>) -> Result<Self> {
+        anyhow::ensure!(!cfg.canaries.is_empty(), "[probes] ohne [[probes.canaries]]");
+        anyhow::ensure!(cfg.interval_secs > 0, "[probes] interval_secs muss > 0 sein");
+        let canaries = cfg.canaries.iter().map(|c| Canary::load(c, cfg.tolerance)).collect::<Result<_>>()?;
+        Ok(Self::with_canaries(canaries, cfg, alerts))
+    }
+
+    fn with_canaries(canaries: Vec<Canary>, cfg: &ProbesCfg, alerts: mpsc::UnboundedSender<Value>) -> Self {
+        Self {
+            canaries,
+            interval: Duration::from_secs(cfg.interval_secs),
+            fallback: cfg.fallback,
+            pending: Mutex::new(HashMap::new()),
+            seq: AtomicU64::new(0),
+            alerts,
+        }
+    }
+
+    /// Creates the job of canary `i` for `worker` and registers it.
+    fn job(&self, i: usize, worker: &str) -> Job {
+        let canary = &self.canaries[i];
+        let id = format!("canary:{}:{}:{}", canary.name, worker, self.seq.fetch_add(1, Ordering::Relaxed));
+        let pending = Pending { canary: i, worker: worker.to_string(), sent: Instant::now() };
+        self.pending.lock().unwrap().insert(id.clone(), pending);
+        Job { id, tensor: canary.input.clone(), enqueued: Some(Instant::now()), ..Default::default() }
+    }
+
+    fn forget(&self, id: &str) {
+        self.pending.lock().unwrap().remove(id);
+    }
+
+    /// Drops canaries that never came back (e.g. lost with a failed worker).
+    fn expire(&self, max_age: Duration) {
+        self.pending.lock().unwrap().retain(|id, p| {
+            let alive = p.sent.elapsed() < max_age;
+            if !alive {
+                tracing::warn!("Canary {} auf {} ohne Ergebnis verworfen", id, p.worker);
+            }
+            alive
+        });
+    }
+
+    fn verify(&self, id: &str, output: ArrayViewD<f32>) -> bool {
+        let Some(pending) = self.pending.lock().unwrap().remove(id) else { return false };
+        let canary = &self.canaries[pending.canary];
+        let deviation = canary.deviation(output);
+        let failed = deviation > canary.tolerance;
+        health().canary_checked(failed);
+        if !failed {
+            tracing::debug!("Canary '{}' auf {}: Abweichung {}", canary.name, pending.worker, deviation);
+            return true;
+        }
+        tracing::error!(
+            "Canary '{}' auf {} weicht ab: {} > Toleranz {}",
+            canary.name,
+            pending.worker,
+            deviation,
+            canary.tolerance
+        );
+        let _ = self.alerts.send(json!({
+            "type": "canary_failure",
+            "canary": canary.name,
+            "worker": pending.worker,
+            // JSON kennt kein Inf
+            "deviation": deviation.is_finite().then_some(deviation),
+            "tolerance": canary.tolerance,
+            "timestamp": chrono::Utc::now().to_rfc3339(),
+        }));
+        if self.fallback {
+            crate::engine::fallback::request(&pending.worker, &format!("Abweichung von Canary '{}'", canary.name));
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::IxDyn;
+
+    fn probes() -> (Probes, mpsc::UnboundedReceiver<Value>) {
+        let cfg: ProbesCfg = toml::from_str(
+            r#"
+            tolerance = 0.01
+            [[canaries]]
+            name = "cat"
+            input = "cat_in.npy"
+            expected = "cat_out.npy"
+            "#,
+        )
+        .unwrap();
+        let canary = Canary {
+            name: "cat".to_string(),
+            input: ArrayD::zeros(IxDyn(&[1, 4])),
+            expected: ArrayD::from_shape_vec(IxDyn(&[2]), vec![0.9, 0.1]).unwrap(),
+            tolerance: cfg.tolerance,
+        };
+        let (tx, rx) = mpsc::unbounded_channel();
+        (Probes::with_canaries(vec![canary], &cfg, tx), rx)
+    }
+
+    #[test]
+    fn test_canary_within_tolerance() {
+        let (probes, mut alerts) = probes();
+        let job = probes.job(0, "gpu:0");
+        assert_eq!(job.tensor.shape(), &[1, 4]);
+
+        let output = ArrayD::from_shape_vec(IxDyn(&[2]), vec![0.905, 0.1]).unwrap();
+        assert!(!probes.verify("job-1", output.view()));
+        assert!(probes.verify(&job.id, output.view()));
+        // Jeder Canary wird genau einmal geprüft
+        assert!(!probes.verify(&job.id, output.view()));
+        assert!(alerts.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_canary_deviation_alerts() {
+        let (probes, mut alerts) = probes();
+        let job = probes.job(0, "gpu:1");
+        let output = ArrayD::from_shape_vec(IxDyn(&[2]), vec![0.5, 0.5]).unwrap();
+        assert!(probes.verify(&job.id, output.view()));
+        let alert = alerts.try_recv().unwrap();
+        assert_eq!(alert["type"], "canary_failure");
+        assert_eq!(alert["worker"], "gpu:1");
+        assert!((alert["deviation"].as_f64().unwrap() - 0.4).abs() < 1e-6);
+
+        // NaN und falsche Shape gelten als unendliche Abweichung
+        let job = probes.job(0, "gpu:1");
+        let output = ArrayD::from_shape_vec(IxDyn(&[2]), vec![f32::NAN, 0.1]).unwrap();
+        assert!(probes.verify(&job.id, output.view()));
+        assert!(alerts.try_recv().unwrap()["deviation"].is_null());
+        assert_eq!(probes.canaries[0].deviation(ArrayD::zeros(IxDyn(&[3])).view()), f32::INFINITY);
+    }
+}
@@ -0,0 +1,111 @@
+//! Built-in Rust pre/post-processing stages.
+//!
+//! Native implementations of common tensor transformations that can be plugged
+//! into a `Pipeline` without going through Python.
+
+use anyhow::Result;
+use ndarray::{ArrayD, Axis};
+
+use crate::pipeline::{Postprocessor, Preprocessor};
+
+/// L2-normalizes each sample of a batch output `[N, ...]`.
+///
+/// Samples with zero norm are left unchanged.
+pub struct L2Normalize;
+
+impl Postprocessor for L2Normalize {
+    fn run(&self, mut input: ArrayD<f32>) -> Result<ArrayD<f32>> {
+        anyhow::ensure!(input.ndim() >= 1, "L2Normalize erwartet mindestens 1D-Output");
+        for mut row in input.axis_iter_mut(Axis(0)) {
+            let norm = row.iter().map(|v| v * v).sum::<f32>().sqrt();
+            if norm > 0.0 {
+                row.mapv_inplace(|v| v / norm);
+            }
+        }
+        Ok(input)
+    }
+}
+
+/// Normalizes a batch input `[N, C, ...]` as `(x - mean) / std`, with one
+/// value for all channels or one per channel (axis 1).
+pub struct Normalize {
+    mean: Vec<f32>,
+    std: Vec<f32>,
+}
+
+impl Normalize {
+    /// Checks that `mean`/`std` hold one value or one per channel and that
+    /// no `std` is zero.
+    pub fn new(mean: Vec<f32>, std: Vec<f32>, channels: usize) -> Result<Self> {
+        let mean = if mean.is_empty() { vec![0.0] } else { mean };
+        let std = if std.is_empty() { vec![1.0] } else { std };
+        for (name, values) in [("mean", &mean), ("std", &std)] {
+            anyhow::ensure!(
+                values.len() == 1 || values.len() == channels,
+                "[input] {} hat {} Werte, erwartet 1 oder {} (channels)",
+                name,
+                values.len(),
+                channels
+            );
+        }
+        anyhow::ensure!(std.iter().all(|&s| s != 0.0), "[input] std darf nicht 0 sein");
+        Ok(Self { mean, std })
+    }
+}
+
+impl Preprocessor for Normalize {
+    fn run(&self, mut input: ArrayD<f32>) -> Result<ArrayD<f32>> {
+        if self.mean.len() == 1 && self.std.len() == 1 {
+            let (mean, std) = (self.mean[0], self.std[0]);
+            input.mapv_inplace(|v| (v - mean) / std);
+            return Ok(input);
+        }
+        let channels = self.mean.len().max(self.std.len());
+        anyhow::ensure!(
+            input.ndim() >= 2 && input.shape()[1] == channels,
+            "Normalize erwartet {} Kanäle auf Achse 1, Input hat Shape {:?}",
+            channels,
+            input.shape()
+        );
+        for (c, mut plane) in input.axis_iter_mut(Axis(1)).enumerate() {
+            let mean = self.mean[c.min(self.mean.len() - 1)];
+            let std = self.std[c.min(self.std.len() - 1)];
+            plane.mapv_inplace(|v| (v - mean) / std);
+        }
+        Ok(input)
+    }
+}
+
+/// Numerically stable softmax over a sequence of logits.
+pub fn softmax(values: impl Iterator<Item = f32>) -> Vec<f32> {
+    let values: Vec<f32> = values.collect();
+    let max = values.iter().cloned().fold(f32::NEG_INFINITY, f32::max);
+    let exps: Vec<f32> = values.iter().map(|v| (v - max).exp()).collect();
+    let sum: f32 = exps.iter().sum();
+    exps.into_iter().map(|e| e / sum).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::Array;
+
+    #[test]
+    fn test_l2_normalize_rows() {
+        let x = Array::from_shape_vec((2, 2), vec![3.0f32, 4.0, 0.0, 0.0]).unwrap().into_dyn();
+        let y = L2Normalize.run(x).unwrap();
+        assert!((y[[0, 0]] - 0.6).abs() < 1e-6);
+        assert!((y[[0, 1]] - 0.8).abs() < 1e-6);
+        assert_eq!(y[[1, 0]], 0.0);
+    }
+
+    #[test]
+    fn test_normalize_per_channel() {
+        let x = Array::from_shape_vec((1, 2, 2), vec![1.0f32, 3.0, 10.0, 20.0]).unwrap().into_dyn();
+        let y = Normalize::new(vec![1.0, 10.0], vec![2.0], 2).unwrap().run(x).unwrap();
+        assert_eq!(y.iter().copied().collect::<Vec<_>>(), vec![0.0, 1.0, 0.0, 5.0]);
+
+        assert!(Normalize::new(vec![0.5; 3], vec![], 2).is_err());
+        assert!(Normalize::new(vec![], vec![0.0], 2).is_err());
+    }
+}
@@ -0,0 +1,214 @@
+//! Protobuf wire format for queue transports (feature `protobuf`).
+//!
+//! The messages follow `proto/omniengine.proto` and are derived with prost
+//! directly, so builds need no `protoc`. Tensors travel as raw bytes, which
+//! avoids the encode/decode cost of JSON number arrays for small models.
+//! Rust producers can use [`encode_job`] to fill the `job_pb` stream field.
+
+use std::collections::HashMap;
+
+use anyhow::{Context, Result};
+use ndarray::{ArrayD, IxDyn};
+use prost::Message;
+use serde_json::Value;
+
+use crate::storage::redis_store::{decode_values, f32_bytes};
+use crate::types::{Job, JobMeta, TensorDtype, SCHEMA_VERSION};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, prost::Enumeration)]
+#[repr(i32)]
+pub enum DType {
+    F32 = 0,
+    F16 = 1,
+    Bf16 = 2,
+    I8 = 3,
+    U8 = 4,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct Tensor {
+    #[prost(uint64, repeated, tag = "1")]
+    pub shape: Vec<u64>,
+    #[prost(enumeration = "DType", tag = "2")]
+    pub dtype: i32,
+    #[prost(bytes = "vec", tag = "3")]
+    pub data: Vec<u8>,
+    #[prost(float, tag = "4")]
+    pub scale: f32,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct JobMessage {
+    #[prost(uint32, tag = "1")]
+    pub schema_version: u32,
+    #[prost(string, tag = "2")]
+    pub id: String,
+    #[prost(message, optional, tag = "3")]
+    pub tensor: Option<Tensor>,
+    #[prost(map = "string, message", tag = "4")]
+    pub inputs: HashMap<String, Tensor>,
+    #[prost(string, tag = "5")]
+    pub meta_json: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ResultMessage {
+    #[prost(uint32, tag = "1")]
+    pub schema_version: u32,
+    #[prost(string, tag = "2")]
+    pub id: String,
+    #[prost(string, tag = "3")]
+    pub timestamp: String,
+    #[prost(string, tag = "4")]
+    pub fields_json: String,
+    #[prost(message, optional, tag = "5")]
+    pub tensor: Option<Tensor>,
+}
+
+impl Tensor {
+    /// f32 tensor from row-major values.
+    pub fn from_f32(shape: &[usize], values: &[f32]) -> Self {
+        Self { shape: shape.iter().map(|&d| d as u64).collect(), dtype: DType::F32 as i32, data: f32_bytes(values), scale: 0.0 }
+    }
+
+    /// Converts the tensor to f32; `u8` values are taken as they are.
+    pub fn to_array(&self) -> Result<ArrayD<f32>> {
+        let dtype = DType::try_from(self.dtype).map_err(|_| anyhow::anyhow!("Unbekannter Tensor-Typ {}", self.dtype))?;
+        let shape: Vec<usize> = self.shape.iter().map(|&d| d as usize).collect();
+        let (size, reduced) = match dtype {
+            DType::F32 => (4, Some(TensorDtype::F32)),
+            DType::F16 => (2, Some(TensorDtype::F16)),
+            DType::Bf16 => (2, Some(TensorDtype::Bf16)),
+            DType::I8 => (1, Some(TensorDtype::I8)),
+            DType::U8 => (1, None),
+        };
+        let expected = shape.iter().try_fold(size, |n: usize, &d| n.checked_mul(d)).context("Shape zu groß")?;
+        anyhow::ensure!(
+            self.data.len() == expected,
+            "Tensor-Daten passen nicht zur Shape {:?}: {} statt {} Bytes",
+            shape,
+            self.data.len(),
+            expected
+        );
+        let scale = if self.scale == 0.0 { 1.0 } else { self.scale };
+        let values = match reduced {
+            Some(dtype) => decode_values(&self.data, dtype, scale),
+            None => self.data.iter().map(|&b| b as f32).collect(),
+        };
+        Ok(ArrayD::from_shape_vec(IxDyn(&shape), values)?)
+    }
+}
+
+/// Decodes a protobuf job message.
+pub fn decode_job(bytes: &[u8]) -> Result<Job> {
+    let message = JobMessage::decode(bytes).context("Ungültige Protobuf-Job-Nachricht")?;
+    let version = message.schema_version.max(1);
+    if version > SCHEMA_VERSION {
+        tracing::debug!("Job '{}' hat Schema-Version {} (unterstützt: {})", message.id, version, SCHEMA_VERSION);
+    }
+    let tensor = message.tensor.with_context(|| format!("Job '{}' ohne Tensor", message.id))?.to_array()?;
+    let inputs = message
+        .inputs
+        .iter()
+        .map(|(name, t)| Ok((name.clone(), t.to_array()?)))
+        .collect::<Result<_>>()?;
+    let meta = match message.meta_json.as_str() {
+        "" => JobMeta::new(),
+        json => serde_json::from_str(json).with_context(|| format!("Job '{}': meta_json ist kein JSON-Objekt", message.id))?,
+    };
+    Ok(Job { id: message.id, tensor, meta, inputs, ack: None, enqueued: None })
+}
+
+/// Encodes a job as a protobuf message (f32 tensors).
+pub fn encode_job(job: &Job) -> Vec<u8> {
+    let tensor = |a: &ArrayD<f32>| Tensor::from_f32(a.shape(), &a.iter().copied().collect::<Vec<_>>());
+    JobMessage {
+        schema_version: SCHEMA_VERSION,
+        id: job.id.clone(),
+        tensor: Some(tensor(&job.tensor)),
+        inputs: job.inputs.iter().map(|(name, a)| (name.clone(), tensor(a))).collect(),
+        meta_json: if job.meta.is_empty() { String::new() } else { Value::Object(job.meta.clone()).to_string() },
+    }
+    .encode_to_vec()
+}
+
+/// Encodes a result payload. Numeric `shape`/`data` fields (raw output)
+/// become the tensor, all other fields stay JSON in `fields_json`.
+pub fn encode_result(result: &Value) -> Vec<u8> {
+    let mut fields = result.as_object().cloned().unwrap_or_default();
+    let text = |fields: &mut JobMeta, key: &str| match fields.remove(key) {
+        Some(Value::String(s)) => s,
+        Some(other) => other.to_string(),
+        None => String::new(),
+    };
+    let id = text(&mut fields, "id");
+    let timestamp = text(&mut fields, "timestamp");
+    fields.remove("schema_version");
+
+    let shape: Option<Vec<usize>> = fields.get("shape").and_then(|s| serde_json::from_value(s.clone()).ok());
+    let data: Option<Vec<f32>> = fields.get("data").and_then(|d| serde_json::from_value(d.clone()).ok());
+    let tensor = match (shape, data) {
+        (Some(shape), Some(data)) => {
+            fields.remove("data");
+            // Der Raw-Formatter kürzt `data`: dann flacher Tensor, `shape` bleibt im JSON
+            let shape = if shape.iter().product::<usize>() == data.len() {
+                fields.remove("shape");
+                shape
+            } else {
+                vec![data.len()]
+            };
+            Some(Tensor::from_f32(&shape, &data))
+        }
+        _ => None,
+    };
+    ResultMessage {
+        schema_version: SCHEMA_VERSION,
+        id,
+        timestamp,
+        fields_json: if fields.is_empty() { String::new() } else { Value::Object(fields).to_string() },
+        tensor,
+    }
+    .encode_to_vec()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_job_roundtrip() {
+        let mut job = Job { id: "j1".to_string(), tensor: ArrayD::from_elem(IxDyn(&[2, 3]), 0.5), ..Default::default() };
+        job.meta.insert("k".to_string(), "v".into());
+        job.inputs.insert("ids".to_string(), ArrayD::from_elem(IxDyn(&[4]), 7.0));
+
+        let decoded = decode_job(&encode_job(&job)).unwrap();
+        assert_eq!(decoded.id, "j1");
+        assert_eq!(decoded.tensor, job.tensor);
+        assert_eq!(decoded.inputs["ids"], job.inputs["ids"]);
+        assert_eq!(decoded.meta["k"], "v");
+    }
+
+    #[test]
+    fn test_u8_tensor_and_size_check() {
+        let tensor = Tensor { shape: vec![3], dtype: DType::U8 as i32, data: vec![0, 128, 255], scale: 0.0 };
+        assert_eq!(tensor.to_array().unwrap().as_slice().unwrap(), &[0.0, 128.0, 255.0]);
+
+        let short = Tensor { shape: vec![4], ..tensor.clone() };
+        assert!(short.to_array().is_err());
+
+        // Überlaufende Shape wird abgewiesen statt umgebrochen
+        let overflow = Tensor { shape: vec![u64::MAX / 2 + 1, 2], dtype: DType::F32 as i32, ..tensor };
+        let err = overflow.to_array().unwrap_err().to_string();
+        assert!(err.contains("Shape zu groß"), "{}", err);
+    }
+
+    #[test]
+    fn test_result_moves_raw_output_into_tensor() {
+        let result = json!({"schema_version": 1, "id": "j1", "timestamp": "t", "shape": [2], "data": [1.0, 2.0], "meta": {"k": "v"}});
+        let message = ResultMessage::decode(encode_result(&result).as_slice()).unwrap();
+        assert_eq!(message.id, "j1");
+        assert_eq!(message.tensor.unwrap().to_array().unwrap().as_slice().unwrap(), &[1.0, 2.0]);
+        assert_eq!(serde_json::from_str::<Value>(&message.fields_json).unwrap(), json!({"meta": {"k": "v"}}));
+    }
+}
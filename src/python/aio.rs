@@ -0,0 +1,73 @@
+//! asyncio integration: awaitables backed by tokio futures.
+//!
+//! Same approach as pyo3-async-runtimes: the Rust future runs on a tokio
+//! runtime and its result is handed to the calling event loop via
+//! `call_soon_threadsafe`, so the loop is never blocked.
+
+use super::to_py_err;
+use pyo3::prelude::*;
+use pyo3::types::PyCFunction;
+use std::future::Future;
+use std::sync::OnceLock;
+
+/// Shared runtime for objects without their own (e.g. `PyOnnxEngine`).
+pub(super) fn runtime() -> &'static tokio::runtime::Runtime {
+    static RUNTIME: OnceLock<tokio::runtime::Runtime> = OnceLock::new();
+    RUNTIME.get_or_init(|| {
+        tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()
+            .expect("tokio-Runtime konnte nicht gestartet werden")
+    })
+}
+
+/// Spawns `fut` on `handle` and returns an `asyncio.Future` of the running
+/// loop that resolves to `convert(result)` or raises the error.
+pub(super) fn future_into_py<'py, T, F, C>(
+    py: Python<'py>,
+    handle: &tokio::runtime::Handle,
+    fut: F,
+    convert: C,
+) -> PyResult<Bound<'py, PyAny>>
+where
+    T: Send + 'static,
+    F: Future<Output = anyhow::Result<T>> + Send + 'static,
+    C: FnOnce(Python<'_>, T) -> PyResult<PyObject> + Send + 'static,
+{
+    let event_loop = py.import_bound("asyncio")?.call_method0("get_running_loop")?;
+    let py_fut = event_loop.call_method0("create_future")?;
+    let (event_loop_ref, py_fut_ref) = (event_loop.unbind(), py_fut.clone().unbind());
+
+    handle.spawn(async move {
+        let out = fut.await;
+        Python::with_gil(|py| {
+            let (value, is_err) = match out.map_err(to_py_err).and_then(|v| convert(py, v)) {
+                Ok(v) => (v, false),
+                Err(e) => (e.into_value(py).into_any(), true),
+            };
+            if let Err(e) = resolve(event_loop_ref.bind(py), py_fut_ref, value, is_err) {
+                // z.B. Event-Loop bereits geschlossen
+                tracing::debug!("[python] Future konnte nicht abgeschlossen werden: {}", e);
+            }
+        });
+    });
+    Ok(py_fut)
+}
+
+/// Sets result or exception of `fut` from within its event loop.
+fn resolve(event_loop: &Bound<'_, PyAny>, fut: PyObject, value: PyObject, is_err: bool) -> PyResult<()> {
+    let py = event_loop.py();
+    let setter = PyCFunction::new_closure_bound(py, None, None, move |args, _kwargs| -> PyResult<()> {
+        let py = args.py();
+        let fut = fut.bind(py);
+        // Abgebrochene Futures nicht mehr setzen
+        if fut.call_method0("done")?.is_truthy()? {
+            return Ok(());
+        }
+        let method = if is_err { "set_exception" } else { "set_result" };
+        fut.call_method1(method, (value.clone_ref(py),))?;
+        Ok(())
+    })?;
+    event_loop.call_method1("call_soon_threadsafe", (setter,))?;
+    Ok(())
+}
@@ -0,0 +1,397 @@
+//! Client for a running OmniEngine service.
+//!
+//! `PyClient` submits jobs and fetches results either over the HTTP API
+//! (`http://...`, needs `[server.http.infer]`) or through Redis
+//! (`redis://...`: jobs are appended to the cluster stream, results read
+//! from the result store), so Python producers need no own protocol code.
+//!
+//! One client is meant to be shared: its methods release the GIL and can
+//! be called from many threads and asyncio tasks at once. HTTP requests
+//! reuse pooled keep-alive connections, Redis commands are multiplexed over
+//! one connection; at most `max_connections` requests are in flight.
+
+use super::{aio, input_array, to_py_err, value_to_py};
+use crate::error::OmniError;
+use crate::storage::redis_store::RedisStorage;
+use anyhow::Context;
+use pyo3::prelude::*;
+use serde_json::{json, Value};
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Semaphore;
+
+/// Longest single long-poll; waits beyond it are split into several requests.
+const POLL: Duration = Duration::from_secs(30);
+
+/// Failure reported by the service or on the way to it.
+#[derive(Debug)]
+pub(super) enum ClientError {
+    /// Error answer (problem details) of the HTTP API.
+    Server { code: String, status: u16, message: String },
+    /// Service not reachable.
+    Connection(String),
+}
+
+impl fmt::Display for ClientError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Server { message, .. } => f.write_str(message),
+            Self::Connection(cause) => write!(f, "OmniEngine nicht erreichbar: {}", cause),
+        }
+    }
+}
+
+impl std::error::Error for ClientError {}
+
+enum Transport {
+    Http {
+        http: reqwest::Client,
+        base: String,
+    },
+    Redis {
+        client: redis::Client,
+        con: tokio::sync::Mutex<Option<redis::aio::MultiplexedConnection>>,
+        store: Box<RedisStorage>,
+        stream: String,
+    },
+}
+
+/// Shared state of a client and its pending async calls.
+struct Inner {
+    transport: Transport,
+    permits: Semaphore,
+    next_id: AtomicU64,
+}
+
+/// Client for a running OmniEngine service, over HTTP or Redis.
+///
+/// ```python
+/// client = omniengine.PyClient("http://localhost:8000")
+/// result = client.infer(x, timeout=5.0)
+/// results = client.infer_many([x1, x2, x3])   # concurrently
+/// ```
+#[pyclass]
+pub struct PyClient {
+    inner: Arc<Inner>,
+    #[pyo3(get)]
+    url: String,
+}
+
+#[pymethods]
+impl PyClient {
+    /// Connects lazily to `url` (`http(s)://` or `redis://`). `stream` and
+    /// `out_prefix` must match `[cluster] stream` and `[redis] out_prefix`
+    /// of the service when using Redis.
+    #[new]
+    #[pyo3(signature = (url, max_connections=16, stream="omniengine:jobs".to_string(), out_prefix="results".to_string()))]
+    pub fn new(url: String, max_connections: usize, stream: String, out_prefix: String) -> PyResult<Self> {
+        let transport = transport(&url, max_connections, stream, out_prefix).map_err(to_py_err)?;
+        let inner = Inner { transport, permits: Semaphore::new(max_connections.max(1)), next_id: AtomicU64::new(0) };
+        Ok(Self { inner: Arc::new(inner), url })
+    }
+
+    /// Submits one input and returns its job id (generated unless `id` is
+    /// given); `meta` is passed through to the result.
+    #[pyo3(signature = (array, id=None, meta=None, normalize=false))]
+    pub fn submit(
+        &self,
+        py: Python<'_>,
+        array: &Bound<'_, PyAny>,
+        id: Option<String>,
+        meta: Option<&Bound<'_, PyAny>>,
+        normalize: bool,
+    ) -> PyResult<String> {
+        let request = request(array, id, meta, normalize)?;
+        let inner = &self.inner;
+        py.allow_threads(|| aio::runtime().block_on(inner.submit(request))).map_err(to_py_err)
+    }
+
+    /// Awaitable variant of `submit`.
+    #[pyo3(signature = (array, id=None, meta=None, normalize=false))]
+    pub fn submit_async<'py>(
+        &self,
+        py: Python<'py>,
+        array: &Bound<'py, PyAny>,
+        id: Option<String>,
+        meta: Option<&Bound<'py, PyAny>>,
+        normalize: bool,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        let request = request(array, id, meta, normalize)?;
+        let inner = Arc::clone(&self.inner);
+        let fut = async move { inner.submit(request).await };
+        aio::future_into_py(py, aio::runtime().handle(), fut, |py, id| Ok(id.into_py(py)))
+    }
+
+    /// Waits for the result of a job and returns it as a dict.
+    ///
+    /// Raises `TimeoutError` after `timeout` seconds (waits forever if
+    /// `None`).
+    #[pyo3(signature = (id, timeout=None))]
+    pub fn get_result(&self, py: Python<'_>, id: String, timeout: Option<f64>) -> PyResult<PyObject> {
+        let inner = &self.inner;
+        let result = py.allow_threads(|| aio::runtime().block_on(inner.result(&id, seconds(timeout))));
+        value_to_py(py, &result.map_err(to_py_err)?)
+    }
+
+    /// Awaitable variant of `get_result`.
+    #[pyo3(signature = (id, timeout=None))]
+    pub fn get_result_async<'py>(&self, py: Python<'py>, id: String, timeout: Option<f64>) -> PyResult<Bound<'py, PyAny>> {
+        let inner = Arc::clone(&self.inner);
+        let fut = async move { inner.result(&id, seconds(timeout)).await };
+        aio::future_into_py(py, aio::runtime().handle(), fut, |py, result| value_to_py(py, &result))
+    }
+
+    /// Submits one input and waits for its result (`submit` plus
+    /// `get_result` in one call).
+    #[pyo3(signature = (array, id=None, meta=None, normalize=false, timeout=None))]
+    pub fn infer(
+        &self,
+        py: Python<'_>,
+        array: &Bound<'_, PyAny>,
+        id: Option<String>,
+        meta: Option<&Bound<'_, PyAny>>,
+        normalize: bool,
+        timeout: Option<f64>,
+    ) -> PyResult<PyObject> {
+        let request = request(array, id, meta, normalize)?;
+        let inner = &self.inner;
+        let result = py.allow_threads(|| aio::runtime().block_on(inner.infer(request, seconds(timeout))));
+        value_to_py(py, &result.map_err(to_py_err)?)
+    }
+
+    /// Awaitable variant of `infer`.
+    #[pyo3(signature = (array, id=None, meta=None, normalize=false, timeout=None))]
+    pub fn infer_async<'py>(
+        &self,
+        py: Python<'py>,
+        array: &Bound<'py, PyAny>,
+        id: Option<String>,
+        meta: Option<&Bound<'py, PyAny>>,
+        normalize: bool,
+        timeout: Option<f64>,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        let request = request(array, id, meta, normalize)?;
+        let inner = Arc::clone(&self.inner);
+        let fut = async move { inner.infer(request, seconds(timeout)).await };
+        aio::future_into_py(py, aio::runtime().handle(), fut, |py, result| value_to_py(py, &result))
+    }
+
+    /// Runs all inputs concurrently (up to `max_connections` at a time) and
+    /// returns their results in input order. `timeout` applies to each
+    /// input; the first failure is raised.
+    #[pyo3(signature = (arrays, normalize=false, timeout=None))]
+    pub fn infer_many(&self, py: Python<'_>, arrays: Vec<Bound<'_, PyAny>>, normalize: bool, timeout: Option<f64>) -> PyResult<Vec<PyObject>> {
+        let requests = arrays.iter().map(|a| request(a, None, None, normalize)).collect::<PyResult<Vec<_>>>()?;
+        let inner = &self.inner;
+        let results = py.allow_threads(|| {
+            aio::runtime().block_on(async {
+                let tasks: Vec<_> = requests
+                    .into_iter()
+                    .map(|request| {
+                        let inner = Arc::clone(inner);
+                        tokio::spawn(async move { inner.infer(request, seconds(timeout)).await })
+                    })
+                    .collect();
+                let mut results = Vec::with_capacity(tasks.len());
+                for task in tasks {
+                    results.push(task.await??);
+                }
+                anyhow::Ok(results)
+            })
+        });
+        results.map_err(to_py_err)?.iter().map(|result| value_to_py(py, result)).collect()
+    }
+
+    fn __repr__(&self) -> String {
+        format!("PyClient('{}')", self.url)
+    }
+}
+
+/// Transport for `url`.
+fn transport(url: &str, max_connections: usize, stream: String, out_prefix: String) -> anyhow::Result<Transport> {
+    if url.starts_with("http://") || url.starts_with("https://") {
+        let http = reqwest::Client::builder().pool_max_idle_per_host(max_connections).build()?;
+        return Ok(Transport::Http { http, base: url.trim_end_matches('/').to_string() });
+    }
+    anyhow::ensure!(
+        url.starts_with("redis://") || url.starts_with("rediss://"),
+        "URL '{}' wird nicht unterstützt (erwartet: http://, https://, redis://)",
+        url
+    );
+    let client = redis::Client::open(url)?;
+    let store = Box::new(RedisStorage::new(url, out_prefix)?);
+    Ok(Transport::Redis { client, con: tokio::sync::Mutex::new(None), store, stream })
+}
+
+/// Job in the wire format; the id is left out for the service to assign.
+fn request(array: &Bound<'_, PyAny>, id: Option<String>, meta: Option<&Bound<'_, PyAny>>, normalize: bool) -> PyResult<Value> {
+    let tensor = input_array(array, normalize)?;
+    let mut request = json!({ "shape": tensor.shape(), "data": tensor.iter().collect::<Vec<_>>() });
+    if let Some(id) = id {
+        request["id"] = id.into();
+    }
+    if let Some(meta) = meta {
+        let json = meta.py().import_bound("json")?.call_method1("dumps", (meta,))?;
+        let meta: Value = serde_json::from_str(&json.extract::<String>()?)
+            .map_err(|e| pyo3::exceptions::PyValueError::new_err(e.to_string()))?;
+        request["meta"] = meta;
+    }
+    Ok(request)
+}
+
+fn seconds(timeout: Option<f64>) -> Option<Duration> {
+    timeout.map(|t| Duration::from_secs_f64(t.max(0.0)))
+}
+
+impl Inner {
+    /// Enqueues `request` and returns its job id.
+    async fn submit(&self, mut request: Value) -> anyhow::Result<String> {
+        let _permit = self.permits.acquire().await?;
+        match &self.transport {
+            Transport::Http { http, base } => {
+                let answer = send(http.post(format!("{}/v1/infer", base)).json(&request)).await?;
+                answer["id"].as_str().map(str::to_owned).context("Antwort ohne Job-ID")
+            }
+            Transport::Redis { stream, .. } => {
+                if request.get("id").is_none() {
+                    let n = self.next_id.fetch_add(1, Ordering::Relaxed);
+                    request["id"] = format!("py-{}-{}-{}", std::process::id(), rand::random::<u32>(), n).into();
+                }
+                let id = request["id"].as_str().context("Job-ID muss ein String sein")?.to_string();
+                let fields = [(crate::cluster::JOB_FIELD, request.to_string())];
+                self.redis(|mut con| async move {
+                    redis::AsyncCommands::xadd::<_, _, _, _, ()>(&mut con, stream, "*", &fields).await
+                })
+                .await?;
+                Ok(id)
+            }
+        }
+    }
+
+    /// Waits up to `timeout` (forever if `None`) for the result of `id`.
+    async fn result(&self, id: &str, timeout: Option<Duration>) -> anyhow::Result<Value> {
+        let started = Instant::now();
+        loop {
+            let left = timeout.map(|t| t.saturating_sub(started.elapsed()));
+            let wait = left.unwrap_or(POLL).min(POLL);
+            if let Some(result) = self.poll(id, wait).await? {
+                return Ok(result);
+            }
+            if left.is_some_and(|left| left <= wait) {
+                let secs = timeout.unwrap_or_default().as_secs_f64();
+                return Err(OmniError::Timeout { id: id.to_string(), secs }.into());
+            }
+        }
+    }
+
+    /// One long-poll for the result of `id`.
+    async fn poll(&self, id: &str, wait: Duration) -> anyhow::Result<Option<Value>> {
+        let _permit = self.permits.acquire().await?;
+        match &self.transport {
+            Transport::Http { http, base } => {
+                let url = format!("{}/v1/results/{}?wait_ms={}", base, id, wait.as_millis());
+                match send(http.get(url).timeout(wait + POLL)).await {
+                    Ok(result) => Ok(Some(result)),
+                    Err(ClientError::Server { code, .. }) if code == "RESULT_NOT_FOUND" => Ok(None),
+                    Err(e) => Err(e.into()),
+                }
+            }
+            Transport::Redis { store, .. } => crate::server::http::wait_for_result(store, id, wait)
+                .await
+                .map_err(|e| ClientError::Connection(format!("{:#}", e)).into()),
+        }
+    }
+
+    /// Submits `request` and waits for its result.
+    async fn infer(&self, request: Value, timeout: Option<Duration>) -> anyhow::Result<Value> {
+        let started = Instant::now();
+        if let Transport::Http { http, base } = &self.transport {
+            // Kurze Jobs in einer Anfrage: der Server wartet selbst
+            let wait = timeout.unwrap_or(POLL).min(POLL);
+            let url = format!("{}/v1/infer?wait_ms={}", base, wait.as_millis());
+            let answer = {
+                let _permit = self.permits.acquire().await?;
+                send(http.post(url).json(&request).timeout(wait + POLL)).await?
+            };
+            // 202: noch kein Ergebnis, weiter wie get_result
+            let pending = answer["result"].as_str().is_some_and(|location| location.starts_with("/v1/results/"));
+            let Some(id) = answer["id"].as_str().filter(|_| pending) else { return Ok(answer) };
+            return self.result(id, timeout.map(|t| t.saturating_sub(started.elapsed()))).await;
+        }
+        let id = self.submit(request).await?;
+        self.result(&id, timeout.map(|t| t.saturating_sub(started.elapsed()))).await
+    }
+
+    /// Runs a command on the shared Redis connection; a broken connection
+    /// is replaced by the next call.
+    async fn redis<T, F, Fut>(&self, command: F) -> Result<T, ClientError>
+    where
+        F: FnOnce(redis::aio::MultiplexedConnection) -> Fut,
+        Fut: std::future::Future<Output = redis::RedisResult<T>>,
+    {
+        let Transport::Redis { client, con, .. } = &self.transport else { unreachable!("Redis-Befehl ohne Redis") };
+        let shared = {
+            let mut con = con.lock().await;
+            if con.is_none() {
+                let connected = client.get_multiplexed_async_connection().await;
+                *con = Some(connected.map_err(|e| ClientError::Connection(e.to_string()))?);
+            }
+            con.clone().unwrap()
+        };
+        command(shared).await.map_err(|e| {
+            if e.is_io_error() || e.is_connection_dropped() {
+                if let Ok(mut con) = con.try_lock() {
+                    *con = None;
+                }
+            }
+            ClientError::Connection(e.to_string())
+        })
+    }
+}
+
+/// Sends an HTTP request; error answers become [`ClientError::Server`].
+async fn send(request: reqwest::RequestBuilder) -> Result<Value, ClientError> {
+    let response = request.send().await.map_err(|e| ClientError::Connection(e.to_string()))?;
+    let status = response.status();
+    let body: Value = response.json().await.map_err(|e| ClientError::Connection(e.to_string()))?;
+    if status.is_success() {
+        return Ok(body);
+    }
+    Err(server_error(status.as_u16(), &body))
+}
+
+/// Error from a problem details body.
+fn server_error(status: u16, body: &Value) -> ClientError {
+    let field = |name: &str| body[name].as_str().map(str::to_owned);
+    ClientError::Server {
+        code: field("code").unwrap_or_else(|| "INTERNAL".to_string()),
+        status,
+        message: field("detail").or_else(|| field("error")).unwrap_or_else(|| format!("HTTP {}", status)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_transport_by_url() {
+        let stream = || "omniengine:jobs".to_string();
+        let Transport::Http { base, .. } = transport("http://localhost:8000/", 4, stream(), "results".into()).unwrap() else {
+            panic!("HTTP erwartet")
+        };
+        assert_eq!(base, "http://localhost:8000");
+        assert!(matches!(transport("redis://127.0.0.1/", 4, stream(), "results".into()).unwrap(), Transport::Redis { .. }));
+        assert!(transport("ftp://host", 4, stream(), "results".into()).is_err());
+    }
+
+    #[test]
+    fn test_server_error_from_problem() {
+        let body = json!({"code": "QUEUE_FULL", "status": 503, "detail": "input queue full, retry later"});
+        let ClientError::Server { code, status, message } = server_error(503, &body) else { panic!("Server-Fehler erwartet") };
+        assert_eq!((code.as_str(), status, message.as_str()), ("QUEUE_FULL", 503, "input queue full, retry later"));
+        assert!(matches!(server_error(502, &json!({})), ClientError::Server { ref code, .. } if code == "INTERNAL"));
+    }
+}
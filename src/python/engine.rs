@@ -0,0 +1,211 @@
+//! Bare inference engine for Python, any backend.
+
+use super::result::PyInferResult;
+use super::{aio, input_array, output_array, to_py_err};
+use crate::engine::Engine;
+use crate::types::Config;
+use anyhow::Result;
+use ndarray::{ArrayD, Axis};
+use numpy::IntoPyArray;
+use pyo3::prelude::*;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+/// Python wrapper around an inference engine.
+///
+/// The backend (`onnx`, `tensorrt`, `torch`, `tensorflow`) is chosen by
+/// `[model] backend` of the TOML configuration via
+/// [`EngineFactory`](crate::engine::EngineFactory); it must be compiled in.
+#[pyclass]
+pub struct PyEngine {
+    inner: Arc<Mutex<Box<dyn Engine>>>,
+    backend: &'static str,
+    cfg: Config,
+}
+
+#[pymethods]
+impl PyEngine {
+    /// Creates the engine from a TOML configuration file.
+    ///
+    /// The TOML file must contain a `[model]` section with fields such as
+    /// `backend`, `model_path`, `input_names`, `input_shapes`, `output_names`,
+    /// and `output_shapes`. `device` (`cpu`, `gpu`, `gpu:N`) overrides the
+    /// configured device.
+    #[new]
+    #[pyo3(signature = (path, device=None))]
+    pub fn new(py: Python<'_>, path: String, device: Option<String>) -> PyResult<Self> {
+        let (cfg, engine) = py
+            .allow_threads(|| {
+                let mut cfg = crate::load_config(&path)?;
+                crate::cli::select_device(&mut cfg, device.as_deref())?;
+                let engine = crate::cli::local_engine(&cfg)?;
+                anyhow::Ok((cfg, engine))
+            })
+            .map_err(to_py_err)?;
+        let backend = engine.name();
+        Ok(Self { inner: Arc::new(Mutex::new(engine)), backend, cfg })
+    }
+
+    /// Name of the backend in use.
+    #[getter]
+    pub fn backend(&self) -> &'static str {
+        self.backend
+    }
+
+    /// Runs inference on a NumPy array and returns the output as NumPy array.
+    ///
+    /// The input must match the configured input shape. `float32`, `float64`,
+    /// `uint8` and `float16` inputs are accepted; `normalize=True` scales
+    /// `uint8` to `[0, 1]`. `dtype` converts the output (default `float32`).
+    /// The GIL is released during inference, so other Python threads (e.g.
+    /// preprocessing the next input) keep running.
+    #[pyo3(signature = (input, normalize=false, dtype=None))]
+    pub fn infer<'py>(
+        &self,
+        py: Python<'py>,
+        input: &Bound<'py, PyAny>,
+        normalize: bool,
+        dtype: Option<&Bound<'py, PyAny>>,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        let array = input_array(input, normalize)?;
+        // Ohne GIL rechnen, damit andere Python-Threads weiterlaufen
+        let inner = &self.inner;
+        let output = py.allow_threads(|| inner.lock().unwrap().infer_array(array)).map_err(to_py_err)?;
+        output_array(py, output, dtype)
+    }
+
+    /// Like `infer`, but returns an `InferResult` with the output tensor,
+    /// latency and model name.
+    #[pyo3(signature = (input, normalize=false))]
+    pub fn predict(&self, py: Python<'_>, input: &Bound<'_, PyAny>, normalize: bool) -> PyResult<PyInferResult> {
+        let array = input_array(input, normalize)?;
+        let inner = &self.inner;
+        let (output, elapsed) = py
+            .allow_threads(|| {
+                let start = Instant::now();
+                let output = inner.lock().unwrap().infer_array(array)?;
+                anyhow::Ok((output, start.elapsed()))
+            })
+            .map_err(to_py_err)?;
+        let model = std::path::Path::new(&self.cfg.model.model_path)
+            .file_stem()
+            .map(|s| s.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        Ok(PyInferResult {
+            tensor: output.into_pyarray_bound(py).unbind(),
+            latency_ms: elapsed.as_secs_f64() * 1000.0,
+            model,
+        })
+    }
+
+    /// Runs a list of samples in model-sized batches and returns one output
+    /// per sample.
+    ///
+    /// Like the server-side batcher, samples (with or without a leading
+    /// batch axis of 1) are stacked, the last batch is zero-padded to the
+    /// model batch size and padding rows are dropped from the outputs.
+    /// `normalize` and `dtype` work as in `infer`.
+    #[pyo3(signature = (inputs, normalize=false, dtype=None))]
+    pub fn infer_batch<'py>(
+        &self,
+        py: Python<'py>,
+        inputs: Vec<Bound<'py, PyAny>>,
+        normalize: bool,
+        dtype: Option<&Bound<'py, PyAny>>,
+    ) -> PyResult<Vec<Bound<'py, PyAny>>> {
+        let mut samples = Vec::with_capacity(inputs.len());
+        for input in &inputs {
+            let sample = crate::cli::sample_from_array(&self.cfg, input_array(input, normalize)?);
+            samples.push(sample.map_err(to_py_err)?);
+        }
+        let spec_n = self.cfg.input_spec().batch;
+        let max_batch = self.cfg.queue.max_batch.min(spec_n).max(1);
+
+        let inner = &self.inner;
+        let outputs = py
+            .allow_threads(|| infer_samples(inner.lock().unwrap().as_mut(), spec_n, max_batch, samples))
+            .map_err(to_py_err)?;
+        outputs.into_iter().map(|y| output_array(py, y, dtype)).collect()
+    }
+
+    /// Awaitable variant of `infer`; runs on a worker thread so the event
+    /// loop stays responsive. Calls on the same engine run one at a time.
+    #[pyo3(signature = (input, normalize=false, dtype=None))]
+    pub fn infer_async<'py>(
+        &self,
+        py: Python<'py>,
+        input: &Bound<'py, PyAny>,
+        normalize: bool,
+        dtype: Option<PyObject>,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        let array = input_array(input, normalize)?;
+        let inner = Arc::clone(&self.inner);
+        let fut = async move {
+            tokio::task::spawn_blocking(move || inner.lock().unwrap().infer_array(array)).await?
+        };
+        aio::future_into_py(py, aio::runtime().handle(), fut, move |py, output| {
+            Ok(output_array(py, output, dtype.as_ref().map(|d| d.bind(py)))?.unbind())
+        })
+    }
+}
+
+/// Stacks `samples` into batches of at most `max_batch` (padded to `spec_n`),
+/// runs them and splits the outputs per sample.
+fn infer_samples(
+    engine: &mut dyn Engine,
+    spec_n: usize,
+    max_batch: usize,
+    samples: Vec<ArrayD<f32>>,
+) -> Result<Vec<ArrayD<f32>>> {
+    let mut outputs = Vec::with_capacity(samples.len());
+    let mut samples = samples.into_iter().peekable();
+    while samples.peek().is_some() {
+        let chunk: Vec<_> = samples.by_ref().take(max_batch).collect();
+        let n = chunk.len();
+        let y = engine.infer_array(crate::batcher::stack_items(chunk, spec_n)?)?;
+        anyhow::ensure!(
+            y.ndim() > 0 && y.shape()[0] >= n,
+            "Output hat {:?} Zeilen, erwartet mindestens {}",
+            y.shape().first(),
+            n
+        );
+        outputs.extend(y.axis_iter(Axis(0)).take(n).map(|row| row.to_owned()));
+    }
+    Ok(outputs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::Array;
+
+    /// Returns the sum of each sample and records the batch shapes.
+    struct SumEngine {
+        shapes: Vec<Vec<usize>>,
+    }
+
+    impl Engine for SumEngine {
+        fn name(&self) -> &'static str {
+            "sum"
+        }
+
+        fn infer_array(&mut self, input: ArrayD<f32>) -> Result<ArrayD<f32>> {
+            self.shapes.push(input.shape().to_vec());
+            let sums = input.axis_iter(Axis(0)).map(|s| s.sum()).collect::<Vec<_>>();
+            Ok(Array::from_shape_vec((sums.len(), 1), sums)?.into_dyn())
+        }
+    }
+
+    #[test]
+    fn test_infer_samples_pads_and_splits() {
+        let mut engine = SumEngine { shapes: vec![] };
+        let samples = (1..=5).map(|k| Array::from_elem((2, 3), k as f32).into_dyn()).collect();
+
+        let outputs = infer_samples(&mut engine, 4, 4, samples).unwrap();
+
+        assert_eq!(engine.shapes, vec![vec![4, 2, 3], vec![4, 2, 3]]);
+        assert_eq!(outputs.len(), 5);
+        assert_eq!(outputs[4].shape(), &[1]);
+        assert_eq!(outputs[4][[0]], 30.0);
+    }
+}
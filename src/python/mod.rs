@@ -0,0 +1,138 @@
+//! Python bindings for OmniEngine using PyO3.
+//!
+//! Three entry points are exposed:
+//!
+//! * [`PyEngine`](engine::PyEngine): a single bare engine of the configured
+//!   backend for lightweight integration and quick prototyping.
+//!   `PyOnnxEngine` remains as an alias.
+//! * [`PyRuntime`](runtime::PyRuntime): the full batching multi-GPU runtime
+//!   embedded in the Python process.
+//! * [`PyClient`](client::PyClient): a thread-safe client for a running
+//!   OmniEngine service over HTTP or Redis.
+//!
+//! Example (Python):
+//!
+//! ```python
+//! import omniengine
+//! import numpy as np
+//!
+//! rt = omniengine.PyRuntime.start("runtime.toml")
+//! job_id = rt.submit(np.zeros((3, 224, 224), dtype=np.float32))
+//! print(rt.get_result(job_id, timeout=5.0))
+//! rt.shutdown()
+//! ```
+//!
+//! All offer awaitable variants (`infer_async`, `submit_async`,
+//! `get_result_async`) that do not block the asyncio event loop, e.g. inside
+//! FastAPI handlers.
+
+// Von #[pymethods] erzeugte Fehlerkonvertierungen
+#![allow(clippy::useless_conversion)]
+
+mod aio;
+mod client;
+mod engine;
+mod result;
+mod runtime;
+#[cfg(test)]
+mod stubs;
+
+use ndarray::ArrayD;
+use numpy::{IntoPyArray, PyArrayDyn, PyArrayMethods};
+use pyo3::prelude::*;
+
+// create_exception! von pyo3 0.22 fragt das Feature "gil-refs" ab
+#[allow(unexpected_cfgs)]
+mod exceptions {
+    pyo3::create_exception!(
+        omniengine,
+        OmniError,
+        pyo3::exceptions::PyRuntimeError,
+        "Runtime error with a stable `code` and its HTTP `status`."
+    );
+}
+
+use exceptions::OmniError;
+
+/// Defines the `omniengine` Python module.
+#[pymodule]
+fn omniengine(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add("OmniError", m.py().get_type_bound::<OmniError>())?;
+    m.add_class::<engine::PyEngine>()?;
+    m.add_class::<result::PyInferResult>()?;
+    // Früherer Name, als die Bindings nur ONNX kannten
+    m.add("PyOnnxEngine", m.getattr("PyEngine")?)?;
+    m.add_class::<runtime::PyRuntime>()?;
+    m.add_class::<runtime::PyResultStream>()?;
+    m.add_class::<client::PyClient>()?;
+    Ok(())
+}
+
+/// Converts an internal error into a Python `OmniError` (a `RuntimeError`).
+///
+/// Errors of a [`PyClient`](client::PyClient) keep the service's code;
+/// an unreachable service raises `ConnectionError`.
+fn to_py_err(e: anyhow::Error) -> PyErr {
+    match e.downcast_ref::<client::ClientError>() {
+        Some(client::ClientError::Server { code, status, message }) => raise(code, *status, message),
+        Some(client::ClientError::Connection(_)) => pyo3::exceptions::PyConnectionError::new_err(e.to_string()),
+        None => py_err(crate::error::OmniError::from_anyhow(&e)),
+    }
+}
+
+/// Raises `err` as `OmniError`, timeouts as `TimeoutError`; both carry
+/// `code` and `status`.
+fn py_err(err: crate::error::OmniError) -> PyErr {
+    raise(err.code(), err.status().as_u16(), &err.to_string())
+}
+
+fn raise(code: &str, status: u16, message: &str) -> PyErr {
+    let py_err = match code {
+        "TIMEOUT" => pyo3::exceptions::PyTimeoutError::new_err(message.to_string()),
+        _ => OmniError::new_err(message.to_string()),
+    };
+    Python::with_gil(|py| {
+        let value = py_err.value_bound(py);
+        let _ = value.setattr("code", code);
+        let _ = value.setattr("status", status);
+    });
+    py_err
+}
+
+/// Converts a NumPy array (or array-like) into the `f32` tensor the engines
+/// expect, copying exactly once.
+///
+/// `float32`, `float64` and `uint8` are converted directly, other dtypes (e.g.
+/// `float16`) through NumPy. With `normalize`, `uint8` inputs are scaled to
+/// `[0, 1]`.
+fn input_array(input: &Bound<'_, PyAny>, normalize: bool) -> PyResult<ArrayD<f32>> {
+    if let Ok(a) = input.downcast::<PyArrayDyn<f32>>() {
+        return Ok(a.readonly().as_array().as_standard_layout().into_owned());
+    }
+    if let Ok(a) = input.downcast::<PyArrayDyn<f64>>() {
+        return Ok(a.readonly().as_array().mapv(|v| v as f32));
+    }
+    if let Ok(a) = input.downcast::<PyArrayDyn<u8>>() {
+        let scale = if normalize { 1.0 / 255.0 } else { 1.0 };
+        return Ok(a.readonly().as_array().mapv(|v| v as f32 * scale));
+    }
+    let converted = input.py().import_bound("numpy")?.call_method1("asarray", (input, "float32"))?;
+    let a = converted.downcast::<PyArrayDyn<f32>>()?;
+    Ok(a.readonly().as_array().as_standard_layout().into_owned())
+}
+
+/// Hands an output to NumPy without copying; `dtype` converts it (e.g.
+/// `"float16"`), `None` keeps `float32`.
+fn output_array<'py>(py: Python<'py>, y: ArrayD<f32>, dtype: Option<&Bound<'py, PyAny>>) -> PyResult<Bound<'py, PyAny>> {
+    let out = y.into_pyarray_bound(py).into_any();
+    match dtype {
+        Some(dtype) => out.call_method1("astype", (dtype,)),
+        None => Ok(out),
+    }
+}
+
+/// Converts a result payload into Python dicts/lists.
+fn value_to_py(py: Python<'_>, payload: &serde_json::Value) -> PyResult<PyObject> {
+    let json = py.import_bound("json")?;
+    Ok(json.call_method1("loads", (payload.to_string(),))?.unbind())
+}
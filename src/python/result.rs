@@ -0,0 +1,36 @@
+//! Result objects returned to Python.
+
+use numpy::{PyArrayDyn, PyArrayMethods, PyUntypedArrayMethods};
+use pyo3::prelude::*;
+
+/// Output of one `PyEngine.predict` call.
+#[pyclass(name = "InferResult", get_all)]
+pub struct PyInferResult {
+    /// Model output (`float32`).
+    pub tensor: Py<PyArrayDyn<f32>>,
+    /// Inference time in milliseconds (without input conversion).
+    pub latency_ms: f64,
+    /// Model name (file stem of `model_path`).
+    pub model: String,
+}
+
+#[pymethods]
+impl PyInferResult {
+    /// The `k` highest scores of the flattened output as `(index, score)`,
+    /// descending.
+    #[pyo3(signature = (k=5))]
+    pub fn top_k(&self, py: Python<'_>, k: usize) -> Vec<(usize, f32)> {
+        let tensor = self.tensor.bind(py).readonly();
+        let scores: Vec<f32> = tensor.as_array().iter().copied().collect();
+        crate::classification::top_k(&scores, k)
+    }
+
+    fn __repr__(&self, py: Python<'_>) -> String {
+        format!(
+            "InferResult(model='{}', shape={:?}, latency_ms={:.3})",
+            self.model,
+            self.tensor.bind(py).shape(),
+            self.latency_ms
+        )
+    }
+}
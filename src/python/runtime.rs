@@ -0,0 +1,509 @@
+//! The batching runtime embedded in a Python process.
+//!
+//! `PyRuntime` starts the same dispatcher and per-device workers as the
+//! service (results still go to Redis), but jobs come from `submit()` and
+//! results are handed back in-process via [`crate::results`]: on request
+//! (`get_result`), to registered callbacks (`on_result`) or as a stream
+//! (`results()`). Sources, probes and API servers are left to the host
+//! application.
+
+use super::{aio, input_array, py_err, to_py_err, value_to_py};
+use crate::error::OmniError;
+use crate::pipeline::{Postprocessor, Preprocessor};
+use crate::scripting::plugins::{PythonPostprocessor, PythonPreprocessor};
+use crate::types::{Config, Job};
+use crate::Runtime;
+use ndarray::ArrayD;
+use pyo3::exceptions::{PyKeyError, PyStopAsyncIteration, PyTypeError};
+use pyo3::prelude::*;
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex, RwLock};
+use std::time::{Duration, Instant};
+use tokio::sync::{broadcast, mpsc, watch};
+
+/// Receiving end of a result stream (callback thread or iterator).
+type Consumer = mpsc::UnboundedReceiver<Arc<Value>>;
+
+/// Results of submitted jobs, shared with the collector task.
+#[derive(Default)]
+struct Results {
+    state: Mutex<ResultState>,
+    ready: Condvar,
+    /// Bumped on every stored result, wakes async waiters.
+    version: watch::Sender<u64>,
+}
+
+#[derive(Default)]
+struct ResultState {
+    /// Submitted, result not yet received.
+    pending: HashSet<String>,
+    /// Received, not yet fetched.
+    done: HashMap<String, Value>,
+    /// Callbacks and iterators; while any is attached, results go to them
+    /// instead of `done`.
+    consumers: Vec<mpsc::UnboundedSender<Arc<Value>>>,
+}
+
+impl Results {
+    /// Stores or streams a result if it belongs to a job submitted here.
+    fn complete(&self, payload: &Arc<Value>) {
+        let Some(id) = payload.get("id").and_then(|v| v.as_str()) else { return };
+        let mut state = self.state.lock().unwrap();
+        if !state.pending.remove(id) {
+            return;
+        }
+        // Geschlossene Consumer aussortieren
+        state.consumers.retain(|tx| tx.send(Arc::clone(payload)).is_ok());
+        if state.consumers.is_empty() {
+            state.done.insert(id.to_string(), (**payload).clone());
+        }
+        self.ready.notify_all();
+        self.version.send_modify(|v| *v += 1);
+    }
+
+    /// Attaches a consumer that receives every following result.
+    fn attach(&self) -> Consumer {
+        let (tx, rx) = mpsc::unbounded_channel();
+        self.state.lock().unwrap().consumers.push(tx);
+        rx
+    }
+
+    /// Ends all result streams.
+    fn detach_all(&self) {
+        self.state.lock().unwrap().consumers.clear();
+    }
+
+    /// Removes a finished result; `Err` if `id` is neither pending nor finished.
+    fn take(&self, id: &str) -> anyhow::Result<Option<Value>> {
+        let mut state = self.state.lock().unwrap();
+        if let Some(v) = state.done.remove(id) {
+            return Ok(Some(v));
+        }
+        anyhow::ensure!(state.pending.contains(id), "Unbekannte Job-ID '{}'", id);
+        Ok(None)
+    }
+
+    /// Waits for the result of `id` and removes it.
+    ///
+    /// `Ok(None)` on timeout, `Err` if `id` was never submitted or already fetched.
+    fn wait(&self, id: &str, timeout: Option<Duration>) -> anyhow::Result<Option<Value>> {
+        let deadline = timeout.map(|t| Instant::now() + t);
+        let mut state = self.state.lock().unwrap();
+        loop {
+            if let Some(v) = state.done.remove(id) {
+                return Ok(Some(v));
+            }
+            anyhow::ensure!(state.pending.contains(id), "Unbekannte Job-ID '{}'", id);
+            state = match deadline {
+                None => self.ready.wait(state).unwrap(),
+                Some(d) => {
+                    let left = d.saturating_duration_since(Instant::now());
+                    if left.is_zero() {
+                        return Ok(None);
+                    }
+                    self.ready.wait_timeout(state, left).unwrap().0
+                }
+            };
+        }
+    }
+
+    /// Async variant of [`Results::wait`].
+    async fn wait_async(&self, id: &str, timeout: Option<Duration>) -> anyhow::Result<Option<Value>> {
+        // Vor der Prüfung abonnieren, damit keine Änderung verloren geht
+        let mut changed = self.version.subscribe();
+        let wait = async {
+            loop {
+                if let Some(v) = self.take(id)? {
+                    return Ok(Some(v));
+                }
+                changed.changed().await?;
+            }
+        };
+        match timeout {
+            None => wait.await,
+            Some(t) => tokio::time::timeout(t, wait).await.unwrap_or(Ok(None)),
+        }
+    }
+}
+
+/// Pipeline stage that can be replaced while the workers are running.
+struct Slot<T: ?Sized> {
+    configured: Arc<T>,
+    current: RwLock<Arc<T>>,
+}
+
+impl<T: ?Sized> Slot<T> {
+    fn new(configured: Arc<T>) -> Self {
+        Self { current: RwLock::new(Arc::clone(&configured)), configured }
+    }
+
+    /// Replaces the stage; `None` restores the configured one.
+    fn set(&self, stage: Option<Arc<T>>) {
+        *self.current.write().unwrap() = stage.unwrap_or_else(|| Arc::clone(&self.configured));
+    }
+
+    fn get(&self) -> Arc<T> {
+        Arc::clone(&self.current.read().unwrap())
+    }
+}
+
+impl Preprocessor for Slot<dyn Preprocessor> {
+    fn run(&self, input: ArrayD<f32>) -> anyhow::Result<ArrayD<f32>> {
+        self.get().run(input)
+    }
+}
+
+impl Postprocessor for Slot<dyn Postprocessor> {
+    fn run(&self, input: ArrayD<f32>) -> anyhow::Result<ArrayD<f32>> {
+        self.get().run(input)
+    }
+}
+
+/// Embedded OmniEngine runtime: dynamic batching across all configured devices.
+///
+/// ```python
+/// rt = omniengine.PyRuntime.start("runtime.toml")
+/// job_id = rt.submit(x)            # x: one sample, optionally with batch axis 1
+/// result = rt.get_result(job_id, timeout=5.0)
+/// rt.shutdown()
+/// ```
+#[pyclass]
+pub struct PyRuntime {
+    cfg: Config,
+    rt: Option<tokio::runtime::Runtime>,
+    core: Option<Runtime>,
+    results: Arc<Results>,
+    next_id: AtomicU64,
+    pre: Arc<Slot<dyn Preprocessor>>,
+    post: Arc<Slot<dyn Postprocessor>>,
+}
+
+#[pymethods]
+impl PyRuntime {
+    /// Loads the configuration and starts the dispatcher and one worker per device.
+    #[staticmethod]
+    pub fn start(py: Python<'_>, config_path: String) -> PyResult<Self> {
+        py.allow_threads(|| Self::start_inner(&config_path)).map_err(to_py_err)
+    }
+
+    /// Queues one sample and returns its job ID (generated unless `id` is given).
+    ///
+    /// Blocks while the input queue is full. Input dtypes and `normalize`
+    /// work as in `PyEngine.infer`.
+    #[pyo3(signature = (array, id=None, normalize=false))]
+    pub fn submit(&self, py: Python<'_>, array: &Bound<'_, PyAny>, id: Option<String>, normalize: bool) -> PyResult<String> {
+        let (rt, core) = self.running()?;
+        let job = self.prepare(array, id, normalize)?;
+        let (id, tx) = (job.id.clone(), core.tx.clone());
+        let sent = py.allow_threads(|| rt.block_on(send(&self.results, &tx, job)));
+        sent.map(|_| id).map_err(to_py_err)
+    }
+
+    /// Awaitable variant of `submit`; waits for queue space without blocking
+    /// the event loop.
+    #[pyo3(signature = (array, id=None, normalize=false))]
+    pub fn submit_async<'py>(
+        &self,
+        py: Python<'py>,
+        array: &Bound<'py, PyAny>,
+        id: Option<String>,
+        normalize: bool,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        let (rt, core) = self.running()?;
+        let job = self.prepare(array, id, normalize)?;
+        let (results, tx) = (Arc::clone(&self.results), core.tx.clone());
+        let fut = async move {
+            let id = job.id.clone();
+            send(&results, &tx, job).await.map(|_| id)
+        };
+        aio::future_into_py(py, rt.handle(), fut, |py, id| Ok(id.into_py(py)))
+    }
+
+    /// Waits for the result of a submitted job and returns it as a dict.
+    ///
+    /// Raises `TimeoutError` after `timeout` seconds (waits forever if `None`)
+    /// and `KeyError` for unknown or already fetched IDs.
+    #[pyo3(signature = (id, timeout=None))]
+    pub fn get_result(&self, py: Python<'_>, id: String, timeout: Option<f64>) -> PyResult<PyObject> {
+        let timeout = timeout.map(|t| Duration::from_secs_f64(t.max(0.0)));
+        let results = Arc::clone(&self.results);
+        let payload = py.allow_threads(|| results.wait(&id, timeout));
+        result_to_py(py, &id, timeout, payload)
+    }
+
+    /// Awaitable variant of `get_result`.
+    #[pyo3(signature = (id, timeout=None))]
+    pub fn get_result_async<'py>(&self, py: Python<'py>, id: String, timeout: Option<f64>) -> PyResult<Bound<'py, PyAny>> {
+        let timeout = timeout.map(|t| Duration::from_secs_f64(t.max(0.0)));
+        let results = Arc::clone(&self.results);
+        let handle = match &self.rt {
+            Some(rt) => rt.handle().clone(),
+            // Nach shutdown() bleiben fertige Ergebnisse abrufbar
+            None => aio::runtime().handle().clone(),
+        };
+        let fut = async move {
+            let payload = results.wait_async(&id, timeout).await;
+            Ok((id, payload))
+        };
+        aio::future_into_py(py, &handle, fut, move |py, (id, payload)| result_to_py(py, &id, timeout, payload))
+    }
+
+    /// Stops accepting jobs, finishes all queued jobs and stops the workers.
+    ///
+    /// Results of finished jobs stay available via `get_result`.
+    pub fn shutdown(&mut self, py: Python<'_>) {
+        let (Some(rt), Some(core)) = (self.rt.take(), self.core.take()) else { return };
+        py.allow_threads(|| {
+            rt.block_on(core.join());
+            rt.shutdown_timeout(Duration::from_secs(1));
+        });
+        self.results.detach_all();
+    }
+
+    /// Calls `callback(result)` with each completed result dict, in
+    /// completion order, on a background thread.
+    ///
+    /// While a callback or stream is attached, results are not kept for
+    /// `get_result`. Exceptions raised by the callback are printed and do not
+    /// stop delivery.
+    pub fn on_result(&self, callback: PyObject) -> PyResult<()> {
+        self.running()?;
+        let mut rx = self.results.attach();
+        std::thread::Builder::new()
+            .name("omniengine-callback".to_string())
+            .spawn(move || {
+                while let Some(payload) = rx.blocking_recv() {
+                    Python::with_gil(|py| {
+                        if let Err(e) = value_to_py(py, &payload).and_then(|r| callback.call1(py, (r,))) {
+                            e.print(py);
+                        }
+                    });
+                }
+            })?;
+        Ok(())
+    }
+
+    /// Runs `func(batch)` before inference instead of the configured
+    /// preprocessor; `None` restores it. `func` takes and returns the
+    /// stacked `float32` batch as NumPy array and applies to the next batch.
+    #[pyo3(signature = (func))]
+    pub fn set_preprocessor(&self, py: Python<'_>, func: Option<PyObject>) -> PyResult<()> {
+        let stage = check_callable(py, func)?.map(|f| Arc::new(PythonPreprocessor::from_callable(f)) as Arc<dyn Preprocessor>);
+        self.pre.set(stage);
+        Ok(())
+    }
+
+    /// Runs `func(output)` after inference instead of the configured
+    /// postprocessor; `None` restores it. Works like `set_preprocessor`.
+    #[pyo3(signature = (func))]
+    pub fn set_postprocessor(&self, py: Python<'_>, func: Option<PyObject>) -> PyResult<()> {
+        let stage = check_callable(py, func)?.map(|f| Arc::new(PythonPostprocessor::from_callable(f)) as Arc<dyn Postprocessor>);
+        self.post.set(stage);
+        Ok(())
+    }
+
+    /// Returns a stream of completed result dicts, usable with `for` and
+    /// `async for`. It ends after `shutdown()`.
+    pub fn results(&self) -> PyResult<PyResultStream> {
+        let (rt, _) = self.running()?;
+        Ok(PyResultStream {
+            rx: Arc::new(tokio::sync::Mutex::new(self.results.attach())),
+            handle: rt.handle().clone(),
+        })
+    }
+}
+
+/// Stream of completed results returned by `PyRuntime.results()`.
+#[pyclass]
+pub struct PyResultStream {
+    rx: Arc<tokio::sync::Mutex<Consumer>>,
+    handle: tokio::runtime::Handle,
+}
+
+#[pymethods]
+impl PyResultStream {
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    /// Blocks (without the GIL) until the next result; `None` ends iteration.
+    fn __next__(&self, py: Python<'_>) -> PyResult<Option<PyObject>> {
+        let rx = &self.rx;
+        match py.allow_threads(|| rx.blocking_lock().blocking_recv()) {
+            Some(payload) => value_to_py(py, &payload).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn __aiter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __anext__<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyAny>> {
+        let rx = Arc::clone(&self.rx);
+        let fut = async move { Ok(rx.lock().await.recv().await) };
+        aio::future_into_py(py, &self.handle, fut, |py, payload| match payload {
+            Some(payload) => value_to_py(py, &payload),
+            None => Err(PyStopAsyncIteration::new_err(())),
+        })
+    }
+}
+
+impl PyRuntime {
+    fn start_inner(config_path: &str) -> anyhow::Result<Self> {
+        let cfg = crate::load_config(config_path)?;
+        let (mut pipeline, _) = crate::build_pipeline(&cfg)?;
+        // Austauschbare Stufen für set_preprocessor/set_postprocessor
+        let pre = Arc::new(Slot::new(Arc::clone(&pipeline.pre)));
+        let post = Arc::new(Slot::new(Arc::clone(&pipeline.post)));
+        pipeline.pre = pre.clone();
+        pipeline.post = post.clone();
+        let rt = tokio::runtime::Builder::new_multi_thread().enable_all().build()?;
+
+        // Vor dem Start abonnieren, damit kein Ergebnis verloren geht
+        let results = Arc::new(Results::default());
+        let mut rx = crate::results::subscribe();
+        let collector = Arc::clone(&results);
+        rt.spawn(async move {
+            loop {
+                match rx.recv().await {
+                    Ok(payload) => collector.complete(&payload),
+                    Err(broadcast::error::RecvError::Lagged(n)) => {
+                        tracing::warn!("[python] {} Ergebnisse verpasst", n);
+                    }
+                    Err(broadcast::error::RecvError::Closed) => break,
+                }
+            }
+        });
+
+        let core = rt.block_on(Runtime::start(&cfg, pipeline))?;
+        Ok(Self { cfg, rt: Some(rt), core: Some(core), results, next_id: AtomicU64::new(0), pre, post })
+    }
+
+    /// Converts the array into a job and registers its ID as pending.
+    fn prepare(&self, array: &Bound<'_, PyAny>, id: Option<String>, normalize: bool) -> PyResult<Job> {
+        let tensor = crate::cli::sample_from_array(&self.cfg, input_array(array, normalize)?)
+            .map_err(|e| py_err(OmniError::InvalidInput(format!("{:#}", e))))?;
+        let id = id.unwrap_or_else(|| {
+            format!("py-{}-{}", std::process::id(), self.next_id.fetch_add(1, Ordering::Relaxed))
+        });
+
+        let mut state = self.results.state.lock().unwrap();
+        if state.done.contains_key(&id) || !state.pending.insert(id.clone()) {
+            return Err(PyKeyError::new_err(format!("Job-ID '{}' ist bereits vergeben", id)));
+        }
+        Ok(Job { id, tensor, ..Default::default() })
+    }
+
+    fn running(&self) -> PyResult<(&tokio::runtime::Runtime, &Runtime)> {
+        match (&self.rt, &self.core) {
+            (Some(rt), Some(core)) => Ok((rt, core)),
+            _ => Err(py_err(OmniError::ShuttingDown)),
+        }
+    }
+}
+
+/// Queues a prepared job; unregisters it if the runtime is shut down.
+async fn send(results: &Results, tx: &mpsc::Sender<Job>, job: Job) -> anyhow::Result<()> {
+    let id = job.id.clone();
+    if tx.send(job).await.is_err() {
+        results.state.lock().unwrap().pending.remove(&id);
+        return Err(OmniError::ShuttingDown.into());
+    }
+    Ok(())
+}
+
+/// Maps a waited-for result to a dict, `TimeoutError` or `KeyError`.
+fn result_to_py(
+    py: Python<'_>,
+    id: &str,
+    timeout: Option<Duration>,
+    payload: anyhow::Result<Option<Value>>,
+) -> PyResult<PyObject> {
+    let payload = payload
+        .map_err(|e| PyKeyError::new_err(e.to_string()))?
+        .ok_or_else(|| py_err(OmniError::Timeout { id: id.to_string(), secs: timeout.unwrap_or_default().as_secs_f64() }))?;
+    value_to_py(py, &payload)
+}
+
+/// Rejects non-callable processors up front instead of failing every batch.
+fn check_callable(py: Python<'_>, func: Option<PyObject>) -> PyResult<Option<PyObject>> {
+    match func {
+        Some(f) if !f.bind(py).is_callable() => Err(PyTypeError::new_err("Prozessor muss aufrufbar sein")),
+        other => Ok(other),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_results_wait_and_timeout() {
+        let results = Results::default();
+        results.state.lock().unwrap().pending.insert("a".to_string());
+
+        assert!(results.wait("a", Some(Duration::from_millis(10))).unwrap().is_none());
+        // Fremde Ergebnisse werden ignoriert
+        results.complete(&Arc::new(serde_json::json!({"id": "other"})));
+        results.complete(&Arc::new(serde_json::json!({"id": "a", "class": 3})));
+
+        assert_eq!(results.wait("a", None).unwrap().unwrap()["class"], 3);
+        assert!(results.wait("a", None).is_err());
+        assert!(results.wait("other", None).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_results_wait_async() {
+        let results = Arc::new(Results::default());
+        results.state.lock().unwrap().pending.insert("b".to_string());
+
+        assert!(results.wait_async("b", Some(Duration::from_millis(10))).await.unwrap().is_none());
+        let waiter = tokio::spawn({
+            let results = Arc::clone(&results);
+            async move { results.wait_async("b", None).await }
+        });
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        results.complete(&Arc::new(serde_json::json!({"id": "b"})));
+        assert!(waiter.await.unwrap().unwrap().is_some());
+    }
+
+    #[test]
+    fn test_consumers_take_results() {
+        let results = Results::default();
+        let mut rx = results.attach();
+        for id in ["c", "d"] {
+            results.state.lock().unwrap().pending.insert(id.to_string());
+        }
+
+        results.complete(&Arc::new(serde_json::json!({"id": "c"})));
+        assert_eq!(rx.try_recv().unwrap()["id"], "c");
+        assert!(results.state.lock().unwrap().done.is_empty());
+
+        // Ohne Consumer wieder für get_result aufbewahren
+        drop(rx);
+        results.complete(&Arc::new(serde_json::json!({"id": "d"})));
+        assert!(results.wait("d", None).unwrap().is_some());
+    }
+
+    struct Scale(f32);
+
+    impl Preprocessor for Scale {
+        fn run(&self, input: ArrayD<f32>) -> anyhow::Result<ArrayD<f32>> {
+            Ok(input * self.0)
+        }
+    }
+
+    #[test]
+    fn test_slot_replaces_and_restores_stage() {
+        let slot: Slot<dyn Preprocessor> = Slot::new(Arc::new(Scale(1.0)));
+        let x = ArrayD::<f32>::ones(vec![2]);
+
+        slot.set(Some(Arc::new(Scale(3.0))));
+        assert_eq!(slot.run(x.clone()).unwrap()[[0]], 3.0);
+        slot.set(None);
+        assert_eq!(slot.run(x).unwrap()[[0]], 1.0);
+    }
+}
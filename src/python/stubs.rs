@@ -0,0 +1,318 @@
+//! Generates the type stubs `omniengine.pyi` from the compiled module.
+//!
+//! Classes, members, parameter names, defaults and docstrings are read from
+//! the bindings through Python introspection (`inspect.signature`, the
+//! `__text_signature__` pyo3 derives from `#[pyo3(signature)]`, and the doc
+//! comments). pyo3 exposes no Python types, so annotations come from
+//! [`ANNOTATIONS`]; a member without an entry, or an entry without member,
+//! fails the generation.
+//!
+//! The test compares the output with the committed stub; `make stubs`
+//! (`UPDATE_STUBS=1`) rewrites it.
+
+use std::collections::HashMap;
+
+use pyo3::exceptions::{PyBaseException, PyValueError};
+use pyo3::prelude::*;
+use pyo3::types::PyType;
+
+/// Imports and aliases used by the annotations.
+const HEADER: &str = r#""""Type stubs for the `omniengine` extension module (built with `--features python`).
+
+Generated from the bindings in `src/python/` by `make stubs`; do not edit by hand.
+"""
+
+from typing import Any, AsyncIterator, Awaitable, Callable, Iterator, Optional, Sequence
+
+import numpy as np
+import numpy.typing as npt
+
+ArrayLike = npt.ArrayLike
+DTypeLike = npt.DTypeLike
+Result = dict[str, Any]
+"#;
+
+const PROCESSOR: &str = "Optional[Callable[[npt.NDArray[np.float32]], npt.NDArray[np.float32]]]";
+
+/// Parameter names with their Python types.
+type Params = &'static [(&'static str, &'static str)];
+
+/// Python types of the exported members: `Class.member`, parameter types
+/// and return (or attribute) type.
+const ANNOTATIONS: &[(&str, Params, &str)] = &[
+    ("OmniError.code", &[], "str"),
+    ("OmniError.status", &[], "int"),
+    ("InferResult.top_k", &[("k", "int")], "list[tuple[int, float]]"),
+    ("InferResult.__repr__", &[], "str"),
+    ("InferResult.tensor", &[], "npt.NDArray[np.float32]"),
+    ("InferResult.latency_ms", &[], "float"),
+    ("InferResult.model", &[], "str"),
+    ("PyEngine.__init__", &[("path", "str"), ("device", "Optional[str]")], "None"),
+    ("PyEngine.infer", &[("input", "ArrayLike"), ("normalize", "bool"), ("dtype", "Optional[DTypeLike]")], "npt.NDArray[Any]"),
+    ("PyEngine.predict", &[("input", "ArrayLike"), ("normalize", "bool")], "InferResult"),
+    (
+        "PyEngine.infer_batch",
+        &[("inputs", "Sequence[ArrayLike]"), ("normalize", "bool"), ("dtype", "Optional[DTypeLike]")],
+        "list[npt.NDArray[Any]]",
+    ),
+    (
+        "PyEngine.infer_async",
+        &[("input", "ArrayLike"), ("normalize", "bool"), ("dtype", "Optional[DTypeLike]")],
+        "Awaitable[npt.NDArray[Any]]",
+    ),
+    ("PyEngine.backend", &[], "str"),
+    ("PyResultStream.__iter__", &[], "Iterator[Result]"),
+    ("PyResultStream.__next__", &[], "Result"),
+    ("PyResultStream.__aiter__", &[], "AsyncIterator[Result]"),
+    ("PyResultStream.__anext__", &[], "Awaitable[Result]"),
+    ("PyRuntime.start", &[("config_path", "str")], "PyRuntime"),
+    ("PyRuntime.submit", &[("array", "ArrayLike"), ("id", "Optional[str]"), ("normalize", "bool")], "str"),
+    ("PyRuntime.submit_async", &[("array", "ArrayLike"), ("id", "Optional[str]"), ("normalize", "bool")], "Awaitable[str]"),
+    ("PyRuntime.get_result", &[("id", "str"), ("timeout", "Optional[float]")], "Result"),
+    ("PyRuntime.get_result_async", &[("id", "str"), ("timeout", "Optional[float]")], "Awaitable[Result]"),
+    ("PyRuntime.shutdown", &[], "None"),
+    ("PyRuntime.on_result", &[("callback", "Callable[[Result], Any]")], "None"),
+    ("PyRuntime.set_preprocessor", &[("func", PROCESSOR)], "None"),
+    ("PyRuntime.set_postprocessor", &[("func", PROCESSOR)], "None"),
+    ("PyRuntime.results", &[], "PyResultStream"),
+    ("PyClient.__init__", &[("url", "str"), ("max_connections", "int"), ("stream", "str"), ("out_prefix", "str")], "None"),
+    ("PyClient.__repr__", &[], "str"),
+    (
+        "PyClient.submit",
+        &[("array", "ArrayLike"), ("id", "Optional[str]"), ("meta", "Optional[dict[str, Any]]"), ("normalize", "bool")],
+        "str",
+    ),
+    (
+        "PyClient.submit_async",
+        &[("array", "ArrayLike"), ("id", "Optional[str]"), ("meta", "Optional[dict[str, Any]]"), ("normalize", "bool")],
+        "Awaitable[str]",
+    ),
+    ("PyClient.get_result", &[("id", "str"), ("timeout", "Optional[float]")], "Result"),
+    ("PyClient.get_result_async", &[("id", "str"), ("timeout", "Optional[float]")], "Awaitable[Result]"),
+    (
+        "PyClient.infer",
+        &[
+            ("array", "ArrayLike"),
+            ("id", "Optional[str]"),
+            ("meta", "Optional[dict[str, Any]]"),
+            ("normalize", "bool"),
+            ("timeout", "Optional[float]"),
+        ],
+        "Result",
+    ),
+    (
+        "PyClient.infer_async",
+        &[
+            ("array", "ArrayLike"),
+            ("id", "Optional[str]"),
+            ("meta", "Optional[dict[str, Any]]"),
+            ("normalize", "bool"),
+            ("timeout", "Optional[float]"),
+        ],
+        "Awaitable[Result]",
+    ),
+    (
+        "PyClient.infer_many",
+        &[("arrays", "Sequence[ArrayLike]"), ("normalize", "bool"), ("timeout", "Optional[float]")],
+        "list[Result]",
+    ),
+    ("PyClient.url", &[], "str"),
+];
+
+/// Longest `def` line before the parameters are wrapped.
+const LINE_WIDTH: usize = 100;
+
+type Annotations = HashMap<String, (Params, &'static str)>;
+
+/// Stub text of the `omniengine` module.
+pub fn generate(py: Python<'_>) -> PyResult<String> {
+    let module = pyo3::wrap_pymodule!(super::omniengine)(py).into_bound(py);
+    let mut annotations: Annotations = ANNOTATIONS.iter().map(|(key, params, ret)| (key.to_string(), (*params, *ret))).collect();
+    let mut out = HEADER.to_string();
+    for (name, value) in module.dict() {
+        let name: String = name.extract()?;
+        let Ok(class) = value.downcast::<PyType>() else { continue };
+        let class_name: String = class.getattr("__name__")?.extract()?;
+        if class_name != name {
+            // Alias (PyOnnxEngine)
+            out.push_str(&format!("\n{} = {}\n", name, class_name));
+            continue;
+        }
+        out.push('\n');
+        out.push_str(&class_stub(class, &mut annotations)?);
+    }
+    if let Some(key) = annotations.keys().min() {
+        return Err(PyValueError::new_err(format!("{} steht in ANNOTATIONS, fehlt aber in den Bindings", key)));
+    }
+    Ok(out)
+}
+
+fn class_stub(class: &Bound<'_, PyType>, annotations: &mut Annotations) -> PyResult<String> {
+    let py = class.py();
+    let inspect = py.import_bound("inspect")?;
+    let name: String = class.getattr("__name__")?.extract()?;
+    let mut lines = Vec::new();
+
+    if class.is_subclass_of::<PyBaseException>()? {
+        let base: String = class.getattr("__base__")?.getattr("__name__")?.extract()?;
+        lines.push(format!("class {}({}):", name, base));
+        lines.extend(docstring("    ", class.as_any()));
+        lines.push(String::new());
+        // Attribute, die erst beim Auslösen gesetzt werden
+        for (key, _, ret) in ANNOTATIONS.iter().filter(|(key, _, _)| key.starts_with(&format!("{}.", name))) {
+            annotations.remove(*key);
+            lines.push(format!("    {}: {}", key.split_once('.').unwrap().1, ret));
+        }
+        return Ok(lines.join("\n") + "\n");
+    }
+
+    lines.push(format!("class {}:", name));
+    lines.extend(docstring("    ", class.as_any()));
+    lines.push(String::new());
+    if !class.getattr("__text_signature__")?.is_none() {
+        // Signatur der Klasse ist die von #[new], ohne self
+        let (params, ret) = annotated(&format!("{}.__init__", name), &inspect.call_method1("signature", (class,))?, annotations)?;
+        let params: Vec<String> = std::iter::once("self".to_string()).chain(params).collect();
+        lines.push(def("    ", "__init__", &params, &ret) + " ...");
+    }
+    // pyo3 hält Getter in einer HashMap: Reihenfolge aus ANNOTATIONS statt aus __dict__
+    let mut members = Vec::new();
+    let mut properties = Vec::new();
+    for item in class.getattr("__dict__")?.call_method0("items")?.iter()? {
+        let (member, value): (String, Bound<'_, PyAny>) = item?.extract()?;
+        if ["__new__", "__doc__", "__module__", "__weakref__", "__dict__"].contains(&member.as_str()) {
+            continue;
+        }
+        let kind: String = value.get_type().getattr("__name__")?.extract()?;
+        if kind == "getset_descriptor" {
+            let key = format!("{}.{}", name, member);
+            let position = ANNOTATIONS.iter().position(|(k, _, _)| *k == key).ok_or_else(|| missing(&key))?;
+            properties.push((position, member, value, kind));
+        } else {
+            members.push((member, value, kind));
+        }
+    }
+    properties.sort_by_key(|(position, ..)| *position);
+    for (member, value, kind) in members.into_iter().chain(properties.into_iter().map(|(_, m, v, k)| (m, v, k))) {
+        let key = format!("{}.{}", name, member);
+        let doc = match (member.starts_with("__"), kind.as_str()) {
+            (true, _) => Vec::new(),
+            (false, "staticmethod") => docstring("        ", &value.getattr("__func__")?),
+            (false, _) => docstring("        ", &value),
+        };
+        let (decorator, params, ret) = match kind.as_str() {
+            "getset_descriptor" => {
+                let (_, ret) = annotations.remove(&key).ok_or_else(|| missing(&key))?;
+                (Some("@property"), vec!["self".to_string()], ret.to_string())
+            }
+            "staticmethod" => {
+                let (params, ret) = annotated(&key, &inspect.call_method1("signature", (&value,))?, annotations)?;
+                (Some("@staticmethod"), params, ret)
+            }
+            _ => {
+                let (params, ret) = annotated(&key, &inspect.call_method1("signature", (&value,))?, annotations)?;
+                (None, params, ret)
+            }
+        };
+        if let Some(decorator) = decorator {
+            lines.push(format!("    {}", decorator));
+        }
+        let line = def("    ", &member, &params, &ret);
+        if doc.is_empty() {
+            lines.push(line + " ...");
+        } else {
+            lines.push(line);
+            lines.extend(doc);
+        }
+    }
+    Ok(lines.join("\n") + "\n")
+}
+
+/// Parameters of `signature` with their types from `annotations` (and
+/// `self` if the signature has it), and the return type.
+fn annotated(key: &str, signature: &Bound<'_, PyAny>, annotations: &mut Annotations) -> PyResult<(Vec<String>, String)> {
+    let (types, ret) = annotations.remove(key).ok_or_else(|| missing(key))?;
+    let ellipsis = signature.py().Ellipsis();
+    let empty = signature.py().import_bound("inspect")?.getattr("Parameter")?.getattr("empty")?;
+    let mut params = Vec::new();
+    let mut typed = 0;
+    for param in signature.getattr("parameters")?.call_method0("values")?.iter()? {
+        let param = param?;
+        let name: String = param.getattr("name")?.extract()?;
+        if name == "self" {
+            params.push(name);
+            continue;
+        }
+        let ty = types
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|(_, ty)| *ty)
+            .ok_or_else(|| PyValueError::new_err(format!("Typ von {}({}) fehlt in ANNOTATIONS", key, name)))?;
+        typed += 1;
+        let default = param.getattr("default")?;
+        params.push(if default.is(&empty) {
+            format!("{}: {}", name, ty)
+        } else if default.is(&ellipsis) {
+            // Kein Literal in __text_signature__ (z. B. String-Defaults)
+            format!("{}: {} = ...", name, ty)
+        } else {
+            format!("{}: {} = {}", name, ty, default.repr()?)
+        });
+    }
+    if typed != types.len() {
+        return Err(PyValueError::new_err(format!("ANNOTATIONS nennt Parameter, die {} nicht hat", key)));
+    }
+    Ok((params, ret.to_string()))
+}
+
+fn missing(key: &str) -> PyErr {
+    PyValueError::new_err(format!("{} fehlt in ANNOTATIONS", key))
+}
+
+/// `def` line, wrapped one parameter per line if it is too long.
+fn def(indent: &str, name: &str, params: &[String], ret: &str) -> String {
+    let line = format!("{}def {}({}) -> {}:", indent, name, params.join(", "), ret);
+    if line.len() <= LINE_WIDTH {
+        return line;
+    }
+    let params: String = params.iter().map(|p| format!("{}    {},\n", indent, p)).collect();
+    format!("{}def {}(\n{}{}) -> {}:", indent, name, params, indent, ret)
+}
+
+/// First paragraph of the object's docstring, on one line.
+fn docstring(indent: &str, object: &Bound<'_, PyAny>) -> Vec<String> {
+    let doc: Option<String> = object.getattr("__doc__").ok().and_then(|d| d.extract().ok());
+    let paragraph = doc.as_deref().unwrap_or_default().split("\n\n").next().unwrap_or_default().split_whitespace().collect::<Vec<_>>().join(" ");
+    if paragraph.is_empty() {
+        return Vec::new();
+    }
+    vec![format!("{}\"\"\"{}\"\"\"", indent, paragraph)]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STUB_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/omniengine.pyi");
+
+    #[test]
+    fn test_stub_is_generated_from_bindings() {
+        pyo3::prepare_freethreaded_python();
+        let generated = Python::with_gil(generate).unwrap();
+        if std::env::var_os("UPDATE_STUBS").is_some() {
+            std::fs::write(STUB_PATH, &generated).unwrap();
+        }
+        let committed = std::fs::read_to_string(STUB_PATH).unwrap();
+        assert!(generated == committed, "omniengine.pyi ist veraltet; mit `make stubs` neu erzeugen");
+    }
+
+    #[test]
+    fn test_long_signatures_are_wrapped() {
+        let params = vec!["self".to_string(), "x: int".to_string()];
+        assert_eq!(def("    ", "f", &params, "None"), "    def f(self, x: int) -> None:");
+        let long: Vec<String> = (0..12).map(|i| format!("argument_{}: int", i)).collect();
+        let wrapped = def("    ", "f", &long, "None");
+        assert!(wrapped.starts_with("    def f(\n        argument_0: int,\n"));
+        assert!(wrapped.ends_with("        argument_11: int,\n    ) -> None:"));
+    }
+}
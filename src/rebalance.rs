@@ -0,0 +1,120 @@
+//! Periodic rebalancing of the per-worker job queues.
+//!
+//! The dispatcher places jobs round-robin, so one worker can pile up a
+//! backlog behind a slow batch while another sits idle. Every
+//! `interval_ms` the rebalancer compares the queue depths and moves the
+//! oldest queued jobs of the fullest queue to the emptiest one once they
+//! differ by more than `threshold`.
+//!
+//! A worker holds its queue lock only while collecting a batch, so jobs
+//! are taken from workers that are busy with inference, which is exactly
+//! when their backlog grows. Generation and session workers keep the lock
+//! for good: their jobs depend on the worker's state and are never moved.
+
+use crate::health::health;
+use crate::types::{Job, RebalanceCfg};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{mpsc, Mutex};
+
+/// Receiving end of a worker queue, shared with the rebalancer.
+pub type JobQueue = Arc<Mutex<mpsc::Receiver<Job>>>;
+
+/// One worker queue as seen by the rebalancer. The weak sender does not
+/// keep the queue open when the dispatcher shuts down.
+pub struct QueueHandle {
+    pub tx: mpsc::WeakSender<Job>,
+    pub rx: JobQueue,
+}
+
+/// Runs the rebalancer until the worker queues are closed.
+pub fn spawn(cfg: RebalanceCfg, queues: Vec<QueueHandle>) {
+    tokio::spawn(async move {
+        let mut tick = tokio::time::interval(Duration::from_millis(cfg.interval_ms.max(1)));
+        loop {
+            tick.tick().await;
+            let Some(senders) = queues.iter().map(|q| q.tx.upgrade()).collect::<Option<Vec<_>>>() else {
+                break; // Dispatcher beendet
+            };
+            let receivers: Vec<_> = queues.iter().map(|q| Arc::clone(&q.rx)).collect();
+            let moved = rebalance_once(&senders, &receivers, cfg.threshold);
+            if moved > 0 {
+                tracing::debug!("{} Jobs zwischen Worker-Queues verschoben", moved);
+            }
+        }
+    });
+}
+
+/// Moves jobs from the fullest to the emptiest queue until both hold about
+/// the same number; returns the number of moved jobs.
+pub fn rebalance_once(senders: &[mpsc::Sender<Job>], receivers: &[JobQueue], threshold: usize) -> usize {
+    let depths: Vec<usize> = senders.iter().map(|tx| tx.max_capacity() - tx.capacity()).collect();
+    let (Some(from), Some(to)) = (
+        (0..depths.len()).max_by_key(|&i| depths[i]),
+        (0..depths.len()).min_by_key(|&i| depths[i]),
+    ) else {
+        return 0;
+    };
+    if depths[from] <= depths[to] + threshold {
+        return 0;
+    }
+
+    // Worker sammelt gerade (oder hält die Queue dauerhaft): nichts verschieben
+    let Ok(mut rx) = receivers[from].try_lock() else { return 0 };
+    let mut moved = 0;
+    for _ in 0..(depths[from] - depths[to]) / 2 {
+        let Ok(job) = rx.try_recv() else { break };
+        if let Err(e) = senders[to].try_send(job) {
+            // Ziel voll oder beendet: zurück ans Ende der eigenen Queue
+            let job = match e {
+                mpsc::error::TrySendError::Full(j) | mpsc::error::TrySendError::Closed(j) => j,
+            };
+            if senders[from].try_send(job).is_err() {
+                tracing::warn!("Job beim Umverteilen verloren");
+                health().job_undelivered();
+            }
+            break;
+        }
+        moved += 1;
+    }
+    health().jobs_rebalanced(moved);
+    moved
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn queue(cap: usize) -> (mpsc::Sender<Job>, JobQueue) {
+        let (tx, rx) = mpsc::channel(cap);
+        (tx, Arc::new(Mutex::new(rx)))
+    }
+
+    #[tokio::test]
+    async fn test_moves_oldest_jobs_to_idle_queue() {
+        let (tx0, rx0) = queue(16);
+        let (tx1, rx1) = queue(16);
+        for i in 0..6 {
+            tx0.send(Job { id: format!("j{}", i), ..Default::default() }).await.unwrap();
+        }
+        let senders = [tx0, tx1];
+        let receivers = [rx0, Arc::clone(&rx1)];
+
+        assert_eq!(rebalance_once(&senders, &receivers, 2), 3);
+        assert_eq!(rx1.lock().await.recv().await.unwrap().id, "j0");
+        // Ausgeglichen: keine weitere Verschiebung
+        assert_eq!(rebalance_once(&senders, &receivers, 2), 0);
+    }
+
+    #[tokio::test]
+    async fn test_skips_locked_queue() {
+        let (tx0, rx0) = queue(16);
+        let (tx1, rx1) = queue(16);
+        for i in 0..6 {
+            tx0.send(Job { id: format!("j{}", i), ..Default::default() }).await.unwrap();
+        }
+        let _collecting = rx0.lock().await;
+
+        assert_eq!(rebalance_once(&[tx0, tx1], &[Arc::clone(&rx0), rx1], 2), 0);
+    }
+}
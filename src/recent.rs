@@ -0,0 +1,143 @@
+//! Rolling log of the last jobs for debugging (`[k8s] recent_jobs`).
+//!
+//! Workers record every finished batch here: per job its id, sample shape,
+//! queue wait, the stage timings of its batch and whether it was stored. The
+//! log is a bounded ring buffer in memory, served newest first at
+//! `/admin/recent-jobs` on the probe server, so "what just happened" can be
+//! answered without tracing infrastructure.
+
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use chrono::Utc;
+use serde::Serialize;
+
+/// One finished job, as served by `/admin/recent-jobs`.
+#[derive(Debug, Clone, Serialize, utoipa::ToSchema)]
+pub struct RecentJob {
+    pub id: String,
+    /// Sample shape without the batch axis, as batched (before preprocessing).
+    pub shape: Vec<usize>,
+    /// Worker that ran the batch, e.g. `gpu:0`.
+    pub worker: String,
+    /// Real jobs in the batch.
+    pub batch_jobs: usize,
+    /// Time from dispatch to batch formation.
+    pub queue_ms: f64,
+    /// Stage timings of the batch.
+    pub preprocess_ms: f64,
+    pub inference_ms: f64,
+    pub postprocess_ms: f64,
+    pub store_ms: f64,
+    /// `stored` or `failed`.
+    pub outcome: Outcome,
+    /// Error of a failed batch.
+    pub error: Option<String>,
+    pub finished_at: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, utoipa::ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Outcome {
+    Stored,
+    Failed,
+}
+
+/// Stage timings of one batch; stages not reached stay zero.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Timings {
+    pub preprocess: Duration,
+    pub inference: Duration,
+    pub postprocess: Duration,
+    pub store: Duration,
+}
+
+static CAPACITY: AtomicUsize = AtomicUsize::new(0);
+static RECENT: Mutex<VecDeque<RecentJob>> = Mutex::new(VecDeque::new());
+
+/// Keeps the last `capacity` jobs; 0 disables the log.
+pub fn init(capacity: usize) {
+    CAPACITY.store(capacity, Ordering::Relaxed);
+    let mut recent = RECENT.lock().unwrap();
+    let excess = recent.len().saturating_sub(capacity);
+    recent.drain(..excess);
+}
+
+/// Records the real jobs `ids` of a batch run by `worker`.
+///
+/// `queue_waits` are per job (missing entries count as zero), `outcome` is
+/// the result of the batch.
+pub fn record(
+    worker: &str,
+    ids: &[String],
+    shape: &[usize],
+    queue_waits: &[Duration],
+    timings: &Timings,
+    outcome: Result<(), &anyhow::Error>,
+) {
+    let capacity = CAPACITY.load(Ordering::Relaxed);
+    if capacity == 0 {
+        return;
+    }
+    let ms = |d: Duration| d.as_secs_f64() * 1000.0;
+    let finished_at = Utc::now().to_rfc3339();
+    let (outcome, error) = match outcome {
+        Ok(()) => (Outcome::Stored, None),
+        Err(e) => (Outcome::Failed, Some(format!("{:#}", e))),
+    };
+
+    let mut recent = RECENT.lock().unwrap();
+    for (i, id) in ids.iter().enumerate() {
+        if recent.len() >= capacity {
+            recent.pop_front();
+        }
+        recent.push_back(RecentJob {
+            id: id.clone(),
+            shape: shape.to_vec(),
+            worker: worker.to_string(),
+            batch_jobs: ids.len(),
+            queue_ms: ms(queue_waits.get(i).copied().unwrap_or_default()),
+            preprocess_ms: ms(timings.preprocess),
+            inference_ms: ms(timings.inference),
+            postprocess_ms: ms(timings.postprocess),
+            store_ms: ms(timings.store),
+            outcome,
+            error: error.clone(),
+            finished_at: finished_at.clone(),
+        });
+    }
+}
+
+/// The recorded jobs, newest first.
+pub fn snapshot() -> Vec<RecentJob> {
+    RECENT.lock().unwrap().iter().rev().cloned().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ring_buffer_keeps_newest() {
+        let ids = |range: std::ops::Range<usize>| range.map(|i| format!("recent-{}", i)).collect::<Vec<_>>();
+        let timings = Timings { inference: Duration::from_millis(4), ..Default::default() };
+        init(3);
+        record("gpu:0", &ids(0..2), &[3, 8], &[Duration::from_millis(2)], &timings, Ok(()));
+        record("gpu:1", &ids(2..4), &[3, 8], &[], &timings, Err(&anyhow::anyhow!("Modell fehlgeschlagen")));
+
+        let jobs = snapshot();
+        let order: Vec<&str> = jobs.iter().map(|j| j.id.as_str()).collect();
+        assert_eq!(order, ["recent-3", "recent-2", "recent-1"]);
+        assert_eq!((jobs[0].outcome, jobs[0].error.as_deref()), (Outcome::Failed, Some("Modell fehlgeschlagen")));
+        assert_eq!((jobs[2].outcome, jobs[2].queue_ms, jobs[2].inference_ms), (Outcome::Stored, 0.0, 4.0));
+        assert_eq!(serde_json::to_value(&jobs[2]).unwrap()["outcome"], "stored");
+
+        init(1);
+        assert_eq!(snapshot().len(), 1);
+        init(0);
+        record("gpu:0", &ids(4..5), &[3, 8], &[], &timings, Ok(()));
+        assert!(snapshot().is_empty());
+    }
+}
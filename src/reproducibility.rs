@@ -0,0 +1,109 @@
+//! Reproducibility mode (`[reproducibility]`).
+//!
+//! Backends are created with their determinism switches (ONNX Runtime
+//! deterministic compute, fixed torch seeds without cuDNN autotuning),
+//! generation jobs without an own seed sample with the configured one, and
+//! every result records runtime and backend versions in a `reproducibility`
+//! field, so a stored result can be traced to the software that produced it.
+
+use std::sync::Arc;
+
+use anyhow::Result;
+use ndarray::ArrayViewD;
+use serde_json::{json, Value};
+
+use crate::pipeline::OutputFormatter;
+use crate::types::Config;
+
+/// Seed and provenance record of a reproducible runtime.
+#[derive(Debug, Clone)]
+pub struct Reproducibility {
+    pub seed: u64,
+    pub provenance: Value,
+}
+
+impl Reproducibility {
+    /// Returns the mode configured in `[reproducibility]`, if any.
+    pub fn from_config(cfg: &Config) -> Option<Self> {
+        let repro = cfg.reproducibility.as_ref()?;
+        let mut provenance = json!({
+            "seed": repro.seed,
+            "omniengine": env!("CARGO_PKG_VERSION"),
+            "backend": cfg.model.backend,
+            "model": cfg.model.model_path,
+        });
+        if repro.record_versions {
+            provenance["backend_version"] = crate::engine::backend_version(&cfg.model.backend).into();
+        }
+        Some(Self { seed: repro.seed, provenance })
+    }
+}
+
+/// Formatter adding the provenance record to every result.
+pub struct RecordedOutput {
+    inner: Arc<dyn OutputFormatter>,
+    provenance: Value,
+}
+
+impl RecordedOutput {
+    pub fn new(inner: Arc<dyn OutputFormatter>, provenance: Value) -> Self {
+        Self { inner, provenance }
+    }
+}
+
+impl OutputFormatter for RecordedOutput {
+    fn format(&self, output: ArrayViewD<f32>) -> Result<Value> {
+        let mut result = self.inner.format(output)?;
+        if let Value::Object(fields) = &mut result {
+            fields.insert("reproducibility".to_string(), self.provenance.clone());
+        }
+        Ok(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pipeline::RawOutput;
+    use ndarray::{ArrayD, IxDyn};
+
+    #[test]
+    fn test_results_record_provenance() {
+        let mut cfg: Config = toml::from_str(
+            r#"
+            [model]
+            backend = "onnx"
+            device = "cpu"
+            model_path = "models/mnist.onnx"
+            input_names = ["input"]
+            input_shapes = [[1, 1, 28, 28]]
+            output_names = ["output"]
+            output_shapes = [[1, 10]]
+            [input]
+            batch = 1
+            channels = 1
+            height = 28
+            width = 28
+            dtype = "f32"
+            [queue]
+            max_batch = 1
+            max_wait_ms = 10
+            [redis]
+            url = "redis://127.0.0.1/"
+            out_prefix = "inference:out"
+            "#,
+        )
+        .unwrap();
+        assert!(Reproducibility::from_config(&cfg).is_none());
+
+        cfg.reproducibility = Some(toml::from_str("seed = 42").unwrap());
+        let repro = Reproducibility::from_config(&cfg).unwrap();
+        assert_eq!(repro.seed, 42);
+        assert_eq!(repro.provenance["omniengine"], env!("CARGO_PKG_VERSION"));
+
+        let output = RecordedOutput::new(Arc::new(RawOutput), repro.provenance.clone());
+        let result = output.format(ArrayD::zeros(IxDyn(&[2])).view()).unwrap();
+        assert_eq!(result["reproducibility"]["seed"], 42);
+        assert_eq!(result["shape"], json!([2]));
+    }
+}
@@ -0,0 +1,100 @@
+//! In-process result notifications.
+//!
+//! Workers publish every stored result payload here in addition to Redis,
+//! so embedders (e.g. the Python bindings) and result sinks can wait for
+//! results without polling the store. Publishing is a no-op while nobody is subscribed.
+//!
+//! Jobs with a route (see [`route`]) are delivered straight to their client
+//! connection, including the full output tensor, instead of being stored.
+
+use ndarray::ArrayD;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, OnceLock};
+use tokio::sync::{broadcast, mpsc};
+
+/// Results kept for slow subscribers before they start lagging.
+const CAPACITY: usize = 4096;
+
+static RESULTS: OnceLock<broadcast::Sender<Arc<Value>>> = OnceLock::new();
+
+fn sender() -> &'static broadcast::Sender<Arc<Value>> {
+    RESULTS.get_or_init(|| broadcast::channel(CAPACITY).0)
+}
+
+/// Subscribes to all results published after this call.
+pub fn subscribe() -> broadcast::Receiver<Arc<Value>> {
+    sender().subscribe()
+}
+
+/// Publishes a stored result payload (must contain `id`).
+pub fn publish(payload: &Value) {
+    let tx = sender();
+    if tx.receiver_count() > 0 {
+        let _ = tx.send(Arc::new(payload.clone()));
+    }
+}
+
+/// Result delivered to the connection that submitted the job.
+#[cfg_attr(not(feature = "websocket"), allow(dead_code))]
+#[derive(Debug)]
+pub struct Direct {
+    pub payload: Value,
+    /// Full output tensor of the job; `None` for errors and generated text.
+    pub tensor: Option<ArrayD<f32>>,
+}
+
+static ROUTES: OnceLock<Mutex<HashMap<String, mpsc::UnboundedSender<Direct>>>> = OnceLock::new();
+
+fn routes() -> std::sync::MutexGuard<'static, HashMap<String, mpsc::UnboundedSender<Direct>>> {
+    ROUTES.get_or_init(Default::default).lock().unwrap()
+}
+
+/// Delivers the result of job `id` to `tx` instead of the result store.
+#[cfg_attr(not(feature = "websocket"), allow(dead_code))]
+pub fn route(id: &str, tx: mpsc::UnboundedSender<Direct>) {
+    routes().insert(id.to_string(), tx);
+}
+
+/// Removes the route of job `id`, e.g. when its connection closed first.
+#[cfg_attr(not(feature = "websocket"), allow(dead_code))]
+pub fn unroute(id: &str) {
+    routes().remove(id);
+}
+
+/// Takes the route of job `id`; `None` means the result is stored as usual.
+pub fn take_route(id: &str) -> Option<mpsc::UnboundedSender<Direct>> {
+    routes().remove(id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_subscriber_receives_published_results() {
+        let mut rx = subscribe();
+        publish(&serde_json::json!({"id": "results-test"}));
+        // Andere Tests können parallel publizieren
+        loop {
+            let v = rx.recv().await.unwrap();
+            if v["id"] == "results-test" {
+                break;
+            }
+        }
+    }
+
+    #[test]
+    fn test_routes_are_taken_once() {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        route("route-test", tx.clone());
+        route("route-test-2", tx);
+        unroute("route-test-2");
+        assert!(take_route("route-test-2").is_none());
+
+        let route = take_route("route-test").unwrap();
+        route.send(Direct { payload: serde_json::json!({"id": "route-test"}), tensor: None }).unwrap();
+        assert_eq!(rx.try_recv().unwrap().payload["id"], "route-test");
+        assert!(take_route("route-test").is_none());
+    }
+}
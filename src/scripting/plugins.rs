@@ -3,7 +3,8 @@
 //! This module provides `Preprocessor` and `Postprocessor` implementations
 //! that call into Python functions via PyO3 and NumPy for data exchange.
 //! It is useful for rapid iteration on data transformations without
-//! recompiling Rust code.
+//! recompiling Rust code. Functions are either looked up by module and name
+//! or passed directly as callables (e.g. defined in a notebook).
 
 use anyhow::{Context, Result};
 use ndarray::{ArrayD, IxDyn};
@@ -12,73 +13,70 @@ use pyo3::prelude::*;
 
 use crate::pipeline::{Postprocessor, Preprocessor};
 
+/// Python function called by a plugin.
+enum Target {
+    /// Looked up by name on every call.
+    Module { module: Py<PyModule>, func_name: String },
+    Callable { func: PyObject, name: String },
+}
+
 /// Python-based preprocessor calling a function from a Python module.
 pub struct PythonPreprocessor {
-    module: Py<PyModule>,
-    func_name: String,
+    target: Target,
 }
 
 /// Python-based postprocessor calling a function from a Python module.
 pub struct PythonPostprocessor {
-    module: Py<PyModule>,
-    func_name: String,
+    target: Target,
 }
 
-impl PythonPreprocessor {
-    /// Construct from a Python module and function name (e.g. module="my_plugins", func="normalize").
-    pub fn new(module: &str, func: &str) -> Result<Self> {
+impl Target {
+    fn import(module: &str, func: &str) -> Result<Self> {
         Python::with_gil(|py| {
             let m = PyModule::import_bound(py, module)
                 .with_context(|| format!("Konnte Python-Modul '{}' nicht importieren", module))?;
-            Ok(Self { module: m.into(), func_name: func.to_string() })
+            Ok(Target::Module { module: m.into(), func_name: func.to_string() })
         })
     }
 
-    /// Fallback identity preprocessor that returns the input unchanged.
-    pub fn identity() -> Self {
-        Python::with_gil(|py| {
-            let code = "def identity(x): return x";
-            let m = PyModule::from_code_bound(py, code, "identity.py", "identity")
-                .expect("inline identity module");
-            Self { module: m.into(), func_name: "identity".to_string() }
-        })
-    }
-}
-
-impl PythonPostprocessor {
-    /// Construct from a Python module and function name.
-    pub fn new(module: &str, func: &str) -> Result<Self> {
-        Python::with_gil(|py| {
-            let m = PyModule::import_bound(py, module)
-                .with_context(|| format!("Konnte Python-Modul '{}' nicht importieren", module))?;
-            Ok(Self { module: m.into(), func_name: func.to_string() })
-        })
+    fn callable(func: PyObject) -> Self {
+        let name = Python::with_gil(|py| {
+            func.bind(py)
+                .getattr("__name__")
+                .and_then(|n| n.extract::<String>())
+                .unwrap_or_else(|_| "<callable>".to_string())
+        });
+        Target::Callable { func, name }
     }
 
-    /// Fallback identity postprocessor that returns the input unchanged.
-    pub fn identity() -> Self {
+    fn identity() -> Self {
         Python::with_gil(|py| {
             let code = "def identity(x): return x";
             let m = PyModule::from_code_bound(py, code, "identity.py", "identity")
                 .expect("inline identity module");
-            Self { module: m.into(), func_name: "identity".to_string() }
+            Target::Module { module: m.into(), func_name: "identity".to_string() }
         })
     }
-}
 
-impl Preprocessor for PythonPreprocessor {
-    fn run(&self, input: ArrayD<f32>) -> Result<ArrayD<f32>> {
+    /// Calls the function with a NumPy array and converts the returned array back.
+    fn call(&self, input: ArrayD<f32>) -> Result<ArrayD<f32>> {
         Python::with_gil(|py| {
-            let m = self.module.bind(py);
-            let func = m
-                .getattr(self.func_name.as_str())
-                .with_context(|| format!("Funktion '{}' nicht gefunden", self.func_name))?;
+            let (func, name) = match self {
+                Target::Module { module, func_name } => {
+                    let func = module
+                        .bind(py)
+                        .getattr(func_name.as_str())
+                        .with_context(|| format!("Funktion '{}' nicht gefunden", func_name))?;
+                    (func, func_name)
+                }
+                Target::Callable { func, name } => (func.bind(py).clone(), name),
+            };
 
             // Robust gegen ndarray-Versionen: über NumPy konvertieren (macht ggf. Kopien)
             let np_in = PyArrayDyn::<f32>::from_owned_array_bound(py, input);
             let any = func
                 .call1((np_in,))
-                .with_context(|| format!("Fehler beim Aufruf '{}(...)'", self.func_name))?;
+                .with_context(|| format!("Fehler beim Aufruf '{}(...)'", name))?;
 
             let np_out: PyReadonlyArrayDyn<f32> = any.extract().context("Python-Rückgabe ist kein NumPy-Array")?;
             let view = np_out.as_array();
@@ -89,24 +87,48 @@ impl Preprocessor for PythonPreprocessor {
     }
 }
 
-impl Postprocessor for PythonPostprocessor {
-    fn run(&self, input: ArrayD<f32>) -> Result<ArrayD<f32>> {
-        Python::with_gil(|py| {
-            let m = self.module.bind(py);
-            let func = m
-                .getattr(self.func_name.as_str())
-                .with_context(|| format!("Funktion '{}' nicht gefunden", self.func_name))?;
+impl PythonPreprocessor {
+    /// Construct from a Python module and function name (e.g. module="my_plugins", func="normalize").
+    pub fn new(module: &str, func: &str) -> Result<Self> {
+        Ok(Self { target: Target::import(module, func)? })
+    }
 
-            let np_in = PyArrayDyn::<f32>::from_owned_array_bound(py, input);
-            let any = func
-                .call1((np_in,))
-                .with_context(|| format!("Fehler beim Aufruf '{}(...)'", self.func_name))?;
+    /// Construct from a Python callable taking and returning a NumPy array.
+    pub fn from_callable(func: PyObject) -> Self {
+        Self { target: Target::callable(func) }
+    }
 
-            let np_out: PyReadonlyArrayDyn<f32> = any.extract().context("Python-Rückgabe ist kein NumPy-Array")?;
-            let view = np_out.as_array();
-            let shape = view.shape().to_vec();
-            let data: Vec<f32> = view.iter().copied().collect();
-            ArrayD::from_shape_vec(IxDyn(&shape), data).context("Shape/Data konnten nicht in ArrayD gebaut werden")
-        })
+    /// Fallback identity preprocessor that returns the input unchanged.
+    pub fn identity() -> Self {
+        Self { target: Target::identity() }
+    }
+}
+
+impl PythonPostprocessor {
+    /// Construct from a Python module and function name.
+    pub fn new(module: &str, func: &str) -> Result<Self> {
+        Ok(Self { target: Target::import(module, func)? })
+    }
+
+    /// Construct from a Python callable taking and returning a NumPy array.
+    pub fn from_callable(func: PyObject) -> Self {
+        Self { target: Target::callable(func) }
+    }
+
+    /// Fallback identity postprocessor that returns the input unchanged.
+    pub fn identity() -> Self {
+        Self { target: Target::identity() }
+    }
+}
+
+impl Preprocessor for PythonPreprocessor {
+    fn run(&self, input: ArrayD<f32>) -> Result<ArrayD<f32>> {
+        self.target.call(input)
+    }
+}
+
+impl Postprocessor for PythonPostprocessor {
+    fn run(&self, input: ArrayD<f32>) -> Result<ArrayD<f32>> {
+        self.target.call(input)
     }
 }
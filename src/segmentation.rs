@@ -0,0 +1,177 @@
+//! Segmentation mask encoding.
+//!
+//! Turns per-pixel class logits (`[C, H, W]` per sample) into a class mask and
+//! stores it compactly as run-length encoding or a base64 PNG, together with
+//! per-class pixel counts.
+
+use anyhow::{Context, Result};
+use base64::Engine as _;
+use ndarray::{Array2, ArrayViewD, Axis};
+use serde_json::{json, Value};
+
+use crate::pipeline::OutputFormatter;
+use crate::types::SegmentationCfg;
+
+/// Computes the class mask `[H, W]` from logits `[C, H, W]` (or `[1, C, H, W]`).
+///
+/// A single channel is treated as binary logits (class 1 where `> 0`).
+pub fn class_mask(output: ArrayViewD<f32>) -> Result<Array2<u16>> {
+    let logits = match output.ndim() {
+        3 => output,
+        4 if output.shape()[0] == 1 => output.index_axis_move(Axis(0), 0),
+        _ => anyhow::bail!("Segmentierungs-Output muss [C, H, W] sein, bekommen {:?}", output.shape()),
+    };
+    let (c, h, w) = (logits.shape()[0], logits.shape()[1], logits.shape()[2]);
+    anyhow::ensure!(c <= u16::MAX as usize, "Zu viele Klassen: {}", c);
+
+    let mut mask = Array2::<u16>::zeros((h, w));
+    for y in 0..h {
+        for x in 0..w {
+            mask[[y, x]] = if c == 1 {
+                (logits[[0, y, x]] > 0.0) as u16
+            } else {
+                (0..c)
+                    .max_by(|&a, &b| logits[[a, y, x]].total_cmp(&logits[[b, y, x]]))
+                    .unwrap_or(0) as u16
+            };
+        }
+    }
+    Ok(mask)
+}
+
+/// Run-length encodes a mask in row-major order as parallel `values`/`counts`.
+pub fn encode_rle(mask: &Array2<u16>) -> Value {
+    let mut values: Vec<u16> = Vec::new();
+    let mut counts: Vec<usize> = Vec::new();
+    for &v in mask.iter() {
+        match values.last() {
+            Some(&last) if last == v => *counts.last_mut().unwrap() += 1,
+            _ => {
+                values.push(v);
+                counts.push(1);
+            }
+        }
+    }
+    json!({ "size": mask.shape(), "values": values, "counts": counts })
+}
+
+/// Encodes a mask as grayscale PNG (8 bit, or 16 bit for more than 256 classes).
+pub fn encode_png(mask: &Array2<u16>) -> Result<Vec<u8>> {
+    let (h, w) = mask.dim();
+    let wide = mask.iter().any(|&v| v > u8::MAX as u16);
+    let mut buf = Vec::new();
+    {
+        let mut encoder = png::Encoder::new(&mut buf, w as u32, h as u32);
+        encoder.set_color(png::ColorType::Grayscale);
+        encoder.set_depth(if wide { png::BitDepth::Sixteen } else { png::BitDepth::Eight });
+        let mut writer = encoder.write_header().context("PNG-Header konnte nicht geschrieben werden")?;
+        let data: Vec<u8> = if wide {
+            mask.iter().flat_map(|v| v.to_be_bytes()).collect()
+        } else {
+            mask.iter().map(|&v| v as u8).collect()
+        };
+        writer.write_image_data(&data).context("PNG-Daten konnten nicht geschrieben werden")?;
+    }
+    Ok(buf)
+}
+
+/// Output formatter producing `{"mask": ..., "classes": [...]}` results.
+pub struct SegmentationOutput {
+    cfg: SegmentationCfg,
+}
+
+impl SegmentationOutput {
+    /// Creates the formatter from the `[segmentation]` configuration.
+    pub fn new(cfg: &SegmentationCfg) -> Result<Self> {
+        anyhow::ensure!(
+            matches!(cfg.encoding.as_str(), "rle" | "png"),
+            "Unbekannte Masken-Kodierung '{}'",
+            cfg.encoding
+        );
+        Ok(Self { cfg: cfg.clone() })
+    }
+}
+
+impl OutputFormatter for SegmentationOutput {
+    fn format(&self, output: ArrayViewD<f32>) -> Result<Value> {
+        let mask = class_mask(output)?;
+
+        let mut pixels = std::collections::BTreeMap::<u16, usize>::new();
+        for &v in mask.iter() {
+            *pixels.entry(v).or_default() += 1;
+        }
+        let classes: Vec<Value> = pixels
+            .into_iter()
+            .map(|(id, count)| {
+                json!({ "class_id": id, "label": self.cfg.labels.get(id as usize), "pixels": count })
+            })
+            .collect();
+
+        let encoded = match self.cfg.encoding.as_str() {
+            "png" => json!({
+                "encoding": "png",
+                "size": mask.shape(),
+                "data": base64::engine::general_purpose::STANDARD.encode(encode_png(&mask)?),
+            }),
+            _ => {
+                let mut rle = encode_rle(&mask);
+                rle["encoding"] = json!("rle");
+                rle
+            }
+        };
+
+        Ok(json!({ "mask": encoded, "classes": classes }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::Array;
+
+    fn logits() -> ndarray::ArrayD<f32> {
+        // 2 Klassen, 2x3: linke Spalte Klasse 1
+        Array::from_shape_vec(
+            (2, 2, 3),
+            vec![
+                0.0, 1.0, 1.0, 0.0, 1.0, 1.0, // Klasse 0
+                1.0, 0.0, 0.0, 1.0, 0.0, 0.0, // Klasse 1
+            ],
+        )
+        .unwrap()
+        .into_dyn()
+    }
+
+    #[test]
+    fn test_class_mask_and_rle() {
+        let mask = class_mask(logits().view()).unwrap();
+        assert_eq!(mask.iter().cloned().collect::<Vec<_>>(), vec![1, 0, 0, 1, 0, 0]);
+
+        let rle = encode_rle(&mask);
+        assert_eq!(rle["values"], json!([1, 0, 1, 0]));
+        assert_eq!(rle["counts"], json!([1, 2, 1, 2]));
+        assert_eq!(rle["size"], json!([2, 3]));
+    }
+
+    #[test]
+    fn test_png_roundtrip() {
+        let mask = class_mask(logits().view()).unwrap();
+        let bytes = encode_png(&mask).unwrap();
+
+        let decoder = png::Decoder::new(bytes.as_slice());
+        let mut reader = decoder.read_info().unwrap();
+        let mut buf = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut buf).unwrap();
+        assert_eq!((info.width, info.height), (3, 2));
+        assert_eq!(&buf[..6], &[1, 0, 0, 1, 0, 0]);
+    }
+
+    #[test]
+    fn test_format_reports_class_pixels() {
+        let cfg = SegmentationCfg { encoding: "rle".to_string(), labels: vec!["bg".into(), "fg".into()] };
+        let v = SegmentationOutput::new(&cfg).unwrap().format(logits().view()).unwrap();
+        assert_eq!(v["mask"]["encoding"], "rle");
+        assert_eq!(v["classes"][1]["label"], "fg");
+        assert_eq!(v["classes"][1]["pixels"], 2);
+    }
+}
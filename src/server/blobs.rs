@@ -0,0 +1,203 @@
+//! Content-addressed input cache (`[server.http.infer.blobs]`).
+//!
+//! Repeated inference over the same large input (other models, other
+//! parameters) should not transfer it again. A client uploads the tensor
+//! once with `PUT /v1/blobs/{sha256}`, the SHA-256 of the request body (an
+//! `.npy` file or `{"shape": ..., "data": ...}`), and then sends
+//! `"blob": "<sha256>"` instead of `shape`/`data` in `POST /v1/infer`, also
+//! per named input. `GET /v1/blobs/{sha256}` (or `HEAD`) tells whether a
+//! blob is still cached; a reference to an expired blob fails with
+//! `BLOB_NOT_FOUND` and the client uploads it again.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use ndarray::ArrayD;
+use serde::Serialize;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+
+use crate::error::OmniError;
+use crate::types::{BlobCfg, TensorData};
+
+/// A cached blob (`PUT`/`GET /v1/blobs/{sha256}`).
+#[derive(Debug, Clone, Serialize, utoipa::ToSchema)]
+pub struct BlobStatus {
+    pub sha256: String,
+    pub shape: Vec<usize>,
+    /// Size of the cached f32 tensor.
+    pub bytes: usize,
+    /// Seconds until the blob expires unless it is used again.
+    pub expires_in_s: u64,
+}
+
+/// Tensors referenced by a job message; the main input has no name.
+pub type References = Vec<(Option<String>, Arc<ArrayD<f32>>)>;
+
+struct Blob {
+    tensor: Arc<ArrayD<f32>>,
+    touched: Instant,
+}
+
+/// Cached input tensors of one HTTP server, keyed by SHA-256.
+pub struct Blobs {
+    cfg: BlobCfg,
+    entries: Mutex<HashMap<String, Blob>>,
+}
+
+impl Blobs {
+    pub fn new(cfg: BlobCfg) -> Self {
+        Self { cfg, entries: Mutex::new(HashMap::new()) }
+    }
+
+    /// Largest accepted upload body.
+    pub fn max_blob_bytes(&self) -> usize {
+        self.cfg.max_blob_bytes
+    }
+
+    /// Locks the cache after dropping expired blobs.
+    fn entries(&self) -> std::sync::MutexGuard<'_, HashMap<String, Blob>> {
+        let ttl = Duration::from_secs(self.cfg.ttl_secs);
+        let mut entries = self.entries.lock().unwrap();
+        entries.retain(|_, blob| blob.touched.elapsed() < ttl);
+        entries
+    }
+
+    /// Caches the tensor in `body` after checking its SHA-256.
+    pub fn put(&self, sha256: &str, body: &[u8]) -> Result<BlobStatus, OmniError> {
+        let sha256 = sha256.to_ascii_lowercase();
+        let actual = format!("{:x}", Sha256::digest(body));
+        if actual != sha256 {
+            return Err(OmniError::BlobHashMismatch { expected: sha256, actual });
+        }
+        let tensor = parse_tensor(body)?;
+        let bytes = tensor.len() * 4;
+        if bytes > self.cfg.max_bytes {
+            return Err(OmniError::UploadTooLarge { shape: tensor.shape().to_vec(), max_bytes: self.cfg.max_bytes });
+        }
+
+        let mut entries = self.entries();
+        entries.remove(&sha256);
+        // Am längsten unbenutzte Blobs verdrängen, bis der neue passt
+        let mut used: usize = entries.values().map(|b| b.tensor.len() * 4).sum();
+        while used + bytes > self.cfg.max_bytes {
+            let Some(oldest) = entries.iter().min_by_key(|(_, b)| b.touched).map(|(k, _)| k.clone()) else { break };
+            used -= entries.remove(&oldest).map_or(0, |b| b.tensor.len() * 4);
+            tracing::debug!("Blob {} verdrängt", oldest);
+        }
+        entries.insert(sha256.clone(), Blob { tensor: Arc::new(tensor), touched: Instant::now() });
+        Ok(self.status_of(&sha256, &entries[&sha256]))
+    }
+
+    /// Status of a cached blob; counts as a use.
+    pub fn status(&self, sha256: &str) -> Result<BlobStatus, OmniError> {
+        let sha256 = sha256.to_ascii_lowercase();
+        let mut entries = self.entries();
+        let blob = entries.get_mut(&sha256).ok_or_else(|| OmniError::BlobNotFound(sha256.clone()))?;
+        blob.touched = Instant::now();
+        Ok(self.status_of(&sha256, blob))
+    }
+
+    /// The cached tensor; counts as a use.
+    pub fn get(&self, sha256: &str) -> Result<Arc<ArrayD<f32>>, OmniError> {
+        let sha256 = sha256.to_ascii_lowercase();
+        let mut entries = self.entries();
+        let blob = entries.get_mut(&sha256).ok_or(OmniError::BlobNotFound(sha256))?;
+        blob.touched = Instant::now();
+        Ok(Arc::clone(&blob.tensor))
+    }
+
+    /// Replaces the `blob` references of a job message (main input and
+    /// `inputs`) by empty tensors and returns the referenced tensors.
+    pub fn resolve(&self, message: &mut Value) -> Result<References, OmniError> {
+        let mut tensors = Vec::new();
+        if let Some(tensor) = self.take_ref(message)? {
+            tensors.push((None, tensor));
+        }
+        if let Some(inputs) = message.get_mut("inputs").and_then(Value::as_object_mut) {
+            for (name, input) in inputs.iter_mut() {
+                if let Some(tensor) = self.take_ref(input)? {
+                    tensors.push((Some(name.clone()), tensor));
+                }
+            }
+        }
+        Ok(tensors)
+    }
+
+    fn take_ref(&self, tensor: &mut Value) -> Result<Option<Arc<ArrayD<f32>>>, OmniError> {
+        let Some(fields) = tensor.as_object_mut() else { return Ok(None) };
+        let Some(reference) = fields.remove("blob") else { return Ok(None) };
+        let Some(sha256) = reference.as_str() else {
+            return Err(OmniError::InvalidInput("blob muss ein SHA-256 (hex) sein".to_string()));
+        };
+        let blob = self.get(sha256)?;
+        fields.insert("shape".to_string(), json!([0]));
+        fields.insert("data".to_string(), json!([]));
+        Ok(Some(blob))
+    }
+
+    fn status_of(&self, sha256: &str, blob: &Blob) -> BlobStatus {
+        let left = Duration::from_secs(self.cfg.ttl_secs).saturating_sub(blob.touched.elapsed());
+        BlobStatus {
+            sha256: sha256.to_string(),
+            shape: blob.tensor.shape().to_vec(),
+            bytes: blob.tensor.len() * 4,
+            expires_in_s: left.as_secs(),
+        }
+    }
+}
+
+/// Tensor of an upload body: `.npy` or the JSON tensor format.
+fn parse_tensor(body: &[u8]) -> Result<ArrayD<f32>, OmniError> {
+    let invalid = |e: &dyn std::fmt::Display| OmniError::InvalidInput(e.to_string());
+    if body.starts_with(b"\x93NUMPY") {
+        return crate::npy::parse(body).map_err(|e| invalid(&format!("{:#}", e)));
+    }
+    serde_json::from_slice::<TensorData>(body).map_err(|e| invalid(&e))?.into_array().map_err(|e| invalid(&e))
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    pub(crate) fn sha256(body: &[u8]) -> String {
+        format!("{:x}", Sha256::digest(body))
+    }
+
+    #[test]
+    fn test_put_resolve_and_evict() {
+        let blobs = Blobs::new(toml::from_str("max_bytes = 32").unwrap());
+        let body = br#"{"shape": [2, 2], "data": [1, 2, 3, 4]}"#;
+        let hash = sha256(body);
+        let status = blobs.put(&hash.to_uppercase(), body).unwrap();
+        assert_eq!((status.shape, status.bytes), (vec![2, 2], 16));
+
+        let err = blobs.put(&sha256(b"other"), body).unwrap_err();
+        assert_eq!(err.code(), "BLOB_HASH_MISMATCH");
+
+        let mut message = json!({"id": "j1", "blob": hash, "inputs": {"mask": {"blob": hash}}});
+        let tensors = blobs.resolve(&mut message).unwrap();
+        assert_eq!(tensors.len(), 2);
+        assert_eq!(tensors[1].0.as_deref(), Some("mask"));
+        assert_eq!(tensors[0].1[[1, 1]], 4.0);
+        assert_eq!(message["shape"], json!([0]));
+
+        // Ein zweiter Blob verdrängt den ersten, der dritte passt gar nicht
+        let second = br#"{"shape": [5], "data": [0, 0, 0, 0, 0]}"#;
+        blobs.put(&sha256(second), second).unwrap();
+        assert_eq!(blobs.status(&hash).unwrap_err(), OmniError::BlobNotFound(hash.clone()));
+        let large = br#"{"shape": [9], "data": [0, 0, 0, 0, 0, 0, 0, 0, 0]}"#;
+        assert_eq!(blobs.put(&sha256(large), large).unwrap_err().code(), "UPLOAD_TOO_LARGE");
+        let mut message = json!({"blob": hash});
+        assert_eq!(blobs.resolve(&mut message).unwrap_err().code(), "BLOB_NOT_FOUND");
+    }
+
+    #[test]
+    fn test_put_npy() {
+        let tensor = ArrayD::from_elem(ndarray::IxDyn(&[3]), 0.5f32);
+        let body = crate::npy::to_bytes(&tensor);
+        let blobs = Blobs::new(toml::from_str("").unwrap());
+        assert_eq!(blobs.put(&sha256(&body), &body).unwrap().shape, vec![3]);
+    }
+}
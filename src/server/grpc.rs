@@ -0,0 +1,312 @@
+//! KServe v2 gRPC API (`[server.grpc]`, feature `grpc`).
+//!
+//! Serves `inference.GRPCInferenceService` as Triton and KServe clients
+//! expect it: `ServerLive`, `ServerReady`, `ModelReady`, `ServerMetadata`,
+//! `ModelMetadata` and `ModelInfer`; other methods answer `UNIMPLEMENTED`.
+//! The messages and their mapping onto jobs live in [`super::kserve`].
+//!
+//! `ModelInfer` enqueues one job per sample and waits up to `max_wait_ms`
+//! for all results. Errors carry the gRPC code of their [`OmniError`], the
+//! message language follows the `accept-language` metadata. `traceparent`
+//! and `tracestate` metadata become the jobs' trace context.
+
+use std::convert::Infallible;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{Context as _, Result};
+use serde_json::Value;
+use tokio::sync::mpsc;
+use tonic::codec::ProstCodec;
+use tonic::codegen::{http, BoxFuture, Context, Poll, Service, StdError};
+use tonic::server::{Grpc, NamedService, UnaryService};
+use tonic::{Code, Request, Response, Status};
+use tracing::info;
+
+use super::kserve::{
+    JobError, KServeModel, ModelInferRequest, ModelInferResponse, ModelMetadataRequest, ModelMetadataResponse,
+    ModelReadyRequest, ModelReadyResponse, Sample, ServerLiveRequest, ServerLiveResponse, ServerMetadataRequest,
+    ServerMetadataResponse, ServerReadyRequest, ServerReadyResponse,
+};
+use crate::error::{Language, OmniError};
+use crate::storage::redis_store::{decode_values, RedisStorage, TensorLayout};
+use crate::trace_context::{TRACEPARENT, TRACESTATE};
+use crate::types::{Config, GrpcCfg, Job};
+
+struct State {
+    model: KServeModel,
+    tx: mpsc::Sender<Job>,
+    store: RedisStorage,
+    max_wait: Duration,
+    max_message_bytes: usize,
+}
+
+/// `inference.GRPCInferenceService` on top of the job queue.
+#[derive(Clone)]
+pub struct KServeService {
+    state: Arc<State>,
+}
+
+impl NamedService for KServeService {
+    const NAME: &'static str = "inference.GRPCInferenceService";
+}
+
+/// Serves the API until the process exits. Jobs are enqueued into `tx`.
+pub async fn serve(cfg: GrpcCfg, runtime: &Config, store: RedisStorage, tx: mpsc::Sender<Job>) -> Result<()> {
+    let addr: SocketAddr = cfg.bind.parse().with_context(|| format!("Ungültige gRPC-Adresse: {}", cfg.bind))?;
+    let model = KServeModel::new(runtime, &cfg);
+    info!("gRPC-API (KServe v2) auf {}, Modell '{}'", addr, model.name());
+    let state = State {
+        model,
+        tx,
+        store,
+        max_wait: Duration::from_millis(cfg.max_wait_ms),
+        max_message_bytes: cfg.max_message_bytes,
+    };
+    tonic::transport::Server::builder()
+        .add_service(KServeService { state: Arc::new(state) })
+        .serve(addr)
+        .await?;
+    Ok(())
+}
+
+impl<B> Service<http::Request<B>> for KServeService
+where
+    B: tonic::codegen::Body + Send + 'static,
+    B::Error: Into<StdError> + Send + 'static,
+{
+    type Response = http::Response<tonic::body::BoxBody>;
+    type Error = Infallible;
+    type Future = BoxFuture<Self::Response, Self::Error>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, req: http::Request<B>) -> Self::Future {
+        let method = req.uri().path().strip_prefix("/inference.GRPCInferenceService/").unwrap_or_default();
+        match method {
+            "ServerLive" => self.unary(req, server_live),
+            "ServerReady" => self.unary(req, server_ready),
+            "ModelReady" => self.unary(req, model_ready),
+            "ServerMetadata" => self.unary(req, server_metadata),
+            "ModelMetadata" => self.unary(req, model_metadata),
+            "ModelInfer" => self.unary(req, model_infer),
+            _ => {
+                let method = req.uri().path().to_string();
+                Box::pin(async move { Ok(Status::unimplemented(method).into_http()) })
+            }
+        }
+    }
+}
+
+impl KServeService {
+    /// Decodes the request message, runs `handler` and encodes its answer.
+    fn unary<B, Req, Resp, F, Fut>(&self, req: http::Request<B>, handler: F) -> BoxFuture<http::Response<tonic::body::BoxBody>, Infallible>
+    where
+        B: tonic::codegen::Body + Send + 'static,
+        B::Error: Into<StdError> + Send + 'static,
+        Req: prost::Message + Default + Send + 'static,
+        Resp: prost::Message + Send + 'static,
+        F: Fn(Arc<State>, Request<Req>) -> Fut + Send + 'static,
+        Fut: Future<Output = Result<Response<Resp>, Status>> + Send + 'static,
+    {
+        let limit = Some(self.state.max_message_bytes);
+        let method = Unary { state: self.state.clone(), handler };
+        Box::pin(async move {
+            let mut grpc = Grpc::new(ProstCodec::<Resp, Req>::default()).apply_max_message_size_config(limit, limit);
+            Ok(grpc.unary(method, req).await)
+        })
+    }
+}
+
+/// Adapts a handler function to tonic's [`UnaryService`].
+struct Unary<F> {
+    state: Arc<State>,
+    handler: F,
+}
+
+impl<Req, Resp, F, Fut> UnaryService<Req> for Unary<F>
+where
+    F: Fn(Arc<State>, Request<Req>) -> Fut,
+    Fut: Future<Output = Result<Response<Resp>, Status>>,
+{
+    type Response = Resp;
+    type Future = Fut;
+
+    fn call(&mut self, request: Request<Req>) -> Fut {
+        (self.handler)(self.state.clone(), request)
+    }
+}
+
+/// Message language of a request: `accept-language`, else `[server] language`.
+fn language<T>(req: &Request<T>) -> Language {
+    let header = req.metadata().get("accept-language").and_then(|v| v.to_str().ok());
+    header.and_then(Language::negotiate).unwrap_or_else(crate::error::language)
+}
+
+fn status(err: OmniError, language: Language) -> Status {
+    Status::new(Code::from_i32(err.grpc_code()), err.message(language))
+}
+
+async fn server_live(_: Arc<State>, _: Request<ServerLiveRequest>) -> Result<Response<ServerLiveResponse>, Status> {
+    Ok(Response::new(ServerLiveResponse { live: true }))
+}
+
+async fn server_ready(_: Arc<State>, _: Request<ServerReadyRequest>) -> Result<Response<ServerReadyResponse>, Status> {
+    Ok(Response::new(ServerReadyResponse { ready: crate::health::health().is_ready() }))
+}
+
+async fn model_ready(state: Arc<State>, req: Request<ModelReadyRequest>) -> Result<Response<ModelReadyResponse>, Status> {
+    state.model.check_name(&req.get_ref().name).map_err(|e| status(e, language(&req)))?;
+    Ok(Response::new(ModelReadyResponse { ready: crate::health::health().is_ready() }))
+}
+
+async fn server_metadata(_: Arc<State>, _: Request<ServerMetadataRequest>) -> Result<Response<ServerMetadataResponse>, Status> {
+    Ok(Response::new(ServerMetadataResponse {
+        name: "omniengine".to_string(),
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        extensions: Vec::new(),
+    }))
+}
+
+async fn model_metadata(state: Arc<State>, req: Request<ModelMetadataRequest>) -> Result<Response<ModelMetadataResponse>, Status> {
+    state.model.check_name(&req.get_ref().name).map_err(|e| status(e, language(&req)))?;
+    Ok(Response::new(state.model.metadata()))
+}
+
+async fn model_infer(state: Arc<State>, req: Request<ModelInferRequest>) -> Result<Response<ModelInferResponse>, Status> {
+    let lang = language(&req);
+    let header = |name| req.metadata().get(name).and_then(|v| v.to_str().ok());
+    let jobs = state.model.jobs(req.get_ref(), header(TRACEPARENT), header(TRACESTATE)).map_err(|e| status(e, lang))?;
+    // Ganze Requests ablehnen statt nur einen Teil der Samples einzureihen
+    if state.tx.capacity() < jobs.len() {
+        return Err(status(OmniError::QueueFull, lang));
+    }
+    let ids: Vec<String> = jobs.iter().map(|job| job.id.clone()).collect();
+
+    // Vor dem Einreihen abonnieren, sonst kann ein Ergebnis verpasst werden
+    let mut results = crate::results::subscribe();
+    for job in jobs {
+        super::infer::enqueue(&state.tx, job).map_err(|e| status(e, lang))?;
+    }
+    let received = super::infer::wait_for_all(&mut results, &ids, state.max_wait).await;
+
+    let mut samples = Vec::with_capacity(ids.len());
+    for (id, result) in ids.iter().zip(received) {
+        let result = match result {
+            Some(result) => result,
+            // Benachrichtigung verpasst oder Wartezeit abgelaufen: im Speicher nachsehen
+            None => match state.store.get_json(id).await {
+                Ok(Some(result)) => Arc::new(result),
+                Ok(None) => {
+                    let timeout = OmniError::Timeout { id: id.clone(), secs: state.max_wait.as_secs_f64() };
+                    return Err(status(timeout, lang));
+                }
+                Err(e) => return Err(status(OmniError::StoreUnavailable(e.to_string()), lang)),
+            },
+        };
+        if let Some(err) = JobError::from_result(&result) {
+            return Err(Status::new(Code::from_i32(err.grpc_code()), err.message));
+        }
+        samples.push(sample(&state.store, id, result).await.map_err(|e| status(e, lang))?);
+    }
+    let response = state.model.response(req.get_ref(), &samples).map_err(|e| status(e, lang))?;
+    Ok(Response::new(response))
+}
+
+/// Attaches the full output tensor to a chunked result.
+async fn sample(store: &RedisStorage, id: &str, result: Arc<Value>) -> Result<Sample, OmniError> {
+    let Some(layout) = result.get("tensor").and_then(|t| serde_json::from_value::<TensorLayout>(t.clone()).ok()) else {
+        return Ok(Sample { result, tensor: None });
+    };
+    let bytes = store
+        .get_chunks(id, 0..layout.chunks, layout.encoding.as_deref())
+        .await
+        .map_err(|e| OmniError::StoreUnavailable(e.to_string()))?;
+    let data = decode_values(&bytes, layout.dtype, layout.scale.unwrap_or(1.0));
+    let tensor = ndarray::ArrayD::from_shape_vec(ndarray::IxDyn(&layout.shape), data)
+        .map_err(|e| OmniError::Internal(format!("Gespeicherter Tensor von '{}': {}", id, e)))?;
+    Ok(Sample { result, tensor: Some(tensor) })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use prost::Message;
+    use tower::ServiceExt;
+
+    fn service() -> KServeService {
+        let runtime: Config = toml::from_str(
+            r#"
+            [model]
+            backend = "onnx"
+            device = "cpu"
+            model_path = "models/resnet50.onnx"
+            input_names = ["input"]
+            input_shapes = [[0, 3, 224, 224]]
+            output_names = ["output"]
+            output_shapes = [[0, 1000]]
+            [queue]
+            max_batch = 8
+            max_wait_ms = 5
+            [redis]
+            url = "redis://127.0.0.1:1/"
+            out_prefix = "results"
+            [input]
+            batch = 8
+            channels = 3
+            height = 224
+            width = 224
+            dtype = "f32"
+            "#,
+        )
+        .unwrap();
+        let cfg: GrpcCfg = toml::from_str("").unwrap();
+        let state = State {
+            model: KServeModel::new(&runtime, &cfg),
+            tx: mpsc::channel(1).0,
+            store: RedisStorage::new("redis://127.0.0.1:1/", "results".into()).unwrap(),
+            max_wait: Duration::ZERO,
+            max_message_bytes: cfg.max_message_bytes,
+        };
+        KServeService { state: Arc::new(state) }
+    }
+
+    /// Sends `message` as a gRPC frame; returns `grpc-status` (0 if absent)
+    /// and the response message bytes.
+    async fn call(method: &str, message: impl Message) -> (i32, Vec<u8>) {
+        let payload = message.encode_to_vec();
+        let mut frame = vec![0];
+        frame.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+        frame.extend_from_slice(&payload);
+        let req = http::Request::builder()
+            .method("POST")
+            .uri(format!("/inference.GRPCInferenceService/{}", method))
+            .header("content-type", "application/grpc")
+            .body(axum::body::Body::from(frame))
+            .unwrap();
+        let response = service().oneshot(req).await.unwrap();
+        let code = response.headers().get("grpc-status").map_or(0, |v| v.to_str().unwrap().parse().unwrap());
+        let body = axum::body::to_bytes(axum::body::Body::new(response.into_body()), usize::MAX).await.unwrap();
+        (code, body.get(5..).unwrap_or_default().to_vec())
+    }
+
+    #[tokio::test]
+    async fn test_routes_methods() {
+        let (code, body) = call("ModelMetadata", ModelMetadataRequest { name: "resnet50".into(), ..Default::default() }).await;
+        assert_eq!(code, 0);
+        let metadata = ModelMetadataResponse::decode(body.as_slice()).unwrap();
+        assert_eq!(metadata.inputs[0].shape, vec![-1, 3, 224, 224]);
+
+        let (code, _) = call("ModelMetadata", ModelMetadataRequest { name: "other".into(), ..Default::default() }).await;
+        assert_eq!(code, Code::NotFound as i32);
+        let (code, _) = call("ModelStatistics", ServerLiveRequest {}).await;
+        assert_eq!(code, Code::Unimplemented as i32);
+        // Ohne Inputs wird nichts eingereiht
+        let request = ModelInferRequest { model_name: "resnet50".into(), ..Default::default() };
+        assert_eq!(call("ModelInfer", request).await.0, Code::InvalidArgument as i32);
+    }
+}
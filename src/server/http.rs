@@ -0,0 +1,545 @@
+//! HTTP API server (`[server.http]`).
+//!
+//! * `POST /v1/infer` - enqueue a job, `?wait_ms=` waits for its result
+//!   (`[server.http.infer]`, see [`super::infer`])
+//! * `GET /v1/results/{job_id}` - stored result of a job; `?wait_ms=` long-polls
+//!   until the result exists (capped at `max_wait_ms`), otherwise 404
+//! * `GET /v1/results/{job_id}/tensor` - full output tensor of a chunked result;
+//!   `?offset=&limit=` or `?chunk=` select elements, `Accept:
+//!   application/octet-stream` returns the raw stored values instead of JSON
+//! * `GET /v1/traces/{trace_id}` - ids of the stored results whose jobs
+//!   carried this W3C trace id (see [`crate::trace_context`])
+//! * `GET /v1/model` - backend, configured inputs/outputs and the
+//!   capabilities reported by each worker's engine
+//! * `GET /v1/usage` - GPU-seconds, energy and cost of the model so far
+//!   (see [`crate::accounting`])
+//! * `POST /v1/uploads`, `PUT /v1/uploads/{job_id}/chunks/{index}`,
+//!   `POST /v1/uploads/{job_id}/complete` - chunked upload of large inputs
+//!   (`[server.http.upload]`, see [`super::upload`]); a `traceparent` header
+//!   on creation becomes the job's trace context
+//! * `PUT /v1/blobs/{sha256}`, `GET /v1/blobs/{sha256}` - cache an input
+//!   under its SHA-256 for `"blob"` references in `POST /v1/infer`
+//!   (`[server.http.infer.blobs]`, see [`super::blobs`])
+//! * `GET /v1/stream` - WebSocket for streaming jobs and results
+//!   (`[server.http.stream]`, feature `websocket`, see [`super::stream`])
+//! * `GET /openapi.json` - OpenAPI document
+//!
+//! Errors are `application/problem+json` bodies with a stable `code` (see
+//! [`crate::error`]); the message language follows `Accept-Language`.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use axum::body::Bytes;
+use axum::extract::{DefaultBodyLimit, FromRequestParts, Path, Query, State};
+use axum::http::request::Parts;
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post, put};
+use axum::{Json, Router};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tokio::net::TcpListener;
+use tokio::sync::mpsc;
+use tokio::time::Instant;
+use tracing::info;
+
+use super::blobs::Blobs;
+use super::infer::Infer;
+use super::upload::{UploadRequest, Uploads};
+use crate::error::{Language, OmniError};
+use crate::storage::redis_store::{chunk_span, decode_values, RedisStorage, TensorLayout};
+use crate::text::TextEncoder;
+use crate::trace_context::{self, TRACEPARENT, TRACESTATE};
+use crate::types::{HttpCfg, Job, ModelCfg};
+
+struct AppState {
+    store: RedisStorage,
+    max_wait: Duration,
+    model: Value,
+    infer: Option<Infer>,
+    uploads: Option<Uploads>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ResultQuery {
+    #[serde(default)]
+    wait_ms: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct TensorQuery {
+    #[serde(default)]
+    offset: usize,
+    limit: Option<usize>,
+    chunk: Option<usize>,
+}
+
+/// Serves the API until the process exits. Uploads are enqueued into `tx`;
+/// `text` tokenizes text jobs (from `[text]`).
+pub async fn serve(cfg: HttpCfg, model: &ModelCfg, store: RedisStorage, tx: mpsc::Sender<Job>, text: Option<Arc<TextEncoder>>) -> Result<()> {
+    let listener = TcpListener::bind(&cfg.bind).await?;
+    info!("HTTP-API auf {}", cfg.bind);
+    let infer = cfg.infer.map(|infer| Infer::new(infer, tx.clone()).with_text(text.clone()));
+    #[cfg(feature = "websocket")]
+    let stream = cfg.stream.map(|stream| super::stream::router(stream, tx.clone(), text));
+    let uploads = cfg.upload.map(|upload| Uploads::new(upload, tx));
+    let app = router(store, Duration::from_millis(cfg.max_wait_ms), model, infer, uploads);
+    #[cfg(feature = "websocket")]
+    let app = match stream {
+        Some(stream) => app.merge(stream),
+        None => app,
+    };
+    axum::serve(listener, app).await?;
+    Ok(())
+}
+
+fn router(store: RedisStorage, max_wait: Duration, model: &ModelCfg, infer: Option<Infer>, uploads: Option<Uploads>) -> Router {
+    let tensors = |names: &[String], shapes: &[Vec<usize>]| -> Vec<Value> {
+        names.iter().zip(shapes).map(|(name, shape)| json!({ "name": name, "shape": shape })).collect()
+    };
+    let model = json!({
+        "backend": model.backend,
+        "device": model.device,
+        "inputs": tensors(&model.input_names, &model.input_shapes),
+        "outputs": tensors(&model.output_names, &model.output_shapes),
+    });
+    let mut router = Router::new()
+        .route("/v1/results/{job_id}", get(get_result))
+        .route("/v1/results/{job_id}/tensor", get(get_tensor))
+        .route("/v1/traces/{trace_id}", get(get_trace))
+        .route("/v1/model", get(get_model))
+        .route("/v1/usage", get(|| async { Json(crate::accounting::report()) }))
+        .route("/openapi.json", get(|| async { Json(crate::openapi::spec()) }));
+    if let Some(infer) = &infer {
+        router = router.route("/v1/infer", post(post_infer).layer(DefaultBodyLimit::max(infer.max_body_bytes())));
+        if let Some(blobs) = infer.blobs() {
+            router = router.route(
+                "/v1/blobs/{sha256}",
+                put(put_blob).get(get_blob).layer(DefaultBodyLimit::max(blobs.max_blob_bytes())),
+            );
+        }
+    }
+    if let Some(uploads) = &uploads {
+        router = router
+            .route("/v1/uploads", post(create_upload))
+            .route("/v1/uploads/{job_id}", get(get_upload).delete(delete_upload))
+            .route(
+                "/v1/uploads/{job_id}/chunks/{index}",
+                put(put_chunk).layer(DefaultBodyLimit::max(uploads.max_chunk_bytes())),
+            )
+            .route("/v1/uploads/{job_id}/complete", post(complete_upload));
+    }
+    router.with_state(Arc::new(AppState { store, max_wait, model, infer, uploads }))
+}
+
+/// Message language of a request: `Accept-Language`, else `[server] language`.
+#[derive(Clone, Copy)]
+struct Lang(Language);
+
+impl<S: Send + Sync> FromRequestParts<S> for Lang {
+    type Rejection = std::convert::Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _: &S) -> Result<Self, Self::Rejection> {
+        let header = parts.headers.get(header::ACCEPT_LANGUAGE).and_then(|v| v.to_str().ok());
+        Ok(Self(header.and_then(Language::negotiate).unwrap_or_else(crate::error::language)))
+    }
+}
+
+fn error(err: OmniError, Lang(language): Lang) -> Response {
+    let mut response = (err.status(), Json(err.to_problem(language))).into_response();
+    response.headers_mut().insert(header::CONTENT_TYPE, header::HeaderValue::from_static("application/problem+json"));
+    response
+}
+
+/// Static model description plus the capabilities of the loaded engines.
+async fn get_model(State(state): State<Arc<AppState>>) -> Json<Value> {
+    let mut model = state.model.clone();
+    model["workers"] = json!(crate::engine::registered_capabilities());
+    Json(model)
+}
+
+async fn get_result(
+    State(state): State<Arc<AppState>>,
+    Path(job_id): Path<String>,
+    Query(query): Query<ResultQuery>,
+    lang: Lang,
+) -> Response {
+    let wait = Duration::from_millis(query.wait_ms).min(state.max_wait);
+    match wait_for_result(&state.store, &job_id, wait).await {
+        Ok(Some(result)) => Json(result).into_response(),
+        Ok(None) => error(OmniError::ResultNotFound(job_id), lang),
+        Err(e) => error(OmniError::StoreUnavailable(e.to_string()), lang),
+    }
+}
+
+/// Ids of the stored results of a distributed trace.
+async fn get_trace(State(state): State<Arc<AppState>>, Path(trace_id): Path<String>, lang: Lang) -> Response {
+    match state.store.trace_jobs(&trace_id).await {
+        Ok(jobs) => Json(json!({ "trace_id": trace_id, "jobs": jobs })).into_response(),
+        Err(e) => error(OmniError::StoreUnavailable(e.to_string()), lang),
+    }
+}
+
+async fn get_tensor(
+    State(state): State<Arc<AppState>>,
+    Path(job_id): Path<String>,
+    Query(query): Query<TensorQuery>,
+    headers: HeaderMap,
+    lang: Lang,
+) -> Response {
+    let unavailable = |e: anyhow::Error, lang| error(OmniError::StoreUnavailable(e.to_string()), lang);
+    let result = match state.store.get_json(&job_id).await {
+        Ok(Some(result)) => result,
+        Ok(None) => return error(OmniError::ResultNotFound(job_id), lang),
+        Err(e) => return unavailable(e, lang),
+    };
+    let Some(layout) = result.get("tensor").and_then(|t| serde_json::from_value::<TensorLayout>(t.clone()).ok()) else {
+        return error(OmniError::TensorNotChunked(job_id), lang);
+    };
+
+    let Some(elements) = element_range(&query, &layout) else {
+        return error(OmniError::RangeNotSatisfiable, lang);
+    };
+    let chunks = chunk_span(&elements, layout.chunk_elements);
+    let bytes = match state.store.get_chunks(&job_id, chunks.clone(), layout.encoding.as_deref()).await {
+        Ok(bytes) => bytes,
+        Err(e) => return unavailable(e, lang),
+    };
+    let size = layout.dtype.size();
+    let skip = (elements.start - chunks.start * layout.chunk_elements) * size;
+    // Gespeicherte Chunks kürzer als das Layout: beschädigt oder abgeschnitten
+    let expected = skip + elements.len() * size;
+    if bytes.len() < expected {
+        return error(OmniError::ChunkLength { index: chunks.start, actual: bytes.len(), expected }, lang);
+    }
+    let bytes = &bytes[skip..expected];
+
+    let shape = layout.shape.iter().map(|d| d.to_string()).collect::<Vec<_>>().join(",");
+    let binary = headers
+        .get(header::ACCEPT)
+        .and_then(|a| a.to_str().ok())
+        .is_some_and(|a| a.contains("application/octet-stream"));
+    if binary {
+        let mut response = (
+            [
+                (header::CONTENT_TYPE, "application/octet-stream".to_string()),
+                (header::HeaderName::from_static("x-tensor-shape"), shape),
+                (header::HeaderName::from_static("x-tensor-offset"), elements.start.to_string()),
+                (header::HeaderName::from_static("x-tensor-dtype"), layout.dtype.name().to_string()),
+            ],
+            bytes.to_vec(),
+        )
+            .into_response();
+        if let Some(scale) = layout.scale {
+            response.headers_mut().insert("x-tensor-scale", scale.to_string().parse().unwrap());
+        }
+        return response;
+    }
+    let data = decode_values(bytes, layout.dtype, layout.scale.unwrap_or(1.0));
+    Json(json!({ "id": job_id, "shape": layout.shape, "offset": elements.start, "data": data })).into_response()
+}
+
+/// Enqueues a job. With `wait_ms` the result is returned if it is ready in
+/// time; otherwise `202` with the job id and its result URL.
+async fn post_infer(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<ResultQuery>,
+    headers: HeaderMap,
+    lang: Lang,
+    body: Bytes,
+) -> Response {
+    // Die Route existiert nur mit [server.http.infer]
+    let infer = state.infer.as_ref().expect("Infer-Route ohne [server.http.infer]");
+    let wait = Duration::from_millis(query.wait_ms).min(state.max_wait);
+    // Vor dem Einreihen abonnieren, sonst kann das Ergebnis verpasst werden
+    let mut results = (!wait.is_zero()).then(crate::results::subscribe);
+    let header = |name| headers.get(name).and_then(|v| v.to_str().ok());
+    let id = match infer.submit(&body, header(TRACEPARENT), header(TRACESTATE)) {
+        Ok(id) => id,
+        Err(e) => return error(e, lang),
+    };
+    if let Some(results) = results.as_mut() {
+        if let Some(result) = super::infer::wait_for(results, &id, wait).await {
+            return Json(result.as_ref()).into_response();
+        }
+        // Benachrichtigung verpasst (z. B. Überlauf): im Speicher nachsehen
+        if let Ok(Some(result)) = state.store.get_json(&id).await {
+            return Json(result).into_response();
+        }
+    }
+    let location = format!("/v1/results/{}", id);
+    let mut response = (StatusCode::ACCEPTED, Json(json!({ "id": id, "result": location }))).into_response();
+    if let Ok(value) = location.parse() {
+        response.headers_mut().insert(header::LOCATION, value);
+    }
+    response
+}
+
+fn blobs(state: &AppState) -> &Blobs {
+    // Die Blob-Routen existieren nur mit [server.http.infer.blobs]
+    state.infer.as_ref().and_then(Infer::blobs).expect("Blob-Route ohne [server.http.infer.blobs]")
+}
+
+/// Caches an input; the body is `.npy` or a JSON tensor with this SHA-256.
+async fn put_blob(State(state): State<Arc<AppState>>, Path(sha256): Path<String>, lang: Lang, body: Bytes) -> Response {
+    match blobs(&state).put(&sha256, &body) {
+        Ok(status) => (StatusCode::CREATED, Json(status)).into_response(),
+        Err(e) => error(e, lang),
+    }
+}
+
+async fn get_blob(State(state): State<Arc<AppState>>, Path(sha256): Path<String>, lang: Lang) -> Response {
+    match blobs(&state).status(&sha256) {
+        Ok(status) => Json(status).into_response(),
+        Err(e) => error(e, lang),
+    }
+}
+
+fn uploads(state: &AppState) -> &Uploads {
+    // Die Upload-Routen existieren nur mit [server.http.upload]
+    state.uploads.as_ref().expect("Upload-Route ohne [server.http.upload]")
+}
+
+/// Creates an upload; W3C trace headers become the job's trace context.
+async fn create_upload(State(state): State<Arc<AppState>>, lang: Lang, headers: HeaderMap, Json(mut req): Json<UploadRequest>) -> Response {
+    let header = |name| headers.get(name).and_then(|v| v.to_str().ok());
+    trace_context::inject(&mut req.meta, header(TRACEPARENT), header(TRACESTATE));
+    match uploads(&state).create(req) {
+        Ok(status) => (StatusCode::CREATED, Json(status)).into_response(),
+        Err(e) => error(e, lang),
+    }
+}
+
+async fn get_upload(State(state): State<Arc<AppState>>, Path(job_id): Path<String>, lang: Lang) -> Response {
+    match uploads(&state).status(&job_id) {
+        Ok(status) => Json(status).into_response(),
+        Err(e) => error(e, lang),
+    }
+}
+
+async fn delete_upload(State(state): State<Arc<AppState>>, Path(job_id): Path<String>, lang: Lang) -> Response {
+    match uploads(&state).abort(&job_id) {
+        Ok(()) => StatusCode::NO_CONTENT.into_response(),
+        Err(e) => error(e, lang),
+    }
+}
+
+/// Stores one chunk; the body is raw data, `X-Chunk-Crc32` its CRC32 in hex.
+async fn put_chunk(
+    State(state): State<Arc<AppState>>,
+    Path((job_id, index)): Path<(String, usize)>,
+    headers: HeaderMap,
+    lang: Lang,
+    body: Bytes,
+) -> Response {
+    let crc32 = headers
+        .get("x-chunk-crc32")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| u32::from_str_radix(v.trim().trim_start_matches("0x"), 16).ok());
+    let Some(crc32) = crc32 else {
+        return error(OmniError::InvalidHeader("X-Chunk-Crc32"), lang);
+    };
+    match uploads(&state).put_chunk(&job_id, index, crc32, &body) {
+        Ok(status) => Json(status).into_response(),
+        Err(e) => error(e, lang),
+    }
+}
+
+async fn complete_upload(State(state): State<Arc<AppState>>, Path(job_id): Path<String>, lang: Lang) -> Response {
+    match uploads(&state).complete(&job_id).await {
+        Ok(()) => {
+            let result = format!("/v1/results/{}", job_id);
+            (StatusCode::ACCEPTED, Json(json!({ "id": job_id, "result": result }))).into_response()
+        }
+        Err(e) => error(e, lang),
+    }
+}
+
+/// Element range selected by `chunk` or `offset`/`limit`; `None` if it lies
+/// outside the tensor.
+fn element_range(query: &TensorQuery, layout: &TensorLayout) -> Option<std::ops::Range<usize>> {
+    let total: usize = layout.shape.iter().product();
+    let (start, len) = match query.chunk {
+        Some(chunk) => (chunk.checked_mul(layout.chunk_elements)?, layout.chunk_elements),
+        None => (query.offset, query.limit.unwrap_or(total)),
+    };
+    if start > total || (start == total && total > 0) {
+        return None;
+    }
+    Some(start..start.saturating_add(len).min(total))
+}
+
+/// Polls the store until the result exists or `wait` has passed.
+pub(crate) async fn wait_for_result(store: &RedisStorage, job_id: &str, wait: Duration) -> Result<Option<Value>> {
+    let deadline = Instant::now() + wait;
+    let mut delay = Duration::from_millis(10);
+    loop {
+        if let Some(result) = store.get_json(job_id).await? {
+            return Ok(Some(result));
+        }
+        let now = Instant::now();
+        if now >= deadline {
+            return Ok(None);
+        }
+        // Exponentielles Backoff bis 250 ms, nie über die Deadline hinaus
+        tokio::time::sleep(delay.min(deadline - now)).await;
+        delay = (delay * 2).min(Duration::from_millis(250));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use axum::http::Request;
+    use tower::ServiceExt;
+
+    fn app() -> Router {
+        app_with(mpsc::channel(1).0)
+    }
+
+    /// Router whose jobs are sent into `tx`.
+    fn app_with(tx: mpsc::Sender<Job>) -> Router {
+        // Nicht erreichbarer Redis: Verbindungsfehler statt Ergebnis
+        let store = RedisStorage::new("redis://127.0.0.1:1/", "results".into()).unwrap();
+        let infer = Infer::new(toml::from_str("").unwrap(), tx.clone());
+        let uploads = Uploads::new(toml::from_str("chunk_bytes = 8").unwrap(), tx);
+        router(store, Duration::from_millis(500), &model(), Some(infer), Some(uploads))
+    }
+
+    fn model() -> ModelCfg {
+        toml::from_str(
+            "backend = \"onnx\"\ndevice = \"cpu\"\nmodel_path = \"m.onnx\"\ninput_names = [\"x\"]\ninput_shapes = [[1, 0]]\noutput_names = [\"y\"]\noutput_shapes = [[1, 2]]",
+        )
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_openapi_route() {
+        let res = app().oneshot(Request::get("/openapi.json").body(Body::empty()).unwrap()).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+    }
+
+    #[test]
+    fn test_element_range() {
+        let layout = TensorLayout {
+            shape: vec![2, 5],
+            dtype: Default::default(),
+            chunk_elements: 4,
+            chunks: 3,
+            scale: None,
+            encoding: None,
+        };
+        let query = |offset, limit, chunk| TensorQuery { offset, limit, chunk };
+
+        assert_eq!(element_range(&query(0, None, None), &layout), Some(0..10));
+        assert_eq!(element_range(&query(3, Some(4), None), &layout), Some(3..7));
+        assert_eq!(element_range(&query(0, None, Some(2)), &layout), Some(8..10));
+        assert_eq!(element_range(&query(0, None, Some(3)), &layout), None);
+        assert_eq!(element_range(&query(11, None, None), &layout), None);
+    }
+
+    #[tokio::test]
+    async fn test_model_route() {
+        crate::engine::register_capabilities("http-test", Default::default());
+        let res = app().oneshot(Request::get("/v1/model").body(Body::empty()).unwrap()).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(res.into_body(), usize::MAX).await.unwrap();
+        let model: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(model["inputs"][0], json!({"name": "x", "shape": [1, 0]}));
+        assert_eq!(model["workers"]["http-test"]["residency"], "host");
+    }
+
+    #[tokio::test]
+    async fn test_tensor_shorter_than_layout() {
+        let store = RedisStorage::new(crate::storage::redis_store::MEMORY_URL, "results".into()).unwrap();
+        let layout = store.store_tensor("short", &[6], &[1.0, 2.0, 3.0, 4.0, 5.0, 6.0], 4).await.unwrap();
+        let get = |layout: TensorLayout, query: &str| {
+            let store = store.clone();
+            let uri = format!("/v1/results/short/tensor{}", query);
+            async move {
+                store.store_json("short", &json!({ "id": "short", "tensor": layout })).await.unwrap();
+                let app = router(store, Duration::from_millis(500), &model(), None, None);
+                app.oneshot(Request::get(uri).body(Body::empty()).unwrap()).await.unwrap().status()
+            }
+        };
+        assert_eq!(get(layout.clone(), "").await, StatusCode::OK);
+        // Layout behauptet mehr Elemente, als im letzten Chunk stehen
+        let longer = TensorLayout { shape: vec![2, 4], ..layout.clone() };
+        assert_eq!(get(longer, "?offset=2").await, StatusCode::BAD_REQUEST);
+        let zero = TensorLayout { chunk_elements: 0, ..layout };
+        assert_eq!(get(zero, "").await, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_result_store_unavailable() {
+        let req = Request::get("/v1/results/job-1?wait_ms=10").body(Body::empty()).unwrap();
+        let res = app().oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+    }
+
+    #[tokio::test]
+    async fn test_errors_are_problem_details() {
+        let req = Request::get("/v1/uploads/missing").header(header::ACCEPT_LANGUAGE, "en-US,de;q=0.5").body(Body::empty()).unwrap();
+        let res = app().oneshot(req).await.unwrap();
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+        assert_eq!(res.headers()[header::CONTENT_TYPE], "application/problem+json");
+        let body = axum::body::to_bytes(res.into_body(), usize::MAX).await.unwrap();
+        let problem: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(problem["code"], "UPLOAD_NOT_FOUND");
+        assert_eq!(problem["status"], 404);
+        assert_eq!(problem["detail"], "no upload 'missing'");
+    }
+
+    #[tokio::test]
+    async fn test_upload_routes() {
+        let app = app();
+        let create = Request::post("/v1/uploads")
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(r#"{"id": "vol-1", "shape": [3]}"#))
+            .unwrap();
+        let res = app.clone().oneshot(create).await.unwrap();
+        assert_eq!(res.status(), StatusCode::CREATED);
+
+        let chunk: Vec<u8> = [1.0f32, 2.0].iter().flat_map(|v| v.to_le_bytes()).collect();
+        let put = |crc: String| {
+            Request::put("/v1/uploads/vol-1/chunks/0").header("x-chunk-crc32", crc).body(Body::from(chunk.clone())).unwrap()
+        };
+        let res = app.clone().oneshot(put("deadbeef".to_string())).await.unwrap();
+        assert_eq!(res.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        let res = app.clone().oneshot(put(format!("{:08x}", crc32fast::hash(&chunk)))).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+
+        let res = app.clone().oneshot(Request::post("/v1/uploads/vol-1/complete").body(Body::empty()).unwrap()).await.unwrap();
+        assert_eq!(res.status(), StatusCode::CONFLICT);
+        let body = axum::body::to_bytes(res.into_body(), usize::MAX).await.unwrap();
+        assert!(String::from_utf8_lossy(&body).contains("[1]"));
+    }
+
+    #[tokio::test]
+    async fn test_infer_route() {
+        let (tx, mut rx) = mpsc::channel(4);
+        let app = app_with(tx);
+        let post = |uri: &str, body: &'static str| Request::post(uri).body(Body::from(body)).unwrap();
+
+        let res = app.clone().oneshot(post("/v1/infer", r#"{"id": "inf-1", "shape": [1], "data": [0.5]}"#)).await.unwrap();
+        assert_eq!(res.status(), StatusCode::ACCEPTED);
+        assert_eq!(res.headers()[header::LOCATION], "/v1/results/inf-1");
+        assert_eq!(rx.recv().await.unwrap().id, "inf-1");
+
+        // Ein Worker-Ersatz beantwortet den Job, die Anfrage wartet darauf
+        tokio::spawn(async move {
+            let job = rx.recv().await.unwrap();
+            crate::results::publish(&json!({"id": job.id, "label": "cat"}));
+        });
+        let res = app.clone().oneshot(post("/v1/infer?wait_ms=5000", r#"{"id": "inf-2", "shape": [1], "data": [0.5]}"#)).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(res.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(serde_json::from_slice::<Value>(&body).unwrap()["label"], "cat");
+
+        let res = app.oneshot(post("/v1/infer", "not json")).await.unwrap();
+        assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+    }
+}
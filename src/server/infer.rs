@@ -0,0 +1,257 @@
+//! Synchronous inference requests (`[server.http.infer]`).
+//!
+//! `POST /v1/infer` takes one job in the JSON wire format (or a CloudEvent
+//! wrapping it) and enqueues it like any other source. Without an `id` the
+//! server assigns one. With `wait_ms` the request waits for the result,
+//! otherwise (or when the wait runs out) it answers with the job id, whose
+//! result is fetched via `/v1/results/{job_id}`. With
+//! `[server.http.infer.blobs]` inputs can reference cached uploads (see
+//! [`super::blobs`]). With a `[text]` section a job may carry `text` instead
+//! of a tensor; it is tokenized like text jobs of the CLI.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde::Deserialize;
+use serde_json::Value;
+use tokio::sync::{broadcast, mpsc};
+
+use super::blobs::Blobs;
+use crate::error::OmniError;
+use crate::text::{TextEncoder, TextJob};
+use crate::trace_context;
+use crate::types::{InferCfg, Job, JobMeta, JobRequest};
+
+/// Enqueues inference requests.
+pub struct Infer {
+    cfg: InferCfg,
+    tx: mpsc::Sender<Job>,
+    blobs: Option<Blobs>,
+    text: Option<Arc<TextEncoder>>,
+}
+
+impl Infer {
+    pub fn new(cfg: InferCfg, tx: mpsc::Sender<Job>) -> Self {
+        let blobs = cfg.blobs.clone().map(Blobs::new);
+        Self { cfg, tx, blobs, text: None }
+    }
+
+    /// Accepts `text` jobs, tokenized with `encoder` (from `[text]`).
+    pub fn with_text(mut self, encoder: Option<Arc<TextEncoder>>) -> Self {
+        self.text = encoder;
+        self
+    }
+
+    /// Input cache, if `[server.http.infer.blobs]` is configured.
+    pub fn blobs(&self) -> Option<&Blobs> {
+        self.blobs.as_ref()
+    }
+
+    /// Largest accepted request body.
+    pub fn max_body_bytes(&self) -> usize {
+        self.cfg.max_body_bytes
+    }
+
+    /// Parses a request body and enqueues the job; returns its id. A full
+    /// input queue is reported instead of waiting for space.
+    pub fn submit(&self, body: &[u8], traceparent: Option<&str>, tracestate: Option<&str>) -> Result<String, OmniError> {
+        let default_id = || format!("http-{:016x}", rand::random::<u64>());
+        let mut job = parse_with(body, default_id, self.blobs.as_ref(), self.text.as_deref())?;
+        trace_context::inject(&mut job.meta, traceparent, tracestate);
+        let id = job.id.clone();
+        enqueue(&self.tx, job)?;
+        Ok(id)
+    }
+}
+
+/// Parses a job in the JSON wire format (or a CloudEvent wrapping it);
+/// `default_id` names jobs without `id`, `text` jobs are tokenized with
+/// `text`.
+#[cfg_attr(not(feature = "websocket"), allow(dead_code))]
+pub fn parse(body: &[u8], default_id: impl FnOnce() -> String, text: Option<&TextEncoder>) -> Result<Job, OmniError> {
+    parse_with(body, default_id, None, text)
+}
+
+/// A job with `text` instead of a tensor.
+#[derive(Deserialize)]
+struct TextRequest {
+    id: String,
+    text: String,
+    #[serde(default)]
+    meta: JobMeta,
+}
+
+/// Like [`parse`]; `blob` references are resolved from `blobs`, `text` jobs
+/// are tokenized with `text`.
+fn parse_with(
+    body: &[u8],
+    default_id: impl FnOnce() -> String,
+    blobs: Option<&Blobs>,
+    text: Option<&TextEncoder>,
+) -> Result<Job, OmniError> {
+    let invalid = |e: &dyn std::fmt::Display| OmniError::InvalidInput(e.to_string());
+    let message = serde_json::from_slice::<Value>(body).map_err(|e| invalid(&e))?;
+    let mut message = crate::cloudevents::unwrap_job(message).map_err(|e| invalid(&e))?;
+    if let Some(fields) = message.as_object_mut() {
+        fields.entry("id").or_insert_with(|| default_id().into());
+    }
+    if message.get("text").is_some() {
+        let encoder = text.ok_or_else(|| invalid(&"'text' benötigt eine [text]-Sektion"))?;
+        let tensor_fields = ["shape", "data", "inputs", "blob"];
+        if let Some(field) = tensor_fields.iter().find(|field| message.get(**field).is_some()) {
+            return Err(invalid(&format!("'text' und '{}' schließen sich aus", field)));
+        }
+        let TextRequest { id, text, meta } = serde_json::from_value(message).map_err(|e| invalid(&e))?;
+        return encoder.encode(&TextJob { id, text, meta }).map_err(|e| invalid(&e));
+    }
+    let cached = match blobs {
+        Some(blobs) => blobs.resolve(&mut message)?,
+        None => Vec::new(),
+    };
+    let mut job = serde_json::from_value::<JobRequest>(message)
+        .map_err(|e| invalid(&e))?
+        .into_job()
+        .map_err(|e| invalid(&e))?;
+    for (name, tensor) in cached {
+        let tensor = tensor.as_ref().clone();
+        match name {
+            Some(name) => {
+                job.inputs.insert(name, tensor);
+            }
+            None => job.tensor = tensor,
+        }
+    }
+    Ok(job)
+}
+
+/// Enqueues a job without waiting for space in the input queue.
+pub fn enqueue(tx: &mpsc::Sender<Job>, job: Job) -> Result<(), OmniError> {
+    tx.try_send(job).map_err(|e| match e {
+        mpsc::error::TrySendError::Full(_) => OmniError::QueueFull,
+        mpsc::error::TrySendError::Closed(_) => OmniError::ShuttingDown,
+    })
+}
+
+/// Waits up to `wait` for the result of job `id` on `results` (subscribed
+/// before the job was enqueued). `None` if it did not arrive in time or
+/// notifications were lost; the caller then falls back to the store.
+pub async fn wait_for(results: &mut broadcast::Receiver<Arc<Value>>, id: &str, wait: Duration) -> Option<Arc<Value>> {
+    wait_for_all(results, &[id.to_string()], wait).await.pop().flatten()
+}
+
+/// Like [`wait_for`] for several jobs; results are in the order of `ids`,
+/// missing ones `None`.
+pub async fn wait_for_all(results: &mut broadcast::Receiver<Arc<Value>>, ids: &[String], wait: Duration) -> Vec<Option<Arc<Value>>> {
+    let mut found = vec![None; ids.len()];
+    let receive = async {
+        let mut pending = ids.len();
+        while pending > 0 {
+            match results.recv().await {
+                Ok(result) => {
+                    let index = ids.iter().position(|id| result["id"] == *id.as_str());
+                    if let Some(slot) = index.map(|i| &mut found[i]).filter(|slot| slot.is_none()) {
+                        *slot = Some(result);
+                        pending -= 1;
+                    }
+                }
+                Err(broadcast::error::RecvError::Lagged(_)) | Err(broadcast::error::RecvError::Closed) => return,
+            }
+        }
+    };
+    let _ = tokio::time::timeout(wait, receive).await;
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn infer(capacity: usize) -> (Infer, mpsc::Receiver<Job>) {
+        let (tx, rx) = mpsc::channel(capacity);
+        (Infer::new(toml::from_str("").unwrap(), tx), rx)
+    }
+
+    #[test]
+    fn test_submit_enqueues_job() {
+        let (infer, mut rx) = infer(2);
+        let id = infer.submit(br#"{"id": "j1", "shape": [2], "data": [1, 2]}"#, None, None).unwrap();
+        assert_eq!(id, "j1");
+        assert_eq!(rx.try_recv().unwrap().tensor[[1]], 2.0);
+
+        // Ohne id vergibt der Server eine
+        let traceparent = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+        let id = infer.submit(br#"{"shape": [1], "data": [0]}"#, Some(traceparent), None).unwrap();
+        assert!(id.starts_with("http-"));
+        let job = rx.try_recv().unwrap();
+        assert_eq!(job.id, id);
+        assert_eq!(job.meta["traceparent"], traceparent);
+    }
+
+    #[test]
+    fn test_submit_rejects_invalid_and_full() {
+        let (infer, rx) = infer(1);
+        let err = infer.submit(b"{", None, None).unwrap_err();
+        assert_eq!(err.code(), "INVALID_INPUT");
+        let err = infer.submit(br#"{"shape": [3], "data": [1]}"#, None, None).unwrap_err();
+        assert_eq!(err.code(), "INVALID_INPUT");
+
+        let job = br#"{"shape": [1], "data": [0]}"#;
+        infer.submit(job, None, None).unwrap();
+        assert_eq!(infer.submit(job, None, None).unwrap_err(), OmniError::QueueFull);
+        drop(rx);
+        assert_eq!(infer.submit(job, None, None).unwrap_err(), OmniError::ShuttingDown);
+    }
+
+    #[test]
+    fn test_submit_resolves_blobs() {
+        let (tx, mut rx) = mpsc::channel(2);
+        let infer = Infer::new(toml::from_str("[blobs]").unwrap(), tx);
+        let body = br#"{"shape": [3], "data": [1, 2, 3]}"#;
+        let sha256 = infer.blobs().unwrap().put(&crate::server::blobs::tests::sha256(body), body).unwrap().sha256;
+
+        let request = format!(r#"{{"id": "j1", "blob": "{0}", "inputs": {{"mask": {{"blob": "{0}"}}}}}}"#, sha256);
+        infer.submit(request.as_bytes(), None, None).unwrap();
+        let job = rx.try_recv().unwrap();
+        assert_eq!((job.tensor.shape(), job.tensor[[2]]), (&[3][..], 3.0));
+        assert_eq!(job.inputs["mask"][[0]], 1.0);
+
+        let err = infer.submit(br#"{"blob": "00"}"#, None, None).unwrap_err();
+        assert_eq!(err.code(), "BLOB_NOT_FOUND");
+    }
+
+    #[test]
+    fn test_submit_tokenizes_text() {
+        let (infer, mut rx) = infer(2);
+        let err = infer.submit(br#"{"text": "hello"}"#, None, None).unwrap_err();
+        assert_eq!(err.code(), "INVALID_INPUT");
+
+        let vocab = ["[PAD]", "[UNK]", "[CLS]", "[SEP]", "hello", "world"];
+        let tokenizer = crate::text::WordPieceTokenizer::from_tokens(vocab.iter().map(|t| t.to_string()).collect(), true).unwrap();
+        let infer = infer.with_text(Some(Arc::new(TextEncoder::with_tokenizer(tokenizer, 5))));
+        let id = infer.submit(br#"{"id": "t1", "text": "Hello world", "meta": {"lang": "en"}}"#, None, None).unwrap();
+        assert_eq!(id, "t1");
+        let job = rx.try_recv().unwrap();
+        assert_eq!(job.tensor.iter().cloned().collect::<Vec<_>>(), vec![2.0, 4.0, 5.0, 3.0, 0.0]);
+        assert_eq!(job.meta["lang"], "en");
+
+        let err = infer.submit(br#"{"text": "hello", "shape": [1], "data": [0]}"#, None, None).unwrap_err();
+        assert_eq!(err.code(), "INVALID_INPUT");
+    }
+
+    #[tokio::test]
+    async fn test_wait_for_result() {
+        let mut results = crate::results::subscribe();
+        crate::results::publish(&serde_json::json!({"id": "infer-other"}));
+        crate::results::publish(&serde_json::json!({"id": "infer-test", "label": "cat"}));
+        let result = wait_for(&mut results, "infer-test", Duration::from_secs(1)).await.unwrap();
+        assert_eq!(result["label"], "cat");
+        assert!(wait_for(&mut results, "infer-missing", Duration::from_millis(10)).await.is_none());
+
+        let ids = vec!["infer-b".to_string(), "infer-a".to_string(), "infer-c".to_string()];
+        crate::results::publish(&serde_json::json!({"id": "infer-a"}));
+        crate::results::publish(&serde_json::json!({"id": "infer-b"}));
+        let found = wait_for_all(&mut results, &ids, Duration::from_millis(50)).await;
+        let found: Vec<_> = found.iter().map(|r| r.as_ref().map(|r| r["id"].clone())).collect();
+        assert_eq!(found, vec![Some("infer-b".into()), Some("infer-a".into()), None]);
+    }
+}
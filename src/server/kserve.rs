@@ -0,0 +1,698 @@
+//! KServe v2 inference protocol messages and their mapping onto jobs
+//! (feature `grpc`).
+//!
+//! The messages follow `grpc_service.proto` of the KServe open inference
+//! protocol (package `inference`, also used by Triton), limited to the
+//! health, metadata and `ModelInfer` calls. Like [`crate::proto`] they are
+//! derived with prost directly, so builds need no `protoc`.
+//!
+//! A `ModelInfer` request is split along the batch axis of its inputs into
+//! one job per sample, which go through the regular dispatcher, batcher and
+//! workers. The input named like the first `model.input_names` entry becomes
+//! the job tensor, further inputs are matched with `[[input.extra]]`.
+//! Request parameters become job metadata. The result fields are returned as
+//! output tensors, stacked over the samples: the raw model output (the full
+//! stored tensor with `[redis] chunk_elements`) under the first
+//! `model.output_names` entry, numeric fields as `FP32`, flags as `BOOL` and
+//! everything else as `BYTES` (strings, or JSON for structured fields).
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+
+use half::{bf16, f16};
+use ndarray::{ArrayD, Axis, IxDyn};
+use serde_json::Value;
+
+use crate::error::OmniError;
+use crate::trace_context;
+use crate::types::{Config, GrpcCfg, Job, JobMeta};
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ServerLiveRequest {}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ServerLiveResponse {
+    #[prost(bool, tag = "1")]
+    pub live: bool,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ServerReadyRequest {}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ServerReadyResponse {
+    #[prost(bool, tag = "1")]
+    pub ready: bool,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ModelReadyRequest {
+    #[prost(string, tag = "1")]
+    pub name: String,
+    #[prost(string, tag = "2")]
+    pub version: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ModelReadyResponse {
+    #[prost(bool, tag = "1")]
+    pub ready: bool,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ServerMetadataRequest {}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ServerMetadataResponse {
+    #[prost(string, tag = "1")]
+    pub name: String,
+    #[prost(string, tag = "2")]
+    pub version: String,
+    #[prost(string, repeated, tag = "3")]
+    pub extensions: Vec<String>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ModelMetadataRequest {
+    #[prost(string, tag = "1")]
+    pub name: String,
+    #[prost(string, tag = "2")]
+    pub version: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct TensorMetadata {
+    #[prost(string, tag = "1")]
+    pub name: String,
+    #[prost(string, tag = "2")]
+    pub datatype: String,
+    /// `-1` for dynamic dimensions.
+    #[prost(int64, repeated, tag = "3")]
+    pub shape: Vec<i64>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ModelMetadataResponse {
+    #[prost(string, tag = "1")]
+    pub name: String,
+    #[prost(string, repeated, tag = "2")]
+    pub versions: Vec<String>,
+    #[prost(string, tag = "3")]
+    pub platform: String,
+    #[prost(message, repeated, tag = "4")]
+    pub inputs: Vec<TensorMetadata>,
+    #[prost(message, repeated, tag = "5")]
+    pub outputs: Vec<TensorMetadata>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct InferParameter {
+    #[prost(oneof = "infer_parameter::ParameterChoice", tags = "1, 2, 3, 4, 5")]
+    pub parameter_choice: Option<infer_parameter::ParameterChoice>,
+}
+
+pub mod infer_parameter {
+    // Variantennamen wie in grpc_service.proto
+    #[allow(clippy::enum_variant_names)]
+    #[derive(Clone, PartialEq, prost::Oneof)]
+    pub enum ParameterChoice {
+        #[prost(bool, tag = "1")]
+        BoolParam(bool),
+        #[prost(int64, tag = "2")]
+        Int64Param(i64),
+        #[prost(string, tag = "3")]
+        StringParam(String),
+        #[prost(double, tag = "4")]
+        DoubleParam(f64),
+        #[prost(uint64, tag = "5")]
+        Uint64Param(u64),
+    }
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct InferTensorContents {
+    #[prost(bool, repeated, tag = "1")]
+    pub bool_contents: Vec<bool>,
+    #[prost(int32, repeated, tag = "2")]
+    pub int_contents: Vec<i32>,
+    #[prost(int64, repeated, tag = "3")]
+    pub int64_contents: Vec<i64>,
+    #[prost(uint32, repeated, tag = "4")]
+    pub uint_contents: Vec<u32>,
+    #[prost(uint64, repeated, tag = "5")]
+    pub uint64_contents: Vec<u64>,
+    #[prost(float, repeated, tag = "6")]
+    pub fp32_contents: Vec<f32>,
+    #[prost(double, repeated, tag = "7")]
+    pub fp64_contents: Vec<f64>,
+    #[prost(bytes = "vec", repeated, tag = "8")]
+    pub bytes_contents: Vec<Vec<u8>>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct InferInputTensor {
+    #[prost(string, tag = "1")]
+    pub name: String,
+    #[prost(string, tag = "2")]
+    pub datatype: String,
+    #[prost(int64, repeated, tag = "3")]
+    pub shape: Vec<i64>,
+    #[prost(map = "string, message", tag = "4")]
+    pub parameters: HashMap<String, InferParameter>,
+    #[prost(message, optional, tag = "5")]
+    pub contents: Option<InferTensorContents>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct InferRequestedOutputTensor {
+    #[prost(string, tag = "1")]
+    pub name: String,
+    #[prost(map = "string, message", tag = "2")]
+    pub parameters: HashMap<String, InferParameter>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ModelInferRequest {
+    #[prost(string, tag = "1")]
+    pub model_name: String,
+    #[prost(string, tag = "2")]
+    pub model_version: String,
+    #[prost(string, tag = "3")]
+    pub id: String,
+    #[prost(map = "string, message", tag = "4")]
+    pub parameters: HashMap<String, InferParameter>,
+    #[prost(message, repeated, tag = "5")]
+    pub inputs: Vec<InferInputTensor>,
+    #[prost(message, repeated, tag = "6")]
+    pub outputs: Vec<InferRequestedOutputTensor>,
+    /// Little-endian input data, one entry per input (instead of `contents`).
+    #[prost(bytes = "vec", repeated, tag = "7")]
+    pub raw_input_contents: Vec<Vec<u8>>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct InferOutputTensor {
+    #[prost(string, tag = "1")]
+    pub name: String,
+    #[prost(string, tag = "2")]
+    pub datatype: String,
+    #[prost(int64, repeated, tag = "3")]
+    pub shape: Vec<i64>,
+    #[prost(map = "string, message", tag = "4")]
+    pub parameters: HashMap<String, InferParameter>,
+    #[prost(message, optional, tag = "5")]
+    pub contents: Option<InferTensorContents>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct ModelInferResponse {
+    #[prost(string, tag = "1")]
+    pub model_name: String,
+    #[prost(string, tag = "2")]
+    pub model_version: String,
+    #[prost(string, tag = "3")]
+    pub id: String,
+    #[prost(map = "string, message", tag = "4")]
+    pub parameters: HashMap<String, InferParameter>,
+    #[prost(message, repeated, tag = "5")]
+    pub outputs: Vec<InferOutputTensor>,
+    #[prost(bytes = "vec", repeated, tag = "6")]
+    pub raw_output_contents: Vec<Vec<u8>>,
+}
+
+/// Result fields that are not returned as output tensors.
+const SKIPPED_FIELDS: &[&str] =
+    &["schema_version", "id", "timestamp", "meta", "traceparent", "tracestate", "tensor", "shape", "data"];
+
+/// Result of one sample: the stored payload and, with `[redis]
+/// chunk_elements`, the full output tensor.
+pub struct Sample {
+    pub result: Arc<Value>,
+    pub tensor: Option<ArrayD<f32>>,
+}
+
+/// Error result of a job, passed on with its stored message.
+#[derive(Debug, Clone, PartialEq)]
+pub struct JobError {
+    pub code: String,
+    pub message: String,
+}
+
+impl JobError {
+    /// The error of a result payload, if it is one.
+    pub fn from_result(result: &Value) -> Option<Self> {
+        let message = result.get("error")?.as_str()?.to_string();
+        let code = result["code"].as_str().unwrap_or("INTERNAL").to_string();
+        Some(Self { code, message })
+    }
+
+    /// gRPC status code, as [`OmniError::grpc_code`] for the same code.
+    pub fn grpc_code(&self) -> i32 {
+        match self.code.as_str() {
+            "INVALID_INPUT" | "INVALID_SHAPE" => 3,
+            "TIMEOUT" => 4,
+            "NO_WORKER" | "SHUTTING_DOWN" | "STORE_UNAVAILABLE" => 14,
+            _ => 13,
+        }
+    }
+}
+
+/// The served model as KServe clients see it.
+pub struct KServeModel {
+    name: String,
+    cfg: Config,
+}
+
+impl KServeModel {
+    pub fn new(cfg: &Config, grpc: &GrpcCfg) -> Self {
+        let name = grpc.model_name.clone().unwrap_or_else(|| {
+            let stem = Path::new(&cfg.model.model_path).file_stem();
+            stem.map(|s| s.to_string_lossy().into_owned()).unwrap_or_else(|| "model".to_string())
+        });
+        Self { name, cfg: cfg.clone() }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Rejects requests for another model.
+    pub fn check_name(&self, name: &str) -> Result<(), OmniError> {
+        if name == self.name {
+            Ok(())
+        } else {
+            Err(OmniError::ModelNotFound(name.to_string()))
+        }
+    }
+
+    /// Inputs and outputs from `[model]`; the batch axis and `0` dimensions
+    /// are dynamic (`-1`).
+    pub fn metadata(&self) -> ModelMetadataResponse {
+        let tensors = |names: &[String], shapes: &[Vec<usize>]| -> Vec<TensorMetadata> {
+            names
+                .iter()
+                .zip(shapes)
+                .map(|(name, shape)| TensorMetadata {
+                    name: name.clone(),
+                    datatype: "FP32".to_string(),
+                    shape: shape
+                        .iter()
+                        .enumerate()
+                        .map(|(axis, &d)| if axis == 0 || d == 0 { -1 } else { d as i64 })
+                        .collect(),
+                })
+                .collect()
+        };
+        let model = &self.cfg.model;
+        let platform = match model.backend.as_str() {
+            "onnx" => "onnxruntime_onnx",
+            "tensorrt" => "tensorrt_plan",
+            "torch" => "pytorch_libtorch",
+            "tensorflow" => "tensorflow_savedmodel",
+            other => other,
+        };
+        ModelMetadataResponse {
+            name: self.name.clone(),
+            versions: Vec::new(),
+            platform: platform.to_string(),
+            inputs: tensors(&model.input_names, &model.input_shapes),
+            outputs: tensors(&model.output_names, &model.output_shapes),
+        }
+    }
+
+    /// Splits a request into one job per sample. Job ids are the request
+    /// id (or `grpc-{random}`), with `-{index}` for batches of several.
+    pub fn jobs(&self, req: &ModelInferRequest, traceparent: Option<&str>, tracestate: Option<&str>) -> Result<Vec<Job>, OmniError> {
+        self.check_name(&req.model_name)?;
+        let invalid = |message: String| OmniError::InvalidInput(message);
+        if req.inputs.is_empty() {
+            return Err(invalid("Request ohne Inputs".to_string()));
+        }
+        if !req.raw_input_contents.is_empty() && req.raw_input_contents.len() != req.inputs.len() {
+            return Err(invalid(format!(
+                "{} raw_input_contents für {} Inputs",
+                req.raw_input_contents.len(),
+                req.inputs.len()
+            )));
+        }
+
+        let primary_name = self.cfg.model.input_names.first();
+        let primary = req
+            .inputs
+            .iter()
+            .position(|input| Some(&input.name) == primary_name)
+            .or((req.inputs.len() == 1).then_some(0))
+            .ok_or_else(|| invalid(format!("Input '{}' fehlt", primary_name.map_or("", |n| n.as_str()))))?;
+
+        let mut tensors = Vec::with_capacity(req.inputs.len());
+        for (i, input) in req.inputs.iter().enumerate() {
+            if i != primary && !self.cfg.input.extra.iter().any(|e| e.name == input.name) {
+                return Err(invalid(format!("Input '{}' ist nicht in [[input.extra]] konfiguriert", input.name)));
+            }
+            let tensor = input_tensor(input, req.raw_input_contents.get(i).map(Vec::as_slice)).map_err(invalid)?;
+            tensors.push((input.name.clone(), tensor));
+        }
+        let batch = tensors[primary].1.shape()[0];
+        if batch == 0 || tensors.iter().any(|(_, t)| t.shape()[0] != batch) {
+            return Err(invalid("Inputs brauchen dieselbe Batch-Größe > 0 auf Achse 0".to_string()));
+        }
+
+        let mut meta: JobMeta = req.parameters.iter().filter_map(|(k, p)| Some((k.clone(), parameter(p)?))).collect();
+        trace_context::inject(&mut meta, traceparent, tracestate);
+        let base = if req.id.is_empty() { format!("grpc-{:016x}", rand::random::<u64>()) } else { req.id.clone() };
+        Ok((0..batch)
+            .map(|n| {
+                let mut job = Job {
+                    id: if batch == 1 { base.clone() } else { format!("{}-{}", base, n) },
+                    meta: meta.clone(),
+                    ..Default::default()
+                };
+                for (i, (name, tensor)) in tensors.iter().enumerate() {
+                    let sample = tensor.index_axis(Axis(0), n).to_owned();
+                    if i == primary {
+                        job.tensor = sample;
+                    } else {
+                        job.inputs.insert(name.clone(), sample);
+                    }
+                }
+                job
+            })
+            .collect())
+    }
+
+    /// Builds the response from the samples' results, in request order.
+    /// With requested `outputs` only those are returned.
+    pub fn response(&self, req: &ModelInferRequest, samples: &[Sample]) -> Result<ModelInferResponse, OmniError> {
+        let mut outputs = Vec::new();
+        if let Some(output) = self.raw_output(samples)? {
+            outputs.push(output);
+        }
+        let mut fields: Vec<&String> = samples
+            .iter()
+            .flat_map(|s| s.result.as_object().into_iter().flat_map(|o| o.keys()))
+            .filter(|k| !SKIPPED_FIELDS.contains(&k.as_str()))
+            .collect();
+        fields.sort();
+        fields.dedup();
+        for field in fields {
+            let values: Vec<&Value> = samples.iter().map(|s| s.result.get(field.as_str()).unwrap_or(&Value::Null)).collect();
+            outputs.push(field_tensor(field, &values));
+        }
+
+        if !req.outputs.is_empty() {
+            let mut selected = Vec::with_capacity(req.outputs.len());
+            for requested in &req.outputs {
+                let index = outputs
+                    .iter()
+                    .position(|o| o.name == requested.name)
+                    .ok_or_else(|| OmniError::InvalidInput(format!("Output '{}' unbekannt", requested.name)))?;
+                selected.push(outputs[index].clone());
+            }
+            outputs = selected;
+        }
+        Ok(ModelInferResponse {
+            model_name: self.name.clone(),
+            model_version: req.model_version.clone(),
+            id: req.id.clone(),
+            outputs,
+            ..Default::default()
+        })
+    }
+
+    /// The model output as `[N, ...]`: the full stored tensor or `shape` and
+    /// `data` of the result. `None` if a task section replaced the raw
+    /// output.
+    fn raw_output(&self, samples: &[Sample]) -> Result<Option<InferOutputTensor>, OmniError> {
+        let mut arrays = Vec::with_capacity(samples.len());
+        for sample in samples {
+            let array = match (&sample.tensor, sample.result.get("data")) {
+                (Some(tensor), _) => tensor.clone(),
+                (None, Some(data)) => {
+                    let values: Vec<f32> = serde_json::from_value(data.clone()).map_err(|e| OmniError::Internal(e.to_string()))?;
+                    let shape: Vec<usize> = serde_json::from_value(sample.result["shape"].clone()).unwrap_or_default();
+                    // Die JSON-Vorschau ist gekürzt: dann flach zurückgeben
+                    let shape = if shape.iter().product::<usize>() == values.len() { shape } else { vec![values.len()] };
+                    ArrayD::from_shape_vec(IxDyn(&shape), values).map_err(|e| OmniError::Internal(e.to_string()))?
+                }
+                (None, None) => return Ok(None),
+            };
+            arrays.push(array);
+        }
+        let views: Vec<_> = arrays.iter().map(|a| a.view()).collect();
+        let stacked = ndarray::stack(Axis(0), &views)
+            .map_err(|_| OmniError::Internal("Outputs der Samples haben unterschiedliche Shapes".to_string()))?;
+        let name = self.cfg.model.output_names.first().cloned().unwrap_or_else(|| "output".to_string());
+        Ok(Some(InferOutputTensor {
+            name,
+            datatype: "FP32".to_string(),
+            shape: stacked.shape().iter().map(|&d| d as i64).collect(),
+            contents: Some(InferTensorContents { fp32_contents: stacked.iter().copied().collect(), ..Default::default() }),
+            ..Default::default()
+        }))
+    }
+}
+
+/// Decodes an input tensor from `contents` or its raw little-endian bytes.
+fn input_tensor(input: &InferInputTensor, raw: Option<&[u8]>) -> Result<ArrayD<f32>, String> {
+    let shape: Vec<usize> = input
+        .shape
+        .iter()
+        .map(|&d| usize::try_from(d).map_err(|_| format!("Input '{}': ungültige Shape {:?}", input.name, input.shape)))
+        .collect::<Result<_, _>>()?;
+    if shape.is_empty() {
+        return Err(format!("Input '{}' ohne Batch-Achse", input.name));
+    }
+    let expected = shape
+        .iter()
+        .try_fold(1usize, |n, &d| n.checked_mul(d))
+        .ok_or_else(|| format!("Input '{}': Shape {:?} ist zu groß", input.name, shape))?;
+    let contents = input.contents.clone().unwrap_or_default();
+    let le = |size| raw_values(input, raw, size);
+    let values: Vec<f32> = match (input.datatype.as_str(), raw.is_some()) {
+        ("FP32", true) => le(4)?.map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]])).collect(),
+        ("FP32", false) => contents.fp32_contents,
+        ("FP64", true) => le(8)?.map(|b| f64::from_le_bytes(b.try_into().unwrap()) as f32).collect(),
+        ("FP64", false) => contents.fp64_contents.iter().map(|&v| v as f32).collect(),
+        ("FP16", _) => le(2)?.map(|b| f16::from_le_bytes([b[0], b[1]]).to_f32()).collect(),
+        ("BF16", _) => le(2)?.map(|b| bf16::from_le_bytes([b[0], b[1]]).to_f32()).collect(),
+        ("UINT8", true) => le(1)?.map(|b| b[0] as f32).collect(),
+        ("INT8", true) => le(1)?.map(|b| b[0] as i8 as f32).collect(),
+        ("INT16", true) => le(2)?.map(|b| i16::from_le_bytes([b[0], b[1]]) as f32).collect(),
+        ("UINT16", true) => le(2)?.map(|b| u16::from_le_bytes([b[0], b[1]]) as f32).collect(),
+        ("INT32", true) => le(4)?.map(|b| i32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f32).collect(),
+        ("INT64", true) => le(8)?.map(|b| i64::from_le_bytes(b.try_into().unwrap()) as f32).collect(),
+        ("INT8" | "INT16" | "INT32", false) => contents.int_contents.iter().map(|&v| v as f32).collect(),
+        ("UINT8" | "UINT16" | "UINT32", false) => contents.uint_contents.iter().map(|&v| v as f32).collect(),
+        ("INT64", false) => contents.int64_contents.iter().map(|&v| v as f32).collect(),
+        ("BOOL", true) => le(1)?.map(|b| f32::from(b[0] != 0)).collect(),
+        ("BOOL", false) => contents.bool_contents.iter().map(|&v| f32::from(v)).collect(),
+        (other, _) => return Err(format!("Input '{}': Datentyp {} nicht unterstützt", input.name, other)),
+    };
+    if values.len() != expected {
+        return Err(format!("Input '{}': {} Werte für Shape {:?}", input.name, values.len(), shape));
+    }
+    ArrayD::from_shape_vec(IxDyn(&shape), values).map_err(|e| format!("Input '{}': {}", input.name, e))
+}
+
+/// Raw input bytes split into values of `size` bytes.
+fn raw_values<'a>(input: &InferInputTensor, raw: Option<&'a [u8]>, size: usize) -> Result<std::slice::ChunksExact<'a, u8>, String> {
+    let raw = raw.ok_or_else(|| format!("Input '{}': {} nur als raw_input_contents", input.name, input.datatype))?;
+    if raw.len() % size != 0 {
+        return Err(format!("Input '{}': {} Bytes sind kein Vielfaches von {}", input.name, raw.len(), size));
+    }
+    Ok(raw.chunks_exact(size))
+}
+
+/// Job metadata value of a request parameter.
+fn parameter(p: &InferParameter) -> Option<Value> {
+    use infer_parameter::ParameterChoice::*;
+    Some(match p.parameter_choice.clone()? {
+        BoolParam(v) => v.into(),
+        Int64Param(v) => v.into(),
+        StringParam(v) => v.into(),
+        DoubleParam(v) => v.into(),
+        Uint64Param(v) => v.into(),
+    })
+}
+
+/// Nested number arrays as shape and row-major values; `None` for ragged
+/// or non-numeric values.
+fn numeric(value: &Value) -> Option<(Vec<usize>, Vec<f32>)> {
+    match value {
+        Value::Number(n) => Some((Vec::new(), vec![n.as_f64()? as f32])),
+        Value::Array(items) => {
+            let (mut inner, mut data) = (None, Vec::new());
+            for item in items {
+                let (shape, values) = numeric(item)?;
+                if *inner.get_or_insert_with(|| shape.clone()) != shape {
+                    return None;
+                }
+                data.extend(values);
+            }
+            let mut shape = vec![items.len()];
+            shape.extend(inner.unwrap_or_default());
+            Some((shape, data))
+        }
+        _ => None,
+    }
+}
+
+/// One result field over all samples as `[N, ...]`.
+fn field_tensor(name: &str, values: &[&Value]) -> InferOutputTensor {
+    let batch = values.len() as i64;
+    let numeric: Option<Vec<_>> = values.iter().map(|v| numeric(v)).collect();
+    let same_shape = |n: &Vec<(Vec<usize>, Vec<f32>)>| n.windows(2).all(|w| w[0].0 == w[1].0);
+    let (datatype, shape, contents) = match numeric.filter(same_shape) {
+        Some(numeric) => {
+            let mut shape = vec![batch];
+            shape.extend(numeric.first().map_or(&[][..], |n| &n.0).iter().map(|&d| d as i64));
+            let fp32_contents = numeric.into_iter().flat_map(|(_, v)| v).collect();
+            ("FP32", shape, InferTensorContents { fp32_contents, ..Default::default() })
+        }
+        None if values.iter().all(|v| v.is_boolean()) => {
+            let bool_contents = values.iter().map(|v| v.as_bool().unwrap_or(false)).collect();
+            ("BOOL", vec![batch], InferTensorContents { bool_contents, ..Default::default() })
+        }
+        None => {
+            let bytes_contents = values
+                .iter()
+                .map(|v| match v {
+                    Value::String(s) => s.clone().into_bytes(),
+                    other => other.to_string().into_bytes(),
+                })
+                .collect();
+            ("BYTES", vec![batch], InferTensorContents { bytes_contents, ..Default::default() })
+        }
+    };
+    InferOutputTensor { name: name.to_string(), datatype: datatype.to_string(), shape, contents: Some(contents), ..Default::default() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn model(extra: &str) -> KServeModel {
+        let raw = format!(
+            r#"
+            [model]
+            backend = "onnx"
+            device = "cpu"
+            model_path = "models/resnet50.onnx"
+            input_names = ["pixels", "mask"]
+            input_shapes = [[0, 3, 2, 2], [0, 4]]
+            output_names = ["logits"]
+            output_shapes = [[0, 0]]
+            [queue]
+            max_batch = 8
+            max_wait_ms = 5
+            [redis]
+            url = "redis://127.0.0.1/"
+            out_prefix = "results"
+            [input]
+            batch = 8
+            channels = 3
+            height = 2
+            width = 2
+            dtype = "f32"
+            {}
+            "#,
+            extra
+        );
+        KServeModel::new(&toml::from_str(&raw).unwrap(), &toml::from_str("").unwrap())
+    }
+
+    fn input(name: &str, datatype: &str, shape: &[i64], fp32: Vec<f32>) -> InferInputTensor {
+        InferInputTensor {
+            name: name.to_string(),
+            datatype: datatype.to_string(),
+            shape: shape.to_vec(),
+            contents: Some(InferTensorContents { fp32_contents: fp32, ..Default::default() }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_metadata_and_name() {
+        let model = model("");
+        assert_eq!(model.name(), "resnet50");
+        assert_eq!(model.check_name("other").unwrap_err(), OmniError::ModelNotFound("other".to_string()));
+        let metadata = model.metadata();
+        assert_eq!(metadata.platform, "onnxruntime_onnx");
+        assert_eq!(metadata.inputs[0].shape, vec![-1, 3, 2, 2]);
+        assert_eq!(metadata.outputs[0].name, "logits");
+    }
+
+    #[test]
+    fn test_request_to_jobs() {
+        let model = model("[[input.extra]]\nname = \"mask\"\nshape = [4]");
+        let mut req = ModelInferRequest {
+            model_name: "resnet50".to_string(),
+            id: "req-1".to_string(),
+            inputs: vec![
+                input("pixels", "FP32", &[2, 3, 2, 2], (0..24).map(|v| v as f32).collect()),
+                InferInputTensor { name: "mask".to_string(), datatype: "UINT8".to_string(), shape: vec![2, 4], ..Default::default() },
+            ],
+            ..Default::default()
+        };
+        req.raw_input_contents = vec![(0..24).flat_map(|v: u32| (v as f32).to_le_bytes()).collect(), vec![1; 8]];
+        req.parameters.insert("priority".to_string(), InferParameter { parameter_choice: Some(infer_parameter::ParameterChoice::Int64Param(2)) });
+
+        let traceparent = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+        let jobs = model.jobs(&req, Some(traceparent), None).unwrap();
+        assert_eq!(jobs.iter().map(|j| j.id.as_str()).collect::<Vec<_>>(), vec!["req-1-0", "req-1-1"]);
+        assert_eq!(jobs[1].tensor.shape(), &[3, 2, 2]);
+        assert_eq!(jobs[1].tensor[[0, 0, 0]], 12.0);
+        assert_eq!(jobs[0].inputs["mask"].shape(), &[4]);
+        assert_eq!(jobs[0].meta["priority"], 2);
+        assert_eq!(jobs[0].meta["traceparent"], traceparent);
+
+        // Unbekannter Input, falsche Länge, fremdes Modell
+        let mut bad = req.clone();
+        bad.inputs[1].name = "depth".to_string();
+        assert_eq!(model.jobs(&bad, None, None).unwrap_err().code(), "INVALID_INPUT");
+        let mut bad = req.clone();
+        bad.raw_input_contents[0].truncate(8);
+        assert_eq!(model.jobs(&bad, None, None).unwrap_err().code(), "INVALID_INPUT");
+        // Überlaufende Shape (Produkt 2^65 wäre 0 ohne Prüfung)
+        let mut bad = req.clone();
+        bad.inputs[0].shape = vec![2, 1 << 62, 4];
+        bad.raw_input_contents[0].clear();
+        assert_eq!(model.jobs(&bad, None, None).unwrap_err().code(), "INVALID_INPUT");
+        let mut bad = req;
+        bad.model_name = "bert".to_string();
+        assert_eq!(model.jobs(&bad, None, None).unwrap_err().code(), "MODEL_NOT_FOUND");
+    }
+
+    #[test]
+    fn test_results_to_response() {
+        let model = model("");
+        let sample = |result: Value| Sample { result: Arc::new(result), tensor: None };
+        let samples = vec![
+            sample(json!({"id": "r-0", "shape": [2], "data": [0.1, 0.9], "label": "dog", "top_k": [{"index": 1}], "ok": true})),
+            sample(json!({"id": "r-1", "shape": [2], "data": [0.8, 0.2], "label": "cat", "top_k": [{"index": 0}], "ok": false})),
+        ];
+        let req = ModelInferRequest { model_name: "resnet50".to_string(), id: "r".to_string(), ..Default::default() };
+        let response = model.response(&req, &samples).unwrap();
+        let names: Vec<_> = response.outputs.iter().map(|o| o.name.as_str()).collect();
+        assert_eq!(names, vec!["logits", "label", "ok", "top_k"]);
+        let logits = &response.outputs[0];
+        assert_eq!(logits.shape, vec![2, 2]);
+        assert_eq!(logits.contents.as_ref().unwrap().fp32_contents, vec![0.1, 0.9, 0.8, 0.2]);
+        assert_eq!(response.outputs[1].datatype, "BYTES");
+        assert_eq!(response.outputs[1].contents.as_ref().unwrap().bytes_contents[1], b"cat");
+        assert_eq!(response.outputs[2].contents.as_ref().unwrap().bool_contents, vec![true, false]);
+        assert_eq!(response.outputs[3].contents.as_ref().unwrap().bytes_contents[0], br#"[{"index":1}]"#);
+
+        let mut req = req;
+        req.outputs = vec![InferRequestedOutputTensor { name: "label".to_string(), ..Default::default() }];
+        assert_eq!(model.response(&req, &samples).unwrap().outputs.len(), 1);
+        req.outputs[0].name = "missing".to_string();
+        assert!(model.response(&req, &samples).is_err());
+
+        let failed = JobError::from_result(&json!({"id": "r-0", "error": "kein Worker verfügbar", "code": "NO_WORKER"})).unwrap();
+        assert_eq!(failed.grpc_code(), 14);
+        assert!(JobError::from_result(&samples[0].result).is_none());
+    }
+}
@@ -0,0 +1,72 @@
+//! Client-facing API servers (`[server.*]` sections): the HTTP API and,
+//! with feature `grpc`, the KServe v2 gRPC API.
+//!
+//! With feature `websocket`, the HTTP API also streams jobs and results over
+//! a WebSocket (`[server.http.stream]`).
+
+pub mod blobs;
+pub mod http;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+pub mod infer;
+#[cfg(feature = "grpc")]
+pub mod kserve;
+#[cfg(feature = "websocket")]
+pub mod stream;
+pub mod upload;
+
+use std::sync::Arc;
+
+use anyhow::Result;
+use tokio::sync::mpsc;
+
+use crate::storage::redis_store::RedisStorage;
+use crate::text::TextEncoder;
+use crate::types::{Config, Job};
+
+/// Starts the configured servers in the background.
+///
+/// Returns `true` if a server accepts jobs (`[server.http.infer]`,
+/// `[server.http.upload]`, `[server.http.stream]` or `[server.grpc]`); like a
+/// source it then keeps the runtime running. `text` (from `[text]`) lets the
+/// HTTP API accept text jobs.
+pub fn spawn_servers(cfg: &Config, store: &RedisStorage, tx: &mpsc::Sender<Job>, text: Option<Arc<TextEncoder>>) -> Result<bool> {
+    let grpc = spawn_grpc(cfg, store, tx)?;
+    let Some(http_cfg) = cfg.server.http.clone() else { return Ok(grpc) };
+    #[cfg(not(feature = "websocket"))]
+    anyhow::ensure!(
+        http_cfg.stream.is_none(),
+        "[server.http.stream] konfiguriert, aber Feature 'websocket' nicht aktiviert"
+    );
+    let accepts_jobs = http_cfg.infer.is_some() || http_cfg.upload.is_some() || http_cfg.stream.is_some();
+    // Ohne Job-Routen hält der Server keinen Sender, sonst endete die Runtime nie
+    let tx = if accepts_jobs { tx.clone() } else { mpsc::channel(1).0 };
+    let (store, model) = (store.clone(), cfg.model.clone());
+    tokio::spawn(async move {
+        if let Err(e) = http::serve(http_cfg, &model, store, tx, text).await {
+            tracing::error!("HTTP-Server fehlgeschlagen: {:?}", e);
+        }
+    });
+    Ok(grpc || accepts_jobs)
+}
+
+#[cfg(feature = "grpc")]
+fn spawn_grpc(cfg: &Config, store: &RedisStorage, tx: &mpsc::Sender<Job>) -> Result<bool> {
+    let Some(grpc_cfg) = cfg.server.grpc.clone() else { return Ok(false) };
+    let (cfg, store, tx) = (cfg.clone(), store.clone(), tx.clone());
+    tokio::spawn(async move {
+        if let Err(e) = grpc::serve(grpc_cfg, &cfg, store, tx).await {
+            tracing::error!("gRPC-Server fehlgeschlagen: {:?}", e);
+        }
+    });
+    Ok(true)
+}
+
+#[cfg(not(feature = "grpc"))]
+fn spawn_grpc(cfg: &Config, _store: &RedisStorage, _tx: &mpsc::Sender<Job>) -> Result<bool> {
+    anyhow::ensure!(
+        cfg.server.grpc.is_none(),
+        "[server.grpc] konfiguriert, aber Feature 'grpc' nicht aktiviert"
+    );
+    Ok(false)
+}
@@ -0,0 +1,182 @@
+//! Streaming inference over WebSocket (`[server.http.stream]`, feature
+//! `websocket`).
+//!
+//! `GET /v1/stream` upgrades to a WebSocket on which a client sends jobs and
+//! receives their results, without the round trip through the result store.
+//! A binary frame is one input sample as `.npy` (without batch axis), a text
+//! frame a job in the JSON wire format (with `[text]` also a `text` job).
+//! Jobs enter the dispatcher channel
+//! like those of any other source.
+//!
+//! Each result is a text frame with the result payload. For tensor results
+//! `tensor` holds the output shape and a binary `.npy` frame with the full
+//! output follows. Results can arrive out of order; binary jobs are named
+//! `ws-{connection}-{n}`. Rejected jobs are answered with `id`, `error` and
+//! `code`, e.g. `QUEUE_FULL` while the input queue or the `max_in_flight`
+//! jobs of the connection are used up. Results of jobs still running when
+//! the connection closes are discarded.
+
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::extract::State;
+use axum::response::Response;
+use axum::routing::get;
+use axum::Router;
+use serde_json::{json, Value};
+use tokio::sync::mpsc;
+use tracing::debug;
+
+use crate::error::OmniError;
+use crate::results::{self, Direct};
+use crate::text::TextEncoder;
+use crate::types::{Job, StreamCfg};
+
+struct Stream {
+    cfg: StreamCfg,
+    tx: mpsc::Sender<Job>,
+    text: Option<Arc<TextEncoder>>,
+}
+
+/// Routes of the streaming endpoint; jobs are enqueued into `tx`, `text`
+/// jobs tokenized with `text`.
+pub fn router(cfg: StreamCfg, tx: mpsc::Sender<Job>, text: Option<Arc<TextEncoder>>) -> Router {
+    Router::new().route("/v1/stream", get(upgrade)).with_state(Arc::new(Stream { cfg, tx, text }))
+}
+
+async fn upgrade(State(stream): State<Arc<Stream>>, ws: WebSocketUpgrade) -> Response {
+    ws.max_message_size(stream.cfg.max_frame_bytes).on_upgrade(move |socket| run(socket, stream))
+}
+
+/// Serves one connection until the client closes it.
+async fn run(mut socket: WebSocket, stream: Arc<Stream>) {
+    let connection = rand::random::<u32>();
+    let mut seq = 0u64;
+    let mut next_id = || {
+        seq += 1;
+        format!("ws-{:08x}-{}", connection, seq)
+    };
+    let (results_tx, mut results_rx) = mpsc::unbounded_channel();
+    let mut pending = HashSet::new();
+    debug!("WebSocket-Verbindung {:08x} geöffnet", connection);
+
+    'connection: loop {
+        let frames = tokio::select! {
+            message = socket.recv() => {
+                let job = match message {
+                    Some(Ok(Message::Binary(bytes))) => crate::npy::parse(&bytes)
+                        .map(|tensor| Job { id: next_id(), tensor, ..Default::default() })
+                        .map_err(|e| OmniError::InvalidInput(format!("{:#}", e))),
+                    Some(Ok(Message::Text(text))) => super::infer::parse(text.as_bytes(), &mut next_id, stream.text.as_deref()),
+                    // Ping/Pong beantwortet axum selbst
+                    Some(Ok(_)) => continue,
+                    None | Some(Err(_)) => break,
+                };
+                match job {
+                    Ok(job) => {
+                        let id = job.id.clone();
+                        match submit(&stream, job, &results_tx, &mut pending) {
+                            Ok(()) => continue,
+                            Err(e) => vec![rejected(Some(&id), e)],
+                        }
+                    }
+                    Err(e) => vec![rejected(None, e)],
+                }
+            }
+            Some(direct) = results_rx.recv() => {
+                if let Some(id) = direct.payload["id"].as_str() {
+                    pending.remove(id);
+                }
+                frames(direct)
+            }
+        };
+        for frame in frames {
+            if socket.send(frame).await.is_err() {
+                break 'connection;
+            }
+        }
+    }
+
+    // Ergebnisse offener Jobs verwerfen statt sie zu speichern
+    for id in &pending {
+        results::unroute(id);
+    }
+    debug!("WebSocket-Verbindung {:08x} geschlossen ({} Jobs offen)", connection, pending.len());
+}
+
+/// Routes the job's result to this connection and enqueues it.
+fn submit(
+    stream: &Stream,
+    job: Job,
+    results_tx: &mpsc::UnboundedSender<Direct>,
+    pending: &mut HashSet<String>,
+) -> Result<(), OmniError> {
+    if pending.len() >= stream.cfg.max_in_flight {
+        return Err(OmniError::QueueFull);
+    }
+    // Vor dem Einreihen registrieren, sonst kann das Ergebnis gespeichert werden
+    let id = job.id.clone();
+    results::route(&id, results_tx.clone());
+    if let Err(e) = super::infer::enqueue(&stream.tx, job) {
+        results::unroute(&id);
+        return Err(e);
+    }
+    pending.insert(id);
+    Ok(())
+}
+
+/// Frames of a result: the payload, followed by the output tensor if any.
+fn frames(direct: Direct) -> Vec<Message> {
+    let Direct { mut payload, tensor } = direct;
+    let Some(tensor) = tensor else {
+        return vec![Message::Text(payload.to_string().into())];
+    };
+    payload["tensor"] = json!({ "shape": tensor.shape(), "encoding": "npy" });
+    vec![
+        Message::Text(payload.to_string().into()),
+        Message::Binary(crate::npy::to_bytes(&tensor).into()),
+    ]
+}
+
+/// Answer to a job that was not accepted.
+fn rejected(id: Option<&str>, error: OmniError) -> Message {
+    let answer: Value = json!({
+        "id": id,
+        "error": error.to_string(),
+        "code": error.code(),
+    });
+    Message::Text(answer.to_string().into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::{ArrayD, IxDyn};
+
+    #[test]
+    fn test_submit_routes_and_limits_jobs() {
+        let (tx, mut rx) = mpsc::channel(4);
+        let stream = Stream { cfg: toml::from_str("max_in_flight = 1").unwrap(), tx, text: None };
+        let (results_tx, mut results_rx) = mpsc::unbounded_channel();
+        let mut pending = HashSet::new();
+        let job = |id: &str| Job { id: id.to_string(), tensor: ArrayD::zeros(IxDyn(&[2])), ..Default::default() };
+
+        submit(&stream, job("stream-test-1"), &results_tx, &mut pending).unwrap();
+        assert_eq!(rx.try_recv().unwrap().id, "stream-test-1");
+        let err = submit(&stream, job("stream-test-2"), &results_tx, &mut pending).unwrap_err();
+        assert_eq!(err.code(), OmniError::QueueFull.code());
+        assert!(results::take_route("stream-test-2").is_none());
+
+        // Der Worker liefert über die Route statt an den Speicher
+        let route = results::take_route("stream-test-1").unwrap();
+        let tensor = ArrayD::from_elem(IxDyn(&[3]), 1.5);
+        route.send(Direct { payload: json!({"id": "stream-test-1"}), tensor: Some(tensor.clone()) }).unwrap();
+        let frames = frames(results_rx.try_recv().unwrap());
+        assert_eq!(frames.len(), 2);
+        let Message::Text(text) = &frames[0] else { panic!("Text-Frame erwartet") };
+        assert_eq!(serde_json::from_str::<Value>(text.as_str()).unwrap()["tensor"]["shape"], json!([3]));
+        let Message::Binary(bytes) = &frames[1] else { panic!("Binär-Frame erwartet") };
+        assert_eq!(crate::npy::parse(bytes).unwrap(), tensor);
+    }
+}
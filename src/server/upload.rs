@@ -0,0 +1,264 @@
+//! Chunked upload of large inputs (`[server.http.upload]`).
+//!
+//! Volumetric scans or long audio do not fit into one request. A client
+//! announces the tensor, sends its raw little-endian f32 data in chunks
+//! (each with a CRC32, resent chunks replace earlier ones) and completes the
+//! upload; the assembled tensor is then enqueued as a regular job whose
+//! result is fetched via `/v1/results/{job_id}`. Interrupted uploads resume
+//! by asking for the missing chunks.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use ndarray::{ArrayD, IxDyn};
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+
+use crate::error::OmniError;
+use crate::types::{Job, JobMeta, UploadCfg};
+
+/// Request announcing an upload (`POST /v1/uploads`).
+#[derive(Debug, Clone, Deserialize, utoipa::ToSchema)]
+pub struct UploadRequest {
+    /// Job id; the result is stored under it.
+    pub id: String,
+    /// Shape of the input tensor.
+    pub shape: Vec<usize>,
+    /// Chunk size in bytes (multiple of 4, at most `chunk_bytes` of the
+    /// server); defaults to the server's chunk size.
+    #[serde(default)]
+    pub chunk_bytes: Option<usize>,
+    #[serde(default)]
+    #[schema(value_type = Object)]
+    pub meta: JobMeta,
+}
+
+/// Progress of an upload.
+#[derive(Debug, Clone, Serialize, utoipa::ToSchema)]
+pub struct UploadStatus {
+    pub id: String,
+    pub shape: Vec<usize>,
+    pub chunk_bytes: usize,
+    pub chunks: usize,
+    pub total_bytes: usize,
+    pub received_bytes: usize,
+    /// Indices of the chunks not received yet.
+    pub missing: Vec<usize>,
+}
+
+struct Upload {
+    shape: Vec<usize>,
+    meta: JobMeta,
+    chunk_bytes: usize,
+    total_bytes: usize,
+    chunks: Vec<Option<Vec<u8>>>,
+    touched: Instant,
+}
+
+impl Upload {
+    /// Expected length of chunk `index`; the last one may be shorter.
+    fn chunk_len(&self, index: usize) -> usize {
+        (self.total_bytes - index * self.chunk_bytes).min(self.chunk_bytes)
+    }
+
+    fn status(&self, id: &str) -> UploadStatus {
+        UploadStatus {
+            id: id.to_string(),
+            shape: self.shape.clone(),
+            chunk_bytes: self.chunk_bytes,
+            chunks: self.chunks.len(),
+            total_bytes: self.total_bytes,
+            received_bytes: self.chunks.iter().flatten().map(Vec::len).sum(),
+            missing: self.chunks.iter().enumerate().filter(|(_, c)| c.is_none()).map(|(i, _)| i).collect(),
+        }
+    }
+}
+
+/// Pending uploads of one HTTP server.
+pub struct Uploads {
+    cfg: UploadCfg,
+    tx: mpsc::Sender<Job>,
+    pending: Mutex<HashMap<String, Upload>>,
+}
+
+impl Uploads {
+    pub fn new(cfg: UploadCfg, tx: mpsc::Sender<Job>) -> Self {
+        Self { cfg, tx, pending: Mutex::new(HashMap::new()) }
+    }
+
+    /// Largest accepted chunk body.
+    pub fn max_chunk_bytes(&self) -> usize {
+        self.cfg.chunk_bytes
+    }
+
+    /// Locks the pending uploads after dropping expired ones.
+    fn pending(&self) -> std::sync::MutexGuard<'_, HashMap<String, Upload>> {
+        let ttl = Duration::from_secs(self.cfg.ttl_secs);
+        let mut pending = self.pending.lock().unwrap();
+        pending.retain(|id, upload| {
+            let alive = upload.touched.elapsed() < ttl;
+            if !alive {
+                tracing::warn!("Upload '{}' abgelaufen, verworfen", id);
+            }
+            alive
+        });
+        pending
+    }
+
+    pub fn create(&self, req: UploadRequest) -> Result<UploadStatus, OmniError> {
+        if req.id.is_empty() {
+            return Err(OmniError::MissingJobId);
+        }
+        if req.shape.is_empty() || req.shape.contains(&0) {
+            return Err(OmniError::InvalidShape(req.shape));
+        }
+        let chunk_bytes = req.chunk_bytes.unwrap_or(self.cfg.chunk_bytes);
+        if chunk_bytes == 0 || !chunk_bytes.is_multiple_of(4) || chunk_bytes > self.cfg.chunk_bytes {
+            return Err(OmniError::InvalidChunkSize { chunk_bytes, max: self.cfg.chunk_bytes });
+        }
+        let total_bytes = req.shape.iter().try_fold(4usize, |n, &d| n.checked_mul(d));
+        let Some(total_bytes) = total_bytes.filter(|&n| n <= self.cfg.max_bytes) else {
+            return Err(OmniError::UploadTooLarge { shape: req.shape, max_bytes: self.cfg.max_bytes });
+        };
+
+        let mut pending = self.pending();
+        if pending.contains_key(&req.id) {
+            return Err(OmniError::UploadExists(req.id));
+        }
+        if pending.len() >= self.cfg.max_uploads {
+            return Err(OmniError::TooManyUploads(pending.len()));
+        }
+        let upload = Upload {
+            shape: req.shape,
+            meta: req.meta,
+            chunk_bytes,
+            total_bytes,
+            chunks: vec![None; total_bytes.div_ceil(chunk_bytes)],
+            touched: Instant::now(),
+        };
+        let status = upload.status(&req.id);
+        pending.insert(req.id, upload);
+        Ok(status)
+    }
+
+    pub fn status(&self, id: &str) -> Result<UploadStatus, OmniError> {
+        self.pending().get(id).map(|u| u.status(id)).ok_or_else(|| not_found(id))
+    }
+
+    /// Stores chunk `index` after checking its length and CRC32.
+    pub fn put_chunk(&self, id: &str, index: usize, crc32: u32, bytes: &[u8]) -> Result<UploadStatus, OmniError> {
+        let mut pending = self.pending();
+        let upload = pending.get_mut(id).ok_or_else(|| not_found(id))?;
+        if index >= upload.chunks.len() {
+            return Err(OmniError::ChunkOutOfRange { index, chunks: upload.chunks.len() });
+        }
+        let expected = upload.chunk_len(index);
+        if bytes.len() != expected {
+            return Err(OmniError::ChunkLength { index, actual: bytes.len(), expected });
+        }
+        let actual = crc32fast::hash(bytes);
+        if actual != crc32 {
+            return Err(OmniError::ChecksumMismatch { index, actual, expected: crc32 });
+        }
+        upload.chunks[index] = Some(bytes.to_vec());
+        upload.touched = Instant::now();
+        Ok(upload.status(id))
+    }
+
+    pub fn abort(&self, id: &str) -> Result<(), OmniError> {
+        self.pending().remove(id).map(|_| ()).ok_or_else(|| not_found(id))
+    }
+
+    /// Assembles a complete upload and enqueues it as a job.
+    pub async fn complete(&self, id: &str) -> Result<(), OmniError> {
+        let upload = {
+            let mut pending = self.pending();
+            let upload = pending.get(id).ok_or_else(|| not_found(id))?;
+            let missing = upload.status(id).missing;
+            if !missing.is_empty() {
+                return Err(OmniError::UploadIncomplete { id: id.to_string(), missing });
+            }
+            pending.remove(id).unwrap()
+        };
+
+        let mut data = Vec::with_capacity(upload.total_bytes / 4);
+        for chunk in upload.chunks.into_iter().flatten() {
+            data.extend(chunk.chunks_exact(4).map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]])));
+        }
+        let tensor = ArrayD::from_shape_vec(IxDyn(&upload.shape), data)
+            .map_err(|e| OmniError::Internal(format!("Tensor-Daten passen nicht zur Shape: {}", e)))?;
+        let job = Job { id: id.to_string(), tensor, meta: upload.meta, ..Default::default() };
+        self.tx
+            .send(job)
+            .await
+            .map_err(|_| OmniError::ShuttingDown)
+    }
+}
+
+fn not_found(id: &str) -> OmniError {
+    OmniError::UploadNotFound(id.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::StatusCode;
+
+    fn uploads(tx: mpsc::Sender<Job>) -> Uploads {
+        let cfg: UploadCfg = toml::from_str("chunk_bytes = 16\nmax_bytes = 1024\nmax_uploads = 2").unwrap();
+        Uploads::new(cfg, tx)
+    }
+
+    fn request(id: &str, shape: Vec<usize>) -> UploadRequest {
+        UploadRequest { id: id.to_string(), shape, chunk_bytes: None, meta: JobMeta::new() }
+    }
+
+    fn bytes(values: &[f32]) -> Vec<u8> {
+        values.iter().flat_map(|v| v.to_le_bytes()).collect()
+    }
+
+    #[tokio::test]
+    async fn test_chunks_assemble_into_job() {
+        let (tx, mut rx) = mpsc::channel(1);
+        let uploads = uploads(tx);
+        let status = uploads.create(request("vol-1", vec![1, 2, 3])).unwrap();
+        assert_eq!((status.chunks, status.total_bytes), (2, 24));
+
+        let values: Vec<f32> = (0..6).map(|v| v as f32).collect();
+        let (first, last) = (bytes(&values[..4]), bytes(&values[4..]));
+        let status = uploads.put_chunk("vol-1", 1, crc32fast::hash(&last), &last).unwrap();
+        assert_eq!(status.missing, vec![0]);
+        assert_eq!(uploads.complete("vol-1").await.unwrap_err().status(), StatusCode::CONFLICT);
+
+        uploads.put_chunk("vol-1", 0, crc32fast::hash(&first), &first).unwrap();
+        uploads.complete("vol-1").await.unwrap();
+        let job = rx.recv().await.unwrap();
+        assert_eq!(job.id, "vol-1");
+        assert_eq!(job.tensor.shape(), &[1, 2, 3]);
+        assert_eq!(job.tensor.iter().copied().collect::<Vec<_>>(), values);
+        assert_eq!(uploads.status("vol-1").unwrap_err().status(), StatusCode::NOT_FOUND);
+    }
+
+    #[test]
+    fn test_rejects_bad_chunks_and_limits() {
+        let (tx, _rx) = mpsc::channel(1);
+        let uploads = uploads(tx);
+        uploads.create(request("a", vec![4])).unwrap();
+
+        let chunk = bytes(&[1.0, 2.0, 3.0, 4.0]);
+        let err = uploads.put_chunk("a", 0, crc32fast::hash(&chunk) ^ 1, &chunk).unwrap_err();
+        assert_eq!(err.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(err.code(), "CHECKSUM_MISMATCH");
+        assert_eq!(uploads.put_chunk("a", 0, 0, &chunk[..8]).unwrap_err().status(), StatusCode::BAD_REQUEST);
+        assert_eq!(uploads.put_chunk("a", 1, 0, &chunk).unwrap_err().status(), StatusCode::BAD_REQUEST);
+        assert_eq!(uploads.status("a").unwrap().missing, vec![0]);
+
+        assert_eq!(uploads.create(request("a", vec![4])).unwrap_err().status(), StatusCode::CONFLICT);
+        assert_eq!(uploads.create(request("big", vec![512])).unwrap_err().status(), StatusCode::PAYLOAD_TOO_LARGE);
+        uploads.create(request("b", vec![4])).unwrap();
+        assert_eq!(uploads.create(request("c", vec![4])).unwrap_err().status(), StatusCode::TOO_MANY_REQUESTS);
+        uploads.abort("b").unwrap();
+        uploads.create(request("c", vec![4])).unwrap();
+    }
+}
@@ -0,0 +1,346 @@
+//! Stateful sessions for sequence models.
+//!
+//! RNNs and streaming ASR models carry hidden state between requests. A
+//! `SessionManager` keeps the state tensors per session id, feeds them as
+//! extra model inputs on every call and stores the returned state outputs for
+//! the next call. Jobs of one session are always routed to the same worker
+//! (see [`sticky_worker`]), so state never has to move between devices.
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use ndarray::{ArrayD, Axis, IxDyn};
+
+use crate::engine::Engine;
+use crate::pipeline::Pipeline;
+use crate::storage::redis_store::RedisStorage;
+use crate::types::{Batch, Config, ModelCfg, SessionCfg};
+use crate::worker::WorkerJobs;
+
+/// State carried between the calls of one session.
+pub struct SessionState {
+    pub tensors: Vec<ArrayD<f32>>,
+    pub steps: u64,
+    last_used: Instant,
+}
+
+/// Picks the worker for a session so all its jobs land on the same worker.
+pub fn sticky_worker(session_id: &str, workers: usize) -> usize {
+    let mut hasher = DefaultHasher::new();
+    session_id.hash(&mut hasher);
+    (hasher.finish() % workers.max(1) as u64) as usize
+}
+
+/// Per-worker store of session states.
+pub struct SessionManager {
+    cfg: SessionCfg,
+    input_name: String,
+    output_idx: usize,
+    state_output_idx: Vec<usize>,
+    initial: Vec<ArrayD<f32>>,
+    sessions: HashMap<String, SessionState>,
+}
+
+impl SessionManager {
+    /// Resolves the state inputs/outputs against the model configuration.
+    ///
+    /// The first model input/output that is not a state tensor is the regular
+    /// data input/output. Initial states are zeros shaped like the configured
+    /// input shape (dynamic dims as 1).
+    pub fn new(cfg: &SessionCfg, model: &ModelCfg) -> Result<Self> {
+        anyhow::ensure!(
+            cfg.state_inputs.len() == cfg.state_outputs.len(),
+            "state_inputs und state_outputs haben unterschiedliche Länge"
+        );
+
+        let initial = cfg
+            .state_inputs
+            .iter()
+            .map(|name| {
+                let idx = model
+                    .input_names
+                    .iter()
+                    .position(|n| n == name)
+                    .with_context(|| format!("State-Input '{}' nicht in model.input_names", name))?;
+                let shape: Vec<usize> = model.input_shapes[idx].iter().map(|&d| d.max(1)).collect();
+                Ok(ArrayD::zeros(IxDyn(&shape)))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let state_output_idx = cfg
+            .state_outputs
+            .iter()
+            .map(|name| {
+                model
+                    .output_names
+                    .iter()
+                    .position(|n| n == name)
+                    .with_context(|| format!("State-Output '{}' nicht in model.output_names", name))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let input_name = model
+            .input_names
+            .iter()
+            .find(|n| !cfg.state_inputs.contains(n))
+            .context("Modell hat keinen Daten-Input neben den State-Inputs")?
+            .clone();
+        let output_idx = model
+            .output_names
+            .iter()
+            .position(|n| !cfg.state_outputs.contains(n))
+            .context("Modell hat keinen Daten-Output neben den State-Outputs")?;
+
+        Ok(Self {
+            cfg: cfg.clone(),
+            input_name,
+            output_idx,
+            state_output_idx,
+            initial,
+            sessions: HashMap::new(),
+        })
+    }
+
+    /// Creates a session with fresh state, replacing an existing one.
+    pub fn create(&mut self, id: &str) -> &mut SessionState {
+        let state = SessionState { tensors: self.initial.clone(), steps: 0, last_used: Instant::now() };
+        self.sessions.insert(id.to_string(), state);
+        self.sessions.get_mut(id).unwrap()
+    }
+
+    /// Closes a session and drops its state. Returns `false` if unknown.
+    pub fn close(&mut self, id: &str) -> bool {
+        self.sessions.remove(id).is_some()
+    }
+
+    /// Number of open sessions.
+    pub fn session_count(&self) -> usize {
+        self.sessions.len()
+    }
+
+    /// Runs one step of a session.
+    ///
+    /// Unknown sessions are created on first use; without a session id the
+    /// call runs with initial state and nothing is kept. Returns the data
+    /// output of the model.
+    pub fn step(&mut self, engine: &mut dyn Engine, session: Option<&str>, x: ArrayD<f32>) -> Result<(ArrayD<f32>, u64)> {
+        if let Some(id) = session.filter(|id| !self.sessions.contains_key(*id)) {
+            self.create(id);
+        }
+        let mut scratch = SessionState { tensors: self.initial.clone(), steps: 0, last_used: Instant::now() };
+        let state = match session {
+            Some(id) => self.sessions.get_mut(id).unwrap(),
+            None => &mut scratch,
+        };
+
+        let mut inputs = vec![(self.input_name.clone(), x)];
+        inputs.extend(self.cfg.state_inputs.iter().cloned().zip(state.tensors.iter().cloned()));
+        let mut outputs = engine.infer_named(inputs)?;
+        anyhow::ensure!(
+            outputs.len() > self.output_idx && self.state_output_idx.iter().all(|&i| i < outputs.len()),
+            "Modell lieferte nur {} Outputs",
+            outputs.len()
+        );
+
+        state.tensors = self.state_output_idx.iter().map(|&i| outputs[i].clone()).collect();
+        state.steps += 1;
+        state.last_used = Instant::now();
+        Ok((outputs.swap_remove(self.output_idx), state.steps))
+    }
+
+    /// Drops sessions idle longer than `ttl_secs` and, if `max_sessions` is
+    /// set, the least recently used ones above the limit. Returns the number
+    /// of evicted sessions.
+    pub fn evict(&mut self, now: Instant) -> usize {
+        let before = self.sessions.len();
+        let ttl = Duration::from_secs(self.cfg.ttl_secs);
+        self.sessions.retain(|_, s| now.saturating_duration_since(s.last_used) < ttl);
+
+        if self.cfg.max_sessions > 0 && self.sessions.len() > self.cfg.max_sessions {
+            let mut by_age: Vec<(String, Instant)> =
+                self.sessions.iter().map(|(k, s)| (k.clone(), s.last_used)).collect();
+            by_age.sort_by_key(|(_, t)| *t);
+            let excess = self.sessions.len() - self.cfg.max_sessions;
+            for (id, _) in by_age.into_iter().take(excess) {
+                self.sessions.remove(&id);
+            }
+        }
+        before - self.sessions.len()
+    }
+}
+
+/// Runs a worker for stateful models, processing one job per model call.
+///
+/// Session control via job metadata: `session_id` selects the session,
+/// `session_reset: true` starts it with fresh state and `session_close: true`
+/// drops the state after the call. Results carry `meta.session_step`.
+/// A job that fails gets an error result; the worker continues. It stops
+/// with [`crate::worker::Evicted`] once its GPU is unhealthy.
+pub async fn run_session_worker(
+    cfg: Config,
+    mut engine: Box<dyn Engine>,
+    jobs: &mut WorkerJobs<'_>,
+    store: RedisStorage,
+    pipeline: Pipeline,
+) -> Result<()> {
+    let worker = jobs.worker;
+    let check = crate::output_check::OutputCheck::from_config(&cfg, worker);
+    let session_cfg = cfg.session.as_ref().context("[session] fehlt")?;
+    let mut sessions = SessionManager::new(session_cfg, &cfg.model)?;
+
+    while let Some(mut job) = jobs.next().await? {
+        let session = job.meta.get("session_id").and_then(|s| s.as_str()).map(str::to_string);
+        let flag = |key: &str| job.meta.get(key).and_then(|v| v.as_bool()) == Some(true);
+        let (reset, close) = (flag("session_reset"), flag("session_close"));
+
+        if let (Some(id), true) = (&session, reset) {
+            sessions.create(id);
+        }
+
+        let mut prepared = false;
+        let tensor = std::mem::take(&mut job.tensor);
+        let output = (|| {
+            let x = pipeline.run_pre(tensor.insert_axis(Axis(0)))?;
+            prepared = true;
+            let (y, steps) = sessions.step(engine.as_mut(), session.as_deref(), x)?;
+            Ok((pipeline.run_post(y)?, steps))
+        })();
+        let (y, steps) = match output {
+            Ok(output) => output,
+            Err(e) => {
+                let failed = Batch {
+                    ids: vec![job.id],
+                    actual_len: 1,
+                    metas: vec![job.meta],
+                    acks: vec![job.ack],
+                    ..Default::default()
+                };
+                crate::worker::fail_batch(&store, worker, &failed, crate::worker::batch_error(&e, prepared)).await;
+                continue;
+            }
+        };
+
+        if let (Some(id), true) = (&session, close) {
+            sessions.close(id);
+        }
+        let evicted = sessions.evict(Instant::now());
+        if evicted > 0 {
+            tracing::debug!("{} Sessions abgelaufen, {} offen", evicted, sessions.session_count());
+        }
+
+        if session.is_some() {
+            job.meta.insert("session_step".to_string(), steps.into());
+        }
+        let batch = Batch {
+            ids: vec![job.id],
+            actual_len: 1,
+            metas: vec![job.meta],
+            acks: vec![job.ack],
+            ..Default::default()
+        };
+        if let Err(e) = crate::worker::write_outputs(&store, &batch, y, pipeline.output.as_ref(), check.as_ref()).await {
+            crate::worker::store_failed(&store, worker, &batch, &e).await;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::Array;
+
+    /// Akkumuliert den Input im State: h' = h + x, Output = h'.
+    struct Accumulator;
+
+    impl Engine for Accumulator {
+        fn name(&self) -> &'static str { "acc" }
+
+        fn infer_array(&mut self, input: ArrayD<f32>) -> Result<ArrayD<f32>> {
+            Ok(input)
+        }
+
+        fn infer_named(&mut self, inputs: Vec<(String, ArrayD<f32>)>) -> Result<Vec<ArrayD<f32>>> {
+            let h = &inputs[0].1 + &inputs[1].1;
+            Ok(vec![h.clone(), h])
+        }
+    }
+
+    fn manager(max_sessions: usize) -> SessionManager {
+        let model = ModelCfg {
+            backend: "onnx".into(),
+            device: "cpu".into(),
+            model_path: String::new(),
+            layout: None,
+            gpu_ids: vec![],
+            standby_gpu_ids: vec![],
+            max_concurrent_batches: None,
+            sidecar: None,
+            preflight: true,
+            custom_ops: Vec::new(),
+            torch: None,
+            tensorflow: None,
+            stages: Vec::new(),
+            input_names: vec!["x".into(), "h_in".into()],
+            input_shapes: vec![vec![1, 2], vec![1, 2]],
+            output_names: vec!["y".into(), "h_out".into()],
+            output_shapes: vec![vec![1, 2], vec![1, 2]],
+        };
+        let cfg = SessionCfg {
+            state_inputs: vec!["h_in".into()],
+            state_outputs: vec!["h_out".into()],
+            ttl_secs: 60,
+            max_sessions,
+        };
+        SessionManager::new(&cfg, &model).unwrap()
+    }
+
+    fn ones() -> ArrayD<f32> {
+        Array::ones((1, 2)).into_dyn()
+    }
+
+    #[test]
+    fn test_state_carried_between_calls() {
+        let mut mgr = manager(0);
+        let mut engine = Accumulator;
+
+        mgr.step(&mut engine, Some("a"), ones()).unwrap();
+        let (y, steps) = mgr.step(&mut engine, Some("a"), ones()).unwrap();
+        assert_eq!(y[[0, 0]], 2.0);
+        assert_eq!(steps, 2);
+
+        // Andere Session und ohne Session starten bei Null
+        assert_eq!(mgr.step(&mut engine, Some("b"), ones()).unwrap().0[[0, 0]], 1.0);
+        assert_eq!(mgr.step(&mut engine, None, ones()).unwrap().0[[0, 0]], 1.0);
+        assert_eq!(mgr.session_count(), 2);
+
+        assert!(mgr.close("a"));
+        assert_eq!(mgr.step(&mut engine, Some("a"), ones()).unwrap().0[[0, 0]], 1.0);
+    }
+
+    #[test]
+    fn test_evict_ttl_and_limit() {
+        let mut mgr = manager(1);
+        let mut engine = Accumulator;
+        mgr.step(&mut engine, Some("old"), ones()).unwrap();
+        mgr.step(&mut engine, Some("new"), ones()).unwrap();
+
+        assert_eq!(mgr.evict(Instant::now()), 1);
+        assert_eq!(mgr.step(&mut engine, Some("new"), ones()).unwrap().1, 2);
+
+        assert_eq!(mgr.evict(Instant::now() + Duration::from_secs(120)), 1);
+        assert_eq!(mgr.session_count(), 0);
+    }
+
+    #[test]
+    fn test_sticky_worker_is_stable() {
+        let w = sticky_worker("session-42", 4);
+        assert!(w < 4);
+        assert_eq!(sticky_worker("session-42", 4), w);
+        assert_eq!(sticky_worker("x", 0), 0);
+    }
+}
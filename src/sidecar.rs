@@ -0,0 +1,214 @@
+//! Per-model sidecar metadata (`model.json`).
+//!
+//! A model artifact can ship its labels and expected preprocessing in a JSON
+//! file next to the model (or inside a model directory), so this metadata
+//! travels with the artifact instead of living only in `runtime.toml`:
+//!
+//! ```json
+//! {
+//!   "labels": ["cat", "dog"],
+//!   "preprocessing": {"mean": [0.485, 0.456, 0.406], "std": [0.229, 0.224, 0.225],
+//!                     "layout": "nchw", "channels": 3, "height": 224, "width": 224}
+//! }
+//! ```
+//!
+//! `runtime.toml` takes precedence: labels fill only the task sections
+//! without labels, `mean`/`std` only an `[input]` without them. Layout and
+//! dimensions are not taken over but checked against `[input]`.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+
+use crate::types::Config;
+
+/// Default file name, looked up next to `model.model_path`.
+pub const FILE_NAME: &str = "model.json";
+
+/// Contents of a sidecar file.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Sidecar {
+    #[serde(default)]
+    pub labels: Vec<String>,
+    #[serde(default)]
+    pub preprocessing: Option<Preprocessing>,
+}
+
+/// Preprocessing the model was trained with.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Preprocessing {
+    #[serde(default)]
+    pub mean: Vec<f32>,
+    #[serde(default)]
+    pub std: Vec<f32>,
+    #[serde(default)]
+    pub layout: Option<String>,
+    #[serde(default)]
+    pub channels: Option<usize>,
+    #[serde(default)]
+    pub height: Option<usize>,
+    #[serde(default)]
+    pub width: Option<usize>,
+}
+
+/// Location of the sidecar of `cfg`: `model.sidecar` if set, otherwise
+/// `model.json` in the model directory, if present.
+fn locate(cfg: &Config) -> Option<PathBuf> {
+    if let Some(path) = &cfg.model.sidecar {
+        return Some(PathBuf::from(path));
+    }
+    let model = Path::new(&cfg.model.model_path);
+    let dir = if model.is_dir() { model } else { model.parent()? };
+    Some(dir.join(FILE_NAME)).filter(|p| p.is_file())
+}
+
+/// Loads the sidecar of `cfg` and merges it into the configuration.
+/// Returns the path of the loaded file; an explicitly configured file must
+/// exist.
+pub fn load(cfg: &mut Config) -> Result<Option<PathBuf>> {
+    let Some(path) = locate(cfg) else { return Ok(None) };
+    let raw = std::fs::read_to_string(&path)
+        .with_context(|| format!("Sidecar konnte nicht gelesen werden: {}", path.display()))?;
+    let sidecar: Sidecar =
+        serde_json::from_str(&raw).with_context(|| format!("Ungültiges Sidecar: {}", path.display()))?;
+    apply(cfg, &sidecar).with_context(|| format!("Sidecar {} passt nicht zur Konfiguration", path.display()))?;
+    Ok(Some(path))
+}
+
+/// Merges `sidecar` into `cfg`; values from `runtime.toml` win.
+pub fn apply(cfg: &mut Config, sidecar: &Sidecar) -> Result<()> {
+    if !sidecar.labels.is_empty() {
+        let labels = &sidecar.labels;
+        if let Some(c) = cfg.classification.as_mut().filter(|c| c.labels.is_empty() && c.labels_path.is_none()) {
+            c.labels = labels.clone();
+        }
+        if let Some(d) = cfg.detection.as_mut().filter(|d| d.labels.is_empty()) {
+            d.labels = labels.clone();
+        }
+        if let Some(s) = cfg.segmentation.as_mut().filter(|s| s.labels.is_empty()) {
+            s.labels = labels.clone();
+        }
+        if let Some(t) = cfg.text.as_mut().filter(|t| t.labels.is_empty()) {
+            t.labels = labels.clone();
+        }
+    }
+
+    let Some(pre) = &sidecar.preprocessing else { return Ok(()) };
+    let input = &mut cfg.input;
+    if let Some(layout) = &pre.layout {
+        anyhow::ensure!(
+            layout.eq_ignore_ascii_case(&input.layout),
+            "Layout {} erwartet, [input] hat {}",
+            layout,
+            input.layout
+        );
+    }
+    // 0 in [input] ist dynamisch und passt zu jedem Wert
+    for (name, expected, configured) in [
+        ("channels", pre.channels, input.channels),
+        ("height", pre.height, input.height),
+        ("width", pre.width, input.width),
+    ] {
+        if let Some(expected) = expected {
+            anyhow::ensure!(
+                configured == 0 || configured == expected,
+                "{} = {} erwartet, [input] hat {}",
+                name,
+                expected,
+                configured
+            );
+        }
+    }
+    if input.mean.is_empty() && input.std.is_empty() {
+        input.mean = pre.mean.clone();
+        input.std = pre.std.clone();
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(extra: &str) -> Config {
+        let raw = format!(
+            r#"
+            [model]
+            backend = "onnx"
+            device = "cpu"
+            model_path = "/nonexistent/model.onnx"
+            input_names = ["input"]
+            input_shapes = [[1, 3, 0, 224]]
+            output_names = ["output"]
+            output_shapes = [[1, 2]]
+            [queue]
+            max_batch = 8
+            max_wait_ms = 5
+            [redis]
+            url = "redis://127.0.0.1/"
+            out_prefix = "results"
+            [input]
+            batch = 1
+            channels = 3
+            height = 0
+            width = 224
+            dtype = "f32"
+            {}
+            "#,
+            extra
+        );
+        toml::from_str(&raw).unwrap()
+    }
+
+    fn sidecar() -> Sidecar {
+        serde_json::from_str(
+            r#"{"labels": ["cat", "dog"],
+                "preprocessing": {"mean": [0.5], "std": [0.25], "layout": "NCHW", "channels": 3, "height": 224}}"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_apply_fills_missing_values() {
+        let mut cfg = config("[classification]\n[detection]\nlabels = [\"car\"]");
+        apply(&mut cfg, &sidecar()).unwrap();
+        assert_eq!(cfg.classification.unwrap().labels, vec!["cat", "dog"]);
+        // runtime.toml hat Vorrang
+        assert_eq!(cfg.detection.unwrap().labels, vec!["car"]);
+        assert_eq!((cfg.input.mean, cfg.input.std), (vec![0.5], vec![0.25]));
+
+        let mut cfg = config("mean = [0.0]\n[classification]\nlabels_path = \"labels.txt\"");
+        apply(&mut cfg, &sidecar()).unwrap();
+        assert!(cfg.classification.unwrap().labels.is_empty());
+        assert_eq!((cfg.input.mean, cfg.input.std), (vec![0.0], vec![]));
+    }
+
+    #[test]
+    fn test_apply_rejects_mismatch() {
+        let mut sidecar = sidecar();
+        sidecar.preprocessing.as_mut().unwrap().width = Some(320);
+        assert!(apply(&mut config(""), &sidecar).is_err());
+        sidecar.preprocessing.as_mut().unwrap().width = None;
+        sidecar.preprocessing.as_mut().unwrap().layout = Some("nct".to_string());
+        assert!(apply(&mut config(""), &sidecar).is_err());
+    }
+
+    #[test]
+    fn test_load_locates_sidecar() {
+        let dir = std::env::temp_dir().join(format!("omni-sidecar-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut cfg = config("[segmentation]");
+        cfg.model.model_path = dir.join("model.onnx").display().to_string();
+        assert!(load(&mut cfg).unwrap().is_none());
+
+        std::fs::write(dir.join(FILE_NAME), r#"{"labels": ["road"]}"#).unwrap();
+        assert_eq!(load(&mut cfg).unwrap(), Some(dir.join(FILE_NAME)));
+        assert_eq!(cfg.segmentation.as_ref().unwrap().labels, vec!["road"]);
+
+        // Explizit angegebene Datei muss existieren
+        cfg.model.sidecar = Some(dir.join("missing.json").display().to_string());
+        assert!(load(&mut cfg).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
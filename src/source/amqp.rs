@@ -0,0 +1,198 @@
+//! AMQP 0-9-1 consumer source (lapin), e.g. RabbitMQ.
+//!
+//! Consumes `queue` with manual acknowledgements. The broker delivers at
+//! most `prefetch` unacked messages to this node, by default two batches
+//! (`2 * [queue] max_batch`): one being inferred, one being collected. Each
+//! message is acknowledged once its results are stored.
+//!
+//! A job that is dropped without a stored result (e.g. its inference
+//! failed) is nacked: a first delivery goes back to the queue, a failed
+//! redelivery is rejected so a dead-letter exchange configured on the queue
+//! takes it. With `requeue = false` failed jobs are rejected right away.
+//! Messages that cannot be decoded are rejected without requeue.
+//!
+//! The job id is the message's `message_id`, else `{queue}-{delivery_tag}`.
+//! `traceparent`/`tracestate` headers become the job's trace context and
+//! `meta.amqp` holds exchange, routing key and redelivery flag.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use futures::StreamExt;
+use lapin::acker::Acker;
+use lapin::options::{BasicAckOptions, BasicConsumeOptions, BasicNackOptions, BasicQosOptions, BasicRejectOptions};
+use lapin::types::{AMQPValue, FieldTable};
+use lapin::{Connection, ConnectionProperties};
+use serde_json::json;
+use tokio::sync::mpsc;
+use tracing::{info, warn};
+
+use crate::health::health;
+use crate::trace_context::{self, TRACEPARENT, TRACESTATE};
+use crate::types::{Ack, AmqpCfg, Job};
+
+/// Unacked message of this node: its acker and whether it was redelivered.
+type Pending = Arc<Mutex<HashMap<String, (Acker, bool)>>>;
+
+/// Checks the `[source.amqp]` settings.
+pub fn validate(cfg: &AmqpCfg) -> Result<()> {
+    anyhow::ensure!(!cfg.queue.is_empty(), "[source.amqp] queue darf nicht leer sein");
+    anyhow::ensure!(cfg.prefetch != Some(0), "[source.amqp] prefetch muss größer als 0 sein");
+    super::validate_encoding("[source.amqp]", &cfg.encoding)
+}
+
+/// Prefetch count: `cfg.prefetch`, else two batches of `max_batch` jobs.
+pub fn prefetch(cfg: &AmqpCfg, max_batch: usize) -> u16 {
+    cfg.prefetch.unwrap_or_else(|| (max_batch.max(1) * 2).min(u16::MAX as usize) as u16)
+}
+
+/// Consumes `cfg.queue` and feeds the jobs into `tx`.
+///
+/// Runs until the input channel is closed or the runtime drains.
+pub async fn run_amqp_source(cfg: AmqpCfg, prefetch: u16, tx: mpsc::Sender<Job>) -> Result<()> {
+    let connection = Connection::connect(&cfg.url, ConnectionProperties::default())
+        .await
+        .with_context(|| format!("Verbindung zu AMQP {} fehlgeschlagen", cfg.url))?;
+    let channel = connection.create_channel().await.context("AMQP-Kanal konnte nicht geöffnet werden")?;
+    channel
+        .basic_qos(prefetch, BasicQosOptions::default())
+        .await
+        .context("AMQP-Prefetch konnte nicht gesetzt werden")?;
+    let mut consumer = channel
+        .basic_consume(&cfg.queue, &cfg.consumer_tag, BasicConsumeOptions::default(), FieldTable::default())
+        .await
+        .with_context(|| format!("AMQP-Queue {} kann nicht konsumiert werden", cfg.queue))?;
+    info!("AMQP-Quelle liest {} (Prefetch {}, {})", cfg.queue, prefetch, cfg.url);
+
+    let pending: Pending = Arc::default();
+    let (ack_tx, nack_tx) = spawn_acker(Arc::clone(&pending), cfg.requeue, connection);
+    loop {
+        // Beim Drain keine neuen Nachrichten mehr annehmen
+        if health().is_draining() {
+            info!("AMQP-Quelle im Drain, liest keine Nachrichten mehr");
+            return Ok(());
+        }
+        // Pausiert: der Broker liefert höchstens `prefetch` Nachrichten voraus
+        if health().is_paused() {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            continue;
+        }
+        let delivery = match tokio::time::timeout(Duration::from_secs(1), consumer.next()).await {
+            Err(_) => continue,
+            Ok(None) => anyhow::bail!("AMQP-Consumer auf {} beendet", cfg.queue),
+            Ok(Some(Err(e))) => anyhow::bail!("AMQP-Fehler auf {}: {}", cfg.queue, e),
+            Ok(Some(Ok(delivery))) => delivery,
+        };
+
+        let tag = delivery.delivery_tag;
+        let default_id = match delivery.properties.message_id() {
+            Some(id) => id.to_string(),
+            None => format!("{}-{}", cfg.queue, tag),
+        };
+        let mut job = match super::decode(&cfg.encoding, &delivery.data, default_id) {
+            Ok(job) => job,
+            Err(e) => {
+                // Nicht dekodierbar: verwerfen statt erneut zustellen
+                warn!("AMQP-Nachricht {}@{} verworfen: {:#}", cfg.queue, tag, e);
+                if let Err(e) = delivery.acker.reject(BasicRejectOptions { requeue: false }).await {
+                    warn!("AMQP-Nachricht {}@{} nicht verworfen: {}", cfg.queue, tag, e);
+                }
+                continue;
+            }
+        };
+        let headers = delivery.properties.headers().as_ref();
+        let (traceparent, tracestate) = (header(headers, TRACEPARENT), header(headers, TRACESTATE));
+        trace_context::inject(&mut job.meta, traceparent.as_deref(), tracestate.as_deref());
+        job.meta.insert(
+            "amqp".to_string(),
+            json!({
+                "exchange": delivery.exchange.as_str(),
+                "routing_key": delivery.routing_key.as_str(),
+                "redelivered": delivery.redelivered,
+            }),
+        );
+
+        let token = tag.to_string();
+        pending.lock().unwrap().insert(token.clone(), (delivery.acker, delivery.redelivered));
+        job.ack = Some(Ack::with_nack(ack_tx.clone(), nack_tx.clone(), token));
+        if tx.send(job).await.is_err() {
+            return Ok(()); // Runtime beendet
+        }
+    }
+}
+
+/// String value of a message header.
+fn header(headers: Option<&FieldTable>, name: &str) -> Option<String> {
+    match headers?.inner().get(name)? {
+        AMQPValue::LongString(value) => Some(String::from_utf8_lossy(value.as_bytes()).into_owned()),
+        AMQPValue::ShortString(value) => Some(value.to_string()),
+        _ => None,
+    }
+}
+
+/// Starts the task acknowledging stored and nacking failed messages.
+///
+/// The task keeps the connection open until all jobs are settled.
+fn spawn_acker(
+    pending: Pending,
+    requeue: bool,
+    connection: Connection,
+) -> (mpsc::UnboundedSender<String>, mpsc::UnboundedSender<String>) {
+    let (ack_tx, mut ack_rx) = mpsc::unbounded_channel::<String>();
+    let (nack_tx, mut nack_rx) = mpsc::unbounded_channel::<String>();
+    tokio::spawn(async move {
+        let _connection = connection;
+        loop {
+            let (token, done) = tokio::select! {
+                Some(token) = ack_rx.recv() => (token, true),
+                Some(token) = nack_rx.recv() => (token, false),
+                else => break,
+            };
+            // Doppelte Quittungen (z. B. nach Retry) haben keinen Eintrag mehr
+            let Some((acker, redelivered)) = pending.lock().unwrap().remove(&token) else { continue };
+            let result = if done {
+                acker.ack(BasicAckOptions::default()).await
+            } else {
+                let requeue = requeue && !redelivered;
+                warn!("AMQP-Nachricht {} fehlgeschlagen, {}", token, if requeue { "erneut eingereiht" } else { "verworfen" });
+                acker.nack(BasicNackOptions { requeue, multiple: false }).await
+            };
+            if let Err(e) = result {
+                warn!("AMQP-Nachricht {} nicht quittiert: {}", token, e);
+            }
+        }
+    });
+    (ack_tx, nack_tx)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_and_prefetch() {
+        let cfg = |extra: &str| toml::from_str::<AmqpCfg>(&format!("queue = \"jobs\"\n{}", extra)).unwrap();
+        let defaults = cfg("");
+        assert_eq!(defaults.url, "amqp://localhost:5672/%2f");
+        assert!(defaults.requeue);
+        assert!(validate(&defaults).is_ok());
+        assert!(validate(&cfg("encoding = \"avro\"")).is_err());
+        assert!(validate(&cfg("prefetch = 0")).is_err());
+
+        assert_eq!(prefetch(&defaults, 16), 32);
+        assert_eq!(prefetch(&defaults, 100_000), u16::MAX);
+        assert_eq!(prefetch(&cfg("prefetch = 5"), 16), 5);
+    }
+
+    #[test]
+    fn test_header_values() {
+        let mut headers = FieldTable::default();
+        headers.insert(TRACEPARENT.into(), AMQPValue::LongString("00-abc-01".into()));
+        headers.insert("x-count".into(), AMQPValue::LongLongInt(3));
+        assert_eq!(header(Some(&headers), TRACEPARENT).as_deref(), Some("00-abc-01"));
+        assert_eq!(header(Some(&headers), "x-count"), None);
+        assert_eq!(header(None, TRACEPARENT), None);
+    }
+}
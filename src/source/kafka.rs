@@ -0,0 +1,245 @@
+//! Kafka consumer source (rdkafka).
+//!
+//! Consumes jobs from a topic as member of a consumer group, so partitions
+//! are spread over all nodes of the group. Each message is handed to the
+//! dispatcher with an [`Ack`]; its offset is stored for the next commit only
+//! once the results of it and of all earlier messages of its partition are
+//! stored. A restart or rebalance therefore resumes at the first unfinished
+//! job (at-least-once). Messages that cannot be decoded are skipped with a
+//! warning.
+//!
+//! The job id defaults to `{topic}-{partition}-{offset}`, so a redelivered
+//! message keeps its id. `traceparent`/`tracestate` headers become the job's
+//! trace context and `meta.kafka` holds topic, partition and offset. With
+//! `partition_affinity` the partition becomes the job's
+//! [`AFFINITY`](super::AFFINITY), keeping each partition on one worker.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use rdkafka::config::ClientConfig;
+use rdkafka::consumer::{Consumer, ConsumerContext, Rebalance, StreamConsumer};
+use rdkafka::message::{Headers, Message};
+use rdkafka::ClientContext;
+use serde_json::json;
+use tokio::sync::mpsc;
+use tracing::{info, warn};
+
+use crate::health::health;
+use crate::trace_context::{self, TRACEPARENT, TRACESTATE};
+use crate::types::{Ack, Job, KafkaCfg};
+
+/// Checks the `[source.kafka]` settings.
+pub fn validate(cfg: &KafkaCfg) -> Result<()> {
+    super::validate_encoding("[source.kafka]", &cfg.encoding)
+}
+
+/// Consumes `cfg.topic` and feeds the jobs into `tx`.
+///
+/// Runs until the input channel is closed or the runtime drains.
+pub async fn run_kafka_source(cfg: KafkaCfg, tx: mpsc::Sender<Job>) -> Result<()> {
+    let tracker = Arc::new(Mutex::new(OffsetTracker::default()));
+    let mut client = ClientConfig::new();
+    client
+        .set("bootstrap.servers", &cfg.brokers)
+        .set("group.id", &cfg.group)
+        .set("auto.offset.reset", "earliest")
+        .set("enable.auto.commit", "true")
+        .set("auto.commit.interval.ms", cfg.commit_interval_ms.to_string());
+    for (key, value) in &cfg.properties {
+        client.set(key, value);
+    }
+    // Offsets speichert nur der Acker, nach dem Speichern der Ergebnisse
+    client.set("enable.auto.offset.store", "false");
+    let consumer: Arc<StreamConsumer<TrackingContext>> = Arc::new(
+        client
+            .create_with_context(TrackingContext { tracker: Arc::clone(&tracker) })
+            .context("Kafka-Consumer konnte nicht erstellt werden")?,
+    );
+    consumer
+        .subscribe(&[&cfg.topic])
+        .with_context(|| format!("Kafka-Topic {} konnte nicht abonniert werden", cfg.topic))?;
+    info!("Kafka-Quelle liest {} (Gruppe {}, {})", cfg.topic, cfg.group, cfg.brokers);
+
+    let ack_tx = spawn_acker(Arc::clone(&consumer), cfg.topic.clone(), Arc::clone(&tracker));
+    loop {
+        // Beim Drain keine neuen Nachrichten mehr annehmen
+        if health().is_draining() {
+            info!("Kafka-Quelle im Drain, liest keine Nachrichten mehr");
+            return Ok(());
+        }
+        // Pausiert: zugestellte Jobs laufen weiter, neue werden nicht gelesen
+        if health().is_paused() {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            continue;
+        }
+        let (partition, offset, decoded) = match tokio::time::timeout(Duration::from_secs(1), consumer.recv()).await {
+            Err(_) => continue,
+            Ok(Err(e)) => {
+                warn!("Kafka-Fehler auf {}: {}", cfg.topic, e);
+                tokio::time::sleep(Duration::from_secs(1)).await;
+                continue;
+            }
+            Ok(Ok(message)) => {
+                let (partition, offset) = (message.partition(), message.offset());
+                let default_id = format!("{}-{}-{}", cfg.topic, partition, offset);
+                let payload = message.payload().unwrap_or_default();
+                let decoded = super::decode(&cfg.encoding, payload, default_id).map(|mut job| {
+                    let header = |name: &str| {
+                        let headers = message.headers()?;
+                        let value = headers.iter().find(|h| h.key == name)?.value?;
+                        std::str::from_utf8(value).ok()
+                    };
+                    trace_context::inject(&mut job.meta, header(TRACEPARENT), header(TRACESTATE));
+                    job
+                });
+                (partition, offset, decoded)
+            }
+        };
+
+        tracker.lock().unwrap().started(partition, offset);
+        let token = format!("{}:{}", partition, offset);
+        let mut job = match decoded {
+            Ok(job) => job,
+            Err(e) => {
+                // Nicht dekodierbar: überspringen, sonst hielte sie den Offset der Partition auf
+                warn!("Kafka-Nachricht {}/{}@{} übersprungen: {:#}", cfg.topic, partition, offset, e);
+                let _ = ack_tx.send(token);
+                continue;
+            }
+        };
+        job.meta.insert("kafka".to_string(), json!({ "topic": cfg.topic, "partition": partition, "offset": offset }));
+        if cfg.partition_affinity {
+            job.meta.insert(super::AFFINITY.to_string(), partition.into());
+        }
+        job.ack = Some(Ack::new(ack_tx.clone(), token));
+        if tx.send(job).await.is_err() {
+            return Ok(()); // Runtime beendet
+        }
+    }
+}
+
+/// Starts the task storing the offsets of finished messages.
+fn spawn_acker(
+    consumer: Arc<StreamConsumer<TrackingContext>>,
+    topic: String,
+    tracker: Arc<Mutex<OffsetTracker>>,
+) -> mpsc::UnboundedSender<String> {
+    let (tx, mut rx) = mpsc::unbounded_channel::<String>();
+    tokio::spawn(async move {
+        while let Some(token) = rx.recv().await {
+            let Some((partition, offset)) = token.split_once(':').and_then(|(p, o)| Some((p.parse().ok()?, o.parse().ok()?)))
+            else {
+                continue;
+            };
+            let Some(offset) = tracker.lock().unwrap().finished(partition, offset) else { continue };
+            if let Err(e) = consumer.store_offset(&topic, partition, offset) {
+                warn!("Kafka-Offset {}/{}@{} nicht gespeichert: {}", topic, partition, offset, e);
+            }
+        }
+    });
+    tx
+}
+
+/// Consumer context that forgets the offsets of revoked partitions.
+struct TrackingContext {
+    tracker: Arc<Mutex<OffsetTracker>>,
+}
+
+impl ClientContext for TrackingContext {}
+
+impl ConsumerContext for TrackingContext {
+    fn pre_rebalance(&self, rebalance: &Rebalance<'_>) {
+        if let Rebalance::Revoke(partitions) = rebalance {
+            let mut tracker = self.tracker.lock().unwrap();
+            for element in partitions.elements() {
+                tracker.revoke(element.partition());
+            }
+        }
+    }
+}
+
+/// Offsets of one partition.
+#[derive(Debug, Default)]
+struct PartitionOffsets {
+    in_flight: BTreeSet<i64>,
+    /// Highest finished offset.
+    done: Option<i64>,
+    /// Last offset stored for commit.
+    stored: Option<i64>,
+}
+
+/// Decides which offsets may be committed: a partition advances only past
+/// messages whose results are stored, even if later ones finish first.
+#[derive(Debug, Default)]
+struct OffsetTracker {
+    partitions: BTreeMap<i32, PartitionOffsets>,
+}
+
+impl OffsetTracker {
+    /// Records a message handed to the dispatcher.
+    fn started(&mut self, partition: i32, offset: i64) {
+        self.partitions.entry(partition).or_default().in_flight.insert(offset);
+    }
+
+    /// Records a finished message. Returns the last offset of `partition`
+    /// that may be committed, if it advanced.
+    fn finished(&mut self, partition: i32, offset: i64) -> Option<i64> {
+        let p = self.partitions.get_mut(&partition)?;
+        if !p.in_flight.remove(&offset) {
+            return None;
+        }
+        p.done = p.done.max(Some(offset));
+        // Bis vor die älteste offene Nachricht, ohne offene bis zur höchsten fertigen
+        let committable = match p.in_flight.first() {
+            Some(&open) => open - 1,
+            None => p.done?,
+        };
+        if p.stored.is_some_and(|stored| stored >= committable) {
+            return None;
+        }
+        p.stored = Some(committable);
+        Some(committable)
+    }
+
+    /// Forgets a revoked partition; late acknowledgements are ignored.
+    fn revoke(&mut self, partition: i32) {
+        self.partitions.remove(&partition);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_offsets_advance_past_finished_messages_only() {
+        let mut tracker = OffsetTracker::default();
+        for offset in 10..14 {
+            tracker.started(0, offset);
+        }
+        tracker.started(1, 5);
+        // 11 und 12 sind fertig, 10 noch offen: nichts über 9 hinaus
+        assert_eq!(tracker.finished(0, 11), Some(9));
+        assert_eq!(tracker.finished(0, 12), None);
+        assert_eq!(tracker.finished(0, 10), Some(12));
+        assert_eq!(tracker.finished(0, 13), Some(13));
+        // Doppelte Quittung
+        assert_eq!(tracker.finished(0, 13), None);
+        assert_eq!(tracker.finished(1, 5), Some(5));
+
+        tracker.started(2, 7);
+        tracker.revoke(2);
+        assert_eq!(tracker.finished(2, 7), None);
+    }
+
+    #[test]
+    fn test_validate_encoding() {
+        let cfg = |encoding: &str| toml::from_str::<KafkaCfg>(&format!("brokers = \"b:9092\"\ntopic = \"jobs\"\nencoding = \"{}\"", encoding)).unwrap();
+        assert!(validate(&cfg("json")).is_ok());
+        assert!(validate(&cfg("avro")).is_err());
+        assert_eq!(validate(&cfg("protobuf")).is_ok(), cfg!(feature = "protobuf"));
+    }
+}
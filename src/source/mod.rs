@@ -0,0 +1,331 @@
+//! Job sources feeding the dispatcher channel.
+//!
+//! Each source turns external input (streams, queues, files, ...) into `Job`s
+//! and pushes them into the runtime's input channel. Sources are enabled via
+//! the `[source.*]` configuration sections and optional cargo features.
+
+#[cfg(feature = "amqp")]
+pub mod amqp;
+#[cfg(feature = "kafka")]
+pub mod kafka;
+#[cfg(feature = "mqtt")]
+pub mod mqtt;
+#[cfg(feature = "nats")]
+pub mod nats;
+#[cfg(feature = "s3")]
+pub mod s3;
+#[cfg(unix)]
+pub mod uds;
+#[cfg(feature = "video")]
+pub mod video;
+#[cfg(feature = "watch")]
+pub mod watch;
+
+use anyhow::{Context, Result};
+use tokio::sync::mpsc;
+
+use crate::types::{Config, Job, JobRequest};
+
+/// Job metadata key with a preferred worker: jobs with the same integer
+/// `affinity` go to worker `affinity % workers` (or the next live one).
+pub const AFFINITY: &str = "affinity";
+
+/// Starts all configured sources, each sending into `tx`.
+///
+/// Returns `true` if at least one source was started; the runtime then keeps
+/// running until the sources finish.
+pub fn spawn_sources(cfg: &Config, tx: &mpsc::Sender<Job>) -> Result<bool> {
+    let mut started = spawn_video(cfg, tx)? + spawn_kafka(cfg, tx)? + spawn_nats(cfg, tx)? + spawn_mqtt(cfg, tx)?
+        + spawn_uds(cfg, tx)? + spawn_amqp(cfg, tx)? + spawn_watch(cfg, tx)? + spawn_s3(cfg, tx)?;
+
+    // Gemeinsame Queue mehrerer Knoten
+    if let Some(cluster) = &cfg.cluster {
+        let (source_cfg, url, tx) = (cluster.clone(), cfg.redis.url.clone(), tx.clone());
+        tokio::spawn(async move {
+            if let Err(e) = crate::cluster::run_cluster_source(source_cfg, url, tx).await {
+                tracing::error!("Cluster-Quelle fehlgeschlagen: {:?}", e);
+            }
+        });
+        started += 1;
+
+        if cluster.result_stream.is_some() {
+            crate::cluster::validate_result_encoding(cluster)?;
+            let (cluster, url) = (cluster.clone(), cfg.redis.url.clone());
+            tokio::spawn(async move {
+                if let Err(e) = crate::cluster::run_result_sink(cluster, url).await {
+                    tracing::error!("Ergebnis-Stream fehlgeschlagen: {:?}", e);
+                }
+            });
+        }
+    }
+
+    if started > 0 {
+        tracing::info!("{} Quelle(n) gestartet", started);
+    }
+    Ok(started > 0)
+}
+
+/// Checks the message `encoding` of a queue source (`section` names it in
+/// errors).
+#[cfg_attr(not(any(feature = "kafka", feature = "nats", feature = "mqtt", feature = "amqp")), allow(dead_code))]
+fn validate_encoding(section: &str, encoding: &str) -> Result<()> {
+    match encoding {
+        "json" => Ok(()),
+        "protobuf" if cfg!(feature = "protobuf") => Ok(()),
+        "protobuf" => anyhow::bail!("{} encoding = \"protobuf\" benötigt das Feature 'protobuf'", section),
+        other => anyhow::bail!("Unbekanntes encoding '{}' in {} (erwartet: json, protobuf)", other, section),
+    }
+}
+
+/// Decodes a queue message into a job; `default_id` applies to jobs
+/// without an id.
+///
+/// JSON messages hold the wire format or a CloudEvent wrapping it.
+#[cfg_attr(not(any(feature = "kafka", feature = "nats", feature = "mqtt", feature = "amqp", test)), allow(dead_code))]
+fn decode(encoding: &str, payload: &[u8], default_id: String) -> Result<Job> {
+    let mut job = match encoding {
+        "protobuf" => crate::cluster::decode_pb(payload)?,
+        _ => {
+            let message = serde_json::from_slice(payload).context("Nachricht ist kein JSON")?;
+            let mut message = crate::cloudevents::unwrap_job(message)?;
+            if let Some(fields) = message.as_object_mut() {
+                fields.entry("id").or_insert_with(|| default_id.clone().into());
+            }
+            serde_json::from_value::<JobRequest>(message)?.into_job()?
+        }
+    };
+    if job.id.is_empty() {
+        job.id = default_id;
+    }
+    Ok(job)
+}
+
+/// Waits for the leader lease if `[k8s] leader_election` is enabled.
+///
+/// Singleton sources (e.g. a camera stream that must only be read once per
+/// deployment) call this before they start.
+#[cfg_attr(not(any(feature = "video", feature = "s3")), allow(dead_code))]
+async fn await_leadership(cfg: &Config) -> Result<()> {
+    match &cfg.k8s {
+        Some(k8s) if k8s.leader_election => {
+            let node = cfg.cluster.as_ref().map(crate::cluster::node_id).unwrap_or_else(crate::cluster::default_node_id);
+            crate::k8s::acquire_leadership(k8s, &cfg.redis.url, &node).await
+        }
+        _ => Ok(()),
+    }
+}
+
+#[cfg(feature = "video")]
+fn spawn_video(cfg: &Config, tx: &mpsc::Sender<Job>) -> Result<usize> {
+    let Some(video_cfg) = &cfg.source.video else { return Ok(0) };
+    let streams = video_cfg.streams.len();
+    let (cfg, tx) = (cfg.clone(), tx.clone());
+
+    // Video-Streams sind Singletons: bei Leader-Election nur auf dem Leader
+    tokio::spawn(async move {
+        if let Err(e) = await_leadership(&cfg).await {
+            tracing::error!("Leader-Election fehlgeschlagen: {:?}", e);
+            return;
+        }
+        let video_cfg = cfg.source.video.clone().unwrap();
+        for stream in video_cfg.streams.clone() {
+            let (video_cfg, tx) = (video_cfg.clone(), tx.clone());
+            let (width, height) = (cfg.input.width, cfg.input.height);
+            tokio::spawn(async move {
+                let id = stream.id.clone();
+                if let Err(e) = video::run_video_source(video_cfg, stream, width, height, tx).await {
+                    tracing::error!("Video-Stream {} fehlgeschlagen: {:?}", id, e);
+                }
+            });
+        }
+    });
+    Ok(streams)
+}
+
+#[cfg(feature = "kafka")]
+fn spawn_kafka(cfg: &Config, tx: &mpsc::Sender<Job>) -> Result<usize> {
+    let Some(kafka_cfg) = cfg.source.kafka.clone() else { return Ok(0) };
+    kafka::validate(&kafka_cfg)?;
+    let tx = tx.clone();
+    tokio::spawn(async move {
+        let topic = kafka_cfg.topic.clone();
+        if let Err(e) = kafka::run_kafka_source(kafka_cfg, tx).await {
+            tracing::error!("Kafka-Quelle {} fehlgeschlagen: {:?}", topic, e);
+        }
+    });
+    Ok(1)
+}
+
+#[cfg(not(feature = "kafka"))]
+fn spawn_kafka(cfg: &Config, _tx: &mpsc::Sender<Job>) -> Result<usize> {
+    anyhow::ensure!(
+        cfg.source.kafka.is_none(),
+        "[source.kafka] konfiguriert, aber Feature 'kafka' nicht aktiviert"
+    );
+    Ok(0)
+}
+
+#[cfg(feature = "nats")]
+fn spawn_nats(cfg: &Config, tx: &mpsc::Sender<Job>) -> Result<usize> {
+    let Some(nats_cfg) = cfg.source.nats.clone() else { return Ok(0) };
+    nats::validate(&nats_cfg)?;
+    let tx = tx.clone();
+    tokio::spawn(async move {
+        let stream = nats_cfg.stream.clone();
+        if let Err(e) = nats::run_nats_source(nats_cfg, tx).await {
+            tracing::error!("NATS-Quelle {} fehlgeschlagen: {:?}", stream, e);
+        }
+    });
+    Ok(1)
+}
+
+#[cfg(not(feature = "nats"))]
+fn spawn_nats(cfg: &Config, _tx: &mpsc::Sender<Job>) -> Result<usize> {
+    anyhow::ensure!(
+        cfg.source.nats.is_none(),
+        "[source.nats] konfiguriert, aber Feature 'nats' nicht aktiviert"
+    );
+    Ok(0)
+}
+
+#[cfg(feature = "mqtt")]
+fn spawn_mqtt(cfg: &Config, tx: &mpsc::Sender<Job>) -> Result<usize> {
+    let Some(mqtt_cfg) = cfg.source.mqtt.clone() else { return Ok(0) };
+    mqtt::validate(&mqtt_cfg)?;
+    let (spec, tx) = (cfg.input_spec(), tx.clone());
+    tokio::spawn(async move {
+        let host = mqtt_cfg.host.clone();
+        if let Err(e) = mqtt::run_mqtt_source(mqtt_cfg, spec, tx).await {
+            tracing::error!("MQTT-Quelle {} fehlgeschlagen: {:?}", host, e);
+        }
+    });
+    Ok(1)
+}
+
+#[cfg(not(feature = "mqtt"))]
+fn spawn_mqtt(cfg: &Config, _tx: &mpsc::Sender<Job>) -> Result<usize> {
+    anyhow::ensure!(
+        cfg.source.mqtt.is_none(),
+        "[source.mqtt] konfiguriert, aber Feature 'mqtt' nicht aktiviert"
+    );
+    Ok(0)
+}
+
+#[cfg(feature = "amqp")]
+fn spawn_amqp(cfg: &Config, tx: &mpsc::Sender<Job>) -> Result<usize> {
+    let Some(amqp_cfg) = cfg.source.amqp.clone() else { return Ok(0) };
+    amqp::validate(&amqp_cfg)?;
+    let (prefetch, tx) = (amqp::prefetch(&amqp_cfg, cfg.queue.max_batch), tx.clone());
+    tokio::spawn(async move {
+        let queue = amqp_cfg.queue.clone();
+        if let Err(e) = amqp::run_amqp_source(amqp_cfg, prefetch, tx).await {
+            tracing::error!("AMQP-Quelle {} fehlgeschlagen: {:?}", queue, e);
+        }
+    });
+    Ok(1)
+}
+
+#[cfg(not(feature = "amqp"))]
+fn spawn_amqp(cfg: &Config, _tx: &mpsc::Sender<Job>) -> Result<usize> {
+    anyhow::ensure!(
+        cfg.source.amqp.is_none(),
+        "[source.amqp] konfiguriert, aber Feature 'amqp' nicht aktiviert"
+    );
+    Ok(0)
+}
+
+#[cfg(feature = "watch")]
+fn spawn_watch(cfg: &Config, tx: &mpsc::Sender<Job>) -> Result<usize> {
+    let Some(watch_cfg) = cfg.source.watch.clone() else { return Ok(0) };
+    watch::validate(&watch_cfg)?;
+    let (config, tx) = (cfg.clone(), tx.clone());
+    tokio::spawn(async move {
+        let dir = watch_cfg.dir.clone();
+        if let Err(e) = watch::run_watch_source(watch_cfg, config, tx).await {
+            tracing::error!("Verzeichnis-Quelle {} fehlgeschlagen: {:?}", dir, e);
+        }
+    });
+    Ok(1)
+}
+
+#[cfg(not(feature = "watch"))]
+fn spawn_watch(cfg: &Config, _tx: &mpsc::Sender<Job>) -> Result<usize> {
+    anyhow::ensure!(
+        cfg.source.watch.is_none(),
+        "[source.watch] konfiguriert, aber Feature 'watch' nicht aktiviert"
+    );
+    Ok(0)
+}
+
+#[cfg(feature = "s3")]
+fn spawn_s3(cfg: &Config, tx: &mpsc::Sender<Job>) -> Result<usize> {
+    let Some(s3_cfg) = cfg.source.s3.clone() else { return Ok(0) };
+    s3::validate(&s3_cfg, &cfg.redis.url)?;
+    let (config, tx) = (cfg.clone(), tx.clone());
+
+    // Bucket-Polling ist ein Singleton: bei Leader-Election nur auf dem Leader
+    tokio::spawn(async move {
+        if let Err(e) = await_leadership(&config).await {
+            tracing::error!("Leader-Election fehlgeschlagen: {:?}", e);
+            return;
+        }
+        let uri = s3_cfg.uri.clone();
+        if let Err(e) = s3::run_s3_source(s3_cfg, config, tx).await {
+            tracing::error!("S3-Quelle {} fehlgeschlagen: {:?}", uri, e);
+        }
+    });
+    Ok(1)
+}
+
+#[cfg(not(feature = "s3"))]
+fn spawn_s3(cfg: &Config, _tx: &mpsc::Sender<Job>) -> Result<usize> {
+    anyhow::ensure!(cfg.source.s3.is_none(), "[source.s3] konfiguriert, aber Feature 's3' nicht aktiviert");
+    Ok(0)
+}
+
+#[cfg(unix)]
+fn spawn_uds(cfg: &Config, tx: &mpsc::Sender<Job>) -> Result<usize> {
+    let Some(uds_cfg) = cfg.source.uds.clone() else { return Ok(0) };
+    let tx = tx.clone();
+    tokio::spawn(async move {
+        let path = uds_cfg.path.clone();
+        if let Err(e) = uds::run_uds_source(uds_cfg, tx).await {
+            tracing::error!("UDS-Quelle {} fehlgeschlagen: {:?}", path, e);
+        }
+    });
+    Ok(1)
+}
+
+#[cfg(not(unix))]
+fn spawn_uds(cfg: &Config, _tx: &mpsc::Sender<Job>) -> Result<usize> {
+    anyhow::ensure!(cfg.source.uds.is_none(), "[source.uds] wird nur auf Unix unterstützt");
+    Ok(0)
+}
+
+#[cfg(not(feature = "video"))]
+fn spawn_video(cfg: &Config, _tx: &mpsc::Sender<Job>) -> Result<usize> {
+    anyhow::ensure!(
+        cfg.source.video.is_none(),
+        "[source.video] konfiguriert, aber Feature 'video' nicht aktiviert"
+    );
+    Ok(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_payload() {
+        let job = decode("json", br#"{"shape": [2], "data": [0.5, 1.5]}"#, "jobs-0-7".into()).unwrap();
+        assert_eq!(job.id, "jobs-0-7");
+        assert_eq!(job.tensor[[1]], 1.5);
+
+        let event = br#"{"specversion": "1.0", "id": "evt-1", "source": "/s", "type": "t", "data": {"shape": [1], "data": [2]}}"#;
+        assert_eq!(decode("json", event, "jobs-0-8".into()).unwrap().id, "evt-1");
+        assert!(decode("json", b"not json", "jobs-0-9".into()).is_err());
+
+        assert!(validate_encoding("[source.x]", "json").is_ok());
+        assert!(validate_encoding("[source.x]", "avro").is_err());
+    }
+}
@@ -0,0 +1,157 @@
+//! MQTT subscriber source (rumqttc).
+//!
+//! Subscribes to the configured topic filters and turns each message into a
+//! job: an encoded image (JPEG, PNG) resized to the model input, or a job
+//! message in the JSON or protobuf wire format. Edge cameras typically
+//! publish frames this way, so messages are treated like video frames: while
+//! the input channel is full they are dropped instead of queueing up in the
+//! broker connection.
+//!
+//! Job ids are `{topic}-{n}` unless the message carries one; `meta.mqtt`
+//! holds the topic and `captured_at` the receive time.
+
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use chrono::Utc;
+use rumqttc::{AsyncClient, Event, MqttOptions, Packet};
+use serde_json::json;
+use tokio::sync::mpsc::{self, error::TrySendError};
+use tracing::{debug, info, warn};
+
+use crate::health::health;
+use crate::types::{InputSpec, Job, MqttCfg};
+
+/// Checks the `[source.mqtt]` settings.
+pub fn validate(cfg: &MqttCfg) -> Result<()> {
+    anyhow::ensure!(!cfg.topics.is_empty(), "[source.mqtt] topics darf nicht leer sein");
+    rumqttc::qos(cfg.qos).map_err(|_| anyhow::anyhow!("[source.mqtt] qos muss 0, 1 oder 2 sein"))?;
+    match cfg.encoding.as_str() {
+        "image" => Ok(()),
+        other => super::validate_encoding("[source.mqtt]", other),
+    }
+}
+
+/// Subscribes to `cfg.topics` and feeds the jobs into `tx`.
+///
+/// Runs until the input channel is closed or the runtime drains; lost
+/// connections are re-established by the event loop.
+pub async fn run_mqtt_source(cfg: MqttCfg, spec: InputSpec, tx: mpsc::Sender<Job>) -> Result<()> {
+    let mut options = MqttOptions::new(&cfg.client_id, &cfg.host, cfg.port);
+    options.set_keep_alive(Duration::from_secs(cfg.keep_alive_s));
+    let max_payload = cfg.max_payload_kb * 1024;
+    options.set_max_packet_size(max_payload, max_payload);
+    if let Some(username) = &cfg.username {
+        options.set_credentials(username, cfg.password.clone().unwrap_or_default());
+    }
+    let qos = rumqttc::qos(cfg.qos).context("[source.mqtt] ungültige qos")?;
+    let (client, mut eventloop) = AsyncClient::new(options, 16);
+    info!("MQTT-Quelle verbindet zu {}:{} ({})", cfg.host, cfg.port, cfg.topics.join(", "));
+
+    let (mut received, mut dropped) = (0u64, 0u64);
+    loop {
+        if health().is_draining() {
+            info!("MQTT-Quelle im Drain, liest keine Nachrichten mehr");
+            let _ = client.disconnect().await;
+            return Ok(());
+        }
+        let publish = match tokio::time::timeout(Duration::from_secs(1), eventloop.poll()).await {
+            Err(_) => continue,
+            Ok(Err(e)) => {
+                warn!("MQTT-Verbindung zu {}:{} unterbrochen: {}, neuer Versuch in 1s", cfg.host, cfg.port, e);
+                tokio::time::sleep(Duration::from_secs(1)).await;
+                continue;
+            }
+            // Abos nach jedem (Neu-)Verbinden erneuern
+            Ok(Ok(Event::Incoming(Packet::ConnAck(_)))) => {
+                for topic in &cfg.topics {
+                    client
+                        .subscribe(topic.as_str(), qos)
+                        .await
+                        .with_context(|| format!("MQTT-Topic {} konnte nicht abonniert werden", topic))?;
+                }
+                continue;
+            }
+            Ok(Ok(Event::Incoming(Packet::Publish(publish)))) => publish,
+            Ok(Ok(_)) => continue,
+        };
+        // Pausiert: Verbindung halten, Nachrichten verwerfen
+        if health().is_paused() {
+            continue;
+        }
+
+        let job = match to_job(&cfg.encoding, &spec, &publish.topic, &publish.payload, received) {
+            Ok(job) => job,
+            Err(e) => {
+                warn!("MQTT-Nachricht auf {} übersprungen: {:#}", publish.topic, e);
+                continue;
+            }
+        };
+        received += 1;
+
+        // Backpressure: Nachricht verwerfen statt die Verbindung zu blockieren
+        match tx.try_send(job) {
+            Ok(()) => {}
+            Err(TrySendError::Full(_)) => {
+                dropped += 1;
+                debug!("MQTT-Nachricht auf {} verworfen ({} gesamt)", publish.topic, dropped);
+            }
+            Err(TrySendError::Closed(_)) => return Ok(()), // Runtime beendet
+        }
+    }
+}
+
+/// Turns the `n`-th message of the source into a job.
+fn to_job(encoding: &str, spec: &InputSpec, topic: &str, payload: &[u8], n: u64) -> Result<Job> {
+    let id = format!("{}-{}", topic, n);
+    let mut job = match encoding {
+        "image" => Job { id, tensor: crate::cli::decode_image(payload, spec)?, ..Default::default() },
+        _ => super::decode(encoding, payload, id)?,
+    };
+    job.meta.insert("mqtt".to_string(), json!({ "topic": topic }));
+    job.meta.entry("captured_at".to_string()).or_insert_with(|| Utc::now().to_rfc3339().into());
+    Ok(job)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spec() -> InputSpec {
+        InputSpec {
+            batch: 1,
+            channels: 3,
+            depth: 0,
+            height: 2,
+            width: 2,
+            dtype: "f32".to_string(),
+            layout: "nchw".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_payload_to_job() {
+        let mut png = Vec::new();
+        image::RgbImage::from_pixel(4, 4, image::Rgb([0, 255, 0]))
+            .write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png)
+            .unwrap();
+        let job = to_job("image", &spec(), "cams/1", &png, 3).unwrap();
+        assert_eq!(job.id, "cams/1-3");
+        assert_eq!(job.tensor.shape(), &[3, 2, 2]);
+        assert_eq!(job.tensor[[1, 0, 0]], 1.0);
+        assert_eq!(job.meta["mqtt"]["topic"], "cams/1");
+        assert!(to_job("image", &spec(), "cams/1", b"no image", 4).is_err());
+
+        let job = to_job("json", &spec(), "jobs", br#"{"id": "a", "shape": [1], "data": [2]}"#, 5).unwrap();
+        assert_eq!(job.id, "a");
+    }
+
+    #[test]
+    fn test_validate() {
+        let cfg = |extra: &str| toml::from_str::<MqttCfg>(&format!("topics = [\"cams/#\"]\n{}", extra)).unwrap();
+        assert!(validate(&cfg("")).is_ok());
+        assert!(validate(&cfg("qos = 3")).is_err());
+        assert!(validate(&cfg("encoding = \"avro\"")).is_err());
+        assert!(validate(&toml::from_str::<MqttCfg>("topics = []").unwrap()).is_err());
+    }
+}
@@ -0,0 +1,164 @@
+//! NATS JetStream source (async-nats).
+//!
+//! Pulls jobs from a stream through a durable consumer, so all nodes using
+//! the same consumer name share the messages. Each message is handed to the
+//! dispatcher with an [`Ack`] and acknowledged explicitly once its results
+//! are stored; messages not acked within `ack_wait_ms` are redelivered
+//! (at-least-once). Messages that cannot be decoded are terminated so they
+//! are not redelivered.
+//!
+//! The job id defaults to `{stream}-{sequence}`, so a redelivered message
+//! keeps its id. `traceparent`/`tracestate` headers become the job's trace
+//! context and `meta.nats` holds subject, stream and sequence.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use async_nats::jetstream::{self, consumer::pull, message::Acker, AckKind};
+use futures::StreamExt;
+use serde_json::json;
+use tokio::sync::mpsc;
+use tracing::{info, warn};
+
+use crate::health::health;
+use crate::trace_context::{self, TRACEPARENT, TRACESTATE};
+use crate::types::{Ack, Job, NatsCfg};
+
+/// Checks the `[source.nats]` settings.
+pub fn validate(cfg: &NatsCfg) -> Result<()> {
+    anyhow::ensure!(cfg.ack_wait_ms > 0, "[source.nats] ack_wait_ms muss größer als 0 sein");
+    super::validate_encoding("[source.nats]", &cfg.encoding)
+}
+
+/// Pulls from `cfg.stream` and feeds the jobs into `tx`.
+///
+/// Runs until the input channel is closed or the runtime drains.
+pub async fn run_nats_source(cfg: NatsCfg, tx: mpsc::Sender<Job>) -> Result<()> {
+    let mut options = async_nats::ConnectOptions::new();
+    if let Some(path) = &cfg.credentials {
+        options = options
+            .credentials_file(path)
+            .await
+            .with_context(|| format!("NATS-Credentials konnten nicht gelesen werden: {}", path))?;
+    }
+    let client = options
+        .connect(cfg.url.as_str())
+        .await
+        .with_context(|| format!("Verbindung zu NATS {} fehlgeschlagen", cfg.url))?;
+    let context = jetstream::new(client);
+    let stream = context
+        .get_stream(&cfg.stream)
+        .await
+        .with_context(|| format!("JetStream-Stream {} nicht gefunden", cfg.stream))?;
+    let consumer = stream
+        .get_or_create_consumer(
+            &cfg.consumer,
+            pull::Config {
+                durable_name: Some(cfg.consumer.clone()),
+                filter_subject: cfg.subject.clone().unwrap_or_default(),
+                ack_policy: jetstream::consumer::AckPolicy::Explicit,
+                ack_wait: Duration::from_millis(cfg.ack_wait_ms),
+                max_ack_pending: cfg.max_ack_pending,
+                ..Default::default()
+            },
+        )
+        .await
+        .with_context(|| format!("JetStream-Consumer {} konnte nicht angelegt werden", cfg.consumer))?;
+    let mut messages = consumer
+        .messages()
+        .await
+        .with_context(|| format!("JetStream-Consumer {} liefert keine Nachrichten", cfg.consumer))?;
+    info!("NATS-Quelle liest {} (Consumer {}, {})", cfg.stream, cfg.consumer, cfg.url);
+
+    let pending: Arc<Mutex<HashMap<String, Acker>>> = Arc::default();
+    let ack_tx = spawn_acker(Arc::clone(&pending));
+    loop {
+        // Beim Drain keine neuen Nachrichten mehr annehmen
+        if health().is_draining() {
+            info!("NATS-Quelle im Drain, liest keine Nachrichten mehr");
+            return Ok(());
+        }
+        // Pausiert: nicht quittierte Nachrichten stellt der Server nach ack_wait erneut zu
+        if health().is_paused() {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            continue;
+        }
+        let message = match tokio::time::timeout(Duration::from_secs(1), messages.next()).await {
+            Err(_) => continue,
+            Ok(None) => anyhow::bail!("JetStream-Consumer {} beendet", cfg.consumer),
+            Ok(Some(Err(e))) => {
+                warn!("NATS-Fehler auf {}: {}", cfg.stream, e);
+                tokio::time::sleep(Duration::from_secs(1)).await;
+                continue;
+            }
+            Ok(Some(Ok(message))) => message,
+        };
+
+        let sequence = match message.info() {
+            Ok(info) => info.stream_sequence,
+            Err(e) => {
+                warn!("NATS-Nachricht auf {} ohne JetStream-Info: {}", message.subject, e);
+                continue;
+            }
+        };
+        let subject = message.subject.to_string();
+        let decoded = super::decode(&cfg.encoding, &message.payload, format!("{}-{}", cfg.stream, sequence));
+        let header = |name: &str| Some(message.headers.as_ref()?.get(name)?.as_str());
+        let (traceparent, tracestate) = (header(TRACEPARENT).map(str::to_owned), header(TRACESTATE).map(str::to_owned));
+        let (_, acker) = message.split();
+
+        let mut job = match decoded {
+            Ok(job) => job,
+            Err(e) => {
+                // Nicht dekodierbar: beenden statt erneut zustellen
+                warn!("NATS-Nachricht {}@{} verworfen: {:#}", cfg.stream, sequence, e);
+                if let Err(e) = acker.ack_with(AckKind::Term).await {
+                    warn!("NATS-Nachricht {}@{} nicht beendet: {}", cfg.stream, sequence, e);
+                }
+                continue;
+            }
+        };
+        trace_context::inject(&mut job.meta, traceparent.as_deref(), tracestate.as_deref());
+        job.meta.insert("nats".to_string(), json!({ "subject": subject, "stream": cfg.stream, "sequence": sequence }));
+
+        let token = sequence.to_string();
+        pending.lock().unwrap().insert(token.clone(), acker);
+        job.ack = Some(Ack::new(ack_tx.clone(), token));
+        if tx.send(job).await.is_err() {
+            return Ok(()); // Runtime beendet
+        }
+    }
+}
+
+/// Starts the task acknowledging messages whose results are stored.
+fn spawn_acker(pending: Arc<Mutex<HashMap<String, Acker>>>) -> mpsc::UnboundedSender<String> {
+    let (tx, mut rx) = mpsc::unbounded_channel::<String>();
+    tokio::spawn(async move {
+        while let Some(token) = rx.recv().await {
+            // Doppelte Quittungen (z. B. nach Retry) haben keinen Eintrag mehr
+            let Some(acker) = pending.lock().unwrap().remove(&token) else { continue };
+            if let Err(e) = acker.ack().await {
+                warn!("NATS-Nachricht {} nicht quittiert: {}", token, e);
+            }
+        }
+    });
+    tx
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate() {
+        let cfg = |extra: &str| toml::from_str::<NatsCfg>(&format!("stream = \"JOBS\"\n{}", extra)).unwrap();
+        let defaults = cfg("");
+        assert_eq!(defaults.url, "nats://localhost:4222");
+        assert_eq!(defaults.consumer, "omniengine");
+        assert!(validate(&defaults).is_ok());
+        assert!(validate(&cfg("encoding = \"avro\"")).is_err());
+        assert!(validate(&cfg("ack_wait_ms = 0")).is_err());
+    }
+}
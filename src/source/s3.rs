@@ -0,0 +1,283 @@
+//! Object store polling source (object_store) for cloud batch inference.
+//!
+//! Lists `s3://bucket/prefix` every `poll_secs` and turns new objects with
+//! one of the configured extensions into jobs, oldest first: `.npy` objects
+//! are loaded as samples, images are decoded and resized like in
+//! `omniengine infer`. The job id is the object key, `meta.source` holds the
+//! `s3://` URI.
+//!
+//! Processed keys are tracked in the Redis set `processed_set`, so objects
+//! are not scored again after a restart. A key is added once its result is
+//! stored; objects that cannot be decoded or whose inference fails go to
+//! `{processed_set}:failed` instead (remove them there to retry). Polling is
+//! a singleton: with `[k8s] leader_election` only the leader lists the
+//! bucket.
+
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use futures::TryStreamExt;
+use object_store::path::Path;
+use object_store::{ObjectMeta, ObjectStore};
+use tokio::sync::mpsc;
+use tracing::{debug, info, warn};
+
+use crate::health::health;
+use crate::types::{Ack, Config, Job, S3Cfg};
+
+/// Keys submitted but not yet settled.
+type InFlight = Arc<Mutex<HashSet<String>>>;
+
+/// Checks the `[source.s3]` settings; processed keys need a real Redis.
+pub fn validate(cfg: &S3Cfg, redis_url: &str) -> Result<()> {
+    parse_uri(&cfg.uri)?;
+    anyhow::ensure!(!cfg.extensions.is_empty(), "[source.s3] extensions darf nicht leer sein");
+    anyhow::ensure!(cfg.poll_secs > 0, "[source.s3] poll_secs muss größer als 0 sein");
+    anyhow::ensure!(
+        redis_url != crate::storage::redis_store::MEMORY_URL,
+        "[source.s3] benötigt Redis für verarbeitete Keys (nicht memory://)"
+    );
+    Ok(())
+}
+
+/// Bucket and key prefix of `s3://bucket/prefix`.
+fn parse_uri(uri: &str) -> Result<(&str, &str)> {
+    let rest = uri.strip_prefix("s3://").with_context(|| format!("[source.s3] uri '{}' muss mit s3:// beginnen", uri))?;
+    let (bucket, prefix) = rest.split_once('/').unwrap_or((rest, ""));
+    anyhow::ensure!(!bucket.is_empty(), "[source.s3] uri '{}' ohne Bucket", uri);
+    Ok((bucket, prefix.trim_end_matches('/')))
+}
+
+/// Polls `cfg.uri` and feeds new objects into `tx`; `config` describes
+/// the model input for decoding.
+///
+/// Runs until the input channel is closed or the runtime drains.
+pub async fn run_s3_source(cfg: S3Cfg, config: Config, tx: mpsc::Sender<Job>) -> Result<()> {
+    let (bucket, prefix) = parse_uri(&cfg.uri)?;
+    let mut builder = object_store::aws::AmazonS3Builder::from_env().with_bucket_name(bucket);
+    if let Some(endpoint) = &cfg.endpoint {
+        builder = builder.with_endpoint(endpoint).with_allow_http(endpoint.starts_with("http://"));
+    }
+    let store = builder.build().with_context(|| format!("S3-Bucket '{}' nicht nutzbar", bucket))?;
+    let client = redis::Client::open(config.redis.url.as_str())?;
+    let mut con = client.get_multiplexed_async_connection().await.context("Redis für [source.s3] nicht erreichbar")?;
+    info!("S3-Quelle liest {} alle {} s", cfg.uri, cfg.poll_secs);
+
+    let in_flight: InFlight = Arc::default();
+    let (ack_tx, nack_tx) = spawn_marker(cfg.processed_set.clone(), con.clone(), Arc::clone(&in_flight));
+    let failed_set = format!("{}:failed", cfg.processed_set);
+    loop {
+        if health().is_draining() {
+            info!("S3-Quelle im Drain, liest keine Objekte mehr");
+            return Ok(());
+        }
+        // Pausiert: nicht listen, Objekte bleiben liegen
+        if health().is_paused() {
+            tokio::time::sleep(Duration::from_secs(1)).await;
+            continue;
+        }
+
+        let skip = in_flight.lock().unwrap().clone();
+        let candidates = match list_new(&store, prefix, &cfg.extensions, &skip).await {
+            Ok(candidates) => candidates,
+            Err(e) => {
+                warn!("S3-Listing von {} fehlgeschlagen: {:#}", cfg.uri, e);
+                tokio::time::sleep(Duration::from_secs(cfg.poll_secs)).await;
+                continue;
+            }
+        };
+        let fresh = unprocessed(&mut con, &cfg.processed_set, &failed_set, candidates).await?;
+        if !fresh.is_empty() {
+            debug!("{} neue Objekte unter {}", fresh.len(), cfg.uri);
+        }
+        for object in fresh {
+            if health().is_draining() {
+                break;
+            }
+            let key = object.location.to_string();
+            let job = match fetch(&store, &object, cfg.max_object_bytes).await {
+                Ok(bytes) => to_job(&config, bucket, &key, &bytes),
+                Err(e) => Err(e),
+            };
+            let mut job = match job {
+                Ok(job) => job,
+                Err(e) => {
+                    warn!("S3-Objekt {} übersprungen: {:#}", key, e);
+                    redis::AsyncCommands::sadd::<_, _, ()>(&mut con, &failed_set, &key).await?;
+                    continue;
+                }
+            };
+            in_flight.lock().unwrap().insert(key.clone());
+            job.ack = Some(Ack::with_nack(ack_tx.clone(), nack_tx.clone(), key));
+            if tx.send(job).await.is_err() {
+                return Ok(()); // Runtime beendet
+            }
+        }
+        tokio::time::sleep(Duration::from_secs(cfg.poll_secs)).await;
+    }
+}
+
+/// Objects under `prefix` with an accepted extension that are not in
+/// `skip`, oldest first.
+async fn list_new(store: &dyn ObjectStore, prefix: &str, extensions: &[String], skip: &HashSet<String>) -> Result<Vec<ObjectMeta>> {
+    let prefix = (!prefix.is_empty()).then(|| Path::from(prefix));
+    let mut objects: Vec<ObjectMeta> = store
+        .list(prefix.as_ref())
+        .try_filter(|object| {
+            let key = object.location.as_ref();
+            let accepted = object
+                .location
+                .extension()
+                .is_some_and(|extension| extensions.iter().any(|e| e.eq_ignore_ascii_case(extension)));
+            futures::future::ready(accepted && !skip.contains(key))
+        })
+        .try_collect()
+        .await?;
+    objects.sort_by(|a, b| (a.last_modified, &a.location).cmp(&(b.last_modified, &b.location)));
+    Ok(objects)
+}
+
+/// Drops the objects already in the processed or failed set.
+async fn unprocessed(
+    con: &mut redis::aio::MultiplexedConnection,
+    processed_set: &str,
+    failed_set: &str,
+    objects: Vec<ObjectMeta>,
+) -> Result<Vec<ObjectMeta>> {
+    let mut fresh = Vec::new();
+    for chunk in objects.chunks(500) {
+        let mut pipe = redis::pipe();
+        for object in chunk {
+            let key = object.location.as_ref();
+            pipe.sismember(processed_set, key).sismember(failed_set, key);
+        }
+        let seen: Vec<bool> = pipe.query_async(con).await.context("Verarbeitete S3-Keys nicht lesbar")?;
+        let done = seen.chunks(2).map(|pair| pair.iter().any(|&s| s));
+        fresh.extend(chunk.iter().zip(done).filter(|(_, done)| !done).map(|(object, _)| object.clone()));
+    }
+    Ok(fresh)
+}
+
+/// Downloads `object` unless it exceeds `max_bytes`.
+async fn fetch(store: &dyn ObjectStore, object: &ObjectMeta, max_bytes: usize) -> Result<Vec<u8>> {
+    anyhow::ensure!(object.size <= max_bytes, "{} Bytes, erlaubt sind {}", object.size, max_bytes);
+    Ok(store.get(&object.location).await?.bytes().await?.to_vec())
+}
+
+/// Decodes an object into a job named after its key.
+fn to_job(config: &Config, bucket: &str, key: &str, bytes: &[u8]) -> Result<Job> {
+    let tensor = if key.to_ascii_lowercase().ends_with(".npy") {
+        crate::cli::sample_from_array(config, crate::npy::parse(bytes)?)?
+    } else {
+        crate::cli::decode_image(bytes, &config.input_spec())?
+    };
+    let mut job = Job { id: key.to_string(), tensor, ..Default::default() };
+    job.meta.insert("source".to_string(), format!("s3://{}/{}", bucket, key).into());
+    Ok(job)
+}
+
+/// Starts the task recording settled keys: stored results in
+/// `processed_set`, failed jobs in `{processed_set}:failed`.
+fn spawn_marker(
+    processed_set: String,
+    mut con: redis::aio::MultiplexedConnection,
+    in_flight: InFlight,
+) -> (mpsc::UnboundedSender<String>, mpsc::UnboundedSender<String>) {
+    let (ack_tx, mut ack_rx) = mpsc::unbounded_channel::<String>();
+    let (nack_tx, mut nack_rx) = mpsc::unbounded_channel::<String>();
+    let failed_set = format!("{}:failed", processed_set);
+    tokio::spawn(async move {
+        loop {
+            let (key, set) = tokio::select! {
+                Some(key) = ack_rx.recv() => (key, &processed_set),
+                Some(key) = nack_rx.recv() => {
+                    warn!("S3-Objekt {} fehlgeschlagen", key);
+                    (key, &failed_set)
+                }
+                else => break,
+            };
+            if let Err(e) = redis::AsyncCommands::sadd::<_, _, ()>(&mut con, set, &key).await {
+                // Ohne Eintrag wird das Objekt beim nächsten Listing erneut bewertet
+                warn!("S3-Key {} nicht als verarbeitet markiert: {}", key, e);
+            }
+            in_flight.lock().unwrap().remove(&key);
+        }
+    });
+    (ack_tx, nack_tx)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use object_store::memory::InMemory;
+    use object_store::PutPayload;
+
+    #[test]
+    fn test_validate_uri() {
+        let cfg = |uri: &str| toml::from_str::<S3Cfg>(&format!("uri = \"{}\"", uri)).unwrap();
+        assert_eq!(parse_uri("s3://scans/incoming/2024/").unwrap(), ("scans", "incoming/2024"));
+        assert_eq!(parse_uri("s3://scans").unwrap(), ("scans", ""));
+        assert!(parse_uri("gs://scans/x").is_err());
+        assert!(parse_uri("s3:///x").is_err());
+
+        let defaults = cfg("s3://scans/in");
+        assert_eq!((defaults.poll_secs, defaults.processed_set.as_str()), (30, "omniengine:s3:processed"));
+        assert!(validate(&defaults, "redis://127.0.0.1/").is_ok());
+        assert!(validate(&defaults, crate::storage::redis_store::MEMORY_URL).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_list_new_filters_and_orders() {
+        let store = InMemory::new();
+        for key in ["in/b.npy", "in/a.PNG", "in/notes.txt", "other/c.npy", "in/done.npy"] {
+            store.put(&Path::from(key), PutPayload::from_static(b"x")).await.unwrap();
+            tokio::time::sleep(Duration::from_millis(2)).await;
+        }
+        let extensions = vec!["npy".to_string(), "png".to_string()];
+        let skip = HashSet::from(["in/done.npy".to_string()]);
+        let objects = list_new(&store, "in", &extensions, &skip).await.unwrap();
+        let keys: Vec<String> = objects.iter().map(|o| o.location.to_string()).collect();
+        // In Schreibreihenfolge (last_modified), Fremdpräfix und -endung ausgelassen
+        assert_eq!(keys, vec!["in/b.npy", "in/a.PNG"]);
+
+        let too_large = fetch(&store, &objects[0], 0).await.unwrap_err();
+        assert!(too_large.to_string().contains("1 Bytes"), "{}", too_large);
+        assert_eq!(fetch(&store, &objects[0], 1).await.unwrap(), b"x");
+    }
+
+    #[test]
+    fn test_to_job_from_npy() {
+        let config: Config = toml::from_str(
+            r#"
+            [model]
+            backend = "onnx"
+            device = "cpu"
+            model_path = "model.onnx"
+            input_names = ["input"]
+            input_shapes = [[0, 1, 2, 1]]
+            output_names = ["output"]
+            output_shapes = [[0, 2]]
+            [queue]
+            max_batch = 4
+            max_wait_ms = 5
+            [redis]
+            url = "redis://127.0.0.1/"
+            out_prefix = "results"
+            [input]
+            batch = 4
+            channels = 1
+            height = 2
+            width = 1
+            dtype = "f32"
+            "#,
+        )
+        .unwrap();
+        let sample = ndarray::ArrayD::from_elem(ndarray::IxDyn(&[1, 1, 2, 1]), 0.5f32);
+        let job = to_job(&config, "scans", "in/a.npy", &crate::npy::to_bytes(&sample)).unwrap();
+        assert_eq!((job.id.as_str(), job.tensor.shape()), ("in/a.npy", &[1, 2, 1][..]));
+        assert_eq!(job.meta["source"], "s3://scans/in/a.npy");
+        assert!(to_job(&config, "scans", "in/b.png", b"not an image").is_err());
+    }
+}
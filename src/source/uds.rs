@@ -0,0 +1,204 @@
+//! Unix domain socket source for co-located producers.
+//!
+//! Producers connect to `[source.uds] path` and write one binary frame per
+//! job; results are read from the result store as usual. A frame, all
+//! integers little-endian:
+//!
+//! * `u32` length of the rest of the frame
+//! * `u16` length of the job id, then the id (UTF-8; empty: `uds-{connection}-{n}`)
+//! * `u8` dtype: `0` f32, `1` f16, `2` bf16, `3` u8, `4` i32, `5` f64
+//! * `u8` rank, then one `u32` per axis
+//! * the elements in C order
+//!
+//! There is no reply. While the input queue is full (or the runtime is
+//! paused) the connection is not read, so producers block in their writes.
+//! A malformed frame closes the connection.
+
+use std::io::ErrorKind;
+use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+use std::path::Path;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use ndarray::{ArrayD, IxDyn};
+use tokio::io::{AsyncRead, AsyncReadExt, BufReader};
+use tokio::net::UnixListener;
+use tokio::sync::mpsc;
+use tracing::{debug, info, warn};
+
+use crate::health::health;
+use crate::storage::redis_store::decode_values;
+use crate::types::{Job, TensorDtype, UdsCfg};
+
+/// Listens on `cfg.path` and feeds the jobs of all connections into `tx`.
+///
+/// Runs until the input channel is closed or the runtime drains.
+pub async fn run_uds_source(cfg: UdsCfg, tx: mpsc::Sender<Job>) -> Result<()> {
+    let path = Path::new(&cfg.path);
+    // Verwaiste Socket-Datei eines früheren Laufs ersetzen, andere Dateien nicht
+    if let Ok(meta) = std::fs::symlink_metadata(path) {
+        anyhow::ensure!(meta.file_type().is_socket(), "[source.uds] path {} ist kein Socket", cfg.path);
+        std::fs::remove_file(path).with_context(|| format!("Socket {} konnte nicht entfernt werden", cfg.path))?;
+    }
+    let listener = UnixListener::bind(path).with_context(|| format!("Socket {} konnte nicht geöffnet werden", cfg.path))?;
+    if let Some(mode) = cfg.mode {
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))
+            .with_context(|| format!("Rechte von {} konnten nicht gesetzt werden", cfg.path))?;
+    }
+    info!("UDS-Quelle auf {}", cfg.path);
+
+    let mut connections = 0u64;
+    loop {
+        if health().is_draining() || tx.is_closed() {
+            info!("UDS-Quelle beendet, nimmt keine Verbindungen mehr an");
+            let _ = std::fs::remove_file(path);
+            return Ok(());
+        }
+        let stream = match tokio::time::timeout(Duration::from_secs(1), listener.accept()).await {
+            Err(_) => continue,
+            Ok(Err(e)) => {
+                warn!("UDS-Verbindung auf {} nicht angenommen: {}", cfg.path, e);
+                continue;
+            }
+            Ok(Ok((stream, _))) => stream,
+        };
+        connections += 1;
+        let (connection, max_frame_bytes, tx) = (connections, cfg.max_frame_bytes, tx.clone());
+        tokio::spawn(async move {
+            match read_frames(BufReader::new(stream), connection, max_frame_bytes, tx).await {
+                Ok(jobs) => debug!("UDS-Verbindung {} geschlossen ({} Jobs)", connection, jobs),
+                Err(e) => warn!("UDS-Verbindung {} abgebrochen: {:#}", connection, e),
+            }
+        });
+    }
+}
+
+/// Reads frames from one producer until it disconnects; returns the number
+/// of jobs.
+async fn read_frames(
+    mut reader: impl AsyncRead + Unpin,
+    connection: u64,
+    max_frame_bytes: usize,
+    tx: mpsc::Sender<Job>,
+) -> Result<u64> {
+    let mut frame = Vec::new();
+    let mut jobs = 0u64;
+    loop {
+        // Pausiert: nicht lesen, der Produzent blockiert
+        while health().is_paused() && !health().is_draining() {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        if health().is_draining() {
+            return Ok(jobs);
+        }
+        let len = match reader.read_u32_le().await {
+            Ok(len) => len as usize,
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(jobs),
+            Err(e) => return Err(e.into()),
+        };
+        anyhow::ensure!(
+            len <= max_frame_bytes,
+            "Frame mit {} Bytes überschreitet max_frame_bytes ({})",
+            len, max_frame_bytes
+        );
+        frame.resize(len, 0);
+        reader.read_exact(&mut frame).await.context("Frame abgeschnitten")?;
+        let job = parse_frame(&frame, || format!("uds-{}-{}", connection, jobs))?;
+        if tx.send(job).await.is_err() {
+            return Ok(jobs); // Runtime beendet
+        }
+        jobs += 1;
+    }
+}
+
+/// Decodes one frame (without its length prefix) into a job.
+fn parse_frame(frame: &[u8], default_id: impl FnOnce() -> String) -> Result<Job> {
+    let mut rest = frame;
+    let id_len = u16::from_le_bytes(take(&mut rest, 2)?.try_into()?) as usize;
+    let id = std::str::from_utf8(take(&mut rest, id_len)?).context("Job-ID ist kein UTF-8")?;
+    let dtype = take(&mut rest, 1)?[0];
+    let rank = take(&mut rest, 1)?[0] as usize;
+    let shape = (0..rank)
+        .map(|_| Ok(u32::from_le_bytes(take(&mut rest, 4)?.try_into()?) as usize))
+        .collect::<Result<Vec<_>>>()?;
+
+    let size: usize = match dtype {
+        0 | 4 => 4,
+        1 | 2 => 2,
+        3 => 1,
+        5 => 8,
+        other => anyhow::bail!("Unbekannter dtype {} (erwartet: 0-5)", other),
+    };
+    let bytes = shape.iter().try_fold(size, |n, &d| n.checked_mul(d)).context("Shape zu groß")?;
+    anyhow::ensure!(
+        rest.len() == bytes,
+        "Frame hat {} Datenbytes, Shape {:?} braucht {}",
+        rest.len(), shape, bytes
+    );
+    let values = match dtype {
+        0 => decode_values(rest, TensorDtype::F32, 1.0),
+        1 => decode_values(rest, TensorDtype::F16, 1.0),
+        2 => decode_values(rest, TensorDtype::Bf16, 1.0),
+        3 => rest.iter().map(|&b| b as f32).collect(),
+        4 => rest.chunks_exact(4).map(|b| i32::from_le_bytes(b.try_into().unwrap()) as f32).collect(),
+        _ => rest.chunks_exact(8).map(|b| f64::from_le_bytes(b.try_into().unwrap()) as f32).collect(),
+    };
+    let id = if id.is_empty() { default_id() } else { id.to_string() };
+    Ok(Job { id, tensor: ArrayD::from_shape_vec(IxDyn(&shape), values)?, ..Default::default() })
+}
+
+/// Splits the first `n` bytes off `rest`.
+fn take<'a>(rest: &mut &'a [u8], n: usize) -> Result<&'a [u8]> {
+    anyhow::ensure!(rest.len() >= n, "Frame abgeschnitten");
+    let (head, tail) = rest.split_at(n);
+    *rest = tail;
+    Ok(head)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(id: &str, dtype: u8, shape: &[u32], data: &[u8]) -> Vec<u8> {
+        let mut body = (id.len() as u16).to_le_bytes().to_vec();
+        body.extend(id.as_bytes());
+        body.extend([dtype, shape.len() as u8]);
+        body.extend(shape.iter().flat_map(|d| d.to_le_bytes()));
+        body.extend(data);
+        let mut out = (body.len() as u32).to_le_bytes().to_vec();
+        out.extend(body);
+        out
+    }
+
+    #[test]
+    fn test_parse_frame() {
+        let data: Vec<u8> = [1.0f32, 2.0, 3.0, 4.0, 5.0, 6.0].iter().flat_map(|v| v.to_le_bytes()).collect();
+        let job = parse_frame(&frame("j1", 0, &[2, 3], &data)[4..], || unreachable!()).unwrap();
+        assert_eq!(job.id, "j1");
+        assert_eq!(job.tensor.shape(), &[2, 3]);
+        assert_eq!(job.tensor[[1, 2]], 6.0);
+
+        let job = parse_frame(&frame("", 3, &[3], &[0, 128, 255])[4..], || "uds-1-0".into()).unwrap();
+        assert_eq!(job.id, "uds-1-0");
+        assert_eq!(job.tensor[[2]], 255.0);
+
+        assert!(parse_frame(&frame("j2", 0, &[2], &[0; 4])[4..], String::new).is_err());
+        assert!(parse_frame(&frame("j3", 9, &[1], &[0; 4])[4..], String::new).is_err());
+        assert!(parse_frame(&[1, 0], String::new).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_read_frames_until_eof() {
+        let mut stream = frame("", 4, &[2], &[1, 0, 0, 0, 2, 0, 0, 0]);
+        stream.extend(frame("b", 5, &[1], &1.5f64.to_le_bytes()));
+        let (tx, mut rx) = mpsc::channel(4);
+        assert_eq!(read_frames(stream.as_slice(), 7, 1024, tx.clone()).await.unwrap(), 2);
+        let job = rx.try_recv().unwrap();
+        assert_eq!((job.id.as_str(), job.tensor[[1]]), ("uds-7-0", 2.0));
+        assert_eq!(rx.try_recv().unwrap().tensor[[0]], 1.5);
+
+        // Zu große und abgeschnittene Frames beenden die Verbindung
+        assert!(read_frames(stream.as_slice(), 7, 8, tx.clone()).await.is_err());
+        assert!(read_frames(&stream[..10], 7, 1024, tx).await.is_err());
+    }
+}
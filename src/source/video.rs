@@ -0,0 +1,144 @@
+//! Video stream source (RTSP, HTTP or files) via ffmpeg.
+//!
+//! Spawns an `ffmpeg` process per stream that decodes, resamples to the
+//! configured FPS and scales frames to the model input size, emitting raw
+//! RGB24 frames on stdout. Each frame becomes a `Job` with stream id, frame
+//! index and timestamps in its metadata. When the input channel is full the
+//! frame is dropped instead of stalling the decoder.
+
+use std::process::Stdio;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use chrono::Utc;
+use ndarray::{ArrayD, IxDyn};
+use tokio::io::AsyncReadExt;
+use tokio::process::Command;
+use tokio::sync::mpsc::{self, error::TrySendError};
+use tracing::{debug, info, warn};
+
+use crate::health::health;
+use crate::types::{Job, JobMeta, VideoCfg, VideoStreamCfg};
+
+/// Converts an interleaved RGB24 frame into a `[3, H, W]` tensor in `[0, 1]`.
+pub fn frame_to_tensor(frame: &[u8], width: usize, height: usize) -> Result<ArrayD<f32>> {
+    anyhow::ensure!(
+        frame.len() == width * height * 3,
+        "Frame-Größe {} passt nicht zu {}x{}x3",
+        frame.len(),
+        width,
+        height
+    );
+    let plane = width * height;
+    let mut data = vec![0f32; plane * 3];
+    for (i, px) in frame.chunks_exact(3).enumerate() {
+        for c in 0..3 {
+            data[c * plane + i] = px[c] as f32 / 255.0;
+        }
+    }
+    Ok(ArrayD::from_shape_vec(IxDyn(&[3, height, width]), data)?)
+}
+
+/// Runs one video stream until it ends (files) or the runtime shuts down.
+///
+/// Live streams (`rtsp://`, `http(s)://`) are reconnected after a short
+/// delay when ffmpeg exits.
+pub async fn run_video_source(
+    cfg: VideoCfg,
+    stream: VideoStreamCfg,
+    width: usize,
+    height: usize,
+    tx: mpsc::Sender<Job>,
+) -> Result<()> {
+    let live = ["rtsp://", "http://", "https://"].iter().any(|p| stream.url.starts_with(p));
+    let mut frame_idx: u64 = 0;
+    let mut dropped: u64 = 0;
+
+    loop {
+        info!("Video-Stream {} startet: {}", stream.id, stream.url);
+        let mut cmd = Command::new(&cfg.ffmpeg);
+        cmd.args(["-loglevel", "error"]);
+        if stream.url.starts_with("rtsp://") {
+            cmd.args(["-rtsp_transport", "tcp"]);
+        }
+        let mut child = cmd
+            .args(["-i", &stream.url])
+            .args(["-vf", &format!("fps={},scale={}:{}", cfg.fps, width, height)])
+            .args(["-f", "rawvideo", "-pix_fmt", "rgb24", "pipe:1"])
+            .stdout(Stdio::piped())
+            .stdin(Stdio::null())
+            .kill_on_drop(true)
+            .spawn()
+            .with_context(|| format!("ffmpeg konnte nicht gestartet werden ({})", cfg.ffmpeg))?;
+        let mut stdout = child.stdout.take().context("ffmpeg stdout fehlt")?;
+
+        let mut frame = vec![0u8; width * height * 3];
+        while stdout.read_exact(&mut frame).await.is_ok() {
+            if health().is_draining() {
+                info!("Video-Stream {} beendet (Drain)", stream.id);
+                return Ok(());
+            }
+            // Pausiert: Live-Frames weiterlesen, aber verwerfen
+            if health().is_paused() {
+                frame_idx += 1;
+                continue;
+            }
+            let pts_ms = (frame_idx as f64 * 1000.0 / cfg.fps as f64) as u64;
+            let mut meta = JobMeta::new();
+            meta.insert("stream_id".to_string(), stream.id.clone().into());
+            meta.insert("frame".to_string(), frame_idx.into());
+            meta.insert("pts_ms".to_string(), pts_ms.into());
+            meta.insert("captured_at".to_string(), Utc::now().to_rfc3339().into());
+
+            let job = Job {
+                id: format!("{}-{}", stream.id, frame_idx),
+                tensor: frame_to_tensor(&frame, width, height)?,
+                meta,
+                ..Default::default()
+            };
+            frame_idx += 1;
+
+            // Backpressure: Frame verwerfen statt den Decoder zu blockieren
+            match tx.try_send(job) {
+                Ok(()) => {}
+                Err(TrySendError::Full(_)) => {
+                    dropped += 1;
+                    debug!("Video-Stream {}: Frame verworfen ({} gesamt)", stream.id, dropped);
+                }
+                Err(TrySendError::Closed(_)) => {
+                    let _ = child.kill().await;
+                    return Ok(());
+                }
+            }
+        }
+
+        let status = child.wait().await?;
+        if !live {
+            info!("Video-Stream {} beendet ({} Frames, {} verworfen)", stream.id, frame_idx, dropped);
+            return Ok(());
+        }
+        warn!("Video-Stream {} unterbrochen ({}), neuer Versuch in 1s", stream.id, status);
+        tokio::time::sleep(Duration::from_secs(1)).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frame_to_tensor_planar() {
+        // 2x1 Pixel: rot, blau
+        let frame = [255, 0, 0, 0, 0, 255];
+        let t = frame_to_tensor(&frame, 2, 1).unwrap();
+        assert_eq!(t.shape(), &[3, 1, 2]);
+        assert_eq!(t[[0, 0, 0]], 1.0);
+        assert_eq!(t[[2, 0, 0]], 0.0);
+        assert_eq!(t[[2, 0, 1]], 1.0);
+    }
+
+    #[test]
+    fn test_frame_to_tensor_size_mismatch() {
+        assert!(frame_to_tensor(&[0; 5], 2, 1).is_err());
+    }
+}
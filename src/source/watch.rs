@@ -0,0 +1,287 @@
+//! Directory watcher source (notify) for batch scoring.
+//!
+//! New files in `dir` (not its subdirectories) with one of the configured
+//! extensions become jobs named after the file: `.npy` files are loaded as
+//! tensors, images are decoded and resized like in `omniengine infer`. A
+//! file is read once it has not changed for `settle_ms`, so files still
+//! being copied are not picked up half-written. Jobs wait for queue space
+//! instead of being dropped.
+//!
+//! Once a result is stored, the file is moved to `done_dir`; files that
+//! cannot be decoded or whose inference fails go to `failed_dir`. Without
+//! these directories files stay in place and are submitted again when they
+//! change (or, with `existing`, on the next start). `meta.source` holds the
+//! file path.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use notify::{RecursiveMode, Watcher};
+use tokio::sync::mpsc;
+use tracing::{debug, info, warn};
+
+use crate::health::health;
+use crate::types::{Ack, Config, Job, WatchCfg};
+
+/// Checks the `[source.watch]` settings.
+pub fn validate(cfg: &WatchCfg) -> Result<()> {
+    anyhow::ensure!(Path::new(&cfg.dir).is_dir(), "[source.watch] dir {} ist kein Verzeichnis", cfg.dir);
+    anyhow::ensure!(!cfg.extensions.is_empty(), "[source.watch] extensions darf nicht leer sein");
+    Ok(())
+}
+
+/// Watches `cfg.dir` and feeds the files into `tx`; `config` describes the
+/// model input for decoding.
+///
+/// Runs until the input channel is closed or the runtime drains.
+pub async fn run_watch_source(cfg: WatchCfg, config: Config, tx: mpsc::Sender<Job>) -> Result<()> {
+    for dir in [&cfg.done_dir, &cfg.failed_dir].into_iter().flatten() {
+        std::fs::create_dir_all(dir).with_context(|| format!("Verzeichnis {} konnte nicht angelegt werden", dir))?;
+    }
+    let (event_tx, mut events) = mpsc::unbounded_channel();
+    let mut watcher = notify::recommended_watcher(move |event| {
+        let _ = event_tx.send(event);
+    })
+    .context("Dateisystem-Watcher konnte nicht gestartet werden")?;
+    watcher
+        .watch(Path::new(&cfg.dir), RecursiveMode::NonRecursive)
+        .with_context(|| format!("Verzeichnis {} kann nicht überwacht werden", cfg.dir))?;
+    info!("Verzeichnis-Quelle überwacht {} ({})", cfg.dir, cfg.extensions.join(", "));
+
+    // Datei -> (Größe, letzte Änderung), bis sie sich `settle_ms` nicht mehr ändert
+    let mut settling = HashMap::new();
+    if cfg.existing {
+        for entry in std::fs::read_dir(&cfg.dir)? {
+            note(&cfg, &mut settling, entry?.path());
+        }
+    }
+    let (ack_tx, nack_tx) = spawn_mover(cfg.clone());
+    let settle = Duration::from_millis(cfg.settle_ms);
+    let mut tick = tokio::time::interval(Duration::from_millis(100));
+    loop {
+        if health().is_draining() {
+            info!("Verzeichnis-Quelle im Drain, liest keine Dateien mehr");
+            return Ok(());
+        }
+        tokio::select! {
+            event = events.recv() => match event {
+                None => anyhow::bail!("Dateisystem-Watcher für {} beendet", cfg.dir),
+                Some(Err(e)) => warn!("Dateisystem-Watcher für {}: {}", cfg.dir, e),
+                Some(Ok(event)) => {
+                    for path in event.paths {
+                        note(&cfg, &mut settling, path);
+                    }
+                }
+            },
+            _ = tick.tick() => {}
+        }
+        // Pausiert: Dateien bleiben liegen, bis es weitergeht
+        if health().is_paused() {
+            continue;
+        }
+
+        for path in settled(&mut settling, settle) {
+            let mut job = match crate::cli::load_input(&config, None, &path) {
+                Ok(job) => job,
+                Err(e) => {
+                    warn!("Datei {} übersprungen: {:#}", path.display(), e);
+                    move_to(&path, cfg.failed_dir.as_deref());
+                    continue;
+                }
+            };
+            debug!("Datei {} eingereiht", path.display());
+            job.ack = Some(Ack::with_nack(ack_tx.clone(), nack_tx.clone(), path.display().to_string()));
+            if tx.send(job).await.is_err() {
+                return Ok(()); // Runtime beendet
+            }
+        }
+    }
+}
+
+/// Whether `path` has one of the accepted extensions.
+fn accepts(cfg: &WatchCfg, path: &Path) -> bool {
+    let Some(extension) = path.extension().and_then(|e| e.to_str()) else { return false };
+    cfg.extensions.iter().any(|accepted| accepted.eq_ignore_ascii_case(extension))
+}
+
+/// Restarts the quiet period of a created or changed file.
+fn note(cfg: &WatchCfg, settling: &mut HashMap<PathBuf, (u64, Instant)>, path: PathBuf) {
+    if !accepts(cfg, &path) {
+        return;
+    }
+    // Entfernte oder verschobene Dateien fallen hier heraus
+    match std::fs::metadata(&path) {
+        Ok(meta) if meta.is_file() => {
+            settling.insert(path, (meta.len(), Instant::now()));
+        }
+        _ => {
+            settling.remove(&path);
+        }
+    }
+}
+
+/// Removes and returns the files that did not change for `settle`.
+fn settled(settling: &mut HashMap<PathBuf, (u64, Instant)>, settle: Duration) -> Vec<PathBuf> {
+    let mut ready = Vec::new();
+    settling.retain(|path, (size, since)| {
+        if since.elapsed() < settle {
+            return true;
+        }
+        match std::fs::metadata(path) {
+            // Ohne Ereignis gewachsen (z. B. Netzlaufwerk): weiter warten
+            Ok(meta) if meta.len() != *size => {
+                (*size, *since) = (meta.len(), Instant::now());
+                true
+            }
+            Ok(_) => {
+                ready.push(path.clone());
+                false
+            }
+            Err(_) => false,
+        }
+    });
+    ready.sort();
+    ready
+}
+
+/// Starts the task moving files to `done_dir` once their result is stored
+/// and to `failed_dir` if their job fails.
+fn spawn_mover(cfg: WatchCfg) -> (mpsc::UnboundedSender<String>, mpsc::UnboundedSender<String>) {
+    let (ack_tx, mut ack_rx) = mpsc::unbounded_channel::<String>();
+    let (nack_tx, mut nack_rx) = mpsc::unbounded_channel::<String>();
+    tokio::spawn(async move {
+        loop {
+            let (path, target) = tokio::select! {
+                Some(path) = ack_rx.recv() => (path, cfg.done_dir.as_deref()),
+                Some(path) = nack_rx.recv() => {
+                    warn!("Datei {} fehlgeschlagen", path);
+                    (path, cfg.failed_dir.as_deref())
+                }
+                else => break,
+            };
+            move_to(Path::new(&path), target);
+        }
+    });
+    (ack_tx, nack_tx)
+}
+
+/// Moves `path` into `dir` (if configured), replacing an older file of the
+/// same name.
+fn move_to(path: &Path, dir: Option<&str>) {
+    let (Some(dir), Some(name)) = (dir, path.file_name()) else { return };
+    let target = Path::new(dir).join(name);
+    // rename scheitert über Dateisystemgrenzen hinweg
+    let moved = std::fs::rename(path, &target).or_else(|_| std::fs::copy(path, &target).and_then(|_| std::fs::remove_file(path)));
+    if let Err(e) = moved {
+        // Doppelte Quittungen (z. B. nach Retry) finden die Datei nicht mehr
+        if path.exists() {
+            warn!("Datei {} nicht nach {} verschoben: {}", path.display(), dir, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cfg(dir: &Path, extra: &str) -> WatchCfg {
+        toml::from_str(&format!("dir = \"{}\"\n{}", dir.display(), extra)).unwrap()
+    }
+
+    #[test]
+    fn test_validate_and_accepts() {
+        let dir = std::env::temp_dir();
+        let defaults = cfg(&dir, "");
+        assert!(validate(&defaults).is_ok());
+        assert!(validate(&cfg(&dir.join("omni-watch-missing"), "")).is_err());
+        assert!(validate(&cfg(&dir, "extensions = []")).is_err());
+
+        assert!(accepts(&defaults, Path::new("scan/a.NPY")));
+        assert!(accepts(&defaults, Path::new("b.jpeg")));
+        assert!(!accepts(&defaults, Path::new("c.npy.part")));
+        assert!(!accepts(&defaults, Path::new("README")));
+    }
+
+    #[test]
+    fn test_settled_waits_for_quiet_files() {
+        let dir = std::env::temp_dir().join(format!("omni-watch-settle-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let cfg = cfg(&dir, "");
+        let path = dir.join("a.npy");
+        std::fs::write(&path, b"12").unwrap();
+
+        let mut settling = HashMap::new();
+        note(&cfg, &mut settling, path.clone());
+        note(&cfg, &mut settling, dir.join("ignored.txt"));
+        assert_eq!(settling.len(), 1);
+        assert!(settled(&mut settling, Duration::from_secs(60)).is_empty());
+
+        // Gewachsen seit dem Ereignis: Wartezeit beginnt neu
+        std::fs::write(&path, b"1234").unwrap();
+        assert!(settled(&mut settling, Duration::ZERO).is_empty());
+        assert_eq!(settled(&mut settling, Duration::ZERO), vec![path]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_files_become_jobs_and_are_moved() {
+        let dir = std::env::temp_dir().join(format!("omni-watch-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let sample = ndarray::ArrayD::from_elem(ndarray::IxDyn(&[1, 2, 1]), 0.5f32);
+        std::fs::write(dir.join("existing.npy"), crate::npy::to_bytes(&sample)).unwrap();
+
+        let done = dir.join("done");
+        let failed = dir.join("failed");
+        let extra = format!("done_dir = \"{}\"\nfailed_dir = \"{}\"\nsettle_ms = 10", done.display(), failed.display());
+        let watch_cfg = cfg(&dir, &extra);
+        let config: Config = toml::from_str(
+            r#"
+            [model]
+            backend = "onnx"
+            device = "cpu"
+            model_path = "model.onnx"
+            input_names = ["input"]
+            input_shapes = [[0, 1, 2, 1]]
+            output_names = ["output"]
+            output_shapes = [[0, 2]]
+            [queue]
+            max_batch = 4
+            max_wait_ms = 5
+            [redis]
+            url = "memory://"
+            out_prefix = "results"
+            [input]
+            batch = 4
+            channels = 1
+            height = 2
+            width = 1
+            dtype = "f32"
+            "#,
+        )
+        .unwrap();
+        let (tx, mut rx) = mpsc::channel(4);
+        tokio::spawn(run_watch_source(watch_cfg, config, tx));
+
+        let job = tokio::time::timeout(Duration::from_secs(5), rx.recv()).await.unwrap().unwrap();
+        assert_eq!(job.id, "existing.npy");
+        assert_eq!(job.tensor.shape(), &[1, 2, 1]);
+        job.ack.as_ref().unwrap().done();
+
+        // Neue Datei, deren Job ohne Ergebnis verworfen wird
+        std::fs::write(dir.join("new.npy"), crate::npy::to_bytes(&sample)).unwrap();
+        let job = tokio::time::timeout(Duration::from_secs(5), rx.recv()).await.unwrap().unwrap();
+        assert_eq!(job.id, "new.npy");
+        drop(job);
+
+        for moved in [done.join("existing.npy"), failed.join("new.npy")] {
+            let deadline = Instant::now() + Duration::from_secs(5);
+            while !moved.exists() && Instant::now() < deadline {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+            assert!(moved.exists(), "{} fehlt", moved.display());
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
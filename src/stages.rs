@@ -0,0 +1,297 @@
+//! Three-stage batch worker for CPU isolation (`[queue.stages]`).
+//!
+//! The regular worker runs preprocessing, inference and postprocessing in
+//! one loop, so a slow Python preprocessor or image decoding delays the next
+//! inference call. Here each worker is split into three stages connected by
+//! bounded channels of `capacity` batches:
+//!
+//! 1. collect and preprocess (blocking thread pool)
+//! 2. inference on a dedicated device thread that owns the engine
+//! 3. postprocess (blocking thread pool), then store in the background as
+//!    with `max_in_flight`
+//!
+//! The device thread only waits when stage 1 has no preprocessed batch
+//! ready, and full channels hold back the stage before them.
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use ndarray::ArrayD;
+use tokio::sync::mpsc;
+use tokio::task::JoinSet;
+
+use crate::batcher::{BatchPolicy, LengthSorter, Unbatchable};
+use crate::engine::Engine;
+use crate::error::OmniError;
+use crate::health::health;
+use crate::output_check::OutputCheck;
+use crate::pipeline::Pipeline;
+use crate::rebalance::JobQueue;
+use crate::storage::redis_store::RedisStorage;
+use crate::storage::vector_store::VectorSink;
+use crate::types::{Batch, BatchStats, Config};
+use crate::recent::Timings;
+use crate::worker::{batch_error, fail_batch, prepare_batch, run_model, store_batch, store_failed, stored_latency, Evicted, ModelInput};
+
+/// Batch passing through the stages; the tensor travels separately.
+struct InFlight {
+    batch: Batch,
+    stats: BatchStats,
+    started: Instant,
+    /// Sample shape and stage timings for the recent-jobs log.
+    shape: Vec<usize>,
+    timings: Timings,
+}
+
+/// Batching state of stage 1, set up by the worker.
+pub struct Collector {
+    pub rx: JobQueue,
+    pub policy: Box<dyn BatchPolicy>,
+    pub sorter: Option<LengthSorter>,
+    /// GPU of the worker; stage 1 stops when it is marked unhealthy.
+    pub device: Option<usize>,
+}
+
+/// Runs the staged worker until the job queue is closed.
+pub async fn run_staged_worker(
+    cfg: Config,
+    engine: Box<dyn Engine>,
+    collector: Collector,
+    store: RedisStorage,
+    pipeline: Pipeline,
+    vectors: Option<Arc<VectorSink>>,
+    worker: String,
+) -> Result<()> {
+    let capacity = cfg.queue.stages.as_ref().map_or(2, |s| s.capacity).max(1);
+    let max_in_flight = cfg.queue.max_in_flight.max(1);
+    let cfg = Arc::new(cfg);
+    let pipeline = Arc::new(pipeline);
+
+    let (prepared_tx, prepared_rx) = mpsc::channel(capacity);
+    let (done_tx, done_rx) = mpsc::unbounded_channel();
+    let mut outputs = spawn_device_thread(engine, prepared_rx, capacity)?;
+    let mut collecting = tokio::spawn(collect_and_prepare(
+        Arc::clone(&cfg),
+        Arc::clone(&pipeline),
+        collector,
+        store.clone(),
+        prepared_tx,
+        done_rx,
+        worker.clone(),
+    ));
+
+    // Stufe 3: Nachverarbeitung, Speichern im Hintergrund
+    let check = OutputCheck::from_config(&cfg, &worker).map(Arc::new);
+    let mut storing: JoinSet<Duration> = JoinSet::new();
+    let stored: Result<()> = async {
+        while let Some((flight, y, inference)) = outputs.recv().await {
+            let InFlight { batch, stats, started, shape, mut timings } = flight;
+            timings.inference = inference;
+            let post = Arc::clone(&pipeline);
+            let stage = Instant::now();
+            let y = match tokio::task::spawn_blocking(move || post.run_post(y?)).await? {
+                Ok(y) => y,
+                Err(e) => {
+                    timings.postprocess = stage.elapsed();
+                    crate::recent::record(&worker, &batch.ids[..batch.actual_len], &shape, &stats.queue_waits, &timings, Err(&e));
+                    fail_batch(&store, &worker, &batch, batch_error(&e, true)).await;
+                    continue;
+                }
+            };
+            timings.postprocess = stage.elapsed();
+
+            while storing.len() >= max_in_flight {
+                let Some(done) = storing.join_next().await else { break };
+                if let Some(latency) = stored_latency(done, &worker) {
+                    let _ = done_tx.send(latency);
+                }
+            }
+            let (store, vectors, worker) = (store.clone(), vectors.clone(), worker.clone());
+            let (output, check) = (Arc::clone(&pipeline.output), check.clone());
+            storing.spawn(async move {
+                let jobs = batch.actual_len;
+                let stage = Instant::now();
+                let stored = store_batch(&store, vectors.as_deref(), &batch, y, output.as_ref(), check.as_deref()).await;
+                timings.store = stage.elapsed();
+                crate::recent::record(&worker, &batch.ids[..jobs], &shape, &stats.queue_waits, &timings, stored.as_ref().copied());
+                match stored {
+                    Ok(()) => health().record_batch(&worker, jobs, &stats),
+                    Err(e) => store_failed(&store, &worker, &batch, &e).await,
+                }
+                started.elapsed()
+            });
+        }
+        while let Some(done) = storing.join_next().await {
+            if let Some(latency) = stored_latency(done, &worker) {
+                let _ = done_tx.send(latency);
+            }
+        }
+        Ok(())
+    }
+    .await;
+
+    if stored.is_err() {
+        // Stufe 1 wartet evtl. auf Jobs: beenden, der Worker fällt ohnehin aus
+        collecting.abort();
+    }
+    let collected = (&mut collecting).await;
+    stored?;
+    collected?
+}
+
+/// Stage 1: collects batches and preprocesses them on the blocking pool.
+async fn collect_and_prepare(
+    cfg: Arc<Config>,
+    pipeline: Arc<Pipeline>,
+    mut collector: Collector,
+    store: RedisStorage,
+    prepared: mpsc::Sender<(InFlight, ModelInput)>,
+    mut done: mpsc::UnboundedReceiver<Duration>,
+    worker: String,
+) -> Result<()> {
+    let spec = cfg.input_spec();
+    let tta = crate::tta::Tta::from_config(&cfg)?;
+    // Job, der nicht mehr in den letzten Batch passte (Byte-Budget)
+    let mut held = None;
+
+    loop {
+        // Defekte GPU: keine neuen Batches; Geräte- und Speicherstufe laufen leer
+        if let Some(mut evicted) = Evicted::check(collector.device, &worker) {
+            evicted.jobs.extend(held);
+            evicted.jobs.extend(collector.sorter.as_mut().map(LengthSorter::take_pending).unwrap_or_default());
+            return Err(evicted.into());
+        }
+        // Latenzen fertig gespeicherter Batches an die Policy melden
+        while let Ok(elapsed) = done.try_recv() {
+            let depth = collector.rx.lock().await.len();
+            collector.policy.observe(elapsed, depth);
+        }
+
+        let next = {
+            let mut rx = collector.rx.lock().await;
+            match collector.sorter.as_mut() {
+                Some(s) => s.next_batch(spec.batch, &mut rx, collector.policy.as_ref()).await,
+                None => crate::batcher::collect_batch_held(spec.batch, &mut rx, collector.policy.as_ref(), &mut held).await,
+            }
+        };
+        let batch = match next {
+            Ok(Some(batch)) => batch,
+            Ok(None) => return Ok(()), // Channel geschlossen
+            Err(e) => {
+                // Nicht stapelbare Jobs abweisen, die Stufe läuft weiter
+                let rejected = e.downcast::<Unbatchable>()?;
+                fail_batch(&store, &worker, &rejected.batch, OmniError::InvalidInput(rejected.reason)).await;
+                continue;
+            }
+        };
+        // Das Sammeln wartet beliebig lange auf den ersten Job: vor dem Start erneut prüfen
+        if let Some(mut evicted) = Evicted::check(collector.device, &worker) {
+            evicted.jobs = crate::batcher::unstack(batch);
+            evicted.jobs.extend(held);
+            evicted.jobs.extend(collector.sorter.as_mut().map(LengthSorter::take_pending).unwrap_or_default());
+            return Err(evicted.into());
+        }
+        let started = Instant::now();
+
+        let Batch { ids, tensor, actual_len, metas, inputs, acks, stats } = batch;
+        let shape = tensor.shape()[1..].to_vec();
+        let (pre_cfg, pre) = (Arc::clone(&cfg), Arc::clone(&pipeline));
+        let input = match tokio::task::spawn_blocking(move || prepare_batch(&pre, &pre_cfg, tensor, inputs)).await? {
+            Ok(input) => input,
+            Err(e) => {
+                let timings = Timings { preprocess: started.elapsed(), ..Default::default() };
+                crate::recent::record(&worker, &ids[..actual_len], &shape, &stats.queue_waits, &timings, Err(&e));
+                let failed = Batch { ids, actual_len, metas, acks, ..Default::default() };
+                fail_batch(&store, &worker, &failed, batch_error(&e, false)).await;
+                continue;
+            }
+        };
+        let input = crate::tta::augment(input, tta.as_ref(), &metas);
+        let timings = Timings { preprocess: started.elapsed(), ..Default::default() };
+
+        let batch = Batch { ids, actual_len, metas, acks, ..Default::default() };
+        if prepared.send((InFlight { batch, stats, started, shape, timings }, input)).await.is_err() {
+            return Ok(()); // Gerätestufe beendet
+        }
+    }
+}
+
+/// Output of the device thread: tag, model output and inference time.
+type Inferred<T> = (T, Result<ArrayD<f32>>, Duration);
+
+/// Stage 2: runs inference on a dedicated thread owning the engine.
+///
+/// Outputs are returned in input order with their tag and the inference
+/// time; a failed inference passes on its error and the thread continues.
+/// The thread ends when the input channel closes or the output channel is
+/// dropped.
+fn spawn_device_thread<T: Send + 'static>(
+    mut engine: Box<dyn Engine>,
+    mut inputs: mpsc::Receiver<(T, ModelInput)>,
+    capacity: usize,
+) -> Result<mpsc::Receiver<Inferred<T>>> {
+    let (tx, outputs) = mpsc::channel(capacity.max(1));
+    std::thread::Builder::new().name(format!("omniengine-{}", engine.name())).spawn(move || {
+        while let Some((tag, input)) = inputs.blocking_recv() {
+            let started = Instant::now();
+            let y = run_model(engine.as_mut(), input);
+            if tx.blocking_send((tag, y, started.elapsed())).is_err() {
+                break;
+            }
+        }
+    })?;
+    Ok(outputs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Doubler;
+
+    impl Engine for Doubler {
+        fn name(&self) -> &'static str {
+            "doubler"
+        }
+
+        fn infer_array(&mut self, input: ArrayD<f32>) -> Result<ArrayD<f32>> {
+            anyhow::ensure!(input.iter().all(|v| *v >= 0.0), "negativer Input");
+            Ok(input * 2.0)
+        }
+    }
+
+    fn input(v: f32) -> ModelInput {
+        ModelInput::Single(ArrayD::from_elem(ndarray::IxDyn(&[1]), v))
+    }
+
+    #[tokio::test]
+    async fn test_device_thread_keeps_order() {
+        let (tx, rx) = mpsc::channel(2);
+        let mut outputs = spawn_device_thread(Box::new(Doubler), rx, 2).unwrap();
+        tokio::spawn(async move {
+            for i in 0..5 {
+                tx.send((i, input(i as f32))).await.unwrap();
+            }
+        });
+        for i in 0..5 {
+            let (tag, y, _) = outputs.recv().await.unwrap();
+            assert_eq!(tag, i);
+            assert_eq!(y.unwrap()[[0]], 2.0 * i as f32);
+        }
+        assert!(outputs.recv().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_device_thread_continues_after_error() {
+        let (tx, rx) = mpsc::channel(4);
+        let mut outputs = spawn_device_thread(Box::new(Doubler), rx, 4).unwrap();
+        tx.send((0, input(-1.0))).await.unwrap();
+        tx.send((1, input(1.0))).await.unwrap();
+        drop(tx);
+
+        assert!(outputs.recv().await.unwrap().1.is_err());
+        assert_eq!(outputs.recv().await.unwrap().1.unwrap()[[0]], 2.0);
+        assert!(outputs.recv().await.is_none());
+    }
+}
@@ -0,0 +1,105 @@
+//! Compression of stored result payloads (`[redis.compression]`).
+//!
+//! Payloads of at least `min_bytes` are compressed with gzip or zstd (zstd
+//! requires the `zstd` feature). The store records the codec next to the
+//! payload so readers can decode it.
+
+use std::io::{Read, Write};
+
+use anyhow::{bail, Result};
+use flate2::{read::GzDecoder, write::GzEncoder};
+
+use crate::types::CompressionCfg;
+
+/// Codec and threshold used for stored payloads.
+#[derive(Debug, Clone)]
+pub struct Compression {
+    codec: &'static str,
+    level: Option<i32>,
+    min_bytes: usize,
+}
+
+impl Compression {
+    pub fn new(cfg: &CompressionCfg) -> Result<Self> {
+        let codec = match cfg.codec.as_str() {
+            "gzip" => "gzip",
+            "zstd" if cfg!(feature = "zstd") => "zstd",
+            "zstd" => bail!("zstd-Kompression benötigt das Feature 'zstd'"),
+            other => bail!("Unbekannte Kompression '{}' (erwartet: gzip, zstd)", other),
+        };
+        Ok(Self { codec, level: cfg.level, min_bytes: cfg.min_bytes })
+    }
+
+    /// Whether payloads of `len` bytes are compressed.
+    pub fn applies_to(&self, len: usize) -> bool {
+        len >= self.min_bytes
+    }
+
+    /// Compresses `data`; returns the codec name and the compressed bytes.
+    pub fn encode(&self, data: &[u8]) -> Result<(&'static str, Vec<u8>)> {
+        let compressed = match self.codec {
+            "gzip" => {
+                let level = self.level.map_or(flate2::Compression::default(), |l| flate2::Compression::new(l.clamp(0, 9) as u32));
+                let mut encoder = GzEncoder::new(Vec::new(), level);
+                encoder.write_all(data)?;
+                encoder.finish()?
+            }
+            #[cfg(feature = "zstd")]
+            "zstd" => zstd::encode_all(data, self.level.unwrap_or(zstd::DEFAULT_COMPRESSION_LEVEL))?,
+            _ => unreachable!("Codec wird in Compression::new geprüft"),
+        };
+        Ok((self.codec, compressed))
+    }
+}
+
+/// Decodes a payload stored with `encoding`.
+pub fn decompress(encoding: &str, data: &[u8]) -> Result<Vec<u8>> {
+    let mut out = Vec::new();
+    match encoding {
+        "gzip" => {
+            GzDecoder::new(data).read_to_end(&mut out)?;
+        }
+        #[cfg(feature = "zstd")]
+        "zstd" => out = zstd::decode_all(data)?,
+        other => bail!("Kodierung '{}' kann nicht gelesen werden", other),
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cfg(codec: &str, min_bytes: usize) -> CompressionCfg {
+        CompressionCfg { codec: codec.to_string(), level: None, min_bytes }
+    }
+
+    #[test]
+    fn test_gzip_roundtrip() {
+        let compression = Compression::new(&cfg("gzip", 64)).unwrap();
+        assert!(!compression.applies_to(b"{\"id\":\"j1\"}".len()));
+
+        let payload = serde_json::to_vec(&vec![0.5f32; 1000]).unwrap();
+        assert!(compression.applies_to(payload.len()));
+        let (encoding, compressed) = compression.encode(&payload).unwrap();
+        assert_eq!(encoding, "gzip");
+        assert!(compressed.len() < payload.len() / 10);
+        assert_eq!(decompress(encoding, &compressed).unwrap(), payload);
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn test_zstd_roundtrip() {
+        let compression = Compression::new(&cfg("zstd", 0)).unwrap();
+        let payload = serde_json::to_vec(&vec![0.25f32; 1000]).unwrap();
+        let (encoding, compressed) = compression.encode(&payload).unwrap();
+        assert_eq!(encoding, "zstd");
+        assert_eq!(decompress(encoding, &compressed).unwrap(), payload);
+    }
+
+    #[test]
+    fn test_unknown_codec() {
+        assert!(Compression::new(&cfg("lzma", 0)).is_err());
+        assert!(decompress("lzma", b"").is_err());
+    }
+}
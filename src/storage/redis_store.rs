@@ -1,23 +1,332 @@
+use std::collections::{BTreeSet, HashMap};
+use std::ops::Range;
+use std::sync::{Arc, Mutex};
+
 use anyhow::Result;
 use redis::AsyncCommands;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
+
+use super::compression::{decompress, Compression};
+use crate::types::TensorDtype;
+
+/// Chunk layout of a stored tensor, kept in the `tensor` field of the result.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TensorLayout {
+    pub shape: Vec<usize>,
+    #[serde(default)]
+    pub dtype: TensorDtype,
+    pub chunk_elements: usize,
+    pub chunks: usize,
+    /// Dequantization factor of `i8` tensors.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scale: Option<f32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub encoding: Option<String>,
+}
+
+/// URL of the process-local store used instead of Redis.
+pub const MEMORY_URL: &str = "memory://";
+
+#[derive(Clone)]
+enum Backend {
+    Redis(redis::Client),
+    /// Results live in this process only (`memory://`), for demos and
+    /// single-process setups without Redis.
+    Memory(Arc<Mutex<Memory>>),
+}
+
+#[derive(Default)]
+struct Memory {
+    values: HashMap<String, Vec<u8>>,
+    sets: HashMap<String, BTreeSet<String>>,
+}
 
 #[derive(Clone)]
 pub struct RedisStorage {
-    client: redis::Client,
+    backend: Backend,
     out_prefix: String,
+    chunk_elements: Option<usize>,
+    compression: Option<Compression>,
+    tensor_dtype: TensorDtype,
 }
 
 impl RedisStorage {
+    /// Connects lazily to the Redis at `url`; `memory://` keeps the results
+    /// in this process instead.
     pub fn new(url: &str, out_prefix: String) -> Result<Self> {
-        Ok(Self { client: redis::Client::open(url)?, out_prefix })
+        let backend = match url {
+            MEMORY_URL => Backend::Memory(Arc::default()),
+            url => Backend::Redis(redis::Client::open(url)?),
+        };
+        Ok(Self { backend, out_prefix, chunk_elements: None, compression: None, tensor_dtype: TensorDtype::F32 })
+    }
+
+    /// Compresses stored results and chunks; the codec of a result is kept
+    /// under `{out_prefix}:{job_id}:encoding`.
+    pub fn with_compression(mut self, compression: Option<Compression>) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Additionally stores full output tensors in chunks of `n` values.
+    pub fn with_chunk_elements(mut self, n: Option<usize>) -> Self {
+        self.chunk_elements = n.filter(|&n| n > 0);
+        self
+    }
+
+    /// Element type of stored tensor chunks.
+    pub fn with_tensor_dtype(mut self, dtype: TensorDtype) -> Self {
+        self.tensor_dtype = dtype;
+        self
     }
 
+    pub fn chunk_elements(&self) -> Option<usize> {
+        self.chunk_elements
+    }
+
+    /// Stores `data` as raw little-endian chunks of the configured element
+    /// type under `{out_prefix}:{job_id}:chunk:{i}`; returns their layout.
+    pub async fn store_tensor(&self, job_id: &str, shape: &[usize], data: &[f32], chunk_elements: usize) -> Result<TensorLayout> {
+        let dtype = self.tensor_dtype;
+        let scale = (dtype == TensorDtype::I8).then(|| i8_scale(data));
+        let chunks: Vec<&[f32]> = data.chunks(chunk_elements.max(1)).collect();
+        // Alle Chunks gleich kodieren, entschieden nach der Größe des ganzen Tensors
+        let compression = self.compression.as_ref().filter(|c| c.applies_to(data.len() * dtype.size()));
+        let mut encoding = None;
+        let mut entries = Vec::with_capacity(chunks.len());
+        for (i, chunk) in chunks.iter().enumerate() {
+            let mut bytes = encode_values(chunk, dtype, scale.unwrap_or(1.0));
+            if let Some(compression) = compression {
+                let (codec, compressed) = compression.encode(&bytes)?;
+                (encoding, bytes) = (Some(codec.to_string()), compressed);
+            }
+            entries.push((self.chunk_key(job_id, i), bytes));
+        }
+        match &self.backend {
+            Backend::Memory(memory) => memory.lock().unwrap().values.extend(entries),
+            Backend::Redis(client) => {
+                let mut con = client.get_multiplexed_async_connection().await?;
+                let mut pipe = redis::pipe();
+                for (key, bytes) in entries {
+                    pipe.set(key, bytes).ignore();
+                }
+                pipe.query_async::<()>(&mut con).await?;
+            }
+        }
+        Ok(TensorLayout { shape: shape.to_vec(), dtype, chunk_elements, chunks: chunks.len(), scale, encoding })
+    }
+
+    /// Reads the raw chunks `range` of a stored tensor, concatenated and
+    /// decoded from `encoding`.
+    pub async fn get_chunks(&self, job_id: &str, range: Range<usize>, encoding: Option<&str>) -> Result<Vec<u8>> {
+        let keys: Vec<String> = range.clone().map(|i| self.chunk_key(job_id, i)).collect();
+        if keys.is_empty() {
+            return Ok(Vec::new());
+        }
+        let chunks = self.get_values(&keys).await?;
+        let mut out = Vec::new();
+        for (i, chunk) in range.zip(chunks) {
+            let chunk = chunk.ok_or_else(|| anyhow::anyhow!("Chunk {} von Job '{}' fehlt", i, job_id))?;
+            match encoding {
+                Some(encoding) => out.extend(decompress(encoding, &chunk)?),
+                None => out.extend(chunk),
+            }
+        }
+        Ok(out)
+    }
+
+    fn chunk_key(&self, job_id: &str, i: usize) -> String {
+        format!("{}:{}:chunk:{}", self.out_prefix, job_id, i)
+    }
+
+    /// Stores a result under `{out_prefix}:{job_id}`. Results with a trace
+    /// context are also added to the set `{out_prefix}:trace:{trace_id}`.
     pub async fn store_json<T: Serialize>(&self, job_id: &str, value: &T) -> Result<()> {
-        let mut con = self.client.get_multiplexed_async_connection().await?;
         let key = format!("{}:{}", self.out_prefix, job_id);
+        let value = serde_json::to_value(value)?;
+        let payload = serde_json::to_vec(&value)?;
+        let trace_key = crate::trace_context::trace_id(&value).map(|trace_id| self.trace_key(&trace_id));
+        let client = match &self.backend {
+            Backend::Redis(client) => client,
+            Backend::Memory(memory) => {
+                // Im Prozess lohnt keine Kompression
+                let mut memory = memory.lock().unwrap();
+                if let Some(trace_key) = trace_key {
+                    memory.sets.entry(trace_key).or_default().insert(job_id.to_string());
+                }
+                memory.values.remove(&format!("{}:encoding", key));
+                memory.values.insert(key, payload);
+                return Ok(());
+            }
+        };
+        let mut con = client.get_multiplexed_async_connection().await?;
+        let mut pipe = redis::pipe();
+        if let Some(trace_key) = trace_key {
+            pipe.sadd(trace_key, job_id).ignore();
+        }
+        match self.compression.as_ref().filter(|c| c.applies_to(payload.len())) {
+            Some(compression) => {
+                let (codec, compressed) = compression.encode(&payload)?;
+                pipe.set(&key, compressed).ignore().set(format!("{}:encoding", key), codec).ignore();
+            }
+            None => {
+                pipe.set(&key, payload).ignore().del(format!("{}:encoding", key)).ignore();
+            }
+        }
+        pipe.atomic().query_async::<()>(&mut con).await?;
+        Ok(())
+    }
+
+    /// Reads a stored result; `None` if there is none (yet).
+    pub async fn get_json(&self, job_id: &str) -> Result<Option<serde_json::Value>> {
+        let key = format!("{}:{}", self.out_prefix, job_id);
+        let mut values = self.get_values(&[format!("{}:encoding", key), key]).await?;
+        let Some(payload) = values.pop().flatten() else { return Ok(None) };
+        let payload = match values.pop().flatten() {
+            Some(encoding) => decompress(&String::from_utf8(encoding)?, &payload)?,
+            None => payload,
+        };
+        Ok(Some(serde_json::from_slice(&payload)?))
+    }
+
+    /// Ids of the stored results belonging to a distributed trace.
+    pub async fn trace_jobs(&self, trace_id: &str) -> Result<Vec<String>> {
+        let client = match &self.backend {
+            Backend::Redis(client) => client,
+            Backend::Memory(memory) => {
+                let memory = memory.lock().unwrap();
+                return Ok(memory.sets.get(&self.trace_key(trace_id)).into_iter().flatten().cloned().collect());
+            }
+        };
+        let mut con = client.get_multiplexed_async_connection().await?;
+        let mut ids: Vec<String> = con.smembers(self.trace_key(trace_id)).await?;
+        ids.sort();
+        Ok(ids)
+    }
+
+    fn trace_key(&self, trace_id: &str) -> String {
+        format!("{}:trace:{}", self.out_prefix, trace_id)
+    }
+
+    /// Publishes on the Redis channel `{out_prefix}:{channel}`; without Redis
+    /// there are no subscribers and nothing is sent.
+    pub async fn publish_json<T: Serialize>(&self, channel: &str, value: &T) -> Result<()> {
+        let Backend::Redis(client) = &self.backend else { return Ok(()) };
+        let mut con = client.get_multiplexed_async_connection().await?;
+        let channel = format!("{}:{}", self.out_prefix, channel);
         let payload = serde_json::to_string(value)?;
-        con.set::<_, _, ()>(key, payload).await?;
+        con.publish::<_, _, ()>(channel, payload).await?;
         Ok(())
     }
+
+    /// Reads raw values; missing keys are `None`.
+    async fn get_values(&self, keys: &[String]) -> Result<Vec<Option<Vec<u8>>>> {
+        match &self.backend {
+            Backend::Memory(memory) => {
+                let memory = memory.lock().unwrap();
+                Ok(keys.iter().map(|key| memory.values.get(key).cloned()).collect())
+            }
+            Backend::Redis(client) => {
+                let mut con = client.get_multiplexed_async_connection().await?;
+                Ok(redis::cmd("MGET").arg(keys).query_async(&mut con).await?)
+            }
+        }
+    }
+}
+
+/// Little-endian bytes of f32 values.
+pub fn f32_bytes(values: &[f32]) -> Vec<u8> {
+    values.iter().flat_map(|v| v.to_le_bytes()).collect()
+}
+
+/// Symmetric `i8` scale: the largest absolute value maps to 127.
+pub fn i8_scale(values: &[f32]) -> f32 {
+    let max = values.iter().fold(0.0f32, |m, v| m.max(v.abs()));
+    if max > 0.0 && max.is_finite() { max / 127.0 } else { 1.0 }
+}
+
+/// Little-endian bytes of `values` converted to `dtype`; `scale` applies to `i8`.
+pub fn encode_values(values: &[f32], dtype: TensorDtype, scale: f32) -> Vec<u8> {
+    match dtype {
+        TensorDtype::F32 => f32_bytes(values),
+        TensorDtype::F16 => values.iter().flat_map(|&v| half::f16::from_f32(v).to_le_bytes()).collect(),
+        TensorDtype::Bf16 => values.iter().flat_map(|&v| half::bf16::from_f32(v).to_le_bytes()).collect(),
+        TensorDtype::I8 => values.iter().map(|&v| (v / scale).round().clamp(-127.0, 127.0) as i8 as u8).collect(),
+    }
+}
+
+/// Inverse of [`encode_values`].
+pub fn decode_values(bytes: &[u8], dtype: TensorDtype, scale: f32) -> Vec<f32> {
+    match dtype {
+        TensorDtype::F32 => bytes.chunks_exact(4).map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]])).collect(),
+        TensorDtype::F16 => bytes.chunks_exact(2).map(|b| half::f16::from_le_bytes([b[0], b[1]]).to_f32()).collect(),
+        TensorDtype::Bf16 => bytes.chunks_exact(2).map(|b| half::bf16::from_le_bytes([b[0], b[1]]).to_f32()).collect(),
+        TensorDtype::I8 => bytes.iter().map(|&b| b as i8 as f32 * scale).collect(),
+    }
+}
+
+/// Chunks holding the elements `elements` for a chunk size of `chunk_elements`;
+/// none for an empty range or a chunk size of `0`.
+pub fn chunk_span(elements: &Range<usize>, chunk_elements: usize) -> Range<usize> {
+    if elements.is_empty() || chunk_elements == 0 {
+        return 0..0;
+    }
+    elements.start / chunk_elements..(elements.end - 1) / chunk_elements + 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chunk_span() {
+        assert_eq!(chunk_span(&(0..10), 4), 0..3);
+        assert_eq!(chunk_span(&(4..8), 4), 1..2);
+        assert_eq!(chunk_span(&(5..9), 4), 1..3);
+        assert_eq!(chunk_span(&(3..3), 4), 0..0);
+        // Leerer Tensor oder beschädigtes Layout
+        assert_eq!(chunk_span(&(0..0), 0), 0..0);
+        assert_eq!(chunk_span(&(0..10), 0), 0..0);
+    }
+
+    #[test]
+    fn test_reduced_dtypes_roundtrip() {
+        let values = [0.5, -1.25, 3.0, 0.0];
+        for dtype in [TensorDtype::F16, TensorDtype::Bf16] {
+            let bytes = encode_values(&values, dtype, 1.0);
+            assert_eq!(bytes.len(), values.len() * 2);
+            assert_eq!(decode_values(&bytes, dtype, 1.0), values);
+        }
+
+        let scale = i8_scale(&values);
+        let bytes = encode_values(&values, TensorDtype::I8, scale);
+        assert_eq!(bytes.len(), values.len());
+        for (v, d) in values.iter().zip(decode_values(&bytes, TensorDtype::I8, scale)) {
+            assert!((v - d).abs() <= scale / 2.0 + 1e-6);
+        }
+    }
+
+    #[test]
+    fn test_f32_bytes_roundtrip() {
+        let bytes = f32_bytes(&[1.0, -2.5]);
+        assert_eq!(bytes.len(), 8);
+        assert_eq!(f32::from_le_bytes(bytes[4..8].try_into().unwrap()), -2.5);
+    }
+
+    #[tokio::test]
+    async fn test_memory_store_roundtrip() {
+        let store = RedisStorage::new(MEMORY_URL, "results".into()).unwrap();
+        assert!(store.get_json("j1").await.unwrap().is_none());
+
+        let traceparent = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+        store.store_json("j1", &serde_json::json!({"id": "j1", "traceparent": traceparent})).await.unwrap();
+        assert_eq!(store.get_json("j1").await.unwrap().unwrap()["id"], "j1");
+        assert_eq!(store.trace_jobs("4bf92f3577b34da6a3ce929d0e0e4736").await.unwrap(), ["j1"]);
+
+        // Klone teilen den Speicher
+        let layout = store.clone().store_tensor("j1", &[3], &[1.0, 2.0, 3.0], 2).await.unwrap();
+        assert_eq!(layout.chunks, 2);
+        assert_eq!(store.get_chunks("j1", 1..2, None).await.unwrap(), f32_bytes(&[3.0]));
+        assert!(store.get_chunks("j1", 2..3, None).await.is_err());
+    }
 }
@@ -0,0 +1,245 @@
+//! Vector database sinks for embedding serving.
+//!
+//! Embeddings are upserted together with the job metadata as payload into
+//! Qdrant (`qdrant` feature), Milvus (`milvus` feature) or PostgreSQL with
+//! pgvector (`pgvector` feature).
+
+// Ohne Sink-Features wird nur die Konfiguration geprüft
+#![cfg_attr(not(any(feature = "qdrant", feature = "milvus", feature = "pgvector")), allow(dead_code))]
+
+use anyhow::Result;
+use ndarray::{ArrayD, Axis};
+use serde_json::Value;
+
+use crate::types::{Batch, EmbeddingCfg, JobMeta};
+
+/// A single embedding to upsert.
+#[derive(Debug, Clone)]
+pub struct VectorPoint {
+    pub id: String,
+    pub vector: Vec<f32>,
+    pub payload: JobMeta,
+}
+
+/// Configured vector database sink.
+pub enum VectorSink {
+    #[cfg(feature = "qdrant")]
+    Qdrant(QdrantSink),
+    #[cfg(feature = "milvus")]
+    Milvus(MilvusSink),
+    #[cfg(feature = "pgvector")]
+    PgVector(PgVectorSink),
+}
+
+impl VectorSink {
+    /// Creates the sink selected by `[embedding] sink`.
+    pub async fn connect(cfg: &EmbeddingCfg) -> Result<Self> {
+        tracing::info!("Vektor-Sink: {} ({}, collection={})", cfg.sink, cfg.url, cfg.collection);
+        match cfg.sink.as_str() {
+            #[cfg(feature = "qdrant")]
+            "qdrant" => Ok(Self::Qdrant(QdrantSink::new(&cfg.url, &cfg.collection))),
+
+            #[cfg(feature = "milvus")]
+            "milvus" => Ok(Self::Milvus(MilvusSink::new(&cfg.url, &cfg.collection))),
+
+            #[cfg(feature = "pgvector")]
+            "pgvector" => Ok(Self::PgVector(PgVectorSink::connect(&cfg.url, &cfg.collection).await?)),
+
+            other => anyhow::bail!(
+                "Vektor-Sink '{}' nicht unterstützt (build mit features: qdrant, milvus, pgvector)",
+                other
+            ),
+        }
+    }
+
+    /// Upserts the real (non-padding) samples of a batch output `y` [N, ...].
+    pub async fn upsert_batch(&self, batch: &Batch, y: &ArrayD<f32>) -> Result<()> {
+        let points: Vec<VectorPoint> = batch
+            .ids
+            .iter()
+            .zip(y.axis_iter(Axis(0)))
+            .take(batch.actual_len)
+            .enumerate()
+            .filter(|(_, (id, _))| !crate::probes::is_canary(id))
+            .map(|(i, (id, row))| VectorPoint {
+                id: id.clone(),
+                vector: row.iter().copied().collect(),
+                payload: batch.metas.get(i).cloned().unwrap_or_default(),
+            })
+            .collect();
+        self.upsert(&points).await
+    }
+
+    /// Inserts or replaces the given points.
+    pub async fn upsert(&self, points: &[VectorPoint]) -> Result<()> {
+        if points.is_empty() {
+            return Ok(());
+        }
+        match self {
+            #[cfg(feature = "qdrant")]
+            Self::Qdrant(sink) => sink.upsert(points).await,
+            #[cfg(feature = "milvus")]
+            Self::Milvus(sink) => sink.upsert(points).await,
+            #[cfg(feature = "pgvector")]
+            Self::PgVector(sink) => sink.upsert(points).await,
+            #[allow(unreachable_patterns)]
+            _ => unreachable!(),
+        }
+    }
+}
+
+/// Payload stored next to the vector: job metadata plus the original job id.
+fn point_payload(point: &VectorPoint) -> Value {
+    let mut payload = point.payload.clone();
+    payload.insert("job_id".to_string(), Value::String(point.id.clone()));
+    Value::Object(payload)
+}
+
+/// Derives a stable numeric point id from a job id (FNV-1a, 63 bit).
+///
+/// Qdrant only accepts unsigned integers or UUIDs as point ids, so arbitrary
+/// job ids are hashed; the original id is kept in the payload as `job_id`.
+pub fn numeric_id(job_id: &str) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for b in job_id.bytes() {
+        hash ^= b as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash >> 1
+}
+
+/// Qdrant sink using the REST API.
+#[cfg(feature = "qdrant")]
+pub struct QdrantSink {
+    client: reqwest::Client,
+    url: String,
+}
+
+#[cfg(feature = "qdrant")]
+impl QdrantSink {
+    pub fn new(base_url: &str, collection: &str) -> Self {
+        let url = format!("{}/collections/{}/points?wait=true", base_url.trim_end_matches('/'), collection);
+        Self { client: reqwest::Client::new(), url }
+    }
+
+    pub async fn upsert(&self, points: &[VectorPoint]) -> Result<()> {
+        let points: Vec<Value> = points
+            .iter()
+            .map(|p| serde_json::json!({ "id": numeric_id(&p.id), "vector": p.vector, "payload": point_payload(p) }))
+            .collect();
+        self.client
+            .put(&self.url)
+            .json(&serde_json::json!({ "points": points }))
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+}
+
+/// Milvus sink using the v2 REST API (`id` VarChar primary key, `vector` field,
+/// payload as dynamic fields).
+#[cfg(feature = "milvus")]
+pub struct MilvusSink {
+    client: reqwest::Client,
+    url: String,
+    collection: String,
+}
+
+#[cfg(feature = "milvus")]
+impl MilvusSink {
+    pub fn new(base_url: &str, collection: &str) -> Self {
+        let url = format!("{}/v2/vectordb/entities/upsert", base_url.trim_end_matches('/'));
+        Self { client: reqwest::Client::new(), url, collection: collection.to_string() }
+    }
+
+    pub async fn upsert(&self, points: &[VectorPoint]) -> Result<()> {
+        let data: Vec<Value> = points
+            .iter()
+            .map(|p| {
+                let mut row = p.payload.clone();
+                row.insert("id".to_string(), Value::String(p.id.clone()));
+                row.insert("vector".to_string(), serde_json::json!(p.vector));
+                Value::Object(row)
+            })
+            .collect();
+        let resp: Value = self
+            .client
+            .post(&self.url)
+            .json(&serde_json::json!({ "collectionName": self.collection, "data": data }))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        // Milvus meldet Fehler im Body mit HTTP 200
+        anyhow::ensure!(
+            resp.get("code").and_then(Value::as_i64).unwrap_or(0) == 0,
+            "Milvus upsert fehlgeschlagen: {}",
+            resp
+        );
+        Ok(())
+    }
+}
+
+/// PostgreSQL/pgvector sink.
+///
+/// Expects a table `(id TEXT PRIMARY KEY, embedding vector(D), payload JSONB)`.
+#[cfg(feature = "pgvector")]
+pub struct PgVectorSink {
+    client: tokio_postgres::Client,
+    statement: String,
+}
+
+#[cfg(feature = "pgvector")]
+impl PgVectorSink {
+    pub async fn connect(url: &str, table: &str) -> Result<Self> {
+        let (client, connection) = tokio_postgres::connect(url, tokio_postgres::NoTls).await?;
+        tokio::spawn(async move {
+            if let Err(e) = connection.await {
+                tracing::error!("pgvector Verbindung beendet: {}", e);
+            }
+        });
+        let statement = format!(
+            "INSERT INTO {} (id, embedding, payload) VALUES ($1, $2::text::vector, $3::text::jsonb) \
+             ON CONFLICT (id) DO UPDATE SET embedding = EXCLUDED.embedding, payload = EXCLUDED.payload",
+            table
+        );
+        Ok(Self { client, statement })
+    }
+
+    pub async fn upsert(&self, points: &[VectorPoint]) -> Result<()> {
+        for p in points {
+            let vector = format!(
+                "[{}]",
+                p.vector.iter().map(|v| v.to_string()).collect::<Vec<_>>().join(",")
+            );
+            let payload = point_payload(p).to_string();
+            self.client.execute(&self.statement, &[&p.id, &vector, &payload]).await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_numeric_id_is_stable() {
+        assert_eq!(numeric_id("job-1"), numeric_id("job-1"));
+        assert_ne!(numeric_id("job-1"), numeric_id("job-2"));
+        assert!(numeric_id("job-1") < (1u64 << 63));
+    }
+
+    #[test]
+    fn test_point_payload_carries_job_id() {
+        let mut meta = JobMeta::new();
+        meta.insert("source".to_string(), Value::String("docs".to_string()));
+        let point = VectorPoint { id: "job-1".to_string(), vector: vec![1.0], payload: meta };
+
+        let payload = point_payload(&point);
+        assert_eq!(payload["job_id"], "job-1");
+        assert_eq!(payload["source"], "docs");
+    }
+}
@@ -0,0 +1,177 @@
+//! Daemon mode for bare-metal systemd services (`[daemon]`).
+//!
+//! * `sd_notify` protocol over `$NOTIFY_SOCKET`: `READY=1` once all workers
+//!   are warm (use `Type=notify`), `STATUS=` lines and `STOPPING=1`.
+//! * Watchdog pings (`WATCHDOG=1`) every half `$WATCHDOG_USEC` when the unit
+//!   sets `WatchdogSec=`; a wedged runtime stops pinging and gets restarted.
+//! * PID file, refused if another live instance holds it.
+//! * SIGTERM/SIGINT: stop taking work, drain in-flight jobs, remove the PID
+//!   file and exit 0.
+
+use std::io;
+use std::os::unix::net::UnixDatagram;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use tokio::signal::unix::{signal, SignalKind};
+use tracing::{info, warn};
+
+use crate::health::health;
+use crate::types::DaemonCfg;
+
+/// Sends a notification to the service manager.
+///
+/// Returns `Ok(false)` when not started by systemd (`$NOTIFY_SOCKET` unset).
+pub fn notify(state: &str) -> io::Result<bool> {
+    match std::env::var_os("NOTIFY_SOCKET") {
+        Some(socket) => notify_to(Path::new(&socket), state).map(|_| true),
+        None => Ok(false),
+    }
+}
+
+/// Sends `state` to a notification socket; `@name` is an abstract socket.
+fn notify_to(socket: &Path, state: &str) -> io::Result<()> {
+    let sock = UnixDatagram::unbound()?;
+    let raw = socket.as_os_str().as_encoded_bytes();
+    if let Some(name) = raw.strip_prefix(b"@") {
+        #[cfg(target_os = "linux")]
+        {
+            use std::os::linux::net::SocketAddrExt;
+            let addr = std::os::unix::net::SocketAddr::from_abstract_name(name)?;
+            sock.send_to_addr(state.as_bytes(), &addr)?;
+            return Ok(());
+        }
+        #[cfg(not(target_os = "linux"))]
+        {
+            let _ = name;
+            return Err(io::Error::new(io::ErrorKind::Unsupported, "abstrakte Sockets nur unter Linux"));
+        }
+    }
+    sock.send_to(state.as_bytes(), socket)?;
+    Ok(())
+}
+
+/// Ping interval from `WATCHDOG_USEC` (half the timeout), if the watchdog is
+/// enabled for this process (`WATCHDOG_PID` unset or equal to our pid).
+fn watchdog_interval(usec: Option<&str>, pid: Option<&str>) -> Option<Duration> {
+    if pid.is_some_and(|p| p.parse::<u32>().ok() != Some(std::process::id())) {
+        return None;
+    }
+    let usec: u64 = usec?.parse().ok().filter(|&u| u > 0)?;
+    Some(Duration::from_micros(usec / 2))
+}
+
+/// PID file removed again on drop.
+struct PidFile(PathBuf);
+
+impl PidFile {
+    /// Writes the current pid; fails if the file belongs to a live process.
+    fn create(path: &str) -> Result<Self> {
+        let path = PathBuf::from(path);
+        if let Ok(existing) = std::fs::read_to_string(&path) {
+            let pid = existing.trim();
+            if !pid.is_empty() && pid != std::process::id().to_string() && Path::new("/proc").join(pid).exists() {
+                anyhow::bail!("Instanz läuft bereits (PID {} in {})", pid, path.display());
+            }
+        }
+        std::fs::write(&path, format!("{}\n", std::process::id()))
+            .with_context(|| format!("PID-Datei konnte nicht geschrieben werden: {}", path.display()))?;
+        Ok(Self(path))
+    }
+}
+
+impl Drop for PidFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+/// Starts daemon integration: PID file, readiness and watchdog
+/// notifications and signal handling.
+pub fn start(cfg: &DaemonCfg) -> Result<()> {
+    let pid_file = cfg.pid_file.as_deref().map(PidFile::create).transpose()?;
+
+    // READY=1 erst, wenn alle Worker aufgewärmt sind
+    tokio::spawn(async {
+        while !health().is_ready() {
+            tokio::time::sleep(Duration::from_millis(200)).await;
+        }
+        let state = format!("READY=1\nMAINPID={}\nSTATUS=bereit", std::process::id());
+        match notify(&state) {
+            Ok(true) => info!("systemd: Readiness gemeldet"),
+            Ok(false) => {}
+            Err(e) => warn!("sd_notify fehlgeschlagen: {}", e),
+        }
+    });
+
+    let usec = std::env::var("WATCHDOG_USEC").ok();
+    let pid = std::env::var("WATCHDOG_PID").ok();
+    if let Some(interval) = watchdog_interval(usec.as_deref(), pid.as_deref()) {
+        info!("systemd-Watchdog aktiv, Ping alle {:?}", interval);
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                if let Err(e) = notify("WATCHDOG=1") {
+                    warn!("Watchdog-Ping fehlgeschlagen: {}", e);
+                }
+            }
+        });
+    }
+
+    let mut term = signal(SignalKind::terminate())?;
+    let mut int = signal(SignalKind::interrupt())?;
+    let timeout = Duration::from_secs(cfg.drain_timeout_secs);
+    tokio::spawn(async move {
+        tokio::select! {
+            _ = term.recv() => info!("SIGTERM empfangen, beende nach Drain"),
+            _ = int.recv() => info!("SIGINT empfangen, beende nach Drain"),
+        }
+        let _ = notify("STOPPING=1\nSTATUS=Drain");
+        crate::k8s::drain(health(), timeout).await;
+        drop(pid_file);
+        std::process::exit(0);
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_watchdog_interval() {
+        assert_eq!(watchdog_interval(Some("10000000"), None), Some(Duration::from_secs(5)));
+        let own = std::process::id().to_string();
+        assert_eq!(watchdog_interval(Some("2000000"), Some(&own)), Some(Duration::from_secs(1)));
+        assert_eq!(watchdog_interval(Some("2000000"), Some("1")), None);
+        assert_eq!(watchdog_interval(None, None), None);
+        assert_eq!(watchdog_interval(Some("0"), None), None);
+    }
+
+    #[test]
+    fn test_notify_sends_datagram() {
+        let path = std::env::temp_dir().join(format!("omni-notify-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let server = UnixDatagram::bind(&path).unwrap();
+
+        notify_to(&path, "READY=1").unwrap();
+        let mut buf = [0u8; 64];
+        let n = server.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..n], b"READY=1");
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_pid_file_written_and_removed() {
+        let path = std::env::temp_dir().join(format!("omni-{}.pid", std::process::id()));
+        let pid_file = PidFile::create(path.to_str().unwrap()).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap().trim(), std::process::id().to_string());
+
+        // Eigene PID blockiert nicht (Neustart nach Absturz mit gleicher PID)
+        assert!(PidFile::create(path.to_str().unwrap()).is_ok());
+        drop(pid_file);
+        assert!(!path.exists());
+    }
+}
@@ -0,0 +1,325 @@
+//! Text job support for NLP classification and embedding models.
+//!
+//! Raw strings are tokenized into fixed-length id tensors (`TextEncoder`),
+//! run through the regular batching/inference path, and turned back into
+//! labels, embeddings or decoded text by `TextOutput`.
+
+use std::collections::HashMap;
+use std::fs;
+
+use anyhow::{Context, Result};
+use ndarray::{ArrayD, ArrayViewD, Axis, IxDyn};
+use serde_json::{json, Value};
+
+use crate::pipeline::OutputFormatter;
+use crate::processors::softmax;
+use crate::types::{Job, JobMeta, TextCfg};
+
+/// A raw text inference job.
+#[derive(Debug, Clone)]
+pub struct TextJob {
+    pub id: String,
+    pub text: String,
+    pub meta: JobMeta,
+}
+
+/// Trait for text tokenizers.
+pub trait Tokenizer: Send + Sync {
+    /// Converts text into token ids (without special tokens or padding).
+    fn encode(&self, text: &str) -> Result<Vec<u32>>;
+
+    /// Converts token ids back into text, skipping special tokens.
+    fn decode(&self, ids: &[u32]) -> Result<String>;
+
+    /// Text of a single token and whether it continues the previous word
+    /// without a space; `None` for special tokens.
+    fn piece(&self, id: u32) -> Option<(String, bool)> {
+        self.decode(&[id]).ok().filter(|t| !t.is_empty()).map(|t| (t, false))
+    }
+}
+
+/// WordPiece tokenizer backed by a BERT-style `vocab.txt` (one token per line).
+pub struct WordPieceTokenizer {
+    vocab: HashMap<String, u32>,
+    tokens: Vec<String>,
+    lowercase: bool,
+    unk_id: u32,
+}
+
+impl WordPieceTokenizer {
+    /// Loads the vocabulary file; token ids are the line numbers.
+    pub fn from_file(path: &str, lowercase: bool) -> Result<Self> {
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Vokabular konnte nicht geladen werden: {}", path))?;
+        Self::from_tokens(content.lines().map(str::to_string).collect(), lowercase)
+    }
+
+    /// Builds the tokenizer from an in-memory token list.
+    pub fn from_tokens(tokens: Vec<String>, lowercase: bool) -> Result<Self> {
+        let vocab: HashMap<String, u32> =
+            tokens.iter().enumerate().map(|(i, t)| (t.clone(), i as u32)).collect();
+        let unk_id = *vocab.get("[UNK]").context("Vokabular enthält kein [UNK]")?;
+        Ok(Self { vocab, tokens, lowercase, unk_id })
+    }
+
+    /// Returns the id of a token, if present.
+    pub fn token_id(&self, token: &str) -> Option<u32> {
+        self.vocab.get(token).copied()
+    }
+
+    fn split_words(&self, text: &str) -> Vec<String> {
+        let text = if self.lowercase { text.to_lowercase() } else { text.to_string() };
+        let mut words = Vec::new();
+        for chunk in text.split_whitespace() {
+            let mut current = String::new();
+            for c in chunk.chars() {
+                if c.is_ascii_punctuation() {
+                    if !current.is_empty() {
+                        words.push(std::mem::take(&mut current));
+                    }
+                    words.push(c.to_string());
+                } else {
+                    current.push(c);
+                }
+            }
+            if !current.is_empty() {
+                words.push(current);
+            }
+        }
+        words
+    }
+
+    fn word_pieces(&self, word: &str, out: &mut Vec<u32>) {
+        let chars: Vec<char> = word.chars().collect();
+        let mut pieces = Vec::new();
+        let mut start = 0;
+
+        // Greedy longest-match-first
+        while start < chars.len() {
+            let mut end = chars.len();
+            let mut found = None;
+            while start < end {
+                let mut piece: String = chars[start..end].iter().collect();
+                if start > 0 {
+                    piece = format!("##{}", piece);
+                }
+                if let Some(&id) = self.vocab.get(&piece) {
+                    found = Some(id);
+                    break;
+                }
+                end -= 1;
+            }
+            match found {
+                Some(id) => pieces.push(id),
+                None => {
+                    out.push(self.unk_id);
+                    return;
+                }
+            }
+            start = end;
+        }
+        out.extend(pieces);
+    }
+}
+
+impl Tokenizer for WordPieceTokenizer {
+    fn encode(&self, text: &str) -> Result<Vec<u32>> {
+        let mut ids = Vec::new();
+        for word in self.split_words(text) {
+            self.word_pieces(&word, &mut ids);
+        }
+        Ok(ids)
+    }
+
+    fn decode(&self, ids: &[u32]) -> Result<String> {
+        let mut text = String::new();
+        for &id in ids {
+            let token = self
+                .tokens
+                .get(id as usize)
+                .with_context(|| format!("Token-ID {} außerhalb des Vokabulars", id))?;
+            if token.starts_with('[') && token.ends_with(']') {
+                continue; // Spezial-Tokens auslassen
+            }
+            match token.strip_prefix("##") {
+                Some(rest) => text.push_str(rest),
+                None => {
+                    if !text.is_empty() {
+                        text.push(' ');
+                    }
+                    text.push_str(token);
+                }
+            }
+        }
+        Ok(text)
+    }
+
+    fn piece(&self, id: u32) -> Option<(String, bool)> {
+        let token = self.tokens.get(id as usize)?;
+        if token.starts_with('[') && token.ends_with(']') {
+            return None;
+        }
+        Some(match token.strip_prefix("##") {
+            Some(rest) => (rest.to_string(), true),
+            None => (token.clone(), false),
+        })
+    }
+}
+
+/// Tokenization stage turning `TextJob`s into tensor `Job`s.
+///
+/// Produces `[max_len]` id tensors (as f32), wrapped in `[CLS]`/`[SEP]` when
+/// the vocabulary has them and padded with `[PAD]` (or 0). With dynamic
+/// padding the tensors keep their length (at most `max_len`).
+pub struct TextEncoder {
+    tokenizer: WordPieceTokenizer,
+    max_len: usize,
+    dynamic_padding: bool,
+}
+
+impl TextEncoder {
+    /// Creates the encoder from the `[text]` configuration.
+    pub fn new(cfg: &TextCfg) -> Result<Self> {
+        anyhow::ensure!(cfg.max_len >= 2, "max_len muss mindestens 2 sein");
+        let tokenizer = WordPieceTokenizer::from_file(&cfg.vocab_path, cfg.lowercase)?;
+        if cfg.dynamic_padding {
+            // Der Batcher füllt mit Nullen auf
+            anyhow::ensure!(
+                tokenizer.token_id("[PAD]").unwrap_or(0) == 0,
+                "dynamic_padding benötigt [PAD] mit ID 0"
+            );
+        }
+        Ok(Self { dynamic_padding: cfg.dynamic_padding, ..Self::with_tokenizer(tokenizer, cfg.max_len) })
+    }
+
+    /// Creates the encoder from an existing tokenizer.
+    pub fn with_tokenizer(tokenizer: WordPieceTokenizer, max_len: usize) -> Self {
+        Self { tokenizer, max_len, dynamic_padding: false }
+    }
+
+    /// Tokenizes a text job into a tensor job.
+    pub fn encode(&self, job: &TextJob) -> Result<Job> {
+        let cls = self.tokenizer.token_id("[CLS]");
+        let sep = self.tokenizer.token_id("[SEP]");
+        let pad = self.tokenizer.token_id("[PAD]").unwrap_or(0);
+
+        let body_len = self.max_len - cls.is_some() as usize - sep.is_some() as usize;
+        let mut ids: Vec<u32> = cls.into_iter().collect();
+        ids.extend(self.tokenizer.encode(&job.text)?.into_iter().take(body_len));
+        ids.extend(sep);
+        if !self.dynamic_padding {
+            ids.resize(self.max_len, pad);
+        }
+
+        let tensor = ArrayD::from_shape_vec(IxDyn(&[ids.len()]), ids.into_iter().map(|i| i as f32).collect())?;
+        Ok(Job { id: job.id.clone(), tensor, meta: job.meta.clone(), ..Default::default() })
+    }
+}
+
+/// Output formatter for text models.
+///
+/// * `"classify"` - argmax label and softmax score
+/// * `"embed"` - the full output vector as `embedding`
+/// * `"tokens"` - per-position argmax (or id) outputs decoded to `text`
+pub struct TextOutput {
+    mode: String,
+    labels: Vec<String>,
+    tokenizer: Option<WordPieceTokenizer>,
+}
+
+impl TextOutput {
+    /// Creates the formatter from the `[text]` configuration.
+    pub fn new(cfg: &TextCfg) -> Result<Self> {
+        let tokenizer = match cfg.mode.as_str() {
+            "tokens" => Some(WordPieceTokenizer::from_file(&cfg.vocab_path, cfg.lowercase)?),
+            "classify" | "embed" => None,
+            other => anyhow::bail!("Unbekannter Text-Modus '{}'", other),
+        };
+        Ok(Self { mode: cfg.mode.clone(), labels: cfg.labels.clone(), tokenizer })
+    }
+}
+
+impl OutputFormatter for TextOutput {
+    fn format(&self, output: ArrayViewD<f32>) -> Result<Value> {
+        match self.mode.as_str() {
+            "classify" => {
+                let probs = softmax(output.iter().copied());
+                let (idx, score) = probs
+                    .iter()
+                    .copied()
+                    .enumerate()
+                    .max_by(|a, b| a.1.total_cmp(&b.1))
+                    .context("Leere Modell-Ausgabe")?;
+                let label = self.labels.get(idx).cloned().unwrap_or_else(|| idx.to_string());
+                Ok(json!({ "label": label, "class_id": idx, "score": score }))
+            }
+            "embed" => Ok(json!({ "embedding": output.iter().cloned().collect::<Vec<f32>>() })),
+            _ => {
+                let tokenizer = self.tokenizer.as_ref().context("Kein Tokenizer für Modus 'tokens'")?;
+                // [T, vocab] Logits -> argmax, sonst bereits IDs
+                let ids: Vec<u32> = if output.ndim() == 2 {
+                    output
+                        .axis_iter(Axis(0))
+                        .map(|row| {
+                            row.iter()
+                                .enumerate()
+                                .max_by(|a, b| a.1.total_cmp(b.1))
+                                .map(|(i, _)| i as u32)
+                                .unwrap_or(0)
+                        })
+                        .collect()
+                } else {
+                    output.iter().map(|&v| v.round().max(0.0) as u32).collect()
+                };
+                Ok(json!({ "text": tokenizer.decode(&ids)? }))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::Array;
+
+    fn tokenizer() -> WordPieceTokenizer {
+        let vocab = ["[PAD]", "[UNK]", "[CLS]", "[SEP]", "hello", "world", "play", "##ing", "!"];
+        WordPieceTokenizer::from_tokens(vocab.iter().map(|t| t.to_string()).collect(), true).unwrap()
+    }
+
+    #[test]
+    fn test_wordpiece_encode_decode() {
+        let tok = tokenizer();
+        let ids = tok.encode("Hello playing world!").unwrap();
+        assert_eq!(ids, vec![4, 6, 7, 5, 8]);
+        assert_eq!(tok.decode(&ids).unwrap(), "hello playing world !");
+        assert_eq!(tok.encode("xyz").unwrap(), vec![1]);
+    }
+
+    #[test]
+    fn test_text_encoder_pads_and_truncates() {
+        let enc = TextEncoder::with_tokenizer(tokenizer(), 5);
+        let job = enc.encode(&TextJob { id: "t1".into(), text: "hello".into(), meta: JobMeta::new() }).unwrap();
+        assert_eq!(job.tensor.shape(), &[5]);
+        assert_eq!(job.tensor.iter().cloned().collect::<Vec<_>>(), vec![2.0, 4.0, 3.0, 0.0, 0.0]);
+
+        let long = enc.encode(&TextJob { id: "t2".into(), text: "hello world hello world".into(), meta: JobMeta::new() }).unwrap();
+        assert_eq!(long.tensor.iter().cloned().collect::<Vec<_>>(), vec![2.0, 4.0, 5.0, 4.0, 3.0]);
+    }
+
+    #[test]
+    fn test_text_encoder_dynamic_padding() {
+        let enc = TextEncoder { dynamic_padding: true, ..TextEncoder::with_tokenizer(tokenizer(), 5) };
+        let job = enc.encode(&TextJob { id: "t3".into(), text: "hello".into(), meta: JobMeta::new() }).unwrap();
+        assert_eq!(job.tensor.shape(), &[3]);
+    }
+
+    #[test]
+    fn test_text_output_classify() {
+        let out = TextOutput { mode: "classify".into(), labels: vec!["neg".into(), "pos".into()], tokenizer: None };
+        let logits = Array::from_vec(vec![0.1f32, 2.0]).into_dyn();
+        let v = out.format(logits.view()).unwrap();
+        assert_eq!(v["label"], "pos");
+        assert_eq!(v["class_id"], 1);
+    }
+}
@@ -0,0 +1,162 @@
+//! Sliding-window batching for time series.
+//!
+//! Metric streams arrive as one job per data point (a scalar or a feature
+//! vector). The `Windower` buffers the points per series and emits a window
+//! job `[features, window]` every `stride` points once `window` points are
+//! available, so anomaly detection and forecasting models see overlapping
+//! windows. Use `layout = "nct"` with `channels = features` and
+//! `width = window`.
+
+use std::collections::{HashMap, VecDeque};
+
+use anyhow::Result;
+use ndarray::{ArrayD, IxDyn};
+
+use crate::types::{Job, JobMeta, TimeSeriesCfg};
+
+struct Series {
+    points: VecDeque<Vec<f32>>,
+    seen: u64,
+    since_emit: usize,
+}
+
+/// Per-series buffer turning point jobs into window jobs.
+pub struct Windower {
+    cfg: TimeSeriesCfg,
+    series: HashMap<String, Series>,
+}
+
+impl Windower {
+    pub fn new(cfg: &TimeSeriesCfg) -> Result<Self> {
+        anyhow::ensure!(cfg.window > 0 && cfg.stride > 0, "window und stride müssen > 0 sein");
+        Ok(Self { cfg: cfg.clone(), series: HashMap::new() })
+    }
+
+    /// Adds one data point and returns the window job it completes, if any.
+    ///
+    /// The series is taken from `meta[series_key]` (default series otherwise).
+    /// The window job takes the id of the completing point and carries
+    /// `series_id`, `window_start` and `window_end` (point indices, inclusive)
+    /// in its metadata.
+    pub fn push(&mut self, job: Job) -> Result<Option<Job>> {
+        let series_id = job
+            .meta
+            .get(&self.cfg.series_key)
+            .and_then(|v| v.as_str())
+            .unwrap_or("default")
+            .to_string();
+        let point: Vec<f32> = job.tensor.iter().copied().collect();
+
+        let window = self.cfg.window;
+        let series = self.series.entry(series_id.clone()).or_insert_with(|| Series {
+            points: VecDeque::with_capacity(window),
+            seen: 0,
+            since_emit: 0,
+        });
+        if let Some(first) = series.points.front() {
+            anyhow::ensure!(
+                first.len() == point.len(),
+                "Serie {}: {} Features erwartet, bekommen {}",
+                series_id,
+                first.len(),
+                point.len()
+            );
+        }
+
+        if series.points.len() == window {
+            series.points.pop_front();
+        }
+        series.points.push_back(point);
+        series.seen += 1;
+        series.since_emit += 1;
+
+        // Erstes Fenster sobald voll, danach alle `stride` Punkte
+        if series.points.len() < window || (series.seen > window as u64 && series.since_emit < self.cfg.stride) {
+            return Ok(None);
+        }
+        series.since_emit = 0;
+
+        // [features, window]: ein Kanal je Feature
+        let features = series.points[0].len();
+        let mut data = vec![0f32; features * window];
+        for (t, p) in series.points.iter().enumerate() {
+            for (f, &v) in p.iter().enumerate() {
+                data[f * window + t] = v;
+            }
+        }
+
+        let mut meta = JobMeta::new();
+        meta.insert("series_id".to_string(), series_id.into());
+        meta.insert("window_start".to_string(), (series.seen - window as u64).into());
+        meta.insert("window_end".to_string(), (series.seen - 1).into());
+
+        Ok(Some(Job {
+            id: job.id,
+            tensor: ArrayD::from_shape_vec(IxDyn(&[features, window]), data)?,
+            meta,
+            ..Default::default()
+        }))
+    }
+
+    /// Number of series currently buffered.
+    pub fn series_count(&self) -> usize {
+        self.series.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::Array;
+
+    fn cfg(window: usize, stride: usize) -> TimeSeriesCfg {
+        TimeSeriesCfg { window, stride, series_key: "series_id".to_string() }
+    }
+
+    fn point(id: usize, series: &str, values: &[f32]) -> Job {
+        let mut meta = JobMeta::new();
+        meta.insert("series_id".to_string(), series.into());
+        Job {
+            id: format!("p{}", id),
+            tensor: Array::from_vec(values.to_vec()).into_dyn(),
+            meta,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_window_and_stride() {
+        let mut w = Windower::new(&cfg(3, 2)).unwrap();
+        let emitted: Vec<Job> = (0..7)
+            .filter_map(|i| w.push(point(i, "cpu", &[i as f32])).unwrap())
+            .collect();
+
+        // Fenster enden bei Punkt 2, 4, 6
+        assert_eq!(emitted.iter().map(|j| j.id.as_str()).collect::<Vec<_>>(), ["p2", "p4", "p6"]);
+        assert_eq!(emitted[1].tensor.shape(), &[1, 3]);
+        assert_eq!(emitted[1].tensor.iter().copied().collect::<Vec<_>>(), vec![2.0, 3.0, 4.0]);
+        assert_eq!(emitted[1].meta["window_start"], 2);
+        assert_eq!(emitted[1].meta["window_end"], 4);
+    }
+
+    #[test]
+    fn test_vector_points_channels_first() {
+        let mut w = Windower::new(&cfg(2, 1)).unwrap();
+        assert!(w.push(point(0, "a", &[1.0, 10.0])).unwrap().is_none());
+        let job = w.push(point(1, "a", &[2.0, 20.0])).unwrap().unwrap();
+        assert_eq!(job.tensor.shape(), &[2, 2]);
+        assert_eq!(job.tensor[[1, 0]], 10.0);
+        assert_eq!(job.tensor[[1, 1]], 20.0);
+
+        assert!(w.push(point(2, "a", &[1.0])).is_err());
+    }
+
+    #[test]
+    fn test_series_are_independent() {
+        let mut w = Windower::new(&cfg(2, 1)).unwrap();
+        assert!(w.push(point(0, "a", &[1.0])).unwrap().is_none());
+        assert!(w.push(point(1, "b", &[1.0])).unwrap().is_none());
+        assert!(w.push(point(2, "a", &[1.0])).unwrap().is_some());
+        assert_eq!(w.series_count(), 2);
+    }
+}
@@ -0,0 +1,184 @@
+//! W3C Trace Context propagation (`traceparent`, `tracestate`).
+//!
+//! The trace context of a job is kept in `meta.traceparent` and
+//! `meta.tracestate`: clients set it there directly, the HTTP API copies the
+//! request headers and CloudEvents carry it as extension attributes. The
+//! dispatcher drops malformed values, as the spec requires. Results repeat
+//! the context at the top level and the stored result is indexed under
+//! `{out_prefix}:trace:{trace_id}`, so results can be joined with the
+//! upstream distributed trace.
+
+use std::fmt;
+
+use serde_json::Value;
+
+use crate::types::JobMeta;
+
+/// Metadata key, header and CloudEvents attribute of the trace parent.
+pub const TRACEPARENT: &str = "traceparent";
+
+/// Metadata key, header and CloudEvents attribute of the vendor state.
+pub const TRACESTATE: &str = "tracestate";
+
+/// A parsed `traceparent` value (`{version}-{trace_id}-{parent_id}-{flags}`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceParent {
+    pub version: u8,
+    pub trace_id: String,
+    pub parent_id: String,
+    pub flags: u8,
+}
+
+impl TraceParent {
+    /// Parses a `traceparent` value; `None` if it violates the spec.
+    ///
+    /// Versions above `00` may append fields, which are ignored.
+    pub fn parse(value: &str) -> Option<Self> {
+        let value = value.trim();
+        let head = value.get(..55)?;
+        let mut fields = head.split('-');
+        let (version, trace_id, parent_id, flags) = (fields.next()?, fields.next()?, fields.next()?, fields.next()?);
+        if version.len() != 2 || !is_hex(version) || version == "ff" {
+            return None;
+        }
+        let version = u8::from_str_radix(version, 16).ok()?;
+        // Version 00 hat genau vier Felder
+        let rest = &value[55..];
+        if !rest.is_empty() && (version == 0 || !rest.starts_with('-')) {
+            return None;
+        }
+        let valid_id = |id: &str, len: usize| id.len() == len && is_hex(id) && id.bytes().any(|b| b != b'0');
+        if !valid_id(trace_id, 32) || !valid_id(parent_id, 16) || flags.len() != 2 || !is_hex(flags) {
+            return None;
+        }
+        Some(Self {
+            version,
+            trace_id: trace_id.to_string(),
+            parent_id: parent_id.to_string(),
+            flags: u8::from_str_radix(flags, 16).ok()?,
+        })
+    }
+}
+
+impl fmt::Display for TraceParent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:02x}-{}-{}-{:02x}", self.version, self.trace_id, self.parent_id, self.flags)
+    }
+}
+
+/// Lowercase hex only, as the spec demands.
+fn is_hex(s: &str) -> bool {
+    s.bytes().all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b))
+}
+
+/// Copies trace headers into job metadata. A context the client already
+/// put into `meta` takes precedence.
+pub fn inject(meta: &mut JobMeta, traceparent: Option<&str>, tracestate: Option<&str>) {
+    let Some(traceparent) = traceparent else { return };
+    if meta.contains_key(TRACEPARENT) {
+        return;
+    }
+    meta.insert(TRACEPARENT.to_string(), traceparent.into());
+    if let Some(tracestate) = tracestate {
+        meta.insert(TRACESTATE.to_string(), tracestate.into());
+    }
+}
+
+/// Returns the trace context of a job. A malformed `traceparent` is
+/// removed from `meta` together with its `tracestate`.
+pub fn sanitize(meta: &mut JobMeta) -> Option<TraceParent> {
+    let value = meta.get(TRACEPARENT)?;
+    let parsed = value.as_str().and_then(TraceParent::parse);
+    match parsed {
+        // Normalisiert (z. B. ohne Leerzeichen) zurückschreiben
+        Some(parent) => {
+            meta.insert(TRACEPARENT.to_string(), parent.to_string().into());
+            Some(parent)
+        }
+        None => {
+            tracing::debug!("Ungültiger traceparent {} verworfen", value);
+            meta.remove(TRACEPARENT);
+            meta.remove(TRACESTATE);
+            None
+        }
+    }
+}
+
+/// Sets `traceparent`/`tracestate` of a result payload from the job
+/// metadata (or removes them, e.g. from a cached result of another job).
+pub fn annotate(payload: &mut Value, meta: &JobMeta) {
+    let Some(fields) = payload.as_object_mut() else { return };
+    for key in [TRACEPARENT, TRACESTATE] {
+        match meta.get(key).filter(|_| meta.contains_key(TRACEPARENT)) {
+            Some(value) => fields.insert(key.to_string(), value.clone()),
+            None => fields.remove(key),
+        };
+    }
+}
+
+/// Trace id of a result payload, if it carries a valid `traceparent`.
+pub fn trace_id(payload: &Value) -> Option<String> {
+    payload.get(TRACEPARENT)?.as_str().and_then(TraceParent::parse).map(|p| p.trace_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const VALID: &str = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+
+    #[test]
+    fn test_parse_traceparent() {
+        let parent = TraceParent::parse(VALID).unwrap();
+        assert_eq!(parent.trace_id, "4bf92f3577b34da6a3ce929d0e0e4736");
+        assert_eq!(parent.parent_id, "00f067aa0ba902b7");
+        assert_eq!(parent.flags, 1);
+        assert_eq!(parent.to_string(), VALID);
+
+        // Spätere Versionen dürfen Felder anhängen
+        let future = format!("cc{}-extra", &VALID[2..]);
+        assert_eq!(TraceParent::parse(&future).unwrap().version, 0xcc);
+
+        for invalid in [
+            "",
+            &VALID.to_uppercase(),
+            &format!("ff{}", &VALID[2..]),
+            &format!("{}-extra", VALID),
+            "00-00000000000000000000000000000000-00f067aa0ba902b7-01",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-0000000000000000-01",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-0g",
+        ] {
+            assert!(TraceParent::parse(invalid).is_none(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_inject_and_sanitize() {
+        let mut meta = JobMeta::new();
+        inject(&mut meta, Some(VALID), Some("vendor=1"));
+        inject(&mut meta, Some("00-ignored"), None);
+        assert_eq!(meta[TRACEPARENT], VALID);
+        assert_eq!(sanitize(&mut meta).unwrap().trace_id, "4bf92f3577b34da6a3ce929d0e0e4736");
+        assert_eq!(meta[TRACESTATE], "vendor=1");
+
+        let mut meta = JobMeta::new();
+        inject(&mut meta, Some("garbage"), Some("vendor=1"));
+        assert!(sanitize(&mut meta).is_none());
+        assert!(meta.is_empty());
+    }
+
+    #[test]
+    fn test_annotate_result() {
+        let mut meta = JobMeta::new();
+        meta.insert(TRACEPARENT.to_string(), VALID.into());
+        let mut payload = json!({"id": "j1", "tracestate": "stale=1"});
+        annotate(&mut payload, &meta);
+        assert_eq!(payload[TRACEPARENT], VALID);
+        assert!(payload.get(TRACESTATE).is_none());
+        assert_eq!(trace_id(&payload).as_deref(), Some("4bf92f3577b34da6a3ce929d0e0e4736"));
+
+        annotate(&mut payload, &JobMeta::new());
+        assert_eq!(payload, json!({"id": "j1"}));
+    }
+}
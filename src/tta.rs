@@ -0,0 +1,379 @@
+//! Test-time augmentation (`[tta]`).
+//!
+//! For batches with at least one flagged job the worker runs the batch once
+//! as is and once per configured augmentation (flip, rotation or input
+//! scale). Dense outputs whose last two axes match the input are mapped back
+//! to the original geometry, detector boxes are mapped back to original
+//! pixel coordinates, everything else (class scores) is used as is. The
+//! predictions of flagged jobs are then merged by mean or maximum, or their
+//! boxes are pooled for the NMS of `[detection]`; unflagged jobs in the same
+//! batch keep the plain prediction.
+
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use ndarray::{ArrayD, ArrayViewD, Axis, IxDyn};
+
+use crate::engine::Engine;
+use crate::types::{Config, JobMeta, TtaMerge, TtaTransform};
+use crate::worker::ModelInput;
+
+/// One augmented copy: an optional transform applied after scaling.
+#[derive(Debug, Clone, Copy)]
+struct Augmentation {
+    transform: Option<TtaTransform>,
+    scale: f32,
+}
+
+/// Test-time augmentation of one model.
+#[derive(Debug, Clone)]
+pub struct Tta {
+    augmentations: Vec<Augmentation>,
+    merge: TtaMerge,
+    default: bool,
+    box_format: String,
+}
+
+impl Tta {
+    /// Returns the augmentation configured in `[tta]`, if any.
+    pub fn from_config(cfg: &Config) -> Result<Option<Arc<Self>>> {
+        let Some(tta) = &cfg.tta else { return Ok(None) };
+        let spec = cfg.input_spec();
+        anyhow::ensure!(spec.rank()? >= 4, "tta: benötigt Bild- oder Volumen-Inputs (layout \"nchw\"/\"ncdhw\")");
+        anyhow::ensure!(cfg.input.extra.is_empty(), "tta: nicht mit [[input.extra]] kombinierbar");
+        anyhow::ensure!(tta.scales.iter().all(|&s| s > 0.0), "tta: Skalierungen müssen > 0 sein");
+
+        let rotates = tta.transforms.iter().any(|t| matches!(t, TtaTransform::Rot90 | TtaTransform::Rot270));
+        anyhow::ensure!(
+            !rotates || spec.height == spec.width || spec.height == 0 || spec.width == 0,
+            "tta: rot90/rot270 benötigen quadratische Inputs ({}x{})",
+            spec.height,
+            spec.width
+        );
+        let box_format = match tta.merge {
+            TtaMerge::Nms => {
+                let det = cfg.detection.as_ref().context("tta: merge = \"nms\" benötigt [detection]")?;
+                anyhow::ensure!(
+                    tta.transforms.iter().all(|t| matches!(t, TtaTransform::Hflip | TtaTransform::Vflip)),
+                    "tta: merge = \"nms\" unterstützt nur hflip/vflip"
+                );
+                det.box_format.clone()
+            }
+            _ => String::new(),
+        };
+
+        let augmentations: Vec<_> = tta
+            .transforms
+            .iter()
+            .map(|&t| Augmentation { transform: Some(t), scale: 1.0 })
+            .chain(tta.scales.iter().map(|&scale| Augmentation { transform: None, scale }))
+            .collect();
+        anyhow::ensure!(!augmentations.is_empty(), "tta: keine transforms oder scales konfiguriert");
+        Ok(Some(Arc::new(Self { augmentations, merge: tta.merge, default: tta.default, box_format })))
+    }
+
+    /// Whether the job with `meta` asked for augmentation.
+    pub(crate) fn wanted(&self, meta: &JobMeta) -> bool {
+        meta.get("tta").and_then(|v| v.as_bool()).unwrap_or(self.default)
+    }
+
+    /// Runs `x` `[N, ...]` plain and augmented; merges the predictions of
+    /// the samples selected by `mask`.
+    pub fn run(&self, engine: &mut dyn Engine, x: ArrayD<f32>, mask: &[bool]) -> Result<ArrayD<f32>> {
+        let size = hw(&x);
+        let mut copies = Vec::with_capacity(self.augmentations.len());
+        for aug in &self.augmentations {
+            let xa = aug.apply(&x)?;
+            let aug_size = hw(&xa);
+            let y = engine.infer_array(xa)?;
+            copies.push(match self.merge {
+                TtaMerge::Nms => aug.invert_boxes(y, aug_size, &self.box_format)?,
+                _ if y.ndim() >= 3 && hw(&y) == aug_size => aug.invert_dense(y, size)?,
+                _ => y,
+            });
+        }
+        let base = engine.infer_array(x)?;
+        let selected = |i: usize| mask.get(i).copied().unwrap_or(false);
+
+        if self.merge == TtaMerge::Nms {
+            // Kandidaten aller Kopien anhängen; nicht gewählte Jobs bekommen Null-Boxen (Score 0)
+            for copy in &mut copies {
+                for (i, mut sample) in copy.outer_iter_mut().enumerate() {
+                    if !selected(i) {
+                        sample.fill(0.0);
+                    }
+                }
+            }
+            let views: Vec<_> = std::iter::once(base.view()).chain(copies.iter().map(|c| c.view())).collect();
+            anyhow::ensure!(base.ndim() == 3, "tta: Detektor-Output {:?} ist nicht [N, Boxen, Werte]", base.shape());
+            return Ok(ndarray::concatenate(Axis(1), &views)?);
+        }
+
+        let mut merged = base;
+        for (i, mut sample) in merged.outer_iter_mut().enumerate().filter(|(i, _)| selected(*i)) {
+            for copy in &copies {
+                let other = copy.index_axis(Axis(0), i);
+                anyhow::ensure!(
+                    other.shape() == sample.shape(),
+                    "tta: Output {:?} passt nicht zu {:?}",
+                    other.shape(),
+                    sample.shape()
+                );
+                match self.merge {
+                    TtaMerge::Max => sample.zip_mut_with(&other, |a, &b| *a = a.max(b)),
+                    _ => sample += &other,
+                }
+            }
+            if self.merge == TtaMerge::Mean {
+                sample /= (copies.len() + 1) as f32;
+            }
+        }
+        Ok(merged)
+    }
+}
+
+/// Marks single-input batches for augmentation if one of their jobs asks
+/// for it; `metas` are the real jobs of the batch.
+pub fn augment(input: ModelInput, tta: Option<&Arc<Tta>>, metas: &[JobMeta]) -> ModelInput {
+    let Some(tta) = tta else { return input };
+    let mask: Vec<bool> = metas.iter().map(|m| tta.wanted(m)).collect();
+    match input {
+        ModelInput::Single(x) if mask.contains(&true) => ModelInput::Augmented(x, mask, Arc::clone(tta)),
+        other => other,
+    }
+}
+
+impl Augmentation {
+    fn apply(&self, x: &ArrayD<f32>) -> Result<ArrayD<f32>> {
+        let x = if self.scale == 1.0 {
+            x.clone()
+        } else {
+            let (h, w) = hw(x);
+            let scaled = |n: usize| ((n as f32 * self.scale).round() as usize).max(1);
+            resize_hw(x.view(), scaled(h), scaled(w))?
+        };
+        Ok(match self.transform {
+            Some(t) => transform(x.view(), t),
+            None => x,
+        })
+    }
+
+    /// Maps a dense output back to the input geometry `size`.
+    fn invert_dense(&self, y: ArrayD<f32>, size: (usize, usize)) -> Result<ArrayD<f32>> {
+        let y = match self.transform {
+            Some(t) => transform(y.view(), inverse(t)),
+            None => y,
+        };
+        if hw(&y) == size {
+            Ok(y)
+        } else {
+            resize_hw(y.view(), size.0, size.1)
+        }
+    }
+
+    /// Maps boxes `[N, boxes, 4 + ...]` from the augmented input of
+    /// `size` back to original pixel coordinates.
+    fn invert_boxes(&self, mut y: ArrayD<f32>, (h, w): (usize, usize), format: &str) -> Result<ArrayD<f32>> {
+        anyhow::ensure!(
+            y.ndim() == 3 && y.shape()[2] >= 4,
+            "tta: Detektor-Output {:?} ist nicht [N, Boxen, 4 + Werte]",
+            y.shape()
+        );
+        let (w, h) = (w as f32, h as f32);
+        for mut b in y.lanes_mut(Axis(2)) {
+            match (self.transform, format) {
+                (Some(TtaTransform::Hflip), "xyxy") => (b[0], b[2]) = (w - b[2], w - b[0]),
+                (Some(TtaTransform::Hflip), "xywh") => b[0] = w - b[0] - b[2],
+                (Some(TtaTransform::Hflip), _) => b[0] = w - b[0],
+                (Some(TtaTransform::Vflip), "xyxy") => (b[1], b[3]) = (h - b[3], h - b[1]),
+                (Some(TtaTransform::Vflip), "xywh") => b[1] = h - b[1] - b[3],
+                (Some(TtaTransform::Vflip), _) => b[1] = h - b[1],
+                _ => {}
+            }
+            // Alle Box-Formate skalieren linear
+            for v in b.iter_mut().take(4) {
+                *v /= self.scale;
+            }
+        }
+        Ok(y)
+    }
+}
+
+/// Size of the last two axes.
+fn hw(x: &ArrayD<f32>) -> (usize, usize) {
+    let n = x.ndim();
+    (x.shape()[n - 2], x.shape()[n - 1])
+}
+
+fn inverse(t: TtaTransform) -> TtaTransform {
+    match t {
+        TtaTransform::Rot90 => TtaTransform::Rot270,
+        TtaTransform::Rot270 => TtaTransform::Rot90,
+        other => other,
+    }
+}
+
+/// Flips or rotates (counter-clockwise) the last two axes.
+fn transform(x: ArrayViewD<f32>, t: TtaTransform) -> ArrayD<f32> {
+    let n = x.ndim();
+    let (h, w) = (Axis(n - 2), Axis(n - 1));
+    let mut v = x;
+    match t {
+        TtaTransform::Hflip => v.invert_axis(w),
+        TtaTransform::Vflip => v.invert_axis(h),
+        TtaTransform::Rot180 => {
+            v.invert_axis(h);
+            v.invert_axis(w);
+        }
+        TtaTransform::Rot90 => {
+            v.invert_axis(w);
+            v.swap_axes(n - 2, n - 1);
+        }
+        TtaTransform::Rot270 => {
+            v.swap_axes(n - 2, n - 1);
+            v.invert_axis(w);
+        }
+    }
+    v.as_standard_layout().into_owned()
+}
+
+/// Bilinear resize of the last two axes (half-pixel centres).
+fn resize_hw(x: ArrayViewD<f32>, h: usize, w: usize) -> Result<ArrayD<f32>> {
+    let n = x.ndim();
+    let (ih, iw) = (x.shape()[n - 2], x.shape()[n - 1]);
+    anyhow::ensure!(ih > 0 && iw > 0 && h > 0 && w > 0, "tta: leere Bildgröße ({}x{} -> {}x{})", ih, iw, h, w);
+    let mut shape = x.shape().to_vec();
+    shape[n - 2] = h;
+    shape[n - 1] = w;
+
+    // Quellkoordinate: (unteres Pixel, oberes Pixel, Gewicht des oberen)
+    let coord = |o: usize, out: usize, len: usize| {
+        let c = ((o as f32 + 0.5) * len as f32 / out as f32 - 0.5).clamp(0.0, (len - 1) as f32);
+        let lo = c.floor() as usize;
+        (lo, (lo + 1).min(len - 1), c - lo as f32)
+    };
+    let src = x.as_standard_layout();
+    let src = src.as_slice().unwrap();
+    let mut out = Vec::with_capacity(shape.iter().product());
+    for plane in src.chunks(ih * iw) {
+        for y in 0..h {
+            let (y0, y1, fy) = coord(y, h, ih);
+            for xx in 0..w {
+                let (x0, x1, fx) = coord(xx, w, iw);
+                let top = plane[y0 * iw + x0] * (1.0 - fx) + plane[y0 * iw + x1] * fx;
+                let bottom = plane[y1 * iw + x0] * (1.0 - fx) + plane[y1 * iw + x1] * fx;
+                out.push(top * (1.0 - fy) + bottom * fy);
+            }
+        }
+    }
+    Ok(ArrayD::from_shape_vec(IxDyn(&shape), out)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// Segmentation stand-in: per-pixel output equal to the input.
+    struct Identity;
+
+    impl Engine for Identity {
+        fn name(&self) -> &'static str {
+            "identity"
+        }
+
+        fn infer_array(&mut self, input: ArrayD<f32>) -> Result<ArrayD<f32>> {
+            Ok(input)
+        }
+    }
+
+    /// Classifier stand-in: score = value of the top-left pixel.
+    struct TopLeft;
+
+    impl Engine for TopLeft {
+        fn name(&self) -> &'static str {
+            "top-left"
+        }
+
+        fn infer_array(&mut self, input: ArrayD<f32>) -> Result<ArrayD<f32>> {
+            let n = input.shape()[0];
+            Ok(ArrayD::from_shape_fn(IxDyn(&[n, 1]), |i| input[[i[0], 0, 0, 0]]))
+        }
+    }
+
+    fn tta(transforms: Vec<TtaTransform>, scales: Vec<f32>, merge: TtaMerge) -> Tta {
+        let augmentations = transforms
+            .into_iter()
+            .map(|t| Augmentation { transform: Some(t), scale: 1.0 })
+            .chain(scales.into_iter().map(|scale| Augmentation { transform: None, scale }))
+            .collect();
+        Tta { augmentations, merge, default: false, box_format: "xyxy".to_string() }
+    }
+
+    fn image() -> ArrayD<f32> {
+        ArrayD::from_shape_fn(IxDyn(&[2, 1, 4, 4]), |i| (i[0] * 100 + i[2] * 4 + i[3]) as f32)
+    }
+
+    #[test]
+    fn test_transforms_invert() {
+        let x = image();
+        for t in [TtaTransform::Hflip, TtaTransform::Vflip, TtaTransform::Rot90, TtaTransform::Rot180, TtaTransform::Rot270] {
+            assert_eq!(transform(transform(x.view(), t).view(), inverse(t)), x, "{:?}", t);
+        }
+        assert_eq!(transform(x.view(), TtaTransform::Rot90)[[0, 0, 0, 0]], 3.0);
+        assert_eq!(resize_hw(x.view(), 4, 4).unwrap(), x);
+        let up = resize_hw(x.view(), 8, 8).unwrap();
+        assert_eq!(up.shape(), &[2, 1, 8, 8]);
+        // Halbe Pixelzentren: Innenpunkte liegen zwischen den Nachbarn
+        assert_eq!(up[[0, 0, 0, 3]], 1.25);
+        assert!(resize_hw(x.view(), 0, 4).is_err());
+        assert!(resize_hw(ArrayD::zeros(IxDyn(&[1, 1, 0, 4])).view(), 4, 4).is_err());
+    }
+
+    #[test]
+    fn test_dense_outputs_map_back() {
+        let x = image();
+        let all = tta(vec![TtaTransform::Hflip, TtaTransform::Rot90], vec![], TtaMerge::Mean);
+        let y = all.run(&mut Identity, x.clone(), &[true, true]).unwrap();
+        assert_eq!(y, x);
+
+        // Skalierte Kopien kommen in Eingabegröße zurück
+        let flat = ArrayD::from_elem(IxDyn(&[1, 1, 4, 4]), 2.0);
+        let scaled = tta(vec![], vec![0.5, 2.0], TtaMerge::Mean).run(&mut Identity, flat.clone(), &[true]).unwrap();
+        assert_eq!(scaled, flat);
+    }
+
+    #[test]
+    fn test_merge_only_flagged_samples() {
+        let x = image();
+        let mask = [true, false];
+        let mean = tta(vec![TtaTransform::Hflip], vec![], TtaMerge::Mean).run(&mut TopLeft, x.clone(), &mask).unwrap();
+        assert_eq!(mean.into_raw_vec_and_offset().0, vec![1.5, 100.0]);
+
+        let max = tta(vec![TtaTransform::Hflip, TtaTransform::Vflip], vec![], TtaMerge::Max).run(&mut TopLeft, x, &mask).unwrap();
+        assert_eq!(max.into_raw_vec_and_offset().0, vec![12.0, 100.0]);
+    }
+
+    #[test]
+    fn test_nms_pools_unflipped_boxes() {
+        // Box links oben, Score 0.9: gespiegelt liegt sie rechts oben
+        let flipped = tta(vec![TtaTransform::Hflip], vec![], TtaMerge::Nms);
+        let y = ArrayD::from_shape_vec(IxDyn(&[1, 1, 5]), vec![0.0, 0.0, 1.0, 1.0, 0.9]).unwrap();
+        let back = flipped.augmentations[0].invert_boxes(y, (4, 4), "xyxy").unwrap();
+        assert_eq!(back.into_raw_vec_and_offset().0, vec![3.0, 0.0, 4.0, 1.0, 0.9]);
+
+        let cx = Augmentation { transform: Some(TtaTransform::Vflip), scale: 2.0 };
+        let y = ArrayD::from_shape_vec(IxDyn(&[1, 1, 4]), vec![2.0, 2.0, 2.0, 2.0]).unwrap();
+        assert_eq!(cx.invert_boxes(y, (8, 8), "cxcywh").unwrap().into_raw_vec_and_offset().0, vec![1.0, 3.0, 1.0, 1.0]);
+    }
+
+    #[test]
+    fn test_jobs_select_augmentation() {
+        let t = Arc::new(tta(vec![TtaTransform::Hflip], vec![], TtaMerge::Mean));
+        let meta = |v: serde_json::Value| v.as_object().unwrap().clone();
+        let input = || ModelInput::Single(image());
+        assert!(matches!(augment(input(), Some(&t), &[meta(json!({})), meta(json!({"tta": true}))]), ModelInput::Augmented(_, ref m, _) if m == &[false, true]));
+        assert!(matches!(augment(input(), Some(&t), &[meta(json!({"tta": false}))]), ModelInput::Single(_)));
+        assert!(matches!(augment(input(), None, &[meta(json!({"tta": true}))]), ModelInput::Single(_)));
+    }
+}
@@ -11,82 +11,1553 @@ use serde::{Deserialize, Serialize};
 /// Defines the expected shape and dtype for model inputs. Used for validation
 /// before inference to ensure tensors match the model's requirements.
 ///
+/// The `layout` selects which dimensions are checked:
+///
+/// * `"nchw"` - images and spectrograms `[N, C, H, W]` (default)
+/// * `"ncdhw"` - volumes and video clips `[N, C, D, H, W]`
+/// * `"nct"` - multi-channel waveforms `[N, C, T]`, `width` is the sample count
+/// * `"nt"` - mono waveforms `[N, T]`, `width` is the sample count
+///
+/// A `width` of `0` marks the last axis as variable-length (e.g. bucketed audio);
+/// `depth` and `height` of `0` likewise accept any size (e.g. volumes that
+/// are tiled to the model input, see [`TilingCfg`]).
+///
 /// # Example
 ///
-/// ```
-/// # use omniengine::types::InputSpec;
-/// let spec = InputSpec {
-///     batch: 4,
-///     channels: 3,
-///     height: 224,
-///     width: 224,
-///     dtype: "f32".to_string(),
-/// };
-/// ```
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct InputSpec {
-    pub batch: usize,
-    pub channels: usize,
-    pub height: usize,
-    pub width: usize,
-    pub dtype: String, // "f32" | "u8" ...
+/// ```
+/// # use omniengine::types::InputSpec;
+/// let spec = InputSpec {
+///     batch: 4,
+///     channels: 3,
+///     depth: 0,
+///     height: 224,
+///     width: 224,
+///     dtype: "f32".to_string(),
+///     layout: "nchw".to_string(),
+/// };
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InputSpec {
+    pub batch: usize,
+    pub channels: usize,
+    #[serde(default)]
+    pub depth: usize, // nur "ncdhw"
+    pub height: usize,
+    pub width: usize,
+    pub dtype: String, // "f32" | "u8" ...
+    #[serde(default = "default_layout")]
+    pub layout: String, // "nchw" | "ncdhw" | "nct" | "nt"
+}
+
+fn default_layout() -> String {
+    "nchw".to_string()
+}
+
+impl InputSpec {
+    /// Validates that a tensor matches this specification.
+    ///
+    /// Checks shape dimensions and data type against the spec requirements.
+    ///
+    /// # Arguments
+    ///
+    /// * `shape` - Tensor shape as slice, rank depending on `layout`
+    /// * `dtype` - Data type string (e.g., "f32", "u8")
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` - Tensor matches specification
+    /// * `Err(e)` - Validation error with details
+    pub fn validate(&self, shape: &[usize], dtype: &str) -> anyhow::Result<()> {
+        let rank = self.rank()?;
+        anyhow::ensure!(
+            shape.len() == rank,
+            "Input muss {}D ({}) sein",
+            rank,
+            self.layout.to_uppercase()
+        );
+        anyhow::ensure!(shape[0] == self.batch, "Batch size passt nicht");
+        if rank >= 3 {
+            anyhow::ensure!(shape[1] == self.channels, "Channels passen nicht");
+        }
+        if rank == 5 {
+            anyhow::ensure!(self.depth == 0 || shape[2] == self.depth, "D passt nicht");
+        }
+        if rank >= 4 {
+            anyhow::ensure!(self.height == 0 || shape[rank - 2] == self.height, "H/W passen nicht");
+        }
+        let last = shape[rank - 1];
+        anyhow::ensure!(
+            self.width == 0 || last == self.width,
+            "{} passt nicht",
+            if rank >= 4 { "H/W" } else { "Länge" }
+        );
+        anyhow::ensure!(dtype == self.dtype, "dtype passt nicht");
+        Ok(())
+    }
+
+    /// Returns the shape of a single sample (without the batch axis).
+    pub fn sample_shape(&self) -> anyhow::Result<Vec<usize>> {
+        Ok(match self.rank()? {
+            5 => vec![self.channels, self.depth, self.height, self.width],
+            4 => vec![self.channels, self.height, self.width],
+            3 => vec![self.channels, self.width],
+            _ => vec![self.width],
+        })
+    }
+
+    /// Returns the tensor rank implied by the layout.
+    pub fn rank(&self) -> anyhow::Result<usize> {
+        match self.layout.as_str() {
+            "ncdhw" => Ok(5),
+            "nchw" => Ok(4),
+            "nct" => Ok(3),
+            "nt" => Ok(2),
+            other => anyhow::bail!("Unbekanntes Layout '{}'", other),
+        }
+    }
+}
+
+/// Model configuration including backend, device, and I/O specifications.
+///
+/// Defines which ML backend to use (onnx, tensorrt, torch, tensorflow),
+/// device allocation (cpu/gpu), and model input/output specifications.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ModelCfg {
+    pub backend: String,
+    pub device: String,
+    pub model_path: String,
+    /// Layout of the model's first input: `"nchw"`/`"ncdhw"`,
+    /// `"nhwc"`/`"ndhwc"` or `"auto"` (default, from `input_shapes`), see
+    /// [`crate::engine::layout`].
+    #[serde(default)]
+    pub layout: Option<String>,
+    /// CUDA ordinals or device UUIDs (`GPU-…`, `MIG-…`), see [`GpuId`].
+    #[serde(default)]
+    pub gpu_ids: Vec<GpuId>,
+    /// GPUs with warm standby workers that take over from failed ones.
+    #[serde(default)]
+    pub standby_gpu_ids: Vec<GpuId>,
+    /// Batches of this model running at once per device, across workers.
+    #[serde(default)]
+    pub max_concurrent_batches: Option<usize>,
+    /// Metadata file shipped with the model (default: `model.json` next to
+    /// `model_path`, see [`crate::sidecar`]).
+    #[serde(default)]
+    pub sidecar: Option<String>,
+    /// Check opsets and operators against the execution provider before
+    /// loading (ONNX only, see [`crate::engine::preflight`]).
+    #[serde(default = "default_true")]
+    pub preflight: bool,
+    /// Custom operator libraries registered with the ONNX Runtime session
+    /// (`[[model.custom_ops]]`).
+    #[serde(default)]
+    pub custom_ops: Vec<CustomOpsCfg>,
+    /// TorchScript method and input convention (`[model.torch]`).
+    #[serde(default)]
+    pub torch: Option<TorchCfg>,
+    /// SavedModel tags and signature (`[model.tensorflow]`).
+    #[serde(default)]
+    pub tensorflow: Option<TensorflowCfg>,
+    /// Models of an ensemble (`[[model.stages]]`, backend `ensemble`), see
+    /// [`crate::engine::ensemble`].
+    #[serde(default)]
+    pub stages: Vec<StageCfg>,
+
+    pub input_names: Vec<String>,
+    pub input_shapes: Vec<Vec<usize>>,
+    pub output_names: Vec<String>,
+    pub output_shapes: Vec<Vec<usize>>,
+}
+
+/// One model of an ensemble (`[[model.stages]]`).
+///
+/// `inputs` feeds `input_names` in order: `"input"` is the job tensor,
+/// `"input.<name>"` a named input of the job, `"<stage>"` the first and
+/// `"<stage>.<output>"` a named output of an earlier stage. Everything else
+/// (device, GPUs, preflight) is taken from `[model]`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct StageCfg {
+    pub name: String,
+    pub backend: String,
+    pub model_path: String,
+    pub inputs: Vec<String>,
+    #[serde(default)]
+    pub layout: Option<String>,
+    pub input_names: Vec<String>,
+    pub input_shapes: Vec<Vec<usize>>,
+    pub output_names: Vec<String>,
+    pub output_shapes: Vec<Vec<usize>>,
+}
+
+/// Custom operator library (`.so`/`.dll`) for ONNX Runtime, e.g. the ops of
+/// mmdeploy or detectron exports. `domains` names the operator domains it
+/// provides for the preflight; empty accepts every custom domain.
+#[derive(Debug, Clone, Deserialize)]
+pub struct CustomOpsCfg {
+    pub library: String,
+    #[serde(default)]
+    pub domains: Vec<String>,
+}
+
+/// TorchScript call convention and optimizations (`[model.torch]`, backend
+/// `torch`).
+///
+/// `method` is called instead of `forward`. Inputs are passed positionally
+/// in `model.input_names` order or, with `inputs = "dict"`, as a single
+/// `Dict[str, Tensor]` argument keyed by those names. The JIT and thread
+/// settings apply to the whole process.
+#[derive(Debug, Clone, Deserialize)]
+#[cfg_attr(not(feature = "torch"), allow(dead_code))]
+pub struct TorchCfg {
+    #[serde(default = "default_torch_method")]
+    pub method: String,
+    #[serde(default = "default_torch_inputs")]
+    pub inputs: String,
+    /// Graph executor optimizations (fusion, specialization) of the JIT.
+    #[serde(default = "default_true")]
+    pub graph_executor_optimize: bool,
+    /// TensorExpr fuser (default: libtorch's choice).
+    #[serde(default)]
+    pub tensor_expr_fuser: Option<bool>,
+    /// Refuse modules that were not frozen at export (`torch.jit.freeze`);
+    /// nothing is frozen at load time.
+    #[serde(default)]
+    pub require_frozen: bool,
+    /// Intra-op threads (default: libtorch's choice, usually all cores).
+    #[serde(default)]
+    pub intra_op_threads: Option<usize>,
+    #[serde(default)]
+    pub inter_op_threads: Option<usize>,
+    /// Pass 4D inputs in channels-last (NHWC) memory format.
+    #[serde(default)]
+    pub channels_last: bool,
+}
+
+impl Default for TorchCfg {
+    fn default() -> Self {
+        Self {
+            method: default_torch_method(),
+            inputs: default_torch_inputs(),
+            graph_executor_optimize: true,
+            tensor_expr_fuser: None,
+            require_frozen: false,
+            intra_op_threads: None,
+            inter_op_threads: None,
+            channels_last: false,
+        }
+    }
+}
+
+/// TensorFlow loading options (`[model.tensorflow]`, backend `tensorflow`).
+///
+/// For a SavedModel directory, `input_names` and `output_names` are the keys
+/// of `signature`; for a frozen graph (`.pb`) they are tensor names
+/// (`op:index`).
+#[derive(Debug, Clone, Deserialize)]
+#[cfg_attr(not(feature = "tensorflow"), allow(dead_code))]
+pub struct TensorflowCfg {
+    #[serde(default = "default_tf_tags")]
+    pub tags: Vec<String>,
+    #[serde(default = "default_tf_signature")]
+    pub signature: String,
+    /// Allocate GPU memory on demand instead of reserving the whole device.
+    #[serde(default = "default_true")]
+    pub allow_growth: bool,
+}
+
+impl Default for TensorflowCfg {
+    fn default() -> Self {
+        Self { tags: default_tf_tags(), signature: default_tf_signature(), allow_growth: true }
+    }
+}
+
+fn default_tf_tags() -> Vec<String> {
+    vec!["serve".to_string()]
+}
+
+fn default_tf_signature() -> String {
+    "serving_default".to_string()
+}
+
+fn default_torch_method() -> String {
+    "forward".to_string()
+}
+
+fn default_torch_inputs() -> String {
+    "positional".to_string()
+}
+
+/// GPU selector in `gpu_ids`: a CUDA ordinal (position in
+/// `CUDA_VISIBLE_DEVICES` if set) or a device UUID as listed by
+/// `nvidia-smi -L`, e.g. `"GPU-5d3c…"` or a MIG instance `"MIG-8f1a…"`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(untagged)]
+pub enum GpuId {
+    Index(usize),
+    Uuid(String),
+}
+
+impl std::fmt::Display for GpuId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GpuId::Index(i) => write!(f, "{}", i),
+            GpuId::Uuid(uuid) => f.write_str(uuid),
+        }
+    }
+}
+
+/// Input tensor configuration for the runtime.
+///
+/// Specifies the expected dimensions and data type for incoming inference requests.
+#[derive(Debug, Clone, Deserialize)]
+pub struct InputCfg {
+    pub batch: usize,
+    pub channels: usize,
+    #[serde(default)]
+    pub depth: usize,
+    pub height: usize,
+    pub width: usize,
+    pub dtype: String,
+    #[serde(default = "default_layout")]
+    pub layout: String,
+    /// Normalization `(x - mean) / std` before inference, one value for all
+    /// channels or one per channel.
+    #[serde(default)]
+    pub mean: Vec<f32>,
+    #[serde(default)]
+    pub std: Vec<f32>,
+    #[serde(default)]
+    pub extra: Vec<NamedInputCfg>,
+}
+
+/// Additional named model input for multi-modal jobs (`[[input.extra]]`).
+///
+/// `shape` is the per-sample shape without the batch axis; `0` marks a
+/// dynamic dimension. The regular input (`job.tensor`) feeds the first entry
+/// of `model.input_names`, extra inputs are matched by name.
+#[derive(Debug, Clone, Deserialize)]
+pub struct NamedInputCfg {
+    pub name: String,
+    pub shape: Vec<usize>,
+}
+
+impl NamedInputCfg {
+    /// Validates a batched tensor `[N, ...shape]` for this input.
+    pub fn validate(&self, shape: &[usize], batch: usize) -> anyhow::Result<()> {
+        let mut expected = vec![batch];
+        expected.extend(&self.shape);
+        anyhow::ensure!(
+            crate::engine::shape_matches(&expected, shape),
+            "Input '{}' passt nicht: erwartet {:?}, bekommen {:?}",
+            self.name,
+            expected,
+            shape
+        );
+        Ok(())
+    }
+}
+
+/// Audio frontend configuration.
+///
+/// Enables the mel-spectrogram preprocessing stage and length bucketing for
+/// variable-length waveforms. Jobs carry raw samples; `buckets` lists the
+/// allowed padded lengths (in samples), longer inputs are truncated to the
+/// largest bucket.
+#[derive(Debug, Clone, Deserialize)]
+pub struct AudioCfg {
+    pub sample_rate: u32,
+    pub n_fft: usize,
+    pub hop_length: usize,
+    pub n_mels: usize,
+    #[serde(default)]
+    pub fmin: f32,
+    #[serde(default)]
+    pub fmax: Option<f32>,
+    #[serde(default)]
+    pub buckets: Vec<usize>,
+}
+
+/// Text frontend configuration.
+///
+/// Enables tokenization of raw text jobs and text-aware result formatting.
+/// `mode` selects the output format: `"classify"` (label + score), `"embed"`
+/// (embedding vector) or `"tokens"` (decoded text).
+#[derive(Debug, Clone, Deserialize)]
+pub struct TextCfg {
+    pub vocab_path: String,
+    pub max_len: usize,
+    #[serde(default)]
+    pub lowercase: bool,
+    #[serde(default = "default_text_mode")]
+    pub mode: String,
+    #[serde(default)]
+    pub labels: Vec<String>,
+    /// Emit unpadded sequences; the batcher pads to the batch/bucket maximum.
+    #[serde(default)]
+    pub dynamic_padding: bool,
+}
+
+fn default_text_mode() -> String {
+    "classify".to_string()
+}
+
+/// Embedding serving configuration.
+///
+/// Outputs are optionally L2-normalized and upserted into a vector database
+/// (`sink` = `"qdrant"`, `"milvus"` or `"pgvector"`) with the job metadata as
+/// payload. `collection` is the collection (Qdrant/Milvus) or table (pgvector).
+#[derive(Debug, Clone, Deserialize)]
+pub struct EmbeddingCfg {
+    #[serde(default = "default_true")]
+    pub normalize: bool,
+    pub sink: String,
+    pub url: String,
+    pub collection: String,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// Sampling parameters for text generation.
+///
+/// Used as `[generation]` defaults and as per-job overrides. A `temperature`
+/// of `0` selects greedy decoding.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SamplingParams {
+    pub temperature: f32,
+    pub top_p: f32,
+    pub max_new_tokens: usize,
+    pub stop: Vec<String>,
+    pub seed: Option<u64>,
+    /// EBNF grammar the generated text must follow (see [`crate::grammar`]).
+    pub grammar: Option<String>,
+    /// JSON schema the generated text must follow.
+    pub json_schema: Option<serde_json::Value>,
+}
+
+impl Default for SamplingParams {
+    fn default() -> Self {
+        Self { temperature: 1.0, top_p: 1.0, max_new_tokens: 64, stop: Vec::new(), seed: None, grammar: None, json_schema: None }
+    }
+}
+
+/// Text generation configuration for decoder models.
+///
+/// Switches workers from batch inference to the token-by-token generation
+/// loop. Requires `[text]` for the tokenizer vocabulary.
+#[derive(Debug, Clone, Deserialize)]
+pub struct GenerationCfg {
+    #[serde(flatten)]
+    pub sampling: SamplingParams,
+    #[serde(default)]
+    pub eos_token: Option<String>,
+}
+
+/// KV-cache configuration for generation sessions.
+///
+/// `budget_mb` is the memory budget per device (worker) for cached attention
+/// state of multi-turn sessions, `prefix_budget_mb` the separate budget for
+/// shared prompt prefixes (`meta.prefix`); `0` disables prefix caching.
+#[derive(Debug, Clone, Deserialize)]
+pub struct KvCacheCfg {
+    pub budget_mb: usize,
+    #[serde(default)]
+    pub prefix_budget_mb: usize,
+}
+
+/// Object detection output configuration.
+///
+/// Decodes detector outputs `[num_boxes, 4 (+1 objectness) + num_classes]`
+/// into structured detections. `box_format` is the model's box encoding
+/// (`"xyxy"`, `"xywh"` or `"cxcywh"`); stored boxes are always xyxy,
+/// optionally normalized by the input size.
+#[derive(Debug, Clone, Deserialize)]
+pub struct DetectionCfg {
+    #[serde(default = "default_box_format")]
+    pub box_format: String,
+    #[serde(default)]
+    pub objectness: bool,
+    #[serde(default = "default_score_threshold")]
+    pub score_threshold: f32,
+    #[serde(default = "default_iou_threshold")]
+    pub iou_threshold: f32,
+    #[serde(default = "default_max_detections")]
+    pub max_detections: usize,
+    #[serde(default)]
+    pub normalize: bool,
+    #[serde(default)]
+    pub labels: Vec<String>,
+}
+
+fn default_box_format() -> String {
+    "xyxy".to_string()
+}
+
+fn default_score_threshold() -> f32 {
+    0.25
+}
+
+fn default_iou_threshold() -> f32 {
+    0.45
+}
+
+fn default_max_detections() -> usize {
+    100
+}
+
+/// Top-k classification output configuration.
+///
+/// Replaces the raw output dump with the `top_k` best classes (index, label,
+/// probability). Labels come from `labels` or a file with one label per line.
+/// With `softmax = false` the model output is taken as probabilities already.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ClassificationCfg {
+    #[serde(default = "default_top_k")]
+    pub top_k: usize,
+    #[serde(default = "default_true")]
+    pub softmax: bool,
+    #[serde(default)]
+    pub labels: Vec<String>,
+    #[serde(default)]
+    pub labels_path: Option<String>,
+}
+
+fn default_top_k() -> usize {
+    5
+}
+
+/// Segmentation output configuration.
+///
+/// Per-pixel logits are reduced to a class mask and stored as `"rle"`
+/// (run-length values/counts) or `"png"` (base64 grayscale image).
+#[derive(Debug, Clone, Deserialize)]
+pub struct SegmentationCfg {
+    #[serde(default = "default_mask_encoding")]
+    pub encoding: String,
+    #[serde(default)]
+    pub labels: Vec<String>,
+}
+
+fn default_mask_encoding() -> String {
+    "rle".to_string()
+}
+
+/// Sliding-window tiling of inputs larger than the model input (`[tiling]`).
+///
+/// `tile` and `overlap` cover the spatial axes after the channel axis
+/// (`[H, W]` for `nchw`, `[D, H, W]` for `ncdhw`). Tiles run in batches of
+/// the model's batch size, or `batch` if the model's batch axis is dynamic.
+#[derive(Debug, Clone, Deserialize)]
+pub struct TilingCfg {
+    pub tile: Vec<usize>,
+    /// Overlap of neighbouring tiles per axis; defaults to none.
+    #[serde(default)]
+    pub overlap: Vec<usize>,
+    #[serde(default = "default_tile_batch")]
+    pub batch: usize,
+    #[serde(default)]
+    pub blend: TileBlend,
+}
+
+fn default_tile_batch() -> usize {
+    8
+}
+
+/// Weighting of overlapping dense tile outputs.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TileBlend {
+    /// Plain average.
+    #[default]
+    Mean,
+    /// Weights ramp down linearly across the overlap towards the tile border.
+    Linear,
+    /// Gaussian weights centred on the tile (sigma = 1/8 of the tile size).
+    Gaussian,
+}
+
+/// Test-time augmentation (`[tta]`).
+///
+/// Jobs with `"tta": true` in their metadata (all jobs with `default = true`,
+/// except those with `"tta": false`) are also run as augmented copies; the
+/// predictions are mapped back and merged.
+#[derive(Debug, Clone, Deserialize)]
+pub struct TtaCfg {
+    #[serde(default)]
+    pub transforms: Vec<TtaTransform>,
+    /// Additional input scales, e.g. `[0.75, 1.25]` (needs dynamic H/W).
+    #[serde(default)]
+    pub scales: Vec<f32>,
+    #[serde(default)]
+    pub merge: TtaMerge,
+    #[serde(default)]
+    pub default: bool,
+}
+
+/// Geometric augmentation of the last two input axes (H, W).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TtaTransform {
+    Hflip,
+    Vflip,
+    Rot90,
+    Rot180,
+    Rot270,
+}
+
+/// Merging of the original and augmented predictions.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TtaMerge {
+    /// Element-wise mean.
+    #[default]
+    Mean,
+    /// Element-wise maximum.
+    Max,
+    /// Pool the candidate boxes of all copies; `[detection]` suppresses
+    /// duplicates with its NMS.
+    Nms,
+}
+
+/// Input checks before dispatch (`[validation]`).
+#[derive(Debug, Clone, Deserialize)]
+pub struct ValidationCfg {
+    /// Rejects NaN and ±Inf values.
+    #[serde(default = "default_true")]
+    pub reject_non_finite: bool,
+    #[serde(default)]
+    pub min_value: Option<f32>,
+    #[serde(default)]
+    pub max_value: Option<f32>,
+    /// Upper bound for elements per input tensor.
+    #[serde(default = "default_validation_max_elements")]
+    pub max_elements: usize,
+    #[serde(default = "default_validation_max_rank")]
+    pub max_rank: usize,
+    /// Compares per-sample shapes with `[input]` and `[[input.extra]]`.
+    #[serde(default = "default_true")]
+    pub check_shape: bool,
+}
+
+fn default_validation_max_elements() -> usize {
+    64 * 1024 * 1024
+}
+
+fn default_validation_max_rank() -> usize {
+    8
+}
+
+/// Deterministic backends and provenance records (`[reproducibility]`).
+#[derive(Debug, Clone, Deserialize)]
+pub struct ReproducibilityCfg {
+    /// Seed for backends and for generation jobs without an own seed.
+    #[serde(default)]
+    pub seed: u64,
+    /// Records the backend library version in results.
+    #[serde(default = "default_true")]
+    pub record_versions: bool,
+}
+
+/// NaN/Inf scan of model outputs (`[output_check]`).
+#[derive(Debug, Clone, Deserialize)]
+pub struct OutputCheckCfg {
+    #[serde(default)]
+    pub action: NonFiniteAction,
+}
+
+/// Handling of results whose output contains NaN or Inf.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NonFiniteAction {
+    /// Keep the result and add a `non_finite` field.
+    #[default]
+    Flag,
+    /// Replace the result by an error result.
+    Fail,
+}
+
+/// Switch to a full-precision model on NaN/Inf (`[precision_fallback]`).
+#[derive(Debug, Clone, Deserialize)]
+pub struct PrecisionFallbackCfg {
+    /// fp32 variant of the (fp16/int8) model in `[model]`.
+    pub model_path: String,
+    /// Backend of the variant; defaults to `model.backend`.
+    #[serde(default)]
+    pub backend: Option<String>,
+    /// Redis pub/sub channel for the alert (below `out_prefix`).
+    #[serde(default = "default_precision_fallback_channel")]
+    pub channel: String,
+}
+
+fn default_precision_fallback_channel() -> String {
+    "alerts".to_string()
+}
+
+/// Periodic canary inputs with known outputs (`[probes]`).
+#[derive(Debug, Clone, Deserialize)]
+pub struct ProbesCfg {
+    /// Seconds between two probe rounds.
+    #[serde(default = "default_probes_interval")]
+    pub interval_secs: u64,
+    /// Largest allowed absolute deviation per output element.
+    #[serde(default = "default_probes_tolerance")]
+    pub tolerance: f32,
+    /// Redis pub/sub channel for the alert (below `out_prefix`).
+    #[serde(default = "default_precision_fallback_channel")]
+    pub channel: String,
+    /// Switch the failing worker to `[precision_fallback]`.
+    #[serde(default)]
+    pub fallback: bool,
+    pub canaries: Vec<CanaryCfg>,
+}
+
+fn default_probes_interval() -> u64 {
+    60
+}
+
+fn default_probes_tolerance() -> f32 {
+    1e-3
+}
+
+/// One canary (`[[probes.canaries]]`).
+#[derive(Debug, Clone, Deserialize)]
+pub struct CanaryCfg {
+    pub name: String,
+    /// `.npy` job tensor, as a worker receives it.
+    pub input: String,
+    /// `.npy` output of one job after postprocessing.
+    pub expected: String,
+    /// Overrides `[probes] tolerance`.
+    #[serde(default)]
+    pub tolerance: Option<f32>,
+}
+
+/// Input and prediction drift monitoring (`[drift]`).
+#[derive(Debug, Clone, Deserialize)]
+pub struct DriftCfg {
+    /// JSON file with the baseline statistics; recorded from the first
+    /// full window if it does not exist.
+    pub baseline_path: String,
+    /// Value range of the input histogram; values outside fall into the
+    /// first or last bin.
+    #[serde(default = "default_drift_range")]
+    pub range: [f32; 2],
+    #[serde(default = "default_drift_bins")]
+    pub bins: usize,
+    /// Jobs per comparison window.
+    #[serde(default = "default_drift_window")]
+    pub window: u64,
+    /// Population stability index above which drift is reported.
+    #[serde(default = "default_drift_threshold")]
+    pub threshold: f64,
+    #[serde(default = "default_drift_interval_secs")]
+    pub interval_secs: u64,
+    /// Redis Pub/Sub channel (below the output prefix) for drift events.
+    #[serde(default = "default_drift_channel")]
+    pub channel: String,
+}
+
+fn default_drift_range() -> [f32; 2] {
+    [-3.0, 3.0]
+}
+
+fn default_drift_bins() -> usize {
+    20
+}
+
+fn default_drift_window() -> u64 {
+    1000
+}
+
+fn default_drift_threshold() -> f64 {
+    0.2
+}
+
+fn default_drift_interval_secs() -> u64 {
+    60
+}
+
+fn default_drift_channel() -> String {
+    "drift".to_string()
+}
+
+/// GPU health probing via NVML (`[gpu_health]`).
+#[derive(Debug, Clone, Deserialize)]
+#[cfg_attr(not(feature = "nvml"), allow(dead_code))]
+pub struct GpuHealthCfg {
+    #[serde(default = "default_gpu_health_interval_secs")]
+    pub interval_secs: u64,
+    /// New uncorrected (volatile) ECC errors tolerated before eviction.
+    #[serde(default)]
+    pub max_uncorrected_ecc: u64,
+    /// Xid codes that do not evict a GPU; by default those caused by
+    /// applications rather than hardware.
+    #[serde(default = "default_gpu_health_ignore_xids")]
+    pub ignore_xids: Vec<u64>,
+}
+
+fn default_gpu_health_interval_secs() -> u64 {
+    10
+}
+
+fn default_gpu_health_ignore_xids() -> Vec<u64> {
+    vec![13, 31, 43, 45, 68, 69]
+}
+
+/// GPU time, energy and cost accounting (`[accounting]`).
+#[derive(Debug, Clone, Deserialize)]
+#[cfg_attr(not(feature = "nvml"), allow(dead_code))]
+pub struct AccountingCfg {
+    /// `model` label of the metrics; defaults to the model file stem.
+    #[serde(default)]
+    pub model: Option<String>,
+    /// Read the GPU energy counters via NVML (feature `nvml`).
+    #[serde(default)]
+    pub energy: bool,
+    /// Energy sampling interval.
+    #[serde(default = "default_accounting_interval_secs")]
+    pub interval_secs: u64,
+    /// Price of one GPU-hour for `omniengine_cost_total`.
+    #[serde(default)]
+    pub gpu_hour_price: Option<f64>,
+    /// Price of one kWh for `omniengine_cost_total` (needs `energy`).
+    #[serde(default)]
+    pub kwh_price: Option<f64>,
+}
+
+fn default_accounting_interval_secs() -> u64 {
+    5
+}
+
+/// Sampled logging of predictions for retraining (`[prediction_log]`).
+#[derive(Debug, Clone, Deserialize)]
+pub struct PredictionLogCfg {
+    /// `s3://bucket/prefix` (credentials from the `AWS_*` environment) or a
+    /// local directory.
+    pub uri: String,
+    /// Fraction of jobs logged; `meta.log_prediction` overrides per job.
+    #[serde(default = "default_prediction_sample_rate")]
+    pub sample_rate: f64,
+    #[serde(default = "default_prediction_rows_per_file")]
+    pub rows_per_file: usize,
+    #[serde(default = "default_prediction_flush_secs")]
+    pub flush_secs: u64,
+    /// Meta field holding the location of the job input (e.g. an image URL).
+    #[serde(default = "default_prediction_input_ref_key")]
+    pub input_ref_key: String,
+    /// Also log the raw output tensor (can be large for dense outputs).
+    #[serde(default = "default_true")]
+    pub include_output: bool,
+    /// Partition name of the model; defaults to the model file stem.
+    #[serde(default)]
+    pub model_name: Option<String>,
+}
+
+fn default_prediction_sample_rate() -> f64 {
+    0.01
+}
+
+fn default_prediction_rows_per_file() -> usize {
+    10_000
+}
+
+fn default_prediction_flush_secs() -> u64 {
+    300
+}
+
+fn default_prediction_input_ref_key() -> String {
+    "input_ref".to_string()
+}
+
+/// Result cache for repeated identical inputs (`[cache]`).
+#[derive(Debug, Clone, Deserialize)]
+pub struct CacheCfg {
+    #[serde(default = "default_cache_ttl_secs")]
+    pub ttl_secs: u64,
+    #[serde(default = "default_cache_max_entries")]
+    pub max_entries: usize,
+    /// Identifies the model in cache keys; defaults to path and mtime of
+    /// the model file.
+    #[serde(default)]
+    pub model_version: Option<String>,
+}
+
+fn default_cache_ttl_secs() -> u64 {
+    3600
+}
+
+fn default_cache_max_entries() -> usize {
+    10_000
+}
+
+/// Output filter applied to formatted results before they are stored.
+///
+/// `expr` is a `|`-separated chain of predicates (`score > 0.5`) and
+/// `topk(N)` stages; `field` restricts it to one result array.
+#[derive(Debug, Clone, Deserialize)]
+pub struct FilterCfg {
+    pub expr: String,
+    #[serde(default)]
+    pub field: Option<String>,
+}
+
+/// Sliding-window configuration for time-series streams.
+///
+/// Point jobs (scalar or feature vector) are buffered per series
+/// (`meta[series_key]`) and turned into `[features, window]` jobs every
+/// `stride` points.
+#[derive(Debug, Clone, Deserialize)]
+pub struct TimeSeriesCfg {
+    pub window: usize,
+    #[serde(default = "default_stride")]
+    pub stride: usize,
+    #[serde(default = "default_series_key")]
+    pub series_key: String,
+}
+
+fn default_stride() -> usize {
+    1
+}
+
+fn default_series_key() -> String {
+    "series_id".to_string()
+}
+
+/// Stateful session configuration for sequence models (RNNs, streaming ASR).
+///
+/// State outputs of one call are fed back as the matching state inputs of the
+/// next call in the same session (`state_outputs[i]` -> `state_inputs[i]`).
+/// New sessions start with zero state shaped like `model.input_shapes`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct SessionCfg {
+    pub state_inputs: Vec<String>,
+    pub state_outputs: Vec<String>,
+    #[serde(default = "default_session_ttl")]
+    pub ttl_secs: u64,
+    #[serde(default)]
+    pub max_sessions: usize,
+}
+
+fn default_session_ttl() -> u64 {
+    600
+}
+
+/// Multi-node work sharing (`[cluster]`).
+///
+/// All nodes consume jobs from one Redis Stream through a shared consumer
+/// group. Messages are acknowledged after their results are stored; messages
+/// left pending by a failed node for longer than `claim_idle_ms` are
+/// reclaimed by the remaining nodes.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ClusterCfg {
+    #[serde(default = "default_cluster_stream")]
+    pub stream: String,
+    #[serde(default = "default_cluster_group")]
+    pub group: String,
+    #[serde(default)]
+    pub node_id: Option<String>,
+    #[serde(default = "default_heartbeat_secs")]
+    pub heartbeat_secs: u64,
+    #[serde(default = "default_claim_idle_ms")]
+    pub claim_idle_ms: u64,
+    #[serde(default = "default_read_count")]
+    pub read_count: usize,
+    /// Also append every result of this node to this stream.
+    #[serde(default)]
+    pub result_stream: Option<String>,
+    /// Emit results on `result_stream` as CloudEvents.
+    #[serde(default)]
+    pub cloudevents: bool,
+    /// Encoding on `result_stream`: `"json"` or `"protobuf"` (feature `protobuf`).
+    #[serde(default = "default_result_encoding")]
+    pub result_encoding: String,
+}
+
+fn default_result_encoding() -> String {
+    "json".to_string()
+}
+
+fn default_cluster_stream() -> String {
+    "omniengine:jobs".to_string()
+}
+
+fn default_cluster_group() -> String {
+    "omniengine".to_string()
+}
+
+fn default_heartbeat_secs() -> u64 {
+    5
+}
+
+fn default_claim_idle_ms() -> u64 {
+    60_000
+}
+
+fn default_read_count() -> usize {
+    16
+}
+
+/// Kubernetes integration (`[k8s]`).
+///
+/// Enables the probe server (`/healthz`, `/readyz`, `/metrics`, `/drain`),
+/// model warmup before readiness and graceful draining on SIGTERM. `labels`
+/// maps metric label names to (Downward-API) environment variables. With
+/// `leader_election`, singleton sources only run on the lease holder.
+/// `recent_jobs` sizes the log served at `/admin/recent-jobs` (0 disables it).
+#[derive(Debug, Clone, Deserialize)]
+pub struct K8sCfg {
+    #[serde(default = "default_probe_port")]
+    pub port: u16,
+    #[serde(default = "default_warmup_runs")]
+    pub warmup_runs: usize,
+    #[serde(default = "default_drain_timeout_secs")]
+    pub drain_timeout_secs: u64,
+    #[serde(default = "default_k8s_labels")]
+    pub labels: std::collections::BTreeMap<String, String>,
+    #[serde(default)]
+    pub leader_election: bool,
+    #[serde(default = "default_lease_key")]
+    pub lease_key: String,
+    #[serde(default = "default_lease_ms")]
+    pub lease_ms: u64,
+    #[serde(default = "default_recent_jobs")]
+    pub recent_jobs: usize,
+}
+
+fn default_probe_port() -> u16 {
+    8080
+}
+
+fn default_warmup_runs() -> usize {
+    1
+}
+
+fn default_drain_timeout_secs() -> u64 {
+    30
+}
+
+fn default_k8s_labels() -> std::collections::BTreeMap<String, String> {
+    [("pod", "POD_NAME"), ("namespace", "POD_NAMESPACE"), ("node", "NODE_NAME")]
+        .into_iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect()
+}
+
+fn default_lease_key() -> String {
+    "omniengine:leader".to_string()
+}
+
+fn default_lease_ms() -> u64 {
+    15_000
+}
+
+fn default_recent_jobs() -> usize {
+    200
+}
+
+/// Daemon mode for running as a systemd service (`[daemon]`).
+///
+/// Signals readiness (`READY=1`) once all workers are warm, sends watchdog
+/// pings when the unit sets `WatchdogSec=`, writes an optional PID file and
+/// drains on SIGTERM/SIGINT before exiting.
+#[derive(Debug, Clone, Deserialize)]
+pub struct DaemonCfg {
+    #[serde(default)]
+    pub pid_file: Option<String>,
+    #[serde(default = "default_drain_timeout_secs")]
+    pub drain_timeout_secs: u64,
+}
+
+/// Client-facing API servers (`[server.*]` sections).
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ServerCfg {
+    #[serde(default)]
+    pub http: Option<HttpCfg>,
+    #[serde(default)]
+    pub grpc: Option<GrpcCfg>,
+    /// Language of error messages in results and API responses.
+    #[serde(default)]
+    pub language: crate::error::Language,
+}
+
+/// HTTP API server (`[server.http]`).
+///
+/// Serves results from the result store (`GET /v1/results/{job_id}`, with
+/// long-polling up to `max_wait_ms`) and the OpenAPI document.
+#[derive(Debug, Clone, Deserialize)]
+pub struct HttpCfg {
+    #[serde(default = "default_http_bind")]
+    pub bind: String,
+    #[serde(default = "default_max_wait_ms")]
+    pub max_wait_ms: u64,
+    #[serde(default)]
+    pub infer: Option<InferCfg>,
+    #[serde(default)]
+    pub upload: Option<UploadCfg>,
+    #[serde(default)]
+    pub stream: Option<StreamCfg>,
+}
+
+/// gRPC API of the KServe v2 inference protocol (`[server.grpc]`, feature
+/// `grpc`).
+///
+/// Serves `ModelInfer`, `ModelMetadata` and the health calls, so Triton and
+/// KServe clients can send jobs without a custom SDK. Requests for another
+/// model than `model_name` (default: file stem of `model.model_path`) are
+/// rejected; `ModelInfer` waits up to `max_wait_ms` for the results.
+#[derive(Debug, Clone, Deserialize)]
+#[cfg_attr(not(feature = "grpc"), allow(dead_code))]
+pub struct GrpcCfg {
+    #[serde(default = "default_grpc_bind")]
+    pub bind: String,
+    #[serde(default)]
+    pub model_name: Option<String>,
+    #[serde(default = "default_max_wait_ms")]
+    pub max_wait_ms: u64,
+    #[serde(default = "default_infer_max_body_bytes")]
+    pub max_message_bytes: usize,
+}
+
+fn default_grpc_bind() -> String {
+    "0.0.0.0:8001".to_string()
+}
+
+/// Inference requests (`[server.http.infer]`): `POST /v1/infer` enqueues a
+/// job in the JSON wire format and optionally waits for its result.
+#[derive(Debug, Clone, Deserialize)]
+pub struct InferCfg {
+    /// Largest request body; larger inputs use `[server.http.upload]`.
+    #[serde(default = "default_infer_max_body_bytes")]
+    pub max_body_bytes: usize,
+    #[serde(default)]
+    pub blobs: Option<BlobCfg>,
+}
+
+/// Content-addressed input cache (`[server.http.infer.blobs]`).
+///
+/// Clients upload a tensor once under its SHA-256 (`PUT /v1/blobs/{sha256}`)
+/// and reference it in later requests instead of sending the data again.
+/// Blobs are kept in memory of this server and dropped `ttl_secs` after
+/// their last use, or earlier (least recently used first) when the cache
+/// exceeds `max_bytes`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct BlobCfg {
+    #[serde(default = "default_blob_ttl_secs")]
+    pub ttl_secs: u64,
+    /// Total size of the cached tensors (f32).
+    #[serde(default = "default_blob_max_bytes")]
+    pub max_bytes: usize,
+    /// Largest upload body.
+    #[serde(default = "default_infer_max_body_bytes")]
+    pub max_blob_bytes: usize,
+}
+
+fn default_blob_ttl_secs() -> u64 {
+    300
+}
+
+fn default_blob_max_bytes() -> usize {
+    1 << 30
+}
+
+fn default_infer_max_body_bytes() -> usize {
+    64 << 20
+}
+
+/// Chunked upload of large inputs (`[server.http.upload]`).
+///
+/// Clients announce the tensor shape, send the raw f32 data in chunks of at
+/// most `chunk_bytes` with a CRC32 each and complete the upload, which
+/// enqueues the assembled job. Pending uploads are kept in memory and
+/// dropped after `ttl_secs` without a new chunk.
+#[derive(Debug, Clone, Deserialize)]
+pub struct UploadCfg {
+    #[serde(default = "default_upload_chunk_bytes")]
+    pub chunk_bytes: usize,
+    #[serde(default = "default_upload_max_bytes")]
+    pub max_bytes: usize,
+    #[serde(default = "default_max_uploads")]
+    pub max_uploads: usize,
+    #[serde(default = "default_upload_ttl_secs")]
+    pub ttl_secs: u64,
+}
+
+fn default_upload_chunk_bytes() -> usize {
+    8 << 20
+}
+
+fn default_upload_max_bytes() -> usize {
+    2 << 30
+}
+
+fn default_max_uploads() -> usize {
+    16
+}
+
+fn default_upload_ttl_secs() -> u64 {
+    3600
+}
+
+/// Streaming inference over WebSocket (`[server.http.stream]`, feature
+/// `websocket`).
+///
+/// `GET /v1/stream` accepts jobs as binary `.npy` or JSON frames and sends
+/// the results back on the same connection instead of storing them. Frames
+/// are limited to `max_frame_bytes`; each connection has at most
+/// `max_in_flight` unanswered jobs.
+#[derive(Debug, Clone, Deserialize)]
+#[cfg_attr(not(feature = "websocket"), allow(dead_code))]
+pub struct StreamCfg {
+    #[serde(default = "default_infer_max_body_bytes")]
+    pub max_frame_bytes: usize,
+    #[serde(default = "default_stream_max_in_flight")]
+    pub max_in_flight: usize,
+}
+
+fn default_stream_max_in_flight() -> usize {
+    32
+}
+
+fn default_http_bind() -> String {
+    "0.0.0.0:8000".to_string()
+}
+
+fn default_max_wait_ms() -> u64 {
+    30_000
+}
+
+/// Job source configuration (`[source.*]` sections).
+///
+/// Sources push jobs into the dispatcher channel, alongside uploads and the
+/// `[loadgen]` synthetic load.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct SourceCfg {
+    #[serde(default)]
+    pub video: Option<VideoCfg>,
+    #[serde(default)]
+    pub kafka: Option<KafkaCfg>,
+    #[serde(default)]
+    pub nats: Option<NatsCfg>,
+    #[serde(default)]
+    pub mqtt: Option<MqttCfg>,
+    #[serde(default)]
+    pub uds: Option<UdsCfg>,
+    #[serde(default)]
+    pub amqp: Option<AmqpCfg>,
+    #[serde(default)]
+    pub watch: Option<WatchCfg>,
+    #[serde(default)]
+    pub s3: Option<S3Cfg>,
+}
+
+/// Synthetic load generator (`[loadgen]`) for smoke and soak tests.
+///
+/// Random inputs (or encoded texts for `[text]` models) are sent through
+/// the regular dispatcher at `rate` jobs per second.
+#[derive(Debug, Clone, Deserialize)]
+pub struct LoadGenCfg {
+    /// Jobs per second (`0` = as fast as the queue accepts them).
+    #[serde(default = "default_loadgen_rate")]
+    pub rate: f64,
+    /// Number of jobs (`0` = until shutdown).
+    #[serde(default)]
+    pub count: usize,
+    /// Sample shape without batch axis (default: from `[input]`); sets the
+    /// length of dynamic axes.
+    #[serde(default)]
+    pub shape: Option<Vec<usize>>,
+    #[serde(default)]
+    pub distribution: Arrival,
+}
+
+fn default_loadgen_rate() -> f64 {
+    10.0
+}
+
+/// Spacing of the generated jobs.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Arrival {
+    /// Fixed interval of `1 / rate`.
+    #[default]
+    Constant,
+    /// Exponentially distributed gaps with mean `1 / rate`, i.e. bursts
+    /// like independent clients produce.
+    Poisson,
+}
+
+/// Video stream source configuration (`[source.video]`, feature `video`).
+///
+/// Frames are decoded by `ffmpeg`, resampled to `fps` and scaled to the model
+/// input size. Frames are dropped when the input queue is full.
+#[derive(Debug, Clone, Deserialize)]
+#[cfg_attr(not(feature = "video"), allow(dead_code))]
+pub struct VideoCfg {
+    #[serde(default = "default_fps")]
+    pub fps: f32,
+    #[serde(default = "default_ffmpeg")]
+    pub ffmpeg: String,
+    pub streams: Vec<VideoStreamCfg>,
+}
+
+/// A single video stream (RTSP/HTTP URL or file path).
+#[derive(Debug, Clone, Deserialize)]
+#[cfg_attr(not(feature = "video"), allow(dead_code))]
+pub struct VideoStreamCfg {
+    pub id: String,
+    pub url: String,
+}
+
+fn default_fps() -> f32 {
+    5.0
+}
+
+fn default_ffmpeg() -> String {
+    "ffmpeg".to_string()
+}
+
+/// Kafka consumer source (`[source.kafka]`, feature `kafka`).
+///
+/// Messages hold jobs in the JSON wire format (or CloudEvents wrapping them)
+/// or, with `encoding = "protobuf"`, protobuf job messages. Offsets are
+/// committed only after the results of all earlier messages of a partition
+/// are stored (at-least-once).
+#[derive(Debug, Clone, Deserialize)]
+#[cfg_attr(not(feature = "kafka"), allow(dead_code))]
+pub struct KafkaCfg {
+    /// Bootstrap servers (`bootstrap.servers`).
+    pub brokers: String,
+    pub topic: String,
+    #[serde(default = "default_kafka_group")]
+    pub group: String,
+    #[serde(default = "default_result_encoding")]
+    pub encoding: String,
+    /// Sends the jobs of a partition to the same worker
+    /// (`partition % workers`).
+    #[serde(default)]
+    pub partition_affinity: bool,
+    #[serde(default = "default_kafka_commit_interval_ms")]
+    pub commit_interval_ms: u64,
+    /// Further librdkafka settings, e.g. `security.protocol`.
+    #[serde(default)]
+    pub properties: std::collections::BTreeMap<String, String>,
+}
+
+fn default_kafka_group() -> String {
+    "omniengine".to_string()
+}
+
+fn default_kafka_commit_interval_ms() -> u64 {
+    5000
+}
+
+/// NATS JetStream source (`[source.nats]`, feature `nats`).
+///
+/// Pulls jobs from `stream` through a durable consumer; messages are acked
+/// explicitly once their results are stored, so unfinished jobs are
+/// redelivered after `ack_wait_ms` (at-least-once).
+#[derive(Debug, Clone, Deserialize)]
+#[cfg_attr(not(feature = "nats"), allow(dead_code))]
+pub struct NatsCfg {
+    #[serde(default = "default_nats_url")]
+    pub url: String,
+    pub stream: String,
+    /// Only messages of this subject (default: all subjects of the stream).
+    #[serde(default)]
+    pub subject: Option<String>,
+    /// Durable consumer name; nodes sharing it share the messages.
+    #[serde(default = "default_kafka_group")]
+    pub consumer: String,
+    #[serde(default = "default_result_encoding")]
+    pub encoding: String,
+    #[serde(default = "default_nats_ack_wait_ms")]
+    pub ack_wait_ms: u64,
+    /// Unacked messages per consumer before the server stops delivering.
+    #[serde(default = "default_nats_max_ack_pending")]
+    pub max_ack_pending: i64,
+    /// NATS credentials file (`.creds`).
+    #[serde(default)]
+    pub credentials: Option<String>,
+}
+
+fn default_nats_url() -> String {
+    "nats://localhost:4222".to_string()
+}
+
+fn default_nats_ack_wait_ms() -> u64 {
+    30000
+}
+
+fn default_nats_max_ack_pending() -> i64 {
+    1000
+}
+
+/// MQTT subscriber source (`[source.mqtt]`, feature `mqtt`).
+///
+/// Each message on `topics` becomes a job: an encoded image (JPEG, PNG)
+/// resized to the `[input]` size, or with `encoding = "json"`/`"protobuf"`
+/// a job message. Like video frames, messages are dropped while the input
+/// queue is full.
+#[derive(Debug, Clone, Deserialize)]
+#[cfg_attr(not(feature = "mqtt"), allow(dead_code))]
+pub struct MqttCfg {
+    #[serde(default = "default_mqtt_host")]
+    pub host: String,
+    #[serde(default = "default_mqtt_port")]
+    pub port: u16,
+    #[serde(default = "default_kafka_group")]
+    pub client_id: String,
+    /// Topic filters, wildcards (`+`, `#`) allowed.
+    pub topics: Vec<String>,
+    #[serde(default)]
+    pub qos: u8,
+    #[serde(default = "default_mqtt_encoding")]
+    pub encoding: String,
+    #[serde(default)]
+    pub username: Option<String>,
+    #[serde(default)]
+    pub password: Option<String>,
+    #[serde(default = "default_mqtt_keep_alive_s")]
+    pub keep_alive_s: u64,
+    /// Largest accepted message in KiB.
+    #[serde(default = "default_mqtt_max_payload_kb")]
+    pub max_payload_kb: usize,
+}
+
+fn default_mqtt_host() -> String {
+    "localhost".to_string()
+}
+
+fn default_mqtt_port() -> u16 {
+    1883
+}
+
+fn default_mqtt_encoding() -> String {
+    "image".to_string()
+}
+
+fn default_mqtt_keep_alive_s() -> u64 {
+    30
+}
+
+fn default_mqtt_max_payload_kb() -> usize {
+    1024
+}
+
+/// Unix domain socket source (`[source.uds]`, Unix only).
+///
+/// Co-located producers connect to `path` and write length-prefixed binary
+/// frames (job id, dtype, shape, raw data) without HTTP or JSON overhead.
+/// A stale socket file is replaced on start; `mode` sets its permissions.
+#[derive(Debug, Clone, Deserialize)]
+#[cfg_attr(not(unix), allow(dead_code))]
+pub struct UdsCfg {
+    pub path: String,
+    #[serde(default)]
+    pub mode: Option<u32>,
+    #[serde(default = "default_infer_max_body_bytes")]
+    pub max_frame_bytes: usize,
 }
 
-impl InputSpec {
-    /// Validates that a tensor matches this specification.
-    ///
-    /// Checks shape dimensions and data type against the spec requirements.
-    ///
-    /// # Arguments
-    ///
-    /// * `shape` - Tensor shape as slice [N, C, H, W]
-    /// * `dtype` - Data type string (e.g., "f32", "u8")
-    ///
-    /// # Returns
-    ///
-    /// * `Ok(())` - Tensor matches specification
-    /// * `Err(e)` - Validation error with details
-    pub fn validate(&self, shape: &[usize], dtype: &str) -> anyhow::Result<()> {
-        anyhow::ensure!(shape.len() == 4, "Input muss 4D (NCHW) sein");
-        anyhow::ensure!(shape[0] == self.batch, "Batch size passt nicht");
-        anyhow::ensure!(shape[1] == self.channels, "Channels passen nicht");
-        anyhow::ensure!(
-            shape[2] == self.height && shape[3] == self.width,
-            "H/W passen nicht"
-        );
-        anyhow::ensure!(dtype == self.dtype, "dtype passt nicht");
-        Ok(())
-    }
+/// AMQP 0-9-1 consumer source (`[source.amqp]`, feature `amqp`), e.g.
+/// RabbitMQ.
+///
+/// Messages of `queue` are acknowledged once their results are stored.
+/// Jobs whose inference fails are requeued once; a failed redelivery is
+/// rejected so the queue's dead-letter exchange (if any) takes it.
+#[derive(Debug, Clone, Deserialize)]
+#[cfg_attr(not(feature = "amqp"), allow(dead_code))]
+pub struct AmqpCfg {
+    #[serde(default = "default_amqp_url")]
+    pub url: String,
+    pub queue: String,
+    #[serde(default = "default_kafka_group")]
+    pub consumer_tag: String,
+    #[serde(default = "default_result_encoding")]
+    pub encoding: String,
+    /// Unacked messages the broker delivers ahead (default: two batches,
+    /// `2 * [queue] max_batch`).
+    #[serde(default)]
+    pub prefetch: Option<u16>,
+    /// Requeue failed jobs once instead of rejecting them right away.
+    #[serde(default = "default_true")]
+    pub requeue: bool,
 }
 
-/// Model configuration including backend, device, and I/O specifications.
+fn default_amqp_url() -> String {
+    "amqp://localhost:5672/%2f".to_string()
+}
+
+/// Directory watcher source (`[source.watch]`, feature `watch`) for batch
+/// scoring.
 ///
-/// Defines which ML backend to use (onnx, tensorrt, torch, tensorflow),
-/// device allocation (cpu/gpu), and model input/output specifications.
+/// New `.npy` and image files in `dir` become jobs named after the file.
+/// Files are read once they stopped changing for `settle_ms`.
 #[derive(Debug, Clone, Deserialize)]
-pub struct ModelCfg {
-    pub backend: String,
-    pub device: String,
-    pub model_path: String,
+#[cfg_attr(not(feature = "watch"), allow(dead_code))]
+pub struct WatchCfg {
+    pub dir: String,
+    /// Accepted file extensions (case-insensitive).
+    #[serde(default = "default_watch_extensions")]
+    pub extensions: Vec<String>,
+    /// Files are moved here once their result is stored (default: left in
+    /// place).
+    #[serde(default)]
+    pub done_dir: Option<String>,
+    /// Files that cannot be decoded or whose inference fails are moved here.
     #[serde(default)]
-    pub gpu_ids: Vec<usize>,
+    pub failed_dir: Option<String>,
+    /// Also submit the files present at startup.
+    #[serde(default = "default_true")]
+    pub existing: bool,
+    #[serde(default = "default_watch_settle_ms")]
+    pub settle_ms: u64,
+}
 
-    pub input_names: Vec<String>,
-    pub input_shapes: Vec<Vec<usize>>,
-    pub output_names: Vec<String>,
-    pub output_shapes: Vec<Vec<usize>>,
+fn default_watch_extensions() -> Vec<String> {
+    ["npy", "png", "jpg", "jpeg"].map(String::from).to_vec()
 }
 
-/// Input tensor configuration for the runtime.
+fn default_watch_settle_ms() -> u64 {
+    500
+}
+
+/// Object store polling source (`[source.s3]`, feature `s3`) for cloud
+/// batch inference, also for S3-compatible stores such as MinIO.
 ///
-/// Specifies the expected dimensions and data type for incoming inference requests.
+/// New `.npy` and image objects under the prefix become jobs named after
+/// their key. Processed keys are tracked in a Redis set.
 #[derive(Debug, Clone, Deserialize)]
-pub struct InputCfg {
-    pub batch: usize,
-    pub channels: usize,
-    pub height: usize,
-    pub width: usize,
-    pub dtype: String,
+#[cfg_attr(not(feature = "s3"), allow(dead_code))]
+pub struct S3Cfg {
+    /// `s3://bucket/prefix`; credentials and region from the `AWS_*`
+    /// environment.
+    pub uri: String,
+    /// Endpoint of an S3-compatible store, e.g. `http://minio:9000`.
+    #[serde(default)]
+    pub endpoint: Option<String>,
+    /// Accepted key extensions (case-insensitive).
+    #[serde(default = "default_watch_extensions")]
+    pub extensions: Vec<String>,
+    #[serde(default = "default_s3_poll_secs")]
+    pub poll_secs: u64,
+    /// Redis set of processed keys; failed keys go to
+    /// `{processed_set}:failed`.
+    #[serde(default = "default_s3_processed_set")]
+    pub processed_set: String,
+    /// Larger objects are skipped as failed.
+    #[serde(default = "default_infer_max_body_bytes")]
+    pub max_object_bytes: usize,
+}
+
+fn default_s3_poll_secs() -> u64 {
+    30
+}
+
+fn default_s3_processed_set() -> String {
+    "omniengine:s3:processed".to_string()
 }
 
 /// Queue configuration for dynamic batching.
@@ -96,6 +1567,103 @@ pub struct InputCfg {
 pub struct QueueCfg {
     pub max_batch: usize,
     pub max_wait_ms: u64,
+    #[serde(default)]
+    pub adaptive: Option<AdaptiveCfg>,
+    /// Name of a policy registered with `batcher::register_policy`.
+    #[serde(default)]
+    pub policy: Option<String>,
+    #[serde(default)]
+    pub length_sort: Option<LengthSortCfg>,
+    /// Batches per worker whose results may still be stored while the next one runs.
+    #[serde(default = "default_max_in_flight")]
+    pub max_in_flight: usize,
+    /// `max_wait_ms` per priority class (job metadata `priority`).
+    #[serde(default)]
+    pub priority_wait_ms: std::collections::BTreeMap<String, u64>,
+    /// Upper bound for the input payload of a batch in bytes.
+    #[serde(default)]
+    pub max_batch_bytes: Option<usize>,
+    #[serde(default)]
+    pub rebalance: Option<RebalanceCfg>,
+    /// Priority classes whose jobs dispatch the pending batch without waiting
+    /// (or after their `priority_wait_ms`).
+    #[serde(default)]
+    pub preempt: Vec<String>,
+    #[serde(default)]
+    pub stages: Option<StagesCfg>,
+}
+
+/// Three-stage batch workers (`[queue.stages]`).
+///
+/// Preprocessing, inference and postprocessing run in separate stages
+/// connected by channels holding up to `capacity` batches.
+#[derive(Debug, Clone, Deserialize)]
+pub struct StagesCfg {
+    #[serde(default = "default_stage_capacity")]
+    pub capacity: usize,
+}
+
+fn default_stage_capacity() -> usize {
+    2
+}
+
+/// Periodic rebalancing of the worker queues (`[queue.rebalance]`).
+///
+/// Every `interval_ms`, queued jobs move from the fullest to the emptiest
+/// worker queue once their depths differ by more than `threshold`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct RebalanceCfg {
+    #[serde(default = "default_rebalance_interval_ms")]
+    pub interval_ms: u64,
+    #[serde(default = "default_rebalance_threshold")]
+    pub threshold: usize,
+}
+
+fn default_rebalance_interval_ms() -> u64 {
+    50
+}
+
+fn default_rebalance_threshold() -> usize {
+    8
+}
+
+fn default_max_in_flight() -> usize {
+    1
+}
+
+/// Length-sorted batching for sequence models (`[queue.length_sort]`).
+///
+/// Up to `window` pending jobs are sorted by sequence length (last axis) and
+/// batched in that order; with `buckets`, a batch only holds jobs of one
+/// length bucket and is padded to the bucket length.
+#[derive(Debug, Clone, Deserialize)]
+pub struct LengthSortCfg {
+    #[serde(default = "default_length_sort_window")]
+    pub window: usize,
+    #[serde(default)]
+    pub buckets: Vec<usize>,
+}
+
+fn default_length_sort_window() -> usize {
+    256
+}
+
+/// Latency-SLO adaptive batching (`[queue.adaptive]`).
+///
+/// Workers tune wait time and batch size between `min_wait_ms`/1 and the
+/// `[queue]` limits so that the p99 batch latency stays below
+/// `target_p99_ms`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct AdaptiveCfg {
+    pub target_p99_ms: f64,
+    #[serde(default)]
+    pub min_wait_ms: u64,
+    #[serde(default = "default_adaptive_window")]
+    pub window: usize,
+}
+
+fn default_adaptive_window() -> usize {
+    256
 }
 
 /// Redis configuration for output storage.
@@ -105,6 +1673,70 @@ pub struct QueueCfg {
 pub struct RedisCfg {
     pub url: String,
     pub out_prefix: String,
+    /// Also store full output tensors in chunks of this many values.
+    #[serde(default)]
+    pub chunk_elements: Option<usize>,
+    /// Compress stored results and chunks above a size threshold.
+    #[serde(default)]
+    pub compression: Option<CompressionCfg>,
+    /// Element type of stored tensor chunks.
+    #[serde(default)]
+    pub tensor_dtype: TensorDtype,
+}
+
+/// Element type of stored tensor chunks. Reduced types shrink the payload
+/// when consumers do not need full precision; `i8` is scaled symmetrically
+/// by the tensor's largest absolute value.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TensorDtype {
+    #[default]
+    F32,
+    F16,
+    Bf16,
+    I8,
+}
+
+impl TensorDtype {
+    /// Bytes per element.
+    pub fn size(self) -> usize {
+        match self {
+            TensorDtype::F32 => 4,
+            TensorDtype::F16 | TensorDtype::Bf16 => 2,
+            TensorDtype::I8 => 1,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            TensorDtype::F32 => "f32",
+            TensorDtype::F16 => "f16",
+            TensorDtype::Bf16 => "bf16",
+            TensorDtype::I8 => "i8",
+        }
+    }
+}
+
+/// Result compression (`[redis.compression]`).
+#[derive(Debug, Clone, Deserialize)]
+pub struct CompressionCfg {
+    /// `"gzip"` or `"zstd"` (feature `zstd`).
+    #[serde(default = "default_compression_codec")]
+    pub codec: String,
+    /// Codec level; the codec's default if unset.
+    #[serde(default)]
+    pub level: Option<i32>,
+    /// Smaller payloads are stored uncompressed.
+    #[serde(default = "default_compression_min_bytes")]
+    pub min_bytes: usize,
+}
+
+fn default_compression_codec() -> String {
+    "gzip".to_string()
+}
+
+fn default_compression_min_bytes() -> usize {
+    4096
 }
 
 /// Complete runtime configuration.
@@ -117,6 +1749,64 @@ pub struct Config {
     pub input: InputCfg,
     pub queue: QueueCfg,
     pub redis: RedisCfg,
+    #[serde(default)]
+    pub audio: Option<AudioCfg>,
+    #[serde(default)]
+    pub text: Option<TextCfg>,
+    #[serde(default)]
+    pub embedding: Option<EmbeddingCfg>,
+    #[serde(default)]
+    pub generation: Option<GenerationCfg>,
+    #[serde(default)]
+    pub kv_cache: Option<KvCacheCfg>,
+    #[serde(default)]
+    pub classification: Option<ClassificationCfg>,
+    #[serde(default)]
+    pub detection: Option<DetectionCfg>,
+    #[serde(default)]
+    pub segmentation: Option<SegmentationCfg>,
+    #[serde(default)]
+    pub filter: Option<FilterCfg>,
+    #[serde(default)]
+    pub cache: Option<CacheCfg>,
+    #[serde(default)]
+    pub tiling: Option<TilingCfg>,
+    #[serde(default)]
+    pub tta: Option<TtaCfg>,
+    #[serde(default)]
+    pub validation: Option<ValidationCfg>,
+    #[serde(default)]
+    pub output_check: Option<OutputCheckCfg>,
+    #[serde(default)]
+    pub precision_fallback: Option<PrecisionFallbackCfg>,
+    #[serde(default)]
+    pub probes: Option<ProbesCfg>,
+    #[serde(default)]
+    pub drift: Option<DriftCfg>,
+    #[serde(default)]
+    pub prediction_log: Option<PredictionLogCfg>,
+    #[serde(default)]
+    pub gpu_health: Option<GpuHealthCfg>,
+    #[serde(default)]
+    pub accounting: Option<AccountingCfg>,
+    #[serde(default)]
+    pub reproducibility: Option<ReproducibilityCfg>,
+    #[serde(default)]
+    pub session: Option<SessionCfg>,
+    #[serde(default)]
+    pub timeseries: Option<TimeSeriesCfg>,
+    #[serde(default)]
+    pub cluster: Option<ClusterCfg>,
+    #[serde(default)]
+    pub k8s: Option<K8sCfg>,
+    #[serde(default)]
+    pub daemon: Option<DaemonCfg>,
+    #[serde(default)]
+    pub source: SourceCfg,
+    #[serde(default)]
+    pub loadgen: Option<LoadGenCfg>,
+    #[serde(default)]
+    pub server: ServerCfg,
 }
 
 impl Config {
@@ -129,9 +1819,11 @@ impl Config {
         InputSpec {
             batch: self.input.batch,
             channels: self.input.channels,
+            depth: self.input.depth,
             height: self.input.height,
             width: self.input.width,
             dtype: self.input.dtype.clone(),
+            layout: self.input.layout.clone(),
         }
     }
 }
@@ -141,13 +1833,152 @@ impl Config {
 /// A single inference job with unique ID and input tensor.
 ///
 /// Jobs are submitted to the runtime queue and processed in batches.
-/// Each job carries a unique identifier for result tracking.
-#[derive(Debug, Clone)]
+/// Each job carries a unique identifier for result tracking and optional
+/// client metadata that is passed through to the result sinks.
+#[derive(Debug, Clone, Default)]
 pub struct Job {
     pub id: String,          // z. B. UUID
     pub tensor: ArrayD<f32>, // NCHW; kann Batch 1 sein, wird in der Mainloop gestapelt
+    pub meta: JobMeta,
+    pub inputs: NamedTensors, // weitere benannte Inputs (multi-modal)
+    pub ack: Option<Ack>,     // Quittung für dauerhafte Queues
+    pub enqueued: Option<std::time::Instant>, // Übergabe an den Worker (Queue-Wartezeit)
+}
+
+/// Completion handle for jobs from durable queues.
+///
+/// The worker calls [`Ack::done`] once the job result has been stored; the
+/// source then acknowledges the message (at-least-once delivery). Jobs that
+/// are never acknowledged are redelivered by the queue. Clones share the
+/// handle.
+#[derive(Debug, Clone)]
+pub struct Ack {
+    inner: std::sync::Arc<AckInner>,
+}
+
+#[derive(Debug)]
+struct AckInner {
+    tx: tokio::sync::mpsc::UnboundedSender<String>,
+    nack: Option<tokio::sync::mpsc::UnboundedSender<String>>,
+    token: String,
+    done: std::sync::atomic::AtomicBool,
+}
+
+impl Ack {
+    /// Creates a handle reporting `token` (e.g. a stream message id) to `tx`.
+    pub fn new(tx: tokio::sync::mpsc::UnboundedSender<String>, token: String) -> Self {
+        Self::build(tx, None, token)
+    }
+
+    /// Like [`Ack::new`], but reports `token` to `nack` when the last clone
+    /// is dropped without [`Ack::done`], e.g. because the inference failed.
+    #[cfg_attr(not(any(feature = "amqp", feature = "watch", feature = "s3")), allow(dead_code))]
+    pub fn with_nack(
+        tx: tokio::sync::mpsc::UnboundedSender<String>,
+        nack: tokio::sync::mpsc::UnboundedSender<String>,
+        token: String,
+    ) -> Self {
+        Self::build(tx, Some(nack), token)
+    }
+
+    fn build(
+        tx: tokio::sync::mpsc::UnboundedSender<String>,
+        nack: Option<tokio::sync::mpsc::UnboundedSender<String>>,
+        token: String,
+    ) -> Self {
+        let done = std::sync::atomic::AtomicBool::new(false);
+        Self { inner: std::sync::Arc::new(AckInner { tx, nack, token, done }) }
+    }
+
+    /// Reports the job as done. Errors are ignored if the source is gone.
+    pub fn done(&self) {
+        self.inner.done.store(true, std::sync::atomic::Ordering::Relaxed);
+        let _ = self.inner.tx.send(self.inner.token.clone());
+    }
+}
+
+impl Drop for AckInner {
+    fn drop(&mut self) {
+        if let (Some(nack), false) = (&self.nack, *self.done.get_mut()) {
+            let _ = nack.send(self.token.clone());
+        }
+    }
+}
+
+/// Tensor in the JSON wire format: row-major `data` with `shape`.
+#[derive(Debug, Clone, Serialize, Deserialize, utoipa::ToSchema)]
+pub struct TensorData {
+    pub shape: Vec<usize>,
+    pub data: Vec<f32>,
+}
+
+impl TensorData {
+    pub fn into_array(self) -> anyhow::Result<ArrayD<f32>> {
+        ArrayD::from_shape_vec(ndarray::IxDyn(&self.shape), self.data)
+            .map_err(|e| anyhow::anyhow!("Tensor-Daten passen nicht zur Shape: {}", e))
+    }
+}
+
+/// Version of the job and result wire formats.
+///
+/// Adding optional fields keeps the version: readers ignore unknown fields,
+/// so producers and consumers can be upgraded in any order. Messages
+/// without `schema_version` predate versioning and are read as version 1.
+pub const SCHEMA_VERSION: u32 = 1;
+
+fn default_schema_version() -> u32 {
+    1
+}
+
+/// Job in the JSON wire format used by external queues.
+///
+/// ```json
+/// {"schema_version": 1, "id": "job-1", "shape": [3, 224, 224], "data": [...], "meta": {...}}
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize, utoipa::ToSchema)]
+pub struct JobRequest {
+    #[serde(default = "default_schema_version")]
+    pub schema_version: u32,
+    pub id: String,
+    #[serde(flatten)]
+    pub tensor: TensorData,
+    #[serde(default)]
+    #[schema(value_type = Object)]
+    pub meta: JobMeta,
+    #[serde(default)]
+    pub inputs: std::collections::BTreeMap<String, TensorData>,
 }
 
+impl JobRequest {
+    /// Converts the request into a runtime job.
+    ///
+    /// Jobs of newer schema versions are accepted; fields this version does
+    /// not know are ignored.
+    pub fn into_job(self) -> anyhow::Result<Job> {
+        anyhow::ensure!(self.schema_version >= 1, "Job '{}': ungültige Schema-Version {}", self.id, self.schema_version);
+        if self.schema_version > SCHEMA_VERSION {
+            tracing::debug!(
+                "Job '{}' hat Schema-Version {} (unterstützt: {}), unbekannte Felder werden ignoriert",
+                self.id,
+                self.schema_version,
+                SCHEMA_VERSION
+            );
+        }
+        let inputs = self
+            .inputs
+            .into_iter()
+            .map(|(name, t)| Ok((name, t.into_array()?)))
+            .collect::<anyhow::Result<NamedTensors>>()?;
+        Ok(Job { id: self.id, tensor: self.tensor.into_array()?, meta: self.meta, inputs, ack: None, enqueued: None })
+    }
+}
+
+/// Free-form job metadata (JSON object).
+pub type JobMeta = serde_json::Map<String, serde_json::Value>;
+
+/// Named input tensors, keyed by model input name.
+pub type NamedTensors = std::collections::BTreeMap<String, ArrayD<f32>>;
+
 /// A batch of jobs ready for inference.
 ///
 /// Contains multiple jobs stacked into a single tensor along the batch dimension.
@@ -158,11 +1989,33 @@ pub struct Job {
 /// * `ids` - Job identifiers for all samples (including padding)
 /// * `tensor` - Stacked tensor with shape [N, C, H, W]
 /// * `actual_len` - Number of real jobs (excluding padding)
-#[derive(Debug, Clone)]
+/// * `metas` - Job metadata for the real jobs (`actual_len` entries)
+/// * `inputs` - Additional named inputs, each stacked along N
+/// * `acks` - Completion handles of the real jobs (`None` unless from a
+///   durable queue)
+/// * `stats` - Fill, padding and queue wait figures for the metrics
+#[derive(Debug, Clone, Default)]
 pub struct Batch {
     pub ids: Vec<String>,
     pub tensor: ArrayD<f32>, // NCHW; N == ids.len()
     pub actual_len: usize,
+    pub metas: Vec<JobMeta>,
+    pub inputs: NamedTensors,
+    pub acks: Vec<Option<Ack>>,
+    pub stats: BatchStats,
+}
+
+/// Utilization figures of one batch, reported to the batch metrics.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BatchStats {
+    /// Batch slots of the model (`spec_n`); `actual_len / slots` is the fill ratio.
+    pub slots: usize,
+    /// Elements of the stacked main tensor.
+    pub elements: usize,
+    /// Elements added as padding (dummy samples and variable-length padding).
+    pub padding: usize,
+    /// Time each real job spent queued before the batch was formed.
+    pub queue_waits: Vec<std::time::Duration>,
 }
 
 #[cfg(test)]
@@ -174,9 +2027,11 @@ mod tests {
         let spec = InputSpec {
             batch: 4,
             channels: 3,
+            depth: 0,
             height: 224,
             width: 224,
             dtype: "f32".to_string(),
+            layout: "nchw".to_string(),
         };
         
         assert!(spec.validate(&[4, 3, 224, 224], "f32").is_ok());
@@ -187,9 +2042,11 @@ mod tests {
         let spec = InputSpec {
             batch: 4,
             channels: 3,
+            depth: 0,
             height: 224,
             width: 224,
             dtype: "f32".to_string(),
+            layout: "nchw".to_string(),
         };
         
         assert!(spec.validate(&[2, 3, 224, 224], "f32").is_err());
@@ -200,19 +2057,77 @@ mod tests {
         let spec = InputSpec {
             batch: 4,
             channels: 3,
+            depth: 0,
             height: 224,
             width: 224,
             dtype: "f32".to_string(),
+            layout: "nchw".to_string(),
         };
         
         assert!(spec.validate(&[4, 3, 224, 224], "u8").is_err());
     }
 
+    #[test]
+    fn test_input_spec_validate_waveform() {
+        let spec = InputSpec {
+            batch: 2,
+            channels: 1,
+            depth: 0,
+            height: 0,
+            width: 16000,
+            dtype: "f32".to_string(),
+            layout: "nct".to_string(),
+        };
+
+        assert!(spec.validate(&[2, 1, 16000], "f32").is_ok());
+        assert!(spec.validate(&[2, 1, 8000], "f32").is_err());
+        assert!(spec.validate(&[2, 1, 1, 16000], "f32").is_err());
+    }
+
+    #[test]
+    fn test_input_spec_validate_volume() {
+        let spec = InputSpec {
+            batch: 1,
+            channels: 1,
+            depth: 0,
+            height: 0,
+            width: 0,
+            dtype: "f32".to_string(),
+            layout: "ncdhw".to_string(),
+        };
+
+        assert!(spec.validate(&[1, 1, 200, 512, 480], "f32").is_ok());
+        assert!(spec.validate(&[1, 2, 200, 512, 480], "f32").is_err());
+        assert!(spec.validate(&[1, 1, 512, 480], "f32").is_err());
+        assert_eq!(spec.sample_shape().unwrap().len(), 4);
+
+        let fixed = InputSpec { depth: 16, height: 112, width: 112, ..spec };
+        assert!(fixed.validate(&[1, 1, 16, 112, 112], "f32").is_ok());
+        assert!(fixed.validate(&[1, 1, 8, 112, 112], "f32").is_err());
+    }
+
+    #[test]
+    fn test_input_spec_validate_variable_length() {
+        let spec = InputSpec {
+            batch: 2,
+            channels: 1,
+            depth: 0,
+            height: 0,
+            width: 0,
+            dtype: "f32".to_string(),
+            layout: "nt".to_string(),
+        };
+
+        assert!(spec.validate(&[2, 8000], "f32").is_ok());
+        assert!(spec.validate(&[2, 32000], "f32").is_ok());
+    }
+
     #[test]
     fn test_job_creation() {
         let job = Job {
             id: "test-123".to_string(),
             tensor: ndarray::Array::zeros((1, 3, 64, 64)).into_dyn(),
+            ..Default::default()
         };
         
         assert_eq!(job.id, "test-123");
@@ -225,10 +2140,61 @@ mod tests {
             ids: vec!["job1".to_string(), "job2".to_string()],
             tensor: ndarray::Array::zeros((2, 3, 64, 64)).into_dyn(),
             actual_len: 2,
+            ..Default::default()
         };
         
         assert_eq!(batch.ids.len(), 2);
         assert_eq!(batch.actual_len, 2);
         assert_eq!(batch.tensor.shape(), &[2, 3, 64, 64]);
     }
+
+    #[test]
+    fn test_job_request_wire_format() {
+        let json = r#"{"id": "j1", "shape": [2, 2], "data": [1, 2, 3, 4], "meta": {"k": "v"},
+                       "inputs": {"ids": {"shape": [3], "data": [7, 8, 9]}}}"#;
+        let job = serde_json::from_str::<JobRequest>(json).unwrap().into_job().unwrap();
+        assert_eq!(job.id, "j1");
+        assert_eq!(job.tensor[[1, 0]], 3.0);
+        assert_eq!(job.meta["k"], "v");
+        assert_eq!(job.inputs["ids"].shape(), &[3]);
+
+        let bad = r#"{"id": "j2", "shape": [3], "data": [1]}"#;
+        assert!(serde_json::from_str::<JobRequest>(bad).unwrap().into_job().is_err());
+    }
+
+    #[test]
+    fn test_job_request_schema_versions() {
+        let legacy = serde_json::from_str::<JobRequest>(r#"{"id": "j1", "shape": [1], "data": [1]}"#).unwrap();
+        assert_eq!(legacy.schema_version, 1);
+
+        // Neuere Producer: unbekannte Felder werden ignoriert
+        let newer = r#"{"schema_version": 2, "id": "j2", "shape": [1], "data": [1], "deadline_ms": 50}"#;
+        assert_eq!(serde_json::from_str::<JobRequest>(newer).unwrap().into_job().unwrap().id, "j2");
+
+        let invalid = r#"{"schema_version": 0, "id": "j3", "shape": [1], "data": [1]}"#;
+        assert!(serde_json::from_str::<JobRequest>(invalid).unwrap().into_job().is_err());
+    }
+
+    #[test]
+    fn test_ack_reports_token() {
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        Ack::new(tx, "1-0".to_string()).done();
+        assert_eq!(rx.try_recv().unwrap(), "1-0");
+    }
+
+    #[test]
+    fn test_ack_nacks_when_dropped_undone() {
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let (nack_tx, mut nack_rx) = tokio::sync::mpsc::unbounded_channel();
+        let ack = Ack::with_nack(tx.clone(), nack_tx.clone(), "7".to_string());
+        let clone = ack.clone();
+        drop(ack);
+        assert!(nack_rx.try_recv().is_err());
+        drop(clone);
+        assert_eq!(nack_rx.try_recv().unwrap(), "7");
+
+        Ack::with_nack(tx, nack_tx, "8".to_string()).done();
+        assert_eq!(rx.try_recv().unwrap(), "8");
+        assert!(nack_rx.try_recv().is_err());
+    }
 }
@@ -0,0 +1,216 @@
+//! Input validation before dispatch (`[validation]`).
+//!
+//! Jobs with non-finite values, values outside the configured range or
+//! shapes that do not match the model input never reach a worker: they get
+//! an error result naming the offending input instead, so a malformed
+//! message cannot crash a backend or poison a whole batch.
+//!
+//! Per-sample shapes are compared with `[input]` (dynamic dimensions as
+//! `0`) and `[[input.extra]]`. The main tensor is not compared when a
+//! configured stage reshapes it before inference (`[audio]`,
+//! `[timeseries]`, `[tiling]`, `[generation]`).
+
+use anyhow::{bail, Result};
+use ndarray::ArrayD;
+
+use crate::engine::shape_matches;
+use crate::types::{Config, Job, NamedInputCfg, ValidationCfg};
+
+/// Checks jobs against `[validation]`.
+#[derive(Debug, Clone)]
+pub struct InputValidator {
+    cfg: ValidationCfg,
+    /// Expected shape of the main tensor without batch axis.
+    sample: Option<Vec<usize>>,
+    /// Expected named inputs; `None` skips the named input shapes.
+    extra: Option<Vec<NamedInputCfg>>,
+}
+
+impl InputValidator {
+    pub fn new(cfg: &ValidationCfg) -> Result<Self> {
+        if let (Some(min), Some(max)) = (cfg.min_value, cfg.max_value) {
+            anyhow::ensure!(min <= max, "validation: min_value {} liegt über max_value {}", min, max);
+        }
+        Ok(Self { cfg: cfg.clone(), sample: None, extra: None })
+    }
+
+    /// Validator for `[validation]` with the input shapes of `cfg`, if configured.
+    pub fn from_config(cfg: &Config) -> Result<Option<Self>> {
+        let Some(validation) = &cfg.validation else { return Ok(None) };
+        let mut validator = Self::new(validation)?;
+        if validation.check_shape {
+            // Spektrogramm, Fenster, Kacheln und Prompts ändern die Shape vor der Inferenz
+            let reshaped = cfg.audio.is_some() || cfg.timeseries.is_some() || cfg.tiling.is_some() || cfg.generation.is_some();
+            if !reshaped {
+                validator.sample = Some(cfg.input_spec().sample_shape()?);
+            }
+            validator.extra = Some(cfg.input.extra.clone());
+        }
+        Ok(Some(validator))
+    }
+
+    /// Checks the main tensor and all named inputs of `job`.
+    pub fn check(&self, job: &Job) -> Result<()> {
+        self.check_tensor("input", &job.tensor)?;
+        if let Some(sample) = &self.sample {
+            check_shape("input", sample, job.tensor.shape())?;
+        }
+        for (name, tensor) in &job.inputs {
+            self.check_tensor(name, tensor)?;
+        }
+        if let Some(extra) = self.extra.as_ref().filter(|_| !job.inputs.is_empty()) {
+            if let Some(name) = job.inputs.keys().find(|n| !extra.iter().any(|e| &e.name == *n)) {
+                bail!("'{}': Input ist nicht in [[input.extra]] konfiguriert", name);
+            }
+            for input in extra {
+                let Some(tensor) = job.inputs.get(&input.name) else { bail!("'{}': Input fehlt", input.name) };
+                check_shape(&input.name, &input.shape, tensor.shape())?;
+            }
+        }
+        Ok(())
+    }
+
+    fn check_tensor(&self, name: &str, tensor: &ArrayD<f32>) -> Result<()> {
+        let shape = tensor.shape();
+        anyhow::ensure!(
+            !shape.is_empty() && shape.len() <= self.cfg.max_rank,
+            "'{}': Rang {} nicht erlaubt (1 bis {})",
+            name,
+            shape.len(),
+            self.cfg.max_rank
+        );
+        anyhow::ensure!(!shape.contains(&0), "'{}': leere Dimension in Shape {:?}", name, shape);
+        anyhow::ensure!(
+            tensor.len() <= self.cfg.max_elements,
+            "'{}': {} Elemente überschreiten max_elements {}",
+            name,
+            tensor.len(),
+            self.cfg.max_elements
+        );
+
+        let min = self.cfg.min_value.unwrap_or(f32::NEG_INFINITY);
+        let max = self.cfg.max_value.unwrap_or(f32::INFINITY);
+        for (i, &v) in tensor.iter().enumerate() {
+            if !v.is_finite() {
+                if self.cfg.reject_non_finite {
+                    bail!("'{}': nicht endlicher Wert {} an Position {}", name, v, i);
+                }
+                continue;
+            }
+            if v < min || v > max {
+                bail!("'{}': Wert {} an Position {} außerhalb von [{}, {}]", name, v, i, min, max);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Compares a per-sample shape with the configured one (`0` is dynamic).
+fn check_shape(name: &str, expected: &[usize], actual: &[usize]) -> Result<()> {
+    anyhow::ensure!(
+        shape_matches(expected, actual),
+        "'{}': Shape {:?} passt nicht zu {:?} (0 = dynamisch)",
+        name,
+        actual,
+        expected
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::IxDyn;
+
+    fn cfg() -> ValidationCfg {
+        toml::from_str("min_value = 0.0\nmax_value = 255.0\nmax_elements = 16").unwrap()
+    }
+
+    fn job(shape: &[usize], value: f32) -> Job {
+        Job { id: "j1".to_string(), tensor: ArrayD::from_elem(IxDyn(shape), value), ..Default::default() }
+    }
+
+    #[test]
+    fn test_values_and_shapes() {
+        let validator = InputValidator::new(&cfg()).unwrap();
+        assert!(validator.check(&job(&[2, 3], 128.0)).is_ok());
+
+        let err = validator.check(&job(&[2], f32::NAN)).unwrap_err().to_string();
+        assert!(err.contains("nicht endlicher Wert"), "{}", err);
+        assert!(validator.check(&job(&[2], 300.0)).is_err());
+        assert!(validator.check(&job(&[17], 1.0)).is_err());
+        assert!(validator.check(&job(&[0, 3], 1.0)).is_err());
+
+        let mut named = job(&[2], 1.0);
+        named.inputs.insert("mask".to_string(), ArrayD::from_elem(IxDyn(&[2]), -1.0));
+        let err = validator.check(&named).unwrap_err().to_string();
+        assert!(err.contains("'mask'"), "{}", err);
+    }
+
+    fn config(extra: &str) -> Config {
+        let raw = format!(
+            r#"
+            [model]
+            backend = "onnx"
+            device = "cpu"
+            model_path = "model.onnx"
+            input_names = ["image", "mask"]
+            input_shapes = [[0, 3, 4, 0], [0, 2]]
+            output_names = ["logits"]
+            output_shapes = [[0, 10]]
+            [queue]
+            max_batch = 2
+            max_wait_ms = 5
+            [redis]
+            url = "memory://"
+            out_prefix = "results"
+            [input]
+            batch = 2
+            channels = 3
+            height = 4
+            width = 0
+            dtype = "f32"
+            [[input.extra]]
+            name = "mask"
+            shape = [2]
+            [validation]
+            {}
+            "#,
+            extra
+        );
+        toml::from_str(&raw).unwrap()
+    }
+
+    #[test]
+    fn test_shapes_against_input_spec() {
+        let validator = InputValidator::from_config(&config("")).unwrap().unwrap();
+        // Breite ist dynamisch
+        assert!(validator.check(&job(&[3, 4, 7], 1.0)).is_ok());
+        let err = validator.check(&job(&[3, 5, 7], 1.0)).unwrap_err().to_string();
+        assert!(err.contains("[3, 5, 7]"), "{}", err);
+        assert!(validator.check(&job(&[4, 7], 1.0)).is_err());
+
+        let mut named = job(&[3, 4, 7], 1.0);
+        named.inputs.insert("mask".to_string(), ArrayD::from_elem(IxDyn(&[3]), 1.0));
+        let err = validator.check(&named).unwrap_err().to_string();
+        assert!(err.contains("'mask'"), "{}", err);
+        named.inputs.insert("mask".to_string(), ArrayD::from_elem(IxDyn(&[2]), 1.0));
+        assert!(validator.check(&named).is_ok());
+        named.inputs.insert("depth".to_string(), ArrayD::from_elem(IxDyn(&[2]), 1.0));
+        assert!(validator.check(&named).is_err());
+
+        let unchecked = InputValidator::from_config(&config("check_shape = false")).unwrap().unwrap();
+        assert!(unchecked.check(&job(&[3, 5, 7], 1.0)).is_ok());
+    }
+
+    #[test]
+    fn test_non_finite_allowed_and_invalid_range() {
+        let mut allow = cfg();
+        allow.reject_non_finite = false;
+        assert!(InputValidator::new(&allow).unwrap().check(&job(&[2], f32::INFINITY)).is_ok());
+
+        let mut inverted = cfg();
+        inverted.min_value = Some(300.0);
+        assert!(InputValidator::new(&inverted).is_err());
+    }
+}
@@ -3,17 +3,81 @@
 //! This module contains the worker logic that processes batches on inference devices.
 //! Workers handle the complete inference pipeline: batching, preprocessing, inference,
 //! postprocessing, and result storage.
+//! With `[queue.stages]` the batch worker hands off to [`crate::stages`].
 
-use crate::engine::EngineFactory;
-use crate::pipeline::Pipeline;
+use crate::batcher::{LengthSorter, Unbatchable};
+use crate::engine::{Engine, EngineFactory};
+use crate::error::OmniError;
+use crate::health::health;
+use crate::output_check::OutputCheck;
+use crate::pipeline::{OutputFormatter, Pipeline};
+use crate::rebalance::JobQueue;
 use crate::storage::redis_store::RedisStorage;
-use crate::types::{Batch, Config, Job};
-use anyhow::Result;
+use crate::storage::vector_store::VectorSink;
+use crate::types::{Batch, Config, Job, NamedTensors, SCHEMA_VERSION};
+use crate::text::WordPieceTokenizer;
+use anyhow::{Context, Result};
 use chrono::Utc;
 use ndarray::Axis;
-use tokio::sync::mpsc;
+use serde_json::Value;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::task::JoinSet;
 use tracing::info;
 
+/// Error of a worker stopped because its GPU is unhealthy.
+///
+/// Carries the jobs the worker had already taken from its queue without
+/// batching them (length-sort window), so they are redistributed together
+/// with the jobs still queued.
+#[derive(Debug)]
+pub struct Evicted {
+    pub worker: String,
+    pub reason: String,
+    pub jobs: Vec<Job>,
+}
+
+impl std::fmt::Display for Evicted {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Worker {} beendet, GPU defekt: {}", self.worker, self.reason)
+    }
+}
+
+impl std::error::Error for Evicted {}
+
+impl Evicted {
+    /// `Some` once the GPU of `device` is unhealthy. Every worker kind checks
+    /// this at the head of its loop and again before running work it waited
+    /// for; the caller adds the jobs it already took from its queue.
+    pub(crate) fn check(device: Option<usize>, worker: &str) -> Option<Self> {
+        let reason = crate::gpu_health::evicted(device)?;
+        Some(Self { worker: worker.to_string(), reason, jobs: Vec::new() })
+    }
+}
+
+/// Queue of a worker that runs one job at a time (generation, sessions).
+pub struct WorkerJobs<'a> {
+    pub rx: &'a mut tokio::sync::mpsc::Receiver<Job>,
+    pub device: Option<usize>,
+    pub worker: &'a str,
+}
+
+impl WorkerJobs<'_> {
+    /// Next job, `None` once the queue is closed. Fails with [`Evicted`]
+    /// once the GPU is unhealthy; a job that arrived after it failed is
+    /// handed back for redistribution.
+    pub async fn next(&mut self) -> Result<Option<Job>, Evicted> {
+        if let Some(evicted) = Evicted::check(self.device, self.worker) {
+            return Err(evicted);
+        }
+        let Some(job) = self.rx.recv().await else { return Ok(None) };
+        match Evicted::check(self.device, self.worker) {
+            Some(evicted) => Err(Evicted { jobs: vec![job], ..evicted }),
+            None => Ok(Some(job)),
+        }
+    }
+}
+
 /// Runs an inference worker on a specific device (GPU or CPU).
 ///
 /// The worker continuously processes jobs from the input channel:
@@ -22,13 +86,16 @@ use tracing::info;
 /// 3. Validates input against model spec
 /// 4. Runs inference on the configured backend
 /// 5. Applies postprocessing pipeline
-/// 6. Stores results in Redis
+/// 6. Stores results in Redis (and upserts embeddings into the vector sink)
+///
+/// The jobs of a batch that cannot be stacked, preprocessed or inferred get
+/// error results; the worker continues with the next batch.
 ///
 /// # Arguments
 ///
 /// * `cfg` - Runtime configuration
 /// * `device_id` - GPU ID (Some(n)) or CPU (None)
-/// * `rx` - Channel receiver for incoming jobs
+/// * `rx` - Queue of incoming jobs, shared with the rebalancer
 /// * `store` - Redis storage client
 /// * `pipeline` - Pre/postprocessing pipeline
 ///
@@ -39,53 +106,375 @@ use tracing::info;
 pub async fn run_gpu_worker(
     cfg: Config,
     device_id: Option<usize>,
-    mut rx: mpsc::Receiver<Job>,
+    rx: JobQueue,
     store: RedisStorage,
     pipeline: Pipeline,
 ) -> Result<()> {
-    let spec = cfg.input_spec();
     let mut engine = EngineFactory::create_for_device(&cfg, device_id)?;
 
     info!("Starte Engine: {}", engine.name());
+    let worker = match device_id {
+        Some(id) => format!("gpu:{}", id),
+        None => "cpu".to_string(),
+    };
+    let capabilities = engine.capabilities();
+    anyhow::ensure!(
+        cfg.input.extra.is_empty() || capabilities.named_inputs,
+        "Backend '{}' unterstützt keine benannten Inputs ([[input.extra]])",
+        engine.name()
+    );
+    crate::engine::register_capabilities(&worker, capabilities);
 
-    loop {
-        let Some(batch) = crate::batcher::collect_batch(
-            spec.batch,
-            &mut rx,
-            cfg.queue.max_batch.min(spec.batch),
-            cfg.queue.max_wait_ms,
-        )
-        .await?
-        else {
-            break; // Channel geschlossen
-        };
+    // Warmup vor der Readiness (erste Inferenz allokiert/kompiliert Kernel)
+    if let Some(k8s) = &cfg.k8s {
+        for _ in 0..k8s.warmup_runs {
+            warmup(engine.as_mut(), &cfg)?;
+        }
+        info!("Warmup abgeschlossen ({} Läufe)", k8s.warmup_runs);
+    }
+    health().worker_ready();
+
+    // Generative Modelle laufen Token für Token statt im Batch
+    if let Some(gen_cfg) = cfg.generation.clone() {
+        let text_cfg = cfg.text.as_ref().context("[generation] benötigt [text] für das Vokabular")?;
+        let tokenizer = WordPieceTokenizer::from_file(&text_cfg.vocab_path, text_cfg.lowercase)?;
+        let mut rx = rx.lock_owned().await;
+        let mut jobs = WorkerJobs { rx: &mut rx, device: device_id, worker: &worker };
+        let repro = crate::reproducibility::Reproducibility::from_config(&cfg);
+        return crate::generation::run_generation_worker(gen_cfg, Box::new(crate::generation::EngineDecoder::new(engine)), tokenizer, &mut jobs, store, cfg.kv_cache.as_ref(), repro).await;
+    }
+
+    // Zustandsbehaftete Modelle: ein Job pro Aufruf, State je Session
+    if cfg.session.is_some() {
+        let mut rx = rx.lock_owned().await;
+        let mut jobs = WorkerJobs { rx: &mut rx, device: device_id, worker: &worker };
+        return crate::session::run_session_worker(cfg, engine, &mut jobs, store, pipeline).await;
+    }
 
-        let Batch { ids, tensor, actual_len } = batch;
+    run_batch_worker(cfg, device_id, engine, rx, store, pipeline, worker).await
+}
 
-        // Preprocessing
-        let x = pipeline.run_pre(tensor)?;
-        spec.validate(x.shape(), "f32")?;
-        let y = engine.infer_array(x)?;
-        let y = pipeline.run_post(y)?;
+/// Batches jobs from `rx` and runs them on `engine` until the queue is
+/// closed or the GPU is evicted.
+async fn run_batch_worker(
+    cfg: Config,
+    device_id: Option<usize>,
+    mut engine: Box<dyn Engine>,
+    rx: JobQueue,
+    store: RedisStorage,
+    pipeline: Pipeline,
+    worker: String,
+) -> Result<()> {
+    let spec = cfg.input_spec();
+    let capabilities = engine.capabilities();
+
+    // Embedding-Modus: Vektoren zusätzlich in die Vektor-DB schreiben
+    let vectors = match &cfg.embedding {
+        Some(emb) => Some(Arc::new(VectorSink::connect(emb).await?)),
+        None => None,
+    };
+
+    // Feste Batch-Größe des Modells begrenzt die Batches zusätzlich
+    let max_batch = cfg.queue.max_batch.min(spec.batch).min(capabilities.max_batch.unwrap_or(usize::MAX));
+    let mut policy = crate::batcher::policy_for(&cfg.queue, max_batch)?;
+
+    let mut sorter = cfg.queue.length_sort.as_ref().map(LengthSorter::new);
+
+    // Vor-/Nachverarbeitung getrennt von der Inferenz auf eigenem Thread
+    if cfg.queue.stages.is_some() {
+        let collector = crate::stages::Collector { rx, policy, sorter, device: device_id };
+        return crate::stages::run_staged_worker(cfg, engine, collector, store, pipeline, vectors, worker).await;
+    }
+
+    // Gespeichert wird im Hintergrund, während der nächste Batch läuft
+    let check = OutputCheck::from_config(&cfg, &worker).map(Arc::new);
+    let tta = crate::tta::Tta::from_config(&cfg)?;
+    let max_in_flight = cfg.queue.max_in_flight.max(1);
+    let mut in_flight: JoinSet<Duration> = JoinSet::new();
+    // Job, der nicht mehr in den letzten Batch passte (Byte-Budget)
+    let mut held = None;
+
+    let evicted = loop {
+        // Defekte GPU: keine neuen Batches, laufende fertig speichern, dann ausfallen
+        if let Some(evicted) = Evicted::check(device_id, &worker) {
+            break Some(evicted);
+        }
+        while in_flight.len() >= max_in_flight {
+            let Some(done) = in_flight.join_next().await else { break };
+            if let Some(latency) = stored_latency(done, &worker) {
+                policy.observe(latency, rx.lock().await.len());
+            }
+        }
+
+        // Queue nur beim Sammeln sperren, damit der Rebalancer währenddessen umverteilen kann
+        let next = {
+            let mut rx = rx.lock().await;
+            match sorter.as_mut() {
+                Some(s) => s.next_batch(spec.batch, &mut rx, policy.as_ref()).await,
+                None => crate::batcher::collect_batch_held(spec.batch, &mut rx, policy.as_ref(), &mut held).await,
+            }
+        };
+        let batch = match next {
+            Ok(Some(batch)) => batch,
+            Ok(None) => break None, // Channel geschlossen
+            Err(e) => {
+                // Nicht stapelbare Jobs abweisen, der Worker läuft weiter
+                let rejected = e.downcast::<Unbatchable>()?;
+                fail_batch(&store, &worker, &rejected.batch, OmniError::InvalidInput(rejected.reason)).await;
+                continue;
+            }
+        };
+        // Das Sammeln wartet beliebig lange auf den ersten Job: vor dem Start erneut prüfen
+        if let Some(evicted) = Evicted::check(device_id, &worker) {
+            break Some(Evicted { jobs: crate::batcher::unstack(batch), ..evicted });
+        }
+        // Wartezeit auf den ersten Job zählt nicht zur Latenz
+        let started = Instant::now();
+
+        let Batch { ids, tensor, actual_len, metas, inputs, acks, stats } = batch;
+        let shape = tensor.shape()[1..].to_vec();
+        let mut timings = crate::recent::Timings::default();
+        let mut prepared = false;
+        let output = (|| {
+            let stage = Instant::now();
+            let input = crate::tta::augment(prepare_batch(&pipeline, &cfg, tensor, inputs)?, tta.as_ref(), &metas);
+            timings.preprocess = stage.elapsed();
+            prepared = true;
+            let stage = Instant::now();
+            let y = run_model(engine.as_mut(), input)?;
+            timings.inference = stage.elapsed();
+            let stage = Instant::now();
+            let y = pipeline.run_post(y)?;
+            timings.postprocess = stage.elapsed();
+            Ok(y)
+        })();
+        let y = match output {
+            Ok(y) => y,
+            Err(e) => {
+                crate::recent::record(&worker, &ids[..actual_len], &shape, &stats.queue_waits, &timings, Err(&e));
+                let failed = Batch { ids, actual_len, metas, acks, ..Default::default() };
+                fail_batch(&store, &worker, &failed, batch_error(&e, prepared)).await;
+                continue;
+            }
+        };
 
         // Batch "rekonstruieren", nur mit neuen Tensor-Werten
-        let batch = Batch { ids, tensor: y.clone(), actual_len };
-        write_outputs(&store, &batch, y).await?;
+        let batch = Batch { ids, tensor: y.clone(), actual_len, metas, acks, ..Default::default() };
+        let store = store.clone();
+        let vectors = vectors.clone();
+        let output = Arc::clone(&pipeline.output);
+        let worker = worker.clone();
+        let check = check.clone();
+        in_flight.spawn(async move {
+            let stage = Instant::now();
+            let stored = store_batch(&store, vectors.as_deref(), &batch, y, output.as_ref(), check.as_deref()).await;
+            timings.store = stage.elapsed();
+            crate::recent::record(&worker, &batch.ids[..actual_len], &shape, &stats.queue_waits, &timings, stored.as_ref().copied());
+            match stored {
+                Ok(()) => health().record_batch(&worker, actual_len, &stats),
+                Err(e) => store_failed(&store, &worker, &batch, &e).await,
+            }
+            started.elapsed()
+        });
+    };
+
+    // Ausstehende Batches fertig speichern
+    while let Some(done) = in_flight.join_next().await {
+        if let Some(latency) = stored_latency(done, &worker) {
+            policy.observe(latency, rx.lock().await.len());
+        }
+    }
+
+    match evicted {
+        Some(mut evicted) => {
+            evicted.jobs.extend(held);
+            evicted.jobs.extend(sorter.as_mut().map(LengthSorter::take_pending).unwrap_or_default());
+            Err(evicted.into())
+        }
+        None => Ok(()),
+    }
+}
+
+/// Client error of a failed batch: before the model ran (preprocessing,
+/// input checks) the input is invalid, afterwards the failure is internal.
+pub(crate) fn batch_error(e: &anyhow::Error, prepared: bool) -> OmniError {
+    if prepared {
+        OmniError::Internal(format!("{:#}", e))
+    } else {
+        OmniError::InvalidInput(format!("{:#}", e))
+    }
+}
+
+/// Answers the real jobs of a batch that could not be processed with error
+/// results and acknowledges them; the worker goes on with the next batch.
+pub(crate) async fn fail_batch(store: &RedisStorage, worker: &str, batch: &Batch, error: OmniError) {
+    tracing::warn!("Batch auf {} fehlgeschlagen, {} Jobs abgewiesen: {}", worker, batch.actual_len, error);
+    for (i, id) in batch.ids.iter().take(batch.actual_len).enumerate() {
+        if matches!(error, OmniError::InvalidInput(_)) {
+            health().job_invalid();
+        }
+        let job = Job { id: id.clone(), meta: batch.metas.get(i).cloned().unwrap_or_default(), ..Default::default() };
+        crate::store_error(store, &job, error.clone()).await;
+    }
+    health().jobs_completed(batch.actual_len);
+    for ack in batch.acks.iter().flatten() {
+        ack.done();
+    }
+}
+
+/// Context of a failed result write: the jobs before this index were stored.
+#[derive(Debug, Clone, Copy)]
+struct StoredBefore(usize);
+
+impl std::fmt::Display for StoredBefore {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Speichern nach {} Ergebnissen abgebrochen", self.0)
+    }
+}
+
+/// Latency of a finished store task; a panicked task is logged and skipped.
+pub(crate) fn stored_latency(done: Result<Duration, tokio::task::JoinError>, worker: &str) -> Option<Duration> {
+    done.inspect_err(|e| tracing::error!("Speichern auf {} abgebrochen: {}", worker, e)).ok()
+}
+
+/// Answers a batch whose results could not be stored (e.g. Redis down) so
+/// the worker can go on with the next batch. Jobs stored before the failure
+/// are acknowledged; of the rest, jobs from durable queues stay
+/// unacknowledged and are delivered again, the others get an error result.
+pub(crate) async fn store_failed(store: &RedisStorage, worker: &str, batch: &Batch, e: &anyhow::Error) {
+    // Ohne Kontext scheiterte der Vektor-Sink oder die Formatierung vor dem ersten Job
+    let before = e.downcast_ref::<StoredBefore>().map(|s| s.0);
+    let stored = before.unwrap_or(0).min(batch.actual_len);
+    tracing::warn!(
+        "Ergebnisse auf {} nicht gespeichert, {} von {} Jobs betroffen: {:#}",
+        worker,
+        batch.actual_len - stored,
+        batch.actual_len,
+        e
+    );
+    let error = match before {
+        Some(_) => OmniError::StoreUnavailable(format!("{:#}", e)),
+        None => OmniError::Internal(format!("{:#}", e)),
+    };
+    let mut answered = stored;
+    for i in stored..batch.actual_len {
+        if batch.acks.get(i).is_some_and(Option::is_some) {
+            continue;
+        }
+        let job = Job { id: batch.ids[i].clone(), meta: batch.metas.get(i).cloned().unwrap_or_default(), ..Default::default() };
+        crate::store_error(store, &job, error.clone()).await;
+        answered += 1;
+    }
+    health().jobs_completed(answered);
+    for ack in batch.acks.iter().take(stored).flatten() {
+        ack.done();
+    }
+}
+
+/// Runs preprocessing, inference and postprocessing for one stacked batch.
+///
+/// `inputs` are the additional named inputs of multi-modal jobs; the main
+/// tensor is fed to the first model input.
+pub fn infer_batch(
+    engine: &mut dyn Engine,
+    pipeline: &Pipeline,
+    cfg: &Config,
+    tensor: ndarray::ArrayD<f32>,
+    inputs: NamedTensors,
+) -> Result<ndarray::ArrayD<f32>> {
+    let input = prepare_batch(pipeline, cfg, tensor, inputs)?;
+    pipeline.run_post(run_model(engine, input)?)
+}
+
+/// Preprocessed and validated model input of one batch.
+pub enum ModelInput {
+    Single(ndarray::ArrayD<f32>),
+    Named(Vec<(String, ndarray::ArrayD<f32>)>),
+    /// Batch with test-time augmentation for the samples marked `true`.
+    Augmented(ndarray::ArrayD<f32>, Vec<bool>, Arc<crate::tta::Tta>),
+}
+
+/// Applies preprocessing and validates the batch against the input spec.
+pub fn prepare_batch(pipeline: &Pipeline, cfg: &Config, tensor: ndarray::ArrayD<f32>, inputs: NamedTensors) -> Result<ModelInput> {
+    let spec = cfg.input_spec();
+
+    let x = pipeline.run_pre(tensor)?;
+    spec.validate(x.shape(), "f32")?;
+    if inputs.is_empty() {
+        return Ok(ModelInput::Single(x));
+    }
+    // Multi-modal: Haupt-Tensor an den ersten Modell-Input, Rest nach Namen
+    for extra in &cfg.input.extra {
+        let t = inputs.get(&extra.name).with_context(|| format!("Input '{}' fehlt", extra.name))?;
+        extra.validate(t.shape(), spec.batch)?;
+    }
+    if let Some(name) = inputs.keys().find(|n| !cfg.input.extra.iter().any(|e| &e.name == *n)) {
+        anyhow::bail!("Input '{}' ist nicht in [[input.extra]] konfiguriert", name);
     }
+    let primary = cfg.model.input_names.first().context("model.input_names ist leer")?;
+    let mut named = vec![(primary.clone(), x)];
+    named.extend(inputs);
+    Ok(ModelInput::Named(named))
+}
+
+/// Runs the model on a prepared batch; returns its first output.
+pub fn run_model(engine: &mut dyn Engine, input: ModelInput) -> Result<ndarray::ArrayD<f32>> {
+    match input {
+        ModelInput::Single(x) => engine.infer_array(x),
+        ModelInput::Named(named) => engine.infer_named(named)?.into_iter().next().context("Modell lieferte keinen Output"),
+        ModelInput::Augmented(x, mask, tta) => tta.run(engine, x, &mask),
+    }
+}
 
+/// Runs one inference with zero tensors for all configured model inputs
+/// (dynamic dimensions as 1).
+fn warmup(engine: &mut dyn Engine, cfg: &Config) -> Result<()> {
+    let inputs = cfg
+        .model
+        .input_names
+        .iter()
+        .zip(&cfg.model.input_shapes)
+        .map(|(name, shape)| {
+            let shape: Vec<usize> = shape.iter().map(|&d| d.max(1)).collect();
+            (name.clone(), ndarray::ArrayD::zeros(shape))
+        })
+        .collect();
+    engine.infer_named(inputs).context("Warmup-Inferenz fehlgeschlagen")?;
     Ok(())
 }
 
+/// Upserts the batch into the vector sink (if any), then stores its outputs.
+pub(crate) async fn store_batch(
+    store: &RedisStorage,
+    vectors: Option<&VectorSink>,
+    batch: &Batch,
+    y: ndarray::ArrayD<f32>,
+    formatter: &dyn OutputFormatter,
+    check: Option<&OutputCheck>,
+) -> Result<()> {
+    if let Some(sink) = vectors {
+        sink.upsert_batch(batch, &y).await?;
+    }
+    write_outputs(store, batch, y, formatter, check).await
+}
+
 /// Stores batch inference outputs to Redis.
 ///
-/// Writes each output tensor as JSON to Redis with metadata including timestamp and shape.
+/// Writes each output tensor as JSON to Redis with metadata including timestamp.
+/// The remaining result fields are produced by the pipeline's output formatter.
+/// With chunking enabled, the full tensor is stored in raw chunks as well and
+/// described by the `tensor` field.
 /// Dummy samples (padding) are automatically skipped based on `batch.actual_len`.
+/// With `check`, outputs containing NaN/Inf are flagged or failed per job.
+/// Jobs from durable queues are acknowledged after all results are stored.
 ///
 /// # Arguments
 ///
 /// * `store` - Redis storage client
 /// * `batch` - Batch containing job IDs and metadata
 /// * `y` - Output tensor with shape [N, ...]
+/// * `formatter` - Formatter producing the per-sample result fields
+/// * `check` - Optional NaN/Inf scan of the per-sample outputs
 ///
 /// # Returns
 ///
@@ -95,6 +484,8 @@ pub async fn write_outputs(
     store: &RedisStorage,
     batch: &Batch,
     y: ndarray::ArrayD<f32>,
+    formatter: &dyn OutputFormatter,
+    check: Option<&OutputCheck>,
 ) -> Result<()> {
     let n = y.shape()[0];
     anyhow::ensure!(
@@ -104,27 +495,217 @@ pub async fn write_outputs(
         batch.ids.len()
     );
 
+    for (i, (id, mut payload)) in batch.ids.iter().zip(format_results(batch, &y, formatter)?).enumerate() {
+        let out = y.index_axis(Axis(0), i);
+        // Canaries werden geprüft statt gespeichert
+        if crate::probes::verify(id, out.view()) {
+            continue;
+        }
+        let failed = check.is_some_and(|c| c.apply(&mut payload, out.view()));
+        if let Some(drift) = crate::drift::monitor().filter(|_| !failed) {
+            drift.observe_output(out.view());
+        }
+        // Gestreamte Jobs gehen direkt an ihre Verbindung statt in den Speicher
+        let route = crate::results::take_route(id);
+        // Vollständigen Tensor in Chunks ablegen, das JSON bleibt die Vorschau
+        if let Some(chunk_elements) = store.chunk_elements().filter(|_| !failed && route.is_none()) {
+            let data: Vec<f32> = out.iter().copied().collect();
+            let layout = store.store_tensor(id, out.shape(), &data, chunk_elements).await.context(StoredBefore(i))?;
+            payload["tensor"] = serde_json::to_value(layout)?;
+        }
+        if route.is_none() {
+            store.store_json(id, &payload).await.context(StoredBefore(i))?;
+        }
+        if let (Some(log), Some(meta)) = (crate::prediction_log::log(), batch.metas.get(i)) {
+            log.record(id, meta, &payload, out.view());
+        }
+        crate::results::publish(&payload);
+        if let Some(route) = route {
+            let _ = route.send(crate::results::Direct { payload, tensor: Some(out.to_owned()) });
+        }
+        tracing::debug!("Stored output for job {}", id);
+    }
+
+    health().jobs_completed(batch.actual_len);
+
+    // Erst nach dem Speichern quittieren (at-least-once)
+    for ack in batch.acks.iter().flatten() {
+        ack.done();
+    }
+
+    Ok(())
+}
+
+/// Builds the result payloads of the real (non-padding) jobs of a batch.
+///
+/// Each payload holds `schema_version`, `id`, `timestamp`, the formatter
+/// fields and the job metadata (if any).
+pub fn format_results(batch: &Batch, y: &ndarray::ArrayD<f32>, formatter: &dyn OutputFormatter) -> Result<Vec<Value>> {
+    let mut results = Vec::with_capacity(batch.actual_len);
     for (i, id) in batch.ids.iter().take(batch.actual_len).enumerate() {
-        let slice = y.index_axis(Axis(0), i).to_owned();
+        let slice = y.index_axis(Axis(0), i);
 
-        let payload = serde_json::json!({
+        let mut payload = serde_json::json!({
+            "schema_version": SCHEMA_VERSION,
             "id": id,
             "timestamp": Utc::now().to_rfc3339(),
-            "shape": slice.shape(),
-            "data": slice.iter().take(256).cloned().collect::<Vec<f32>>() // Beispiel: nur Top-256 Werte
         });
-
-        store.store_json(id, &payload).await?;
-        tracing::debug!("Stored output for job {}", id);
+        if let Value::Object(fields) = formatter.format(slice)? {
+            payload.as_object_mut().unwrap().extend(fields);
+        }
+        // Job-Metadaten (z.B. Stream-ID/Zeitstempel von Quellen) mitschreiben
+        if let Some(meta) = batch.metas.get(i).filter(|m| !m.is_empty()) {
+            payload["meta"] = Value::Object(meta.clone());
+            crate::trace_context::annotate(&mut payload, meta);
+        }
+        results.push(payload);
     }
-
-    Ok(())
+    Ok(results)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use ndarray::{Array, ArrayD};
+    use tokio::sync::{mpsc, Mutex};
+
+    /// Doubles its input; negative inputs fail.
+    struct Doubler;
+
+    impl Engine for Doubler {
+        fn name(&self) -> &'static str {
+            "doubler"
+        }
+
+        fn infer_array(&mut self, input: ArrayD<f32>) -> Result<ArrayD<f32>> {
+            anyhow::ensure!(input.iter().all(|v| *v >= 0.0), "negativer Input");
+            Ok(input * 2.0)
+        }
+    }
+
+    struct Identity;
+
+    impl crate::pipeline::Preprocessor for Identity {
+        fn run(&self, input: ArrayD<f32>) -> Result<ArrayD<f32>> {
+            Ok(input)
+        }
+    }
+
+    impl crate::pipeline::Postprocessor for Identity {
+        fn run(&self, input: ArrayD<f32>) -> Result<ArrayD<f32>> {
+            Ok(input)
+        }
+    }
+
+    fn config(extra: &str) -> Config {
+        let raw = format!(
+            r#"
+            [model]
+            backend = "onnx"
+            device = "cpu"
+            model_path = "model.onnx"
+            input_names = ["input"]
+            input_shapes = [[0, 1, 2, 1]]
+            output_names = ["output"]
+            output_shapes = [[0, 1, 2, 1]]
+            [queue]
+            max_batch = 2
+            max_wait_ms = 50
+            {}
+            [redis]
+            url = "memory://"
+            out_prefix = "results"
+            [input]
+            batch = 2
+            channels = 1
+            height = 2
+            width = 1
+            dtype = "f32"
+            "#,
+            extra
+        );
+        toml::from_str(&raw).unwrap()
+    }
+
+    fn job(id: &str, height: usize, value: f32) -> Job {
+        Job { id: id.to_string(), tensor: ArrayD::from_elem(ndarray::IxDyn(&[1, height, 1]), value), ..Default::default() }
+    }
+
+    /// Runs the batch worker over `jobs` (two per batch) until the queue is closed.
+    async fn serve(cfg: Config, jobs: Vec<Job>) -> RedisStorage {
+        let store = RedisStorage::new(&cfg.redis.url, "results".to_string()).unwrap();
+        let (tx, rx) = mpsc::channel(jobs.len());
+        for job in jobs {
+            tx.send(job).await.unwrap();
+        }
+        drop(tx);
+        let rx = Arc::new(Mutex::new(rx));
+        let pipeline = Pipeline {
+            pre: Arc::new(Identity),
+            post: Arc::new(Identity),
+            output: Arc::new(crate::pipeline::RawOutput),
+        };
+        run_batch_worker(cfg, None, Box::new(Doubler), rx, store.clone(), pipeline, "cpu".to_string()).await.unwrap();
+        store
+    }
+
+    #[tokio::test]
+    async fn test_worker_answers_failed_batches_and_continues() {
+        for extra in ["", "[queue.stages]"] {
+            let store = serve(
+                config(extra),
+                vec![
+                    // Nicht stapelbar, dann Inferenzfehler, dann ein gültiger Batch
+                    job("bad-shape", 3, 1.0),
+                    job("neighbour", 2, 1.0),
+                    job("negative", 2, -1.0),
+                    job("failed", 2, 1.0),
+                    job("ok", 2, 1.5),
+                ],
+            )
+            .await;
+
+            let result = |id: &'static str| {
+                let store = store.clone();
+                async move { store.get_json(id).await.unwrap().unwrap() }
+            };
+            assert_eq!(result("bad-shape").await["code"], "INVALID_INPUT", "{}", extra);
+            assert_eq!(result("neighbour").await["code"], "INVALID_INPUT", "{}", extra);
+            assert_eq!(result("negative").await["code"], "INTERNAL", "{}", extra);
+            assert_eq!(result("failed").await["code"], "INTERNAL", "{}", extra);
+            assert_eq!(result("ok").await["data"], serde_json::json!([3.0, 3.0]), "{}", extra);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_worker_survives_store_failures() {
+        for extra in ["", "[queue.stages]"] {
+            let mut cfg = config(extra);
+            // Nicht erreichbarer Redis: jedes Speichern schlägt fehl
+            cfg.redis.url = "redis://127.0.0.1:1/".to_string();
+            let id = |name: &str| format!("{}-unstored{}", name, extra);
+            let (ack_tx, mut acked) = mpsc::unbounded_channel();
+            let (nack_tx, mut nacked) = mpsc::unbounded_channel();
+            let durable = Job { ack: Some(crate::types::Ack::with_nack(ack_tx, nack_tx, "durable".into())), ..job(&id("durable"), 2, 1.0) };
+            let mut results = crate::results::subscribe();
+            // Der Worker läuft über beide Batches weiter und endet regulär
+            serve(cfg, vec![job(&id("a"), 2, 1.0), durable, job(&id("b"), 2, 1.0), job(&id("c"), 2, 1.0)]).await;
+
+            let mut codes = std::collections::HashMap::new();
+            while let Ok(payload) = results.try_recv() {
+                if let Some(job) = payload["id"].as_str().filter(|job| job.ends_with(&format!("unstored{}", extra))) {
+                    codes.insert(job.to_string(), payload["code"].clone());
+                }
+            }
+            for name in ["a", "b", "c"] {
+                assert_eq!(codes[&id(name)], "STORE_UNAVAILABLE", "{}", extra);
+            }
+            // Dauerhafte Jobs werden zurückgegeben statt beantwortet
+            assert!(!codes.contains_key(&id("durable")), "{}", extra);
+            assert_eq!(nacked.try_recv().unwrap(), "durable", "{}", extra);
+            assert!(acked.try_recv().is_err(), "{}", extra);
+        }
+    }
 
     #[test]
     fn test_batch_output_dimension_check() {
@@ -132,6 +713,7 @@ mod tests {
             ids: vec!["job1".to_string(), "job2".to_string()],
             tensor: Array::zeros((2, 3, 64, 64)).into_dyn(),
             actual_len: 2,
+            ..Default::default()
         };
         
         let y: ArrayD<f32> = Array::zeros((2, 10)).into_dyn();
@@ -145,6 +727,7 @@ mod tests {
             ids: vec!["job1".to_string(), "DUMMY-1".to_string(), "DUMMY-2".to_string()],
             tensor: Array::zeros((3, 10)).into_dyn(),
             actual_len: 1, // only first job is real
+            ..Default::default()
         };
         
         let real_jobs: Vec<_> = batch.ids.iter().take(batch.actual_len).collect();